@@ -0,0 +1,158 @@
+//! Monitoring of Transmission daemon availability: alerts when its RPC endpoint is unreachable for too long and
+//! optionally tries to recover it (restarting its systemd unit, for example).
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::config;
+use crate::notifications::{Event, Notifier};
+use crate::util;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct AvailabilityConfig {
+    /// Time of unavailability after which the alert is sent
+    #[serde(deserialize_with = "config::deserialize_optional_duration")]
+    pub alert_after: Option<Duration>,
+    /// Command (the program and its arguments) which is executed on the alert and then repeated each alert-after
+    /// period until the daemon is available again
+    pub recovery_command: Option<Vec<String>>,
+}
+
+pub struct AvailabilityMonitor {
+    config: AvailabilityConfig,
+    unavailable_since: Option<Instant>,
+    alerted: bool,
+    recovery_time: Option<Instant>,
+    notifier: Arc<Notifier>,
+}
+
+impl AvailabilityMonitor {
+    pub fn new(config: AvailabilityConfig, notifier: Arc<Notifier>) -> AvailabilityMonitor {
+        AvailabilityMonitor {
+            config: config,
+            unavailable_since: None,
+            alerted: false,
+            recovery_time: None,
+            notifier: notifier,
+        }
+    }
+
+    /// Registers the result of the daemon communication attempt
+    pub fn update(&mut self, error: Option<&str>) {
+        self.update_at(Instant::now(), error)
+    }
+
+    fn update_at(&mut self, now: Instant, error: Option<&str>) {
+        let Some(threshold) = self.config.alert_after else {
+            return;
+        };
+
+        let Some(error) = error else {
+            if let Some(since) = self.unavailable_since.take() {
+                if self.alerted {
+                    let downtime = util::time::format_duration(now.duration_since(since).as_secs());
+                    info!("Transmission daemon is available again after {} of downtime.", downtime);
+                    self.notifier.notify_in_background(
+                        Event::Recovered, "Transmission daemon is available again", &format!(
+                            "Transmission daemon is available again after {} of downtime.", downtime));
+                }
+                self.alerted = false;
+                self.recovery_time = None;
+            }
+            return;
+        };
+
+        let since = *self.unavailable_since.get_or_insert(now);
+        if now.duration_since(since) < threshold {
+            return;
+        }
+
+        if !self.alerted {
+            let duration = util::time::format_duration(threshold.as_secs());
+            error!("Transmission daemon has been unavailable for {}: {}.", duration, error);
+            self.notifier.notify_in_background(Event::Problem, "Transmission daemon is unavailable", &format!(
+                "Transmission daemon has been unavailable for {}: {}.", duration, error));
+            self.alerted = true;
+        }
+
+        if self.recovery_time.is_none_or(|time| now.duration_since(time) >= threshold) {
+            self.recovery_time = Some(now);
+            self.recover();
+        }
+    }
+
+    fn recover(&self) {
+        let Some(command) = self.config.recovery_command.clone() else {
+            return;
+        };
+
+        info!("Trying to recover Transmission daemon: executing `{}`...", command.join(" "));
+        tokio::task::spawn_blocking(move || {
+            match util::process::run_command(&command[0], &command[1..]) {
+                Ok(_) => info!("Transmission daemon recovery command has completed."),
+                Err(e) => error!("Failed to recover Transmission daemon: {}.", e),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use tokio::runtime::Runtime;
+
+    use super::*;
+
+    #[test]
+    fn test_availability() {
+        let runtime = Runtime::new().unwrap();
+        let _guard = runtime.enter();
+
+        let temp_dir = TempDir::new().unwrap();
+        let marker = temp_dir.path().join("recovered");
+        let recovered = || {
+            for _ in 0..100 {
+                if marker.exists() {
+                    std::fs::remove_file(&marker).unwrap();
+                    return true;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            false
+        };
+
+        let mut monitor = AvailabilityMonitor::new(AvailabilityConfig {
+            alert_after: Some(Duration::from_secs(60)),
+            recovery_command: Some(vec![s!("touch"), marker.display().to_string()]),
+        }, Arc::new(Notifier::new(None)));
+
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+        let error = Some("Connection refused");
+
+        monitor.update_at(at(0), error);
+        monitor.update_at(at(30), error);
+        assert!(!monitor.alerted);
+
+        monitor.update_at(at(60), error);
+        assert!(monitor.alerted);
+        assert!(recovered());
+
+        monitor.update_at(at(90), error);
+        monitor.update_at(at(120), error);
+        assert!(recovered());
+
+        monitor.update_at(at(130), None);
+        assert!(!monitor.alerted);
+        assert!(monitor.unavailable_since.is_none());
+
+        // A short outage doesn't trigger the alert
+        monitor.update_at(at(140), error);
+        monitor.update_at(at(150), None);
+        assert!(!monitor.alerted);
+        assert!(!marker.exists());
+    }
+}
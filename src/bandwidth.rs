@@ -0,0 +1,82 @@
+use std::collections::HashSet;
+
+use crate::config::BandwidthGroupConfig;
+use crate::torrent_client::{self, BandwidthGroup, TorrentClient};
+use crate::transmissionrpc::Torrent;
+
+/// Manages the client's bandwidth groups: configures their speed limits and assigns new torrents to them
+pub struct BandwidthGroups {
+    groups: Vec<BandwidthGroupConfig>,
+    configured: bool,
+}
+
+impl BandwidthGroups {
+    pub fn new(groups: Vec<BandwidthGroupConfig>) -> BandwidthGroups {
+        BandwidthGroups {
+            groups: groups,
+            configured: false,
+        }
+    }
+
+    /// Assigns the torrents which don't belong to any group yet. Returns hashes of the changed torrents.
+    pub async fn assign(
+        &mut self, client: &dyn TorrentClient, torrents: &[Torrent],
+    ) -> torrent_client::Result<HashSet<String>> {
+        let mut changed = HashSet::new();
+
+        if self.groups.is_empty() {
+            return Ok(changed);
+        }
+
+        if !self.configured {
+            self.configure(client).await?;
+            self.configured = true;
+        }
+
+        for torrent in torrents {
+            if torrent.bandwidth_group.is_some() {
+                continue;
+            }
+
+            if let Some(group) = self.find_group(torrent) {
+                info!(hash = torrent.hash.as_str(), action = "bandwidth-group";
+                      "Assigning '{}' torrent to {:?} bandwidth group...", torrent.name, group.name);
+                client.set_torrent_bandwidth_group(&torrent.hash, &group.name).await?;
+                changed.insert(torrent.hash.clone());
+            }
+        }
+
+        Ok(changed)
+    }
+
+    async fn configure(&self, client: &dyn TorrentClient) -> torrent_client::EmptyResult {
+        let current_groups = client.get_bandwidth_groups().await?;
+
+        for config in &self.groups {
+            let group = BandwidthGroup {
+                name: config.name.clone(),
+                honors_session_limits: config.honors_session_limits,
+                download_limit: config.download_limit,
+                upload_limit: config.upload_limit,
+            };
+
+            if current_groups.contains(&group) {
+                continue;
+            }
+
+            info!(action = "bandwidth-group"; "Configuring {:?} bandwidth group...", group.name);
+            client.set_bandwidth_group(&group).await?;
+        }
+
+        Ok(())
+    }
+
+    fn find_group(&self, torrent: &Torrent) -> Option<&BandwidthGroupConfig> {
+        let tracker_hosts = torrent.tracker_hosts();
+
+        self.groups.iter().find(|group| {
+            group.labels.iter().any(|label| torrent.labels.contains(label)) ||
+            group.trackers.iter().any(|pattern| pattern.matches_any(tracker_hosts.iter().map(String::as_str)))
+        })
+    }
+}
@@ -1,17 +1,79 @@
 use std::collections::HashMap;
+use std::env;
+use std::io;
 use std::path::PathBuf;
+use std::process;
 
 use itertools::Itertools;
 
 use crate::common::GenericResult;
+use crate::control;
 use crate::controller::Action;
+use crate::daemon;
 use crate::email::{Mailer, EmailTemplate};
 use crate::util;
-use crate::util::time::{Duration, WeekPeriods};
+use crate::util::time::{Duration, Timestamp, WeekPeriods};
+
+pub enum Command {
+    Daemon,
+    Rename {
+        hash: String,
+        name: Option<String>,
+    },
+    Reannounce {
+        hashes: Vec<String>,
+    },
+    Verify {
+        hashes: Vec<String>,
+        wait: bool,
+    },
+    TorrentDone {
+        hash: String,
+    },
+    Status {
+        json: bool,
+    },
+    List {
+        json: bool,
+    },
+    Pause {
+        hashes: Vec<String>,
+    },
+    Resume {
+        hashes: Vec<String>,
+    },
+    Cleanup,
+    Control {
+        command: String,
+    },
+    History {
+        period: Option<(Timestamp, Timestamp)>,
+        json: bool,
+    },
+    Restore {
+        backup: Option<PathBuf>,
+    },
+    Tui,
+    RenderTemplate {
+        path: PathBuf,
+        params: Vec<(String, String)>,
+    },
+}
+
+const TORRENT_HASH_ENV_VAR: &str = "TR_TORRENT_HASH";
 
 pub struct Arguments {
+    pub command: Command,
+
     pub config: PathBuf,
+    pub controller_config: Option<PathBuf>,
     pub debug_level: usize,
+    pub dry_run: bool,
+
+    pub daemon: bool,
+    pub once: bool,
+    pub pid_file: Option<PathBuf>,
+    pub log_file: Option<PathBuf>,
 
     pub action: Option<Action>,
     pub action_periods: WeekPeriods,
@@ -30,10 +92,20 @@ pub struct Arguments {
 
 pub fn parse() -> GenericResult<Arguments> {
     let default_config_path = "~/.config/transmission-daemon/settings.json";
+    let default_controller_config_path = "~/.config/transmission-controller.toml";
 
     let mut args = Arguments {
+        command: Command::Daemon,
+
         config: PathBuf::from(shellexpand::tilde(default_config_path).to_string()),
+        controller_config: None,
         debug_level: 0,
+        dry_run: false,
+
+        daemon: false,
+        once: false,
+        pid_file: None,
+        log_file: None,
 
         action: None,
         action_periods: WeekPeriods::new(),
@@ -48,9 +120,13 @@ pub fn parse() -> GenericResult<Arguments> {
         error_mailer: None,
         notifications_mailer: None,
         torrent_downloaded_email_template: EmailTemplate::new(
-            "Downloaded: {{name}}", "{{name}} torrent has been downloaded."),
+            "Downloaded: {{name}}", "{{name}} torrent has been downloaded.{{copy_details}}"),
     };
 
+    let mut command: Option<String> = None;
+    let mut command_args: Vec<String> = Vec::new();
+
+    let mut controller_config_string: Option<String> = None;
     let mut action_string: Option<String> = None;
     let mut period_strings: Vec<String> = Vec::new();
     let mut copy_to_string: Option<String> = None;
@@ -61,21 +137,28 @@ pub fn parse() -> GenericResult<Arguments> {
     let mut email_errors_to: Option<String> = None;
     let mut email_notifications_to: Option<String> = None;
     let mut torrent_downloaded_email_template: Option<String> = None;
+    let mut pid_file_string: Option<String> = None;
+    let mut log_file_string: Option<String> = None;
 
     let action_map: HashMap<String, Action> =
         [Action::StartOrPause, Action::PauseOrStart]
         .iter().map(|&action| (action.to_string(), action)).collect();
 
     {
-        use argparse::{ArgumentParser, Store, StoreOption, IncrBy, Collect};
+        use argparse::{ArgumentParser, Store, StoreOption, StoreTrue, IncrBy, Collect, List};
 
         let config_help = format!("configuration file path ({})", default_config_path);
+        let controller_config_help = format!(
+            "controller configuration file path ({}, used if exists)", default_controller_config_path);
 
         let mut parser = ArgumentParser::new();
         parser.set_description("Transmission controller daemon.");
+        parser.stop_on_first_argument(true);
 
         parser.refer(&mut args.config).metavar("PATH").add_option(
             &["--config"], Store, &config_help);
+        parser.refer(&mut controller_config_string).metavar("PATH").add_option(
+            &["--controller-config"], StoreOption, &controller_config_help);
         parser.refer(&mut action_string).metavar(&action_map.keys().join("|")).add_option(
             &["-a", "--action"], StoreOption, "action that will be taken according to the specified time periods");
         parser.refer(&mut period_strings).metavar("PERIOD").add_option(
@@ -101,12 +184,49 @@ pub fn parse() -> GenericResult<Arguments> {
             &["-n", "--email-notifications"], StoreOption, "address to send notifications to");
         parser.refer(&mut torrent_downloaded_email_template).metavar("PATH").add_option(
             &["-t", "--torrent-downloaded-email-template"], StoreOption, "template of 'torrent downloaded' notification");
+        parser.refer(&mut args.dry_run).add_option(
+            &["--dry-run"], StoreTrue, "only log the actions that would be taken without changing anything");
+        parser.refer(&mut args.daemon).add_option(
+            &["--daemon"], StoreTrue, "fork to background and detach from the terminal");
+        parser.refer(&mut args.once).add_option(
+            &["--once"], StoreTrue,
+            "run a single processing cycle, wait for the copies and exit (for cron): exit code is 1 if the cycle has \
+             failed and 2 if some torrents have failed to be copied");
+        parser.refer(&mut pid_file_string).metavar("PATH").add_option(
+            &["--pid-file"], StoreOption, "PID file path");
+        parser.refer(&mut log_file_string).metavar("PATH").add_option(
+            &["--log-file"], StoreOption, "file to redirect the output to in daemon mode (/dev/null by default)");
         parser.refer(&mut args.debug_level).add_option(
             &["-d", "--debug"], IncrBy(1usize), "debug mode");
+        parser.refer(&mut command).metavar("COMMAND").add_argument(
+            "command", StoreOption,
+            "command to execute instead of running the daemon: cleanup, control, history, list, pause, reannounce, \
+             render-template, rename, restore, resume, status, torrent-done, tui, verify");
+        parser.refer(&mut command_args).metavar("ARGS").add_argument(
+            "arguments", List, "command arguments");
 
         parser.parse_args_or_exit();
     }
 
+    if let Some(command) = command {
+        args.command = parse_command(&command, command_args)?;
+    } else if let Ok(hash) = env::var(TORRENT_HASH_ENV_VAR) {
+        // We are invoked by Transmission as script-torrent-done-filename
+        args.command = Command::TorrentDone { hash: hash };
+    }
+
+    args.controller_config = match controller_config_string {
+        Some(path) => Some(PathBuf::from(path)),
+        None => {
+            let path = PathBuf::from(shellexpand::tilde(default_controller_config_path).to_string());
+            if path.exists() {
+                Some(path)
+            } else {
+                None
+            }
+        },
+    };
+
     if let Some(action_string) = action_string {
         match action_map.get(&action_string) {
             Some(action) => {
@@ -125,6 +245,32 @@ pub fn parse() -> GenericResult<Arguments> {
 
     args.action_periods = util::time::parse_periods(&period_strings)?;
 
+    args.pid_file = pid_file_string.map(PathBuf::from);
+    args.log_file = log_file_string.map(PathBuf::from);
+
+    if !matches!(args.command, Command::Daemon) && args.daemon {
+        return Err!("--daemon can't be used with commands");
+    } else if !matches!(args.command, Command::Daemon) && args.once {
+        return Err!("--once can't be used with commands");
+    } else if args.once && args.daemon {
+        return Err!("--once can't be used with --daemon");
+    } else if !matches!(args.command, Command::Daemon | Command::Cleanup | Command::TorrentDone { .. }) &&
+        args.pid_file.is_some() {
+        return Err!("--pid-file can't be used with the specified command");
+    } else if args.log_file.is_some() && !args.daemon {
+        return Err!("--log-file can be used only with --daemon");
+    }
+
+    // The daemon changes its current directory to root
+    if args.daemon {
+        args.config = daemon::absolute_path(&args.config)?;
+
+        let paths = [&mut args.controller_config, &mut args.pid_file, &mut args.log_file];
+        for path in paths.into_iter().flatten() {
+            *path = daemon::absolute_path(path)?;
+        }
+    }
+
     {
         let paths: Vec<(&mut Option<String>, &mut Option<PathBuf>)> = vec![
             (&mut copy_to_string, &mut args.copy_to),
@@ -185,3 +331,233 @@ pub fn parse() -> GenericResult<Arguments> {
 
     Ok(args)
 }
+
+fn parse_command(name: &str, args: Vec<String>) -> GenericResult<Command> {
+    use argparse::{ArgumentParser, Collect, List, Store, StoreOption, StoreTrue};
+
+    Ok(match name {
+        "rename" => {
+            let mut hash = String::new();
+            let mut new_name: Option<String> = None;
+
+            {
+                let mut parser = ArgumentParser::new();
+                parser.set_description(
+                    "Renames top-level torrent directory to the specified name or according to the rename rules.");
+                parser.refer(&mut hash).required().metavar("HASH").add_argument(
+                    "hash", Store, "torrent hash");
+                parser.refer(&mut new_name).metavar("NAME").add_argument(
+                    "name", StoreOption, "new name (if not specified, the rename rules are applied)");
+                parse_command_args(&parser, name, args);
+            }
+
+            Command::Rename { hash: hash, name: new_name }
+        },
+        "reannounce" => {
+            let mut hashes: Vec<String> = Vec::new();
+
+            {
+                let mut parser = ArgumentParser::new();
+                parser.set_description("Forces announce of the specified torrents to their trackers.");
+                parser.refer(&mut hashes).required().metavar("HASH").add_argument(
+                    "hashes", List, "torrent hashes");
+                parse_command_args(&parser, name, args);
+            }
+
+            Command::Reannounce { hashes: hashes }
+        },
+        "verify" => {
+            let mut hashes: Vec<String> = Vec::new();
+            let mut no_wait = false;
+
+            {
+                let mut parser = ArgumentParser::new();
+                parser.set_description(
+                    "Verifies local data of the specified torrents and resumes the ones that have passed the check.");
+                parser.refer(&mut no_wait).add_option(
+                    &["--no-wait"], StoreTrue, "don't wait for verification completion");
+                parser.refer(&mut hashes).required().metavar("HASH").add_argument(
+                    "hashes", List, "torrent hashes");
+                parse_command_args(&parser, name, args);
+            }
+
+            Command::Verify { hashes: hashes, wait: !no_wait }
+        },
+        "torrent-done" => {
+            let mut hash: Option<String> = None;
+
+            {
+                let mut parser = ArgumentParser::new();
+                parser.set_description(
+                    "Processes the downloaded torrent right away. Intended to be used as Transmission's \
+                     script-torrent-done-filename (the controller switches to this mode automatically when it's \
+                     started by Transmission with TR_TORRENT_HASH environment variable). If the daemon is running, \
+                     it's asked to process the torrent instead (via control-socket or --pid-file).");
+                parser.refer(&mut hash).metavar("HASH").add_argument(
+                    "hash", StoreOption, "torrent hash (TR_TORRENT_HASH environment variable by default)");
+                parse_command_args(&parser, name, args);
+            }
+
+            let hash = match hash {
+                Some(hash) => hash,
+                None => env::var(TORRENT_HASH_ENV_VAR).map_err(|_| format!(
+                    "Torrent hash must be specified via command line or {} environment variable",
+                    TORRENT_HASH_ENV_VAR))?,
+            };
+
+            Command::TorrentDone { hash: hash }
+        },
+        "status" | "list" => {
+            let mut json = false;
+
+            {
+                let mut parser = ArgumentParser::new();
+                parser.set_description(match name {
+                    "status" => "Prints a summary of the managed torrents.",
+                    _ => "Lists the managed torrents and their state.",
+                });
+                parser.refer(&mut json).add_option(
+                    &["--json"], StoreTrue, "print the result in JSON format (see README.md for the schema)");
+                parse_command_args(&parser, name, args);
+            }
+
+            match name {
+                "status" => Command::Status { json: json },
+                _ => Command::List { json: json },
+            }
+        },
+        "cleanup" => {
+            {
+                let mut parser = ArgumentParser::new();
+                parser.set_description(
+                    "Asks the running daemon (specified by --pid-file) to run a full check right away.");
+                parse_command_args(&parser, name, args);
+            }
+
+            Command::Cleanup
+        },
+        "tui" => {
+            {
+                let mut parser = ArgumentParser::new();
+                parser.set_description(
+                    "Shows an interactive dashboard of the managed torrents and the running daemon's state (requires \
+                     control-socket option for the latter).");
+                parse_command_args(&parser, name, args);
+            }
+
+            Command::Tui
+        },
+        "control" => {
+            let mut command = String::new();
+            let help = format!("command to send: {}", control::COMMANDS.join(", "));
+
+            {
+                let mut parser = ArgumentParser::new();
+                parser.set_description(
+                    "Sends the command to the running daemon via its control socket (control-socket option).");
+                parser.refer(&mut command).required().metavar("COMMAND").add_argument(
+                    "command", Store, &help);
+                parse_command_args(&parser, name, args);
+            }
+
+            if control::ControlCommand::parse(&command).is_none() {
+                return Err!("Invalid control command: {}", command);
+            }
+
+            Command::Control { command: command }
+        },
+        "history" => {
+            let mut period: Option<String> = None;
+            let mut json = false;
+
+            {
+                let mut parser = ArgumentParser::new();
+                parser.set_description(
+                    "Prints the torrents which have completed downloading in the specified period (requires \
+                     history-db option).");
+                parser.refer(&mut json).add_option(
+                    &["--json"], StoreTrue, "print the result in JSON format (see README.md for the schema)");
+                parser.refer(&mut period).metavar("PERIOD").add_argument(
+                    "period", StoreOption, "YYYY, YYYY-MM or YYYY-MM-DD (the whole history by default)");
+                parse_command_args(&parser, name, args);
+            }
+
+            let period = match period {
+                Some(period) => Some(util::time::parse_date_period(&period)?),
+                None => None,
+            };
+
+            Command::History { period: period, json: json }
+        },
+        "restore" => {
+            let mut backup: Option<PathBuf> = None;
+
+            {
+                let mut parser = ArgumentParser::new();
+                parser.set_description(
+                    "Restores Transmission's state from the backup (requires [backup] configuration). The daemon must \
+                     be stopped.");
+                parser.refer(&mut backup).metavar("BACKUP").add_argument(
+                    "backup", StoreOption, "backup archive to restore (the latest one by default)");
+                parse_command_args(&parser, name, args);
+            }
+
+            Command::Restore { backup: backup }
+        },
+        "render-template" => {
+            let mut path = PathBuf::new();
+            let mut param_strings: Vec<String> = Vec::new();
+
+            {
+                let mut parser = ArgumentParser::new();
+                parser.set_description(
+                    "Renders the notification template (--torrent-downloaded-email-template or report template) with \
+                     sample parameters and prints the result.");
+                parser.refer(&mut param_strings).metavar("KEY=VALUE").add_option(
+                    &["--param"], Collect, "template parameter to use instead of the sample one");
+                parser.refer(&mut path).required().metavar("PATH").add_argument(
+                    "path", Store, "template file path");
+                parse_command_args(&parser, name, args);
+            }
+
+            let mut params = Vec::new();
+            for param in param_strings {
+                let (key, value) = param.split_once('=').ok_or_else(|| format!(
+                    "Invalid template parameter: {:?} (it must be in KEY=VALUE format)", param))?;
+                params.push((s!(key), s!(value)));
+            }
+
+            Command::RenderTemplate { path: path, params: params }
+        },
+        "pause" | "resume" => {
+            let mut hashes: Vec<String> = Vec::new();
+
+            {
+                let mut parser = ArgumentParser::new();
+                parser.set_description(if name == "pause" {
+                    "Pauses the specified torrents."
+                } else {
+                    "Resumes the specified torrents."
+                });
+                parser.refer(&mut hashes).required().metavar("HASH").add_argument(
+                    "hashes", List, "torrent hashes");
+                parse_command_args(&parser, name, args);
+            }
+
+            if name == "pause" {
+                Command::Pause { hashes: hashes }
+            } else {
+                Command::Resume { hashes: hashes }
+            }
+        },
+        _ => return Err!("Invalid command: {}", name),
+    })
+}
+
+fn parse_command_args(parser: &argparse::ArgumentParser, name: &str, mut args: Vec<String>) {
+    args.insert(0, format!("transmission-controller {}", name));
+
+    if let Err(code) = parser.parse(args, &mut io::stdout(), &mut io::stderr()) {
+        process::exit(code);
+    }
+}
@@ -0,0 +1,456 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use regex::Regex;
+use serde_json::{json, Value};
+
+use crate::common::{EmptyResult, GenericResult};
+use crate::config::ControllerConfig;
+use crate::control;
+use crate::disk_space::format_size;
+use crate::email::EmailTemplate;
+use crate::history::History;
+use crate::policy::Policies;
+use crate::rename;
+use crate::scope::Scope;
+use crate::tasks::backup;
+use crate::torrent_client::blocking::TorrentClient;
+use crate::transmissionrpc::{Torrent, TorrentError, TorrentFields, TorrentStatus};
+use crate::util::time::{Timestamp, format_date, format_duration};
+
+pub fn rename(client: &TorrentClient, config: &ControllerConfig, hash: &str, name: Option<&str>) -> EmptyResult {
+    let torrent = client.get_torrent(hash, TorrentFields::basic().with_files())?;
+
+    let new_name = match name {
+        Some(name) => s!(name),
+        None => match rename::get_new_name(&config.rename_rules, &torrent.name) {
+            Some(name) => name,
+            None => {
+                info!("'{}' torrent doesn't match any rename rule.", torrent.name);
+                return Ok(());
+            },
+        },
+    };
+
+    rename::rename_torrent(client, &torrent, &new_name)?;
+    info!("'{}' torrent has been renamed to '{}'.", torrent.name, new_name);
+
+    Ok(())
+}
+
+pub fn reannounce(client: &TorrentClient, hashes: &[String]) -> EmptyResult {
+    for hash in hashes {
+        let torrent = client.get_torrent(hash, TorrentFields::basic())?;
+        info!("Reannouncing '{}' torrent...", torrent.name);
+        client.reannounce(hash)?;
+    }
+
+    Ok(())
+}
+
+/// Starts verification of the specified torrents and (if `wait` is set) waits for its completion resuming the
+/// torrents that have passed it.
+pub fn verify(client: &TorrentClient, hashes: &[String], wait: bool) -> EmptyResult {
+    for hash in hashes {
+        let torrent = client.get_torrent(hash, TorrentFields::basic())?;
+        info!("Verifying '{}' torrent...", torrent.name);
+        client.verify(hash)?;
+    }
+
+    if !wait {
+        return Ok(());
+    }
+
+    let mut failed = false;
+    let mut pending: HashSet<String> = hashes.iter().cloned().collect();
+
+    while !pending.is_empty() {
+        thread::sleep(Duration::from_secs(1));
+
+        let pending_hashes: Vec<String> = pending.iter().cloned().collect();
+        for torrent in client.get_torrents_by_hash(&pending_hashes, TorrentFields::basic())? {
+            if torrent.status.is_checking() {
+                continue;
+            }
+
+            pending.remove(&torrent.hash);
+
+            if let Some(TorrentError::LocalError(ref error)) = torrent.error {
+                error!("'{}' torrent has failed verification: {}.", torrent.name, error);
+                failed = true;
+                continue;
+            }
+
+            info!("'{}' torrent has been verified.", torrent.name);
+
+            if torrent.status == TorrentStatus::Stopped {
+                info!("Resuming '{}' torrent...", torrent.name);
+                client.start(&torrent.hash)?;
+            }
+        }
+    }
+
+    if failed {
+        return Err!("Some torrents have failed verification");
+    }
+
+    Ok(())
+}
+
+/// Prints a summary of the torrents managed by the controller
+pub fn status(
+    client: &TorrentClient, config: &ControllerConfig, download_dir: &Path, json: bool,
+) -> EmptyResult {
+    let torrents = get_managed_torrents(client, config, download_dir)?;
+    let free_space = client.get_free_space(&download_dir.to_string_lossy())?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&get_status_json(&torrents, free_space.free))?);
+        return Ok(());
+    }
+
+    let mut statuses = BTreeMap::new();
+    for torrent in &torrents {
+        *statuses.entry(torrent.status.to_string()).or_insert(0) += 1;
+    }
+
+    let pending = torrents.iter().filter(|torrent| torrent.done && !torrent.processed).count();
+    let processed = torrents.iter().filter(|torrent| torrent.processed).count();
+    let size: u64 = torrents.iter().map(|torrent| torrent.size).sum();
+
+    println!("Managed torrents: {} ({})", torrents.len(), format_size(size));
+    for (status, count) in statuses {
+        println!("  {}: {}", status, count);
+    }
+    println!("Pending copies: {}", pending);
+    println!("Processed: {}", processed);
+    println!("Free space: {}", format_size(free_space.free));
+
+    let errors: Vec<_> = torrents.iter().filter_map(|torrent| {
+        torrent.error.as_ref().map(|error| (torrent, error))
+    }).collect();
+
+    if !errors.is_empty() {
+        println!("Errors:");
+        for (torrent, error) in errors {
+            println!("  {}: {}", torrent.name, error);
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the torrents managed by the controller and their state
+pub fn list(client: &TorrentClient, config: &ControllerConfig, download_dir: &Path, json: bool) -> EmptyResult {
+    let policies = Policies::new(config.policies.clone());
+
+    let mut torrents = get_managed_torrents(client, config, download_dir)?;
+    torrents.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&get_list_json(&torrents, &policies))?);
+        return Ok(());
+    }
+
+    for torrent in torrents {
+        let state = match get_state(&torrent) {
+            "downloading" => format!("{}%", get_percent_done(&torrent)),
+            state => s!(state),
+        };
+
+        let ratio = torrent.upload_ratio.map(|ratio| format!("{:.2}", ratio)).unwrap_or_else(|| s!("-"));
+        let policy = policies.find(&torrent).map(|policy| policy.name.as_str()).unwrap_or("-");
+
+        println!("{} {:13} {:9} {:>6} {:10} {}", torrent.hash, torrent.status.to_string(), state, ratio, policy,
+                 torrent.name);
+
+        if let Some(ref error) = torrent.error {
+            println!("{:40} Error: {}", "", error);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn pause(client: &TorrentClient, hashes: &[String]) -> EmptyResult {
+    for hash in hashes {
+        let torrent = client.get_torrent(hash, TorrentFields::basic())?;
+        info!("Pausing '{}' torrent...", torrent.name);
+        client.stop(hash)?;
+    }
+
+    Ok(())
+}
+
+pub fn resume(client: &TorrentClient, hashes: &[String]) -> EmptyResult {
+    for hash in hashes {
+        let torrent = client.get_torrent(hash, TorrentFields::basic())?;
+        info!("Resuming '{}' torrent...", torrent.name);
+        client.start(hash)?;
+    }
+
+    Ok(())
+}
+
+/// Prints the torrents which have completed downloading in the specified period according to the history database
+pub fn history(config: &ControllerConfig, period: Option<(Timestamp, Timestamp)>, json: bool) -> EmptyResult {
+    let path = config.history_db.as_deref().ok_or("The history database isn't configured ('history-db' option)")?;
+    let (from, to) = period.unwrap_or((Timestamp::MIN, Timestamp::MAX));
+    let records = History::open(path)?.get_completed(from, to)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&records)?);
+        return Ok(());
+    }
+
+    for record in &records {
+        let ratio = record.ratio.map(|ratio| format!("{:.2}", ratio)).unwrap_or_else(|| s!("-"));
+        println!("{} {:>9} {:>6} {:20} {}", format_date(record.completed_time.unwrap_or_default()),
+                 format_size(record.size), ratio, record.tracker.as_deref().unwrap_or("-"), record.name);
+    }
+
+    let size: u64 = records.iter().map(|record| record.size).sum();
+    println!("Completed torrents: {} ({})", records.len(), format_size(size));
+
+    Ok(())
+}
+
+/// Restores Transmission's state from the backup
+pub fn restore(config: &ControllerConfig, backup: Option<&Path>) -> EmptyResult {
+    let path = backup::restore(&config.backup, backup)?;
+    info!("Transmission's state has been restored from '{}'.", path.display());
+    Ok(())
+}
+
+/// Asks the running daemon to run a full check (apply seeding limits, free space cleanup, etc.) right away
+pub fn cleanup(pid_file: Option<&Path>, control_socket: Option<&Path>) -> EmptyResult {
+    request_full_check(pid_file, control_socket)?;
+    info!("Full check has been requested.");
+    Ok(())
+}
+
+/// Wakes up the running daemon via its control socket or SIGUSR1 signal
+pub fn request_full_check(pid_file: Option<&Path>, control_socket: Option<&Path>) -> EmptyResult {
+    if let Some(path) = control_socket.filter(|_| pid_file.is_none()) {
+        control::send_command(path, "process-now")?;
+        return Ok(());
+    }
+
+    let path = pid_file.ok_or(
+        "The daemon's PID file must be specified via --pid-file option (or control-socket must be configured)")?;
+
+    let pid = match fs::read_to_string(path) {
+        Ok(data) => data.trim().parse::<libc::pid_t>().map_err(|_| format!(
+            "Invalid PID file: '{}'", path.display()))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err!("The daemon is not running"),
+        Err(e) => return Err!("Unable to read '{}': {}", path.display(), e),
+    };
+
+    if unsafe { libc::kill(pid, libc::SIGUSR1) } != 0 {
+        return Err!("Unable to send signal to the daemon (PID {}): {}", pid, io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Sends the command to the running daemon via its control socket
+pub fn control(control_socket: Option<&Path>, command: &str) -> EmptyResult {
+    let path = control_socket.ok_or("The daemon's control socket isn't configured ('control-socket' option)")?;
+    println!("{}", control::send_command(path, command)?);
+    Ok(())
+}
+
+/// Renders the notification template with the sample parameters to review it before any notification is sent
+pub fn render_template(path: &Path, params: &[(String, String)]) -> EmptyResult {
+    let template = EmailTemplate::new_from_file(path).map_err(|e| format!(
+        "Unable to read '{}' template: {}", path.display(), e))?;
+
+    let (subject, body) = get_template_preview(&template, params)?;
+    println!("Subject: {}\n\n{}", subject, body);
+
+    let placeholder_re = Regex::new(r"\{\{(\w+)\}\}").unwrap();
+    let mut unknown: Vec<&str> = placeholder_re.captures_iter(&subject).chain(placeholder_re.captures_iter(&body))
+        .map(|captures| captures.get(1).unwrap().as_str()).collect();
+    unknown.sort();
+    unknown.dedup();
+
+    if !unknown.is_empty() {
+        warn!("The template has unknown parameters: {}.", unknown.join(", "));
+    }
+
+    Ok(())
+}
+
+fn get_template_preview(template: &EmailTemplate, params: &[(String, String)]) -> GenericResult<(String, String)> {
+    let copy_duration = format_duration(150);
+    let list = |items: &[&str]| items.iter().map(|item| format!("* {}", item)).collect::<Vec<_>>().join("\n");
+
+    // Both torrent downloaded notification and statistics report parameters
+    let mut template_params: HashMap<&str, String> = HashMap::from([
+        ("name", s!("Ubuntu 24.04 Desktop")),
+        ("copy_duration", copy_duration.clone()),
+        ("copy_details", format!(" It has been copied to '/media/Ubuntu 24.04 Desktop' in {}.", copy_duration)),
+
+        ("period", format_duration(7 * 24 * 60 * 60)),
+        ("added", s!("3")),
+        ("completed", s!("2")),
+        ("removed", s!("1")),
+        ("torrents", s!("42")),
+        ("downloaded", format_size(12 * 1024 * 1024 * 1024)),
+        ("uploaded", format_size(30 * 1024 * 1024 * 1024)),
+        ("downloads", list(&[
+            &format!("Ubuntu 24.04 Desktop ({})", format_size(5 * 1024 * 1024 * 1024)),
+            &format!("Debian 12 Netinst ({})", format_size(628 * 1024 * 1024)),
+        ])),
+        ("trackers", list(&["tracker.example.com: 1.53 (40 torrents)"])),
+        ("tracker_traffic", list(&[&format!(
+            "tracker.example.com: {} uploaded, {} downloaded",
+            format_size(30 * 1024 * 1024 * 1024), format_size(12 * 1024 * 1024 * 1024))])),
+        ("disk_space", list(&[&format!("/downloads: {} free", format_size(1024 * 1024 * 1024 * 1024))])),
+        ("copy_failures", s!("0")),
+        ("rpc_errors", s!("0")),
+    ]);
+
+    for (key, value) in params {
+        template_params.insert(key, value.clone());
+    }
+
+    template.render(&template_params)
+}
+
+/// The JSON schema is a part of the public interface (see README.md), so it must be changed only in a compatible way
+fn get_status_json(torrents: &[Torrent], free_space: u64) -> Value {
+    let mut statuses = BTreeMap::new();
+    for torrent in torrents {
+        *statuses.entry(torrent.status.to_string()).or_insert(0) += 1;
+    }
+
+    json!({
+        "torrents": torrents.len(),
+        "size": torrents.iter().map(|torrent| torrent.size).sum::<u64>(),
+        "statuses": statuses,
+        "pending": torrents.iter().filter(|torrent| torrent.done && !torrent.processed).count(),
+        "processed": torrents.iter().filter(|torrent| torrent.processed).count(),
+        "free_space": free_space,
+        "errors": torrents.iter().filter_map(|torrent| torrent.error.as_ref().map(|error| json!({
+            "hash": torrent.hash,
+            "name": torrent.name,
+            "error": error.to_string(),
+        }))).collect::<Vec<_>>(),
+    })
+}
+
+fn get_list_json(torrents: &[Torrent], policies: &Policies) -> Value {
+    Value::Array(torrents.iter().map(|torrent| json!({
+        "hash": torrent.hash,
+        "name": torrent.name,
+        "status": torrent.status.to_string(),
+        "state": get_state(torrent),
+        "percent_done": get_percent_done(torrent),
+        "size": torrent.size,
+        "ratio": torrent.upload_ratio,
+        "policy": policies.find(torrent).map(|policy| policy.name.as_str()),
+        "error": torrent.error.as_ref().map(ToString::to_string),
+    })).collect())
+}
+
+/// Processing state of the torrent: downloading, pending (copying) or processed
+pub fn get_state(torrent: &Torrent) -> &'static str {
+    if !torrent.done {
+        "downloading"
+    } else if torrent.processed {
+        "processed"
+    } else {
+        "pending"
+    }
+}
+
+pub fn get_percent_done(torrent: &Torrent) -> u64 {
+    (torrent.size - torrent.left_until_done) * 100 / torrent.size.max(1)
+}
+
+fn get_managed_torrents(
+    client: &TorrentClient, config: &ControllerConfig, download_dir: &Path,
+) -> GenericResult<Vec<Torrent>> {
+    let scope = Scope::new(&config.manage_only, &config.ignore, download_dir);
+    Ok(client.get_torrents(TorrentFields::basic())?.into_iter().filter(|torrent| scope.contains(torrent)).collect())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    #[test]
+    fn test_json_output() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+
+        server.add_torrent(MockTorrent::new(1, "processed", "/downloads").processed());
+        let mut torrent = MockTorrent::new(2, "downloading", "/downloads").downloading();
+        torrent.left_until_done = 256;
+        torrent.error = 3;
+        torrent.error_string = s!("No data found!");
+        server.add_torrent(torrent);
+
+        let torrents = runtime.block_on(server.client().get_torrents(TorrentFields::basic())).unwrap();
+
+        assert_eq!(get_status_json(&torrents, 4096), json!({
+            "torrents": 2,
+            "size": 2048,
+            "statuses": {"downloading": 1, "seeding": 1},
+            "pending": 0,
+            "processed": 1,
+            "free_space": 4096,
+            "errors": [{
+                "hash": format!("{:040x}", 2),
+                "name": "downloading",
+                "error": torrents[1].error.as_ref().unwrap().to_string(),
+            }],
+        }));
+
+        assert_eq!(get_list_json(&torrents, &Policies::new(Vec::new())), json!([{
+            "hash": format!("{:040x}", 1),
+            "name": "processed",
+            "status": "seeding",
+            "state": "processed",
+            "percent_done": 100,
+            "size": 1024,
+            "ratio": null,
+            "policy": null,
+            "error": null,
+        }, {
+            "hash": format!("{:040x}", 2),
+            "name": "downloading",
+            "status": "downloading",
+            "state": "downloading",
+            "percent_done": 75,
+            "size": 1024,
+            "ratio": null,
+            "policy": null,
+            "error": torrents[1].error.as_ref().unwrap().to_string(),
+        }]));
+    }
+
+    #[test]
+    fn test_template_preview() {
+        let template = EmailTemplate::new("{{name}} has been downloaded", "Done.{{copy_details}} {{unknown}}");
+        assert_eq!(get_template_preview(&template, &[]).unwrap(), (
+            s!("Ubuntu 24.04 Desktop has been downloaded"),
+            s!("Done. It has been copied to '/media/Ubuntu 24.04 Desktop' in 2m 30s. {{unknown}}"),
+        ));
+
+        let params = [(s!("name"), s!("Debian")), (s!("copy_details"), String::new())];
+        assert_eq!(get_template_preview(&template, &params).unwrap(), (
+            s!("Debian has been downloaded"), s!("Done. {{unknown}}"),
+        ));
+    }
+}
@@ -1,3 +1,6 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
 pub type EmptyResult = GenericResult<()>;
 pub type GenericResult<T> = Result<T, GenericError>;
 pub type GenericError = Box<dyn std::error::Error + Send + Sync>;
@@ -13,3 +16,94 @@ macro_rules! format_to {
 macro_rules! Err {
     ($($arg:tt)*) => (::std::result::Result::Err(format_to!($($arg)*)))
 }
+
+/// An error which tells what has failed and on which object. The original error is kept as its source, so the
+/// messages form a chain like "Failed to copy 'X' torrent: Failed to copy '/a/b/c': Permission denied (os error 13)".
+#[derive(Debug)]
+pub enum Error {
+    Torrent {action: &'static str, name: String, source: GenericError},
+    File {action: &'static str, path: PathBuf, source: GenericError},
+    Rename {src: PathBuf, dst: PathBuf, source: GenericError},
+    AlreadyExists(PathBuf),
+    Verification(GenericError),
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Torrent {source, ..} | Error::File {source, ..} | Error::Rename {source, ..} |
+            Error::Verification(source) => Some(source.as_ref()),
+            Error::AlreadyExists(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Torrent {action, name, source} => write!(f, "{} '{}' torrent: {}", action, name, source),
+            Error::File {action, path, source} => write!(f, "{} '{}': {}", action, path.display(), source),
+            Error::Rename {src, dst, source} => write!(
+                f, "Failed to rename '{}' to '{}': {}", src.display(), dst.display(), source),
+            Error::AlreadyExists(path) => write!(f, "'{}' already exists", path.display()),
+            Error::Verification(source) => write!(f, "Copy verification failed: {}", source),
+        }
+    }
+}
+
+pub trait ErrorContext<T> {
+    fn torrent_context(self, action: &'static str, name: &str) -> GenericResult<T>;
+    fn file_context<P: AsRef<Path>>(self, action: &'static str, path: P) -> GenericResult<T>;
+    fn rename_context<S: AsRef<Path>, D: AsRef<Path>>(self, src: S, dst: D) -> GenericResult<T>;
+    fn verification_context(self) -> GenericResult<T>;
+}
+
+impl<T, E: Into<GenericError>> ErrorContext<T> for Result<T, E> {
+    fn torrent_context(self, action: &'static str, name: &str) -> GenericResult<T> {
+        self.map_err(|e| Error::Torrent {action: action, name: s!(name), source: e.into()}.into())
+    }
+
+    fn file_context<P: AsRef<Path>>(self, action: &'static str, path: P) -> GenericResult<T> {
+        self.map_err(|e| Error::File {action: action, path: path.as_ref().to_owned(), source: e.into()}.into())
+    }
+
+    fn rename_context<S: AsRef<Path>, D: AsRef<Path>>(self, src: S, dst: D) -> GenericResult<T> {
+        self.map_err(|e| Error::Rename {
+            src: src.as_ref().to_owned(), dst: dst.as_ref().to_owned(), source: e.into(),
+        }.into())
+    }
+
+    fn verification_context(self) -> GenericResult<T> {
+        self.map_err(|e| Error::Verification(e.into()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+    use std::io;
+
+    use super::*;
+
+    #[test]
+    fn test_error_chain() {
+        let result: io::Result<()> = Err(io::Error::from_raw_os_error(libc::EACCES));
+        let error = result.file_context("Failed to copy", "/a/b/c").torrent_context("Failed to copy", "X")
+            .unwrap_err();
+
+        assert_eq!(error.to_string(),
+                   "Failed to copy 'X' torrent: Failed to copy '/a/b/c': Permission denied (os error 13)");
+
+        let source = error.source().unwrap().downcast_ref::<Error>().unwrap();
+        assert!(matches!(source, Error::File {path, ..} if path == Path::new("/a/b/c")));
+        assert_eq!(source.source().unwrap().downcast_ref::<io::Error>().unwrap().raw_os_error(), Some(libc::EACCES));
+
+        let result: io::Result<()> = Err(io::Error::from_raw_os_error(libc::EXDEV));
+        assert_eq!(result.rename_context("/a/b", "/c/d").unwrap_err().to_string(),
+                   "Failed to rename '/a/b' to '/c/d': Invalid cross-device link (os error 18)");
+
+        let result: Result<(), &str> = Err("'/a/b' has an invalid size");
+        assert_eq!(result.verification_context().unwrap_err().to_string(),
+                   "Copy verification failed: '/a/b' has an invalid size");
+    }
+}
@@ -1,14 +1,60 @@
 #![allow(deprecated)] // We still use deprecated RustcDecodable here
 
+use std::collections::HashSet;
 use std::error::Error;
 use std::fmt;
 use std::fs::File;
 use std::io;
-use std::path::Path;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, de};
 
+use crate::availability::AvailabilityConfig;
+use crate::common::GenericResult;
+use crate::duplicates::DuplicatesConfig;
+use crate::email::EmailTemplate;
+use crate::hooks::HooksConfig;
+use crate::http::HttpConfig;
+use crate::integrations::IntegrationsConfig;
+use crate::logging::{LogFileConfig, LogFilter, LogFormat, SyslogConfig};
+use crate::maintenance::MaintenanceConfig;
+use crate::mqtt::MqttConfig;
+use crate::notifications::NotificationsConfig;
+use crate::policy::Policy;
+use crate::progress::ProgressNotificationsConfig;
+use crate::quotas::LabelQuota;
+use crate::rename::{RenameRule, SortRule};
+use crate::retention::RetentionRule;
+use crate::routing::Route;
+use crate::scope::{IgnoreConfig, ManageOnlyConfig};
+use crate::seed_cycling::SeedCyclingConfig;
+use crate::seeding::SeedingLimit;
+use crate::speed_schedule::SpeedScheduleRule;
+use crate::tasks::backup::BackupConfig;
+use crate::tasks::backup_trackers::BackupTrackersConfig;
+use crate::tasks::dedup::DeduplicationConfig;
+use crate::tasks::library_retention::LibraryRetentionConfig;
+use crate::tasks::port_forwarding::PortForwardingConfig;
+use crate::tasks::report::ReportConfig;
+use crate::tasks::rss::RssConfig;
+use crate::tasks::tracker_errors::TrackerMonitoringConfig;
+use crate::telegram::TelegramConfig;
+use crate::torrent_client::Backend;
+use crate::torrent_errors::TorrentErrorsConfig;
+use crate::verification::PeriodicVerificationConfig;
+use crate::vpn::VpnConfig;
+use crate::tracker_limits::TrackerSpeedLimit;
+use crate::trackers::{TrackerRewriteRule, TrackersConfig};
+use crate::transmissionrpc::{AddressFamily, Torrent};
+use crate::unpack::UnpackConfig;
 use crate::util;
+use crate::util::fs::FilePermissions;
+use crate::util::matching::{FilePattern, HostPattern};
+use crate::util::time::WeekPeriods;
+use crate::webhooks::WebhookKind;
+use crate::xmpp::XmppClient;
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
@@ -30,6 +76,408 @@ pub struct Config {
     pub rpc_plain_password: Option<String>,
 }
 
+/// Controller's own configuration which complements Transmission's settings.json
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ControllerConfig {
+    /// The torrent client to work with
+    pub backend: Backend,
+    pub rpc: RpcConfig,
+    pub manage_only: ManageOnlyConfig,
+    pub ignore: IgnoreConfig,
+    pub policies: Vec<Policy>,
+    pub bandwidth_groups: Vec<BandwidthGroupConfig>,
+    pub speed_schedule: Vec<SpeedScheduleRule>,
+    pub rename_rules: Vec<RenameRule>,
+    pub sort_rules: Vec<SortRule>,
+    pub routes: Vec<Route>,
+    /// Files which are never copied to the destination
+    pub exclude_files: Vec<FilePattern>,
+    pub unpack: UnpackConfig,
+    pub seeding_limits: Vec<SeedingLimit>,
+    pub seed_cycling: SeedCyclingConfig,
+    pub periodic_verification: PeriodicVerificationConfig,
+    pub retention: Vec<RetentionRule>,
+    pub removal: RemovalConfig,
+    pub auto_resume: AutoResumeConfig,
+    pub duplicates: DuplicatesConfig,
+    pub trackers: TrackersConfig,
+    pub tracker_rewrites: Vec<TrackerRewriteRule>,
+    pub tracker_speed_limits: Vec<TrackerSpeedLimit>,
+    pub free_space: FreeSpaceConfig,
+    pub label_quotas: Vec<LabelQuota>,
+    pub orphaned_files: OrphanedFilesConfig,
+    pub deduplication: DeduplicationConfig,
+    pub library_retention: LibraryRetentionConfig,
+    pub watch_dir: WatchDirConfig,
+    pub rss: RssConfig,
+    pub tracker_monitoring: TrackerMonitoringConfig,
+    pub backup_trackers: BackupTrackersConfig,
+    pub report: ReportConfig,
+    pub torrent_errors: TorrentErrorsConfig,
+    pub progress_notifications: ProgressNotificationsConfig,
+    pub availability: AvailabilityConfig,
+    pub vpn: VpnConfig,
+    pub maintenance: MaintenanceConfig,
+    pub port_forwarding: PortForwardingConfig,
+    pub backup: BackupConfig,
+    pub hooks: HooksConfig,
+
+    #[serde(deserialize_with = "deserialize_optional_duration")]
+    pub blocklist_update_interval: Option<Duration>,
+    #[serde(deserialize_with = "deserialize_optional_duration")]
+    pub port_test_interval: Option<Duration>,
+    /// Maximum number of simultaneously downloading torrents
+    pub max_active_downloads: Option<usize>,
+    /// Time without download progress after which the torrent is considered stalled
+    #[serde(deserialize_with = "deserialize_optional_duration")]
+    pub stalled_torrent_timeout: Option<Duration>,
+
+    /// Verify torrent's local data when it fails to be copied and retry the copying if verification passes
+    pub verify_after_copy_failure: bool,
+    /// Check the copied files against the originals before the torrent is considered processed
+    pub verify_copies: CopyVerification,
+    /// How to put torrent's files to the destination directory
+    pub link_mode: LinkMode,
+    /// Copy the files by reading and writing them instead of using reflinks and copy_file_range()
+    pub legacy_copy: bool,
+    /// Whether the torrents are copied to the destination or moved there to be seeded from the new location
+    pub transfer_mode: TransferMode,
+    pub copy_throttling: CopyThrottlingConfig,
+    pub copy_permissions: CopyPermissionsConfig,
+    /// Number of threads copying the torrents concurrently
+    pub copy_workers: Option<usize>,
+    /// Maximum number of torrents which are copied to the same destination concurrently
+    pub copy_workers_per_destination: Option<usize>,
+    /// Free space in MB which must remain on the destination file system after copying
+    pub copy_space_reserve: Option<u64>,
+    /// File to store the processing history in (the processed torrents and the notifications sent for them)
+    pub state_file: Option<PathBuf>,
+    /// SQLite database to keep the long-term history of the torrents in (for the statistics and `history` command)
+    pub history_db: Option<PathBuf>,
+    /// Directory to archive the added torrents' .torrent files in
+    pub torrent_archive_dir: Option<PathBuf>,
+    /// Timezone for the schedules (the system one by default)
+    pub timezone: Option<String>,
+    /// Lock file which prevents several daemons from managing the same Transmission instance (named after the RPC URL
+    /// in the temporary directory by default)
+    pub lock_file: Option<PathBuf>,
+    /// Per-module log levels (see `LogFilter`)
+    pub log_level: Option<String>,
+    pub log_format: LogFormat,
+    pub log_file: LogFileConfig,
+    pub syslog: SyslogConfig,
+    /// Append-only log of the state-changing actions
+    pub audit_log: Option<PathBuf>,
+    pub http: HttpConfig,
+    pub telegram: TelegramConfig,
+    pub mqtt: MqttConfig,
+    pub integrations: IntegrationsConfig,
+    pub notifications: NotificationsConfig,
+    /// UNIX socket to control the running daemon through
+    pub control_socket: Option<PathBuf>,
+    pub poll: PollConfig,
+    /// Maximum time to wait for the in-flight copies on shutdown (5 minutes by default)
+    #[serde(deserialize_with = "deserialize_optional_duration")]
+    pub shutdown_timeout: Option<Duration>,
+}
+
+/// How often the daemon checks the torrents
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct PollConfig {
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub interval: Duration,
+    /// Random delay up to this value is added to each interval to not check in sync with other instances
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub jitter: Duration,
+    /// Shorter interval which is used while any torrent is being copied or is about to be downloaded
+    #[serde(deserialize_with = "deserialize_optional_duration")]
+    pub fast_interval: Option<Duration>,
+    /// The downloads with ETA less than this are considered almost done
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub fast_eta: Duration,
+}
+
+impl Default for PollConfig {
+    fn default() -> PollConfig {
+        PollConfig {
+            interval: Duration::from_secs(5),
+            jitter: Duration::ZERO,
+            fast_interval: None,
+            fast_eta: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Limits of the copying to not starve the other disk users (Transmission itself, media servers, etc.)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CopyThrottlingConfig {
+    /// Copy speed limit in MB/s
+    pub speed_limit: Option<u64>,
+    /// I/O scheduling class of the torrent consuming threads (copying, verification and unpacking)
+    pub io_class: IoClass,
+    /// Priority within the best-effort I/O class: from 0 (the highest) to 7 (the lowest, default)
+    pub io_priority: Option<u8>,
+    /// CPU scheduling priority (nice value) of the torrent consuming threads
+    pub nice: Option<i32>,
+}
+
+/// Ownership and permissions of the copied files (to make them accessible to media servers, for example). Hard links
+/// and moved torrents share the data with Transmission, so they are left untouched.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CopyPermissionsConfig {
+    /// User name or ID
+    pub owner: Option<String>,
+    /// Group name or ID
+    pub group: Option<String>,
+    #[serde(deserialize_with = "deserialize_optional_mode")]
+    pub file_mode: Option<u32>,
+    #[serde(deserialize_with = "deserialize_optional_mode")]
+    pub dir_mode: Option<u32>,
+}
+
+impl CopyPermissionsConfig {
+    pub fn resolve(&self) -> GenericResult<FilePermissions> {
+        Ok(FilePermissions {
+            uid: self.owner.as_deref().map(util::fs::get_user_id).transpose()?,
+            gid: self.group.as_deref().map(util::fs::get_group_id).transpose()?,
+            file_mode: self.file_mode,
+            dir_mode: self.dir_mode,
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoClass {
+    /// Don't change I/O priority
+    #[default]
+    Normal,
+    /// Best effort class with the lowest priority
+    BestEffort,
+    /// Get disk time only when no one else needs it
+    Idle,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkMode {
+    /// Create hard links when the download and destination directories are on the same file system
+    #[default]
+    Auto,
+    Copy,
+    Hardlink,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TransferMode {
+    #[default]
+    Copy,
+    /// Rename the data when the directories are on the same file system and copy and delete it otherwise
+    Move,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CopyVerification {
+    #[default]
+    None,
+    /// Compare file sizes and a few sampled blocks
+    Fast,
+    /// Compare the whole contents of the files
+    Full,
+    /// Check the copies against the piece hashes from the torrent's .torrent file
+    Pieces,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RpcConfig {
+    /// Overrides the RPC URL derived from Transmission's settings (for example, when the daemon is behind a reverse
+    /// proxy).
+    pub url: Option<String>,
+    /// Connect to RPC via UNIX domain socket instead of TCP
+    pub socket_path: Option<PathBuf>,
+
+    /// CA certificate file (PEM) to verify the server's certificate with
+    pub ca_file: Option<PathBuf>,
+    pub verify_certificate: bool,
+    /// The name to send via SNI and verify the certificate against instead of the URL's host
+    pub server_name: Option<String>,
+    /// Client certificate (PEM) and its private key (PKCS #8 PEM) to authenticate with to a mutual TLS proxy
+    pub client_certificate: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    /// Address family to try first when RPC host resolves to both IPv4 and IPv6 addresses
+    pub prefer_address_family: Option<AddressFamily>,
+
+    pub retry: RetryConfig,
+}
+
+impl Default for RpcConfig {
+    fn default() -> RpcConfig {
+        RpcConfig {
+            url: None,
+            socket_path: None,
+            ca_file: None,
+            verify_certificate: true,
+            server_name: None,
+            client_certificate: None,
+            client_key: None,
+            prefer_address_family: None,
+            retry: RetryConfig::default(),
+        }
+    }
+}
+
+/// Automatic removal of the torrents which have been consumed (copied to the destination directory)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RemovalConfig {
+    /// Time since the torrent has been downloaded after which it's removed even if it hasn't reached its seeding
+    /// target yet
+    #[serde(deserialize_with = "deserialize_optional_duration")]
+    pub grace_period: Option<Duration>,
+    /// Delete torrent's local data along with the torrent
+    pub delete_data: bool,
+    /// Send a notification with the list of removed torrents and the reclaimed space
+    pub notify: bool,
+    /// Move the data to this directory instead of deleting it (except when the cleanup is forced by lack of free space)
+    pub trash_dir: Option<PathBuf>,
+    /// Time after which the data is deleted from the trash
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub trash_retention: Duration,
+    /// The torrents with this label are exempted from seeding limits, retention rules and cleanup (but are still
+    /// copied and reported on)
+    pub keep_label: Option<String>,
+}
+
+impl Default for RemovalConfig {
+    fn default() -> RemovalConfig {
+        RemovalConfig {
+            grace_period: None,
+            delete_data: true,
+            notify: false,
+            trash_dir: None,
+            trash_retention: Duration::from_secs(7 * 24 * 60 * 60),
+            keep_label: None,
+        }
+    }
+}
+
+/// Resuming of the downloads which have been stopped not by the controller (by Transmission on disk full or by
+/// accident via the web UI, for example). Applies only when the controller doesn't manage the torrents by schedule.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct AutoResumeConfig {
+    pub enabled: bool,
+    /// Send a notification when a torrent is resumed
+    pub notify: bool,
+    /// The torrents which are paused intentionally and must stay paused
+    pub exclude_labels: Vec<String>,
+    pub exclude_trackers: Vec<HostPattern>,
+}
+
+impl AutoResumeConfig {
+    pub fn is_excluded(&self, torrent: &Torrent) -> bool {
+        let tracker_hosts = torrent.tracker_hosts();
+
+        self.exclude_labels.iter().any(|label| torrent.labels.contains(label)) ||
+        self.exclude_trackers.iter().any(|pattern| pattern.matches_any(tracker_hosts.iter().map(String::as_str)))
+    }
+}
+
+/// Free space monitoring of the download directory. Thresholds are in percents of the file system size.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FreeSpaceConfig {
+    /// Send a notification when free space drops to the threshold
+    pub warning_threshold: Option<u8>,
+    /// Apply the actions below when free space drops to the threshold
+    pub critical_threshold: Option<u8>,
+    /// Pause the downloading torrents until free space recovers
+    pub pause_downloads: bool,
+    /// Remove the oldest processed torrents until free space recovers
+    pub cleanup: bool,
+}
+
+/// Periodic search for the files in the download directory which don't belong to any torrent
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct OrphanedFilesConfig {
+    #[serde(deserialize_with = "deserialize_optional_duration")]
+    pub check_interval: Option<Duration>,
+    /// Delete the orphaned files which haven't been modified for the specified time (otherwise only report them)
+    #[serde(deserialize_with = "deserialize_optional_duration")]
+    pub delete_after: Option<Duration>,
+}
+
+/// Directory to add .torrent files and magnet links (.magnet files) from
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct WatchDirConfig {
+    pub path: Option<PathBuf>,
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub check_interval: Duration,
+    /// Move the added files to the directory instead of deleting them
+    pub archive_dir: Option<PathBuf>,
+    /// Download the torrents from subdirectories of the watch directory to the same subdirectories of the download
+    /// directory
+    pub subdir_download_dirs: bool,
+}
+
+impl Default for WatchDirConfig {
+    fn default() -> WatchDirConfig {
+        WatchDirConfig {
+            path: None,
+            check_interval: Duration::from_secs(10),
+            archive_dir: None,
+            subdir_download_dirs: false,
+        }
+    }
+}
+
+/// Retry policy for RPC calls and webhook requests failed due to transient errors
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RetryConfig {
+    pub max_attempts: u32,
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub initial_delay: Duration,
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub max_delay: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> RetryConfig {
+        RetryConfig {
+            max_attempts: 3,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Transmission 4.x bandwidth group which new torrents are assigned to by their labels or trackers
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BandwidthGroupConfig {
+    pub name: String,
+    /// Speed limits in KB/s
+    pub download_limit: Option<u64>,
+    pub upload_limit: Option<u64>,
+    #[serde(default = "default_true")]
+    pub honors_session_limits: bool,
+
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub trackers: Vec<HostPattern>,
+}
+
 #[derive(Debug)]
 pub enum ConfigReadingError {
     Io(io::Error),
@@ -49,6 +497,16 @@ pub fn read_config<P: AsRef<Path>>(path: P) -> Result<Config> {
     Ok(config)
 }
 
+pub fn read_controller_config<P: AsRef<Path>>(path: P) -> Result<ControllerConfig> {
+    let mut data = String::new();
+    File::open(path)?.read_to_string(&mut data)?;
+
+    let config: ControllerConfig = toml::from_str(&data)?;
+    validate_controller_config(&config)?;
+
+    Ok(config)
+}
+
 fn validate_config(config: &Config) -> Result<()> {
     let error = |e: &str| Err(Validation(s!(e)));
 
@@ -74,6 +532,498 @@ fn validate_config(config: &Config) -> Result<()> {
     Ok(())
 }
 
+fn validate_controller_config(config: &ControllerConfig) -> Result<()> {
+    let error = |e: &str| Err(Validation(s!(e)));
+    let rpc = &config.rpc;
+
+    let https = match rpc.url {
+        Some(ref url) => {
+            let url = reqwest::Url::parse(url).map_err(|e| Validation(format!(
+                "Invalid 'rpc.url' value: {}", e)))?;
+
+            match url.scheme() {
+                "http" => false,
+                "https" => true,
+                _ => return error("Invalid 'rpc.url' value: only http:// and https:// URLs are supported"),
+            }
+        },
+        None => false,
+    };
+
+    if !https && (
+        rpc.ca_file.is_some() || rpc.server_name.is_some() || !rpc.verify_certificate ||
+        rpc.client_certificate.is_some() || rpc.client_key.is_some()
+    ) {
+        return error("TLS options in 'rpc' section may be specified only for https:// RPC URL");
+    }
+
+    if let Some(ref path) = rpc.socket_path {
+        if !path.is_absolute() {
+            return error("Invalid 'rpc.socket-path' value: it must be an absolute path");
+        }
+
+        if https {
+            return error("'rpc.socket-path' can't be used with https:// RPC URL");
+        }
+    }
+
+    for (name, path) in [("ca-file", &rpc.ca_file), ("client-certificate", &rpc.client_certificate),
+                         ("client-key", &rpc.client_key)] {
+        if path.as_ref().is_some_and(|path| !path.is_absolute()) {
+            return Err(Validation(format!("Invalid 'rpc.{}' value: it must be an absolute path", name)));
+        }
+    }
+
+    if rpc.client_certificate.is_some() != rpc.client_key.is_some() {
+        return error("Invalid 'rpc' section: both 'client-certificate' and 'client-key' must be specified");
+    }
+
+    if let Some(ref server_name) = rpc.server_name {
+        if server_name.trim().is_empty() {
+            return error("Invalid 'rpc.server-name' value: it mustn't be empty");
+        }
+    }
+
+    if rpc.retry.max_attempts == 0 {
+        return error("Invalid 'rpc.retry.max-attempts' value: it must be positive");
+    }
+
+    if rpc.retry.initial_delay > rpc.retry.max_delay {
+        return error("Invalid 'rpc.retry' section: 'initial-delay' must not be greater than 'max-delay'");
+    }
+
+    if config.backup.config_dir.is_some() != config.backup.path.is_some() {
+        return error("Invalid backup configuration: both 'config-dir' and 'path' must be specified");
+    }
+
+    if config.seed_cycling.max_active == Some(0) {
+        return error("Invalid 'seed-cycling.max-active' value: it must be positive");
+    }
+
+    if config.max_active_downloads == Some(0) {
+        return error("Invalid 'max-active-downloads' value: it must be positive");
+    }
+
+    for (name, value) in [
+        ("copy-workers", config.copy_workers), ("copy-workers-per-destination", config.copy_workers_per_destination),
+    ] {
+        if value == Some(0) {
+            return error(&format!("Invalid '{}' value: it must be positive", name));
+        }
+    }
+
+    if config.copy_throttling.speed_limit == Some(0) {
+        return error("Invalid copy speed limit: it must be positive");
+    }
+
+    if let Some(priority) = config.copy_throttling.io_priority {
+        if priority > 7 {
+            return error("Invalid I/O priority: it must be in 0-7 range");
+        } else if config.copy_throttling.io_class != IoClass::BestEffort {
+            return error("I/O priority may be specified only for best-effort I/O class");
+        }
+    }
+
+    if config.copy_throttling.nice.is_some_and(|nice| !(-20..=19).contains(&nice)) {
+        return error("Invalid nice value: it must be in -20-19 range");
+    }
+
+    let free_space = &config.free_space;
+    for (name, threshold) in [("warning", free_space.warning_threshold), ("critical", free_space.critical_threshold)] {
+        if threshold.is_some_and(|threshold| threshold > 100) {
+            return Err(Validation(format!("Invalid 'free-space.{}-threshold' value: it must be a percent", name)));
+        }
+    }
+
+    if let (Some(warning), Some(critical)) = (free_space.warning_threshold, free_space.critical_threshold) {
+        if warning < critical {
+            return error("Invalid 'free-space' section: warning threshold must not be less than the critical one");
+        }
+    }
+
+    if (free_space.pause_downloads || free_space.cleanup) && free_space.critical_threshold.is_none() {
+        return error("Invalid 'free-space' section: the actions require critical threshold to be specified");
+    }
+
+    for (name, path) in [("path", &config.watch_dir.path), ("archive-dir", &config.watch_dir.archive_dir)] {
+        if path.as_ref().is_some_and(|path| !path.is_absolute()) {
+            return error(&format!("Invalid 'watch-dir.{}' value: it must be an absolute path", name));
+        }
+    }
+
+    if let Some(hash) = config.ignore.hashes.iter().find(|hash| {
+        hash.len() != 40 || !hash.chars().all(|c| c.is_ascii_hexdigit())
+    }) {
+        return Err(Validation(format!("Invalid ignored torrent hash: {:?}", hash)));
+    }
+
+    if config.ignore.label.as_ref().is_some_and(|label| label.is_empty()) {
+        return error("Invalid 'ignore.label' value: it mustn't be empty");
+    }
+
+    for rule in &config.library_retention.rules {
+        if !rule.path.is_absolute() {
+            return Err(Validation(format!(
+                "Invalid library retention rule path: '{}' - it must be absolute", rule.path.display())));
+        } else if rule.max_age.is_none() && rule.max_size.is_none() && rule.keep_last.is_none() {
+            return Err(Validation(format!(
+                "Invalid '{}' library retention rule: no limits are specified", rule.path.display())));
+        }
+    }
+
+    if config.removal.trash_dir.as_ref().is_some_and(|path| !path.is_absolute()) {
+        return error("Invalid 'removal.trash-dir' value: it must be an absolute path");
+    }
+
+    if config.log_file.path.as_ref().is_some_and(|path| !path.is_absolute()) {
+        return error("Invalid 'log-file.path' value: it must be an absolute path");
+    }
+
+    if config.syslog.enabled && config.log_file.path.is_some() {
+        return error("Logging to syslog can't be used with logging to a file");
+    }
+
+    if config.log_file.max_size == Some(0) {
+        return error("Invalid 'log-file.max-size' value: it must be positive");
+    }
+
+    if config.audit_log.as_ref().is_some_and(|path| !path.is_absolute()) {
+        return error("Invalid 'audit-log' value: it must be an absolute path");
+    }
+
+    if config.poll.interval.is_zero() {
+        return error("Invalid 'poll.interval' value: it must be positive");
+    }
+
+    if config.poll.fast_interval.is_some_and(|interval| interval.is_zero() || interval > config.poll.interval) {
+        return error("Invalid 'poll.fast-interval' value: it must be positive and not greater than 'poll.interval'");
+    }
+
+    if let Some(ref timezone) = config.timezone {
+        util::time::check_timezone(timezone).map_err(|e| Validation(format!("Invalid 'timezone' value: {}", e)))?;
+    }
+
+    if let Some(ref spec) = config.log_level {
+        LogFilter::parse(spec).map_err(|e| Validation(format!("Invalid 'log-level' value: {}", e)))?;
+    }
+
+    if config.control_socket.as_ref().is_some_and(|path| !path.is_absolute()) {
+        return error("Invalid 'control-socket' value: it must be an absolute path");
+    }
+
+    if config.http.api_token.as_ref().is_some_and(|token| token.trim().is_empty()) {
+        return error("Invalid 'http.api-token' value: it mustn't be empty");
+    }
+
+    if config.telegram.bot_token.as_ref().is_some_and(|token| token.trim().is_empty()) {
+        return error("Invalid 'telegram.bot-token' value: it mustn't be empty");
+    }
+
+    if config.telegram.bot_token.is_some() != config.telegram.chat_id.is_some() {
+        return error("'telegram.bot-token' and 'telegram.chat-id' must be specified together");
+    }
+
+    if config.telegram.commands && config.telegram.bot_token.is_none() {
+        return error("Telegram bot commands require 'telegram.bot-token' and 'telegram.chat-id' to be specified");
+    }
+
+    reqwest::Url::parse(&config.telegram.api_url).map_err(|e| Validation(format!(
+        "Invalid 'telegram.api-url' value: {}", e)))?;
+
+    if config.mqtt.broker.as_ref().is_some_and(|broker| broker.trim().is_empty()) {
+        return error("Invalid 'mqtt.broker' value: it mustn't be empty");
+    }
+
+    if config.mqtt.topic_prefix.is_empty() || config.mqtt.topic_prefix.contains(['+', '#']) {
+        return error("Invalid 'mqtt.topic-prefix' value: it must be a non-empty topic name without wildcards");
+    }
+
+    for webhook in &config.notifications.webhooks {
+        let push = matches!(webhook.kind, WebhookKind::Pushover | WebhookKind::Gotify | WebhookKind::Ntfy);
+
+        match webhook.url {
+            Some(ref url) => {
+                let url = reqwest::Url::parse(url).map_err(|e| Validation(format!("Invalid webhook URL: {}", e)))?;
+                if !matches!(url.scheme(), "http" | "https") {
+                    return error("Invalid webhook URL: only http:// and https:// URLs are supported");
+                }
+            },
+            None if matches!(webhook.kind, WebhookKind::Pushover | WebhookKind::Ntfy) => {},
+            None => return error("Webhook URL must be specified"),
+        }
+
+        if matches!(webhook.kind, WebhookKind::Pushover | WebhookKind::Gotify) && webhook.token.is_none() {
+            return error("Pushover and Gotify webhooks require the application token to be specified");
+        } else if webhook.kind == WebhookKind::Pushover && webhook.user.is_none() {
+            return error("Pushover webhooks require the user key to be specified");
+        } else if webhook.kind == WebhookKind::Ntfy && webhook.topic.is_none() {
+            return error("ntfy webhooks require the topic to be specified");
+        } else if !push && (webhook.token.is_some() || webhook.user.is_some() || !webhook.priorities.is_empty()) {
+            return error("Webhook token, user and priorities may be specified only for Pushover, Gotify and ntfy");
+        } else if webhook.kind != WebhookKind::Ntfy && (webhook.topic.is_some() || !webhook.tags.is_empty()) {
+            return error("Webhook topic and tags may be specified only for ntfy");
+        }
+
+        let priorities = match webhook.kind {
+            // Emergency priority requires the acknowledgement parameters
+            WebhookKind::Pushover => -2..=1,
+            WebhookKind::Ntfy => 1..=5,
+            _ => 0..=10,
+        };
+        if let Some(priority) = webhook.priorities.values().find(|&&priority| !priorities.contains(&priority)) {
+            return Err(Validation(format!("Invalid {:?} webhook priority: {}", webhook.kind, priority)));
+        }
+
+        for (name, value) in &webhook.headers {
+            if reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_err() ||
+                reqwest::header::HeaderValue::from_str(value).is_err() {
+                return Err(Validation(format!("Invalid {:?} webhook header", name)));
+            }
+        }
+
+        if webhook.retry.max_attempts == 0 {
+            return error("Invalid webhook 'retry.max-attempts' value: it must be positive");
+        }
+
+        if webhook.retry.initial_delay > webhook.retry.max_delay {
+            return error("Invalid webhook 'retry' section: 'initial-delay' must not be greater than 'max-delay'");
+        }
+    }
+
+    if let Some(ref xmpp) = config.notifications.xmpp {
+        XmppClient::new(xmpp).map_err(|e| Validation(format!("Invalid 'notifications.xmpp' section: {}", e)))?;
+        if xmpp.recipient.is_empty() {
+            return error("Invalid 'notifications.xmpp.recipient' value: it mustn't be empty");
+        }
+    }
+
+    let mut channels = HashSet::from(["email", "telegram", "xmpp", "mqtt"]);
+    for webhook in &config.notifications.webhooks {
+        if let Some(ref name) = webhook.name {
+            if name.is_empty() || !channels.insert(name) {
+                return error(&format!("Invalid webhook name: {:?}", name));
+            }
+        }
+    }
+    channels.extend(config.notifications.webhooks.iter().map(|webhook| webhook.kind.name()));
+
+    for route in &config.notifications.routes {
+        if route.channels.is_empty() {
+            return error("Invalid notification route: no channels are specified");
+        } else if let Some(channel) = route.channels.iter().find(|&channel| !channels.contains(channel.as_str())) {
+            return error(&format!("Invalid notification route: unknown {:?} channel", channel));
+        }
+
+        if route.digest.is_some_and(|interval| interval.is_zero()) {
+            return error("Invalid notification route 'digest' value: it must be positive");
+        }
+
+        if let (Some(min_size), Some(max_size)) = (route.min_size, route.max_size) {
+            if min_size > max_size {
+                return error("Invalid notification route: 'min-size' must not be greater than 'max-size'");
+            }
+        }
+    }
+
+    for service in &config.integrations.arr {
+        let url = reqwest::Url::parse(&service.url).map_err(|e| Validation(format!(
+            "Invalid {:?} URL: {}", service.kind, e)))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return error(&format!("Invalid {:?} URL: only http:// and https:// URLs are supported", service.kind));
+        }
+
+        if service.api_key.is_empty() {
+            return error(&format!("Invalid {:?} API key: it mustn't be empty", service.kind));
+        }
+
+        if service.path_mappings.iter().any(|(local, remote)| !local.is_absolute() || !remote.is_absolute()) {
+            return error(&format!("Invalid {:?} path mappings: the paths must be absolute", service.kind));
+        }
+    }
+
+    for server in &config.integrations.media_servers {
+        let url = reqwest::Url::parse(&server.url).map_err(|e| Validation(format!(
+            "Invalid {:?} URL: {}", server.kind, e)))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return error(&format!("Invalid {:?} URL: only http:// and https:// URLs are supported", server.kind));
+        }
+
+        if server.token.is_empty() {
+            return error(&format!("Invalid {:?} token: it mustn't be empty", server.kind));
+        }
+
+        if server.path_mappings.iter().any(|(local, remote)| !local.is_absolute() || !remote.is_absolute()) {
+            return error(&format!("Invalid {:?} path mappings: the paths must be absolute", server.kind));
+        }
+    }
+
+    if config.tracker_monitoring.max_failures == 0 {
+        return error("Invalid 'tracker-monitoring.max-failures' value: it must be positive");
+    }
+
+    if config.report.interval.is_some_and(|interval| interval.is_zero()) {
+        return error("Invalid 'report.interval' value: it must be positive");
+    }
+
+    if let Some(ref path) = config.report.template {
+        EmailTemplate::new_from_file(path).map_err(|e| Validation(format!(
+            "Invalid 'report.template' value: unable to read '{}': {}", path.display(), e)))?;
+    }
+
+    let mut feeds = HashSet::new();
+    for feed in &config.rss.feeds {
+        if !feeds.insert(feed.name.as_str()) {
+            return error(&format!("Duplicated RSS feed name: {:?}", feed.name));
+        }
+
+        reqwest::Url::parse(&feed.url).map_err(|e| Validation(format!(
+            "Invalid URL of {:?} RSS feed: {}", feed.name, e)))?;
+    }
+
+    if config.orphaned_files.delete_after.is_some() && config.orphaned_files.check_interval.is_none() {
+        return error("Invalid 'orphaned-files' section: 'delete-after' requires 'check-interval' to be specified");
+    }
+
+    let mut policy_names = HashSet::new();
+    for policy in &config.policies {
+        if policy.name.trim().is_empty() {
+            return error("Invalid policy name: it mustn't be empty");
+        }
+
+        if !policy_names.insert(&policy.name) {
+            return Err(Validation(format!("Duplicated policy: {:?}", policy.name)));
+        }
+
+        if policy.labels.is_empty() && policy.trackers.is_empty() {
+            return Err(Validation(format!(
+                "{:?} policy must have at least one label or tracker to match torrents by", policy.name)));
+        }
+
+        if let Some(ratio) = policy.ratio {
+            if !ratio.is_finite() || ratio < 0.0 {
+                return Err(Validation(format!("Invalid {:?} policy ratio: {}", policy.name, ratio)));
+            }
+        }
+    }
+
+    let mut group_names = HashSet::new();
+    for group in &config.bandwidth_groups {
+        if group.name.trim().is_empty() {
+            return error("Invalid bandwidth group name: it mustn't be empty");
+        }
+
+        if !group_names.insert(&group.name) {
+            return Err(Validation(format!("Duplicated bandwidth group: {:?}", group.name)));
+        }
+
+        if config.policies.iter().any(|policy| policy.name == group.name && policy.bandwidth_group().is_some()) {
+            return Err(Validation(format!(
+                "{:?} bandwidth group conflicts with the policy which has speed limits", group.name)));
+        }
+
+        if group.labels.is_empty() && group.trackers.is_empty() {
+            return Err(Validation(format!(
+                "{:?} bandwidth group must have at least one label or tracker to match torrents by", group.name)));
+        }
+    }
+
+    for limit in &config.tracker_speed_limits {
+        if limit.trackers.is_empty() {
+            return error("Invalid tracker speed limit: it must have at least one tracker to match torrents by");
+        }
+
+        if limit.download_limit.is_none() && limit.upload_limit.is_none() {
+            return error(
+                "Invalid tracker speed limit: either 'download-limit' or 'upload-limit' must be specified");
+        }
+    }
+
+    for limit in &config.seeding_limits {
+        if limit.trackers.is_empty() && limit.labels.is_empty() {
+            return error("Invalid seeding limit: it must have at least one tracker or label to match torrents by");
+        }
+
+        if limit.ratio.is_none() && limit.seed_time.is_none() {
+            return error("Invalid seeding limit: either 'ratio' or 'seed-time' must be specified");
+        }
+
+        if let Some(ratio) = limit.ratio {
+            if !ratio.is_finite() || ratio < 0.0 {
+                return Err(Validation(format!("Invalid seeding limit ratio: {}", ratio)));
+            }
+        }
+    }
+
+    config.copy_permissions.resolve().map_err(|e| Validation(format!("Invalid copy-permissions: {}", e)))?;
+
+    if config.transfer_mode == TransferMode::Move && config.link_mode == LinkMode::Hardlink {
+        return error("'link-mode = \"hardlink\"' can't be used with 'transfer-mode = \"move\"'");
+    }
+
+    if config.retention.iter().any(|rule| rule.max_age.is_zero()) {
+        return error("Invalid retention rule: 'max-age' must be positive");
+    }
+
+    if let Some(ref command) = config.availability.recovery_command {
+        if command.is_empty() || command[0].is_empty() {
+            return error("Invalid 'availability.recovery-command' value: it mustn't be empty");
+        } else if config.availability.alert_after.is_none() {
+            return error("'availability.recovery-command' requires 'availability.alert-after' to be specified");
+        }
+    }
+
+    if config.progress_notifications.thresholds.iter().any(|&threshold| threshold == 0 || threshold >= 100) {
+        return error("Invalid 'progress-notifications.thresholds' value: the thresholds must be in 1-99 range");
+    }
+
+    if config.availability.alert_after == Some(Duration::ZERO) {
+        return error("Invalid 'availability.alert-after' value: it must be positive");
+    }
+
+    for (event, command) in config.hooks.commands() {
+        if command.is_some_and(|command| command.is_empty() || command[0].is_empty()) {
+            return Err(Validation(format!("Invalid 'hooks.on-{}' value: it mustn't be empty", event.name())));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn deserialize_optional_duration<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<Duration>, D::Error> {
+    Ok(Some(deserialize_duration(deserializer)?))
+}
+
+/// Parses octal file mode ("0644", "755", etc.)
+fn deserialize_optional_mode<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<u32>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    match u32::from_str_radix(&value, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(Some(mode)),
+        _ => Err(de::Error::custom(format!("Invalid file mode: {:?}", value))),
+    }
+}
+
+pub fn default_true() -> bool {
+    true
+}
+
+pub fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Duration, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    let duration = util::time::parse_duration(&value).map_err(de::Error::custom)?;
+    Ok(Duration::from_secs(duration as u64))
+}
+
+/// Parses time periods in D[-D]/HH:MM-HH:MM format
+pub fn deserialize_periods<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<WeekPeriods, D::Error> {
+    let periods = Vec::<String>::deserialize(deserializer)?;
+    util::time::parse_periods(&periods).map_err(de::Error::custom)
+}
+
+pub fn deserialize_optional_periods<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<WeekPeriods>, D::Error> {
+    Ok(Some(deserialize_periods(deserializer)?))
+}
+
 
 impl Error for ConfigReadingError {
     fn description(&self) -> &str {
@@ -96,6 +1046,12 @@ impl From<io::Error> for ConfigReadingError {
     }
 }
 
+impl From<toml::de::Error> for ConfigReadingError {
+    fn from(err: toml::de::Error) -> ConfigReadingError {
+        Parsing(err.to_string())
+    }
+}
+
 impl From<serde_json::Error> for ConfigReadingError {
     fn from(err: serde_json::Error) -> ConfigReadingError {
         Parsing(err.to_string())
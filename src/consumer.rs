@@ -1,72 +1,281 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::ffi::OsString;
+use std::fmt::Write;
 use std::fs;
 use std::io;
 use std::mem;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use itertools::Itertools;
 
-use crate::common::{EmptyResult, GenericResult};
-use crate::email::{Mailer, EmailTemplate};
-use crate::transmissionrpc::{TransmissionClient, Torrent, TransmissionClientError, TransmissionRpcError};
+use crate::common::{EmptyResult, Error, ErrorContext, GenericError, GenericResult};
+use crate::config::{
+    ControllerConfig, CopyPermissionsConfig, CopyThrottlingConfig, CopyVerification, LinkMode, TransferMode};
+use crate::disk_space;
+use crate::email::EmailTemplate;
+use crate::history::History;
+use crate::hooks::{HookEvent, HookParams, Hooks};
+use crate::integrations::Integrations;
+use crate::metainfo::Pieces;
+use crate::metrics::METRICS;
+use crate::notifications::{Event, Notifier};
+use crate::rename::{self, RenameRule, SortRule};
+use crate::routing::{Destination, Routes};
+use crate::state_db::{Notification, StateDb};
+use crate::torrent_client::ClientError;
+use crate::torrent_client::blocking::TorrentClient;
+use crate::transmissionrpc::{Torrent, TorrentFields, TorrentFile, TorrentStatus};
+use crate::unpack::{self, ArchiveFile, Unpacker};
 use crate::util;
+use crate::util::fs::CopyOptions;
+use crate::util::matching::FilePattern;
 
 pub struct Consumer {
     data: Arc<Mutex<SharedData>>,
-    thread_handle: Option<thread::JoinHandle<()>>,
+    thread_handles: Vec<thread::JoinHandle<()>>,
 }
 
-struct ConsumerThread {
-    copy_to: Option<PathBuf>,
-    move_to: Option<PathBuf>,
+/// The consumer's dependencies which are shared with the controller
+pub struct ConsumerOptions {
+    pub routes: Routes,
+    pub notifier: Arc<Notifier>,
+    pub torrent_downloaded_email_template: EmailTemplate,
+    pub hooks: Arc<Hooks>,
+    pub state_db: Arc<StateDb>,
+    pub history: Arc<History>,
+}
 
-    notifications_mailer: Option<Mailer>,
+struct ConsumerThread {
+    routes: Routes,
+    rename_rules: Vec<RenameRule>,
+    sort_rules: Vec<SortRule>,
+    exclude_files: Vec<FilePattern>,
+    verify_after_copy_failure: bool,
+    verify_copies: CopyVerification,
+    link_mode: LinkMode,
+    legacy_copy: bool,
+    transfer_mode: TransferMode,
+    throttling: CopyThrottlingConfig,
+    permissions: CopyPermissionsConfig,
+    unpacker: Option<Unpacker>,
+    per_destination_limit: Option<usize>,
+    space_reserve: u64,
+
+    notifier: Arc<Notifier>,
     torrent_downloaded_email_template: EmailTemplate,
+    hooks: Arc<Hooks>,
+    integrations: Integrations,
+    state_db: Arc<StateDb>,
+    history: Arc<History>,
 
-    client: Arc<TransmissionClient>,
-
-    failed: HashSet<String>,
+    client: TorrentClient,
     data: Arc<Mutex<SharedData>>,
 }
 
 struct SharedData {
     stop: bool,
     in_process: HashSet<String>,
+    verification_requests: HashSet<String>,
+    /// Torrents which must be copied again even if they have been consumed before
+    forced: HashSet<String>,
+
+    failed: HashSet<String>,
+    verified: HashSet<String>,
+    /// Torrents which are being processed by the workers
+    processing: HashSet<String>,
+    /// Torrents which wait for a free slot of their destination
+    postponed: HashSet<String>,
+    retry_times: HashMap<String, Instant>,
+    /// Number of torrents which are being copied to each destination
+    destinations: HashMap<PathBuf, usize>,
+    /// Destinations which have run out of free space (to notify only once)
+    low_space_destinations: HashSet<PathBuf>,
+    /// Sizes of the torrents which are being copied to each destination: the space they are going to occupy is
+    /// reserved until the copying completes
+    reservations: HashMap<PathBuf, HashMap<String, u64>>,
+    /// Progress of the torrents which are being copied
+    copies: HashMap<String, CopyProgress>,
+    threads: Vec<thread::Thread>,
+}
+
+impl SharedData {
+    fn unpark_all(&self) {
+        for thread in &self.threads {
+            thread.unpark();
+        }
+    }
+
+    /// Returns the space which the copies in progress are yet to occupy in the destination
+    fn reserved_space(&self, path: &Path) -> u64 {
+        self.reservations.get(path).map(|torrents| torrents.iter().map(|(hash, &size)| {
+            size.saturating_sub(self.copies.get(hash).map(|progress| progress.copied).unwrap_or(0))
+        }).sum()).unwrap_or(0)
+    }
+}
+
+/// Occupied slot of the destination workers limit which is released on drop
+struct DestinationSlot {
+    path: PathBuf,
+    data: Arc<Mutex<SharedData>>,
+}
+
+impl Drop for DestinationSlot {
+    fn drop(&mut self) {
+        let mut data = self.data.lock().unwrap();
+
+        let count = data.destinations.get_mut(&self.path).unwrap();
+        *count -= 1;
+        if *count == 0 {
+            data.destinations.remove(&self.path);
+        }
+
+        data.postponed.clear();
+        data.unpark_all();
+    }
+}
+
+/// Free space of the destination reserved for the torrent which is released on drop
+struct SpaceReservation {
+    path: PathBuf,
+    hash: String,
+    data: Arc<Mutex<SharedData>>,
+}
+
+impl Drop for SpaceReservation {
+    fn drop(&mut self) {
+        let mut data = self.data.lock().unwrap();
+
+        let torrents = data.reservations.get_mut(&self.path).unwrap();
+        torrents.remove(&self.hash);
+        if torrents.is_empty() {
+            data.reservations.remove(&self.path);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CopyProgress {
+    pub name: String,
+    pub size: u64,
+    pub copied: u64,
+    start_time: Instant,
+    report_time: Instant,
+}
+
+impl CopyProgress {
+    fn new(torrent: &Torrent) -> CopyProgress {
+        let now = Instant::now();
+        CopyProgress {
+            name: torrent.name.clone(),
+            size: torrent.size,
+            copied: 0,
+            start_time: now,
+            report_time: now,
+        }
+    }
+
+    pub fn percent(&self) -> u64 {
+        let size = self.size.max(1);
+        self.copied.min(size) * 100 / size
+    }
+
+    /// Average copy speed in bytes per second
+    pub fn speed(&self) -> u64 {
+        let elapsed = self.start_time.elapsed().as_secs_f64();
+        if elapsed > 0.0 { (self.copied as f64 / elapsed) as u64 } else { 0 }
+    }
+
+    /// Estimated remaining time in seconds
+    pub fn eta(&self) -> Option<u64> {
+        self.size.saturating_sub(self.copied).checked_div(self.speed())
+    }
+
+    fn log(&self) {
+        let eta = self.eta().map(util::time::format_duration).unwrap_or_else(|| s!("unknown"));
+        info!("Copying '{}': {}% ({} of {}), {}/s, ETA {}.",
+              self.name, self.percent(), disk_space::format_size(self.copied.min(self.size)),
+              disk_space::format_size(self.size), disk_space::format_size(self.speed()), eta);
+    }
 }
 
 enum ProcessError {
-    Cancelled(String),
-    Temporary(String),
-    Persistent(String),
+    Cancelled(GenericError),
+    Temporary(GenericError),
+    Persistent(GenericError),
+    CopyFailed(GenericError),
+    /// All slots of the destination are occupied by other workers
+    Postponed(PathBuf),
 }
 type ProcessResult = Result<(), ProcessError>;
 
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+const PROGRESS_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+/// Subdirectory of the temporary directory for the files which match the sort rules
+const SORTED_DIR: &str = ".sorted";
+
 impl Consumer {
-    pub fn new(client: Arc<TransmissionClient>, copy_to: Option<PathBuf>, move_to: Option<PathBuf>,
-               notifications_mailer: Option<Mailer>, torrent_downloaded_email_template: EmailTemplate) -> Consumer {
+    pub fn new(client: TorrentClient, config: &ControllerConfig, options: ConsumerOptions) -> Consumer {
         let data = Arc::new(Mutex::new(SharedData {
             stop: false,
             in_process: HashSet::new(),
-        }));
+            verification_requests: HashSet::new(),
+            forced: HashSet::new(),
 
-        let mut consumer_thread = ConsumerThread {
-            copy_to: copy_to,
-            move_to: move_to,
+            failed: HashSet::new(),
+            verified: HashSet::new(),
+            processing: HashSet::new(),
+            postponed: HashSet::new(),
+            retry_times: HashMap::new(),
+            destinations: HashMap::new(),
+            low_space_destinations: HashSet::new(),
+            reservations: HashMap::new(),
+            copies: HashMap::new(),
+            threads: Vec::new(),
+        }));
 
-            notifications_mailer: notifications_mailer,
-            torrent_downloaded_email_template: torrent_downloaded_email_template,
+        let consumer_thread = Arc::new(ConsumerThread {
+            routes: options.routes,
+            rename_rules: config.rename_rules.clone(),
+            sort_rules: config.sort_rules.clone(),
+            exclude_files: config.exclude_files.clone(),
+            verify_after_copy_failure: config.verify_after_copy_failure,
+            verify_copies: config.verify_copies,
+            link_mode: config.link_mode,
+            legacy_copy: config.legacy_copy,
+            transfer_mode: config.transfer_mode,
+            throttling: config.copy_throttling.clone(),
+            permissions: config.copy_permissions.clone(),
+            unpacker: Unpacker::new(&config.unpack),
+            per_destination_limit: config.copy_workers_per_destination,
+            space_reserve: config.copy_space_reserve.unwrap_or(0).saturating_mul(1024 * 1024),
+
+            notifier: options.notifier,
+            torrent_downloaded_email_template: options.torrent_downloaded_email_template,
+            hooks: options.hooks,
+            integrations: Integrations::new(&config.integrations),
+            state_db: options.state_db,
+            history: options.history,
 
             client: client,
-            failed: HashSet::new(),
             data: data.clone(),
-        };
+        });
+
+        let thread_handles: Vec<_> = (0..config.copy_workers.unwrap_or(1)).map(|index| {
+            let consumer_thread = consumer_thread.clone();
+            thread::spawn(move || { consumer_thread.run(index == 0) })
+        }).collect();
+
+        {
+            let mut data = data.lock().unwrap();
+            data.threads = thread_handles.iter().map(|handle| handle.thread().clone()).collect();
+            data.unpark_all(); // Process the torrents that may have been scheduled before the threads were registered
+        }
 
         Consumer {
-            thread_handle: Some(thread::spawn(move || { consumer_thread.run() })),
+            thread_handles: thread_handles,
             data: data,
         }
     }
@@ -76,43 +285,139 @@ impl Consumer {
         data.in_process.clone()
     }
 
+    /// Returns None while the torrent is being consumed and then whether it has been consumed successfully. The
+    /// torrents which are scheduled for a retry or verification are considered as failed.
+    pub fn get_result(&self, hash: &str) -> Option<bool> {
+        let data = self.data.lock().unwrap();
+
+        if data.failed.contains(hash) || data.retry_times.contains_key(hash) ||
+            data.verification_requests.contains(hash) {
+            Some(false)
+        } else if data.in_process.contains(hash) {
+            None
+        } else {
+            Some(true)
+        }
+    }
+
+    /// Returns false if any of the workers has died (panicked), so its torrents won't ever be consumed
+    pub fn is_alive(&self) -> bool {
+        self.thread_handles.iter().all(|handle| !handle.is_finished())
+    }
+
+    pub fn get_copying_count(&self) -> usize {
+        self.data.lock().unwrap().copies.len()
+    }
+
+    /// Returns progress of the torrents which are being copied by their hashes
+    pub fn get_copies(&self) -> Vec<(String, CopyProgress)> {
+        let data = self.data.lock().unwrap();
+        data.copies.iter().map(|(hash, copy)| (hash.clone(), copy.clone())).collect()
+    }
+
+    /// Logs progress of the torrents which are being copied
+    pub fn log_progress(&self) {
+        let data = self.data.lock().unwrap();
+        for copy in data.copies.values() {
+            copy.log();
+        }
+    }
+
+    /// Returns the torrents which have failed to be copied and should be verified
+    pub fn take_verification_requests(&self) -> HashSet<String> {
+        let mut data = self.data.lock().unwrap();
+        mem::take(&mut data.verification_requests)
+    }
+
+    /// Retries the torrents which have failed to be consumed (after the user has fixed the problem, for example)
+    pub fn retry_failed(&self) {
+        let mut data = self.data.lock().unwrap();
+        data.failed.clear();
+        data.verified.clear();
+        data.retry_times.clear();
+        data.unpark_all();
+    }
+
     pub fn consume(&self, hash: &str) {
         debug!("Scheduling {:?} torrent for consuming.", hash);
 
+        let mut data = self.data.lock().unwrap();
+        data.in_process.insert(s!(hash));
+        data.unpark_all();
+    }
+
+    /// Consumes the torrent ignoring the fact that it has been consumed before
+    pub fn consume_again(&self, hash: &str) {
+        self.data.lock().unwrap().forced.insert(s!(hash));
+        self.consume(hash);
+    }
+}
+
+impl Consumer {
+    /// Stops the workers letting them finish the torrents which are being consumed. Gives up waiting for them after the
+    /// timeout: the unfinished torrents aren't marked as processed, so they will be consumed again after restart.
+    pub fn stop(&mut self, timeout: Duration) {
         {
             let mut data = self.data.lock().unwrap();
-            data.in_process.insert(s!(hash));
+            data.stop = true;
+            data.unpark_all();
+
+            if !data.processing.is_empty() {
+                info!("Waiting for {} torrent(s) to finish consuming...", data.processing.len());
+            }
         }
 
-        if let Some(ref thread_handle) = self.thread_handle {
-            thread_handle.thread().unpark();
+        let deadline = Instant::now() + timeout;
+        while self.thread_handles.iter().any(|handle| !handle.is_finished()) {
+            if Instant::now() >= deadline {
+                error!("Torrent consuming hasn't finished in {}. Exiting without waiting for it.",
+                       util::time::format_duration(timeout.as_secs()));
+                self.thread_handles.clear();
+                return;
+            }
+            thread::sleep(Duration::from_millis(100));
         }
     }
 }
 
 impl Drop for Consumer {
     fn drop(&mut self) {
-        let mut thread_handle = None;
-        mem::swap(&mut thread_handle, &mut self.thread_handle);
+        if self.thread_handles.is_empty() {
+            return;
+        }
 
-        if let Some(thread_handle) = thread_handle {
-            debug!("Stopping torrent consuming thread...");
+        debug!("Stopping torrent consuming threads...");
 
-            self.data.lock().unwrap().stop = true;
-            thread_handle.thread().unpark();
+        {
+            let mut data = self.data.lock().unwrap();
+            data.stop = true;
+            data.unpark_all();
+        }
 
+        for thread_handle in self.thread_handles.drain(..) {
             if let Err(error) = thread_handle.join() {
                 error!("Torrent consuming thread has panicked: {:?}.", error);
-            } else {
-                debug!("Torrent consuming thread has stopped.");
             }
         }
+
+        debug!("Torrent consuming threads have stopped.");
     }
 }
 
 impl ConsumerThread {
-    fn run(&mut self) {
-        if let (Some(copy_to), Some(_)) = (self.copy_to.as_ref(), self.move_to.as_ref()) {
+    fn run(&self, first: bool) {
+        if let Err(error) = util::process::set_io_class(self.throttling.io_class, self.throttling.io_priority) {
+            error!("Unable to lower I/O priority of torrent consuming thread: {}.", error);
+        }
+
+        if let Some(nice) = self.throttling.nice {
+            if let Err(error) = util::process::set_nice(nice) {
+                error!("Unable to change CPU priority of torrent consuming thread: {}.", error);
+            }
+        }
+
+        let destination = self.routes.default();
+        if let (true, Some(copy_to), Some(_)) = (first, destination.copy_to.as_ref(), destination.move_to.as_ref()) {
             if let Err(error) = check_copy_to_directory(copy_to) {
                 error!("Failed to check copy to directory: {}.", error);
             }
@@ -136,98 +441,439 @@ impl ConsumerThread {
             // A workaround for https://github.com/seanmonstar/reqwest/issues/1131
             if self.data.lock().unwrap().stop {
                 break;
-            } else if retry_after.is_none() && !self.to_process().is_empty() {
-                thread::current().unpark();
             }
         }
     }
 
-    fn to_process(&self) -> Vec<String> {
-        let data = self.data.lock().unwrap();
-        data.in_process.difference(&self.failed).cloned().collect()
+    /// Takes the next torrent to process. Returns time until the next retry if there is nothing to process now.
+    fn take_next(&self) -> Result<String, Option<Duration>> {
+        let mut data = self.data.lock().unwrap();
+        if data.stop {
+            return Err(None);
+        }
+
+        let now = Instant::now();
+        let mut retry_after: Option<Duration> = None;
+
+        let hash = data.in_process.iter().find(|&hash| {
+            if data.failed.contains(hash) || data.processing.contains(hash) || data.postponed.contains(hash) {
+                return false;
+            }
+
+            match data.retry_times.get(hash) {
+                Some(&retry_time) if retry_time > now => {
+                    let delay = retry_time - now;
+                    retry_after = Some(retry_after.map_or(delay, |retry_after| retry_after.min(delay)));
+                    false
+                },
+                _ => true,
+            }
+        }).cloned();
+
+        match hash {
+            Some(hash) => {
+                data.retry_times.remove(&hash);
+                data.processing.insert(hash.clone());
+                Ok(hash)
+            },
+            None => Err(retry_after),
+        }
     }
 
-    fn process(&mut self) -> Option<Duration> {
-        for hash in &self.to_process() {
-            match self.process_torrent(hash)  {
+    fn process(&self) -> Option<Duration> {
+        loop {
+            let hash = match self.take_next() {
+                Ok(hash) => hash,
+                Err(retry_after) => return retry_after,
+            };
+
+            let result = self.process_torrent(&hash);
+
+            let mut data = self.data.lock().unwrap();
+            data.processing.remove(&hash);
+
+            match result {
                 Ok(_) => {
-                    assert!(self.data.lock().unwrap().in_process.remove(hash));
+                    assert!(data.in_process.remove(&hash));
+                    data.forced.remove(&hash);
                 },
                 Err(error) => match error {
                     ProcessError::Cancelled(error) => {
                         warn!("{}.", error);
-                        assert!(self.data.lock().unwrap().in_process.remove(hash));
+                        assert!(data.in_process.remove(&hash));
+                        data.forced.remove(&hash);
                     },
                     ProcessError::Temporary(error) => {
                         error!("{}.", error);
-                        return Some(Duration::from_secs(60));
+                        data.retry_times.insert(hash, Instant::now() + RETRY_INTERVAL);
                     },
                     ProcessError::Persistent(error) => {
                         error!("{}.", error);
-                        assert!(self.failed.insert(hash.clone()));
+                        assert!(data.failed.insert(hash.clone()));
+                        drop(data);
+                        self.hooks.run(HookEvent::Error, &HookParams::new(&hash).error(&error.to_string()));
+                    },
+                    ProcessError::CopyFailed(error) => {
+                        METRICS.on_copy_failure();
+
+                        // Request verification only once to not get into an endless verification loop
+                        if self.verify_after_copy_failure && data.verified.insert(hash.clone()) {
+                            error!("{}. Requesting torrent verification...", error);
+                            assert!(data.in_process.remove(&hash));
+                            data.verification_requests.insert(hash);
+                        } else {
+                            error!("{}.", error);
+                            assert!(data.failed.insert(hash.clone()));
+                            drop(data);
+                            self.hooks.run(HookEvent::Error, &HookParams::new(&hash).error(&error.to_string()));
+                        }
+                    },
+                    ProcessError::Postponed(path) => {
+                        // The slot might have been released while we were getting here
+                        let limit = self.per_destination_limit.unwrap();
+                        if data.destinations.get(&path).copied().unwrap_or(0) >= limit {
+                            debug!("Postponing consuming of {} torrent: '{}' is busy.", hash, path.display());
+                            data.postponed.insert(hash);
+                        }
                     },
                 },
             }
         }
+    }
+
+    /// Occupies a slot of the destination or returns None if all its slots are occupied
+    fn acquire_destination(&self, path: &Path) -> Option<DestinationSlot> {
+        let mut data = self.data.lock().unwrap();
+        let count = data.destinations.entry(path.to_owned()).or_insert(0);
 
-        None
+        if self.per_destination_limit.is_some_and(|limit| *count >= limit) {
+            return None;
+        }
+        *count += 1;
+
+        Some(DestinationSlot {
+            path: path.to_owned(),
+            data: self.data.clone(),
+        })
     }
 
     fn process_torrent(&self, hash: &str) -> ProcessResult {
-        let torrent = self.client.get_torrent(hash).map_err(|error| {
-            if let TransmissionClientError::Rpc(TransmissionRpcError::TorrentNotFoundError(_)) = error {
-                return ProcessError::Cancelled(format!(
+        let torrent = self.client.get_torrent(hash, TorrentFields::basic().with_files()).map_err(|error| {
+            if let ClientError::TorrentNotFound(_) = error {
+                return ProcessError::Cancelled(format_to!(
                     "Failed to consume {} torrent: it has been removed", hash));
             }
 
-            ProcessError::Temporary(format!("Failed to get '{}' torrent info: {}", hash, error))
+            ProcessError::Temporary(format_to!("Failed to get '{}' torrent info: {}", hash, error))
         })?;
 
         if !torrent.done {
-            return Err(ProcessError::Cancelled(format!(
+            return Err(ProcessError::Cancelled(format_to!(
                 "Cancelling consuming of {} torrent: it has started to download", torrent.name)));
         }
 
-        if let Err(error) = self.consume_torrent(&torrent) {
-            return Err(ProcessError::Persistent(error.to_string()));
+        // The torrent has been consumed before, but hasn't been marked as processed (or has lost the mark)
+        let forced = self.data.lock().unwrap().forced.contains(hash);
+        if let Some(record) = self.state_db.get(hash).filter(|record| !forced && record.processed_time.is_some()) {
+            let destination = record.copied_to.map(|path| format!(" to '{}'", path.display())).unwrap_or_default();
+            info!("'{}' torrent has been already consumed{}. Marking it as processed...", torrent.name, destination);
+
+            self.client.mark_processed(&torrent.hash).map_err(|e| ProcessError::Persistent(e.into()))?;
+            self.notify_downloaded(&torrent, None);
+
+            return Ok(());
         }
 
-        Ok(())
+        let destination = self.routes.get_destination(&torrent);
+        let _slot = match destination.copy_to {
+            Some(ref copy_to) => Some(self.acquire_destination(copy_to).ok_or_else(|| {
+                ProcessError::Postponed(copy_to.clone())
+            })?),
+            None => None,
+        };
+        let mut hard_links = false;
+        let mut _reservation = None;
+
+        if let Some(ref copy_to) = destination.copy_to {
+            hard_links = self.use_hard_links(&torrent, copy_to).map_err(|e| ProcessError::Temporary(format_to!(
+                "Failed to check '{}' torrent's file system: {}", torrent.name, e)))?;
+
+            // Hard links don't occupy any additional space
+            if !hard_links {
+                _reservation = Some(self.reserve_free_space(&torrent, copy_to)?);
+            }
+        }
+
+        self.consume_torrent(&torrent, &destination, hard_links)
+    }
+
+    /// Checks that the destination has enough free space for the torrent taking into account the torrents which are
+    /// being copied to it concurrently and reserves the space until the returned reservation is dropped
+    fn reserve_free_space(&self, torrent: &Torrent, copy_to: &Path) -> Result<SpaceReservation, ProcessError> {
+        let space = disk_space::get_local_space(copy_to).map_err(|e| ProcessError::Temporary(format_to!(
+            "Failed to check free space for '{}' torrent: {}", torrent.name, e)))?;
+
+        METRICS.set_free_space(copy_to, space);
+
+        let mut data = self.data.lock().unwrap();
+        let reserved = data.reserved_space(copy_to);
+        let required = torrent.size.saturating_add(self.space_reserve);
+
+        if space.free.saturating_sub(reserved) >= required {
+            if data.low_space_destinations.remove(copy_to) {
+                info!("There is enough free space in '{}' again.", copy_to.display());
+            }
+
+            data.reservations.entry(copy_to.to_owned()).or_default().insert(torrent.hash.clone(), torrent.size);
+            return Ok(SpaceReservation {
+                path: copy_to.to_owned(),
+                hash: torrent.hash.clone(),
+                data: self.data.clone(),
+            });
+        }
+
+        let mut details = format!(
+            "'{}' torrent requires {} of free space (including {} reserve), but there is only {}",
+            torrent.name, disk_space::format_size(required), disk_space::format_size(self.space_reserve), space);
+        if reserved != 0 {
+            write!(details, " and {} of it is reserved for the torrents which are being copied",
+                   disk_space::format_size(reserved)).unwrap();
+        }
+
+        let notify = data.low_space_destinations.insert(copy_to.to_owned());
+        drop(data);
+
+        if notify {
+            self.notifier.notify_about(
+                Event::FreeSpace, Some(torrent), &format!("Not enough free space in '{}'", copy_to.display()), &format!(
+                    "{}. The torrents will be copied to '{}' when there is enough free space.",
+                    details, copy_to.display()));
+        }
+
+        Err(ProcessError::Temporary(format_to!(
+            "Postponing consuming of '{}' torrent: not enough free space in '{}': {}",
+            torrent.name, copy_to.display(), details)))
     }
 
-    fn consume_torrent(&self, torrent: &Torrent) -> EmptyResult {
-        info!("Consuming '{}' torrent...", torrent.name);
+    fn use_hard_links(&self, torrent: &Torrent, copy_to: &Path) -> GenericResult<bool> {
+        // The moved torrents are renamed instead when possible
+        if self.transfer_mode == TransferMode::Move {
+            return util::fs::is_same_file_system(&torrent.download_dir, copy_to);
+        }
 
-        if let Some(ref copy_to) = self.copy_to {
-            let torrent_files = copy_torrent(torrent, copy_to).map_err(|e| format!(
-                "Failed to copy '{}' torrent: {}", torrent.name, e))?;
+        Ok(match self.link_mode {
+            LinkMode::Copy => false,
+            LinkMode::Hardlink => true,
+            LinkMode::Auto => util::fs::is_same_file_system(&torrent.download_dir, copy_to)?,
+        })
+    }
 
-            if let Some(ref move_to) = self.move_to {
-                for file_path in &torrent_files {
-                    move_torrent_file(file_path, move_to).map_err(|e| format!(
-                        "Failed to move '{}' torrent: {}", torrent.name, e))?;
+    fn consume_torrent(&self, torrent: &Torrent, destination: &Destination, hard_links: bool) -> ProcessResult {
+        info!(hash = torrent.hash.as_str(), action = "consume"; "Consuming '{}' torrent...", torrent.name);
+
+        let renamed_torrent = if destination.rename {
+            rename::apply_rules(&self.client, &self.rename_rules, torrent)
+                .torrent_context("Failed to rename", &torrent.name).map_err(ProcessError::Persistent)?
+        } else {
+            None
+        };
+        let torrent = renamed_torrent.as_ref().unwrap_or(torrent);
+
+        let copy = match destination.copy_to {
+            Some(ref copy_to) => {
+                let start_time = Instant::now();
+
+                if self.transfer_mode == TransferMode::Move {
+                    self.move_to_destination(torrent, destination, copy_to)?;
+                } else {
+                    self.copy_to_destination(torrent, destination, copy_to, hard_links)?;
                 }
+
+                Some((copy_to.as_path(), start_time.elapsed()))
+            },
+            None => None,
+        };
+
+        self.state_db.record_processed(torrent, destination.copy_to.as_deref());
+        if let Some(ref copy_to) = destination.copy_to {
+            self.history.record_copied(torrent, copy_to);
+        }
+        self.client.mark_processed(&torrent.hash).map_err(|e| ProcessError::Persistent(e.into()))?;
+
+        match copy {
+            Some((_, duration)) => info!(hash = torrent.hash.as_str(), action = "consumed";
+                                         "'{}' torrent has been consumed in {}.",
+                                         torrent.name, util::time::format_duration(duration.as_secs())),
+            None => info!(hash = torrent.hash.as_str(), action = "consumed";
+                          "'{}' torrent has been consumed.", torrent.name),
+        }
+
+        if let Some(ref copy_to) = destination.copy_to {
+            self.hooks.run(HookEvent::Copied, &HookParams::from_torrent(torrent).destination(copy_to));
+
+            let path = destination.move_to.as_ref().unwrap_or(copy_to).join(&torrent.name);
+            self.integrations.on_copied(&self.client, torrent, &path);
+        }
+
+        self.notify_downloaded(torrent, copy);
+
+        Ok(())
+    }
+
+    fn copy_to_destination(
+        &self, torrent: &Torrent, destination: &Destination, copy_to: &Path, hard_links: bool,
+    ) -> ProcessResult {
+        let verification = if hard_links { CopyVerification::None } else { self.verify_copies };
+
+        // The sorted files are put directly to their final location
+        let sorting = destination.rename.then(|| {
+            (self.sort_rules.as_slice(), destination.move_to.as_deref().unwrap_or(copy_to))
+        });
+
+        let params = CopyParams {
+            hard_links: hard_links,
+            verification: verification,
+            options: self.copy_options(),
+            exclude_files: &self.exclude_files,
+            unpacker: self.unpacker.as_ref(),
+        };
+
+        let (torrent_files, sorted_files) = self.track_progress(torrent, |progress| copy_torrent(
+            torrent, copy_to, params, sorting, progress,
+        )).torrent_context("Failed to copy", &torrent.name).map_err(ProcessError::CopyFailed)?;
+
+        // Hard links share the data (and so its permissions) with the seeded files
+        if !hard_links {
+            self.set_permissions(torrent_files.iter().chain(&sorted_files))
+                .torrent_context("Failed to set permissions of", &torrent.name).map_err(ProcessError::Persistent)?;
+        }
+
+        if let Some(ref move_to) = destination.move_to {
+            for file_path in &torrent_files {
+                move_torrent_file(file_path, move_to).torrent_context("Failed to move", &torrent.name)
+                    .map_err(ProcessError::Persistent)?;
             }
         }
 
-        self.client.set_processed(&torrent.hash)?;
-        info!("'{}' torrent has been consumed.", torrent.name);
+        Ok(())
+    }
+
+    /// Moves torrent's data to the destination and points Transmission to the new location to continue seeding from
+    /// it. The torrent is stopped during the move to not access the data which is being moved.
+    fn move_to_destination(&self, torrent: &Torrent, destination: &Destination, move_to: &Path) -> ProcessResult {
+        let error = |e: GenericError| ProcessError::Persistent(Error::Torrent {
+            action: "Failed to move",
+            name: torrent.name.clone(),
+            source: e,
+        }.into());
+
+        if destination.move_to.is_some() {
+            return Err(error(s!("move-to directory can't be used with 'transfer-mode = \"move\"'").into()));
+        }
+        let location = move_to.to_str().ok_or_else(|| error(format_to!("Invalid path: '{}'", move_to.display())))?;
+
+        let running = torrent.status != TorrentStatus::Stopped;
+        if running {
+            self.client.stop(&torrent.hash).torrent_context("Failed to stop", &torrent.name)
+                .map_err(ProcessError::Temporary)?;
+        }
 
-        if let Some(ref mailer) = self.notifications_mailer {
-            let mut params = HashMap::new();
-            params.insert("name", torrent.name.clone());
+        let result = self.track_progress(torrent, |progress| {
+            move_torrent(torrent, move_to, self.verify_copies, self.copy_options(), progress)
+        }).and_then(|_| Ok(self.client.set_location(&torrent.hash, location, false)?));
 
-            if let Err(e) = self.torrent_downloaded_email_template.send(mailer, &params) {
-                error!("Failed to send 'torrent downloaded' notification for '{}' torrent: {}.",
-                    torrent.name, e);
+        if running {
+            if let Err(err) = self.client.start(&torrent.hash) {
+                error!("Failed to start '{}' torrent: {}.", torrent.name, err);
             }
         }
 
+        result.map_err(error)
+    }
+
+    /// Tracks progress of the torrent's copying, periodically logging it
+    fn track_progress<T, F>(&self, torrent: &Torrent, copy: F) -> T where F: FnOnce(&mut dyn FnMut(u64)) -> T {
+        self.data.lock().unwrap().copies.insert(torrent.hash.clone(), CopyProgress::new(torrent));
+
+        let result = copy(&mut |size| {
+            let mut data = self.data.lock().unwrap();
+            let progress = data.copies.get_mut(&torrent.hash).unwrap();
+
+            progress.copied += size;
+            METRICS.on_copied(size);
+            if progress.report_time.elapsed() >= PROGRESS_REPORT_INTERVAL {
+                progress.report_time = Instant::now();
+                progress.log();
+            }
+        });
+
+        self.data.lock().unwrap().copies.remove(&torrent.hash);
+        result
+    }
+
+    fn set_permissions<'a, I: IntoIterator<Item = &'a PathBuf>>(&self, paths: I) -> EmptyResult {
+        let permissions = self.permissions.resolve()?;
+        if permissions.is_empty() {
+            return Ok(());
+        }
+
+        for path in paths {
+            util::fs::set_permissions(path, &permissions)?;
+        }
+
         Ok(())
     }
+
+    fn copy_options(&self) -> CopyOptions {
+        CopyOptions {
+            speed_limit: self.throttling.speed_limit.map(|limit| limit * 1024 * 1024),
+            legacy: self.legacy_copy,
+        }
+    }
+
+    fn notify_downloaded(&self, torrent: &Torrent, copy: Option<(&Path, Duration)>) {
+        if !self.state_db.record_notification(torrent, Notification::Downloaded) {
+            return;
+        }
+
+        let copy_duration = copy.map(|(_, duration)| util::time::format_duration(duration.as_secs()));
+
+        let mut params = HashMap::new();
+        params.insert("name", torrent.name.clone());
+        params.insert("copy_duration", copy_duration.clone().unwrap_or_default());
+        params.insert("copy_details", match copy {
+            Some((path, _)) => format!(" It has been copied to '{}' in {}.", path.display(), copy_duration.unwrap()),
+            None => String::new(),
+        });
+
+        let template = &self.torrent_downloaded_email_template;
+        if let Err(e) = self.notifier.notify_with_template(Event::Downloaded, torrent, template, &params) {
+            error!("Failed to send 'torrent downloaded' notification for '{}' torrent: {}.",
+                torrent.name, e);
+        }
+    }
 }
 
-fn copy_torrent<P: AsRef<Path>>(torrent: &Torrent, destination: P) -> GenericResult<HashSet<PathBuf>> {
+/// Parameters of the torrent files copying
+#[derive(Clone, Copy)]
+struct CopyParams<'a> {
+    hard_links: bool,
+    verification: CopyVerification,
+    options: CopyOptions,
+    exclude_files: &'a [FilePattern],
+    unpacker: Option<&'a Unpacker>,
+}
+
+/// Copies the torrent to a hidden temporary directory in the destination directory and then moves its files into
+/// place, so the destination never contains partially copied files. The temporary directory is preserved on failure
+/// to resume the copying on the next attempt.
+///
+/// The files which match the sort rules are moved to their paths in the specified library directory instead. Returns
+/// the top-level paths of the torrent in the destination directory and the paths of the sorted files.
+fn copy_torrent<P: AsRef<Path>>(
+    torrent: &Torrent, destination: P, params: CopyParams, sorting: Option<(&[SortRule], &Path)>,
+    progress: &mut dyn FnMut(u64),
+) -> GenericResult<(HashSet<PathBuf>, Vec<PathBuf>)> {
     let destination = destination.as_ref();
 
     let download_dir_path = Path::new(&torrent.download_dir);
@@ -236,11 +882,219 @@ fn copy_torrent<P: AsRef<Path>>(torrent: &Torrent, destination: P) -> GenericRes
             torrent.download_dir)
     }
 
-    info!("Copying '{}' to '{}'...", torrent.name, destination.display());
+    info!("{} '{}' to '{}'...", if params.hard_links { "Linking" } else { "Copying" },
+          torrent.name, destination.display());
 
-    let mut torrent_files = HashSet::new();
+    let temp_dir = destination.join(format!(".{}.tmp", torrent.hash));
+
+    if fs::symlink_metadata(&temp_dir).is_ok() {
+        info!("Resuming interrupted copying of '{}'...", torrent.name);
+    } else {
+        fs::create_dir(&temp_dir).file_context("Failed to create", &temp_dir)?;
+    }
+
+    let (temp_files, temp_sorted_files) = copy_torrent_files(
+        torrent, download_dir_path, &temp_dir, params, sorting.map(|(rules, _)| rules).unwrap_or_default(), progress,
+    )?;
+
+    let torrent_files = temp_files.into_iter().map(|temp_path| {
+        let path = destination.join(temp_path.file_name().unwrap());
+        if fs::symlink_metadata(&path).is_ok() {
+            return Err(Error::AlreadyExists(path).into());
+        }
+
+        fs::rename(&temp_path, &path).rename_context(&temp_path, &path)?;
+
+        Ok(path)
+    }).collect::<GenericResult<HashSet<PathBuf>>>()?;
+    util::fs::sync_dir(destination)?;
+
+    let mut sorted_files = Vec::new();
+    if let Some((_, library)) = sorting {
+        let mut directories = BTreeSet::new();
+
+        for sorted_path in temp_sorted_files {
+            let (temp_path, path) = (temp_dir.join(SORTED_DIR).join(&sorted_path), library.join(&sorted_path));
+            if fs::symlink_metadata(&path).is_ok() {
+                return Err(Error::AlreadyExists(path).into());
+            }
+
+            if let Some(dir_path) = sorted_path.parent() {
+                util::fs::create_all_dirs_from_base(library, dir_path)?;
+            }
+
+            info!("Moving '{}' to '{}'...", sorted_path.display(), library.display());
+            fs::rename(&temp_path, &path).rename_context(&temp_path, &path)?;
+
+            directories.extend(sorted_path.ancestors().skip(1).map(|path| library.join(path)));
+            sorted_files.push(path);
+        }
+
+        for path in directories {
+            util::fs::sync_dir(path)?;
+        }
+    }
+
+    // The directory may contain files from the previous attempts which are no longer needed (if the torrent has been
+    // renamed, for example)
+    if let Err(err) = fs::remove_dir_all(&temp_dir) {
+        error!("Failed to remove '{}': {}.", temp_dir.display(), err);
+    }
+
+    Ok((torrent_files, sorted_files))
+}
 
+/// Moves the torrent's data to the destination directory: renames it when the directories are on the same file system
+/// and falls back to copying and deleting the originals otherwise. The data which has been already moved on the
+/// previous attempts is skipped.
+fn move_torrent(
+    torrent: &Torrent, destination: &Path, verification: CopyVerification, options: CopyOptions,
+    progress: &mut dyn FnMut(u64),
+) -> EmptyResult {
+    let download_dir_path = Path::new(&torrent.download_dir);
+    if !download_dir_path.is_absolute() {
+        return Err!("Torrent's download directory is not an absolute path: {}",
+            torrent.download_dir)
+    }
+
+    let mut roots = Vec::new();
+    for file in torrent.files.as_ref().unwrap() {
+        let (root_path, _, _) = validate_torrent_file_name(&file.name)?;
+        if !roots.contains(&root_path) {
+            roots.push(root_path);
+        }
+    }
+
+    let pending: Vec<_> = roots.iter().filter(|&root| {
+        fs::symlink_metadata(download_dir_path.join(root)).is_ok() ||
+        fs::symlink_metadata(destination.join(root)).is_err()
+    }).collect();
+
+    if pending.is_empty() {
+        info!("'{}' has been already moved to '{}'.", torrent.name, destination.display());
+        return Ok(());
+    }
+
+    if util::fs::is_same_file_system(download_dir_path, destination)? {
+        info!("Moving '{}' to '{}'...", torrent.name, destination.display());
+
+        for root in pending {
+            let (src_path, dst_path) = (download_dir_path.join(root), destination.join(root));
+            if fs::symlink_metadata(&dst_path).is_ok() {
+                return Err(Error::AlreadyExists(dst_path).into());
+            }
+
+            fs::rename(&src_path, &dst_path).rename_context(&src_path, &dst_path)?;
+        }
+
+        return util::fs::sync_dir(destination);
+    }
+
+    // The copying skips hidden files, so they would be lost
     for file in torrent.files.as_ref().unwrap().iter().filter(|file| file.selected) {
+        let (_, file_path, file_name) = validate_torrent_file_name(&file.name)?;
+        if file_name.to_string_lossy().starts_with('.') {
+            return Err!("'{}' can't be moved to another file system: it contains hidden '{}' file",
+                        torrent.name, file_path.display());
+        }
+    }
+
+    // The originals are deleted, so they mustn't be deleted until the copy is verified
+    let verification = match verification {
+        CopyVerification::None => CopyVerification::Full,
+        verification => verification,
+    };
+
+    // The previous attempt may have been interrupted after moving some of the copies to the destination directory, so
+    // they are verified against the originals and skipped
+    let mut remaining = torrent.clone();
+    for root in &pending {
+        let (src_path, dst_path) = (download_dir_path.join(root), destination.join(root));
+        if fs::symlink_metadata(&src_path).is_err() || fs::symlink_metadata(&dst_path).is_err() {
+            continue;
+        }
+
+        info!("Verifying '{}' which has been already copied to '{}'...", root.display(), destination.display());
+
+        for file in remaining.files.as_mut().unwrap().iter_mut().filter(|file| file.selected) {
+            let (file_root_path, file_path, _) = validate_torrent_file_name(&file.name)?;
+            if file_root_path != **root {
+                continue;
+            }
+
+            util::fs::verify_copy(
+                download_dir_path.join(&file_path), destination.join(&file_path),
+                verification == CopyVerification::Fast,
+            ).file_context("Unable to reuse already existing", &dst_path)?;
+
+            file.selected = false;
+        }
+    }
+
+    if remaining.files.as_ref().unwrap().iter().any(|file| file.selected) {
+        let params = CopyParams {
+            hard_links: false,
+            verification: verification,
+            options: options,
+            exclude_files: &[],
+            unpacker: None,
+        };
+        copy_torrent(&remaining, destination, params, None, progress)?;
+    }
+
+    for root in pending {
+        let path = download_dir_path.join(root);
+        let result = match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&path),
+            Ok(_) => fs::remove_file(&path),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        };
+        result.file_context("Failed to delete", &path)?;
+    }
+
+    Ok(())
+}
+
+fn copy_torrent_files(
+    torrent: &Torrent, download_dir_path: &Path, destination: &Path, params: CopyParams, sort_rules: &[SortRule],
+    progress: &mut dyn FnMut(u64),
+) -> GenericResult<(HashSet<PathBuf>, Vec<PathBuf>)> {
+    let CopyParams {hard_links, verification, options, exclude_files, unpacker} = params;
+    let files = torrent.files.as_ref().unwrap();
+    let mut copies = vec![None; files.len()];
+
+    let mut torrent_files = HashSet::new();
+    let mut sorted_files = Vec::new();
+    let mut excluded_files = Vec::new();
+    let mut directories = BTreeSet::new();
+    let mut extracted = false;
+
+    if let Some(unpacker) = unpacker {
+        for file in torrent.files.as_ref().unwrap().iter().filter(|file| file.selected) {
+            let (file_root_path, file_path, file_name) = validate_torrent_file_name(&file.name)?;
+            if unpack::get_archive_file(&file_name.to_string_lossy()) != Some(ArchiveFile::First) {
+                continue;
+            }
+
+            // Archives from the torrent's root are extracted to a directory named after them
+            let (extract_to, extract_root) = match file_path.parent().filter(|path| !path.as_os_str().is_empty()) {
+                Some(path) => (path.to_owned(), file_root_path),
+                None => {
+                    let name = PathBuf::from(file_path.file_stem().unwrap());
+                    (name.clone(), name)
+                },
+            };
+
+            util::fs::create_all_dirs_from_base(destination, &extract_to)?;
+            unpacker.extract(&download_dir_path.join(&file_path), &destination.join(&extract_to))?;
+
+            torrent_files.insert(destination.join(&extract_root));
+            extracted = true;
+        }
+    }
+
+    for (index, file) in files.iter().enumerate().filter(|(_, file)| file.selected) {
         let (file_root_path, file_path, file_name) = validate_torrent_file_name(&file.name)?;
 
         if file_name.to_string_lossy().starts_with('.') {
@@ -248,20 +1102,103 @@ fn copy_torrent<P: AsRef<Path>>(torrent: &Torrent, destination: P) -> GenericRes
             continue;
         }
 
+        if extracted && unpacker.is_some_and(Unpacker::skip_archives) &&
+            unpack::get_archive_file(&file_name.to_string_lossy()).is_some() {
+            debug!("'{}': Skipping extracted '{}'.", torrent.name, file_path.display());
+            continue;
+        }
+
+        if exclude_files.iter().any(|pattern| pattern.matches(&file.name)) {
+            excluded_files.push(file.name.as_str());
+            continue;
+        }
+
+        let sorted_path = rename::get_sorted_path(sort_rules, &file.name);
+        let dst_file_path = match sorted_path {
+            Some(ref path) if sorted_files.contains(path) => {
+                return Err!("'{}' is sorted to the same path as another file: '{}'", file.name, path.display());
+            },
+            Some(ref path) => Path::new(SORTED_DIR).join(path),
+            None => file_path.clone(),
+        };
+
         let src_path = download_dir_path.join(&file_path);
-        let dst_path = destination.join(&file_path);
+        let dst_path = destination.join(&dst_file_path);
 
-        debug!("Copying '{}'...", src_path.display());
+        debug!("{} '{}'...", if hard_links { "Linking" } else { "Copying" }, src_path.display());
 
-        if let Some(file_dir_path) = file_path.parent() {
+        if let Some(file_dir_path) = dst_file_path.parent() {
             util::fs::create_all_dirs_from_base(destination, file_dir_path)?;
         }
 
-        util::fs::copy_downloaded_file(&src_path, &dst_path)?;
-        torrent_files.insert(destination.join(&file_root_path));
+        if hard_links {
+            util::fs::link_downloaded_file(&src_path, &dst_path)?;
+        } else {
+            util::fs::copy_downloaded_file(&src_path, &dst_path, options, progress)?;
+        }
+
+        if matches!(verification, CopyVerification::Fast | CopyVerification::Full) {
+            debug!("Verifying '{}'...", dst_path.display());
+
+            // Remove the corrupted copy to be able to retry the copying after the torrent verification
+            if let Err(err) = util::fs::verify_copy(&src_path, &dst_path, verification == CopyVerification::Fast) {
+                if let Err(err) = fs::remove_file(&dst_path) {
+                    error!("Failed to remove '{}': {}.", dst_path.display(), err);
+                }
+                return Err(Error::Verification(err).into());
+            }
+        }
+
+        // The directory entries must reach the disk as well as the files' data
+        directories.extend(dst_file_path.ancestors().skip(1).map(|path| destination.join(path)));
+        match sorted_path {
+            Some(path) => sorted_files.push(path),
+            None => {
+                torrent_files.insert(destination.join(&file_root_path));
+            },
+        }
+        copies[index] = Some(dst_path);
+    }
+
+    if verification == CopyVerification::Pieces {
+        verify_pieces(torrent, files, copies)?;
+    }
+
+    for path in directories {
+        util::fs::sync_dir(path)?;
+    }
+
+    if !excluded_files.is_empty() {
+        info!("'{}': Skipped {} excluded file(s): {}.", torrent.name, excluded_files.len(),
+              excluded_files.iter().map(|name| format!("'{}'", name)).join(", "));
+    }
+
+    Ok((torrent_files, sorted_files))
+}
+
+/// Checks the copies against the torrent's piece hashes. The pieces which are shared with the files that haven't been
+/// copied (excluded or extracted ones, for example) can't be verified.
+fn verify_pieces(torrent: &Torrent, files: &[TorrentFile], copies: Vec<Option<PathBuf>>) -> EmptyResult {
+    debug!("Verifying '{}' against its piece hashes...", torrent.name);
+
+    let torrent_file = torrent.torrent_file.as_ref().ok_or("unknown .torrent file path").verification_context()?;
+    let pieces = Pieces::read(torrent_file).verification_context()?;
+
+    let files: Vec<_> = files.iter().map(|file| file.size).zip(copies).collect();
+    let corrupted = pieces.verify(&files).verification_context()?;
+    if corrupted.is_empty() {
+        return Ok(());
+    }
+
+    // Remove the corrupted copies to be able to retry the copying after the torrent verification
+    for path in &corrupted {
+        if let Err(err) = fs::remove_file(path) {
+            error!("Failed to remove '{}': {}.", path.display(), err);
+        }
     }
 
-    Ok(torrent_files)
+    Err(format!("{} don't match the torrent's piece hashes",
+                corrupted.iter().map(|path| format!("'{}'", path.display())).join(", "))).verification_context()
 }
 
 fn validate_torrent_file_name(torrent_file_name: &str) -> GenericResult<(PathBuf, PathBuf, OsString)> {
@@ -310,13 +1247,12 @@ fn move_torrent_file<S, D>(src: S, dst_dir: D) -> EmptyResult where S: AsRef<Pat
             Ok(_) => continue,
             Err(err) => match err.kind() {
                 io::ErrorKind::NotFound => {},
-                _ => return Err!("Failed to stat() '{}': {}", dst.display(), err)
+                _ => return Err(err).file_context("Failed to stat()", &dst),
             }
         }
 
         info!("Moving '{}' to '{}'...", src.display(), dst.display());
-        fs::rename(src, &dst).map_err(|e| format!(
-            "Failed to rename '{}' to '{}': {}", src.display(), dst.display(), e))?;
+        fs::rename(src, &dst).rename_context(src, &dst)?;
 
         return Ok(());
     }
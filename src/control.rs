@@ -0,0 +1,248 @@
+//! Local control interface of the running daemon: a UNIX socket with a line-based protocol. Each request line is a
+//! command name and each response is a single line starting with "OK" or "ERROR" followed by a message.
+
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::common::{EmptyResult, GenericResult};
+
+pub const COMMANDS: &[&str] = &[
+    "status", "dashboard", "process-now", "reload-config", "pause-processing", "resume-processing", "copy HASH"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlCommand {
+    Status,
+    /// The daemon's state as a single-line JSON (the same as the web dashboard gets)
+    Dashboard,
+    ProcessNow,
+    ReloadConfig,
+    PauseProcessing,
+    ResumeProcessing,
+    /// Consume the torrent right away
+    Copy(String),
+}
+
+impl ControlCommand {
+    pub fn parse(line: &str) -> Option<ControlCommand> {
+        let mut args = line.split_whitespace();
+        let name = args.next()?;
+
+        let command = match (name, args.next()) {
+            ("status", None) => ControlCommand::Status,
+            ("dashboard", None) => ControlCommand::Dashboard,
+            ("process-now", None) => ControlCommand::ProcessNow,
+            ("reload-config", None) => ControlCommand::ReloadConfig,
+            ("pause-processing", None) => ControlCommand::PauseProcessing,
+            ("resume-processing", None) => ControlCommand::ResumeProcessing,
+            ("copy", Some(hash)) => ControlCommand::Copy(hash.to_lowercase()),
+            _ => return None,
+        };
+
+        if args.next().is_some() {
+            return None;
+        }
+
+        Some(command)
+    }
+
+    /// Whether the command doesn't change the daemon's state
+    pub fn is_read_only(&self) -> bool {
+        matches!(self, ControlCommand::Status | ControlCommand::Dashboard)
+    }
+}
+
+/// The requests are executed by the daemon's main loop
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    reply: oneshot::Sender<GenericResult<String>>,
+}
+
+impl ControlRequest {
+    pub fn reply(self, result: GenericResult<String>) {
+        let _ = self.reply.send(result);
+    }
+}
+
+/// Listens on the control socket which is removed on drop
+pub struct ControlServer {
+    path: PathBuf,
+    requests: mpsc::Receiver<ControlRequest>,
+}
+
+impl ControlServer {
+    pub fn start(path: &Path) -> GenericResult<ControlServer> {
+        remove_stale_socket(path)?;
+
+        let listener = UnixListener::bind(path).map_err(|e| format!(
+            "Unable to listen on '{}': {}", path.display(), e))?;
+
+        let (sender, receiver) = mpsc::channel(16);
+        let server = ControlServer {
+            path: path.to_owned(),
+            requests: receiver,
+        };
+
+        // The socket allows to control the daemon, so it must be accessible only by its owner
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600)).map_err(|e| format!(
+            "Unable to change permissions of '{}': {}", path.display(), e))?;
+
+        info!("Listening for control commands on '{}'.", path.display());
+
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((connection, _)) => {
+                        let requests = sender.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = handle_connection(connection, requests).await {
+                                debug!("Failed to handle control connection: {}.", e);
+                            }
+                        });
+                    },
+                    Err(e) => error!("Failed to accept control connection: {}.", e),
+                }
+            }
+        });
+
+        Ok(server)
+    }
+
+    pub async fn request(&mut self) -> ControlRequest {
+        match self.requests.recv().await {
+            Some(request) => request,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            error!("Failed to remove '{}': {}.", self.path.display(), e);
+        }
+    }
+}
+
+fn remove_stale_socket(path: &Path) -> EmptyResult {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {},
+        Ok(_) => return Err!("Unable to listen on '{}': the file already exists and is not a socket", path.display()),
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err!("Unable to stat '{}': {}", path.display(), e),
+    }
+
+    if net::UnixStream::connect(path).is_ok() {
+        return Err!("'{}' control socket is already in use by another daemon", path.display());
+    }
+
+    fs::remove_file(path).map_err(|e| format!("Unable to remove '{}': {}", path.display(), e))?;
+    Ok(())
+}
+
+async fn handle_connection(connection: UnixStream, requests: mpsc::Sender<ControlRequest>) -> EmptyResult {
+    let (reader, mut writer) = connection.into_split();
+    let mut lines = tokio::io::BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let result = match ControlCommand::parse(line) {
+            Some(command) => execute(&requests, command).await,
+            None => Err!("Unknown command: {}", line),
+        };
+
+        let response = match result {
+            Ok(message) => format!("OK {}\n", message),
+            Err(e) => format!("ERROR {}\n", e.to_string().replace('\n', " ")),
+        };
+        writer.write_all(response.as_bytes()).await?;
+    }
+
+    Ok(())
+}
+
+async fn execute(requests: &mpsc::Sender<ControlRequest>, command: ControlCommand) -> GenericResult<String> {
+    let (reply, result) = oneshot::channel();
+
+    requests.send(ControlRequest {
+        command: command,
+        reply: reply,
+    }).await.map_err(|_| "The daemon is shutting down")?;
+
+    result.await.map_err(|_| "The daemon is shutting down")?
+}
+
+/// Sends the command to the running daemon and returns its response message
+pub fn send_command(path: &Path, command: &str) -> GenericResult<String> {
+    let mut connection = net::UnixStream::connect(path).map_err(|e| format!(
+        "Unable to connect to the daemon via '{}': {}", path.display(), e))?;
+    connection.set_read_timeout(Some(Duration::from_secs(60)))?;
+
+    connection.write_all(format!("{}\n", command).as_bytes())?;
+
+    let mut response = String::new();
+    BufReader::new(&connection).read_line(&mut response).map_err(|e| format!(
+        "Failed to read the daemon's response: {}", e))?;
+
+    let response = response.trim_end();
+    if let Some(message) = response.strip_prefix("OK ") {
+        Ok(s!(message))
+    } else if let Some(error) = response.strip_prefix("ERROR ") {
+        Err!("{}", error)
+    } else {
+        Err!("Got an invalid response from the daemon: {:?}", response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use tokio::runtime::Runtime;
+
+    use super::*;
+
+    #[test]
+    fn test_control_socket() {
+        let runtime = Runtime::new().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("control.sock");
+
+        {
+            let mut server = runtime.block_on(async { ControlServer::start(&path) }).unwrap();
+            assert!(runtime.block_on(async { ControlServer::start(&path) }).is_err());
+
+            runtime.spawn(async move {
+                loop {
+                    let request = server.request().await;
+                    let result = match request.command {
+                        ControlCommand::Status => Ok(s!("1 torrents, 0 copying")),
+                        ControlCommand::Copy(ref hash) => Ok(format!("{} is scheduled for copying", hash)),
+                        _ => Err!("Not implemented"),
+                    };
+                    request.reply(result);
+                }
+            });
+
+            assert_eq!(send_command(&path, "status").unwrap(), "1 torrents, 0 copying");
+            assert_eq!(send_command(&path, "reload-config").unwrap_err().to_string(), "Not implemented");
+            assert_eq!(send_command(&path, "copy ABC").unwrap(), "abc is scheduled for copying");
+            assert_eq!(send_command(&path, "unknown").unwrap_err().to_string(), "Unknown command: unknown");
+            assert_eq!(send_command(&path, "status now").unwrap_err().to_string(), "Unknown command: status now");
+        }
+
+        // The server is dropped along with the runtime
+        drop(runtime);
+        assert!(!path.exists());
+    }
+}
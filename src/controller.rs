@@ -1,32 +1,151 @@
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{self as std_time, Instant};
 
-use time::{OffsetDateTime, Duration};
+use serde_json::{json, Value};
+use time::Duration;
 
+use crate::bandwidth::BandwidthGroups;
+use crate::commands;
 use crate::common::{EmptyResult, GenericResult};
-use crate::consumer::Consumer;
-use crate::email::{Mailer, EmailTemplate};
-use crate::transmissionrpc::{self, TransmissionClient, Torrent, TorrentStatus};
+use crate::config::{AutoResumeConfig, ControllerConfig, FreeSpaceConfig, RemovalConfig, TransferMode};
+use crate::consumer::{Consumer, ConsumerOptions};
+use crate::disk_space::{self, format_size};
+use crate::download_queue::DownloadQueue;
+use crate::duplicates::{self, DuplicateAction, Duplicates};
+use crate::email::EmailTemplate;
+use crate::history::History;
+use crate::hooks::{HookEvent, HookParams, Hooks};
+use crate::http::{ApiCommand, ApiError, ApiResult};
+use crate::maintenance::Maintenance;
+use crate::metrics::METRICS;
+use crate::notifications::{Event, Notifier};
+use crate::policy::{Policies, Policy};
+use crate::progress::{ProgressEvent, ProgressNotifications};
+use crate::quotas::LabelQuotas;
+use crate::retention::Retention;
+use crate::routing::Routes;
+use crate::scope::Scope;
+use crate::seed_cycling::SeedCycler;
+use crate::seeding::{SeedingAction, SeedingLimit, SeedingLimits};
+use crate::snapshot::{TorrentChange, TorrentSnapshot};
+use crate::speed_schedule::SpeedSchedule;
+use crate::stalled::{StallAction, StalledTorrents};
+use crate::state_db::{Notification, StateDb};
+use crate::tasks::Scheduler;
+use crate::torrent_archive::TorrentArchive;
+use crate::torrent_errors::{ErrorAction, TorrentErrors};
+use crate::torrent_client::{self, Capability, ClientError, TorrentClient, blocking};
+use crate::tasks::backup::StateBackup;
+use crate::tasks::backup_trackers::BackupTrackers;
+use crate::tasks::dedup::FileDeduplicator;
+use crate::tasks::library_retention::LibraryRetention;
+use crate::tasks::blocklist::BlocklistUpdater;
+use crate::tasks::digests::NotificationDigests;
+use crate::tasks::orphans::OrphanedFilesCleaner;
+use crate::tasks::port_forwarding::PortForwarder;
+use crate::tasks::port_test::PortTester;
+use crate::tasks::report::StatisticsReport;
+use crate::tasks::rss::RssFeeds;
+use crate::tasks::tracker_errors::TrackerMonitor;
+use crate::tasks::trash::{self, TrashCleaner};
+use crate::tasks::watch_dir::WatchDirectory;
+use crate::telegram::BotCommand;
+use crate::tracker_limits::TrackerSpeedLimits;
+use crate::trackers::{BlockedTrackerAction, TrackerRewriter, Trackers, TrackerStatus};
+use crate::traffic::TrackerTraffic;
+use crate::transmissionrpc::{
+    Eta, Torrent, TorrentFields, TorrentStatus, TorrentError};
 use crate::util;
 use crate::util::time::{WeekPeriods, Timestamp};
+use crate::verification::PeriodicVerification;
+use crate::vpn::VpnMonitor;
 
 pub struct Controller {
     action: Option<Action>,
     action_periods: WeekPeriods,
 
     download_dir: PathBuf,
+    free_space: FreeSpaceConfig,
     free_space_threshold: Option<u8>,
-    upload_ratio_limit: Option<f64>,
-    seed_time_limit: Option<util::time::Duration>,
+    free_space_level: FreeSpaceLevel,
+    policies: Policies,
+    seeding_limits: SeedingLimits,
+    retention: Retention,
+    removal: RemovalConfig,
+    auto_resume: AutoResumeConfig,
+    transfer_mode: TransferMode,
 
-    client: Arc<TransmissionClient>,
+    client: Arc<dyn TorrentClient>,
+    notifier: Arc<Notifier>,
+    hooks: Arc<Hooks>,
+    state_db: Arc<StateDb>,
+    history: Arc<History>,
+    torrent_archive: Option<TorrentArchive>,
+    scope: Scope,
+    routes: Routes,
     consumer: Consumer,
+    bandwidth_groups: BandwidthGroups,
+    speed_schedule: SpeedSchedule,
+    download_queue: Option<DownloadQueue>,
+    seed_cycler: Option<SeedCycler>,
+    periodic_verification: Option<PeriodicVerification>,
+    duplicates: Option<Duplicates>,
+    trackers: Trackers,
+    label_quotas: LabelQuotas,
+    tracker_rewriter: TrackerRewriter,
+    tracker_speed_limits: TrackerSpeedLimits,
+    tracker_traffic: TrackerTraffic,
+    stalled_torrents: Option<StalledTorrents>,
+    torrent_errors: TorrentErrors,
+    progress_notifications: ProgressNotifications,
+    vpn: Option<VpnMonitor>,
+    maintenance: Option<Maintenance>,
+    tasks: Scheduler,
+
+    required_capabilities: Vec<Capability>,
+    capabilities_checked: bool,
 
     manual_time: Option<Instant>,
+    /// Paused via the control socket: the torrents aren't touched until processing is resumed
+    processing_paused: bool,
+
+    torrents: HashMap<u64, Torrent>,
+    snapshot: TorrentSnapshot,
+    /// The daemon state, whether downloads are paused due to lack of free space and the version of the exceeded label
+    /// quotas as of the previous check: the unchanged downloads are checked only when they change
+    check_context: Option<(State, bool, u64)>,
+    consuming_torrents: HashSet<String>,
+    verifying_torrents: HashSet<String>,
+    /// Torrents which have been paused due to lack of free space
+    paused_downloads: HashSet<String>,
+    stale_torrents: HashSet<String>,
+    /// In dry run mode the torrents aren't consumed, so remember the ones which would be consumed to not log them
+    /// on every check
+    dry_run: bool,
+    dry_run_consumed: HashSet<String>,
+    /// Torrents removed during the current check: name and size of the deleted data
+    removed_torrents: Vec<(String, Option<u64>)>,
+    full_update_time: Option<Instant>,
+    update_time: Option<Instant>,
+}
+
+// Transmission considers torrents recently active during 60 seconds, so we have to do a full update if we haven't
+// been able to get the updates for a long time.
+const MAX_INCREMENTAL_UPDATE_INTERVAL: std_time::Duration = std_time::Duration::from_secs(30);
+const FULL_UPDATE_INTERVAL: std_time::Duration = std_time::Duration::from_secs(10 * 60);
+const TRASH_CLEANUP_INTERVAL: std_time::Duration = std_time::Duration::from_secs(60 * 60);
+const DIGESTS_CHECK_INTERVAL: std_time::Duration = std_time::Duration::from_secs(60);
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum FreeSpaceLevel {
+    Normal,
+    Warning,
+    Critical,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum State {
     Active,
     Paused,
@@ -39,182 +158,3060 @@ pub enum Action {
     PauseOrStart,
 }
 
+/// All torrents with their files which are needed to check whether the removed torrents share their files with the
+/// others. They are fetched on the first removal and then reused by the other removals of the processing pass.
+#[derive(Default)]
+struct TorrentsWithFiles {
+    torrents: Option<Vec<Torrent>>,
+}
+
+impl TorrentsWithFiles {
+    async fn get(&mut self, client: &dyn TorrentClient) -> torrent_client::Result<&[Torrent]> {
+        if self.torrents.is_none() {
+            self.torrents = Some(client.get_torrents(TorrentFields::basic().with_files()).await?);
+        }
+        Ok(self.torrents.as_ref().unwrap())
+    }
+
+    fn remove(&mut self, hash: &str) {
+        if let Some(ref mut torrents) = self.torrents {
+            torrents.retain(|torrent| torrent.hash != hash);
+        }
+    }
+}
+
+/// The controller's settings which may be overridden from the command line
+pub struct ControllerOptions {
+    pub action: Option<Action>,
+    pub action_periods: WeekPeriods,
+    pub download_dir: PathBuf,
+    pub copy_to: Option<PathBuf>,
+    pub move_to: Option<PathBuf>,
+    pub seed_time_limit: Option<util::time::Duration>,
+    pub upload_ratio_limit: Option<f64>,
+    pub free_space_threshold: Option<u8>,
+    pub torrent_downloaded_email_template: EmailTemplate,
+}
+
 impl Controller {
     pub fn new(
-        client: TransmissionClient, action: Option<Action>, action_periods: WeekPeriods,
-        download_dir: PathBuf, copy_to: Option<PathBuf>, move_to: Option<PathBuf>,
-        seed_time_limit: Option<util::time::Duration>, upload_ratio_limit: Option<f64>,
-        free_space_threshold: Option<u8>, notifications_mailer: Option<Mailer>,
-        torrent_downloaded_email_template: EmailTemplate,
+        client: Arc<dyn TorrentClient>, blocking_client: blocking::TorrentClient, config: &ControllerConfig,
+        options: ControllerOptions, notifier: Arc<Notifier>,
     ) -> Controller {
-        let client = Arc::new(client);
+        let ControllerOptions {
+            action, action_periods, download_dir, copy_to, move_to, seed_time_limit, upload_ratio_limit,
+            free_space_threshold, torrent_downloaded_email_template,
+        } = options;
+
+        // Policies take precedence over the standalone bandwidth groups
+        let bandwidth_groups: Vec<_> = config.policies.iter().filter_map(Policy::bandwidth_group)
+            .chain(config.bandwidth_groups.iter().cloned()).collect();
+
+        let mut required_capabilities = Vec::new();
+        if !bandwidth_groups.is_empty() {
+            required_capabilities.push(Capability::BandwidthGroups);
+        }
+        if bandwidth_groups.iter().any(|group| !group.labels.is_empty()) {
+            required_capabilities.push(Capability::Labels);
+        }
+        if config.rss.feeds.iter().any(|feed| feed.sequential_download) {
+            required_capabilities.push(Capability::SequentialDownload);
+        }
+
+        let dry_run = client.is_dry_run();
+
+        let mut hooks = Hooks::new(config.hooks.clone(), notifier.clone());
+        hooks.set_dry_run(dry_run);
+        let hooks = Arc::new(hooks);
+
+        let state_db = Arc::new(StateDb::new(config.state_file.clone(), dry_run));
+        let history = Arc::new(History::new(config.history_db.as_deref(), dry_run));
+        let routes = Routes::new(config.routes.clone(), &download_dir, copy_to, move_to);
+        let scope = Scope::new(&config.manage_only, &config.ignore, &download_dir);
+
+        let mut tasks = Scheduler::new();
+        if let Some(interval) = config.port_test_interval {
+            tasks.add(PortTester::new(notifier.clone()), interval);
+        }
+        if let Some(interval) = config.orphaned_files.check_interval {
+            // Orphaned files are only reported in dry run mode
+            let delete_after = if dry_run { None } else { config.orphaned_files.delete_after };
+            tasks.add(OrphanedFilesCleaner::new(download_dir.clone(), delete_after, notifier.clone()), interval);
+        }
+        if !config.deduplication.paths.is_empty() {
+            // Duplicates are only reported in dry run mode
+            tasks.add(FileDeduplicator::new(&config.deduplication, dry_run, notifier.clone()),
+                      config.deduplication.check_interval);
+        }
+        if !config.library_retention.rules.is_empty() {
+            // The items to delete are only reported in dry run mode
+            tasks.add(LibraryRetention::new(
+                &config.library_retention, config.removal.trash_dir.clone(), dry_run, notifier.clone(),
+            ), config.library_retention.check_interval);
+        }
+        if let Some(interval) = config.tracker_monitoring.check_interval {
+            tasks.add(TrackerMonitor::new(config.tracker_monitoring.max_failures, notifier.clone()), interval);
+        }
+        if let Some(interval) = config.report.interval {
+            tasks.add(StatisticsReport::new(
+                &config.report, download_dir.clone(), history.clone(), notifier.clone()), interval);
+        }
+        if notifier.has_digests() {
+            tasks.add(NotificationDigests::new(notifier.clone()), DIGESTS_CHECK_INTERVAL);
+        }
+
+        // The following tasks exist only to modify the daemon's state, so they are disabled in dry run mode
+        if !dry_run {
+            if let Some(interval) = config.blocklist_update_interval {
+                tasks.add(BlocklistUpdater::new(notifier.clone()), interval);
+            }
+            if !config.backup_trackers.urls.is_empty() {
+                tasks.add(BackupTrackers::new(&config.backup_trackers), config.backup_trackers.check_interval);
+            }
+            if let Some(gateway) = config.port_forwarding.gateway {
+                let lifetime = config.port_forwarding.lifetime;
+                tasks.add(PortForwarder::new(gateway, lifetime), lifetime / 2);
+            }
+            if let Some(ref path) = config.watch_dir.path {
+                let watch_dir = &config.watch_dir;
+                tasks.add(WatchDirectory::new(
+                    path.clone(), download_dir.clone(), watch_dir.archive_dir.clone(), watch_dir.subdir_download_dirs,
+                ), watch_dir.check_interval);
+            }
+            if !config.rss.feeds.is_empty() {
+                tasks.add(RssFeeds::new(&config.rss, download_dir.clone()), config.rss.check_interval);
+            }
+            if let (Some(config_dir), Some(path)) = (&config.backup.config_dir, &config.backup.path) {
+                tasks.add(StateBackup::new(
+                    config_dir.clone(), path.clone(), config.backup.max_backups), config.backup.interval);
+            }
+            if let Some(ref path) = config.removal.trash_dir {
+                tasks.add(TrashCleaner::new(path.clone(), config.removal.trash_retention), TRASH_CLEANUP_INTERVAL);
+            }
+        }
 
         Controller {
             action, action_periods,
 
-            download_dir, free_space_threshold,
-            upload_ratio_limit, seed_time_limit,
+            download_dir,
+            free_space: get_free_space_config(config, free_space_threshold),
+            free_space_threshold: free_space_threshold,
+            free_space_level: FreeSpaceLevel::Normal,
+            policies: Policies::new(config.policies.clone()),
+            seeding_limits: SeedingLimits::new(
+                get_seeding_limits(config), upload_ratio_limit,
+                seed_time_limit.map(|limit| std_time::Duration::from_secs(limit as u64))),
+            retention: Retention::new(config.retention.clone()),
+            removal: config.removal.clone(),
+            auto_resume: config.auto_resume.clone(),
+            transfer_mode: config.transfer_mode,
 
-            client: client.clone(),
-            consumer: Consumer::new(client, copy_to, move_to, notifications_mailer, torrent_downloaded_email_template),
+            client: client,
+            notifier: notifier.clone(),
+            hooks: hooks.clone(),
+            state_db: state_db.clone(),
+            history: history.clone(),
+            torrent_archive: config.torrent_archive_dir.clone().map(|path| TorrentArchive::new(path, dry_run)),
+            scope: scope,
+            routes: routes.clone(),
+            consumer: Consumer::new(blocking_client, config, ConsumerOptions {
+                routes: routes,
+                notifier: notifier.clone(),
+                torrent_downloaded_email_template: torrent_downloaded_email_template,
+                hooks: hooks,
+                state_db: state_db,
+                history: history,
+            }),
+            bandwidth_groups: BandwidthGroups::new(bandwidth_groups),
+            speed_schedule: SpeedSchedule::new(config.speed_schedule.clone()),
+            download_queue: config.max_active_downloads.map(DownloadQueue::new),
+            seed_cycler: config.seed_cycling.max_active.map(|max_active| {
+                SeedCycler::new(max_active, config.seed_cycling.interval)
+            }),
+            periodic_verification: config.periodic_verification.enabled.then(|| {
+                PeriodicVerification::new(&config.periodic_verification)
+            }),
+            duplicates: config.duplicates.action.map(|action| Duplicates::new(
+                action, config.duplicates.history_file.clone())),
+            trackers: Trackers::new(config.trackers.clone()),
+            label_quotas: LabelQuotas::new(config.label_quotas.clone()),
+            tracker_rewriter: TrackerRewriter::new(config.tracker_rewrites.clone()),
+            tracker_speed_limits: TrackerSpeedLimits::new(config.tracker_speed_limits.clone()),
+            tracker_traffic: TrackerTraffic::new(),
+            stalled_torrents: config.stalled_torrent_timeout.map(StalledTorrents::new),
+            torrent_errors: TorrentErrors::new(config.torrent_errors.clone()),
+            progress_notifications: ProgressNotifications::new(config.progress_notifications.clone()),
+            vpn: config.vpn.is_enabled().then(|| VpnMonitor::new(config.vpn.clone())),
+            maintenance: config.maintenance.is_enabled().then(|| Maintenance::new(config.maintenance.clone())),
+            tasks: tasks,
+
+            required_capabilities: required_capabilities,
+            capabilities_checked: false,
 
             manual_time: None,
+            processing_paused: false,
+
+            torrents: HashMap::new(),
+            snapshot: TorrentSnapshot::new(),
+            check_context: None,
+            consuming_torrents: HashSet::new(),
+            verifying_torrents: HashSet::new(),
+            paused_downloads: HashSet::new(),
+            stale_torrents: HashSet::new(),
+            dry_run: dry_run,
+            dry_run_consumed: HashSet::new(),
+            removed_torrents: Vec::new(),
+            full_update_time: None,
+            update_time: None,
         }
     }
 
-    pub fn control(&mut self) -> transmissionrpc::EmptyResult {
-        let state = self.calculate_state()?;
+    pub async fn control(&mut self) -> torrent_client::EmptyResult {
+        // The kill-switch works even when processing is paused
+        if let Some(ref mut vpn) = self.vpn {
+            let was_down = vpn.is_down();
+            if !vpn.control(self.client.as_ref(), &self.notifier).await? {
+                return Ok(());
+            }
+
+            // The torrents haven't been tracked while the VPN has been down
+            if was_down {
+                self.full_update_time = None;
+            }
+        }
+
+        if let Some(ref mut maintenance) = self.maintenance {
+            let was_active = maintenance.is_active();
+            if !maintenance.control(self.client.as_ref()).await? {
+                return Ok(());
+            }
+
+            // The torrents haven't been tracked during the maintenance
+            if was_active {
+                self.full_update_time = None;
+            }
+        }
+
+        if self.processing_paused {
+            debug!("Processing is paused.");
+            return Ok(());
+        }
+
+        // Fail early with a clear error if the client doesn't support the configured features
+        if !self.capabilities_checked {
+            // The version is queried on startup to log it and to check the required capabilities right away
+            self.client.get_version().await?;
+            for capability in &self.required_capabilities {
+                self.client.check_capability(*capability).await?;
+            }
+            self.capabilities_checked = true;
+        }
+
+        let state = self.calculate_state().await?;
         debug!("Transmission daemon should be in {:?} state.", state);
 
+        self.speed_schedule.apply(self.client.as_ref()).await?;
+
         // Be careful here: we should get snapshot of current torrent status in exactly the
         // following order to not get into data race.
         let consuming_torrents = self.consumer.get_in_process();
-        let torrents = self.client.get_torrents()?;
+        self.stale_torrents.extend(self.consuming_torrents.difference(&consuming_torrents).cloned());
+        self.consuming_torrents = consuming_torrents.clone();
+
+        for hash in self.consumer.take_verification_requests() {
+            self.verify_torrent(&hash).await?;
+        }
+
+        // The seeds are verified one by one to not overload the disk
+        if self.verifying_torrents.is_empty() {
+            let now = ::time::OffsetDateTime::now_utc().unix_timestamp();
+            let hash = self.periodic_verification.as_ref().and_then(|verification| {
+                verification.select(&self.get_managed_torrents(), &self.state_db, now)
+            }).map(|torrent| torrent.hash.clone());
 
+            if let Some(hash) = hash {
+                self.verify_torrent(&hash).await?;
+            }
+        }
+
+        let torrents = self.update_torrents().await?;
+        self.state_db.retain(&torrents);
+        self.verifying_torrents.retain(|hash| torrents.iter().any(|torrent| &torrent.hash == hash));
+
+        self.paused_downloads.retain(|hash| torrents.iter().any(|torrent| &torrent.hash == hash));
+        self.dry_run_consumed.retain(|hash| torrents.iter().any(|torrent| &torrent.hash == hash));
+
+        let mut all_torrents = TorrentsWithFiles::default();
+        let torrents = torrents.into_iter().filter(|torrent| self.scope.contains(torrent)).collect();
+        let torrents = self.handle_trackers(torrents, &mut all_torrents).await?;
+        let torrents = self.handle_duplicates(torrents, &mut all_torrents).await?;
+        METRICS.set_torrents(&torrents);
+
+        self.tracker_traffic.update(&torrents);
+        METRICS.set_tracker_traffic(self.tracker_traffic.get());
+
+        let changes = self.snapshot.update(&torrents);
+        for torrent in &torrents {
+            match changes.get(&torrent.hash) {
+                Some(TorrentChange::Added) => info!("'{}' torrent has been added.", torrent.name),
+                Some(TorrentChange::Completed) => info!("'{}' torrent has completed downloading.", torrent.name),
+                Some(TorrentChange::Errored) => info!(
+                    "'{}' torrent has entered an error state: {}.", torrent.name, torrent.error.as_ref().unwrap()),
+                Some(TorrentChange::Recovered) => info!("'{}' torrent has recovered from the error.", torrent.name),
+                Some(TorrentChange::Changed) | None => {},
+            }
+        }
+
+        // All records are refreshed on full checks to keep the ratios up to date
+        if self.check_context.is_none() {
+            self.history.retain(&torrents);
+            self.history.record_torrents(&torrents.iter().collect::<Vec<_>>());
+        } else if !changes.is_empty() {
+            self.history.record_torrents(&torrents.iter().filter(|torrent| {
+                changes.contains_key(&torrent.hash)
+            }).collect::<Vec<_>>());
+        }
+
+        self.archive_torrent_files(&torrents, &changes).await?;
+
+        let changed_torrents = self.bandwidth_groups.assign(self.client.as_ref(), &torrents).await?;
+        self.stale_torrents.extend(changed_torrents);
+
+        let changed_torrents = self.tracker_rewriter.rewrite(self.client.as_ref(), &torrents).await?;
+        self.stale_torrents.extend(changed_torrents);
+
+        let changed_torrents = self.tracker_speed_limits.apply(self.client.as_ref(), &torrents).await?;
+        self.stale_torrents.extend(changed_torrents);
+
+        self.handle_stalled_torrents(&torrents).await?;
+        self.handle_torrent_errors(&torrents).await?;
+        self.handle_progress(&torrents);
+
+        let free_space_level = match self.check_free_space().await {
+            Ok(level) => level,
+            Err(e) => {
+                error!("Failed to check free space: {}.", e);
+                self.free_space_level
+            },
+        };
+        let pause_downloads = free_space_level == FreeSpaceLevel::Critical && self.free_space.pause_downloads;
+        self.check_label_quotas(&torrents);
+
+        let started_downloads = if state != State::Paused && !pause_downloads {
+            self.enforce_download_limit(&torrents).await?
+        } else {
+            HashSet::new()
+        };
+
+        let started_seeds = if state != State::Paused {
+            self.enforce_seeding_limit(&torrents).await?
+        } else {
+            HashSet::new()
+        };
+
+        let context = (state, pause_downloads, self.label_quotas.version());
+        let same_context = self.check_context.replace(context) == Some(context);
         let mut removable_torrents = Vec::new();
 
         for torrent in torrents {
-            debug!("Checking '{}' torrent...", torrent.name);
+            let changed = !same_context || changes.contains_key(&torrent.hash);
+
+            // Nothing applies to an unchanged download until the conditions change. The seeding torrents are always
+            // checked since their limits depend on time.
+            if !changed && !torrent.done && !self.verifying_torrents.contains(&torrent.hash) &&
+                !started_downloads.contains(&torrent.hash) {
+                continue;
+            }
 
-            if torrent.status == TorrentStatus::Paused && state == State::Active {
-                info!("Resuming '{}' torrent...", torrent.name);
-                self.client.start(&torrent.hash)?;
-            } else if torrent.status != TorrentStatus::Paused && state == State::Paused {
-                info!("Pausing '{}' torrent...", torrent.name);
-                self.client.stop(&torrent.hash)?;
+            if changed {
+                debug!("Checking '{}' torrent ({}, ETA: {})...", torrent.name, torrent.status, torrent.eta);
             }
 
-            if !torrent.done || consuming_torrents.contains(&torrent.hash) {
+            if self.verifying_torrents.contains(&torrent.hash) && !self.check_verification(&torrent, &state).await? {
                 continue;
             }
 
-            if !torrent.processed {
-                info!("'{}' torrent has been downloaded.", torrent.name);
-                self.consumer.consume(&torrent.hash);
+            let seeding = torrent.done && torrent.processed && !consuming_torrents.contains(&torrent.hash) &&
+                !self.is_kept(&torrent);
+
+            // Retention rules don't depend on the seeding limits and apply to the stopped torrents as well
+            if let Some(reason) = seeding.then(|| self.retention.check(&torrent)).flatten() {
+                info!(hash = torrent.hash.as_str(), action = "remove";
+                      "'{}' torrent has {}. Deleting it...", torrent.name, reason);
+                let delete_data = self.removal.delete_data && self.policies.allows_data_deletion(&torrent);
+                self.remove_torrent(&torrent, delete_data, &mut all_torrents).await?;
                 continue;
             }
 
-            match (torrent.upload_ratio, self.upload_ratio_limit) {
-                (Some(ratio), Some(limit)) if ratio >= limit => {
-                    info!("'{}' torrent has seeded above upload ratio limit. Deleting it...", torrent.name);
-                    self.client.remove(&torrent.hash)?;
-                    continue;
-                },
-                _ => {},
+            let seeding_action = if seeding {
+                self.seeding_limits.check(&torrent)
+            } else {
+                None
+            };
+
+            // The torrents that have reached their seeding target must stay stopped regardless of the schedule
+            if let Some((SeedingAction::Stop, ref reason)) = seeding_action {
+                if torrent.status != TorrentStatus::Stopped {
+                    info!(hash = torrent.hash.as_str(), action = "stop";
+                          "'{}' torrent has {}. Stopping it...", torrent.name, reason);
+                    self.client.stop(&torrent.hash).await?;
+                    self.stale_torrents.insert(torrent.hash.clone());
+                }
+                removable_torrents.push(torrent);
+                continue;
+            }
+
+            if !torrent.done && pause_downloads {
+                if torrent.status != TorrentStatus::Stopped {
+                    info!(hash = torrent.hash.as_str(), action = "pause";
+                          "Pausing '{}' torrent due to lack of free space...", torrent.name);
+                    self.client.stop(&torrent.hash).await?;
+                    self.stale_torrents.insert(torrent.hash.clone());
+                    self.paused_downloads.insert(torrent.hash.clone());
+                }
+                continue;
+            }
+
+            let exceeded_quota = if torrent.done {
+                None
+            } else {
+                self.label_quotas.get_exceeded(&torrent).map(|quota| quota.label.clone())
+            };
+
+            if let Some(label) = exceeded_quota {
+                if torrent.status != TorrentStatus::Stopped {
+                    info!(hash = torrent.hash.as_str(), action = "pause";
+                          "Pausing '{}' torrent: '{}' label has exceeded its disk quota...", torrent.name, label);
+                    self.client.stop(&torrent.hash).await?;
+                    self.stale_torrents.insert(torrent.hash.clone());
+                    self.paused_downloads.insert(torrent.hash.clone());
+                }
+                continue;
+            }
+
+            if started_downloads.contains(&torrent.hash) || started_seeds.contains(&torrent.hash) ||
+                self.download_queue.as_ref().is_some_and(|queue| queue.is_queued(&torrent.hash)) ||
+                self.seed_cycler.as_ref().is_some_and(|cycler| cycler.is_cycled_out(&torrent.hash)) {
+                continue;
+            }
+
+            // Resume the torrents paused by us even in manual mode
+            if self.paused_downloads.remove(&torrent.hash) && torrent.status == TorrentStatus::Stopped &&
+                state == State::Manual {
+                info!(hash = torrent.hash.as_str(), action = "resume";
+                      "Resuming '{}' torrent: free space has been recovered...", torrent.name);
+                self.client.start(&torrent.hash).await?;
+                self.stale_torrents.insert(torrent.hash.clone());
+                continue;
+            }
+
+            // Completed torrents may be stopped by Transmission's own seeding limits, so only downloads are resumed
+            if torrent.status == TorrentStatus::Stopped && !torrent.done && self.action.is_none() &&
+                self.auto_resume.enabled && !self.auto_resume.is_excluded(&torrent) {
+                warn!("'{}' torrent has been paused unexpectedly. Resuming it...", torrent.name);
+                self.client.start(&torrent.hash).await?;
+                self.stale_torrents.insert(torrent.hash.clone());
+
+                if self.auto_resume.notify {
+                    let mut message = format!("'{}' torrent has been paused unexpectedly", torrent.name);
+                    if let Some(ref error) = torrent.error {
+                        message += &format!(" with the error: {}", error);
+                    }
+                    self.notifier.notify_about_in_background(
+                        Event::Problem, &torrent, &format!("'{}' torrent has been resumed", torrent.name),
+                        &format!("{}. Resuming it.", message));
+                }
+                continue;
+            }
+
+            if torrent.status == TorrentStatus::Stopped && state == State::Active {
+                info!(hash = torrent.hash.as_str(), action = "resume"; "Resuming '{}' torrent...", torrent.name);
+                self.client.start(&torrent.hash).await?;
+                self.stale_torrents.insert(torrent.hash.clone());
+            } else if torrent.status != TorrentStatus::Stopped && state == State::Paused {
+                info!(hash = torrent.hash.as_str(), action = "pause"; "Pausing '{}' torrent...", torrent.name);
+                self.client.stop(&torrent.hash).await?;
+                self.stale_torrents.insert(torrent.hash.clone());
+            }
+
+            if !torrent.done || consuming_torrents.contains(&torrent.hash) {
+                continue;
             }
 
-            if let Some(ref seed_time_limit) = self.seed_time_limit {
-                if OffsetDateTime::now_utc().unix_timestamp() - torrent.done_time.unwrap() >= *seed_time_limit {
-                    info!("'{}' torrent has seeded enough time to delete it. Deleting it...", torrent.name);
-                    self.client.remove(&torrent.hash)?;
+            if !torrent.processed {
+                if self.duplicates.as_ref().is_some_and(|duplicates| duplicates.is_skipped(&torrent.hash)) {
+                    continue;
+                }
+
+                if self.dry_run {
+                    if self.dry_run_consumed.insert(torrent.hash.clone()) {
+                        self.log_dry_run_consumption(&torrent);
+                    }
                     continue;
                 }
+
+                info!(hash = torrent.hash.as_str(), action = "downloaded";
+                      "'{}' torrent has been downloaded.", torrent.name);
+                self.hooks.run_in_background(HookEvent::TorrentFinished, HookParams::from_torrent(&torrent));
+                self.consumer.consume(&torrent.hash);
+                continue;
+            }
+
+            let removal_reason = match seeding_action {
+                Some((SeedingAction::Remove, reason)) => Some(reason),
+                _ => self.check_removal_grace_period(&torrent),
+            };
+
+            if let Some(reason) = removal_reason {
+                info!(hash = torrent.hash.as_str(), action = "remove";
+                      "'{}' torrent has {}. Deleting it...", torrent.name, reason);
+                let delete_data = self.removal.delete_data && self.policies.allows_data_deletion(&torrent);
+                self.remove_torrent(&torrent, delete_data, &mut all_torrents).await?;
+                continue;
             }
 
             removable_torrents.push(torrent);
         }
 
-        if let Err(e) = self.cleanup_fs(&removable_torrents) {
+        let removable_torrents = self.cleanup_label_quotas(removable_torrents, &mut all_torrents).await?;
+
+        if let Err(e) = self.cleanup_fs(&removable_torrents, &mut all_torrents).await {
             error!("Failed to cleanup the download directory: {}.", e)
         }
 
+        self.notify_removed_torrents();
+
+        self.tasks.run(self.client.as_ref()).await;
+
         Ok(())
     }
 
-    fn calculate_state(&mut self) -> transmissionrpc::Result<State> {
-        if self.action.is_none() {
-            return Ok(State::Manual);
+    /// Processes the specified torrent right away. Used when the controller is invoked by Transmission on torrent
+    /// completion (script-torrent-done-filename) instead of waiting for the daemon to notice it.
+    pub async fn process_downloaded(&mut self, hash: &str) -> GenericResult<()> {
+        let torrent = self.client.get_torrent(hash, TorrentFields::basic()).await?;
+        let name = torrent.name.clone();
+
+        let mut all_torrents = TorrentsWithFiles::default();
+        let torrents = if self.scope.contains(&torrent) { vec![torrent] } else { Vec::new() };
+        let torrents = self.handle_trackers(torrents, &mut all_torrents).await?;
+        let torrents = self.handle_duplicates(torrents, &mut all_torrents).await?;
+
+        let torrent = match torrents.into_iter().next() {
+            Some(torrent) => torrent,
+            None => {
+                info!("'{}' torrent isn't managed by the controller or has been removed.", name);
+                return Ok(());
+            },
+        };
+
+        if !torrent.done {
+            return Err!("'{}' torrent hasn't been downloaded yet", torrent.name);
+        } else if torrent.processed {
+            info!("'{}' torrent has been already processed.", torrent.name);
+            return Ok(());
+        } else if self.duplicates.as_ref().is_some_and(|duplicates| duplicates.is_skipped(&torrent.hash)) {
+            return Ok(());
+        } else if self.dry_run {
+            self.log_dry_run_consumption(&torrent);
+            return Ok(());
         }
 
-        if self.client.is_manual_mode()? {
-            if let Some(manual_time) = self.manual_time {
-                if manual_time.elapsed() < Duration::days(1) {
-                    return Ok(State::Manual);
-                }
+        info!(hash = torrent.hash.as_str(), action = "downloaded"; "'{}' torrent has been downloaded.", torrent.name);
 
-                error!("Reset outdated manual mode.");
-                self.client.set_manual_mode(false)?;
-            } else {
-                self.manual_time = Some(Instant::now());
-                return Ok(State::Manual);
+        // The hook must complete before the torrent is consumed
+        let (hooks, params) = (self.hooks.clone(), HookParams::from_torrent(&torrent));
+        tokio::task::spawn_blocking(move || hooks.run(HookEvent::TorrentFinished, &params)).await?;
+
+        self.consumer.consume(&torrent.hash);
+
+        loop {
+            match self.consumer.get_result(&torrent.hash) {
+                Some(true) => return Ok(()),
+                Some(false) => return Err!("Failed to consume '{}' torrent", torrent.name),
+                None if !self.consumer.is_alive() => return Err!(
+                    "Failed to consume '{}' torrent: the consumer has crashed", torrent.name),
+                None => tokio::time::sleep(std_time::Duration::from_millis(100)).await,
             }
         }
+    }
 
-        self.manual_time = None;
+    fn log_dry_run_consumption(&self, torrent: &Torrent) {
+        let destination = self.routes.get_destination(torrent);
+        let action = match (destination.copy_to, destination.move_to) {
+            (Some(copy_to), Some(move_to)) => format!(
+                " (copy to '{}' and move to '{}')", copy_to.display(), move_to.display()),
+            (Some(copy_to), None) => format!(" (copy to '{}')", copy_to.display()),
+            _ => String::new(),
+        };
+        info!("Dry run: would consume '{}' torrent{}.", torrent.name, action);
+    }
 
-        Ok(match self.action.unwrap() {
-            Action::StartOrPause => {
-                if util::time::is_now_in(&self.action_periods) {
-                    State::Active
-                } else {
-                    State::Paused
-                }
-            }
-            Action::PauseOrStart => {
-                if util::time::is_now_in(&self.action_periods) {
-                    State::Paused
-                } else {
-                    State::Active
-                }
-            }
-        })
+    fn is_in_maintenance(&self) -> bool {
+        self.maintenance.as_ref().is_some_and(|maintenance| maintenance.is_active())
     }
 
-    fn cleanup_fs(&self, torrents: &[Torrent]) -> EmptyResult {
-        if torrents.is_empty() || self.check_free_space()? {
-            return Ok(());
+    /// Short status for the service manager
+    pub fn status(&self) -> String {
+        let mut status = format!("{} torrents, {} copying", self.torrents.len(), self.consumer.get_copying_count());
+        if self.processing_paused {
+            status += ", processing is paused";
+        }
+        if self.is_in_maintenance() {
+            status += ", maintenance is in progress";
         }
+        status
+    }
 
-        let mut torrents: Vec<_> = torrents.iter()
-            .filter(|&torrent| Path::new(&torrent.download_dir) == self.download_dir.as_path())
-            .collect();
+    /// Machine-readable status for the MQTT subscribers (Home Assistant sensors, for example)
+    pub fn get_status_json(&self) -> Value {
+        let torrents = self.get_managed_torrents();
+        let count = |filter: fn(&Torrent) -> bool| torrents.iter().filter(|torrent| filter(torrent)).count();
 
-        torrents.sort_by(|a, b| {
-            let a = a.done_time.unwrap_or(Timestamp::MIN);
-            let b = b.done_time.unwrap_or(Timestamp::MAX);
-            a.cmp(&b)
-        });
+        let mut copies = self.consumer.get_copies();
+        copies.sort_by(|(_, a), (_, b)| a.name.cmp(&b.name));
 
-        for (id, torrent) in torrents.iter().enumerate() {
-            info!("Removing '{}' torrent to get a free space on the disk...", torrent.name);
-            self.client.remove(&torrent.hash)?;
+        let free_space: serde_json::Map<String, Value> = METRICS.get_free_space().into_iter().map(|(path, space)| {
+            (path.display().to_string(), json!(space.free))
+        }).collect();
 
-            if id == torrents.len() - 1 || self.check_free_space()? {
-                break;
+        json!({
+            "status": self.status(),
+            "torrents": torrents.len(),
+            "downloading": count(|torrent| !torrent.done),
+            "seeding": count(|torrent| torrent.status == TorrentStatus::Seeding),
+            "pending": count(|torrent| torrent.done && !torrent.processed),
+            "copying": self.consumer.get_copying_count(),
+            "processing_paused": self.processing_paused,
+            "maintenance": self.is_in_maintenance(),
+            "free_space": free_space,
+            "copies": copies.iter().map(|(_, copy)| json!({
+                "name": copy.name,
+                "percent": copy.percent(),
+                "speed": copy.speed(),
+                "eta": copy.eta(),
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    /// Whether something is about to happen: a torrent is being copied or is about to be downloaded
+    pub fn is_active(&self, eta_threshold: std_time::Duration) -> bool {
+        self.consumer.get_copying_count() != 0 || self.get_managed_torrents().into_iter().any(|torrent| {
+            torrent.status == TorrentStatus::Downloading &&
+                matches!(torrent.eta, Eta::Seconds(eta) if eta <= eta_threshold.as_secs())
+        })
+    }
+
+    /// Waits until the torrents scheduled for consuming are consumed or fail. Returns the number of failed torrents.
+    pub async fn wait_for_consuming(&self) -> usize {
+        let scheduled = self.consumer.get_in_process();
+
+        loop {
+            let results: Vec<_> = scheduled.iter().map(|hash| self.consumer.get_result(hash)).collect();
+            if !results.contains(&None) {
+                return results.into_iter().filter(|&result| result == Some(false)).count();
             }
+            tokio::time::sleep(std_time::Duration::from_millis(100)).await;
         }
+    }
 
-        Ok(())
+    /// Waits for the in-flight copies to finish (see Consumer::stop())
+    pub fn shutdown(&mut self, timeout: std_time::Duration) {
+        self.consumer.stop(timeout);
+
+        // The digests are kept in memory
+        self.notifier.send_digests(true);
+    }
+
+    pub fn pause_processing(&mut self) {
+        self.processing_paused = true;
+    }
+
+    pub fn resume_processing(&mut self) {
+        self.processing_paused = false;
+    }
+
+    /// Applies the new configuration of the torrent management rules: policies, seeding limits, retention, removal,
+    /// free space, auto resume and the scope. The other options require restart.
+    pub fn reload_config(&mut self, config: &ControllerConfig) {
+        self.free_space = get_free_space_config(config, self.free_space_threshold);
+        self.policies = Policies::new(config.policies.clone());
+        self.seeding_limits.set_limits(get_seeding_limits(config));
+        self.retention = Retention::new(config.retention.clone());
+        self.removal = config.removal.clone();
+        self.auto_resume = config.auto_resume.clone();
+        self.progress_notifications = ProgressNotifications::new(config.progress_notifications.clone());
+        self.scope = Scope::new(&config.manage_only, &config.ignore, &self.download_dir);
+
+        // The torrents may have got in or out of the scope
+        self.full_update_time = None;
+    }
+
+    /// Forces the next check to be a full one: all torrents are updated, all periodic tasks are executed and the
+    /// torrents which have failed to be consumed are retried.
+    pub fn request_full_check(&mut self) {
+        self.full_update_time = None;
+        self.tasks.reset();
+        self.consumer.retry_failed();
+        self.consumer.log_progress();
     }
 
-    fn check_free_space(&self) -> GenericResult<bool> {
-        let free_space_threshold = match self.free_space_threshold {
-            Some(value) => value,
-            None => return Ok(true),
+    pub async fn handle_api_command(&mut self, command: &ApiCommand) -> ApiResult {
+        let hash = match *command {
+            ApiCommand::GetTorrents => return Ok(self.get_api_torrents()),
+            ApiCommand::GetDashboard => return Ok(self.get_dashboard()),
+            ApiCommand::Process => {
+                info!("Running a full check on API request...");
+                self.request_full_check();
+                return Ok(json!({"message": "A full check has been scheduled"}));
+            },
+            ApiCommand::Copy(ref hash) | ApiCommand::Exempt(ref hash) | ApiCommand::Remove(ref hash) => hash,
         };
 
-        let (device, usage) = util::fs::get_device_usage(&self.download_dir)?;
+        let torrent = match self.client.get_torrent(hash, TorrentFields::basic()).await {
+            Ok(torrent) if self.scope.contains(&torrent) => torrent,
+            Ok(_) | Err(ClientError::TorrentNotFound(_)) => {
+                return Err(ApiError::NotFound(format!(
+                    "{} torrent doesn't exist or isn't managed by the controller", hash)));
+            },
+            Err(e) => return Err(ApiError::Failed(e.to_string())),
+        };
+        let consuming = self.consumer.get_in_process().contains(&torrent.hash);
 
-        let free_space = 100 - usage;
-        let needs_cleanup = free_space <= free_space_threshold;
+        let message = match *command {
+            ApiCommand::Copy(_) => {
+                if !torrent.done {
+                    return Err(ApiError::Conflict(format!("'{}' torrent hasn't been downloaded yet", torrent.name)));
+                } else if consuming {
+                    return Err(ApiError::Conflict(format!("'{}' torrent is already being consumed", torrent.name)));
+                }
 
-        if needs_cleanup {
-            info!("We don't have enough free space on {}: {}% vs allowed > {}%.",
-                device, free_space, free_space_threshold)
-        }
+                if self.dry_run {
+                    self.log_dry_run_consumption(&torrent);
+                } else {
+                    info!(hash = torrent.hash.as_str(), action = "copy";
+                          "Copying '{}' torrent on API request...", torrent.name);
+                    self.consumer.consume_again(&torrent.hash);
+                }
+
+                format!("'{}' torrent has been scheduled for copying", torrent.name)
+            },
+
+            ApiCommand::Exempt(_) => {
+                let label = self.removal.keep_label.clone().ok_or_else(|| ApiError::Conflict(s!(
+                    "The torrents can't be exempted from removal: 'removal.keep-label' isn't configured")))?;
+
+                if !torrent.labels.contains(&label) {
+                    let mut labels = torrent.labels.clone();
+                    labels.push(label);
+
+                    self.client.set_labels(&torrent.hash, &labels).await.map_err(|e| ApiError::Failed(e.to_string()))?;
+                    self.stale_torrents.insert(torrent.hash.clone());
+                    info!(hash = torrent.hash.as_str(), action = "exempt";
+                          "'{}' torrent has been exempted from removal on API request.", torrent.name);
+                }
+
+                format!("'{}' torrent has been exempted from removal", torrent.name)
+            },
+
+            ApiCommand::Remove(_) => {
+                if consuming {
+                    return Err(ApiError::Conflict(format!("'{}' torrent is being consumed", torrent.name)));
+                }
+
+                info!(hash = torrent.hash.as_str(), action = "remove";
+                      "Deleting '{}' torrent on API request...", torrent.name);
+                let delete_data = self.removal.delete_data && self.policies.allows_data_deletion(&torrent);
+                self.remove_torrent(&torrent, delete_data, &mut TorrentsWithFiles::default()).await
+                    .map_err(|e| ApiError::Failed(e.to_string()))?;
+
+                format!("'{}' torrent has been removed", torrent.name)
+            },
+
+            ApiCommand::GetTorrents | ApiCommand::GetDashboard | ApiCommand::Process => unreachable!(),
+        };
 
-        Ok(!needs_cleanup)
+        Ok(json!({"message": message}))
     }
-}
 
-#[allow(clippy::to_string_trait_impl)]
-impl ToString for Action {
-    fn to_string(&self) -> String {
-        use self::Action::*;
+    pub async fn handle_bot_command(&mut self, command: &BotCommand) -> GenericResult<String> {
+        Ok(match *command {
+            BotCommand::Status => self.status(),
 
-        s!(match *self {
-            StartOrPause => "start-or-pause",
-            PauseOrStart => "pause-or-start",
+            BotCommand::List => {
+                let torrents: Vec<String> = self.get_managed_torrents().into_iter().map(|torrent| {
+                    let state = match commands::get_state(torrent) {
+                        "downloading" => format!("{}%", commands::get_percent_done(torrent)),
+                        state => s!(state),
+                    };
+                    format!("• {} ({}, {})", torrent.name, torrent.status, state)
+                }).collect();
+
+                if torrents.is_empty() {
+                    s!("There are no managed torrents")
+                } else {
+                    torrents.join("\n")
+                }
+            },
+
+            BotCommand::Pause(ref name) => {
+                let torrent = self.find_torrent_by_name(name)?;
+
+                info!(hash = torrent.hash.as_str(), action = "pause";
+                      "Pausing '{}' torrent on Telegram bot request...", torrent.name);
+                self.client.stop(&torrent.hash).await?;
+                self.stale_torrents.insert(torrent.hash.clone());
+
+                // Auto resume must not resume the torrent
+                match self.auto_resume.exclude_labels.first() {
+                    Some(label) if self.auto_resume.enabled && !self.auto_resume.is_excluded(&torrent) => {
+                        let mut labels = torrent.labels.clone();
+                        labels.push(label.clone());
+                        self.client.set_labels(&torrent.hash, &labels).await?;
+                    },
+                    _ => {},
+                }
+
+                format!("'{}' torrent has been paused", torrent.name)
+            },
+
+            BotCommand::Process => {
+                info!("Running a full check on Telegram bot request...");
+                self.request_full_check();
+                s!("A full check has been scheduled")
+            },
         })
     }
+
+    /// Finds the managed torrent by its exact name or by a unique part of it
+    fn find_torrent_by_name(&self, name: &str) -> GenericResult<Torrent> {
+        let torrents = self.get_managed_torrents();
+
+        let mut matching: Vec<&Torrent> = torrents.iter().copied().filter(|torrent| torrent.name == name).collect();
+        if matching.is_empty() {
+            let name = name.to_lowercase();
+            matching = torrents.iter().copied().filter(|torrent| torrent.name.to_lowercase().contains(&name)).collect();
+        }
+
+        match matching.as_slice() {
+            [] => Err!("There is no managed torrent matching '{}'", name),
+            [torrent] => Ok((*torrent).clone()),
+            _ => {
+                let names: Vec<&str> = matching.iter().map(|torrent| torrent.name.as_str()).collect();
+                Err!("'{}' matches several torrents: {}", name, names.join(", "))
+            },
+        }
+    }
+
+    fn get_managed_torrents(&self) -> Vec<&Torrent> {
+        let mut torrents: Vec<&Torrent> = self.torrents.values()
+            .filter(|torrent| self.scope.contains(torrent))
+            .collect();
+        torrents.sort_by(|a, b| a.name.cmp(&b.name));
+        torrents
+    }
+
+    fn get_api_torrents(&self) -> Value {
+        Value::Array(self.get_managed_torrents().into_iter().map(|torrent| {
+            let record = self.state_db.get(&torrent.hash).unwrap_or_default();
+            let (removable, removal_reason) = self.get_removal_state(torrent);
+
+            json!({
+                "hash": torrent.hash,
+                "name": torrent.name,
+                "status": torrent.status.to_string(),
+                "done": torrent.done,
+                "processed": torrent.processed,
+                "consuming": self.consuming_torrents.contains(&torrent.hash),
+                "copied_to": record.copied_to,
+                "processed_time": record.processed_time,
+                "policy": self.policies.find(torrent).map(|policy| policy.name.as_str()),
+                "kept": self.is_kept(torrent),
+                "removable": removable,
+                "removal_reason": removal_reason,
+            })
+        }).collect())
+    }
+
+    pub fn get_dashboard(&self) -> Value {
+        let downloads: Vec<Value> = self.get_managed_torrents().into_iter()
+            .filter(|torrent| !torrent.done)
+            .map(|torrent| json!({
+                "hash": torrent.hash,
+                "name": torrent.name,
+                "status": torrent.status.to_string(),
+                "percent": (torrent.size - torrent.left_until_done.min(torrent.size)) * 100 / torrent.size.max(1),
+                "eta": torrent.eta.to_string(),
+                "peers": torrent.peers_connected,
+            }))
+            .collect();
+
+        let mut copies = self.consumer.get_copies();
+        copies.sort_by(|(_, a), (_, b)| a.name.cmp(&b.name));
+
+        let queued: Vec<&str> = self.get_managed_torrents().into_iter().filter(|torrent| {
+            self.consuming_torrents.contains(&torrent.hash) && !copies.iter().any(|(hash, _)| hash == &torrent.hash)
+        }).map(|torrent| torrent.name.as_str()).collect();
+
+        let copies: Vec<Value> = copies.iter().map(|(hash, copy)| json!({
+            "hash": hash,
+            "name": copy.name,
+            "size": copy.size,
+            "copied": copy.copied,
+            "percent": copy.percent(),
+            "speed": copy.speed(),
+            "eta": copy.eta(),
+        })).collect();
+
+        let notifications: Vec<Value> = self.notifier.get_history().into_iter().map(|(time, subject)| json!({
+            "time": time,
+            "subject": subject,
+        })).collect();
+
+        let disk_space: Vec<Value> = METRICS.get_free_space().into_iter().map(|(path, space)| json!({
+            "path": path,
+            "free": space.free,
+            "total": space.total,
+        })).collect();
+
+        json!({
+            "status": self.status(),
+            "downloads": downloads,
+            "copies": copies,
+            "queued": queued,
+            "notifications": notifications,
+            "disk_space": disk_space,
+            "torrents": self.get_api_torrents(),
+        })
+    }
+
+    /// Explains whether the torrent is going to be removed by the controller (mirrors the checks of the control loop)
+    fn get_removal_state(&self, torrent: &Torrent) -> (bool, String) {
+        if !torrent.done {
+            return (false, s!("hasn't been downloaded yet"));
+        } else if self.consuming_torrents.contains(&torrent.hash) {
+            return (false, s!("is being consumed"));
+        } else if !torrent.processed {
+            return (false, s!("hasn't been consumed yet"));
+        } else if self.is_kept(torrent) {
+            return (false, s!("is kept by the label"));
+        }
+
+        if let Some(reason) = self.retention.check(torrent) {
+            return (true, format!("has {}", reason));
+        }
+
+        match self.seeding_limits.check(torrent) {
+            Some((SeedingAction::Remove, reason)) => return (true, format!("has {}", reason)),
+            Some((SeedingAction::Stop, reason)) => return (false, format!(
+                "has {} and is stopped (it's removed only on lack of free space)", reason)),
+            None => {},
+        }
+
+        match self.check_removal_grace_period(torrent) {
+            Some(reason) => (true, format!("has {}", reason)),
+            None => (false, s!("hasn't reached its seeding limits (may be removed on lack of free space)")),
+        }
+    }
+
+    // On a big number of torrents fetching all of them on each poll is expensive, so we do a full update only from
+    // time to time and request only recently active torrents between the full updates.
+    async fn update_torrents(&mut self) -> torrent_client::Result<Vec<Torrent>> {
+        // Reset the state to force full update in case of error
+        let last_full_update_time = self.full_update_time.take();
+        let last_update_time = self.update_time.take();
+
+        let full_update = match (last_full_update_time, last_update_time) {
+            (Some(full_update_time), Some(update_time)) => {
+                full_update_time.elapsed() >= FULL_UPDATE_INTERVAL ||
+                update_time.elapsed() >= MAX_INCREMENTAL_UPDATE_INTERVAL
+            },
+            _ => true,
+        };
+
+        let update_time = Instant::now();
+
+        if full_update {
+            // Recheck all torrents from time to time just in case
+            self.check_context = None;
+
+            debug!("Getting all torrents...");
+            let torrents = self.client.get_torrents(TorrentFields::basic()).await?;
+            self.torrents = torrents.into_iter().map(|torrent| (torrent.id, torrent)).collect();
+            self.stale_torrents.clear();
+            self.full_update_time = Some(update_time);
+        } else {
+            debug!("Getting recently active torrents...");
+            let update = self.client.get_recently_active_torrents(TorrentFields::basic()).await?;
+
+            for id in update.removed {
+                self.torrents.remove(&id);
+            }
+
+            for torrent in update.torrents {
+                self.stale_torrents.remove(&torrent.hash);
+                self.torrents.insert(torrent.id, torrent);
+            }
+
+            // The torrents that have been changed by us may not be considered as active, so update them explicitly
+            if !self.stale_torrents.is_empty() {
+                let hashes: Vec<String> = self.stale_torrents.iter().cloned().collect();
+                let torrents = self.client.get_torrents_by_hash(&hashes, TorrentFields::basic()).await?;
+
+                self.torrents.retain(|_, torrent| !self.stale_torrents.contains(&torrent.hash));
+                for torrent in torrents {
+                    self.torrents.insert(torrent.id, torrent);
+                }
+
+                self.stale_torrents.clear();
+            }
+
+            self.full_update_time = last_full_update_time;
+        }
+
+        self.update_time = Some(update_time);
+
+        let mut torrents: Vec<Torrent> = self.torrents.values().cloned().collect();
+        torrents.sort_by_key(|torrent| torrent.id);
+
+        Ok(torrents)
+    }
+
+    /// Archives .torrent files of the new torrents (all torrents are considered new on the first check)
+    async fn archive_torrent_files(
+        &self, torrents: &[Torrent], changes: &HashMap<String, TorrentChange>,
+    ) -> torrent_client::EmptyResult {
+        let Some(ref archive) = self.torrent_archive else {
+            return Ok(());
+        };
+
+        let hashes: Vec<String> = torrents.iter().filter(|torrent| {
+            changes.get(&torrent.hash) == Some(&TorrentChange::Added) && !archive.contains(torrent)
+        }).map(|torrent| torrent.hash.clone()).collect();
+
+        if hashes.is_empty() {
+            return Ok(());
+        }
+
+        for torrent in self.client.get_torrents_by_hash(&hashes, TorrentFields::basic().with_files()).await? {
+            if let Err(e) = archive.archive(&torrent) {
+                error!("Failed to archive '{}' torrent file: {}.", torrent.name, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles the torrents from the blocked trackers. Returns only the managed torrents.
+    async fn handle_trackers(
+        &mut self, torrents: Vec<Torrent>, all_torrents: &mut TorrentsWithFiles,
+    ) -> torrent_client::Result<Vec<Torrent>> {
+        let mut managed = Vec::with_capacity(torrents.len());
+
+        for torrent in torrents {
+            match self.trackers.check(&torrent) {
+                TrackerStatus::Managed => managed.push(torrent),
+                TrackerStatus::Unmanaged => {},
+                TrackerStatus::Blocked => match self.trackers.blocked_action() {
+                    Some(BlockedTrackerAction::Notify)
+                        if self.state_db.record_notification(&torrent, Notification::BlockedTracker) => {
+                        let message = format!("'{}' torrent has been added from a blocked tracker", torrent.name);
+                        warn!("{}.", message);
+                        self.notifier.notify_about_in_background(
+                            Event::Problem, &torrent, &message, &format!("{}.", message));
+                    },
+                    Some(BlockedTrackerAction::Remove) => {
+                        warn!("'{}' torrent has been added from a blocked tracker. Removing it...", torrent.name);
+                        let delete_data = self.removal.delete_data && self.policies.allows_data_deletion(&torrent);
+                        self.remove_torrent(&torrent, delete_data, all_torrents).await?;
+                    },
+                    _ => {},
+                },
+            }
+        }
+
+        Ok(managed)
+    }
+
+    /// Handles the new duplicate torrents. Returns the torrents without the removed duplicates.
+    async fn handle_duplicates(
+        &mut self, mut torrents: Vec<Torrent>, all_torrents: &mut TorrentsWithFiles,
+    ) -> torrent_client::Result<Vec<Torrent>> {
+        let duplicates = match self.duplicates {
+            Some(ref mut duplicates) => duplicates,
+            None => return Ok(torrents),
+        };
+
+        if let Err(e) = duplicates.record_processed(&torrents) {
+            error!("Failed to update processed torrents history: {}.", e);
+        }
+
+        if !duplicates.needs_check(&torrents) {
+            return Ok(torrents);
+        }
+
+        let all_torrents_with_files = all_torrents.get(self.client.as_ref()).await?;
+        let found: Vec<(Torrent, String, bool)> = duplicates.check(all_torrents_with_files).into_iter()
+            .filter(|duplicate| torrents.iter().any(|torrent| torrent.hash == duplicate.torrent.hash))
+            .map(|duplicate| (duplicate.torrent.clone(), duplicate.reason, duplicate.shares_files))
+            .collect();
+        let action = duplicates.action();
+
+        for (torrent, reason, shares_files) in found {
+            let message = format!("'{}' torrent is a duplicate: it {}", torrent.name, reason);
+
+            match action {
+                DuplicateAction::Notify => {
+                    warn!("{}.", message);
+                    if self.state_db.record_notification(&torrent, Notification::Duplicate) {
+                        self.notifier.notify_about_in_background(
+                            Event::Problem, &torrent, &format!("'{}' torrent is a duplicate", torrent.name),
+                            &format!("{}.", message));
+                    }
+                },
+                DuplicateAction::Skip => {
+                    warn!("{}. Skipping its processing.", message);
+                },
+                DuplicateAction::Remove => {
+                    warn!("{}. Removing it...", message);
+
+                    // Deleting the shared files would damage the original torrent
+                    let delete_data = !shares_files && self.policies.allows_data_deletion(&torrent);
+                    self.remove_torrent(&torrent, delete_data, all_torrents).await?;
+                    torrents.retain(|other| other.hash != torrent.hash);
+                },
+            }
+        }
+
+        Ok(torrents)
+    }
+
+    /// Pauses the torrents exceeding the limit of simultaneous downloads and resumes them as the others complete.
+    /// Returns hashes of the resumed torrents.
+    async fn enforce_download_limit(&mut self, torrents: &[Torrent]) -> torrent_client::Result<HashSet<String>> {
+        let (to_stop, to_start) = match self.download_queue {
+            Some(ref mut queue) => queue.check(torrents),
+            None => return Ok(HashSet::new()),
+        };
+
+        for torrent in to_stop {
+            info!(hash = torrent.hash.as_str(), action = "pause";
+                  "Pausing '{}' torrent: too many active downloads...", torrent.name);
+            self.client.stop(&torrent.hash).await?;
+            self.stale_torrents.insert(torrent.hash.clone());
+        }
+
+        let mut started = HashSet::new();
+
+        for torrent in to_start {
+            info!(hash = torrent.hash.as_str(), action = "resume"; "Resuming queued '{}' torrent...", torrent.name);
+            self.client.start(&torrent.hash).await?;
+            self.stale_torrents.insert(torrent.hash.clone());
+            started.insert(torrent.hash.clone());
+        }
+
+        Ok(started)
+    }
+
+    /// Pauses the seeding torrents exceeding the limit of active seeds and rotates them. Returns hashes of the resumed
+    /// torrents.
+    async fn enforce_seeding_limit(&mut self, torrents: &[Torrent]) -> torrent_client::Result<HashSet<String>> {
+        let (to_stop, to_start) = match self.seed_cycler {
+            Some(ref mut cycler) => cycler.check(torrents, &self.seeding_limits),
+            None => return Ok(HashSet::new()),
+        };
+
+        for torrent in to_stop {
+            info!(hash = torrent.hash.as_str(), action = "pause";
+                  "Pausing '{}' torrent: too many active seeds...", torrent.name);
+            self.client.stop(&torrent.hash).await?;
+            self.stale_torrents.insert(torrent.hash.clone());
+        }
+
+        let mut started = HashSet::new();
+
+        for torrent in to_start {
+            info!(hash = torrent.hash.as_str(), action = "resume"; "Resuming '{}' torrent's seeding...", torrent.name);
+            self.client.start(&torrent.hash).await?;
+            self.stale_torrents.insert(torrent.hash.clone());
+            started.insert(torrent.hash.clone());
+        }
+
+        Ok(started)
+    }
+
+    async fn handle_stalled_torrents(&mut self, torrents: &[Torrent]) -> torrent_client::EmptyResult {
+        let stalled_torrents: Vec<(Torrent, StallAction)> = match self.stalled_torrents {
+            Some(ref mut stalled_torrents) => stalled_torrents.check(torrents).into_iter()
+                .map(|(torrent, action)| (torrent.clone(), action)).collect(),
+            None => return Ok(()),
+        };
+
+        for (torrent, action) in stalled_torrents {
+            let message = format!("'{}' torrent is stalled ({} peers connected)", torrent.name, torrent.peers_connected);
+
+            match action {
+                StallAction::Reannounce => {
+                    info!(hash = torrent.hash.as_str(), action = "reannounce"; "{}. Reannouncing it...", message);
+                    self.client.reannounce(&torrent.hash).await?;
+                },
+                StallAction::Restart => {
+                    info!(hash = torrent.hash.as_str(), action = "restart"; "{}. Restarting it...", message);
+                    self.client.stop(&torrent.hash).await?;
+                    self.client.start(&torrent.hash).await?;
+                    self.stale_torrents.insert(torrent.hash.clone());
+                },
+                StallAction::Notify => {
+                    warn!("{}.", message);
+                    self.notifier.notify_about_in_background(
+                        Event::Problem, &torrent, &format!("'{}' torrent is stalled", torrent.name), &format!(
+                        "{} and hasn't made any progress after reannounce and restart.", message));
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_progress(&mut self, torrents: &[Torrent]) {
+        for (torrent, event) in self.progress_notifications.check(torrents) {
+            let percent = commands::get_percent_done(torrent);
+            let (subject, message) = match event {
+                ProgressEvent::Threshold(threshold) => (
+                    format!("'{}' torrent is {}% downloaded", torrent.name, threshold),
+                    format!("'{}' torrent ({}) is {}% downloaded (ETA: {})",
+                            torrent.name, format_size(torrent.size), percent, torrent.eta),
+                ),
+                ProgressEvent::Eta(eta) => (
+                    format!("'{}' torrent is almost downloaded", torrent.name),
+                    format!("'{}' torrent ({}) is {}% downloaded and will be completed in {}",
+                            torrent.name, format_size(torrent.size), percent, util::time::format_duration(eta)),
+                ),
+            };
+
+            info!("{}.", message);
+            self.notifier.notify_about_in_background(Event::Progress, torrent, &subject, &format!("{}.", message));
+        }
+    }
+
+    async fn handle_torrent_errors(&mut self, torrents: &[Torrent]) -> torrent_client::EmptyResult {
+        let errored_torrents: Vec<(Torrent, ErrorAction)> = self.torrent_errors.check(torrents).into_iter()
+            .map(|(torrent, action)| (torrent.clone(), action)).collect();
+
+        for (torrent, action) in errored_torrents {
+            // Verification errors are handled separately
+            if self.verifying_torrents.contains(&torrent.hash) {
+                continue;
+            }
+
+            let error = torrent.error.as_ref().unwrap();
+
+            match action {
+                ErrorAction::Notify => {
+                    warn!("'{}' torrent has got an error: {}.", torrent.name, error);
+                    self.notifier.notify_about_in_background(
+                        Event::Problem, &torrent, &format!("'{}' torrent has got an error", torrent.name),
+                        &format!("'{}' torrent has got an error: {}.", torrent.name, error));
+                },
+                ErrorAction::Retry => {
+                    info!(hash = torrent.hash.as_str(), action = "restart";
+                          "Restarting '{}' torrent after the error: {}...", torrent.name, error);
+                    self.client.start(&torrent.hash).await?;
+                    self.stale_torrents.insert(torrent.hash.clone());
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn verify_torrent(&mut self, hash: &str) -> torrent_client::EmptyResult {
+        info!(hash = hash, action = "verify"; "Verifying {} torrent...", hash);
+        self.client.verify(hash).await?;
+        self.verifying_torrents.insert(s!(hash));
+        self.stale_torrents.insert(s!(hash));
+        Ok(())
+    }
+
+    /// Checks the status of the torrent which is being verified. Returns false if the torrent should be skipped
+    /// during the current check.
+    async fn check_verification(&mut self, torrent: &Torrent, state: &State) -> torrent_client::Result<bool> {
+        if torrent.status.is_checking() {
+            return Ok(false);
+        }
+
+        self.verifying_torrents.remove(&torrent.hash);
+
+        if let Some(TorrentError::LocalError(ref error)) = torrent.error {
+            error!("'{}' torrent has failed verification: {}.", torrent.name, error);
+            return Ok(false);
+        }
+
+        // The processed torrent has lost some of its pieces: its data is corrupted
+        if torrent.processed && !torrent.done {
+            let message = format!("'{}' torrent's data is corrupted", torrent.name);
+            error!("{}: {} are missing after verification.", message, format_size(torrent.left_until_done));
+            self.notifier.notify_about_in_background(Event::Problem, torrent, &message, &format!(
+                "{}: {} are missing after verification. The corrupted pieces will be downloaded again.",
+                message, format_size(torrent.left_until_done)));
+            return Ok(true);
+        }
+
+        info!(hash = torrent.hash.as_str(), action = "verified"; "'{}' torrent has been verified.", torrent.name);
+        self.state_db.record_verified(torrent);
+
+        if torrent.status == TorrentStatus::Stopped && *state != State::Paused {
+            info!(hash = torrent.hash.as_str(), action = "resume"; "Resuming '{}' torrent...", torrent.name);
+            self.client.start(&torrent.hash).await?;
+            self.stale_torrents.insert(torrent.hash.clone());
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// The torrents with the keep label are exempted from seeding limits and cleanup
+    fn is_kept(&self, torrent: &Torrent) -> bool {
+        self.removal.keep_label.as_ref().is_some_and(|label| torrent.labels.contains(label))
+    }
+
+    fn check_removal_grace_period(&self, torrent: &Torrent) -> Option<String> {
+        if self.is_kept(torrent) {
+            return None;
+        }
+
+        let grace_period = self.policies.removal_grace_period(torrent, self.removal.grace_period)?;
+        let done_time = self.state_db.processed_time(&torrent.hash).or(torrent.done_time)?;
+
+        let seed_time = (::time::OffsetDateTime::now_utc().unix_timestamp() - done_time).max(0) as u64;
+        if seed_time < grace_period.as_secs() {
+            return None;
+        }
+
+        Some(format!("been copied {} ago", util::time::format_duration(seed_time)))
+    }
+
+    async fn remove_torrent(
+        &mut self, torrent: &Torrent, delete_data: bool, all_torrents: &mut TorrentsWithFiles,
+    ) -> torrent_client::EmptyResult {
+        // The data of the processed torrents has been moved to the destination and must be preserved
+        let mut delete_data = delete_data && !(self.transfer_mode == TransferMode::Move && torrent.processed);
+
+        // Deleting the data would break the cross-seeded torrents
+        if delete_data {
+            let torrents = all_torrents.get(self.client.as_ref()).await?;
+            let torrent = torrents.iter().find(|other| other.hash == torrent.hash).unwrap_or(torrent);
+
+            if let Some(other) = duplicates::find_shared_files_owner(torrent, torrents) {
+                info!("'{}' torrent shares its files with '{}'. Keeping its data.", torrent.name, other.name);
+                delete_data = false;
+            }
+        }
+
+        // The data is deleted right away when we have run out of free space
+        let mut trash_dir = self.removal.trash_dir.clone().filter(|_| {
+            delete_data && self.free_space_level != FreeSpaceLevel::Critical
+        });
+
+        // The data is moved before the torrent removal: if it can't be moved, it's deleted along with the torrent
+        // instead of being left on the disk untracked.
+        if let Some(path) = trash_dir.clone() {
+            if self.dry_run {
+                info!("Dry run: would move '{}' torrent's data to '{}'.", torrent.name, path.display());
+            } else {
+                // The trash may be on another file system, so the move may take a while
+                let trashed_torrent = torrent.clone();
+                let result = match tokio::task::spawn_blocking(move || {
+                    trash::move_to_trash(&path, &trashed_torrent).map(|_| path)
+                }).await {
+                    Ok(result) => result,
+                    Err(err) => Err(err.into()),
+                };
+
+                match result {
+                    Ok(path) => info!(hash = torrent.hash.as_str(), action = "trash";
+                                      "'{}' torrent's data has been moved to '{}'.", torrent.name, path.display()),
+                    Err(e) => {
+                        error!("Failed to move '{}' torrent's data to the trash: {}. Deleting it instead.",
+                               torrent.name, e);
+                        trash_dir = None;
+                    },
+                }
+            }
+        }
+
+        self.client.remove(&torrent.hash, delete_data && trash_dir.is_none()).await?;
+        all_torrents.remove(&torrent.hash);
+        self.torrents.remove(&torrent.id);
+        self.history.record_removed(torrent);
+
+        if delete_data && trash_dir.is_none() && !self.dry_run {
+            info!(hash = torrent.hash.as_str(), action = "delete-data";
+                  "'{}' torrent's data has been deleted.", torrent.name);
+        }
+
+        self.hooks.run_in_background(HookEvent::Removed, HookParams::from_torrent(torrent));
+        self.removed_torrents.push((torrent.name.clone(), if delete_data && trash_dir.is_none() {
+            Some(torrent.size)
+        } else {
+            None
+        }));
+
+        Ok(())
+    }
+
+    fn notify_removed_torrents(&mut self) {
+        let removed_torrents = std::mem::take(&mut self.removed_torrents);
+        if !self.removal.notify || removed_torrents.is_empty() {
+            return;
+        }
+
+        let mut body = String::new();
+        let mut reclaimed_space = 0;
+
+        for (name, size) in &removed_torrents {
+            match size {
+                Some(size) => {
+                    body += &format!("* {} ({})\n", name, disk_space::format_size(*size));
+                    reclaimed_space += size;
+                },
+                None => body += &format!("* {}\n", name),
+            }
+        }
+
+        if reclaimed_space != 0 {
+            body += &format!("\nReclaimed space: {}.", disk_space::format_size(reclaimed_space));
+        }
+
+        let subject = match removed_torrents.len() {
+            1 => format!("'{}' torrent has been removed", removed_torrents[0].0),
+            count => format!("{} torrents have been removed", count),
+        };
+
+        self.notifier.notify_in_background(Event::Removed, &subject, &body);
+    }
+
+    async fn calculate_state(&mut self) -> torrent_client::Result<State> {
+        if self.action.is_none() {
+            return Ok(State::Manual);
+        }
+
+        if self.client.is_manual_mode().await? {
+            if let Some(manual_time) = self.manual_time {
+                if manual_time.elapsed() < Duration::days(1) {
+                    return Ok(State::Manual);
+                }
+
+                error!("Reset outdated manual mode.");
+                self.client.set_manual_mode(false).await?;
+            } else {
+                self.manual_time = Some(Instant::now());
+                return Ok(State::Manual);
+            }
+        }
+
+        self.manual_time = None;
+
+        Ok(match self.action.unwrap() {
+            Action::StartOrPause => {
+                if util::time::is_now_in(&self.action_periods) {
+                    State::Active
+                } else {
+                    State::Paused
+                }
+            }
+            Action::PauseOrStart => {
+                if util::time::is_now_in(&self.action_periods) {
+                    State::Paused
+                } else {
+                    State::Active
+                }
+            }
+        })
+    }
+
+    /// Notifies about the labels which have exceeded their disk quotas or got back within them
+    fn check_label_quotas(&mut self, torrents: &[Torrent]) {
+        for (quota, usage, exceeded) in self.label_quotas.update(torrents) {
+            let (usage, limit) = (format_size(usage), format_size(quota.size_limit()));
+
+            if exceeded {
+                let message = format!("'{}' label has exceeded its disk quota", quota.label);
+                warn!("{}: {} > {}.", message, usage, limit);
+                self.notifier.notify_in_background(Event::FreeSpace, &message, &format!(
+                    "{}: {} > {}. Its downloads are paused until the quota is freed.", message, usage, limit));
+            } else {
+                info!("'{}' label's disk usage is within its quota again: {} <= {}.", quota.label, usage, limit);
+            }
+        }
+    }
+
+    /// Removes the oldest processed torrents of the labels which have exceeded their disk quotas. Returns the remaining
+    /// torrents.
+    async fn cleanup_label_quotas(
+        &mut self, torrents: Vec<Torrent>, all_torrents: &mut TorrentsWithFiles,
+    ) -> torrent_client::Result<Vec<Torrent>> {
+        let candidates: Vec<Torrent> = torrents.iter()
+            .filter(|&torrent| self.policies.allows_data_deletion(torrent) && !self.is_kept(torrent))
+            .cloned().collect();
+
+        let selected: Vec<(Torrent, String)> = self.label_quotas.select_for_cleanup(&candidates).into_iter()
+            .map(|(torrent, quota)| (torrent.clone(), quota.label.clone())).collect();
+
+        for (torrent, label) in &selected {
+            info!(hash = torrent.hash.as_str(), action = "remove";
+                  "Removing '{}' torrent to fit into '{}' label's disk quota...", torrent.name, label);
+            self.remove_torrent(torrent, self.removal.delete_data, all_torrents).await?;
+        }
+
+        Ok(torrents.into_iter().filter(|torrent| {
+            !selected.iter().any(|(removed, _)| removed.hash == torrent.hash)
+        }).collect())
+    }
+
+    async fn cleanup_fs(&mut self, torrents: &[Torrent], all_torrents: &mut TorrentsWithFiles) -> EmptyResult {
+        if torrents.is_empty() || !self.free_space.cleanup || self.free_space_level != FreeSpaceLevel::Critical {
+            return Ok(());
+        }
+
+        let mut torrents: Vec<_> = torrents.iter()
+            .filter(|&torrent| Path::new(&torrent.download_dir) == self.download_dir.as_path())
+            .filter(|&torrent| self.policies.allows_data_deletion(torrent) && !self.is_kept(torrent))
+            .collect();
+
+        torrents.sort_by_key(|torrent| torrent.done_time.unwrap_or(Timestamp::MIN));
+
+        for (id, torrent) in torrents.iter().enumerate() {
+            info!(hash = torrent.hash.as_str(), action = "remove";
+                  "Removing '{}' torrent to get a free space on the disk...", torrent.name);
+            self.remove_torrent(torrent, true, all_torrents).await?;
+
+            if id == torrents.len() - 1 || self.check_free_space().await? != FreeSpaceLevel::Critical {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks free space in the download directory, notifying about its changes
+    async fn check_free_space(&mut self) -> GenericResult<FreeSpaceLevel> {
+        let (warning_threshold, critical_threshold) = (
+            self.free_space.warning_threshold, self.free_space.critical_threshold);
+
+        if warning_threshold.is_none() && critical_threshold.is_none() && !METRICS.is_enabled() {
+            return Ok(FreeSpaceLevel::Normal);
+        }
+
+        let space = disk_space::get_download_dir_space(self.client.as_ref(), &self.download_dir).await?;
+        METRICS.set_free_space(&self.download_dir, space);
+
+        if warning_threshold.is_none() && critical_threshold.is_none() {
+            return Ok(FreeSpaceLevel::Normal);
+        }
+
+        let (free_space, location) = match space.free_percent() {
+            Some(free_space) => (free_space, format!("'{}' ({})", self.download_dir.display(), space)),
+            None => {
+                // Old Transmission doesn't report total space and the directory isn't accessible via statvfs
+                let (device, usage) = util::fs::get_device_usage(&self.download_dir)?;
+                (100 - usage, device)
+            },
+        };
+
+        let level = if critical_threshold.is_some_and(|threshold| free_space <= threshold) {
+            FreeSpaceLevel::Critical
+        } else if warning_threshold.is_some_and(|threshold| free_space <= threshold) {
+            FreeSpaceLevel::Warning
+        } else {
+            FreeSpaceLevel::Normal
+        };
+
+        let previous_level = self.free_space_level;
+        self.free_space_level = level;
+
+        if level != previous_level {
+            let message = match level {
+                FreeSpaceLevel::Normal => format!("Free space on {} has been recovered: {}%", location, free_space),
+                FreeSpaceLevel::Warning => format!("Free space on {} is running low: {}%", location, free_space),
+                FreeSpaceLevel::Critical => format!("Free space on {} is critically low: {}%", location, free_space),
+            };
+
+            if level > previous_level {
+                warn!("{}.", message);
+                self.notifier.notify_in_background(Event::FreeSpace, &message, &format!("{}.", message));
+            } else {
+                info!("{}.", message);
+            }
+        }
+
+        Ok(level)
+    }
+}
+
+/// Policies take precedence over the standalone seeding limits
+fn get_seeding_limits(config: &ControllerConfig) -> Vec<SeedingLimit> {
+    config.policies.iter().map(Policy::seeding_limit).chain(config.seeding_limits.iter().cloned()).collect()
+}
+
+/// --free-space-threshold is a shortcut for critical threshold with cleanup
+fn get_free_space_config(config: &ControllerConfig, free_space_threshold: Option<u8>) -> FreeSpaceConfig {
+    let mut free_space = config.free_space.clone();
+    if let Some(threshold) = free_space_threshold {
+        free_space.critical_threshold = Some(threshold);
+        free_space.cleanup = true;
+    }
+    free_space
+}
+
+#[allow(clippy::to_string_trait_impl)]
+impl ToString for Action {
+    fn to_string(&self) -> String {
+        use self::Action::*;
+
+        s!(match *self {
+            StartOrPause => "start-or-pause",
+            PauseOrStart => "pause-or-start",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+    use std::thread;
+    use std::time;
+
+    use serde_json::json;
+    use tempfile::TempDir;
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    struct TestController {
+        server: MockServer,
+        download_dir: TempDir,
+        copy_to: TempDir,
+        runtime: Runtime,
+    }
+
+    impl TestController {
+        fn new() -> TestController {
+            let runtime = Runtime::new().unwrap();
+            TestController {
+                server: runtime.block_on(MockServer::start()),
+                download_dir: TempDir::new().unwrap(),
+                copy_to: TempDir::new().unwrap(),
+                runtime: runtime,
+            }
+        }
+
+        fn create(&self, upload_ratio_limit: Option<f64>, free_space_threshold: Option<u8>) -> Controller {
+            self.create_with_config(&ControllerConfig::default(), upload_ratio_limit, free_space_threshold)
+        }
+
+        fn create_with_config(
+            &self, config: &ControllerConfig, upload_ratio_limit: Option<f64>, free_space_threshold: Option<u8>,
+        ) -> Controller {
+            let client = Arc::new(self.server.client());
+            let blocking_client = blocking::TorrentClient::new(client.clone(), self.runtime.handle().clone());
+
+            Controller::new(client, blocking_client, config, self.options(upload_ratio_limit, free_space_threshold),
+                            Arc::new(Notifier::new(None)))
+        }
+
+        fn options(&self, upload_ratio_limit: Option<f64>, free_space_threshold: Option<u8>) -> ControllerOptions {
+            ControllerOptions {
+                action: None,
+                action_periods: Vec::new(),
+                download_dir: self.download_dir.path().to_owned(),
+                copy_to: Some(self.copy_to.path().to_owned()),
+                move_to: None,
+                seed_time_limit: None,
+                upload_ratio_limit: upload_ratio_limit,
+                free_space_threshold: free_space_threshold,
+                torrent_downloaded_email_template: EmailTemplate::new("Downloaded", "{{name}} has been downloaded"),
+            }
+        }
+
+        fn add_torrent(&self, id: u64, name: &str) -> MockTorrent {
+            let torrent = MockTorrent::new(id, name, self.download_dir.path().to_str().unwrap());
+            fs::write(self.download_dir.path().join(name), name).unwrap();
+            torrent
+        }
+
+        fn control(&self, controller: &mut Controller) {
+            self.runtime.block_on(controller.control()).unwrap();
+        }
+
+        fn wait_processed(&self, id: u64) {
+            for _ in 0..100 {
+                if self.server.torrent(id).unwrap().is_processed() {
+                    break;
+                }
+                thread::sleep(time::Duration::from_millis(50));
+            }
+        }
+    }
+
+    #[test]
+    fn test_consuming() {
+        let test = TestController::new();
+        test.server.add_torrent(test.add_torrent(1, "downloaded"));
+        test.server.add_torrent(test.add_torrent(2, "downloading").downloading());
+        test.server.add_torrent(test.add_torrent(3, "processed").processed());
+
+        {
+            let mut controller = test.create(None, None);
+            test.control(&mut controller);
+            test.wait_processed(1);
+        }
+
+        assert!(test.server.torrent(1).unwrap().is_processed());
+        assert!(!test.server.torrent(2).unwrap().is_processed());
+        assert_eq!(fs::read_to_string(test.copy_to.path().join("downloaded")).unwrap(), "downloaded");
+        assert!(!test.copy_to.path().join("downloading").exists());
+        assert!(!test.copy_to.path().join("processed").exists());
+    }
+
+    #[test]
+    fn test_link_mode() {
+        for (link_mode, linked) in [("auto", true), ("copy", false)] {
+            let test = TestController::new();
+            test.server.add_torrent(test.add_torrent(1, "downloaded"));
+
+            let config: ControllerConfig = toml::from_str(&format!(r#"
+                link-mode = "{}"
+                verify-copies = "full"
+            "#, link_mode)).unwrap();
+
+            {
+                let mut controller = test.create_with_config(&config, None, None);
+                test.control(&mut controller);
+                test.wait_processed(1);
+            }
+
+            let inode = |path: &Path| fs::metadata(path).unwrap().ino();
+            assert!(test.server.torrent(1).unwrap().is_processed());
+            assert_eq!(
+                inode(&test.copy_to.path().join("downloaded")) == inode(&test.download_dir.path().join("downloaded")),
+                linked);
+        }
+    }
+
+    #[test]
+    fn test_upload_ratio_limit() {
+        let test = TestController::new();
+
+        let mut torrent = test.add_torrent(1, "seeded").processed();
+        torrent.upload_ratio = 2.5;
+        test.server.add_torrent(torrent);
+
+        let mut torrent = test.add_torrent(2, "seeding").processed();
+        torrent.upload_ratio = 1.5;
+        test.server.add_torrent(torrent);
+
+        let mut controller = test.create(Some(2.0), None);
+        test.control(&mut controller);
+
+        assert!(test.server.torrent(1).is_none());
+        assert!(test.server.torrent(2).is_some());
+    }
+
+    #[test]
+    fn test_seeding_limits() {
+        let test = TestController::new();
+
+        for (id, tracker, ratio) in [
+            (1, "private.example.com", 2.5), (2, "bt.private.example.com", 3.5),
+            (3, "public.example.com", 2.5), (4, "public.example.com", 1.5),
+        ] {
+            let mut torrent = test.add_torrent(id, &format!("torrent-{}", id)).processed();
+            torrent.trackers = vec![format!("http://{}/announce", tracker)];
+            torrent.upload_ratio = ratio;
+            test.server.add_torrent(torrent);
+        }
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [[seeding-limits]]
+            trackers = ["private.example.com"]
+            ratio = 3.0
+            action = "stop"
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, Some(2.0), None);
+        test.control(&mut controller);
+
+        assert_eq!(test.server.torrent(1).unwrap().status, TorrentStatus::Seeding);
+        assert_eq!(test.server.torrent(2).unwrap().status, TorrentStatus::Stopped);
+        assert!(test.server.torrent(3).is_none());
+        assert!(test.server.torrent(4).is_some());
+    }
+
+    #[test]
+    fn test_seed_time_limits() {
+        let test = TestController::new();
+        let now = ::time::OffsetDateTime::now_utc().unix_timestamp();
+
+        for (id, label, seeded_hours) in [(1, "public", 80), (2, "public", 10), (3, "other", 80)] {
+            let mut torrent = test.add_torrent(id, &format!("torrent-{}", id)).processed();
+            torrent.labels = vec![s!(label)];
+            torrent.done_date = now - seeded_hours * 60 * 60;
+            test.server.add_torrent(torrent);
+        }
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [[seeding-limits]]
+            labels = ["public"]
+            seed-time = "3d"
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, None, None);
+        test.control(&mut controller);
+
+        assert!(test.server.torrent(1).is_none());
+        assert!(test.server.torrent(2).is_some());
+        assert!(test.server.torrent(3).is_some());
+    }
+
+    #[test]
+    fn test_removal_grace_period() {
+        let test = TestController::new();
+        let now = ::time::OffsetDateTime::now_utc().unix_timestamp();
+
+        for (id, seeded_hours) in [(1, 2), (2, 0)] {
+            let mut torrent = test.add_torrent(id, &format!("torrent-{}", id)).processed();
+            torrent.done_date = now - seeded_hours * 60 * 60;
+            test.server.add_torrent(torrent);
+        }
+        let free_space = test.server.state().free_space;
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [removal]
+            grace-period = "1h"
+            delete-data = false
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, None, None);
+        test.control(&mut controller);
+
+        assert!(test.server.torrent(1).is_none());
+        assert!(test.server.torrent(2).is_some());
+        assert_eq!(test.server.state().free_space, free_space);
+    }
+
+    #[test]
+    fn test_policies() {
+        let test = TestController::new();
+        let now = ::time::OffsetDateTime::now_utc().unix_timestamp();
+
+        for (id, tracker, ratio, seeded_days) in [
+            (1, "private.example.com", 1.5, 1), (2, "public.example.com", 1.2, 0),
+            (3, "private.example.com", 3.5, 0), (4, "private.example.com", 0.5, 40),
+        ] {
+            let mut torrent = test.add_torrent(id, &format!("torrent-{}", id)).processed();
+            torrent.trackers = vec![format!("http://{}/announce", tracker)];
+            torrent.upload_ratio = ratio;
+            torrent.done_date = now - seeded_days * 24 * 60 * 60;
+            test.server.add_torrent(torrent);
+        }
+        let free_space = test.server.state().free_space;
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [removal]
+            grace-period = "1h"
+
+            [[policies]]
+            name = "private"
+            trackers = ["private.example.com"]
+            ratio = 3.0
+            action = "stop"
+            removal-grace-period = "30d"
+            allow-data-deletion = false
+
+            [[policies]]
+            name = "public"
+            trackers = ["public.example.com"]
+            ratio = 1.0
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, None, None);
+        test.control(&mut controller);
+
+        assert_eq!(test.server.torrent(1).unwrap().status, TorrentStatus::Seeding);
+        assert!(test.server.torrent(2).is_none());
+        assert_eq!(test.server.torrent(3).unwrap().status, TorrentStatus::Stopped);
+        assert!(test.server.torrent(4).is_none());
+        assert_eq!(test.server.state().free_space, free_space + 1024);
+    }
+
+    #[test]
+    fn test_free_space_cleanup() {
+        let test = TestController::new();
+
+        for (id, name, done_date) in [(1, "newest", 3000), (2, "oldest", 1000), (3, "middle", 2000)] {
+            let mut torrent = test.add_torrent(id, name).processed();
+            torrent.done_date = done_date;
+            torrent.files[0].1 = 10;
+            test.server.add_torrent(torrent);
+        }
+
+        {
+            let mut state = test.server.state();
+            state.free_space = 5;
+            state.total_space = Some(100);
+        }
+
+        let mut controller = test.create(None, Some(20));
+        test.control(&mut controller);
+
+        // Oldest torrents are removed until there is enough free space
+        assert!(test.server.torrent(1).is_some());
+        assert!(test.server.torrent(2).is_none());
+        assert!(test.server.torrent(3).is_none());
+        assert_eq!(test.server.state().free_space, 25);
+    }
+
+    #[test]
+    fn test_free_space_pause_downloads() {
+        let test = TestController::new();
+        test.server.add_torrent(test.add_torrent(1, "downloading").downloading());
+
+        {
+            let mut state = test.server.state();
+            state.free_space = 5;
+            state.total_space = Some(100);
+        }
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [free-space]
+            warning-threshold = 20
+            critical-threshold = 10
+            pause-downloads = true
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, None, None);
+        test.control(&mut controller);
+        assert_eq!(test.server.torrent(1).unwrap().status, TorrentStatus::Stopped);
+
+        test.server.state().free_space = 15;
+        test.control(&mut controller);
+        assert_eq!(test.server.torrent(1).unwrap().status, TorrentStatus::Downloading);
+    }
+
+    #[test]
+    fn test_torrent_error_retry() {
+        let test = TestController::new();
+
+        let mut torrent = test.add_torrent(1, "errored").processed();
+        torrent.status = TorrentStatus::Stopped;
+        torrent.error = 3;
+        torrent.error_string = s!("No data found! Ensure your drives are connected");
+        test.server.add_torrent(torrent);
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [torrent-errors]
+            notify = true
+            retry-interval = "1s"
+            max-retries = 1
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, None, None);
+        let starts = || test.server.state().calls.iter().filter(|&call| call == "torrent-start").count();
+
+        test.control(&mut controller);
+        assert_eq!(starts(), 0);
+
+        for _ in 0..2 {
+            thread::sleep(time::Duration::from_millis(1100));
+            test.control(&mut controller);
+        }
+        assert_eq!(starts(), 1);
+    }
+
+    #[test]
+    fn test_speed_schedule() {
+        let test = TestController::new();
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [[speed-schedule]]
+            periods = ["1-7/00:00-24:00"]
+            download-limit = 1024
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, None, None);
+        test.control(&mut controller);
+
+        let state = test.server.state();
+        assert_eq!(state.download_limit, Some(1024));
+        assert_eq!(state.upload_limit, None);
+        assert!(!state.alt_speed_enabled);
+    }
+
+    #[test]
+    fn test_download_queue() {
+        let test = TestController::new();
+
+        for (id, priority) in [(1, 0), (2, 1), (3, 0)] {
+            let mut torrent = test.add_torrent(id, &format!("torrent-{}", id)).downloading();
+            torrent.bandwidth_priority = priority;
+            test.server.add_torrent(torrent);
+        }
+
+        let config: ControllerConfig = toml::from_str("max-active-downloads = 2").unwrap();
+        let mut controller = test.create_with_config(&config, None, None);
+
+        test.control(&mut controller);
+        let status = |id| test.server.torrent(id).unwrap().status;
+        assert_eq!(status(1), TorrentStatus::Downloading);
+        assert_eq!(status(2), TorrentStatus::Downloading);
+        assert_eq!(status(3), TorrentStatus::Stopped);
+
+        {
+            let mut state = test.server.state();
+            let torrent = state.torrents.iter_mut().find(|torrent| torrent.id == 2).unwrap();
+            torrent.left_until_done = 0;
+            torrent.status = TorrentStatus::Seeding;
+        }
+
+        test.control(&mut controller);
+        assert_eq!(status(3), TorrentStatus::Downloading);
+    }
+
+    #[test]
+    fn test_tracker_speed_limits() {
+        let test = TestController::new();
+
+        for (id, tracker) in [(1, "public.example.com"), (2, "private.example.com"), (3, "public.example.com")] {
+            let mut torrent = test.add_torrent(id, &format!("torrent-{}", id));
+            torrent = if id == 3 { torrent.downloading() } else { torrent.processed() };
+            torrent.trackers = vec![format!("http://{}/announce", tracker)];
+            test.server.add_torrent(torrent);
+        }
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [[tracker-speed-limits]]
+            trackers = ["public.example.com"]
+            download-limit = 2048
+            upload-limit = 1024
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, None, None);
+        test.control(&mut controller);
+
+        let limits = |id| {
+            let torrent = test.server.torrent(id).unwrap();
+            (torrent.download_limited.then_some(torrent.download_limit),
+             torrent.upload_limited.then_some(torrent.upload_limit))
+        };
+
+        // The processed torrent marker must be preserved
+        assert!(test.server.torrent(1).unwrap().is_processed());
+        assert_eq!(limits(1), (None, Some(1024)));
+        assert_eq!(limits(2), (None, None));
+        assert_eq!(limits(3), (Some(2048), Some(1024)));
+    }
+
+    #[test]
+    fn test_periodic_verification() {
+        let test = TestController::new();
+        let now = ::time::OffsetDateTime::now_utc().unix_timestamp();
+
+        for (id, seeded_days) in [(1, 10), (2, 20)] {
+            let mut torrent = test.add_torrent(id, &format!("torrent-{}", id)).processed();
+            torrent.done_date = now - seeded_days * 24 * 60 * 60;
+            test.server.add_torrent(torrent);
+        }
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [periodic-verification]
+            enabled = true
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, None, None);
+        let status = |id| test.server.torrent(id).unwrap().status;
+
+        // The torrents are selected from the previous check
+        test.control(&mut controller);
+        test.control(&mut controller);
+        assert_eq!(status(1), TorrentStatus::Seeding);
+        assert_eq!(status(2), TorrentStatus::CheckWait);
+
+        // The torrent has lost some of its pieces
+        {
+            let mut state = test.server.state();
+            let torrent = state.torrents.iter_mut().find(|torrent| torrent.id == 2).unwrap();
+            torrent.status = TorrentStatus::Downloading;
+            torrent.left_until_done = 1024;
+        }
+
+        test.control(&mut controller);
+        test.control(&mut controller);
+        assert_eq!(status(1), TorrentStatus::CheckWait);
+
+        {
+            let mut state = test.server.state();
+            state.torrents.iter_mut().find(|torrent| torrent.id == 1).unwrap().status = TorrentStatus::Seeding;
+        }
+        test.control(&mut controller);
+        test.control(&mut controller);
+
+        // All seeds have been verified during the current period
+        assert_eq!(status(1), TorrentStatus::Seeding);
+        assert_eq!(status(2), TorrentStatus::Downloading);
+    }
+
+    #[test]
+    fn test_label_quotas() {
+        let test = TestController::new();
+
+        for id in 1..=4 {
+            let mut torrent = test.add_torrent(id, &format!("torrent-{}", id));
+            torrent = if id == 3 { torrent.downloading() } else { torrent.processed() };
+            torrent.labels = vec![s!(if id == 4 { "movies" } else { "tv" })];
+            torrent.files[0].1 = 600 * 1024 * 1024;
+            test.server.add_torrent(torrent);
+        }
+        let free_space = test.server.state().free_space;
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [[label-quotas]]
+            label = "tv"
+            max-size = 1
+            cleanup = true
+
+            [removal]
+            delete-data = false
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, None, None);
+        test.control(&mut controller);
+
+        // The oldest processed torrents are removed until the label fits into the quota
+        assert!(test.server.torrent(1).is_none());
+        assert!(test.server.torrent(2).is_none());
+        assert_eq!(test.server.torrent(3).unwrap().status, TorrentStatus::Stopped);
+        assert!(test.server.torrent(4).is_some());
+        assert_eq!(test.server.state().free_space, free_space);
+
+        test.control(&mut controller);
+        assert_eq!(test.server.torrent(3).unwrap().status, TorrentStatus::Downloading);
+    }
+
+    #[test]
+    fn test_seed_cycling() {
+        let test = TestController::new();
+
+        for (id, ratio) in [(1, 0.5), (2, 1.5), (3, 2.5), (4, 0.1)] {
+            let mut torrent = test.add_torrent(id, &format!("torrent-{}", id)).processed();
+            torrent.labels = vec![s!(if id == 3 { "private" } else { "public" })];
+            torrent.upload_ratio = ratio;
+            test.server.add_torrent(torrent);
+        }
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [[seeding-limits]]
+            labels = ["private"]
+            ratio = 5.0
+
+            [seed-cycling]
+            max-active = 2
+            interval = "1s"
+        "#).unwrap();
+        let mut controller = test.create_with_config(&config, Some(2.0), None);
+
+        // Torrents 4 (0.1 / 2.0) and 1 (0.5 / 2.0) are the furthest from their targets
+        test.control(&mut controller);
+        let statuses = || (1..=4).map(|id| test.server.torrent(id).unwrap().status).collect::<Vec<_>>();
+        assert_eq!(statuses(), vec![
+            TorrentStatus::Seeding, TorrentStatus::Stopped, TorrentStatus::Stopped, TorrentStatus::Seeding]);
+
+        // The active torrents keep their slots until the next rotation
+        {
+            let mut state = test.server.state();
+            state.torrents.iter_mut().find(|torrent| torrent.id == 4).unwrap().upload_ratio = 1.9;
+        }
+        test.control(&mut controller);
+        assert_eq!(statuses(), vec![
+            TorrentStatus::Seeding, TorrentStatus::Stopped, TorrentStatus::Stopped, TorrentStatus::Seeding]);
+
+        // Torrent 3 (2.5 / 5.0) is further from its target than torrent 4 (1.9 / 2.0)
+        std::thread::sleep(std_time::Duration::from_secs(1));
+        test.control(&mut controller);
+        assert_eq!(statuses(), vec![
+            TorrentStatus::Seeding, TorrentStatus::Stopped, TorrentStatus::Seeding, TorrentStatus::Stopped]);
+    }
+
+    #[test]
+    fn test_auto_resume() {
+        let test = TestController::new();
+
+        for (id, label) in [(1, None), (2, Some("paused")), (3, None)] {
+            let mut torrent = test.add_torrent(id, &format!("torrent-{}", id)).downloading();
+            torrent.status = TorrentStatus::Stopped;
+            torrent.labels.extend(label.map(String::from));
+            test.server.add_torrent(torrent);
+        }
+
+        // Completed torrents must stay stopped
+        {
+            let mut state = test.server.state();
+            let torrent = state.torrents.iter_mut().find(|torrent| torrent.id == 3).unwrap();
+            torrent.left_until_done = 0;
+        }
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [auto-resume]
+            enabled = true
+            exclude-labels = ["paused"]
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, None, None);
+
+        test.control(&mut controller);
+        let status = |id| test.server.torrent(id).unwrap().status;
+        assert_eq!(status(1), TorrentStatus::Downloading);
+        assert_eq!(status(2), TorrentStatus::Stopped);
+        assert_eq!(status(3), TorrentStatus::Stopped);
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let test = TestController::new();
+        test.server.add_torrent(test.add_torrent(1, "original"));
+
+        let mut torrent = test.add_torrent(2, "shared-files");
+        torrent.files = vec![(s!("original"), 1024, true)];
+        test.server.add_torrent(torrent);
+
+        let mut torrent = test.add_torrent(3, "original");
+        torrent.files = vec![(s!("other"), 1024, true)];
+        test.server.add_torrent(torrent);
+
+        test.server.add_torrent(test.add_torrent(4, "unique"));
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [duplicates]
+            action = "remove"
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, None, None);
+        test.control(&mut controller);
+
+        assert!(test.server.torrent(1).is_some());
+        assert!(test.server.torrent(2).is_none());
+        assert!(test.server.torrent(3).is_none());
+        assert!(test.server.torrent(4).is_some());
+        assert!(test.download_dir.path().join("original").exists());
+    }
+
+    #[test]
+    fn test_routes() {
+        let test = TestController::new();
+        let library_dir = TempDir::new().unwrap();
+
+        let mut torrent = test.add_torrent(1, "tv-show");
+        torrent.labels = vec![s!("tv")];
+        test.server.add_torrent(torrent);
+
+        fs::create_dir(test.download_dir.path().join("movies")).unwrap();
+        fs::write(test.download_dir.path().join("movies/movie"), "movie").unwrap();
+        let mut torrent = MockTorrent::new(2, "movie", test.download_dir.path().join("movies").to_str().unwrap());
+        torrent.files = vec![(s!("movie"), 5, true)];
+        test.server.add_torrent(torrent);
+
+        test.server.add_torrent(test.add_torrent(3, "other"));
+
+        let config: ControllerConfig = toml::from_str(&format!(r#"
+            [[routes]]
+            labels = ["tv"]
+            copy-to = "{library}/tv"
+
+            [[routes]]
+            download-dirs = ["movies"]
+            copy-to = "{library}/movies"
+        "#, library = library_dir.path().display())).unwrap();
+
+        for name in ["tv", "movies"] {
+            fs::create_dir(library_dir.path().join(name)).unwrap();
+        }
+
+        {
+            let mut controller = test.create_with_config(&config, None, None);
+            test.control(&mut controller);
+            for id in 1..=3 {
+                test.wait_processed(id);
+            }
+        }
+
+        assert!(library_dir.path().join("tv/tv-show").exists());
+        assert!(library_dir.path().join("movies/movie").exists());
+        assert!(test.copy_to.path().join("other").exists());
+        assert!(!test.copy_to.path().join("tv-show").exists());
+    }
+
+    #[test]
+    fn test_exclude_files() {
+        let test = TestController::new();
+
+        fs::create_dir(test.download_dir.path().join("movie")).unwrap();
+        for name in ["movie.mkv", "movie.nfo", "movie-sample.mkv"] {
+            fs::write(test.download_dir.path().join("movie").join(name), name).unwrap();
+        }
+
+        let mut torrent = MockTorrent::new(1, "movie", test.download_dir.path().to_str().unwrap());
+        torrent.files = ["movie.mkv", "movie.nfo", "movie-sample.mkv"].iter()
+            .map(|name| (format!("movie/{}", name), name.len() as u64, true)).collect();
+        test.server.add_torrent(torrent);
+
+        let config: ControllerConfig = toml::from_str(r#"exclude-files = ["*sample*", "*.nfo"]"#).unwrap();
+
+        {
+            let mut controller = test.create_with_config(&config, None, None);
+            test.control(&mut controller);
+            test.wait_processed(1);
+        }
+
+        let mut names: Vec<String> = fs::read_dir(test.copy_to.path().join("movie")).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string()).collect();
+        names.sort();
+        assert_eq!(names, vec!["movie.mkv"]);
+    }
+
+    #[test]
+    fn test_sort_rules() {
+        let test = TestController::new();
+
+        let files = ["Show.S02.1080p/Show.S02E03.1080p.mkv", "Show.S02.1080p/Show.S02.nfo"];
+        fs::create_dir(test.download_dir.path().join("Show.S02.1080p")).unwrap();
+        for name in files {
+            fs::write(test.download_dir.path().join(name), name).unwrap();
+        }
+
+        let mut torrent = MockTorrent::new(1, "Show.S02.1080p", test.download_dir.path().to_str().unwrap());
+        torrent.files = files.iter().map(|name| (s!(*name), name.len() as u64, true)).collect();
+        test.server.add_torrent(torrent);
+
+        // The sorted files are added to the existing directories
+        fs::create_dir_all(test.copy_to.path().join("Show/Season 01")).unwrap();
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [[sort-rules]]
+            pattern = '(?:^|/)(?P<show>[^/]+?)\.S(?P<season>\d+)E(?P<episode>\d+)[^/]*\.(?P<ext>mkv)$'
+            path = '${show}/Season ${season}/${show} - S${season}E${episode}.${ext}'
+        "#).unwrap();
+
+        {
+            let mut controller = test.create_with_config(&config, None, None);
+            test.control(&mut controller);
+            test.wait_processed(1);
+        }
+
+        assert_eq!(fs::read_to_string(test.copy_to.path().join("Show/Season 02/Show - S02E03.mkv")).unwrap(), files[0]);
+        assert!(test.copy_to.path().join("Show/Season 01").exists());
+        assert!(test.copy_to.path().join("Show.S02.1080p/Show.S02.nfo").exists());
+        assert!(!test.copy_to.path().join("Show.S02.1080p/Show.S02E03.1080p.mkv").exists());
+    }
+
+    #[test]
+    fn test_atomic_copying() {
+        let test = TestController::new();
+        let torrent = test.add_torrent(1, "downloaded");
+
+        // A leftover from an interrupted copying which must be resumed
+        let temp_dir = test.copy_to.path().join(format!(".{}.tmp", torrent.hash));
+        fs::create_dir(&temp_dir).unwrap();
+        fs::write(temp_dir.join("downloaded"), "down").unwrap();
+
+        test.server.add_torrent(torrent);
+
+        {
+            let mut controller = test.create(None, None);
+            test.control(&mut controller);
+            test.wait_processed(1);
+        }
+
+        let names: Vec<String> = fs::read_dir(test.copy_to.path()).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string()).collect();
+        assert_eq!(names, vec!["downloaded"]);
+        assert_eq!(fs::read_to_string(test.copy_to.path().join("downloaded")).unwrap(), "downloaded");
+    }
+
+    #[test]
+    fn test_wait_for_consuming() {
+        let test = TestController::new();
+        test.server.add_torrent(test.add_torrent(1, "downloaded"));
+        test.server.add_torrent(MockTorrent::new(2, "missing", test.download_dir.path().to_str().unwrap()));
+
+        let mut controller = test.create(None, None);
+        test.control(&mut controller);
+
+        assert_eq!(test.runtime.block_on(controller.wait_for_consuming()), 1);
+        assert!(test.server.torrent(1).unwrap().is_processed());
+        assert!(!test.server.torrent(2).unwrap().is_processed());
+    }
+
+    #[test]
+    fn test_is_active() {
+        let test = TestController::new();
+        for (id, eta) in [(1, 600), (2, -1)] {
+            let mut torrent = test.add_torrent(id, &format!("torrent-{}", id)).downloading();
+            torrent.eta = eta;
+            test.server.add_torrent(torrent);
+        }
+
+        let mut controller = test.create(None, None);
+        test.control(&mut controller);
+        assert!(!controller.is_active(time::Duration::from_secs(60)));
+        assert!(controller.is_active(time::Duration::from_secs(600)));
+    }
+
+    #[test]
+    fn test_graceful_shutdown() {
+        let test = TestController::new();
+        let torrent = test.add_torrent(1, "downloaded");
+        fs::write(test.download_dir.path().join("downloaded"), vec![0; 2 * 1024 * 1024]).unwrap();
+        test.server.add_torrent(torrent);
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [copy-throttling]
+            speed-limit = 1
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, None, None);
+        test.control(&mut controller);
+
+        for _ in 0..100 {
+            if controller.consumer.get_copying_count() != 0 {
+                break;
+            }
+            thread::sleep(time::Duration::from_millis(10));
+        }
+
+        // The copy which is in progress must be finished
+        controller.shutdown(time::Duration::from_secs(60));
+        assert!(test.server.torrent(1).unwrap().is_processed());
+        assert_eq!(fs::metadata(test.copy_to.path().join("downloaded")).unwrap().len(), 2 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_parallel_copying() {
+        let test = TestController::new();
+        for id in 1..=4 {
+            test.server.add_torrent(test.add_torrent(id, &format!("torrent-{}", id)));
+        }
+
+        let config: ControllerConfig = toml::from_str(r#"
+            copy-workers = 3
+            copy-workers-per-destination = 2
+        "#).unwrap();
+
+        {
+            let mut controller = test.create_with_config(&config, None, None);
+            test.control(&mut controller);
+            for id in 1..=4 {
+                test.wait_processed(id);
+            }
+        }
+
+        for id in 1..=4 {
+            let name = format!("torrent-{}", id);
+            assert!(test.server.torrent(id).unwrap().is_processed());
+            assert_eq!(fs::read_to_string(test.copy_to.path().join(&name)).unwrap(), name);
+        }
+    }
+
+    #[test]
+    fn test_trackers() {
+        let test = TestController::new();
+
+        for (id, tracker) in [(1, "allowed.example.com"), (2, "other.example.com"), (3, "blocked.example.com")] {
+            let mut torrent = test.add_torrent(id, &format!("torrent-{}", id));
+            torrent.trackers = vec![format!("http://{}/announce", tracker)];
+            test.server.add_torrent(torrent);
+        }
+        let free_space = test.server.state().free_space;
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [trackers]
+            allowed = ["allowed.example.com", "blocked.example.com"]
+            blocked = ["blocked.example.com"]
+            blocked-action = "remove"
+
+            [removal]
+            delete-data = false
+        "#).unwrap();
+
+        {
+            let mut controller = test.create_with_config(&config, None, None);
+            test.control(&mut controller);
+            test.wait_processed(1);
+        }
+
+        assert!(test.server.torrent(1).unwrap().is_processed());
+        assert!(!test.server.torrent(2).unwrap().is_processed());
+        assert!(test.server.torrent(3).is_none());
+        assert_eq!(test.server.state().free_space, free_space);
+        assert!(test.copy_to.path().join("torrent-1").exists());
+        assert!(!test.copy_to.path().join("torrent-2").exists());
+    }
+
+    #[test]
+    fn test_state_db() {
+        let test = TestController::new();
+        test.server.add_torrent(test.add_torrent(1, "consumed"));
+        test.server.add_torrent(test.add_torrent(2, "downloaded"));
+
+        let state_file = test.download_dir.path().join("state.json");
+        fs::write(&state_file, format!(r#"{{"{:040x}": {{"name": "consumed", "processed_time": 1000000}}}}"#, 1)).unwrap();
+
+        let config: ControllerConfig = toml::from_str(&format!(r#"
+            state-file = "{}"
+        "#, state_file.display())).unwrap();
+
+        {
+            let mut controller = test.create_with_config(&config, None, None);
+            test.control(&mut controller);
+            test.wait_processed(1);
+            test.wait_processed(2);
+        }
+
+        assert!(test.server.torrent(1).unwrap().is_processed());
+        assert!(test.server.torrent(2).unwrap().is_processed());
+        assert!(!test.copy_to.path().join("consumed").exists());
+        assert!(test.copy_to.path().join("downloaded").exists());
+
+        let state: serde_json::Value = serde_json::from_str(&fs::read_to_string(&state_file).unwrap()).unwrap();
+        let record = &state[format!("{:040x}", 2)];
+        assert_eq!(record["copied_to"], json!(test.copy_to.path()));
+        assert_eq!(record["notifications"], json!(["downloaded"]));
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let test = TestController::new();
+        test.server.add_torrent(test.add_torrent(1, "downloaded"));
+
+        let mut torrent = test.add_torrent(2, "removable").processed();
+        torrent.upload_ratio = 2.0;
+        test.server.add_torrent(torrent);
+
+        let mut client = test.server.client();
+        client.set_dry_run(true);
+        let client = Arc::new(client);
+        let blocking_client = blocking::TorrentClient::new(client.clone(), test.runtime.handle().clone());
+
+        {
+            let mut controller = Controller::new(
+                client, blocking_client, &ControllerConfig::default(), test.options(Some(1.0), None),
+                Arc::new(Notifier::new(None)));
+
+            test.control(&mut controller);
+            test.control(&mut controller);
+        }
+
+        assert!(!test.server.torrent(1).unwrap().is_processed());
+        assert!(test.server.torrent(2).is_some());
+        assert!(!test.copy_to.path().join("downloaded").exists());
+
+        let calls = &test.server.state().calls;
+        assert!(calls.iter().all(|method| method == "torrent-get" || method == "session-get"), "{:?}", calls);
+    }
+
+    #[test]
+    fn test_full_check_request() {
+        let test = TestController::new();
+        test.server.add_torrent(MockTorrent::new(1, "broken", test.download_dir.path().to_str().unwrap()));
+
+        {
+            let mut controller = test.create(None, None);
+            test.control(&mut controller);
+            thread::sleep(time::Duration::from_millis(500));
+
+            // The torrent has failed to be copied, so it's not retried until the user requests it
+            fs::write(test.download_dir.path().join("broken"), "fixed").unwrap();
+            test.control(&mut controller);
+            thread::sleep(time::Duration::from_millis(500));
+            assert!(!test.server.torrent(1).unwrap().is_processed());
+
+            controller.request_full_check();
+            test.control(&mut controller);
+            test.wait_processed(1);
+        }
+
+        assert!(test.server.torrent(1).unwrap().is_processed());
+        assert_eq!(fs::read_to_string(test.copy_to.path().join("broken")).unwrap(), "fixed");
+    }
+
+    #[test]
+    fn test_retention() {
+        let test = TestController::new();
+        let now = ::time::OffsetDateTime::now_utc().unix_timestamp();
+
+        for (id, tracker, label, age_days) in [
+            (1, "private.example.com", "archive", 40), (2, "private.example.com", "other", 40),
+            (3, "private.example.com", "other", 10),
+        ] {
+            let mut torrent = test.add_torrent(id, &format!("torrent-{}", id)).processed();
+            torrent.trackers = vec![format!("http://{}/announce", tracker)];
+            torrent.labels = vec![s!(label)];
+            torrent.done_date = now - age_days * 24 * 60 * 60;
+            test.server.add_torrent(torrent);
+        }
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [[seeding-limits]]
+            trackers = ["private.example.com"]
+            ratio = 3.0
+            action = "stop"
+
+            [[retention]]
+            labels = ["archive"]
+            max-age = "180d"
+
+            [[retention]]
+            max-age = "30d"
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, None, None);
+        test.control(&mut controller);
+
+        assert!(test.server.torrent(1).is_some());
+        assert!(test.server.torrent(2).is_none());
+        assert!(test.server.torrent(3).is_some());
+    }
+
+    #[test]
+    fn test_copy_space_reserve() {
+        let test = TestController::new();
+        test.server.add_torrent(test.add_torrent(1, "torrent"));
+
+        let config: ControllerConfig = toml::from_str(r#"
+            link-mode = "copy"
+            copy-space-reserve = 1_000_000_000_000
+        "#).unwrap();
+
+        {
+            let mut controller = test.create_with_config(&config, None, None);
+            test.control(&mut controller);
+            thread::sleep(time::Duration::from_millis(500));
+        }
+
+        assert!(!test.server.torrent(1).unwrap().is_processed());
+        assert!(!test.copy_to.path().join("torrent").exists());
+    }
+
+    #[test]
+    fn test_move_transfer_mode() {
+        let test = TestController::new();
+        test.server.add_torrent(test.add_torrent(1, "downloaded"));
+
+        let config: ControllerConfig = toml::from_str(r#"
+            transfer-mode = "move"
+        "#).unwrap();
+
+        {
+            let mut controller = test.create_with_config(&config, None, None);
+            test.control(&mut controller);
+            test.wait_processed(1);
+        }
+
+        let torrent = test.server.torrent(1).unwrap();
+        assert!(torrent.is_processed());
+        assert_eq!(torrent.status, TorrentStatus::Seeding);
+        assert_eq!(torrent.download_dir, test.copy_to.path().to_str().unwrap());
+
+        assert!(!test.download_dir.path().join("downloaded").exists());
+        assert_eq!(fs::read_to_string(test.copy_to.path().join("downloaded")).unwrap(), "downloaded");
+    }
+
+    #[test]
+    fn test_copy_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let test = TestController::new();
+        test.server.add_torrent(test.add_torrent(1, "downloaded"));
+
+        let config: ControllerConfig = toml::from_str(&format!(r#"
+            link-mode = "copy"
+
+            [copy-permissions]
+            group = "{}"
+            file-mode = "0640"
+        "#, fs::metadata(test.copy_to.path()).unwrap().gid())).unwrap();
+
+        {
+            let mut controller = test.create_with_config(&config, None, None);
+            test.control(&mut controller);
+            test.wait_processed(1);
+        }
+
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+        assert!(test.server.torrent(1).unwrap().is_processed());
+        assert_eq!(mode(&test.copy_to.path().join("downloaded")), 0o640);
+        assert_ne!(mode(&test.download_dir.path().join("downloaded")), 0o640);
+    }
+
+    #[test]
+    fn test_trash() {
+        let test = TestController::new();
+        let trash_dir = TempDir::new().unwrap();
+
+        let mut torrent = test.add_torrent(1, "seeded").processed();
+        torrent.upload_ratio = 2.5;
+        let hash = torrent.hash.clone();
+        test.server.add_torrent(torrent);
+
+        let config: ControllerConfig = toml::from_str(&format!(r#"
+            [removal]
+            trash-dir = "{}"
+        "#, trash_dir.path().display())).unwrap();
+
+        let mut controller = test.create_with_config(&config, Some(2.0), None);
+        test.control(&mut controller);
+
+        assert!(test.server.torrent(1).is_none());
+        assert!(!test.download_dir.path().join("seeded").exists());
+        assert_eq!(fs::read_to_string(trash_dir.path().join(hash).join("seeded")).unwrap(), "seeded");
+    }
+
+    #[test]
+    fn test_trash_failure() {
+        let test = TestController::new();
+        let temp_dir = TempDir::new().unwrap();
+
+        // Not a directory, so nothing can be moved there
+        let trash_dir = temp_dir.path().join("trash");
+        fs::write(&trash_dir, "").unwrap();
+
+        let mut torrent = test.add_torrent(1, "seeded").processed();
+        torrent.upload_ratio = 2.5;
+        test.server.add_torrent(torrent);
+
+        let config: ControllerConfig = toml::from_str(&format!(r#"
+            [removal]
+            trash-dir = "{}"
+        "#, trash_dir.display())).unwrap();
+
+        let free_space = test.server.state().free_space;
+
+        let mut controller = test.create_with_config(&config, Some(2.0), None);
+        test.control(&mut controller);
+
+        // The data is deleted along with the torrent instead of being left untracked
+        assert!(test.server.torrent(1).is_none());
+        assert_eq!(test.server.state().free_space, free_space + 1024);
+    }
+
+    #[test]
+    fn test_cross_seed_data_preservation() {
+        let test = TestController::new();
+
+        for (id, tracker, ratio) in [(1, "first.example.com", 2.5), (2, "second.example.com", 0.5), (3, "first.example.com", 2.5)] {
+            let name = if id == 3 { "other" } else { "release" };
+            let mut torrent = test.add_torrent(id, name).processed();
+            torrent.trackers = vec![format!("http://{}/announce", tracker)];
+            torrent.upload_ratio = ratio;
+            test.server.add_torrent(torrent);
+        }
+
+        let free_space = test.server.state().free_space;
+
+        let mut controller = test.create(Some(2.0), None);
+        test.control(&mut controller);
+
+        // Torrent 1 is removed without its data since torrent 2 still seeds it
+        assert!(test.server.torrent(1).is_none());
+        assert!(test.server.torrent(2).is_some());
+        assert!(test.server.torrent(3).is_none());
+        assert_eq!(test.server.state().free_space, free_space + 1024);
+    }
+
+    #[test]
+    fn test_manage_only() {
+        let test = TestController::new();
+
+        fs::create_dir(test.download_dir.path().join("managed")).unwrap();
+        let managed_dir = test.download_dir.path().join("managed");
+
+        for (id, label, download_dir) in [
+            (1, "controller", &managed_dir), (2, "other", &managed_dir),
+            (3, "controller", &test.download_dir.path().to_owned()),
+        ] {
+            let mut torrent = test.add_torrent(id, &format!("torrent-{}", id)).processed();
+            torrent.labels = vec![s!(label)];
+            torrent.download_dir = s!(download_dir.to_str().unwrap());
+            torrent.upload_ratio = 2.5;
+            test.server.add_torrent(torrent);
+        }
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [manage-only]
+            labels = ["controller"]
+            download-dirs = ["managed"]
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, Some(2.0), None);
+        test.control(&mut controller);
+
+        assert!(test.server.torrent(1).is_none());
+        assert!(test.server.torrent(2).is_some());
+        assert!(test.server.torrent(3).is_some());
+    }
+
+    #[test]
+    fn test_ignored_torrents() {
+        let test = TestController::new();
+
+        for (id, label) in [(1, "other"), (2, "tc:ignore"), (3, "other")] {
+            let mut torrent = test.add_torrent(id, &format!("torrent-{}", id)).processed();
+            torrent.labels = vec![s!(label)];
+            torrent.upload_ratio = 2.5;
+            test.server.add_torrent(torrent);
+        }
+
+        let config: ControllerConfig = toml::from_str(&format!(r#"
+            [ignore]
+            hashes = ["{:040X}"]
+        "#, 1)).unwrap();
+
+        let mut controller = test.create_with_config(&config, Some(2.0), None);
+        test.control(&mut controller);
+
+        assert!(test.server.torrent(1).is_some());
+        assert!(test.server.torrent(2).is_some());
+        assert!(test.server.torrent(3).is_none());
+    }
+
+    #[test]
+    fn test_keep_label() {
+        let test = TestController::new();
+        let now = ::time::OffsetDateTime::now_utc().unix_timestamp();
+
+        for (id, label) in [(1, "keep"), (2, "other")] {
+            let mut torrent = test.add_torrent(id, &format!("torrent-{}", id)).processed();
+            torrent.labels = vec![s!(label)];
+            torrent.upload_ratio = 2.5;
+            torrent.done_date = now - 100 * 24 * 60 * 60;
+            test.server.add_torrent(torrent);
+        }
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [[retention]]
+            max-age = "30d"
+
+            [removal]
+            grace-period = "7d"
+            keep-label = "keep"
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, Some(2.0), None);
+        test.control(&mut controller);
+
+        let torrent = test.server.torrent(1).unwrap();
+        assert_eq!(torrent.status, TorrentStatus::Seeding);
+        assert!(test.server.torrent(2).is_none());
+    }
+
+    #[test]
+    fn test_process_downloaded() {
+        let test = TestController::new();
+        test.server.add_torrent(test.add_torrent(1, "downloaded"));
+        test.server.add_torrent(test.add_torrent(2, "other"));
+        test.server.add_torrent(test.add_torrent(3, "downloading").downloading());
+
+        {
+            let mut controller = test.create(None, None);
+            test.runtime.block_on(controller.process_downloaded(&format!("{:040x}", 1))).unwrap();
+            test.runtime.block_on(controller.process_downloaded(&format!("{:040x}", 3))).unwrap_err();
+        }
+
+        assert!(test.server.torrent(1).unwrap().is_processed());
+        assert!(!test.server.torrent(2).unwrap().is_processed());
+        assert_eq!(fs::read_to_string(test.copy_to.path().join("downloaded")).unwrap(), "downloaded");
+        assert!(!test.copy_to.path().join("other").exists());
+    }
+
+    #[test]
+    fn test_processing_pause_and_reload() {
+        let test = TestController::new();
+        let mut torrent = test.add_torrent(1, "old").processed();
+        torrent.done_date = ::time::OffsetDateTime::now_utc().unix_timestamp() - 100 * 24 * 60 * 60;
+        test.server.add_torrent(torrent);
+
+        let mut controller = test.create(None, None);
+        controller.pause_processing();
+        assert_eq!(controller.status(), "0 torrents, 0 copying, processing is paused");
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [[retention]]
+            max-age = "30d"
+        "#).unwrap();
+        controller.reload_config(&config);
+
+        test.control(&mut controller);
+        assert!(test.server.torrent(1).is_some());
+
+        controller.resume_processing();
+        test.control(&mut controller);
+        assert!(test.server.torrent(1).is_none());
+    }
+
+    #[test]
+    fn test_maintenance() {
+        let test = TestController::new();
+        let mut torrent = test.add_torrent(1, "old").processed();
+        torrent.done_date = ::time::OffsetDateTime::now_utc().unix_timestamp() - 100 * 24 * 60 * 60;
+        test.server.add_torrent(torrent);
+
+        let lock_file = test.copy_to.path().join(".maintenance");
+        let config: ControllerConfig = toml::from_str(&format!(r#"
+            [[retention]]
+            max-age = "30d"
+
+            [maintenance]
+            lock-file = "{}"
+        "#, lock_file.display())).unwrap();
+
+        let mut controller = test.create_with_config(&config, None, None);
+        fs::write(&lock_file, "").unwrap();
+
+        test.control(&mut controller);
+        assert!(test.server.torrent(1).is_some());
+        assert_eq!(controller.status(), "0 torrents, 0 copying, maintenance is in progress");
+
+        fs::remove_file(&lock_file).unwrap();
+        test.control(&mut controller);
+        assert!(test.server.torrent(1).is_none());
+    }
+
+    #[test]
+    fn test_api() {
+        let test = TestController::new();
+        let hash = |id| format!("{:040x}", id);
+
+        let mut torrent = test.add_torrent(1, "seeded").processed();
+        torrent.upload_ratio = 2.5;
+        test.server.add_torrent(torrent);
+        for (id, name) in [(2, "seeding"), (3, "copied")] {
+            test.server.add_torrent(test.add_torrent(id, name).processed());
+        }
+        test.server.add_torrent(test.add_torrent(4, "downloading").downloading());
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [removal]
+            keep-label = "keep"
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, Some(2.0), None);
+        let mut api = |command| test.runtime.block_on(controller.handle_api_command(&command));
+
+        // Exempt the torrent before the controller removes it
+        api(ApiCommand::Exempt(hash(1))).ok().unwrap();
+        assert_eq!(test.server.torrent(1).unwrap().labels, ["keep"]);
+
+        api(ApiCommand::Copy(hash(3))).ok().unwrap();
+        assert!(matches!(api(ApiCommand::Copy(hash(4))), Err(ApiError::Conflict(_))));
+        assert!(matches!(api(ApiCommand::Remove(hash(5))), Err(ApiError::NotFound(_))));
+
+        test.control(&mut controller);
+        for _ in 0..100 {
+            if test.copy_to.path().join("copied").exists() {
+                break;
+            }
+            thread::sleep(time::Duration::from_millis(50));
+        }
+        assert_eq!(fs::read_to_string(test.copy_to.path().join("copied")).unwrap(), "copied");
+        assert!(!test.copy_to.path().join("seeding").exists());
+
+        let mut api = |command| test.runtime.block_on(controller.handle_api_command(&command));
+        api(ApiCommand::Remove(hash(2))).ok().unwrap();
+        assert!(test.server.torrent(2).is_none());
+
+        test.control(&mut controller);
+        let torrents = test.runtime.block_on(controller.handle_api_command(&ApiCommand::GetTorrents)).ok().unwrap();
+        let states: Vec<_> = torrents.as_array().unwrap().iter().map(|torrent| (
+            torrent["name"].as_str().unwrap(), torrent["kept"].as_bool().unwrap(),
+            torrent["removable"].as_bool().unwrap(), torrent["removal_reason"].as_str().unwrap(),
+        )).collect();
+
+        assert_eq!(states, [
+            ("copied", false, false, "hasn't reached its seeding limits (may be removed on lack of free space)"),
+            ("downloading", false, false, "hasn't been downloaded yet"),
+            ("seeded", true, false, "is kept by the label"),
+        ]);
+
+        let dashboard = test.runtime.block_on(controller.handle_api_command(&ApiCommand::GetDashboard)).ok().unwrap();
+        let downloads = dashboard["downloads"].as_array().unwrap();
+        assert_eq!(downloads.len(), 1);
+        assert_eq!(downloads[0]["name"], "downloading");
+        assert_eq!(dashboard["torrents"], torrents);
+    }
+
+    #[test]
+    fn test_bot_commands() {
+        let test = TestController::new();
+        test.server.add_torrent(test.add_torrent(1, "Some show S01").downloading());
+        test.server.add_torrent(test.add_torrent(2, "Some show S02").downloading());
+        test.server.add_torrent(test.add_torrent(3, "Movie").processed());
+
+        let config: ControllerConfig = toml::from_str(r#"
+            [auto-resume]
+            enabled = true
+            exclude-labels = ["paused"]
+        "#).unwrap();
+
+        let mut controller = test.create_with_config(&config, None, None);
+        test.control(&mut controller);
+
+        let mut bot = |command| test.runtime.block_on(controller.handle_bot_command(&command));
+        assert_eq!(bot(BotCommand::Status).unwrap(), "3 torrents, 0 copying");
+        assert_eq!(bot(BotCommand::List).unwrap(), [
+            "• Movie (seeding, processed)",
+            "• Some show S01 (downloading, 0%)",
+            "• Some show S02 (downloading, 0%)",
+        ].join("\n"));
+
+        assert_eq!(bot(BotCommand::Pause(s!("some show"))).unwrap_err().to_string(),
+                   "'some show' matches several torrents: Some show S01, Some show S02");
+        assert_eq!(bot(BotCommand::Pause(s!("S03"))).unwrap_err().to_string(),
+                   "There is no managed torrent matching 'S03'");
+
+        assert_eq!(bot(BotCommand::Pause(s!("show s02"))).unwrap(), "'Some show S02' torrent has been paused");
+        let torrent = test.server.torrent(2).unwrap();
+        assert_eq!(torrent.status, TorrentStatus::Stopped);
+        assert_eq!(torrent.labels, ["paused"]);
+
+        // Auto resume leaves it paused
+        test.control(&mut controller);
+        assert_eq!(test.server.torrent(2).unwrap().status, TorrentStatus::Stopped);
+    }
+
+    #[test]
+    fn test_status_json() {
+        let test = TestController::new();
+        test.server.add_torrent(test.add_torrent(1, "Downloading").downloading());
+        test.server.add_torrent(test.add_torrent(2, "Processed").processed());
+
+        let mut torrent = test.add_torrent(3, "Stopped").processed();
+        torrent.status = TorrentStatus::Stopped;
+        test.server.add_torrent(torrent);
+
+        let mut controller = test.create_with_config(&ControllerConfig::default(), None, None);
+        test.control(&mut controller);
+
+        let status = controller.get_status_json();
+        assert_eq!(status["status"], "3 torrents, 0 copying");
+        assert_eq!(status["torrents"], 3);
+        assert_eq!(status["downloading"], 1);
+        assert_eq!(status["seeding"], 1);
+        assert_eq!(status["pending"], 0);
+        assert_eq!(status["copying"], 0);
+        assert_eq!(status["processing_paused"], false);
+        assert_eq!(status["copies"], json!([]));
+        assert!(status["free_space"].is_object());
+    }
 }
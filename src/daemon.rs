@@ -0,0 +1,217 @@
+//! Classic UNIX daemonization for the init systems which don't supervise the processes by themselves
+
+use std::env;
+use std::ffi::CString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::common::{EmptyResult, GenericResult};
+
+/// Forks to background and detaches from the terminal. stdin is redirected to /dev/null, stdout and stderr - to the
+/// log file (or /dev/null if it's not specified).
+///
+/// Must be called before any threads are spawned: only the calling thread survives the fork.
+pub fn daemonize(log_file: Option<&Path>) -> EmptyResult {
+    let null_path = Path::new("/dev/null");
+    let stdin = OpenOptions::new().read(true).open(null_path).map_err(|e| format!(
+        "Unable to open '{}': {}", null_path.display(), e))?;
+
+    let output_path = log_file.unwrap_or(null_path);
+    let output = OpenOptions::new().create(true).append(true).open(output_path).map_err(|e| format!(
+        "Unable to open '{}': {}", output_path.display(), e))?;
+
+    fork()?;
+    if unsafe { libc::setsid() } < 0 {
+        return Err!("Unable to create a new session: {}", io::Error::last_os_error());
+    }
+
+    // The second fork guarantees that the daemon is not a session leader, so it never acquires a controlling terminal
+    fork()?;
+
+    let root = CString::new("/").unwrap();
+    if unsafe { libc::chdir(root.as_ptr()) } != 0 {
+        return Err!("Unable to change current directory: {}", io::Error::last_os_error());
+    }
+
+    for (file, fd) in [(&stdin, libc::STDIN_FILENO), (&output, libc::STDOUT_FILENO), (&output, libc::STDERR_FILENO)] {
+        if unsafe { libc::dup2(file.as_raw_fd(), fd) } < 0 {
+            return Err!("Unable to redirect standard I/O: {}", io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Continues execution in the child process, terminating the parent one
+fn fork() -> EmptyResult {
+    match unsafe { libc::fork() } {
+        -1 => Err!("Unable to fork: {}", io::Error::last_os_error()),
+        0 => Ok(()),
+        _ => unsafe { libc::_exit(0) },
+    }
+}
+
+/// PID file which is removed on drop
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Fails if the file exists and the process which has created it is still running. Stale PID files (left after a
+    /// crash, for example) are removed.
+    pub fn check(path: &Path) -> EmptyResult {
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err!("Unable to read '{}': {}", path.display(), e),
+        };
+
+        if let Ok(pid) = data.trim().parse::<libc::pid_t>() {
+            if pid > 0 && pid != process::id() as libc::pid_t && is_running(pid) {
+                return Err!("The daemon is already running with PID {} (see '{}')", pid, path.display());
+            }
+        }
+
+        warn!("Removing stale PID file '{}'.", path.display());
+        fs::remove_file(path).map_err(|e| format!("Unable to remove '{}': {}", path.display(), e))?;
+
+        Ok(())
+    }
+
+    pub fn create(path: &Path) -> GenericResult<PidFile> {
+        PidFile::check(path)?;
+
+        let mut file = OpenOptions::new().write(true).create_new(true).open(path).map_err(|e| format!(
+            "Unable to create '{}': {}", path.display(), e))?;
+
+        let pid_file = PidFile {
+            path: path.to_owned(),
+        };
+
+        file.write_all(format!("{}\n", process::id()).as_bytes()).map_err(|e| format!(
+            "Unable to write '{}': {}", path.display(), e))?;
+
+        Ok(pid_file)
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            error!("Failed to remove '{}': {}.", self.path.display(), e);
+        }
+    }
+}
+
+/// Prevents several controllers from managing the same Transmission instance. The lock is held until the process exits
+/// (it's released by the kernel, so the lock files left after a crash don't matter).
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    pub fn acquire(path: &Path) -> GenericResult<InstanceLock> {
+        InstanceLock::try_acquire(path)?.ok_or_else(|| format!(
+            "Another controller is already managing this Transmission instance (locked '{}')", path.display()).into())
+    }
+
+    /// Returns None if the lock is held by another controller
+    pub fn try_acquire(path: &Path) -> GenericResult<Option<InstanceLock>> {
+        // The file may be created by another user: read access is enough for locking
+        let file = match File::open(path) {
+            Ok(file) => Ok(file),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                OpenOptions::new().write(true).create(true).truncate(false).mode(0o644).open(path)
+            },
+            Err(e) => Err(e),
+        }.map_err(|e| format!("Unable to open '{}' lock file: {}", path.display(), e))?;
+
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() == Some(libc::EWOULDBLOCK) {
+                return Ok(None);
+            }
+            return Err!("Unable to lock '{}': {}", path.display(), error);
+        }
+
+        Ok(Some(InstanceLock {
+            _file: file,
+        }))
+    }
+
+    /// The lock file in the temporary directory named after the RPC URL
+    pub fn default_path(rpc_url: &str) -> PathBuf {
+        let name: String = rpc_url.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+        env::temp_dir().join(format!("transmission-controller-{}.lock", name))
+    }
+}
+
+fn is_running(pid: libc::pid_t) -> bool {
+    // The process exists, but belongs to another user if we get EPERM
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Converts the path to absolute one, so it remains valid after the current directory change
+pub fn absolute_path(path: &Path) -> GenericResult<PathBuf> {
+    Ok(std::path::absolute(path).map_err(|e| format!("Invalid path '{}': {}", path.display(), e))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_pid_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("controller.pid");
+
+        {
+            let _pid_file = PidFile::create(&path).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}\n", process::id()));
+        }
+        assert!(!path.exists());
+
+        // A running process
+        let mut child = Command::new("sleep").arg("60").spawn().unwrap();
+        fs::write(&path, format!("{}\n", child.id())).unwrap();
+        assert!(PidFile::create(&path).is_err());
+        assert!(path.exists());
+
+        // A stale PID file
+        child.kill().unwrap();
+        child.wait().unwrap();
+        let _pid_file = PidFile::create(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}\n", process::id()));
+    }
+
+    #[test]
+    fn test_instance_lock() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("controller.lock");
+
+        {
+            let _lock = InstanceLock::acquire(&path).unwrap();
+            assert!(InstanceLock::acquire(&path).unwrap_err().to_string().starts_with(
+                "Another controller is already managing this Transmission instance"));
+            assert!(InstanceLock::try_acquire(&path).unwrap().is_none());
+        }
+
+        // The file is left, but it's unlocked
+        assert!(path.exists());
+        let _lock = InstanceLock::acquire(&path).unwrap();
+
+        assert_eq!(
+            InstanceLock::default_path("http://localhost:9091/transmission/rpc").file_name().unwrap(),
+            "transmission-controller-http___localhost_9091_transmission_rpc.lock");
+    }
+}
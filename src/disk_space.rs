@@ -0,0 +1,91 @@
+//! Free space information for the directories the controller works with.
+//!
+//! The download directory is asked from Transmission via `free-space` RPC (so it works even if Transmission runs in
+//! a container or on another host), destination directories are always local, so they are checked via statvfs(2).
+
+use std::fmt;
+use std::path::Path;
+
+use crate::common::GenericResult;
+use crate::util;
+use crate::torrent_client::TorrentClient;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DiskSpace {
+    pub free: u64,
+    pub total: Option<u64>,
+}
+
+impl DiskSpace {
+    pub fn free_percent(&self) -> Option<u8> {
+        let total = self.total?;
+        if total == 0 {
+            return None;
+        }
+        Some((self.free.min(total) as u128 * 100 / total as u128) as u8)
+    }
+}
+
+impl fmt::Display for DiskSpace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} free", format_size(self.free))?;
+        if let (Some(total), Some(percent)) = (self.total, self.free_percent()) {
+            write!(f, " of {} ({}%)", format_size(total), percent)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns free space of Transmission's download directory.
+///
+/// Transmission reports total space only since 4.0, so for older versions we fall back to the local file system
+/// statistics if the directory is accessible to us.
+pub async fn get_download_dir_space(client: &dyn TorrentClient, path: &Path) -> GenericResult<DiskSpace> {
+    let path_str = path.to_str().ok_or_else(|| format!("Invalid path: '{}'", path.display()))?;
+
+    let space = client.get_free_space(path_str).await.map_err(|e| format!(
+        "Unable to get free space of '{}': {}", path.display(), e))?;
+
+    let total = match space.total {
+        Some(total) => Some(total),
+        None => match util::fs::get_file_system_space(path) {
+            Ok((_, total)) => Some(total),
+            Err(err) => {
+                debug!("{}.", err);
+                None
+            },
+        },
+    };
+
+    Ok(DiskSpace {
+        free: space.free,
+        total: total,
+    })
+}
+
+/// Returns free space of a local directory
+pub fn get_local_space(path: &Path) -> GenericResult<DiskSpace> {
+    let (free, total) = util::fs::get_file_system_space(path)?;
+    Ok(DiskSpace {
+        free: free,
+        total: Some(total),
+    })
+}
+
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut unit = 0;
+    let mut value = size as f64;
+
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", size, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,59 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+
+use crate::transmissionrpc::{Torrent, TorrentStatus};
+
+/// Limits the number of simultaneously downloading torrents independently of Transmission's own queue: the torrents
+/// with the lowest priority (and the newest ones among them) are paused until the others complete.
+pub struct DownloadQueue {
+    max_active: usize,
+    queued: HashSet<String>,
+}
+
+impl DownloadQueue {
+    pub fn new(max_active: usize) -> DownloadQueue {
+        DownloadQueue {
+            max_active: max_active,
+            queued: HashSet::new(),
+        }
+    }
+
+    /// Returns true if the torrent has been paused by the queue
+    pub fn is_queued(&self, hash: &str) -> bool {
+        self.queued.contains(hash)
+    }
+
+    /// Returns the torrents which must be stopped and started to satisfy the limit. The stopped torrents are considered
+    /// queued until they are started.
+    pub fn check<'a>(&mut self, torrents: &'a [Torrent]) -> (Vec<&'a Torrent>, Vec<&'a Torrent>) {
+        let mut candidates: Vec<&Torrent> = torrents.iter().filter(|torrent| {
+            !torrent.done && (
+                matches!(torrent.status, TorrentStatus::Downloading | TorrentStatus::DownloadWait) ||
+                torrent.status == TorrentStatus::Stopped && self.queued.contains(&torrent.hash)
+            )
+        }).collect();
+
+        self.queued.retain(|hash| candidates.iter().any(|torrent| &torrent.hash == hash));
+        candidates.sort_by_key(|torrent| (Reverse(torrent.priority), torrent.added_time, torrent.id));
+
+        let (mut to_stop, mut to_start) = (Vec::new(), Vec::new());
+
+        for (index, torrent) in candidates.into_iter().enumerate() {
+            let stopped = torrent.status == TorrentStatus::Stopped;
+
+            if index < self.max_active {
+                if stopped {
+                    to_start.push(torrent);
+                }
+                self.queued.remove(&torrent.hash);
+            } else {
+                if !stopped {
+                    to_stop.push(torrent);
+                }
+                self.queued.insert(torrent.hash.clone());
+            }
+        }
+
+        (to_stop, to_start)
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::common::{EmptyResult, GenericResult};
+use crate::transmissionrpc::Torrent;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct DuplicatesConfig {
+    /// Action to take on the duplicates (detection is disabled if not specified)
+    pub action: Option<DuplicateAction>,
+    /// File to store hashes of the processed torrents in to detect the torrents which are added again after their
+    /// removal
+    pub history_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicateAction {
+    /// Send a notification, but process the torrent as usual
+    Notify,
+    /// Don't consume the torrent
+    Skip,
+    /// Remove the torrent
+    Remove,
+}
+
+pub struct Duplicate<'a> {
+    pub torrent: &'a Torrent,
+    pub reason: String,
+    /// Whether the torrent shares its files with the original one
+    pub shares_files: bool,
+}
+
+/// Detects the torrents which duplicate the already existing (or already processed) ones: a duplicate is the newer of
+/// the torrents with the same name or with overlapping files.
+pub struct Duplicates {
+    action: DuplicateAction,
+    history_file: Option<PathBuf>,
+    history: HashSet<String>,
+    checked: HashSet<String>,
+    skipped: HashSet<String>,
+}
+
+impl Duplicates {
+    pub fn new(action: DuplicateAction, history_file: Option<PathBuf>) -> Duplicates {
+        let history = match history_file {
+            Some(ref path) => load_history(path).unwrap_or_else(|e| {
+                error!("Failed to load processed torrents history from {:?}: {}.", path, e);
+                HashSet::new()
+            }),
+            None => HashSet::new(),
+        };
+
+        Duplicates {
+            action: action,
+            history_file: history_file,
+            history: history,
+            checked: HashSet::new(),
+            skipped: HashSet::new(),
+        }
+    }
+
+    pub fn action(&self) -> DuplicateAction {
+        self.action
+    }
+
+    /// Returns true if the duplicate must not be consumed
+    pub fn is_skipped(&self, hash: &str) -> bool {
+        self.skipped.contains(hash)
+    }
+
+    /// Forgets the removed torrents and returns true if there are new torrents to check
+    pub fn needs_check(&mut self, torrents: &[Torrent]) -> bool {
+        let hashes: HashSet<&str> = torrents.iter().map(|torrent| torrent.hash.as_str()).collect();
+        self.checked.retain(|hash| hashes.contains(hash.as_str()));
+        self.skipped.retain(|hash| hashes.contains(hash.as_str()));
+        hashes.iter().any(|&hash| !self.checked.contains(hash))
+    }
+
+    /// Checks the new torrents against all the others. The torrents must be requested with their files.
+    pub fn check<'a>(&mut self, torrents: &'a [Torrent]) -> Vec<Duplicate<'a>> {
+        let mut duplicates = Vec::new();
+
+        for torrent in torrents {
+            if !self.checked.insert(torrent.hash.clone()) || torrent.processed {
+                continue;
+            }
+
+            let duplicate = match self.find_original(torrent, torrents) {
+                Some(duplicate) => duplicate,
+                None => continue,
+            };
+
+            if self.action == DuplicateAction::Skip {
+                self.skipped.insert(torrent.hash.clone());
+            }
+            duplicates.push(duplicate);
+        }
+
+        duplicates
+    }
+
+    /// Adds the processed torrents to the history
+    pub fn record_processed(&mut self, torrents: &[Torrent]) -> EmptyResult {
+        let path = match self.history_file {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+
+        let mut new = String::new();
+
+        for torrent in torrents {
+            if torrent.processed && !self.history.contains(&torrent.hash) {
+                new += &torrent.hash;
+                new.push('\n');
+            }
+        }
+
+        if new.is_empty() {
+            return Ok(());
+        }
+
+        OpenOptions::new().create(true).append(true).open(path)
+            .and_then(|mut file| file.write_all(new.as_bytes()))
+            .map_err(|e| format!("Unable to write {:?}: {}", path, e))?;
+
+        self.history.extend(new.lines().map(String::from));
+        Ok(())
+    }
+
+    fn find_original<'a>(&self, torrent: &'a Torrent, torrents: &[Torrent]) -> Option<Duplicate<'a>> {
+        let files = get_files(torrent);
+        let mut reason = None;
+
+        // Older torrents are considered as originals
+        for other in torrents {
+            if (other.added_time, other.id) >= (torrent.added_time, torrent.id) {
+                continue;
+            }
+
+            if get_files(other).iter().any(|path| files.contains(path)) {
+                return Some(Duplicate {
+                    torrent: torrent,
+                    reason: format!("shares files with '{}'", other.name),
+                    shares_files: true,
+                });
+            }
+
+            if reason.is_none() && other.name == torrent.name {
+                reason = Some(format!("has the same name as '{}'", other.name));
+            }
+        }
+
+        if reason.is_none() && self.history.contains(&torrent.hash) {
+            reason = Some(s!("has been already processed before"));
+        }
+
+        reason.map(|reason| Duplicate {
+            torrent: torrent,
+            reason: reason,
+            shares_files: false,
+        })
+    }
+}
+
+/// Returns another torrent which references the same files (with the same paths and sizes) as the specified one: a
+/// cross-seed of the same release from another tracker, for example
+pub fn find_shared_files_owner<'a>(torrent: &Torrent, torrents: &'a [Torrent]) -> Option<&'a Torrent> {
+    let get_files = |torrent: &Torrent| -> HashSet<(PathBuf, u64)> {
+        let download_dir = Path::new(&torrent.download_dir);
+        torrent.files.iter().flatten().map(|file| (download_dir.join(&file.name), file.size)).collect()
+    };
+
+    let files = get_files(torrent);
+    torrents.iter()
+        .filter(|other| other.hash != torrent.hash)
+        .find(|other| get_files(other).iter().any(|file| files.contains(file)))
+}
+
+fn get_files(torrent: &Torrent) -> HashSet<PathBuf> {
+    let download_dir = Path::new(&torrent.download_dir);
+    torrent.files.iter().flatten()
+        .filter(|file| file.selected)
+        .map(|file| download_dir.join(&file.name))
+        .collect()
+}
+
+fn load_history(path: &Path) -> GenericResult<HashSet<String>> {
+    match fs::read_to_string(path) {
+        Ok(data) => Ok(data.lines().map(str::trim).filter(|hash| !hash.is_empty()).map(String::from).collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(e) => Err(e.into()),
+    }
+}
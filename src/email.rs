@@ -76,11 +76,6 @@ impl EmailTemplate {
         Ok(EmailTemplate::new(subject, &body))
     }
 
-    pub fn send(&self, mailer: &Mailer, params: &HashMap<&str, String>) -> EmptyResult {
-        let (subject, body) = self.render(params)?;
-        mailer.send(&subject, &body)
-    }
-
     pub fn render(&self, params: &HashMap<&str, String>) -> GenericResult<(String, String)> {
         Ok((
             render_template(&self.subject, params)?,
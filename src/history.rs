@@ -0,0 +1,276 @@
+//! Long-term history of the managed torrents (SQLite database): when they have been added, completed, copied and
+//! removed. Unlike the state database, the records are kept after the torrents' removal, so it allows to gather
+//! statistics for any period.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use rusqlite::{Connection, OpenFlags, params};
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::common::{EmptyResult, GenericResult};
+use crate::transmissionrpc::Torrent;
+use crate::util::time::Timestamp;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS torrents (
+        hash TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        tracker TEXT,
+        added_time INTEGER NOT NULL,
+        completed_time INTEGER,
+        copied_time INTEGER,
+        copied_to TEXT,
+        removed_time INTEGER,
+        ratio REAL
+    );
+    CREATE INDEX IF NOT EXISTS torrents_completed_time ON torrents (completed_time);
+";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryRecord {
+    pub hash: String,
+    pub name: String,
+    pub size: u64,
+    /// Host of the first tracker
+    pub tracker: Option<String>,
+    pub added_time: Timestamp,
+    pub completed_time: Option<Timestamp>,
+    pub copied_time: Option<Timestamp>,
+    pub copied_to: Option<PathBuf>,
+    pub removed_time: Option<Timestamp>,
+    /// Upload ratio as of the last check (or the torrent's removal)
+    pub ratio: Option<f64>,
+}
+
+/// The history is disabled if path is not specified. In dry run mode it's only read.
+pub struct History {
+    connection: Option<Mutex<Connection>>,
+    dry_run: bool,
+}
+
+impl History {
+    pub fn new(path: Option<&Path>, dry_run: bool) -> History {
+        let connection = path.and_then(|path| {
+            open(path, dry_run).map_err(|e| {
+                error!("Failed to open history database '{}': {}.", path.display(), e);
+            }).ok()
+        });
+
+        History {
+            connection: connection.map(Mutex::new),
+            dry_run: dry_run,
+        }
+    }
+
+    /// Opens the existing database for the queries only
+    pub fn open(path: &Path) -> GenericResult<History> {
+        let connection = open(path, true).map_err(|e| format!(
+            "Unable to open history database '{}': {}", path.display(), e))?;
+
+        Ok(History {
+            connection: Some(Mutex::new(connection)),
+            dry_run: true,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    /// Updates the torrents' records (their state, size and ratio)
+    pub fn record_torrents(&self, torrents: &[&Torrent]) {
+        self.update("torrents", |connection| {
+            let transaction = connection.transaction()?;
+            for torrent in torrents {
+                upsert(&transaction, torrent)?;
+            }
+            Ok(transaction.commit()?)
+        });
+    }
+
+    pub fn record_copied(&self, torrent: &Torrent, copied_to: &Path) {
+        self.update("copied torrent", |connection| {
+            upsert(connection, torrent)?;
+            connection.execute("UPDATE torrents SET copied_time = ?2, copied_to = ?3 WHERE hash = ?1", params![
+                torrent.hash, now(), copied_to.to_string_lossy(),
+            ])?;
+            Ok(())
+        });
+    }
+
+    pub fn record_removed(&self, torrent: &Torrent) {
+        self.update("removed torrent", |connection| {
+            upsert(connection, torrent)?;
+            connection.execute("UPDATE torrents SET removed_time = ?2 WHERE hash = ?1", params![torrent.hash, now()])?;
+            Ok(())
+        });
+    }
+
+    /// Marks the torrents which have been removed outside of the controller
+    pub fn retain(&self, torrents: &[Torrent]) {
+        self.update("removed torrents", |connection| {
+            let transaction = connection.transaction()?;
+            transaction.execute("CREATE TEMPORARY TABLE IF NOT EXISTS existing (hash TEXT PRIMARY KEY)", [])?;
+            transaction.execute("DELETE FROM existing", [])?;
+
+            for torrent in torrents {
+                transaction.execute("INSERT OR IGNORE INTO existing (hash) VALUES (?1)", [&torrent.hash])?;
+            }
+
+            transaction.execute(
+                "UPDATE torrents SET removed_time = ?1
+                 WHERE removed_time IS NULL AND hash NOT IN (SELECT hash FROM existing)", [now()])?;
+
+            Ok(transaction.commit()?)
+        });
+    }
+
+    #[cfg(test)]
+    pub fn get(&self, hash: &str) -> GenericResult<Option<HistoryRecord>> {
+        use rusqlite::OptionalExtension;
+
+        let Some(connection) = self.connection.as_ref() else {
+            return Ok(None);
+        };
+
+        let connection = connection.lock().unwrap();
+        Ok(connection.query_row(
+            &format!("SELECT {} FROM torrents WHERE hash = ?1", COLUMNS), [hash], read_record,
+        ).optional()?)
+    }
+
+    /// Returns the torrents which have completed downloading in [from, to) period ordered by completion time
+    pub fn get_completed(&self, from: Timestamp, to: Timestamp) -> GenericResult<Vec<HistoryRecord>> {
+        let Some(connection) = self.connection.as_ref() else {
+            return Ok(Vec::new());
+        };
+
+        let connection = connection.lock().unwrap();
+        let mut statement = connection.prepare(&format!(
+            "SELECT {} FROM torrents WHERE completed_time >= ?1 AND completed_time < ?2 ORDER BY completed_time, name",
+            COLUMNS))?;
+
+        let records = statement.query_map([from, to], read_record)?.collect::<Result<_, _>>()?;
+        Ok(records)
+    }
+
+    fn update<F: FnOnce(&mut Connection) -> EmptyResult>(&self, name: &str, update: F) {
+        let connection = match self.connection {
+            Some(ref connection) if !self.dry_run => connection,
+            _ => return,
+        };
+
+        if let Err(e) = update(&mut connection.lock().unwrap()) {
+            error!("Failed to record {} to the history database: {}.", name, e);
+        }
+    }
+}
+
+const COLUMNS: &str =
+    "hash, name, size, tracker, added_time, completed_time, copied_time, copied_to, removed_time, ratio";
+
+fn open(path: &Path, read_only: bool) -> GenericResult<Connection> {
+    if read_only {
+        return Ok(Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?);
+    }
+
+    let connection = Connection::open(path)?;
+    connection.execute_batch(SCHEMA)?;
+    Ok(connection)
+}
+
+fn upsert(connection: &Connection, torrent: &Torrent) -> EmptyResult {
+    // The completion time is kept when the torrent's data is rechecked, and the record is revived if the torrent has
+    // been added again
+    connection.execute(
+        "INSERT INTO torrents (hash, name, size, tracker, added_time, completed_time, ratio)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT (hash) DO UPDATE SET
+            name = excluded.name, size = excluded.size, tracker = excluded.tracker,
+            completed_time = coalesce(completed_time, excluded.completed_time),
+            ratio = coalesce(excluded.ratio, ratio), removed_time = NULL", params![
+            torrent.hash, torrent.name, torrent.size, torrent.tracker_hosts().into_iter().next(), torrent.added_time,
+            torrent.done_time, torrent.upload_ratio,
+        ])?;
+    Ok(())
+}
+
+fn read_record(row: &rusqlite::Row) -> rusqlite::Result<HistoryRecord> {
+    Ok(HistoryRecord {
+        hash: row.get(0)?,
+        name: row.get(1)?,
+        size: row.get(2)?,
+        tracker: row.get(3)?,
+        added_time: row.get(4)?,
+        completed_time: row.get(5)?,
+        copied_time: row.get(6)?,
+        copied_to: row.get::<_, Option<String>>(7)?.map(PathBuf::from),
+        removed_time: row.get(8)?,
+        ratio: row.get(9)?,
+    })
+}
+
+fn now() -> Timestamp {
+    OffsetDateTime::now_utc().unix_timestamp()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::TorrentFields;
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+        let client = server.client();
+        let get_torrents = || runtime.block_on(client.get_torrents(TorrentFields::basic())).unwrap();
+
+        let mut torrent = MockTorrent::new(1, "completed", "/downloads");
+        torrent.trackers = vec![s!("http://tracker.example.com/announce")];
+        torrent.done_date = 2_000_000;
+        torrent.upload_ratio = 1.5;
+        server.add_torrent(torrent);
+
+        server.add_torrent(MockTorrent::new(2, "downloading", "/downloads").downloading());
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("history.sqlite");
+        let history = History::new(Some(&path), false);
+
+        let torrents = get_torrents();
+        history.record_torrents(&torrents.iter().collect::<Vec<_>>());
+        history.record_copied(&torrents[0], Path::new("/copies"));
+
+        server.state().torrents.remove(1);
+        history.retain(&get_torrents());
+
+        let record = history.get(&torrents[0].hash).unwrap().unwrap();
+        assert_eq!(record.tracker.as_deref(), Some("tracker.example.com"));
+        assert_eq!(record.completed_time, Some(2_000_000));
+        assert_eq!(record.copied_to.as_deref(), Some(Path::new("/copies")));
+        assert!(record.copied_time.is_some());
+        assert_eq!(record.removed_time, None);
+        assert_eq!(record.ratio, Some(1.5));
+
+        let record = history.get(&torrents[1].hash).unwrap().unwrap();
+        assert_eq!(record.completed_time, None);
+        assert!(record.removed_time.is_some());
+
+        let history = History::open(&path).unwrap();
+        assert_eq!(history.get_completed(1_000_000, 2_000_001).unwrap().len(), 1);
+        assert!(history.get_completed(1_000_000, 2_000_000).unwrap().is_empty());
+
+        // The read-only history isn't modified
+        history.record_removed(&torrents[0]);
+        assert_eq!(history.get(&torrents[0].hash).unwrap().unwrap().removed_time, None);
+    }
+}
@@ -0,0 +1,321 @@
+//! External scripts which are executed on torrent lifecycle events.
+//!
+//! The script gets the torrent hash as its last argument and the rest of torrent metadata via `TC_*` environment
+//! variables.
+
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::common::EmptyResult;
+use crate::config;
+use crate::notifications::{Event, Notifier};
+use crate::transmissionrpc::Torrent;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct HooksConfig {
+    /// Maximum execution time after which the script is killed
+    #[serde(deserialize_with = "config::deserialize_duration")]
+    pub timeout: Duration,
+
+    /// Commands (the program and its arguments) to execute on the events
+    pub on_torrent_finished: Option<Vec<String>>,
+    pub on_copied: Option<Vec<String>>,
+    pub on_removed: Option<Vec<String>>,
+    pub on_error: Option<Vec<String>>,
+}
+
+impl Default for HooksConfig {
+    fn default() -> HooksConfig {
+        HooksConfig {
+            timeout: Duration::from_secs(5 * 60),
+            on_torrent_finished: None,
+            on_copied: None,
+            on_removed: None,
+            on_error: None,
+        }
+    }
+}
+
+impl HooksConfig {
+    pub fn commands(&self) -> [(HookEvent, Option<&Vec<String>>); 4] {
+        [
+            (HookEvent::TorrentFinished, self.on_torrent_finished.as_ref()),
+            (HookEvent::Copied, self.on_copied.as_ref()),
+            (HookEvent::Removed, self.on_removed.as_ref()),
+            (HookEvent::Error, self.on_error.as_ref()),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HookEvent {
+    /// Torrent has been downloaded
+    TorrentFinished,
+    /// Torrent has been consumed (copied to the destination directory)
+    Copied,
+    Removed,
+    /// Torrent has failed to be consumed
+    Error,
+}
+
+impl HookEvent {
+    pub fn name(self) -> &'static str {
+        match self {
+            HookEvent::TorrentFinished => "torrent-finished",
+            HookEvent::Copied => "copied",
+            HookEvent::Removed => "removed",
+            HookEvent::Error => "error",
+        }
+    }
+}
+
+/// Torrent metadata which is passed to the hook
+#[derive(Debug, Clone)]
+pub struct HookParams {
+    hash: String,
+    name: Option<String>,
+    download_dir: Option<String>,
+    size: Option<u64>,
+    destination: Option<PathBuf>,
+    error: Option<String>,
+}
+
+impl HookParams {
+    pub fn new(hash: &str) -> HookParams {
+        HookParams {
+            hash: s!(hash),
+            name: None,
+            download_dir: None,
+            size: None,
+            destination: None,
+            error: None,
+        }
+    }
+
+    pub fn from_torrent(torrent: &Torrent) -> HookParams {
+        HookParams {
+            name: Some(torrent.name.clone()),
+            download_dir: Some(torrent.download_dir.clone()),
+            size: Some(torrent.size),
+            ..HookParams::new(&torrent.hash)
+        }
+    }
+
+    pub fn destination(mut self, path: &Path) -> HookParams {
+        self.destination = Some(path.to_owned());
+        self
+    }
+
+    pub fn error(mut self, error: &str) -> HookParams {
+        self.error = Some(s!(error));
+        self
+    }
+
+    fn env(&self, event: HookEvent) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("TC_EVENT", s!(event.name())),
+            ("TC_TORRENT_HASH", self.hash.clone()),
+        ];
+
+        if let Some(ref name) = self.name {
+            env.push(("TC_TORRENT_NAME", name.clone()));
+        }
+        if let Some(ref download_dir) = self.download_dir {
+            env.push(("TC_TORRENT_DIR", download_dir.clone()));
+        }
+        if let Some(size) = self.size {
+            env.push(("TC_TORRENT_SIZE", size.to_string()));
+        }
+        if let Some(ref destination) = self.destination {
+            env.push(("TC_DESTINATION", destination.to_string_lossy().to_string()));
+        }
+        if let Some(ref error) = self.error {
+            env.push(("TC_ERROR", error.clone()));
+        }
+
+        env
+    }
+}
+
+pub struct Hooks {
+    config: HooksConfig,
+    notifier: Arc<Notifier>,
+    dry_run: bool,
+}
+
+impl Hooks {
+    pub fn new(config: HooksConfig, notifier: Arc<Notifier>) -> Hooks {
+        Hooks { config: config, notifier: notifier, dry_run: false }
+    }
+
+    /// In dry run mode the hooks are only logged
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// Executes the hook (if it's configured) waiting for its completion. Failures are logged and notified about.
+    pub fn run(&self, event: HookEvent, params: &HookParams) {
+        let command = match self.command(event) {
+            Some(command) => command,
+            None => return,
+        };
+
+        if self.dry_run {
+            info!("Dry run: would execute {} hook for {} torrent.", event.name(), params.hash);
+            return;
+        }
+
+        debug!("Executing {} hook for {} torrent...", event.name(), params.hash);
+
+        if let Err(e) = execute(command, &params.env(event), &params.hash, self.config.timeout) {
+            let name = params.name.as_deref().unwrap_or(&params.hash);
+            error!("{} hook for {} torrent has failed: {}.", event.name(), params.hash, e);
+            self.notifier.notify(Event::Problem, &format!("{} hook has failed", event.name()), &format!(
+                "{} hook for '{}' torrent has failed: {}.", event.name(), name, e));
+        }
+    }
+
+    /// Executes the hook in a background thread to not block the asynchronous code.
+    pub fn run_in_background(self: &Arc<Self>, event: HookEvent, params: HookParams) {
+        if self.command(event).is_some() {
+            let hooks = self.clone();
+            tokio::task::spawn_blocking(move || hooks.run(event, &params));
+        }
+    }
+
+    fn command(&self, event: HookEvent) -> Option<&Vec<String>> {
+        self.config.commands().into_iter().find(|&(hook_event, _)| hook_event == event).and_then(|(_, command)| command)
+    }
+}
+
+fn execute(command: &[String], env: &[(&str, String)], hash: &str, timeout: Duration) -> EmptyResult {
+    let mut child = Command::new(&command[0])
+        .args(&command[1..]).arg(hash)
+        .envs(env.iter().map(|(name, value)| (name, value)))
+        .stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped())
+        // The script's own children (which may hold the output pipes) are killed along with it on timeout
+        .process_group(0)
+        .spawn().map_err(|e| format!("Failed to execute {:?}: {}", command[0], e))?;
+
+    // Read the output in separate threads to not block the child on a full pipe
+    let readers: Vec<JoinHandle<Vec<u8>>> = [
+        child.stdout.take().map(|stdout| Box::new(stdout) as Box<dyn Read + Send>),
+        child.stderr.take().map(|stderr| Box::new(stderr) as Box<dyn Read + Send>),
+    ].into_iter().flatten().map(|mut pipe| thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = pipe.read_to_end(&mut output);
+        output
+    })).collect();
+
+    let start_time = Instant::now();
+    let mut status = None;
+
+    let timed_out = loop {
+        if status.is_none() {
+            status = child.try_wait()?;
+        }
+
+        // The script's background processes may still write to the pipes after its exit
+        if status.is_some() && readers.iter().all(JoinHandle::is_finished) {
+            break false;
+        }
+
+        if start_time.elapsed() >= timeout {
+            unsafe { libc::killpg(child.id() as libc::pid_t, libc::SIGKILL) };
+            if status.is_none() {
+                child.wait()?;
+            }
+            break true;
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    };
+
+    let mut output = String::new();
+    for reader in readers {
+        output.push_str(&String::from_utf8_lossy(&reader.join().unwrap()));
+    }
+    let output = output.trim();
+
+    if !output.is_empty() {
+        info!("{} output:\n{}", command[0], output);
+    }
+
+    match status {
+        _ if timed_out => Err!("{:?} has been killed due to timeout ({} seconds)", command[0], timeout.as_secs()),
+        Some(status) if status.success() => Ok(()),
+        Some(status) if output.is_empty() => Err!("{:?} has exited with {}", command[0], status),
+        Some(status) => Err!("{:?} has exited with {}: {}", command[0], status, output),
+        None => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("output");
+
+        let command = vec![s!("sh"), s!("-c"), format!(
+            r#"echo "$TC_EVENT $TC_TORRENT_NAME $1" > {}"#, output_path.display()), s!("sh")];
+        let params = HookParams {
+            name: Some(s!("name")),
+            ..HookParams::new("hash")
+        };
+
+        execute(&command, &params.env(HookEvent::Copied), &params.hash, Duration::from_secs(10)).unwrap();
+        assert_eq!(fs::read_to_string(&output_path).unwrap(), "copied name hash\n");
+    }
+
+    #[test]
+    fn test_execute_failure() {
+        let command = vec![s!("sh"), s!("-c"), s!("echo some error >&2; exit 1")];
+        assert_eq!(
+            execute(&command, &[], "hash", Duration::from_secs(10)).unwrap_err().to_string(),
+            r#""sh" has exited with exit status: 1: some error"#);
+    }
+
+    #[test]
+    fn test_execute_timeout() {
+        // The child of the script holds the output pipes after the script is killed
+        for script in ["exec sleep 10", "sleep 10; true", "sleep 10 & echo started"] {
+            let command = vec![s!("sh"), s!("-c"), s!(script)];
+            let start_time = Instant::now();
+
+            assert!(execute(&command, &[], "hash", Duration::from_millis(100)).unwrap_err().to_string()
+                .contains("timeout"), "{}", script);
+            assert!(start_time.elapsed() < Duration::from_secs(5), "{}", script);
+        }
+    }
+
+    #[test]
+    fn test_failure_notification() {
+        let notifier = Arc::new(Notifier::new(None));
+        let hooks = Hooks::new(HooksConfig {
+            on_error: Some(vec![s!("false")]),
+            ..Default::default()
+        }, notifier.clone());
+
+        hooks.run(HookEvent::Copied, &HookParams::new("hash"));
+        assert!(notifier.get_history().is_empty());
+
+        hooks.run(HookEvent::Error, &HookParams::new("hash"));
+        let history: Vec<String> = notifier.get_history().into_iter().map(|(_, subject)| subject).collect();
+        assert_eq!(history, vec!["error hook has failed"]);
+    }
+}
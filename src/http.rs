@@ -0,0 +1,509 @@
+//! Optional HTTP listener serving Prometheus metrics, the health check, the controller API and the web dashboard
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serde_json::json;
+use time::OffsetDateTime;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::common::{EmptyResult, GenericResult};
+use crate::config;
+use crate::metrics::METRICS;
+use crate::util::time::Timestamp;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct HttpConfig {
+    /// Address to listen on (the listener is disabled if not specified)
+    pub listen_address: Option<SocketAddr>,
+    /// /healthz reports failure when the controller hasn't been able to complete its checks for this time
+    #[serde(deserialize_with = "config::deserialize_duration")]
+    pub unhealthy_after: Duration,
+    /// Enables the API (/api/*) which requires the requests to be authorized with this bearer token
+    pub api_token: Option<String>,
+    /// Enables the read-only web dashboard (it doesn't require the API token)
+    pub dashboard: bool,
+}
+
+impl Default for HttpConfig {
+    fn default() -> HttpConfig {
+        HttpConfig {
+            listen_address: None,
+            unhealthy_after: Duration::from_secs(5 * 60),
+            api_token: None,
+            dashboard: false,
+        }
+    }
+}
+
+pub enum ApiCommand {
+    GetTorrents,
+    GetDashboard,
+    Process,
+    Copy(String),
+    Exempt(String),
+    Remove(String),
+}
+
+pub enum ApiError {
+    NotFound(String),
+    Conflict(String),
+    Failed(String),
+}
+
+pub type ApiResult = Result<serde_json::Value, ApiError>;
+
+/// The API requests are executed by the daemon's main loop, so they never race with the controller's checks
+pub struct ApiRequest {
+    pub command: ApiCommand,
+    reply: oneshot::Sender<ApiResult>,
+}
+
+impl ApiRequest {
+    pub fn reply(self, result: ApiResult) {
+        let _ = self.reply.send(result);
+    }
+}
+
+pub struct HttpServer {
+    state: Arc<HttpState>,
+    api_requests: Option<mpsc::Receiver<ApiRequest>>,
+}
+
+impl HttpServer {
+    pub fn on_cycle<E: ToString>(&self, result: Result<(), E>) {
+        self.state.on_cycle(result)
+    }
+
+    /// Waits for the next API request (never completes if the API is disabled)
+    pub async fn api_request(&mut self) -> ApiRequest {
+        if let Some(ref mut api_requests) = self.api_requests {
+            if let Some(request) = api_requests.recv().await {
+                return request;
+            }
+        }
+        std::future::pending().await
+    }
+}
+
+struct HttpState {
+    unhealthy_after: Duration,
+    health: Mutex<Health>,
+    api_token: Option<String>,
+    dashboard: bool,
+    api: Option<mpsc::Sender<ApiRequest>>,
+}
+
+struct Health {
+    last_cycle_time: Instant,
+    last_success_time: Option<Timestamp>,
+    /// Start of the current series of failures (or daemon's start time if there have been no successful checks yet)
+    failing_since: Option<Instant>,
+    last_error: Option<String>,
+}
+
+impl HttpState {
+    fn new(config: &HttpConfig) -> (HttpState, Option<mpsc::Receiver<ApiRequest>>) {
+        let (api, api_requests) = if config.api_token.is_some() || config.dashboard {
+            let (sender, receiver) = mpsc::channel(API_QUEUE_SIZE);
+            (Some(sender), Some(receiver))
+        } else {
+            (None, None)
+        };
+
+        let now = Instant::now();
+        let state = HttpState {
+            unhealthy_after: config.unhealthy_after,
+            health: Mutex::new(Health {
+                last_cycle_time: now,
+                last_success_time: None,
+                failing_since: Some(now),
+                last_error: None,
+            }),
+            api_token: config.api_token.clone(),
+            dashboard: config.dashboard,
+            api: api,
+        };
+
+        (state, api_requests)
+    }
+
+    fn on_cycle<E: ToString>(&self, result: Result<(), E>) {
+        let mut health = self.health.lock().unwrap();
+        health.last_cycle_time = Instant::now();
+
+        match result {
+            Ok(()) => {
+                health.last_success_time = Some(OffsetDateTime::now_utc().unix_timestamp());
+                health.failing_since = None;
+                health.last_error = None;
+            },
+            Err(e) => {
+                health.failing_since.get_or_insert_with(Instant::now);
+                health.last_error = Some(e.to_string());
+            },
+        }
+    }
+
+    fn health_check(&self) -> Response {
+        let health = self.health.lock().unwrap();
+
+        // The main loop may also wedge without reporting any errors
+        let healthy = health.last_cycle_time.elapsed() < self.unhealthy_after &&
+            health.failing_since.is_none_or(|time| time.elapsed() < self.unhealthy_after);
+
+        let body = json!({
+            "healthy": healthy,
+            "last_success_time": health.last_success_time,
+            "last_error": health.last_error,
+        });
+
+        Response::json(if healthy { "200 OK" } else { "503 Service Unavailable" }, &body)
+    }
+
+    async fn handle_api_request(&self, request: &Request, path: &str) -> Response {
+        let token = match self.api_token {
+            Some(ref token) => token,
+            None => return Response::text("404 Not Found", "Not found"),
+        };
+
+        let authorized = request.header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|value| compare_tokens(value.trim(), token));
+
+        if !authorized {
+            return Response::text("401 Unauthorized", "Unauthorized");
+        }
+
+        let (method, command) = match path.split('/').collect::<Vec<_>>().as_slice() {
+            ["torrents"] => ("GET", ApiCommand::GetTorrents),
+            ["process"] => ("POST", ApiCommand::Process),
+            ["torrents", hash, "copy"] => ("POST", ApiCommand::Copy(hash.to_lowercase())),
+            ["torrents", hash, "exempt"] => ("POST", ApiCommand::Exempt(hash.to_lowercase())),
+            ["torrents", hash, "remove"] => ("POST", ApiCommand::Remove(hash.to_lowercase())),
+            _ => return Response::text("404 Not Found", "Not found"),
+        };
+
+        if request.method != method {
+            return Response::text("405 Method Not Allowed", "Method not allowed");
+        }
+
+        self.call(command).await
+    }
+
+    async fn handle_dashboard_request(&self, request: &Request) -> Response {
+        let command = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/") => return Response {
+                status: "200 OK",
+                content_type: "text/html; charset=utf-8",
+                body: s!(DASHBOARD),
+            },
+            ("GET", "/dashboard.json") => ApiCommand::GetDashboard,
+            _ => return Response::text("405 Method Not Allowed", "Method not allowed"),
+        };
+
+        self.call(command).await
+    }
+
+    /// Executes the command in the daemon's main loop
+    async fn call(&self, command: ApiCommand) -> Response {
+        let api = self.api.as_ref().unwrap();
+
+        let (reply, result) = oneshot::channel();
+        if api.send(ApiRequest {command: command, reply: reply}).await.is_err() {
+            return Response::text("503 Service Unavailable", "The controller is shutting down");
+        }
+
+        let (status, message) = match result.await {
+            Ok(Ok(body)) => return Response::json("200 OK", &body),
+            Ok(Err(ApiError::NotFound(message))) => ("404 Not Found", message),
+            Ok(Err(ApiError::Conflict(message))) => ("409 Conflict", message),
+            Ok(Err(ApiError::Failed(message))) => ("500 Internal Server Error", message),
+            Err(_) => ("503 Service Unavailable", s!("The controller is shutting down")),
+        };
+
+        Response::json(status, &json!({"error": message}))
+    }
+}
+
+const API_QUEUE_SIZE: usize = 16;
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+/// Compares the tokens in constant time to not leak them via timing
+fn compare_tokens(actual: &str, expected: &str) -> bool {
+    actual.len() == expected.len() &&
+        actual.bytes().zip(expected.bytes()).fold(0, |result, (a, b)| result | (a ^ b)) == 0
+}
+
+/// Starts serving the requests in background
+pub async fn start_server(config: &HttpConfig) -> GenericResult<Option<HttpServer>> {
+    let address = match config.listen_address {
+        Some(address) => address,
+        None => return Ok(None),
+    };
+
+    let listener = TcpListener::bind(address).await.map_err(|e| format!(
+        "Unable to listen on {}: {}", address, e))?;
+
+    let (state, api_requests) = HttpState::new(config);
+    let state = Arc::new(state);
+    METRICS.enable();
+    info!("Listening for HTTP requests on {}.", address);
+
+    let server_state = state.clone();
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((connection, _)) => {
+                    let state = server_state.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_connection(connection, &state).await {
+                            debug!("Failed to handle HTTP request: {}.", e);
+                        }
+                    });
+                },
+                Err(e) => error!("Failed to accept HTTP connection: {}.", e),
+            }
+        }
+    });
+
+    Ok(Some(HttpServer {
+        state: state,
+        api_requests: api_requests,
+    }))
+}
+
+struct Request {
+    method: String,
+    path: String,
+    /// Header names are lowercased
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(header, _)| header == name).map(|(_, value)| value.as_str())
+    }
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn text(status: &'static str, body: &str) -> Response {
+        Response {
+            status: status,
+            content_type: "text/plain",
+            body: s!(body) + "\n",
+        }
+    }
+
+    fn json(status: &'static str, body: &serde_json::Value) -> Response {
+        Response {
+            status: status,
+            content_type: "application/json",
+            body: body.to_string() + "\n",
+        }
+    }
+}
+
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+async fn handle_connection(mut connection: TcpStream, state: &HttpState) -> EmptyResult {
+    let request = tokio::time::timeout(Duration::from_secs(10), read_request(&mut connection)).await
+        .map_err(|_| "Request timed out")??;
+
+    let response = match request {
+        Some(request) => handle_request(&request, state).await,
+        None => Response::text("400 Bad Request", "Bad request"),
+    };
+
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status, response.content_type, response.body.len());
+
+    connection.write_all(head.as_bytes()).await?;
+    connection.write_all(response.body.as_bytes()).await?;
+    connection.shutdown().await?;
+
+    Ok(())
+}
+
+async fn handle_request(request: &Request, state: &HttpState) -> Response {
+    if let Some(path) = request.path.strip_prefix("/api/") {
+        return state.handle_api_request(request, path).await;
+    } else if state.dashboard && matches!(request.path.as_str(), "/" | "/dashboard.json") {
+        return state.handle_dashboard_request(request).await;
+    }
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/metrics") => Response {
+            status: "200 OK",
+            content_type: "text/plain; version=0.0.4",
+            body: METRICS.render(),
+        },
+        ("GET", "/healthz") => state.health_check(),
+        (_, "/metrics" | "/healthz") => Response::text("405 Method Not Allowed", "Method not allowed"),
+        _ => Response::text("404 Not Found", "Not found"),
+    }
+}
+
+/// Reads the request returning None if it's malformed
+async fn read_request(connection: &mut TcpStream) -> GenericResult<Option<Request>> {
+    let mut data = Vec::new();
+    let mut buf = [0; 4096];
+
+    let head_size = loop {
+        if let Some(position) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        } else if data.len() > MAX_REQUEST_SIZE {
+            return Ok(None);
+        }
+
+        let size = connection.read(&mut buf).await?;
+        if size == 0 {
+            return Ok(None);
+        }
+        data.extend_from_slice(&buf[..size]);
+    };
+
+    let head = String::from_utf8_lossy(&data[..head_size]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (method, path) = match (request_line.next(), request_line.next()) {
+        (Some(method), Some(path)) if !method.is_empty() => (s!(method), path),
+        _ => return Ok(None),
+    };
+    let path = s!(path.split('?').next().unwrap());
+
+    let headers = lines.filter_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        Some((name.trim().to_lowercase(), s!(value.trim())))
+    }).collect();
+
+    Ok(Some(Request {
+        method: method,
+        path: path,
+        headers: headers,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use super::*;
+
+    fn request(method: &str, path: &str, token: Option<&str>) -> Request {
+        Request {
+            method: s!(method),
+            path: s!(path),
+            headers: token.map(|token| (s!("authorization"), format!("Bearer {}", token))).into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn test_health_check() {
+        let runtime = Runtime::new().unwrap();
+        let (state, _) = HttpState::new(&HttpConfig {
+            unhealthy_after: Duration::from_secs(60),
+            ..Default::default()
+        });
+        let status = |method, path| runtime.block_on(handle_request(&request(method, path, None), &state)).status;
+
+        assert_eq!(status("GET", "/healthz"), "200 OK");
+
+        state.on_cycle(Err("RPC error"));
+        let response = runtime.block_on(handle_request(&request("GET", "/healthz", None), &state));
+        assert_eq!(response.status, "200 OK");
+        assert!(response.body.contains(r#""last_error":"RPC error""#));
+
+        state.health.lock().unwrap().failing_since = Some(Instant::now() - Duration::from_secs(61));
+        assert_eq!(status("GET", "/healthz"), "503 Service Unavailable");
+
+        state.on_cycle(Ok::<_, String>(()));
+        assert_eq!(status("GET", "/healthz"), "200 OK");
+
+        state.health.lock().unwrap().last_cycle_time = Instant::now() - Duration::from_secs(61);
+        assert_eq!(status("GET", "/healthz"), "503 Service Unavailable");
+
+        assert_eq!(status("POST", "/healthz"), "405 Method Not Allowed");
+        assert_eq!(status("GET", "/"), "404 Not Found");
+        assert_eq!(status("GET", "/api/torrents"), "404 Not Found");
+    }
+
+    #[test]
+    fn test_api() {
+        let runtime = Runtime::new().unwrap();
+        let (state, api_requests) = HttpState::new(&HttpConfig {
+            api_token: Some(s!("secret")),
+            ..Default::default()
+        });
+
+        let mut api_requests = api_requests.unwrap();
+        runtime.spawn(async move {
+            while let Some(request) = api_requests.recv().await {
+                let result = match request.command {
+                    ApiCommand::GetTorrents => Ok(json!([])),
+                    ApiCommand::Remove(ref hash) if hash == "abc" => Ok(json!({"message": "removed"})),
+                    ApiCommand::Remove(ref hash) => Err(ApiError::NotFound(format!("{} torrent not found", hash))),
+                    _ => Err(ApiError::Conflict(s!("conflict"))),
+                };
+                request.reply(result);
+            }
+        });
+
+        let call = |method, path, token| runtime.block_on(handle_request(&request(method, path, token), &state));
+
+        assert_eq!(call("GET", "/api/torrents", None).status, "401 Unauthorized");
+        assert_eq!(call("GET", "/api/torrents", Some("invalid")).status, "401 Unauthorized");
+        assert_eq!(call("POST", "/api/torrents", Some("secret")).status, "405 Method Not Allowed");
+        assert_eq!(call("GET", "/api/unknown", Some("secret")).status, "404 Not Found");
+
+        let response = call("GET", "/api/torrents", Some("secret"));
+        assert_eq!((response.status, response.body.as_str()), ("200 OK", "[]\n"));
+
+        assert_eq!(call("POST", "/api/torrents/ABC/remove", Some("secret")).status, "200 OK");
+        assert_eq!(call("POST", "/api/torrents/def/remove", Some("secret")).status, "404 Not Found");
+        assert_eq!(call("POST", "/api/process", Some("secret")).status, "409 Conflict");
+
+        // The dashboard is disabled
+        assert_eq!(call("GET", "/", None).status, "404 Not Found");
+    }
+
+    #[test]
+    fn test_dashboard() {
+        let runtime = Runtime::new().unwrap();
+        let (state, api_requests) = HttpState::new(&HttpConfig {
+            dashboard: true,
+            ..Default::default()
+        });
+
+        let mut api_requests = api_requests.unwrap();
+        runtime.spawn(async move {
+            while let Some(request) = api_requests.recv().await {
+                request.reply(Ok(json!({"status": "0 torrents, 0 copying"})));
+            }
+        });
+
+        let call = |method, path, token| runtime.block_on(handle_request(&request(method, path, token), &state));
+
+        let response = call("GET", "/", None);
+        assert_eq!((response.status, response.content_type), ("200 OK", "text/html; charset=utf-8"));
+        assert!(response.body.contains("dashboard.json"));
+
+        assert_eq!(call("GET", "/dashboard.json", None).body, "{\"status\":\"0 torrents, 0 copying\"}\n");
+        assert_eq!(call("POST", "/dashboard.json", None).status, "405 Method Not Allowed");
+
+        // The API is disabled
+        assert_eq!(call("GET", "/api/torrents", Some("secret")).status, "404 Not Found");
+    }
+}
@@ -0,0 +1,145 @@
+//! Sonarr/Radarr completed download handoff: asks the service to import the consumed torrent right away instead of
+//! waiting for its periodic scan of the completed downloads.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use reqwest::{Client, header};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::common::EmptyResult;
+use crate::transmissionrpc::Torrent;
+
+use super::map_path;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArrKind {
+    Sonarr,
+    Radarr,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ArrConfig {
+    pub kind: ArrKind,
+    pub url: String,
+    pub api_key: String,
+    /// Only the torrents with any of the labels are handed off (all by default)
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Local to remote path prefixes for the services which see the destination under another path (in a container,
+    /// for example)
+    #[serde(default)]
+    pub path_mappings: BTreeMap<PathBuf, PathBuf>,
+}
+
+pub struct ArrService {
+    http: Client,
+    config: ArrConfig,
+}
+
+impl ArrService {
+    pub fn new(config: &ArrConfig) -> ArrService {
+        ArrService {
+            http: Client::new(),
+            config: config.clone(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.config.kind {
+            ArrKind::Sonarr => "Sonarr",
+            ArrKind::Radarr => "Radarr",
+        }
+    }
+
+    pub fn accepts(&self, torrent: &Torrent) -> bool {
+        self.config.labels.is_empty() || self.config.labels.iter().any(|label| torrent.labels.contains(label))
+    }
+
+    /// The service identifies the release by the torrent hash (its grab history keeps the original release name), so
+    /// the renamed torrents are imported properly as well.
+    pub async fn import(&self, torrent: &Torrent, path: &Path) -> EmptyResult {
+        let url = format!("{}/api/v3/command", self.config.url.trim_end_matches('/'));
+
+        self.http.post(url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("X-Api-Key", &self.config.api_key)
+            .body(self.get_command(torrent, path).to_string())
+            .timeout(REQUEST_TIMEOUT)
+            .send().await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    fn get_command(&self, torrent: &Torrent, path: &Path) -> Value {
+        let name = match self.config.kind {
+            ArrKind::Sonarr => "DownloadedEpisodesScan",
+            ArrKind::Radarr => "DownloadedMoviesScan",
+        };
+
+        json!({
+            "name": name,
+            "path": map_path(&self.config.path_mappings, path),
+            "downloadClientId": torrent.hash.to_uppercase(),
+            // The destination is the controller's own copy
+            "importMode": "Move",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::TorrentFields;
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    #[test]
+    fn test_import_command() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+
+        let mut torrent = MockTorrent::new(1, "Some.Show.S01E01.1080p", "/downloads");
+        torrent.labels = vec![s!("tv")];
+        server.add_torrent(torrent);
+
+        let torrent = runtime.block_on(server.client().get_torrent(&format!("{:040x}", 1), TorrentFields::basic()))
+            .unwrap();
+
+        let sonarr = ArrService::new(&toml::from_str(r#"
+            kind = "sonarr"
+            url = "http://localhost:8989/"
+            api-key = "secret"
+            labels = ["tv"]
+            path-mappings = { "/mnt" = "/data", "/mnt/library" = "/library" }
+        "#).unwrap());
+        assert!(sonarr.accepts(&torrent));
+
+        assert_eq!(sonarr.get_command(&torrent, Path::new("/mnt/library/Some.Show.S01E01.1080p")), json!({
+            "name": "DownloadedEpisodesScan",
+            "path": "/library/Some.Show.S01E01.1080p",
+            "downloadClientId": format!("{:040x}", 1).to_uppercase(),
+            "importMode": "Move",
+        }));
+        assert_eq!(sonarr.get_command(&torrent, Path::new("/mnt/other/file"))["path"], "/data/other/file");
+        assert_eq!(sonarr.get_command(&torrent, Path::new("/media/file"))["path"], "/media/file");
+
+        let radarr = ArrService::new(&toml::from_str(r#"
+            kind = "radarr"
+            url = "http://localhost:7878"
+            api-key = "secret"
+            labels = ["movies"]
+        "#).unwrap());
+        assert!(!radarr.accepts(&torrent));
+        assert_eq!(radarr.get_command(&torrent, Path::new("/movies/Movie"))["name"], "DownloadedMoviesScan");
+    }
+}
@@ -0,0 +1,164 @@
+//! Plex/Jellyfin library refresh: the copied torrents appear in the library right away instead of on the next
+//! scheduled scan.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use reqwest::{Client, header};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::common::{EmptyResult, GenericResult};
+
+use super::map_path;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MediaServerKind {
+    Plex,
+    Jellyfin,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct MediaServerConfig {
+    pub kind: MediaServerKind,
+    pub url: String,
+    /// Plex token or Jellyfin API key
+    pub token: String,
+    /// Local to remote path prefixes for the servers which see the destination under another path
+    #[serde(default)]
+    pub path_mappings: BTreeMap<PathBuf, PathBuf>,
+}
+
+pub struct MediaServer {
+    http: Client,
+    config: MediaServerConfig,
+}
+
+#[derive(Deserialize)]
+struct PlexSections {
+    #[serde(rename = "MediaContainer")]
+    container: PlexContainer,
+}
+
+#[derive(Deserialize)]
+struct PlexContainer {
+    #[serde(rename = "Directory", default)]
+    sections: Vec<PlexSection>,
+}
+
+#[derive(Deserialize)]
+struct PlexSection {
+    key: String,
+    #[serde(rename = "Location", default)]
+    locations: Vec<PlexLocation>,
+}
+
+#[derive(Deserialize)]
+struct PlexLocation {
+    path: PathBuf,
+}
+
+impl MediaServer {
+    pub fn new(config: &MediaServerConfig) -> MediaServer {
+        MediaServer {
+            http: Client::new(),
+            config: config.clone(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.config.kind {
+            MediaServerKind::Plex => "Plex",
+            MediaServerKind::Jellyfin => "Jellyfin",
+        }
+    }
+
+    /// Refreshes only the part of the library which contains the path
+    pub async fn refresh(&self, path: &Path) -> EmptyResult {
+        let path = map_path(&self.config.path_mappings, path);
+        match self.config.kind {
+            MediaServerKind::Plex => self.refresh_plex(&path).await,
+            MediaServerKind::Jellyfin => self.refresh_jellyfin(&path).await,
+        }
+    }
+
+    async fn refresh_plex(&self, path: &Path) -> EmptyResult {
+        let sections: PlexSections = serde_json::from_str(&self.request_plex("library/sections", &[]).await?)
+            .map_err(|e| format!("Got an invalid response from Plex: {}", e))?;
+
+        let section = find_plex_section(&sections.container.sections, path).ok_or_else(|| format!(
+            "there is no library section containing '{}'", path.display()))?;
+
+        let path = path.to_str().ok_or_else(|| format!("invalid path: '{}'", path.display()))?;
+        self.request_plex(&format!("library/sections/{}/refresh", section), &[("path", path)]).await?;
+
+        Ok(())
+    }
+
+    async fn request_plex(&self, method: &str, query: &[(&str, &str)]) -> GenericResult<String> {
+        Ok(self.http.get(format!("{}/{}", self.config.url.trim_end_matches('/'), method))
+            .query(query)
+            .header(header::ACCEPT, "application/json")
+            .header("X-Plex-Token", &self.config.token)
+            .timeout(REQUEST_TIMEOUT)
+            .send().await?
+            .error_for_status()?
+            .text().await?)
+    }
+
+    async fn refresh_jellyfin(&self, path: &Path) -> EmptyResult {
+        let updates = json!({
+            "Updates": [{
+                "Path": path,
+                "UpdateType": "Created",
+            }],
+        });
+
+        self.http.post(format!("{}/Library/Media/Updated", self.config.url.trim_end_matches('/')))
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("X-Emby-Token", &self.config.token)
+            .body(updates.to_string())
+            .timeout(REQUEST_TIMEOUT)
+            .send().await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+/// Returns the key of the section with the most specific location containing the path
+fn find_plex_section<'a>(sections: &'a [PlexSection], path: &Path) -> Option<&'a str> {
+    sections.iter().flat_map(|section| {
+        section.locations.iter()
+            .filter(|location| path.starts_with(&location.path))
+            .map(move |location| (location.path.components().count(), section.key.as_str()))
+    }).max_by_key(|&(depth, _)| depth).map(|(_, key)| key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plex_sections() {
+        let sections: PlexSections = serde_json::from_str(r#"{"MediaContainer": {"size": 3, "Directory": [
+            {"key": "1", "type": "movie", "Location": [{"id": 1, "path": "/data/movies"}]},
+            {"key": "2", "type": "show", "Location": [{"id": 2, "path": "/data/tv"}, {"id": 3, "path": "/data/anime"}]},
+            {"key": "3", "type": "show", "Location": [{"id": 4, "path": "/data/tv/kids"}]}
+        ]}}"#).unwrap();
+        let sections = &sections.container.sections;
+
+        assert_eq!(find_plex_section(sections, Path::new("/data/movies/Movie (2020)")), Some("1"));
+        assert_eq!(find_plex_section(sections, Path::new("/data/anime/Show")), Some("2"));
+        assert_eq!(find_plex_section(sections, Path::new("/data/tv/kids/Show")), Some("3"));
+        assert_eq!(find_plex_section(sections, Path::new("/data/tv-shows/Show")), None);
+
+        let sections: PlexSections = serde_json::from_str(r#"{"MediaContainer": {"size": 0}}"#).unwrap();
+        assert_eq!(find_plex_section(&sections.container.sections, Path::new("/data/movies")), None);
+    }
+}
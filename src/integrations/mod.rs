@@ -0,0 +1,69 @@
+//! Integrations with the external services which are notified about the consumed torrents
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::torrent_client::blocking::TorrentClient;
+use crate::transmissionrpc::Torrent;
+
+pub mod arr;
+pub mod media_servers;
+
+use arr::{ArrConfig, ArrService};
+use media_servers::{MediaServer, MediaServerConfig};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct IntegrationsConfig {
+    pub arr: Vec<ArrConfig>,
+    pub media_servers: Vec<MediaServerConfig>,
+}
+
+/// Failures are only logged: the services find the downloads by themselves eventually anyway
+pub struct Integrations {
+    arr: Vec<ArrService>,
+    media_servers: Vec<MediaServer>,
+}
+
+impl Integrations {
+    pub fn new(config: &IntegrationsConfig) -> Integrations {
+        Integrations {
+            arr: config.arr.iter().map(ArrService::new).collect(),
+            media_servers: config.media_servers.iter().map(MediaServer::new).collect(),
+        }
+    }
+
+    /// Called by the consumer threads when the torrent has been copied to the destination path
+    pub fn on_copied(&self, client: &TorrentClient, torrent: &Torrent, path: &Path) {
+        for service in self.arr.iter().filter(|service| service.accepts(torrent)) {
+            match client.block_on(service.import(torrent, path)) {
+                Ok(()) => info!("{} has been asked to import '{}'.", service.name(), path.display()),
+                Err(e) => error!("Failed to ask {} to import '{}': {}.", service.name(), path.display(), e),
+            }
+        }
+
+        // The media servers scan directories
+        let directory = if path.is_dir() { path } else { path.parent().unwrap_or(path) };
+
+        for server in &self.media_servers {
+            match client.block_on(server.refresh(directory)) {
+                Ok(()) => info!("{} library has been refreshed for '{}'.", server.name(), directory.display()),
+                Err(e) => error!("Failed to refresh {} library for '{}': {}.",
+                                 server.name(), directory.display(), e),
+            }
+        }
+    }
+}
+
+/// Maps the local path to the path the service sees via the longest matching prefix
+fn map_path(mappings: &BTreeMap<PathBuf, PathBuf>, path: &Path) -> PathBuf {
+    // A prefix is always sorted before the paths it contains
+    for (local, remote) in mappings.iter().rev() {
+        if let Ok(relative) = path.strip_prefix(local) {
+            return remote.join(relative);
+        }
+    }
+    path.to_owned()
+}
@@ -1,33 +1,134 @@
 use std::cmp;
+use std::ffi::CString;
 use std::fmt;
+use std::fs::{self, File, OpenOptions};
 use std::io;
 use std::io::Write;
-use std::sync::{Arc, Mutex, Weak};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock, Weak};
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration as StdDuration, Instant};
 
 use itertools::Itertools;
-use log::{self, Log, Record, Level, Metadata, SetLoggerError};
-use time::Duration;
+use log::{self, Log, Record, Level, LevelFilter, Metadata};
+use log::kv::{self, Source, VisitSource};
+use serde::Deserialize;
+use time::{Duration, OffsetDateTime};
 
+use crate::common::GenericResult;
+use crate::config;
 use crate::email::Mailer;
 use crate::util::helpers::SelfArc;
 
 
-pub fn init(level: Level, target: Option<&'static str>, mailer: Option<Mailer>) -> Result<LoggerGuard, SetLoggerError> {
-    let mut logger = Logger::new(level, target);
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line for log collectors
+    Json,
+}
+
+/// Per-module log levels: a comma-separated list of a default level and `module=level` directives, for example
+/// "info, rpc=debug, copy=trace". Modules are the controller's module paths, "rpc" and "copy" are aliases for the
+/// Transmission client and the torrent copying.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LogFilter {
+    level: Option<LevelFilter>,
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+    pub fn parse(spec: &str) -> GenericResult<LogFilter> {
+        let mut filter = LogFilter::default();
+
+        for directive in spec.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            let Some((module, level)) = directive.split_once('=') else {
+                filter.level = Some(parse_level(directive)?);
+                continue;
+            };
+
+            let module = match module.trim() {
+                "" => return Err!("Invalid log filter directive: {:?}", directive),
+                "rpc" => "transmissionrpc",
+                "copy" => "consumer",
+                module => module,
+            };
+
+            filter.modules.push((format!("{}::{}", env!("CARGO_CRATE_NAME"), module), parse_level(level.trim())?));
+        }
+
+        // The most specific module wins
+        filter.modules.sort_by_key(|(module, _)| cmp::Reverse(module.len()));
+
+        Ok(filter)
+    }
+
+    fn get_level(&self, target: &str, default: LevelFilter) -> LevelFilter {
+        for (module, level) in &self.modules {
+            if target == module || target.strip_prefix(module.as_str()).is_some_and(|rest| rest.starts_with("::")) {
+                return *level;
+            }
+        }
 
-    let stderr_handler = StderrHandler::new(level >= Level::Debug);
-    logger.add_handler(stderr_handler.clone());
+        // The default level is set by the command line flags which can only make logging more verbose
+        match self.level {
+            Some(level) if default <= LevelFilter::Info => level,
+            Some(level) => cmp::max(level, default),
+            None => default,
+        }
+    }
+
+    fn max_level(&self, default: LevelFilter) -> LevelFilter {
+        self.modules.iter().map(|&(_, level)| level).fold(self.get_level("", default), cmp::max)
+    }
+}
+
+fn parse_level(name: &str) -> GenericResult<LevelFilter> {
+    name.parse().map_err(|_| format!("Invalid log level: {:?}", name).into())
+}
+
+/// Where and how the log messages are written
+pub struct LogOutputs<'a> {
+    pub format: LogFormat,
+    pub log_file: &'a LogFileConfig,
+    pub syslog: &'a SyslogConfig,
+    pub audit_log: Option<&'a Path>,
+}
+
+pub fn init(
+    level: Level, target: Option<&'static str>, filter: LogFilter, outputs: LogOutputs, mailer: Option<Mailer>,
+) -> GenericResult<LoggerGuard> {
+    let LogOutputs {format, log_file, syslog, audit_log} = outputs;
+    let mut logger = Logger::new(level, target, filter);
+    let formatter = Formatter {
+        debug: level >= Level::Debug,
+        format: format,
+    };
+
+    let output_handler: Arc<dyn LoggingHandler> = if syslog.enabled {
+        SyslogHandler::new(syslog, formatter)?
+    } else if log_file.path.is_some() {
+        FileHandler::new(log_file.clone(), formatter)?
+    } else {
+        StderrHandler::new(formatter)
+    };
+    logger.add_handler(output_handler.clone());
 
     if let Some(mailer) = mailer {
-        logger.add_handler(EmailHandler::new("Transmission controller errors", mailer, stderr_handler));
+        logger.add_handler(EmailHandler::new("Transmission controller errors", mailer, output_handler));
+    }
+
+    if let Some(path) = audit_log {
+        logger.audit = Some(AuditHandler::new(path)?);
     }
 
+    let max_level = logger.max_level(&logger.filter.read().unwrap());
     let logger = Arc::new(logger);
 
     log::set_boxed_logger(Box::new(LoggerWrapper { logger: logger.clone() }))?;
-    log::set_max_level(level.to_level_filter());
+    log::set_max_level(max_level);
 
     Ok(LoggerGuard { logger: Arc::downgrade(&logger) })
 }
@@ -37,6 +138,28 @@ pub struct LoggerGuard {
     logger: Weak<Logger>
 }
 
+impl LoggerGuard {
+    pub fn reopen(&self) {
+        if let Some(logger) = self.logger.upgrade() {
+            for handler in &logger.handlers {
+                handler.reopen();
+            }
+
+            if let Some(ref audit) = logger.audit {
+                audit.reopen();
+            }
+        }
+    }
+
+    /// Applies the new per-module log levels at runtime
+    pub fn set_filter(&self, filter: LogFilter) {
+        if let Some(logger) = self.logger.upgrade() {
+            log::set_max_level(logger.max_level(&filter));
+            *logger.filter.write().unwrap() = filter;
+        }
+    }
+}
+
 impl Drop for LoggerGuard {
     fn drop(&mut self) {
         if let Some(logger) = self.logger.upgrade() {
@@ -68,26 +191,43 @@ impl Log for LoggerWrapper {
 struct Logger {
     target: Option<&'static str>,
     level: Level,
+    filter: RwLock<LogFilter>,
     handlers: Vec<Arc<dyn LoggingHandler>>,
+    audit: Option<Arc<AuditHandler>>,
 }
 
 impl Logger {
-    fn new(level: Level, target: Option<&'static str>) -> Logger {
+    fn new(level: Level, target: Option<&'static str>, filter: LogFilter) -> Logger {
         Logger {
             target: target,
             level: level,
+            filter: RwLock::new(filter),
             handlers: Vec::new(),
+            audit: None,
         }
     }
 
     fn add_handler(&mut self, handler: Arc<dyn LoggingHandler>) {
         self.handlers.push(handler);
     }
+
+    fn max_level(&self, filter: &LogFilter) -> LevelFilter {
+        let max_level = filter.max_level(self.level.to_level_filter());
+
+        // The audit log must get the actions regardless of the log level
+        if self.audit.is_some() {
+            cmp::max(max_level, LevelFilter::Info)
+        } else {
+            max_level
+        }
+    }
 }
 
 impl Log for Logger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= self.level && (
+        let level = self.filter.read().unwrap().get_level(metadata.target(), self.level.to_level_filter());
+
+        metadata.level() <= level && (
             self.target.is_none() ||
             metadata.target() == self.target.unwrap() ||
             metadata.target().starts_with(&(s!(self.target.unwrap()) + "::"))
@@ -96,12 +236,19 @@ impl Log for Logger {
 
     fn log(&self, record: &Record) {
         let metadata = record.metadata();
+
+        if let Some(ref audit) = self.audit {
+            audit.log(metadata.target(), record.file(), record.line(), metadata.level(), record.args(),
+                      record.key_values());
+        }
+
         if !self.enabled(metadata) {
             return;
         }
 
         for handler in &self.handlers {
-            handler.log(metadata.target(), record.file(), record.line(), metadata.level(), record.args());
+            handler.log(metadata.target(), record.file(), record.line(), metadata.level(), record.args(),
+                        record.key_values());
         }
     }
 
@@ -109,26 +256,34 @@ impl Log for Logger {
         for handler in &self.handlers {
             handler.flush();
         }
+
+        if let Some(ref audit) = self.audit {
+            audit.flush();
+        }
     }
 }
 
 
-struct StderrHandler {
+#[derive(Clone, Copy)]
+struct Formatter {
     debug: bool,
-    stderr: io::Stderr,
+    format: LogFormat,
 }
 
-impl StderrHandler {
-    fn new(debug: bool) -> Arc<StderrHandler> {
-        Arc::new(StderrHandler {
-            debug: debug,
-            stderr: io::stderr(),
-        })
+impl Formatter {
+    fn format(
+        &self, target: &str, file: Option<&str>, line: Option<u32>, level: Level, args: &fmt::Arguments,
+        key_values: &dyn Source,
+    ) -> String {
+        match self.format {
+            LogFormat::Text => self.format_text(target, file, line, level, args),
+            LogFormat::Json => self.format_json(target, file, line, level, args, key_values),
+        }
     }
-}
 
-impl LoggingHandler for StderrHandler {
-    fn log(&self, target: &str, file: Option<&str>, line: Option<u32>, level: Level, args: &fmt::Arguments) {
+    fn format_text(
+        &self, target: &str, file: Option<&str>, line: Option<u32>, level: Level, args: &fmt::Arguments,
+    ) -> String {
         let mut prefix = String::new();
 
         if let (true, Some(file), Some(line)) = (self.debug, file, line) {
@@ -149,11 +304,56 @@ impl LoggingHandler for StderrHandler {
             Level::Trace => "T",
         } + ": ";
 
-        {
-            let mut stderr = self.stderr.lock();
-            if writeln!(stderr, "{}{}", prefix, args).is_ok() {
-                let _ = stderr.flush();
-            }
+        format!("{}{}", prefix, args)
+    }
+
+    fn format_json(
+        &self, target: &str, file: Option<&str>, line: Option<u32>, level: Level, args: &fmt::Arguments,
+        key_values: &dyn Source,
+    ) -> String {
+        let mut event = serde_json::Map::new();
+
+        event.insert(s!("timestamp"), format_timestamp(OffsetDateTime::now_utc()).into());
+        event.insert(s!("level"), level.as_str().to_lowercase().into());
+        event.insert(s!("module"), target.into());
+
+        if let (true, Some(file), Some(line)) = (self.debug, file, line) {
+            event.insert(s!("file"), file.into());
+            event.insert(s!("line"), line.into());
+        }
+
+        event.insert(s!("message"), args.to_string().into());
+        let _ = key_values.visit(&mut JsonVisitor(&mut event));
+
+        serde_json::Value::Object(event).to_string()
+    }
+}
+
+
+struct StderrHandler {
+    formatter: Formatter,
+    stderr: io::Stderr,
+}
+
+impl StderrHandler {
+    fn new(formatter: Formatter) -> Arc<StderrHandler> {
+        Arc::new(StderrHandler {
+            formatter: formatter,
+            stderr: io::stderr(),
+        })
+    }
+}
+
+impl LoggingHandler for StderrHandler {
+    fn log(
+        &self, target: &str, file: Option<&str>, line: Option<u32>, level: Level, args: &fmt::Arguments,
+        key_values: &dyn Source,
+    ) {
+        let message = self.formatter.format(target, file, line, level, args, key_values);
+
+        let mut stderr = self.stderr.lock();
+        if writeln!(stderr, "{}", message).is_ok() {
+            let _ = stderr.flush();
         }
     }
 
@@ -163,6 +363,286 @@ impl LoggingHandler for StderrHandler {
 }
 
 
+/// Logging to a file: it's rotated when it exceeds the maximum size or age (the rotated files are named `$path.1`,
+/// `$path.2`, etc.) or may be rotated externally by logrotate which should send SIGUSR2 to reopen the file afterwards.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LogFileConfig {
+    pub path: Option<PathBuf>,
+    /// Maximum file size in MB
+    pub max_size: Option<u64>,
+    #[serde(deserialize_with = "config::deserialize_optional_duration")]
+    pub max_age: Option<StdDuration>,
+    /// Number of the rotated files to keep
+    pub max_files: usize,
+}
+
+impl Default for LogFileConfig {
+    fn default() -> LogFileConfig {
+        LogFileConfig {
+            path: None,
+            max_size: None,
+            max_age: None,
+            max_files: 5,
+        }
+    }
+}
+
+struct FileHandler {
+    config: LogFileConfig,
+    path: PathBuf,
+    formatter: Formatter,
+    file: Mutex<LogFile>,
+}
+
+struct LogFile {
+    file: File,
+    size: u64,
+    open_time: Instant,
+}
+
+impl LogFile {
+    fn open(path: &Path) -> io::Result<LogFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(LogFile {
+            file: file,
+            size: size,
+            open_time: Instant::now(),
+        })
+    }
+}
+
+impl FileHandler {
+    fn new(config: LogFileConfig, formatter: Formatter) -> GenericResult<Arc<FileHandler>> {
+        let path = config.path.clone().unwrap();
+        let file = LogFile::open(&path).map_err(|e| format!("Unable to open '{}': {}", path.display(), e))?;
+
+        Ok(Arc::new(FileHandler {
+            config: config,
+            path: path,
+            formatter: formatter,
+            file: Mutex::new(file),
+        }))
+    }
+
+    fn needs_rotation(&self, file: &LogFile, size: u64) -> bool {
+        // Don't rotate empty files to not lose the previous logs when a single message exceeds the limit
+        file.size != 0 && (
+            self.config.max_size.is_some_and(|max_size| file.size + size > max_size * 1024 * 1024) ||
+            self.config.max_age.is_some_and(|max_age| file.open_time.elapsed() >= max_age)
+        )
+    }
+
+    fn rotate(&self) -> io::Result<LogFile> {
+        let rotated_path = |index: usize| {
+            let mut path = self.path.as_os_str().to_owned();
+            path.push(format!(".{}", index));
+            PathBuf::from(path)
+        };
+
+        if self.config.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.config.max_files).rev() {
+                match fs::rename(rotated_path(index), rotated_path(index + 1)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {},
+                }
+            }
+            fs::rename(&self.path, rotated_path(1))?;
+        }
+
+        LogFile::open(&self.path)
+    }
+
+    fn write(&self, message: &str) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+
+        if self.needs_rotation(&file, message.len() as u64 + 1) {
+            *file = self.rotate()?;
+        }
+
+        writeln!(file.file, "{}", message)?;
+        file.size += message.len() as u64 + 1;
+
+        Ok(())
+    }
+}
+
+impl LoggingHandler for FileHandler {
+    fn log(
+        &self, target: &str, file: Option<&str>, line: Option<u32>, level: Level, args: &fmt::Arguments,
+        key_values: &dyn Source,
+    ) {
+        let message = self.formatter.format(target, file, line, level, args, key_values);
+
+        if let Err(e) = self.write(&message) {
+            let _ = writeln!(io::stderr(), "Failed to write to '{}': {}. {}", self.path.display(), e, message);
+        }
+    }
+
+    fn flush(&self) {
+        let _ = self.file.lock().unwrap().file.flush();
+    }
+
+    fn reopen(&self) {
+        match LogFile::open(&self.path) {
+            Ok(file) => *self.file.lock().unwrap() = file,
+            Err(e) => {
+                let _ = writeln!(io::stderr(), "Failed to reopen '{}': {}.", self.path.display(), e);
+            },
+        }
+    }
+}
+
+
+/// Append-only log of the state-changing actions (the messages with `action` key) in JSON lines format, so it's always
+/// possible to find out why a torrent has disappeared. It doesn't depend on the log level and is never rotated by the
+/// controller itself.
+struct AuditHandler {
+    file: Arc<FileHandler>,
+}
+
+impl AuditHandler {
+    fn new(path: &Path) -> GenericResult<Arc<AuditHandler>> {
+        let config = LogFileConfig {
+            path: Some(path.to_owned()),
+            ..Default::default()
+        };
+
+        Ok(Arc::new(AuditHandler {
+            file: FileHandler::new(config, Formatter { debug: false, format: LogFormat::Json })?,
+        }))
+    }
+}
+
+impl LoggingHandler for AuditHandler {
+    fn log(
+        &self, target: &str, file: Option<&str>, line: Option<u32>, level: Level, args: &fmt::Arguments,
+        key_values: &dyn Source,
+    ) {
+        // Debug messages may describe the actions that are not going to be made
+        if level <= Level::Info && key_values.get(kv::Key::from_str("action")).is_some() {
+            self.file.log(target, file, line, level, args, key_values);
+        }
+    }
+
+    fn flush(&self) {
+        self.file.flush();
+    }
+
+    fn reopen(&self) {
+        self.file.reopen();
+    }
+}
+
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SyslogConfig {
+    /// Log to syslog instead of stderr
+    pub enabled: bool,
+    pub identity: String,
+    pub facility: SyslogFacility,
+}
+
+impl Default for SyslogConfig {
+    fn default() -> SyslogConfig {
+        SyslogConfig {
+            enabled: false,
+            identity: s!("transmission-controller"),
+            facility: SyslogFacility::Daemon,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyslogFacility {
+    Daemon,
+    User,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+
+impl SyslogFacility {
+    fn value(self) -> libc::c_int {
+        match self {
+            SyslogFacility::Daemon => libc::LOG_DAEMON,
+            SyslogFacility::User => libc::LOG_USER,
+            SyslogFacility::Local0 => libc::LOG_LOCAL0,
+            SyslogFacility::Local1 => libc::LOG_LOCAL1,
+            SyslogFacility::Local2 => libc::LOG_LOCAL2,
+            SyslogFacility::Local3 => libc::LOG_LOCAL3,
+            SyslogFacility::Local4 => libc::LOG_LOCAL4,
+            SyslogFacility::Local5 => libc::LOG_LOCAL5,
+            SyslogFacility::Local6 => libc::LOG_LOCAL6,
+            SyslogFacility::Local7 => libc::LOG_LOCAL7,
+        }
+    }
+}
+
+struct SyslogHandler {
+    formatter: Formatter,
+    // openlog() doesn't copy the identity, so it must outlive the handler
+    _identity: CString,
+}
+
+impl SyslogHandler {
+    fn new(config: &SyslogConfig, formatter: Formatter) -> GenericResult<Arc<SyslogHandler>> {
+        let identity = CString::new(config.identity.as_str()).map_err(|_| format!(
+            "Invalid syslog identity: {:?}", config.identity))?;
+
+        unsafe { libc::openlog(identity.as_ptr(), libc::LOG_PID, config.facility.value()) };
+
+        Ok(Arc::new(SyslogHandler {
+            formatter: formatter,
+            _identity: identity,
+        }))
+    }
+}
+
+impl LoggingHandler for SyslogHandler {
+    fn log(
+        &self, target: &str, file: Option<&str>, line: Option<u32>, level: Level, args: &fmt::Arguments,
+        key_values: &dyn Source,
+    ) {
+        // syslog has its own level indication
+        let message = match self.formatter.format {
+            LogFormat::Text => args.to_string(),
+            LogFormat::Json => self.formatter.format(target, file, line, level, args, key_values),
+        };
+
+        let priority = match level {
+            Level::Error => libc::LOG_ERR,
+            Level::Warn => libc::LOG_WARNING,
+            Level::Info => libc::LOG_INFO,
+            Level::Debug | Level::Trace => libc::LOG_DEBUG,
+        };
+
+        let message = CString::new(message.replace('\0', "\\0")).unwrap();
+        let format = CString::new("%s").unwrap();
+        unsafe { libc::syslog(priority, format.as_ptr(), message.as_ptr()) };
+    }
+
+    fn flush(&self) {}
+}
+
+impl Drop for SyslogHandler {
+    fn drop(&mut self) {
+        unsafe { libc::closelog() };
+    }
+}
+
+
 struct EmailHandler {
     subject: String,
     mailer: Mailer,
@@ -187,13 +667,16 @@ impl EmailHandler {
     fn send(&self, message: &str) {
         if let Err(error) = self.mailer.send(&self.subject, message) {
             self.fallback_handler.log(module_path!(), Some(file!()), Some(line!()), Level::Error,
-                &format_args!("Failed to send an error via email: {}.", error));
+                &format_args!("Failed to send an error via email: {}.", error), &NO_KEY_VALUES);
         }
     }
 }
 
 impl LoggingHandler for EmailHandler {
-    fn log(&self, _target: &str, _file: Option<&str>, _line: Option<u32>, level: Level, args: &fmt::Arguments) {
+    fn log(
+        &self, _target: &str, _file: Option<&str>, _line: Option<u32>, level: Level, args: &fmt::Arguments,
+        _key_values: &dyn Source,
+    ) {
         if level > Level::Error {
             return;
         }
@@ -307,6 +790,142 @@ impl Drop for EmailLog {
 
 
 pub trait LoggingHandler: Send + Sync {
-    fn log(&self, target: &str, file: Option<&str>, line: Option<u32>, level: Level, args: &fmt::Arguments);
+    fn log(
+        &self, target: &str, file: Option<&str>, line: Option<u32>, level: Level, args: &fmt::Arguments,
+        key_values: &dyn Source,
+    );
     fn flush(&self);
+
+    /// Reopens the output after it has been rotated externally
+    fn reopen(&self) {}
+}
+
+const NO_KEY_VALUES: [(&str, &str); 0] = [];
+
+struct JsonVisitor<'a>(&'a mut serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonVisitor<'_> {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.insert(key.to_string(), value.to_string().into());
+        Ok(())
+    }
+}
+
+/// Formats the time in RFC 3339 format with millisecond precision
+fn format_timestamp(time: OffsetDateTime) -> String {
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            time.year(), u8::from(time.month()), time.day(), time.hour(), time.minute(), time.second(),
+            time.millisecond())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_format() {
+        let formatter = Formatter { debug: false, format: LogFormat::Json };
+        let key_values = [("hash", "0123456789abcdef"), ("action", "remove")];
+
+        let message = formatter.format(
+            "transmission_controller::controller", None, None, Level::Info,
+            &format_args!("Removing '{}' torrent...", "Some torrent"), &key_values);
+
+        let event: serde_json::Value = serde_json::from_str(&message).unwrap();
+        assert_eq!(event["level"], "info");
+        assert_eq!(event["module"], "transmission_controller::controller");
+        assert_eq!(event["message"], "Removing 'Some torrent' torrent...");
+        assert_eq!(event["hash"], "0123456789abcdef");
+        assert_eq!(event["action"], "remove");
+        assert!(event["timestamp"].as_str().unwrap().ends_with('Z'));
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        let time = OffsetDateTime::from_unix_timestamp_nanos(1_700_000_000_123_000_000).unwrap();
+        assert_eq!(format_timestamp(time), "2023-11-14T22:13:20.123Z");
+    }
+
+    #[test]
+    fn test_log_file_rotation() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("controller.log");
+        let rotated_path = |index| temp_dir.path().join(format!("controller.log.{}", index));
+
+        let config = LogFileConfig {
+            path: Some(path.clone()),
+            max_size: Some(1),
+            max_age: None,
+            max_files: 2,
+        };
+        let handler = FileHandler::new(config, Formatter { debug: false, format: LogFormat::Text }).unwrap();
+
+        let message = "x".repeat(1024 * 1024 - 1);
+        for _ in 0..4 {
+            handler.write(&message).unwrap();
+        }
+
+        assert_eq!(fs::metadata(&path).unwrap().len(), 1024 * 1024);
+        assert_eq!(fs::metadata(rotated_path(1)).unwrap().len(), 1024 * 1024);
+        assert_eq!(fs::metadata(rotated_path(2)).unwrap().len(), 1024 * 1024);
+        assert!(!rotated_path(3).exists());
+
+        // Rotated externally
+        fs::rename(&path, rotated_path(3)).unwrap();
+        handler.reopen();
+        handler.write("message").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "message\n");
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("audit.log");
+        let handler = AuditHandler::new(&path).unwrap();
+
+        let log = |level, message, key_values: &dyn Source| handler.log(
+            "transmission_controller::controller", None, None, level, &format_args!("{}", message), key_values);
+
+        log(Level::Info, "Removing 'Some torrent' torrent: it's been seeded for 7d.",
+            &[("hash", "0123456789abcdef"), ("action", "remove")]);
+        log(Level::Info, "Checking the torrents...", &NO_KEY_VALUES);
+        log(Level::Debug, "Would remove 'Some torrent' torrent.", &[("action", "remove")]);
+        log(Level::Info, "'Some torrent' torrent's data has been deleted.",
+            &[("hash", "0123456789abcdef"), ("action", "delete-data")]);
+        handler.flush();
+
+        let events: Vec<serde_json::Value> = fs::read_to_string(&path).unwrap().lines()
+            .map(|line| serde_json::from_str(line).unwrap()).collect();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["action"], "remove");
+        assert_eq!(events[0]["hash"], "0123456789abcdef");
+        assert_eq!(events[0]["message"], "Removing 'Some torrent' torrent: it's been seeded for 7d.");
+        assert_eq!(events[1]["action"], "delete-data");
+    }
+
+    #[test]
+    fn test_log_filter() {
+        let filter = LogFilter::parse("warn, rpc=debug, copy=trace, controller=off").unwrap();
+        let level = |target| filter.get_level(target, LevelFilter::Info);
+
+        assert_eq!(level("transmission_controller"), LevelFilter::Warn);
+        assert_eq!(level("transmission_controller::transmissionrpc::blocking"), LevelFilter::Debug);
+        assert_eq!(level("transmission_controller::transmissionrpcx"), LevelFilter::Warn);
+        assert_eq!(level("transmission_controller::consumer"), LevelFilter::Trace);
+        assert_eq!(level("transmission_controller::controller"), LevelFilter::Off);
+        assert_eq!(filter.max_level(LevelFilter::Info), LevelFilter::Trace);
+
+        let filter = LogFilter::parse("util::fs=debug, util=error").unwrap();
+        assert_eq!(filter.get_level("transmission_controller::util::fs", LevelFilter::Info), LevelFilter::Debug);
+        assert_eq!(filter.get_level("transmission_controller::util::helpers", LevelFilter::Info), LevelFilter::Error);
+        assert_eq!(filter.get_level("transmission_controller::http", LevelFilter::Debug), LevelFilter::Debug);
+
+        let filter = LogFilter::parse("warn").unwrap();
+        assert_eq!(filter.get_level("transmission_controller::http", LevelFilter::Debug), LevelFilter::Debug);
+
+        assert_eq!(LogFilter::parse("").unwrap(), LogFilter::default());
+        assert!(LogFilter::parse("verbose").is_err());
+        assert!(LogFilter::parse("=debug").is_err());
+    }
 }
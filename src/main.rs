@@ -1,43 +1,105 @@
 extern crate argparse;
-#[macro_use] extern crate chan;
-extern crate chan_signal; // Attention: this crate calls pthread_sigmask() in crate's init() which masks all signals
 extern crate email as libemail;
-#[macro_use] extern crate enum_primitive;
 extern crate itertools;
 extern crate lettre;
 extern crate lettre_email;
 extern crate libc;
 #[macro_use] extern crate log;
 extern crate mime;
-extern crate num;
 extern crate regex;
 extern crate reqwest;
 extern crate time;
 
 #[macro_use] mod common;
+mod availability;
+mod bandwidth;
 mod cli_args;
+mod commands;
 mod config;
 mod consumer;
+mod control;
 mod controller;
+mod daemon;
+mod disk_space;
+mod download_queue;
+mod duplicates;
 mod email;
+mod history;
+mod hooks;
+mod integrations;
+mod http;
 mod logging;
+mod maintenance;
+mod metainfo;
+mod metrics;
+mod mqtt;
+mod notifications;
+mod policy;
+mod progress;
+mod quotas;
+mod rename;
+mod retention;
+mod routing;
+mod scope;
+mod seed_cycling;
+mod seeding;
+mod snapshot;
+mod speed_schedule;
+mod stalled;
+mod state_db;
+mod systemd;
+mod tasks;
+mod telegram;
+mod torrent_archive;
+mod torrent_client;
+mod torrent_errors;
+mod tracker_limits;
+mod trackers;
+mod traffic;
 mod transmissionrpc;
+mod tui;
+mod unpack;
 mod util;
+mod verification;
+mod vpn;
+mod webhooks;
+mod xmpp;
 
 use std::io::Write;
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 use std::process;
-use std::time::Instant;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use chan_signal::Signal;
+use rand::Rng;
+use tokio::runtime::Runtime;
+use tokio::signal::unix::{signal, SignalKind};
 
-use crate::common::GenericResult;
-use crate::config::{Config, ConfigReadingError};
+use crate::availability::AvailabilityMonitor;
+use crate::common::{EmptyResult, GenericResult};
+use crate::config::{Config, ControllerConfig, ConfigReadingError, PollConfig};
 use crate::email::Mailer;
+use crate::metrics::METRICS;
+use crate::notifications::Notifier;
+use crate::cli_args::Command;
+use crate::mqtt::MqttPublisher;
+use crate::telegram::TelegramBot;
+use crate::torrent_client::{Backend, TorrentClient, blocking};
+use crate::transmissionrpc::{RetryPolicy, TlsOptions, TransmissionClient};
 
-fn get_rpc_url(config: &Config) -> String {
-    let mut url = format!("http://{host}:{port}{path}",
-        host=config.rpc_bind_address, port=config.rpc_port, path=config.rpc_url);
+fn get_rpc_url(config: &Config, controller_config: &ControllerConfig) -> String {
+    if let Some(ref url) = controller_config.rpc.url {
+        return url.clone();
+    }
+
+    // IPv6 literals (including "::" which Transmission binds to all interfaces with) must be enclosed in brackets
+    let host = match config.rpc_bind_address.trim().parse::<IpAddr>() {
+        Ok(IpAddr::V6(address)) => format!("[{}]", address),
+        _ => config.rpc_bind_address.trim().to_owned(),
+    };
+
+    let mut url = format!("http://{host}:{port}{path}", host=host, port=config.rpc_port, path=config.rpc_url);
 
     if !url.ends_with('/') {
         url.push('/');
@@ -56,11 +118,28 @@ fn load_config(path: &Path) -> GenericResult<Config> {
         _ => format!("Error while reading '{}' configuration file: {}", path.display(), e),
     })?;
 
-    debug!("Loaded config: {:?}", config);
     Ok(config)
 }
 
-fn setup_logging(debug_level: usize, error_mailer: Option<Mailer>) -> GenericResult<logging::LoggerGuard> {
+fn load_controller_config(path: Option<&Path>) -> GenericResult<ControllerConfig> {
+    let path = match path {
+        Some(path) => path,
+        None => return Ok(ControllerConfig::default()),
+    };
+
+    let config = config::read_controller_config(path).map_err(|e| match e {
+        ConfigReadingError::Validation(_) => {
+            format!("Validation of '{}' controller configuration file failed: {}", path.display(), e)
+        },
+        _ => format!("Error while reading '{}' controller configuration file: {}", path.display(), e),
+    })?;
+
+    Ok(config)
+}
+
+fn setup_logging(
+    debug_level: usize, config: &ControllerConfig, dry_run: bool, error_mailer: Option<Mailer>,
+) -> GenericResult<logging::LoggerGuard> {
     let mut log_target = Some(module_path!());
 
     let log_level = match debug_level {
@@ -73,62 +152,496 @@ fn setup_logging(debug_level: usize, error_mailer: Option<Mailer>) -> GenericRes
         }
     };
 
-    Ok(logging::init(log_level, log_target, error_mailer)?)
+    // Nothing is actually changed in dry run mode
+    let audit_log = config.audit_log.as_deref().filter(|_| !dry_run);
+
+    logging::init(log_level, log_target, get_log_filter(config)?, logging::LogOutputs {
+        format: config.log_format,
+        log_file: &config.log_file,
+        syslog: &config.syslog,
+        audit_log: audit_log,
+    }, error_mailer)
+}
+
+fn get_log_filter(config: &ControllerConfig) -> GenericResult<logging::LogFilter> {
+    Ok(match config.log_level {
+        Some(ref spec) => logging::LogFilter::parse(spec)?,
+        None => logging::LogFilter::default(),
+    })
+}
+
+fn create_client(
+    config: &Config, controller_config: &ControllerConfig, dry_run: bool,
+) -> GenericResult<Arc<dyn TorrentClient>> {
+    match controller_config.backend {
+        Backend::Transmission => {
+            let mut client = create_transmission_client(config, controller_config)?;
+            client.set_dry_run(dry_run);
+            Ok(Arc::new(client))
+        },
+    }
 }
 
-fn daemon() -> GenericResult<i32> {
-    let signal_channel = chan_signal::notify(
-        &[Signal::INT, Signal::TERM, Signal::QUIT]);
+fn create_transmission_client(
+    config: &Config, controller_config: &ControllerConfig,
+) -> GenericResult<TransmissionClient> {
+    let rpc_url = get_rpc_url(config, controller_config);
+    debug!("Use RPC URL: {}.", rpc_url);
+
+    let mut client = match controller_config.rpc.socket_path {
+        Some(ref socket_path) => {
+            debug!("Connect to RPC via {} UNIX socket.", socket_path.display());
+            TransmissionClient::new_with_unix_socket(socket_path, &rpc_url)?
+        },
+        None => TransmissionClient::new(&rpc_url, &TlsOptions {
+            ca_file: controller_config.rpc.ca_file.clone(),
+            verify_certificate: controller_config.rpc.verify_certificate,
+            server_name: controller_config.rpc.server_name.clone(),
+            client_identity: controller_config.rpc.client_certificate.clone().zip(
+                controller_config.rpc.client_key.clone()),
+        }, controller_config.rpc.prefer_address_family)?,
+    };
+
+    if config.rpc_authentication_required {
+        client.set_authentication(&config.rpc_username, config.rpc_plain_password.as_ref().unwrap());
+    }
+
+    client.set_retry_policy(RetryPolicy {
+        max_attempts: controller_config.rpc.retry.max_attempts,
+        initial_delay: controller_config.rpc.retry.initial_delay,
+        max_delay: controller_config.rpc.retry.max_delay,
+    });
+
+    Ok(client)
+}
 
+fn run() -> GenericResult<i32> {
     let args = cli_args::parse().map_err(|e| format!(
         "Command line arguments parsing error: {}", e))?;
 
-    let _logging = setup_logging(args.debug_level, args.error_mailer)?;
-    info!("Starting the daemon...");
+    // Logging is configured by the controller config, so it's loaded before logging initialization
+    let controller_config = load_controller_config(args.controller_config.as_deref())?;
+
+    // Must be done before any threads are spawned
+    if let Some(ref timezone) = controller_config.timezone {
+        util::time::set_timezone(timezone)?;
+    }
+
+    // The commands use the PID file to find the running daemon
+    let pid_file = args.pid_file.as_deref().filter(|_| matches!(args.command, Command::Daemon));
+
+    if let Some(path) = pid_file {
+        daemon::PidFile::check(path)?;
+    }
 
     let config = load_config(&args.config)?;
-    let rpc_url = get_rpc_url(&config);
-    debug!("Use RPC URL: {}.", rpc_url);
 
-    let mut client = transmissionrpc::TransmissionClient::new(&rpc_url);
-    if config.rpc_authentication_required {
-        client.set_authentication(&config.rpc_username, config.rpc_plain_password.as_ref().unwrap());
+    // Two controllers would race on copies and removals. The lock is inherited by the forked process.
+    let instance_lock_path = match controller_config.lock_file {
+        Some(ref path) => path.clone(),
+        None => daemon::InstanceLock::default_path(&get_rpc_url(&config, &controller_config)),
+    };
+    let (_instance_lock, daemon_is_running) = match args.command {
+        Command::Daemon => (Some(daemon::InstanceLock::acquire(&instance_lock_path)?), false),
+        Command::TorrentDone { .. } => match daemon::InstanceLock::try_acquire(&instance_lock_path)? {
+            Some(lock) => (Some(lock), false),
+            None => (None, true),
+        },
+        _ => (None, false),
+    };
+
+    // Must be done before logging initialization which spawns threads
+    if args.daemon {
+        daemon::daemonize(args.log_file.as_deref())?;
+    }
+
+    let logging = setup_logging(args.debug_level, &controller_config, args.dry_run, args.error_mailer)?;
+    debug!("Loaded config: {:?}", config);
+    debug!("Loaded controller config: {:?}", controller_config);
+
+    // The running daemon processes the downloaded torrents by itself, so it's only woken up to not wait for its next
+    // check
+    if daemon_is_running {
+        match commands::request_full_check(args.pid_file.as_deref(), controller_config.control_socket.as_deref()) {
+            Ok(()) => info!("The controller daemon is running: it has been asked to process the torrent right away."),
+            Err(e) => warn!(
+                "The controller daemon is running, but can't be woken up: {}. Leaving the torrent to its next check.",
+                e),
+        }
+        return Ok(0);
+    }
+
+    let _pid_file = match pid_file {
+        Some(path) => Some(daemon::PidFile::create(path)?),
+        None => None,
+    };
+
+    if let Command::Daemon = args.command {
+        let dry_run = if args.dry_run { " in dry run mode" } else { "" };
+        if args.once {
+            info!("Running a single processing cycle{}...", dry_run);
+        } else {
+            info!("Starting the daemon{}...", dry_run);
+        }
     }
 
+    let runtime = Runtime::new().map_err(|e| format!("Unable to create async runtime: {}", e))?;
+    let client = create_client(&config, &controller_config, args.dry_run)?;
+    let blocking_client = blocking::TorrentClient::new(client.clone(), runtime.handle().clone());
+
+    match args.command {
+        Command::Daemon => {},
+        Command::Rename { ref hash, ref name } => {
+            commands::rename(&blocking_client, &controller_config, hash, name.as_deref())?;
+            return Ok(0);
+        },
+        Command::Reannounce { ref hashes } => {
+            commands::reannounce(&blocking_client, hashes)?;
+            return Ok(0);
+        },
+        Command::Verify { ref hashes, wait } => {
+            commands::verify(&blocking_client, hashes, wait)?;
+            return Ok(0);
+        },
+        Command::TorrentDone { .. } => {},
+        Command::Status { json } => {
+            commands::status(&blocking_client, &controller_config, Path::new(&config.download_dir), json)?;
+            return Ok(0);
+        },
+        Command::List { json } => {
+            commands::list(&blocking_client, &controller_config, Path::new(&config.download_dir), json)?;
+            return Ok(0);
+        },
+        Command::Pause { ref hashes } => {
+            commands::pause(&blocking_client, hashes)?;
+            return Ok(0);
+        },
+        Command::Resume { ref hashes } => {
+            commands::resume(&blocking_client, hashes)?;
+            return Ok(0);
+        },
+        Command::Cleanup => {
+            commands::cleanup(args.pid_file.as_deref(), controller_config.control_socket.as_deref())?;
+            return Ok(0);
+        },
+        Command::Control { ref command } => {
+            commands::control(controller_config.control_socket.as_deref(), command)?;
+            return Ok(0);
+        },
+        Command::History { period, json } => {
+            commands::history(&controller_config, period, json)?;
+            return Ok(0);
+        },
+        Command::Restore { ref backup } => {
+            commands::restore(&controller_config, backup.as_deref())?;
+            return Ok(0);
+        },
+        Command::Tui => {
+            tui::run(&blocking_client, &controller_config, Path::new(&config.download_dir))?;
+            return Ok(0);
+        },
+        Command::RenderTemplate { ref path, ref params } => {
+            commands::render_template(path, params)?;
+            return Ok(0);
+        },
+    }
+
+    let removal_grace_period = controller_config.removal.grace_period.is_some() ||
+        controller_config.policies.iter().any(|policy| policy.removal_grace_period.is_some());
+
+    // Alternative speed mode is used as manual mode indicator when the controller manages torrents by schedule
+    if args.action.is_some() && controller_config.speed_schedule.iter().any(|rule| rule.alt_speed) {
+        return Err!("Speed schedule with alternative speed limits can't be used with --action option");
+    }
+
+    if removal_grace_period && args.copy_to.is_none() {
+        return Err!("Removal grace period can be used only with --copy-to option");
+    }
+
+    let mut notifier = Notifier::new(args.notifications_mailer);
+    notifier.set_dry_run(args.dry_run);
+
+    let telegram = TelegramBot::new(&controller_config.telegram);
+    let mqtt = runtime.block_on(async { MqttPublisher::new(&controller_config.mqtt) }).map(Arc::new);
+    notifier.set_channels(
+        &controller_config.notifications, telegram.clone(), mqtt.clone(), runtime.handle().clone());
+    let notifier = Arc::new(notifier);
+
     let mut controller = controller::Controller::new(
-        client, args.action, args.action_periods,
-        PathBuf::from(&config.download_dir), args.copy_to, args.move_to,
-        args.seed_time_limit, args.upload_ratio_limit, args.free_space_threshold,
-        args.notifications_mailer, args.torrent_downloaded_email_template);
+        client, blocking_client, &controller_config, controller::ControllerOptions {
+            action: args.action,
+            action_periods: args.action_periods,
+            download_dir: PathBuf::from(&config.download_dir),
+            copy_to: args.copy_to,
+            move_to: args.move_to,
+            seed_time_limit: args.seed_time_limit,
+            upload_ratio_limit: args.upload_ratio_limit,
+            free_space_threshold: args.free_space_threshold,
+            torrent_downloaded_email_template: args.torrent_downloaded_email_template,
+        }, notifier.clone());
+
+    // The controller is dropped outside of the runtime: it waits for the consumer thread which may use the runtime
+    if let Command::TorrentDone { ref hash } = args.command {
+        runtime.block_on(controller.process_downloaded(hash))?;
+        return Ok(0);
+    }
+
+    let result = if args.once {
+        runtime.block_on(run_once(&mut controller))
+    } else {
+        runtime.block_on(run_daemon(
+            &mut controller, &controller_config, args.controller_config.as_deref(), telegram.as_ref(), mqtt.as_deref(),
+            &notifier, &logging))
+    };
 
-    let tick = chan::tick_ms(5000);
+    // New torrents aren't scheduled anymore, but the in-flight copies are allowed to finish
+    controller.shutdown(controller_config.shutdown_timeout.unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT));
+
+    result
+}
+
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+async fn run_daemon(
+    controller: &mut controller::Controller, config: &ControllerConfig, config_path: Option<&Path>,
+    telegram: Option<&Arc<TelegramBot>>, mqtt: Option<&MqttPublisher>, notifier: &Arc<Notifier>,
+    logging: &logging::LoggerGuard,
+) -> GenericResult<i32> {
+    let mut http = http::start_server(&config.http).await?;
+    let mut bot = telegram.and_then(|bot| bot.listen());
+    let mut control = match config.control_socket {
+        Some(ref path) => Some(control::ControlServer::start(path)?),
+        None => None,
+    };
+
+    let handle_signal = |kind| signal(kind).map_err(|e| format!("Unable to set up signal handler: {}", e));
+    let mut sigint = handle_signal(SignalKind::interrupt())?;
+    let mut sigterm = handle_signal(SignalKind::terminate())?;
+    let mut sigquit = handle_signal(SignalKind::quit())?;
+    let mut sigusr1 = handle_signal(SignalKind::user_defined1())?;
+    let mut sigusr2 = handle_signal(SignalKind::user_defined2())?;
+    let mut sighup = handle_signal(SignalKind::hangup())?;
+
+    let mut service = systemd::ServiceNotifier::new();
+    let mut availability = AvailabilityMonitor::new(config.availability.clone(), notifier.clone());
     let start_time = Instant::now();
 
-    loop {
-        if let Err(e) = controller.control() {
-            // Transmission RPC may not respond for some time after startup. Increase the severity
-            // of error messages to not send emails after each reboot.
-            if start_time.elapsed().as_secs() < 60 {
+    'main: loop {
+        let cycle_start_time = Instant::now();
+        let result = controller.control().await;
+        METRICS.set_cycle_duration(cycle_start_time.elapsed());
+
+        if let Some(ref http) = http {
+            http.on_cycle(result.as_ref().map(|_| ()));
+        }
+
+        // Any response means that the daemon is alive
+        let unavailable = result.as_ref().err().filter(|e| e.is_transient()).map(|e| e.to_string());
+        availability.update(unavailable.as_deref());
+
+        if let Err(e) = result {
+            METRICS.on_rpc_error();
+            service.status(e.to_string());
+            if e.is_fatal() {
+                return Err(e.into());
+            }
+
+            // Transmission RPC may not respond for some time after startup or while it's restarting. Decrease the
+            // severity of such error messages to not send emails after each reboot.
+            if e.is_transient() || start_time.elapsed().as_secs() < 60 {
                 warn!("{}.", e)
             } else {
                 error!("{}.", e)
             }
+        } else {
+            // Transmission has been reached successfully
+            service.ready();
+            service.status(controller.status());
+
+            if let Some(mqtt) = mqtt {
+                mqtt.publish_status(&controller.get_status_json());
+            }
         }
+        service.ping_watchdog();
+
+        let fast = config.poll.fast_interval.is_some() && controller.is_active(config.poll.fast_eta);
+        let next_check = tokio::time::sleep(get_poll_delay(&config.poll, fast, service.watchdog_interval()));
+        tokio::pin!(next_check);
+
+        // Wait for the next check, handling the requests which don't require it
+        loop {
+            tokio::select! {
+                _ = sigint.recv() => {},
+                _ = sigterm.recv() => {},
+                _ = sigquit.recv() => {},
+                _ = sigusr1.recv() => {
+                    info!("Got SIGUSR1 signal. Running a full check...");
+                    controller.request_full_check();
+                    continue 'main;
+                },
+                _ = sigusr2.recv() => {
+                    logging.reopen();
+                    info!("Got SIGUSR2 signal. The log file has been reopened.");
+                    continue;
+                },
+                _ = sighup.recv() => {
+                    info!("Got SIGHUP signal. Reloading the configuration...");
+                    if let Err(e) = reload_config(controller, config_path, logging) {
+                        error!("Failed to reload the configuration: {}.", e);
+                    }
+                    continue 'main;
+                },
+                request = get_api_request(&mut http) => {
+                    // The next check applies the changes right away
+                    let result = controller.handle_api_command(&request.command).await;
+                    request.reply(result);
+                    continue 'main;
+                },
+                request = get_control_request(&mut control) => {
+                    let result = handle_control_command(controller, config_path, logging, &request.command).await;
+                    let read_only = request.command.is_read_only();
+                    request.reply(result);
+
+                    if read_only {
+                        continue;
+                    }
+                    continue 'main;
+                },
+                request = get_bot_request(&mut bot) => {
+                    let result = controller.handle_bot_command(&request.command).await;
+                    let read_only = matches!(
+                        request.command, telegram::BotCommand::Status | telegram::BotCommand::List);
+                    request.reply(result);
 
-        chan_select! {
-            signal_channel.recv() => {
-                info!("Got a termination UNIX signal. Exiting...");
-                break;
-            },
-            tick.recv() => {}
+                    if read_only {
+                        continue;
+                    }
+                    continue 'main;
+                },
+                _ = &mut next_check => continue 'main,
+            }
+
+            info!("Got a termination UNIX signal. Exiting...");
+            service.stopping();
+            service.extend_timeout(config.shutdown_timeout.unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT));
+            break 'main;
         }
     }
 
     Ok(0)
 }
 
+/// Runs a single processing cycle for cron. Returns 2 if some of the scheduled torrents have failed to be consumed.
+async fn run_once(controller: &mut controller::Controller) -> GenericResult<i32> {
+    let cycle_start_time = Instant::now();
+    controller.control().await?;
+
+    let failed = controller.wait_for_consuming().await;
+    info!("The processing cycle has finished in {}.",
+          util::time::format_duration(cycle_start_time.elapsed().as_secs()));
+
+    if failed != 0 {
+        error!("{} torrent(s) have failed to be consumed.", failed);
+        return Ok(2);
+    }
+
+    Ok(0)
+}
+
+/// The fast poll interval is used while something is about to happen. The delay never exceeds the systemd watchdog
+/// interval.
+fn get_poll_delay(config: &PollConfig, fast: bool, watchdog_interval: Option<Duration>) -> Duration {
+    let mut delay = match config.fast_interval {
+        Some(interval) if fast => interval,
+        _ => config.interval,
+    };
+
+    if !config.jitter.is_zero() {
+        delay += config.jitter.mul_f64(rand::thread_rng().gen_range(0.0..=1.0));
+    }
+
+    match watchdog_interval {
+        Some(interval) => delay.min(interval),
+        None => delay,
+    }
+}
+
+async fn handle_control_command(
+    controller: &mut controller::Controller, config_path: Option<&Path>, logging: &logging::LoggerGuard,
+    command: &control::ControlCommand,
+) -> GenericResult<String> {
+    use control::ControlCommand;
+
+    Ok(match *command {
+        ControlCommand::Status => controller.status(),
+        ControlCommand::Dashboard => controller.get_dashboard().to_string(),
+        ControlCommand::ProcessNow => {
+            info!("Running a full check on control request...");
+            controller.request_full_check();
+            s!("A full check has been scheduled")
+        },
+        ControlCommand::ReloadConfig => {
+            reload_config(controller, config_path, logging)?;
+            s!("The configuration has been reloaded")
+        },
+        ControlCommand::PauseProcessing => {
+            info!("Pausing processing on control request.");
+            controller.pause_processing();
+            s!("Processing has been paused")
+        },
+        ControlCommand::ResumeProcessing => {
+            info!("Resuming processing on control request.");
+            controller.resume_processing();
+            s!("Processing has been resumed")
+        },
+        ControlCommand::Copy(ref hash) => {
+            match controller.handle_api_command(&http::ApiCommand::Copy(hash.clone())).await {
+                Ok(result) => result["message"].as_str().unwrap_or_default().to_owned(),
+                Err(http::ApiError::NotFound(e) | http::ApiError::Conflict(e) | http::ApiError::Failed(e)) => {
+                    return Err(e.into());
+                },
+            }
+        },
+    })
+}
+
+/// Applies the settings which can be changed without restart: the torrent rules and the log levels
+fn reload_config(
+    controller: &mut controller::Controller, config_path: Option<&Path>, logging: &logging::LoggerGuard,
+) -> EmptyResult {
+    let path = config_path.ok_or("The daemon has been started without controller configuration file")?;
+    let config = load_controller_config(Some(path))?;
+
+    logging.set_filter(get_log_filter(&config)?);
+    controller.reload_config(&config);
+    info!("The controller configuration has been reloaded.");
+
+    Ok(())
+}
+
+async fn get_api_request(http: &mut Option<http::HttpServer>) -> http::ApiRequest {
+    match http {
+        Some(http) => http.api_request().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn get_control_request(control: &mut Option<control::ControlServer>) -> control::ControlRequest {
+    match control {
+        Some(control) => control.request().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn get_bot_request(bot: &mut Option<telegram::BotListener>) -> telegram::BotRequest {
+    match bot {
+        Some(bot) => bot.request().await,
+        None => std::future::pending().await,
+    }
+}
+
 fn main() {
-    let exit_code = match daemon() {
+    let exit_code = match run() {
         Ok(code) => code,
         Err(err) => {
             let _ = writeln!(&mut std::io::stderr(), "Error: {}.", err);
@@ -138,3 +651,33 @@ fn main() {
 
     process::exit(exit_code);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_rpc_url() {
+        let mut config = Config {
+            download_dir: s!("/downloads"),
+            rpc_enabled: true,
+            rpc_bind_address: String::new(),
+            rpc_port: 9091,
+            rpc_authentication_required: false,
+            rpc_url: s!("/transmission/"),
+            rpc_username: String::new(),
+            rpc_plain_password: None,
+        };
+        let controller_config = ControllerConfig::default();
+
+        for (address, url) in [
+            ("::", "http://[::]:9091/transmission/rpc"),
+            ("::1", "http://[::1]:9091/transmission/rpc"),
+            ("127.0.0.1", "http://127.0.0.1:9091/transmission/rpc"),
+            ("transmission.local", "http://transmission.local:9091/transmission/rpc"),
+        ] {
+            config.rpc_bind_address = s!(address);
+            assert_eq!(get_rpc_url(&config, &controller_config), url);
+        }
+    }
+}
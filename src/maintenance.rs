@@ -0,0 +1,173 @@
+//! Maintenance windows (nightly backups of the storage, for example) during which the torrents aren't processed: no
+//! copies, removals or other file operations are performed, and optionally all torrents are paused.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::config;
+use crate::torrent_client::{self, TorrentClient};
+use crate::transmissionrpc::{TorrentFields, TorrentStatus};
+use crate::util::time::{self, WeekPeriods};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct MaintenanceConfig {
+    /// Time periods in D[-D]/HH:MM-HH:MM format
+    #[serde(deserialize_with = "config::deserialize_optional_periods")]
+    pub periods: Option<WeekPeriods>,
+    /// The maintenance is in progress while the file exists (created by the backup script, for example)
+    pub lock_file: Option<PathBuf>,
+    /// Pause all torrents during the maintenance
+    pub pause_torrents: bool,
+}
+
+impl MaintenanceConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.periods.is_some() || self.lock_file.is_some()
+    }
+}
+
+pub struct Maintenance {
+    config: MaintenanceConfig,
+    active: bool,
+    /// The torrents which have been paused by us
+    paused: HashSet<String>,
+}
+
+impl Maintenance {
+    pub fn new(config: MaintenanceConfig) -> Maintenance {
+        Maintenance {
+            config: config,
+            active: false,
+            paused: HashSet::new(),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Checks whether the maintenance is in progress pausing or resuming the torrents on its state changes. Returns
+    /// false if the torrents mustn't be processed.
+    pub async fn control(&mut self, client: &dyn TorrentClient) -> torrent_client::Result<bool> {
+        let Some(reason) = self.check() else {
+            if self.active {
+                info!("Maintenance has finished. Resuming processing...");
+                self.resume(client).await?;
+                self.active = false;
+                self.paused.clear();
+            }
+            return Ok(true);
+        };
+
+        if !self.active {
+            info!("Maintenance has started ({}). Suspending processing...", reason);
+            self.active = true;
+        }
+
+        if self.config.pause_torrents {
+            // The torrents which have been started during the maintenance are paused as well
+            for torrent in client.get_torrents(TorrentFields::basic()).await? {
+                if torrent.status != TorrentStatus::Stopped {
+                    info!(hash = torrent.hash.as_str(), action = "pause";
+                          "Pausing '{}' torrent: maintenance is in progress...", torrent.name);
+                    client.stop(&torrent.hash).await?;
+                    self.paused.insert(torrent.hash);
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    async fn resume(&self, client: &dyn TorrentClient) -> torrent_client::EmptyResult {
+        if self.paused.is_empty() {
+            return Ok(());
+        }
+
+        for torrent in client.get_torrents(TorrentFields::basic()).await? {
+            if self.paused.contains(&torrent.hash) && torrent.status == TorrentStatus::Stopped {
+                info!(hash = torrent.hash.as_str(), action = "resume"; "Resuming '{}' torrent...", torrent.name);
+                client.start(&torrent.hash).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the reason of the maintenance if it's in progress
+    fn check(&self) -> Option<String> {
+        if let Some(ref path) = self.config.lock_file {
+            if let Some(reason) = check_lock_file(path) {
+                return Some(reason);
+            }
+        }
+
+        if self.config.periods.as_ref().is_some_and(time::is_now_in) {
+            return Some(s!("scheduled maintenance window"));
+        }
+
+        None
+    }
+}
+
+fn check_lock_file(path: &Path) -> Option<String> {
+    match path.try_exists() {
+        Ok(true) => Some(format!("{} exists", path.display())),
+        Ok(false) => None,
+        // Better to be safe with the storage which may be unavailable at the moment
+        Err(e) => {
+            error!("Unable to check '{}' maintenance lock file: {}.", path.display(), e);
+            Some(format!("unable to check {}", path.display()))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    #[test]
+    fn test_lock_file() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+        let client = server.client();
+
+        server.add_torrent(MockTorrent::new(1, "seeding", "/downloads"));
+        let mut torrent = MockTorrent::new(2, "stopped", "/downloads");
+        torrent.status = TorrentStatus::Stopped;
+        server.add_torrent(torrent);
+
+        let temp_dir = TempDir::new().unwrap();
+        let lock_file = temp_dir.path().join("backup.lock");
+        let statuses = || server.state().torrents.iter().map(|torrent| torrent.status).collect::<Vec<_>>();
+
+        let mut maintenance = Maintenance::new(MaintenanceConfig {
+            lock_file: Some(lock_file.clone()),
+            pause_torrents: true,
+            ..Default::default()
+        });
+
+        assert!(runtime.block_on(maintenance.control(&client)).unwrap());
+        assert!(!maintenance.is_active());
+
+        fs::write(&lock_file, "").unwrap();
+        assert!(!runtime.block_on(maintenance.control(&client)).unwrap());
+        assert!(maintenance.is_active());
+        assert_eq!(statuses(), vec![TorrentStatus::Stopped; 2]);
+
+        fs::remove_file(&lock_file).unwrap();
+        assert!(runtime.block_on(maintenance.control(&client)).unwrap());
+        assert!(!maintenance.is_active());
+        assert_eq!(statuses(), vec![TorrentStatus::Seeding, TorrentStatus::Stopped]);
+    }
+}
@@ -0,0 +1,259 @@
+//! Verification of the copied torrent data against the piece hashes from the torrent's .torrent file
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::common::GenericResult;
+use crate::util::sha1::{self, Sha1};
+
+pub struct Pieces {
+    length: u64,
+    hashes: Vec<[u8; sha1::DIGEST_SIZE]>,
+}
+
+impl Pieces {
+    pub fn read<P: AsRef<Path>>(path: P) -> GenericResult<Pieces> {
+        let path = path.as_ref();
+        let data = fs::read(path).map_err(|e| format!("Unable to read '{}': {}", path.display(), e))?;
+        Pieces::parse(&data).map_err(|e| format!("Invalid '{}' torrent file: {}", path.display(), e).into())
+    }
+
+    fn parse(data: &[u8]) -> GenericResult<Pieces> {
+        let (metainfo, _) = decode(data)?;
+
+        let info = match metainfo {
+            Value::Dict(mut metainfo) => metainfo.remove(b"info".as_slice()),
+            _ => None,
+        };
+        let Some(Value::Dict(info)) = info else {
+            return Err!("there is no info dictionary");
+        };
+
+        let length = match info.get(b"piece length".as_slice()) {
+            Some(&Value::Int(length)) if length > 0 => length as u64,
+            _ => return Err!("invalid piece length"),
+        };
+
+        // BitTorrent v2-only torrents have per-file Merkle trees instead
+        let hashes = match info.get(b"pieces".as_slice()) {
+            Some(Value::Bytes(hashes)) if hashes.len() % sha1::DIGEST_SIZE == 0 => {
+                hashes.chunks_exact(sha1::DIGEST_SIZE).map(|hash| hash.try_into().unwrap()).collect()
+            },
+            Some(_) => return Err!("invalid piece hashes"),
+            None => return Err!("there are no v1 piece hashes"),
+        };
+
+        Ok(Pieces {
+            length: length,
+            hashes: hashes,
+        })
+    }
+
+    /// Verifies the pieces which are fully covered by the copied files. `files` are the torrent's files in the
+    /// metainfo order: their sizes and paths to the copies (None for the files which haven't been copied). Returns
+    /// the copies which belong to the corrupted pieces.
+    pub fn verify(&self, files: &[(u64, Option<PathBuf>)]) -> GenericResult<Vec<PathBuf>> {
+        let total_size: u64 = files.iter().map(|&(size, _)| size).sum();
+        if total_size.div_ceil(self.length) != self.hashes.len() as u64 {
+            return Err!("The torrent's files don't match its piece hashes");
+        }
+
+        let mut corrupted: Vec<usize> = Vec::new();
+        for (index, (size, path)) in files.iter().enumerate() {
+            if let Some(path) = path {
+                let copy_size = fs::metadata(path).map_err(|e| format!(
+                    "Failed to stat() '{}': {}", path.display(), e))?.len();
+                if copy_size != *size {
+                    corrupted.push(index);
+                }
+            }
+        }
+
+        let mut file_offsets = Vec::with_capacity(files.len());
+        let mut offset = 0;
+        for &(size, _) in files {
+            file_offsets.push(offset);
+            offset += size;
+        }
+
+        let mut first_file = 0;
+        let mut opened: Option<(usize, File)> = None;
+        let mut data = vec![0; self.length as usize];
+
+        for (piece, hash) in self.hashes.iter().enumerate() {
+            let start = piece as u64 * self.length;
+            let end = (start + self.length).min(total_size);
+
+            while file_offsets[first_file] + files[first_file].0 <= start {
+                first_file += 1;
+            }
+
+            let piece_files: Vec<usize> = (first_file..files.len())
+                .take_while(|&index| file_offsets[index] < end)
+                .filter(|&index| files[index].0 != 0)
+                .collect();
+
+            if piece_files.iter().any(|index| files[*index].1.is_none() || corrupted.contains(index)) {
+                continue;
+            }
+
+            let mut hasher = Sha1::new();
+
+            for &index in &piece_files {
+                let path = files[index].1.as_ref().unwrap();
+                let file_start = file_offsets[index].max(start);
+                let file_end = (file_offsets[index] + files[index].0).min(end);
+                let data = &mut data[..(file_end - file_start) as usize];
+
+                let file = match opened {
+                    Some((opened_index, ref mut file)) if opened_index == index => file,
+                    _ => {
+                        let file = File::open(path).map_err(|e| format!(
+                            "Failed to open '{}': {}", path.display(), e))?;
+                        &mut opened.insert((index, file)).1
+                    },
+                };
+
+                file.seek(SeekFrom::Start(file_start - file_offsets[index]))
+                    .and_then(|_| file.read_exact(data))
+                    .map_err(|e| format!("Failed to read '{}': {}", path.display(), e))?;
+
+                hasher.update(data);
+            }
+
+            // The corrupted data can't be attributed to one of the files sharing the piece
+            if hasher.finish() != *hash {
+                for index in piece_files {
+                    if !corrupted.contains(&index) {
+                        corrupted.push(index);
+                    }
+                }
+            }
+        }
+
+        corrupted.sort();
+        Ok(corrupted.into_iter().map(|index| files[index].1.clone().unwrap()).collect())
+    }
+}
+
+enum Value<'a> {
+    Int(i64),
+    Bytes(&'a [u8]),
+    /// The lists' contents aren't needed
+    List,
+    Dict(BTreeMap<&'a [u8], Value<'a>>),
+}
+
+/// Decodes a bencoded value returning it and the rest of the data
+fn decode(data: &[u8]) -> GenericResult<(Value<'_>, &[u8])> {
+    let read_until = |data: &[u8], end: u8| -> GenericResult<(i64, usize)> {
+        let position = data.iter().position(|&byte| byte == end).ok_or("unexpected end of data")?;
+        let value = std::str::from_utf8(&data[..position]).ok().and_then(|value| value.parse().ok())
+            .ok_or("invalid integer")?;
+        Ok((value, position + 1))
+    };
+
+    Ok(match data.first() {
+        Some(b'i') => {
+            let (value, size) = read_until(&data[1..], b'e')?;
+            (Value::Int(value), &data[1 + size..])
+        },
+        Some(b'l') => {
+            let mut data = &data[1..];
+
+            while data.first() != Some(&b'e') {
+                let (_, rest) = decode(data)?;
+                data = rest;
+            }
+
+            (Value::List, &data[1..])
+        },
+        Some(b'd') => {
+            let mut dict = BTreeMap::new();
+            let mut data = &data[1..];
+
+            while data.first() != Some(&b'e') {
+                let Ok((Value::Bytes(key), rest)) = decode(data) else {
+                    return Err!("invalid dictionary key");
+                };
+                let (value, rest) = decode(rest)?;
+                dict.insert(key, value);
+                data = rest;
+            }
+
+            (Value::Dict(dict), &data[1..])
+        },
+        Some(b'0'..=b'9') => {
+            let (size, position) = read_until(data, b':')?;
+            let data = &data[position..];
+            if size < 0 || size as usize > data.len() {
+                return Err!("unexpected end of data");
+            }
+            (Value::Bytes(&data[..size as usize]), &data[size as usize..])
+        },
+        Some(_) => return Err!("invalid data"),
+        None => return Err!("unexpected end of data"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn hash(data: &[u8]) -> [u8; sha1::DIGEST_SIZE] {
+        let mut hasher = Sha1::new();
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_verification() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path();
+
+        // Files: a (6 bytes), b (0 bytes), c (5 bytes), d (4 bytes) with 4-byte pieces
+        let data = b"aaaaaacccccdddd";
+        let hashes: Vec<u8> = data.chunks(4).flat_map(hash).collect();
+
+        let mut metainfo = b"d8:announce3:url13:announce-listll3:urlee".to_vec();
+        metainfo.extend(b"4:infod6:lengthi15e12:piece lengthi4e6:pieces80:");
+        metainfo.extend(&hashes);
+        metainfo.extend(b"ee");
+
+        let torrent_path = path.join("test.torrent");
+        fs::write(&torrent_path, &metainfo).unwrap();
+        let pieces = Pieces::read(&torrent_path).unwrap();
+        assert_eq!(pieces.hashes.len(), 4);
+
+        fs::write(path.join("a"), b"aaaaaa").unwrap();
+        fs::write(path.join("b"), b"").unwrap();
+        fs::write(path.join("c"), b"ccccc").unwrap();
+        fs::write(path.join("d"), b"dddd").unwrap();
+
+        let files = |names: &[&str]| -> Vec<(u64, Option<PathBuf>)> {
+            [("a", 6), ("b", 0), ("c", 5), ("d", 4)].iter().map(|&(name, size)| {
+                (size, if names.contains(&name) { Some(path.join(name)) } else { None })
+            }).collect()
+        };
+
+        assert_eq!(pieces.verify(&files(&["a", "b", "c", "d"])).unwrap(), Vec::<PathBuf>::new());
+
+        // The corrupted piece is shared by two files
+        fs::write(path.join("c"), b"ccCcc").unwrap();
+        assert_eq!(pieces.verify(&files(&["a", "b", "c", "d"])).unwrap(), vec![path.join("c"), path.join("d")]);
+        assert_eq!(pieces.verify(&files(&["a", "d"])).unwrap(), Vec::<PathBuf>::new());
+
+        fs::write(path.join("c"), b"cccc").unwrap();
+        assert_eq!(pieces.verify(&files(&["c"])).unwrap(), vec![path.join("c")]);
+
+        fs::write(path.join("a"), b"baaaaa").unwrap();
+        assert_eq!(pieces.verify(&files(&["a", "d"])).unwrap(), vec![path.join("a")]);
+
+        assert!(Pieces::parse(b"d4:infod6:lengthi15eee").is_err());
+        assert!(Pieces::parse(b"d4:infod12:piece lengthi4e6:pieces3:abcee").is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! Prometheus metrics (served by the HTTP listener)
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::disk_space::DiskSpace;
+use crate::traffic::Traffic;
+use crate::transmissionrpc::Torrent;
+
+pub static METRICS: Metrics = Metrics::new();
+
+pub struct Metrics {
+    enabled: AtomicBool,
+    bytes_copied: AtomicU64,
+    copy_failures: AtomicU64,
+    notifications_sent: AtomicU64,
+    rpc_errors: AtomicU64,
+    state: Mutex<State>,
+}
+
+struct State {
+    torrents: BTreeMap<String, u64>,
+    free_space: BTreeMap<PathBuf, DiskSpace>,
+    tracker_traffic: BTreeMap<String, Traffic>,
+    cycle_duration: Option<Duration>,
+}
+
+impl Metrics {
+    const fn new() -> Metrics {
+        Metrics {
+            enabled: AtomicBool::new(false),
+            bytes_copied: AtomicU64::new(0),
+            copy_failures: AtomicU64::new(0),
+            notifications_sent: AtomicU64::new(0),
+            rpc_errors: AtomicU64::new(0),
+            state: Mutex::new(State {
+                torrents: BTreeMap::new(),
+                free_space: BTreeMap::new(),
+                tracker_traffic: BTreeMap::new(),
+                cycle_duration: None,
+            }),
+        }
+    }
+
+    pub fn enable(&self) {
+        self.enabled.store(true, Ordering::Relaxed);
+    }
+
+    /// Allows to skip collection of the metrics which are expensive to get
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn on_copied(&self, size: u64) {
+        self.bytes_copied.fetch_add(size, Ordering::Relaxed);
+    }
+
+    pub fn on_copy_failure(&self) {
+        self.copy_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn on_notification(&self) {
+        self.notifications_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn on_rpc_error(&self) {
+        self.rpc_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get_copy_failures(&self) -> u64 {
+        self.copy_failures.load(Ordering::Relaxed)
+    }
+
+    pub fn get_rpc_errors(&self) -> u64 {
+        self.rpc_errors.load(Ordering::Relaxed)
+    }
+
+    pub fn set_torrents(&self, torrents: &[Torrent]) {
+        let mut statuses = BTreeMap::new();
+        for torrent in torrents {
+            *statuses.entry(torrent.status.to_string()).or_insert(0) += 1;
+        }
+        self.state.lock().unwrap().torrents = statuses;
+    }
+
+    pub fn set_free_space(&self, path: &Path, space: DiskSpace) {
+        self.state.lock().unwrap().free_space.insert(path.to_owned(), space);
+    }
+
+    pub fn get_free_space(&self) -> Vec<(PathBuf, DiskSpace)> {
+        self.state.lock().unwrap().free_space.iter().map(|(path, &space)| (path.clone(), space)).collect()
+    }
+
+    pub fn set_tracker_traffic(&self, traffic: &BTreeMap<String, Traffic>) {
+        self.state.lock().unwrap().tracker_traffic.clone_from(traffic);
+    }
+
+    pub fn get_tracker_traffic(&self) -> BTreeMap<String, Traffic> {
+        self.state.lock().unwrap().tracker_traffic.clone()
+    }
+
+    pub fn set_cycle_duration(&self, duration: Duration) {
+        self.state.lock().unwrap().cycle_duration = Some(duration);
+    }
+
+    /// Renders the metrics in Prometheus text format
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, values: &[(String, String)]| {
+            let _ = writeln!(output, "# HELP transmission_controller_{} {}", name, help);
+            let _ = writeln!(output, "# TYPE transmission_controller_{} {}", name, kind);
+            for (labels, value) in values {
+                let _ = writeln!(output, "transmission_controller_{}{} {}", name, labels, value);
+            }
+        };
+
+        let counter = |value: &AtomicU64| vec![(String::new(), value.load(Ordering::Relaxed).to_string())];
+        metric("bytes_copied_total", "counter", "Bytes copied to the destinations.", &counter(&self.bytes_copied));
+        metric("copy_failures_total", "counter", "Failed copy attempts.", &counter(&self.copy_failures));
+        metric("notifications_sent_total", "counter", "Notifications sent.", &counter(&self.notifications_sent));
+        metric("rpc_errors_total", "counter", "Failed processing cycles due to RPC errors.",
+               &counter(&self.rpc_errors));
+
+        let state = self.state.lock().unwrap();
+
+        metric("torrents", "gauge", "Managed torrents by status.", &state.torrents.iter().map(|(status, count)| {
+            (format!("{{status=\"{}\"}}", status), count.to_string())
+        }).collect::<Vec<_>>());
+
+        metric("free_space_bytes", "gauge", "Free space on the download and destination directories.",
+               &state.free_space.iter().map(|(path, space)| {
+                   (format!("{{path=\"{}\"}}", escape_label(&path.to_string_lossy())), space.free.to_string())
+               }).collect::<Vec<_>>());
+
+        let tracker_traffic = |get: fn(&Traffic) -> u64| state.tracker_traffic.iter().map(|(host, traffic)| {
+            (format!("{{tracker=\"{}\"}}", escape_label(host)), get(traffic).to_string())
+        }).collect::<Vec<_>>();
+        metric("tracker_uploaded_bytes_total", "counter", "Bytes uploaded by tracker since the daemon start.",
+               &tracker_traffic(|traffic| traffic.uploaded));
+        metric("tracker_downloaded_bytes_total", "counter", "Bytes downloaded by tracker since the daemon start.",
+               &tracker_traffic(|traffic| traffic.downloaded));
+
+        if let Some(duration) = state.cycle_duration {
+            metric("cycle_duration_seconds", "gauge", "Duration of the last processing cycle.",
+                   &[(String::new(), format!("{:.3}", duration.as_secs_f64()))]);
+        }
+
+        output
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.on_copied(1024);
+        metrics.on_copy_failure();
+        metrics.set_free_space(Path::new("/downloads"), DiskSpace {free: 4096, total: None});
+        metrics.set_tracker_traffic(&BTreeMap::from([
+            (s!("tracker.example.com"), Traffic {uploaded: 2048, downloaded: 512}),
+        ]));
+        metrics.set_cycle_duration(Duration::from_millis(1500));
+
+        let output = metrics.render();
+        assert!(output.contains("# TYPE transmission_controller_bytes_copied_total counter\n"));
+        assert!(output.contains("\ntransmission_controller_bytes_copied_total 1024\n"));
+        assert!(output.contains("\ntransmission_controller_copy_failures_total 1\n"));
+        assert!(output.contains("\ntransmission_controller_free_space_bytes{path=\"/downloads\"} 4096\n"));
+        assert!(output.contains(
+            "\ntransmission_controller_tracker_uploaded_bytes_total{tracker=\"tracker.example.com\"} 2048\n"));
+        assert!(output.contains(
+            "\ntransmission_controller_tracker_downloaded_bytes_total{tracker=\"tracker.example.com\"} 512\n"));
+        assert!(output.contains("\ntransmission_controller_cycle_duration_seconds 1.500\n"));
+    }
+}
@@ -0,0 +1,336 @@
+//! Publishes the controller's events and status to an MQTT broker (Home Assistant, for example). Implements only the
+//! required subset of MQTT 3.1.1: QoS 0 publishing with the last will. TLS isn't supported.
+
+use std::io;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::common::{EmptyResult, GenericResult};
+
+const DEFAULT_PORT: u16 = 1883;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct MqttConfig {
+    /// Broker address in host[:port] format (publishing is disabled if not specified)
+    pub broker: Option<String>,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// The topics are: $prefix/availability, $prefix/status and $prefix/events
+    pub topic_prefix: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> MqttConfig {
+        MqttConfig {
+            broker: None,
+            client_id: s!("transmission-controller"),
+            username: None,
+            password: None,
+            topic_prefix: s!("transmission-controller"),
+        }
+    }
+}
+
+struct Message {
+    topic: String,
+    payload: String,
+    retain: bool,
+}
+
+/// The messages are published by a background task which reconnects to the broker on failures. The messages which
+/// are published while there is no connection are dropped.
+pub struct MqttPublisher {
+    topic_prefix: String,
+    messages: mpsc::UnboundedSender<Message>,
+}
+
+impl MqttPublisher {
+    /// Returns None if publishing isn't configured. Must be called from within the runtime.
+    pub fn new(config: &MqttConfig) -> Option<MqttPublisher> {
+        let broker = config.broker.as_ref()?;
+        let address = if broker.contains(':') { broker.clone() } else { format!("{}:{}", broker, DEFAULT_PORT) };
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let connection = Connection {
+            address: address,
+            config: config.clone(),
+            availability_topic: format!("{}/availability", config.topic_prefix),
+        };
+        tokio::spawn(connection.run(receiver));
+
+        Some(MqttPublisher {
+            topic_prefix: config.topic_prefix.clone(),
+            messages: sender,
+        })
+    }
+
+    /// The status is retained, so the subscribers get the latest one right away
+    pub fn publish_status(&self, status: &Value) {
+        self.publish("status", status, true);
+    }
+
+    pub fn publish_event(&self, event: &Value) {
+        self.publish("events", event, false);
+    }
+
+    fn publish(&self, topic: &str, payload: &Value, retain: bool) {
+        let _ = self.messages.send(Message {
+            topic: format!("{}/{}", self.topic_prefix, topic),
+            payload: payload.to_string(),
+            retain: retain,
+        });
+    }
+}
+
+struct Connection {
+    address: String,
+    config: MqttConfig,
+    availability_topic: String,
+}
+
+impl Connection {
+    async fn run(self, mut messages: mpsc::UnboundedReceiver<Message>) {
+        let mut connected = true;
+
+        loop {
+            let error = match self.connect().await {
+                Ok(stream) => {
+                    if !connected {
+                        info!("Connected to '{}' MQTT broker.", self.address);
+                    }
+                    connected = true;
+
+                    match self.serve(stream, &mut messages).await {
+                        Ok(()) => return,
+                        Err(e) => e,
+                    }
+                },
+                Err(e) => e,
+            };
+
+            // Report only the first failure of the outage
+            if connected {
+                warn!("MQTT connection to '{}' has failed: {}. Reconnecting...", self.address, error);
+                connected = false;
+            }
+
+            // Drop the messages which won't be delivered anyway
+            tokio::time::sleep(RECONNECT_DELAY).await;
+            while messages.try_recv().is_ok() {
+            }
+        }
+    }
+
+    async fn connect(&self) -> GenericResult<TcpStream> {
+        let mut stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(&self.address)).await
+            .map_err(|_| "connection timed out")??;
+
+        stream.write_all(&encode_connect(&self.config, &self.availability_topic)).await?;
+
+        let mut connack = [0; 4];
+        tokio::time::timeout(CONNECT_TIMEOUT, stream.read_exact(&mut connack)).await
+            .map_err(|_| "the broker hasn't responded")??;
+
+        match connack {
+            [0x20, 0x02, _, 0] => {},
+            [0x20, 0x02, _, 4 | 5] => return Err!("authentication failed"),
+            [0x20, 0x02, _, code] => return Err!("the broker has refused the connection with {} code", code),
+            _ => return Err!("got an invalid response from the broker"),
+        }
+
+        stream.write_all(&encode_publish(&self.availability_topic, ONLINE, true)).await?;
+        Ok(stream)
+    }
+
+    /// Returns Ok(()) when the publisher is dropped
+    async fn serve(&self, stream: TcpStream, messages: &mut mpsc::UnboundedReceiver<Message>) -> EmptyResult {
+        let (mut reader, mut writer) = stream.into_split();
+
+        // Reading isn't cancellation safe, so it's done by a separate task
+        let mut reader = AbortOnDrop(tokio::spawn(async move {
+            loop {
+                if let Err(e) = skip_packet(&mut reader).await {
+                    return e;
+                }
+            }
+        }));
+
+        let mut keep_alive = tokio::time::interval(KEEP_ALIVE / 2);
+        keep_alive.tick().await;
+
+        loop {
+            tokio::select! {
+                message = messages.recv() => {
+                    let Some(message) = message else {
+                        writer.write_all(&encode_publish(&self.availability_topic, OFFLINE, true)).await?;
+                        return Ok(());
+                    };
+                    writer.write_all(&encode_publish(&message.topic, &message.payload, message.retain)).await?;
+                },
+                _ = keep_alive.tick() => {
+                    writer.write_all(&[0xC0, 0x00]).await?; // PINGREQ
+                },
+                result = &mut reader.0 => return Err(result?),
+            }
+        }
+    }
+}
+
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Reads and ignores the broker's packets (PINGRESP)
+async fn skip_packet(reader: &mut OwnedReadHalf) -> EmptyResult {
+    let mut header = [0; 1];
+    if reader.read(&mut header).await? == 0 {
+        return Err!("the broker has closed the connection");
+    }
+
+    let mut size = 0;
+    for shift in (0..28).step_by(7) {
+        let byte = reader.read_u8().await?;
+        size |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            let mut payload = vec![0; size];
+            reader.read_exact(&mut payload).await?;
+            return Ok(());
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::InvalidData, "invalid packet length").into())
+}
+
+fn encode_connect(config: &MqttConfig, will_topic: &str) -> Vec<u8> {
+    // Clean session with retained QoS 0 last will
+    let mut flags = 0x02 | 0x04 | 0x20;
+    let mut packet = Vec::new();
+
+    encode_string(&mut packet, "MQTT");
+    packet.push(4); // Protocol level (3.1.1)
+    packet.push(0);
+    packet.extend_from_slice(&(KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+
+    encode_string(&mut packet, &config.client_id);
+    encode_string(&mut packet, will_topic);
+    encode_string(&mut packet, OFFLINE);
+
+    if let Some(ref username) = config.username {
+        flags |= 0x80;
+        encode_string(&mut packet, username);
+    }
+    if let Some(ref password) = config.password {
+        flags |= 0x40;
+        encode_string(&mut packet, password);
+    }
+    packet[7] = flags;
+
+    encode_packet(0x10, &packet)
+}
+
+fn encode_publish(topic: &str, payload: &str, retain: bool) -> Vec<u8> {
+    let mut packet = Vec::new();
+    encode_string(&mut packet, topic);
+    packet.extend_from_slice(payload.as_bytes());
+    encode_packet(if retain { 0x31 } else { 0x30 }, &packet)
+}
+
+fn encode_packet(header: u8, data: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+
+    let mut size = data.len();
+    loop {
+        let mut byte = (size % 128) as u8;
+        size /= 128;
+        if size != 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if size == 0 {
+            break;
+        }
+    }
+
+    packet.extend_from_slice(data);
+    packet
+}
+
+fn encode_string(packet: &mut Vec<u8>, string: &str) {
+    packet.extend_from_slice(&(string.len() as u16).to_be_bytes());
+    packet.extend_from_slice(string.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::net::TcpListener;
+    use tokio::runtime::Runtime;
+
+    use super::*;
+
+    #[test]
+    fn test_encoding() {
+        assert_eq!(encode_publish("a/b", "on", true), [0x31, 7, 0, 3, b'a', b'/', b'b', b'o', b'n']);
+        assert_eq!(&encode_packet(0x30, &[0; 200])[..3], [0x30, 0xC8, 0x01]);
+
+        let config = MqttConfig {
+            username: Some(s!("user")),
+            password: Some(s!("secret")),
+            ..Default::default()
+        };
+        let packet = encode_connect(&config, "t/availability");
+        assert_eq!(&packet[..12], [0x10, 74, 0, 4, b'M', b'Q', b'T', b'T', 4, 0xE6, 0, 60]);
+    }
+
+    #[test]
+    fn test_publishing() {
+        let runtime = Runtime::new().unwrap();
+
+        runtime.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let config = MqttConfig {
+                broker: Some(listener.local_addr().unwrap().to_string()),
+                topic_prefix: s!("seedbox"),
+                ..Default::default()
+            };
+
+            let publisher = MqttPublisher::new(&config).unwrap();
+            publisher.publish_status(&json!({"torrents": 1}));
+
+            let (mut connection, _) = listener.accept().await.unwrap();
+            let mut connect = vec![0; encode_connect(&config, "seedbox/availability").len()];
+            connection.read_exact(&mut connect).await.unwrap();
+            assert_eq!(connect, encode_connect(&config, "seedbox/availability"));
+            connection.write_all(&[0x20, 0x02, 0, 0]).await.unwrap();
+
+            let mut expected = encode_publish("seedbox/availability", "online", true);
+            expected.extend(encode_publish("seedbox/status", r#"{"torrents":1}"#, true));
+            expected.extend(encode_publish("seedbox/availability", "offline", true));
+
+            drop(publisher);
+
+            let mut data = vec![0; expected.len()];
+            connection.read_exact(&mut data).await.unwrap();
+            assert_eq!(data, expected);
+        });
+    }
+}
@@ -0,0 +1,518 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use time::OffsetDateTime;
+use tokio::runtime::Handle;
+
+use crate::common::EmptyResult;
+use crate::config;
+use crate::email::{EmailTemplate, Mailer};
+use crate::metrics::METRICS;
+use crate::mqtt::MqttPublisher;
+use crate::telegram::TelegramBot;
+use crate::transmissionrpc::Torrent;
+use crate::util::matching::HostPattern;
+use crate::util::time::{Timestamp, format_duration};
+use crate::webhooks::{Webhook, WebhookConfig};
+use crate::xmpp::{XmppClient, XmppConfig};
+
+const HISTORY_SIZE: usize = 20;
+
+/// Notification event types which the channels may be subscribed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Event {
+    /// A torrent has been downloaded (and copied)
+    Downloaded,
+    /// Torrents have been removed
+    Removed,
+    /// Free space is running low
+    FreeSpace,
+    /// Torrent, tracker, peer port or blocklist problems
+    Problem,
+    /// A problem has gone away
+    Recovered,
+    /// Statistics and orphaned files reports
+    Report,
+    /// A long download has crossed a progress threshold
+    Progress,
+}
+
+impl Event {
+    pub fn severity(self) -> Severity {
+        match self {
+            Event::Problem => Severity::Error,
+            Event::FreeSpace => Severity::Warning,
+            Event::Downloaded | Event::Removed | Event::Recovered | Event::Report | Event::Progress => Severity::Info,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct NotificationsConfig {
+    /// The events to send by email (all by default)
+    pub email_events: Option<Vec<Event>>,
+    pub webhooks: Vec<WebhookConfig>,
+    pub xmpp: Option<XmppConfig>,
+    pub routes: Vec<NotificationRoute>,
+}
+
+/// Routes the matching notifications to the specified channels. A channel which is mentioned by any route gets only
+/// the notifications routed to it, the other channels get all the notifications they are subscribed to.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct NotificationRoute {
+    /// email, telegram, xmpp, mqtt or webhook names
+    pub channels: Vec<String>,
+    pub events: Option<Vec<Event>>,
+    pub min_severity: Option<Severity>,
+
+    /// The torrent filters: the include ones match only the notifications about a single torrent
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub exclude_labels: Vec<String>,
+    #[serde(default)]
+    pub trackers: Vec<HostPattern>,
+    #[serde(default)]
+    pub exclude_trackers: Vec<HostPattern>,
+    /// Torrent size limits (MB)
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+
+    /// Collect the notifications and send them as a single digest with the specified interval
+    #[serde(default, deserialize_with = "config::deserialize_optional_duration")]
+    pub digest: Option<Duration>,
+}
+
+impl NotificationRoute {
+    fn matches(&self, event: Event, torrent: Option<&Torrent>) -> bool {
+        if self.events.as_ref().is_some_and(|events| !events.contains(&event)) ||
+            self.min_severity.is_some_and(|severity| event.severity() < severity) {
+            return false;
+        }
+
+        let Some(torrent) = torrent else {
+            return self.labels.is_empty() && self.trackers.is_empty() &&
+                self.min_size.is_none() && self.max_size.is_none();
+        };
+
+        let size = torrent.size / 1024 / 1024;
+        let tracker_hosts = torrent.tracker_hosts();
+        let has_tracker = |patterns: &[HostPattern]| patterns.iter().any(|pattern| {
+            pattern.matches_any(tracker_hosts.iter().map(String::as_str))
+        });
+
+        (self.labels.is_empty() || self.labels.iter().any(|label| torrent.labels.contains(label))) &&
+            !self.exclude_labels.iter().any(|label| torrent.labels.contains(label)) &&
+            (self.trackers.is_empty() || has_tracker(&self.trackers)) &&
+            !has_tracker(&self.exclude_trackers) &&
+            self.min_size.is_none_or(|min_size| size >= min_size) &&
+            self.max_size.is_none_or(|max_size| size <= max_size)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Delivery {
+    Skip,
+    Now,
+    /// Add to the digest of the specified route
+    Digest(usize),
+}
+
+struct Digest {
+    start_time: Instant,
+    interval: Duration,
+    notifications: Vec<(String, String)>,
+}
+
+#[derive(Clone, Copy)]
+enum Channel<'a> {
+    Email(&'a Mailer),
+    Telegram(&'a TelegramBot),
+    Xmpp(&'a XmppClient),
+    Mqtt(&'a MqttPublisher),
+    Webhook(&'a Webhook),
+}
+
+impl Channel<'_> {
+    fn name(&self) -> &str {
+        match self {
+            Channel::Email(_) => "email",
+            Channel::Telegram(_) => "telegram",
+            Channel::Xmpp(_) => "xmpp",
+            Channel::Mqtt(_) => "mqtt",
+            Channel::Webhook(webhook) => webhook.name(),
+        }
+    }
+}
+
+/// Sends user notifications (not to be confused with error reports which are sent by the logging subsystem). The
+/// notifications are sent synchronously, so it mustn't be used from within the asynchronous runtime threads.
+pub struct Notifier {
+    mailer: Option<Mailer>,
+    email_events: Option<Vec<Event>>,
+    telegram: Option<Arc<TelegramBot>>,
+    webhooks: Vec<Webhook>,
+    xmpp: Option<XmppClient>,
+    mqtt: Option<Arc<MqttPublisher>>,
+    routes: Vec<NotificationRoute>,
+    /// Pending digests by route and channel
+    digests: Mutex<HashMap<(usize, String), Digest>>,
+    /// The runtime to send the notifications to the asynchronous channels with
+    runtime: Option<Handle>,
+    dry_run: bool,
+    /// Subjects of the recent notifications (for the dashboard)
+    history: Mutex<VecDeque<(Timestamp, String)>>,
+}
+
+impl Notifier {
+    pub fn new(mailer: Option<Mailer>) -> Notifier {
+        Notifier {
+            mailer: mailer,
+            email_events: None,
+            telegram: None,
+            webhooks: Vec::new(),
+            xmpp: None,
+            mqtt: None,
+            routes: Vec::new(),
+            digests: Mutex::new(HashMap::new()),
+            runtime: None,
+            dry_run: false,
+            history: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Configures the channels to send the notifications to in addition to email
+    pub fn set_channels(
+        &mut self, config: &NotificationsConfig, telegram: Option<Arc<TelegramBot>>, mqtt: Option<Arc<MqttPublisher>>,
+        runtime: Handle,
+    ) {
+        self.email_events = config.email_events.clone();
+        self.telegram = telegram;
+        self.webhooks = config.webhooks.iter().map(Webhook::new).collect();
+        self.xmpp = config.xmpp.as_ref().and_then(|config| {
+            XmppClient::new(config).map_err(|e| error!("Unable to configure XMPP notifications: {}.", e)).ok()
+        });
+        self.mqtt = mqtt;
+        self.routes = config.routes.clone();
+        self.runtime = Some(runtime);
+    }
+
+    /// Returns the recent notifications starting from the newest one
+    pub fn get_history(&self) -> Vec<(Timestamp, String)> {
+        self.history.lock().unwrap().iter().rev().cloned().collect()
+    }
+
+    /// In dry run mode the notifications are only logged
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    pub fn has_digests(&self) -> bool {
+        self.routes.iter().any(|route| route.digest.is_some())
+    }
+
+    pub fn notify(&self, event: Event, subject: &str, body: &str) {
+        self.notify_about(event, None, subject, body);
+    }
+
+    /// The torrent's metadata is passed to the JSON webhooks
+    pub fn notify_about(&self, event: Event, torrent: Option<&Torrent>, subject: &str, body: &str) {
+        if let Err(e) = self.send(event, torrent, subject, body) {
+            error!("Failed to send {:?} notification: {}.", subject, e);
+        }
+    }
+
+    /// Sends the notification in a background thread to not block the asynchronous code.
+    pub fn notify_in_background(self: &Arc<Self>, event: Event, subject: &str, body: &str) {
+        let (notifier, subject, body) = (self.clone(), s!(subject), s!(body));
+        tokio::task::spawn_blocking(move || notifier.notify(event, &subject, &body));
+    }
+
+    pub fn notify_about_in_background(self: &Arc<Self>, event: Event, torrent: &Torrent, subject: &str, body: &str) {
+        let (notifier, torrent, subject, body) = (self.clone(), torrent.clone(), s!(subject), s!(body));
+        tokio::task::spawn_blocking(move || notifier.notify_about(event, Some(&torrent), &subject, &body));
+    }
+
+    pub fn notify_with_template(
+        &self, event: Event, torrent: &Torrent, template: &EmailTemplate, params: &HashMap<&str, String>,
+    ) -> EmptyResult {
+        let (subject, body) = template.render(params)?;
+        self.send(event, Some(torrent), &subject, &body)
+    }
+
+    /// Sends the digests which are due (or all pending ones on shutdown)
+    pub fn send_digests(&self, all: bool) {
+        for (channel_name, subject, body) in self.take_digests(all) {
+            let Some(channel) = self.get_channels().into_iter().find(|channel| channel.name() == channel_name) else {
+                continue;
+            };
+
+            match self.send_to(channel, Event::Report, None, &subject, &body) {
+                Ok(()) => {
+                    METRICS.on_notification();
+                    info!(action = "notify"; "Notification digest has been sent to {}.", channel_name);
+                },
+                Err(e) => error!("Failed to send notification digest to {}: {}.", channel_name, e),
+            }
+        }
+    }
+
+    fn send(&self, event: Event, torrent: Option<&Torrent>, subject: &str, body: &str) -> EmptyResult {
+        if self.dry_run {
+            info!("Dry run: would send {:?} notification.", subject);
+            return Ok(());
+        }
+
+        let mut channels = Vec::new();
+        let mut digested = false;
+
+        for channel in self.get_channels() {
+            let subscribed = match channel {
+                Channel::Email(_) => self.email_events.as_ref().is_none_or(|events| events.contains(&event)),
+                Channel::Webhook(webhook) => webhook.accepts(event),
+                Channel::Xmpp(client) => client.accepts(event),
+                Channel::Telegram(_) | Channel::Mqtt(_) => true,
+            };
+
+            match self.route(channel.name(), subscribed, event, torrent) {
+                Delivery::Skip => {},
+                Delivery::Now => channels.push(channel),
+                Delivery::Digest(route) => {
+                    self.add_to_digest(route, channel.name(), subject, body);
+                    digested = true;
+                },
+            }
+        }
+
+        if channels.is_empty() {
+            if digested {
+                debug!("{:?} notification has been added to the digest.", subject);
+            } else {
+                debug!("Notification: {}", subject);
+            }
+        } else {
+            // A failure of one of the channels mustn't prevent sending to the other ones
+            let mut errors = Vec::new();
+
+            for channel in channels {
+                if let Err(e) = self.send_to(channel, event, torrent, subject, body) {
+                    errors.push(e.to_string());
+                }
+            }
+
+            if !errors.is_empty() {
+                return Err(errors.join(", ").into());
+            }
+
+            METRICS.on_notification();
+            info!(action = "notify"; "{:?} notification has been sent.", subject);
+        }
+
+        let mut history = self.history.lock().unwrap();
+        if history.len() >= HISTORY_SIZE {
+            history.pop_front();
+        }
+        history.push_back((OffsetDateTime::now_utc().unix_timestamp(), s!(subject)));
+
+        Ok(())
+    }
+
+    fn get_channels(&self) -> Vec<Channel<'_>> {
+        let mut channels = Vec::new();
+
+        if let Some(ref mailer) = self.mailer {
+            channels.push(Channel::Email(mailer));
+        }
+        if let Some(ref bot) = self.telegram {
+            channels.push(Channel::Telegram(bot));
+        }
+        if let Some(ref client) = self.xmpp {
+            channels.push(Channel::Xmpp(client));
+        }
+        if let Some(ref mqtt) = self.mqtt {
+            channels.push(Channel::Mqtt(mqtt));
+        }
+        channels.extend(self.webhooks.iter().map(Channel::Webhook));
+
+        channels
+    }
+
+    fn send_to(
+        &self, channel: Channel, event: Event, torrent: Option<&Torrent>, subject: &str, body: &str,
+    ) -> EmptyResult {
+        let runtime = || self.runtime.as_ref().ok_or("the runtime isn't configured");
+
+        match channel {
+            Channel::Email(mailer) => mailer.send(subject, body).map_err(|e| format!("email: {}", e))?,
+            Channel::Telegram(bot) => runtime()?.block_on(bot.send(subject, body)).map_err(|e| format!(
+                "Telegram: {}", e))?,
+            Channel::Xmpp(client) => runtime()?.block_on(client.send(subject, body))?,
+            // Publishing is asynchronous, so it never fails here
+            Channel::Mqtt(mqtt) => mqtt.publish_event(&get_event_json(event, torrent, subject, body)),
+            Channel::Webhook(webhook) => runtime()?.block_on(webhook.send(event, torrent, subject, body))?,
+        }
+
+        Ok(())
+    }
+
+    fn route(&self, channel: &str, subscribed: bool, event: Event, torrent: Option<&Torrent>) -> Delivery {
+        let mut routes = self.routes.iter().enumerate()
+            .filter(|(_, route)| route.channels.iter().any(|name| name == channel))
+            .peekable();
+
+        if routes.peek().is_none() {
+            return if subscribed { Delivery::Now } else { Delivery::Skip };
+        }
+
+        // Immediate delivery takes precedence over the digests
+        let mut delivery = Delivery::Skip;
+
+        for (index, route) in routes.filter(|(_, route)| route.matches(event, torrent)) {
+            if route.digest.is_none() {
+                return Delivery::Now;
+            } else if delivery == Delivery::Skip {
+                delivery = Delivery::Digest(index);
+            }
+        }
+
+        delivery
+    }
+
+    fn add_to_digest(&self, route: usize, channel: &str, subject: &str, body: &str) {
+        let mut digests = self.digests.lock().unwrap();
+        let digest = digests.entry((route, s!(channel))).or_insert_with(|| Digest {
+            start_time: Instant::now(),
+            interval: self.routes[route].digest.unwrap(),
+            notifications: Vec::new(),
+        });
+        digest.notifications.push((s!(subject), s!(body.trim())));
+    }
+
+    /// Returns channel, subject and body of the digests to send
+    fn take_digests(&self, all: bool) -> Vec<(String, String, String)> {
+        let mut digests = self.digests.lock().unwrap();
+
+        let due: Vec<(usize, String)> = digests.iter()
+            .filter(|(_, digest)| all || digest.start_time.elapsed() >= digest.interval)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        due.into_iter().map(|key| {
+            let digest = digests.remove(&key).unwrap();
+
+            let subject = format!("{} notifications for the last {}", digest.notifications.len(),
+                                  format_duration(digest.start_time.elapsed().as_secs()));
+
+            let body: Vec<String> = digest.notifications.into_iter().map(|(subject, body)| {
+                if body.is_empty() { format!("* {}", subject) } else { format!("* {}\n{}", subject, body) }
+            }).collect();
+
+            (key.1, subject, body.join("\n\n"))
+        }).collect()
+    }
+}
+
+/// The event with the torrent's metadata (JSON webhooks and MQTT)
+pub fn get_event_json(event: Event, torrent: Option<&Torrent>, subject: &str, body: &str) -> Value {
+    json!({
+        "event": event,
+        "time": OffsetDateTime::now_utc().unix_timestamp(),
+        "subject": subject,
+        "message": body.trim(),
+        "torrent": torrent.map(|torrent| json!({
+            "hash": torrent.hash,
+            "name": torrent.name,
+            "download_dir": torrent.download_dir,
+            "size": torrent.size,
+            "labels": torrent.labels,
+            "added_time": torrent.added_time,
+            "done_time": torrent.done_time,
+        })),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::TorrentFields;
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    #[test]
+    fn test_routing() {
+        let config: NotificationsConfig = toml::from_str(r#"
+            [[routes]]
+            channels = ["telegram"]
+            min-severity = "error"
+
+            [[routes]]
+            channels = ["telegram"]
+            events = ["downloaded"]
+            labels = ["movies"]
+            exclude-trackers = ["public.example.com"]
+
+            [[routes]]
+            channels = ["email", "telegram"]
+            events = ["downloaded"]
+            min-size = 100
+            digest = "1d"
+        "#).unwrap();
+
+        let mut notifier = Notifier::new(None);
+        notifier.routes = config.routes;
+
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+
+        let mut torrent = MockTorrent::new(1, "Movie", "/downloads");
+        torrent.files = vec![(s!("Movie"), 1024 * 1024 * 1024, true)];
+        server.add_torrent(torrent);
+
+        let torrent = runtime.block_on(server.client().get_torrent(&format!("{:040x}", 1), TorrentFields::basic()))
+            .unwrap();
+        let mut movie = torrent.clone();
+        movie.labels = vec![s!("movies")];
+
+        let route = |channel, subscribed, event, torrent| notifier.route(channel, subscribed, event, torrent);
+        assert_eq!(route("telegram", true, Event::Problem, None), Delivery::Now);
+        assert_eq!(route("telegram", true, Event::FreeSpace, None), Delivery::Skip);
+        assert_eq!(route("telegram", true, Event::Downloaded, Some(&movie)), Delivery::Now);
+        assert_eq!(route("telegram", true, Event::Downloaded, Some(&torrent)), Delivery::Digest(2));
+        assert_eq!(route("email", true, Event::Downloaded, Some(&torrent)), Delivery::Digest(2));
+        assert_eq!(route("email", true, Event::Downloaded, None), Delivery::Skip);
+        assert_eq!(route("email", true, Event::Report, None), Delivery::Skip);
+
+        // The channels which aren't mentioned by the routes get everything they are subscribed to
+        assert_eq!(route("slack", true, Event::Report, None), Delivery::Now);
+        assert_eq!(route("slack", false, Event::Report, None), Delivery::Skip);
+
+        notifier.add_to_digest(2, "email", "'Movie' torrent has been downloaded", "");
+        notifier.add_to_digest(2, "email", "'Other' torrent has been downloaded", "Details.\n");
+        assert_eq!(notifier.take_digests(false), []);
+
+        let digests = notifier.take_digests(true);
+        assert_eq!(digests.len(), 1);
+
+        let (channel, subject, body) = &digests[0];
+        assert_eq!(channel, "email");
+        assert!(subject.starts_with("2 notifications for the last "));
+        assert_eq!(body, "* 'Movie' torrent has been downloaded\n\n* 'Other' torrent has been downloaded\nDetails.");
+        assert_eq!(notifier.take_digests(true), []);
+    }
+}
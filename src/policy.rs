@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::config::{self, BandwidthGroupConfig};
+use crate::seeding::{SeedingAction, SeedingLimit};
+use crate::transmissionrpc::Torrent;
+use crate::util::matching::HostPattern;
+
+/// Named set of rules for the torrents of the specified trackers or labels (private vs public trackers, for example).
+/// The first matching policy is applied and takes precedence over the global settings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Policy {
+    pub name: String,
+    #[serde(default)]
+    pub trackers: Vec<HostPattern>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+
+    /// Seeding target
+    pub ratio: Option<f64>,
+    #[serde(default, deserialize_with = "config::deserialize_optional_duration")]
+    pub seed_time: Option<Duration>,
+    #[serde(default)]
+    pub action: SeedingAction,
+
+    /// Overrides the global removal grace period
+    #[serde(default, deserialize_with = "config::deserialize_optional_duration")]
+    pub removal_grace_period: Option<Duration>,
+    /// Whether torrent's local data may be deleted on its removal (including free space cleanup)
+    #[serde(default = "config::default_true")]
+    pub allow_data_deletion: bool,
+
+    /// Speed limits in KB/s (implemented via a bandwidth group with the policy's name)
+    pub download_limit: Option<u64>,
+    pub upload_limit: Option<u64>,
+}
+
+impl Policy {
+    pub fn seeding_limit(&self) -> SeedingLimit {
+        SeedingLimit {
+            trackers: self.trackers.clone(),
+            labels: self.labels.clone(),
+            ratio: self.ratio,
+            seed_time: self.seed_time,
+            action: self.action,
+        }
+    }
+
+    pub fn bandwidth_group(&self) -> Option<BandwidthGroupConfig> {
+        if self.download_limit.is_none() && self.upload_limit.is_none() {
+            return None;
+        }
+
+        Some(BandwidthGroupConfig {
+            name: self.name.clone(),
+            download_limit: self.download_limit,
+            upload_limit: self.upload_limit,
+            honors_session_limits: true,
+            labels: self.labels.clone(),
+            trackers: self.trackers.clone(),
+        })
+    }
+}
+
+pub struct Policies {
+    policies: Vec<Policy>,
+}
+
+impl Policies {
+    pub fn new(policies: Vec<Policy>) -> Policies {
+        Policies { policies: policies }
+    }
+
+    pub fn find(&self, torrent: &Torrent) -> Option<&Policy> {
+        let tracker_hosts = torrent.tracker_hosts();
+
+        self.policies.iter().find(|policy| {
+            policy.labels.iter().any(|label| torrent.labels.contains(label)) ||
+            policy.trackers.iter().any(|pattern| pattern.matches_any(tracker_hosts.iter().map(String::as_str)))
+        })
+    }
+
+    /// Returns removal grace period for the torrent or the default one
+    pub fn removal_grace_period(&self, torrent: &Torrent, default: Option<Duration>) -> Option<Duration> {
+        self.find(torrent).and_then(|policy| policy.removal_grace_period).or(default)
+    }
+
+    pub fn allows_data_deletion(&self, torrent: &Torrent) -> bool {
+        self.find(torrent).is_none_or(|policy| policy.allow_data_deletion)
+    }
+}
@@ -0,0 +1,140 @@
+//! Notifications about the progress of long downloads, so it's known when a huge torrent is almost ready
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::commands;
+use crate::config;
+use crate::transmissionrpc::{Eta, Torrent, TorrentStatus};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ProgressNotificationsConfig {
+    /// Completion percents to notify at (like [50, 90])
+    pub thresholds: Vec<u8>,
+    /// Notify when the download's ETA drops below the specified time
+    #[serde(deserialize_with = "config::deserialize_optional_duration")]
+    pub eta: Option<Duration>,
+    /// Minimum torrent size in MB to notify about
+    pub min_size: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgressEvent {
+    /// The torrent has crossed the completion threshold (percent)
+    Threshold(u8),
+    /// The torrent's ETA has dropped below the configured time
+    Eta(u64),
+}
+
+struct ProgressState {
+    percent: u64,
+    eta_notified: bool,
+}
+
+/// Tracks the downloading torrents to notify about each progress event only once. The torrents are notified only about
+/// the progress made since they have been seen for the first time.
+pub struct ProgressNotifications {
+    config: ProgressNotificationsConfig,
+    torrents: HashMap<String, ProgressState>,
+}
+
+impl ProgressNotifications {
+    pub fn new(config: ProgressNotificationsConfig) -> ProgressNotifications {
+        ProgressNotifications {
+            config: config,
+            torrents: HashMap::new(),
+        }
+    }
+
+    /// Returns the progress events of the downloading torrents. Must be called with all the torrents to forget the ones
+    /// which are no longer downloading.
+    pub fn check<'a>(&mut self, torrents: &'a [Torrent]) -> Vec<(&'a Torrent, ProgressEvent)> {
+        if self.config.thresholds.is_empty() && self.config.eta.is_none() {
+            return Vec::new();
+        }
+
+        let downloading: Vec<&Torrent> = torrents.iter()
+            .filter(|torrent| !torrent.done && torrent.size >= self.config.min_size * 1024 * 1024)
+            .collect();
+        self.torrents.retain(|hash, _| downloading.iter().any(|torrent| &torrent.hash == hash));
+
+        let mut events = Vec::new();
+
+        for torrent in downloading {
+            // ETA of the paused torrents is meaningless
+            let eta = match torrent.eta {
+                Eta::Seconds(seconds) if torrent.status == TorrentStatus::Downloading => Some(seconds),
+                _ => None,
+            };
+
+            for event in self.check_torrent(&torrent.hash, commands::get_percent_done(torrent), eta) {
+                events.push((torrent, event));
+            }
+        }
+
+        events
+    }
+
+    fn check_torrent(&mut self, hash: &str, percent: u64, eta: Option<u64>) -> Vec<ProgressEvent> {
+        let eta = eta.filter(|&eta| self.config.eta.is_some_and(|limit| eta <= limit.as_secs()));
+
+        let Some(state) = self.torrents.get_mut(hash) else {
+            self.torrents.insert(s!(hash), ProgressState {
+                percent: percent,
+                eta_notified: eta.is_some(),
+            });
+            return Vec::new();
+        };
+
+        let mut events = Vec::new();
+
+        // Only the highest of the crossed thresholds is notified about
+        if let Some(&threshold) = self.config.thresholds.iter().filter(|&&threshold| {
+            state.percent < threshold.into() && percent >= threshold.into()
+        }).max() {
+            events.push(ProgressEvent::Threshold(threshold));
+        }
+        state.percent = state.percent.max(percent);
+
+        if let Some(eta) = eta {
+            if !state.eta_notified {
+                events.push(ProgressEvent::Eta(eta));
+                state.eta_notified = true;
+            }
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let mut progress = ProgressNotifications::new(ProgressNotificationsConfig {
+            thresholds: vec![50, 90, 75],
+            eta: Some(Duration::from_secs(10 * 60)),
+            ..Default::default()
+        });
+
+        let mut check = |percent, eta| progress.check_torrent("hash", percent, eta);
+
+        // The torrent's state at the first check is considered as already notified
+        assert_eq!(check(60, Some(60)), vec![]);
+        assert_eq!(check(70, Some(60 * 60)), vec![]);
+        assert_eq!(check(95, None), vec![ProgressEvent::Threshold(90)]);
+        assert_eq!(check(80, Some(5 * 60)), vec![]);
+        assert_eq!(check(100, Some(60)), vec![]);
+
+        let mut check = |percent, eta| progress.check_torrent("other", percent, eta);
+        assert_eq!(check(10, None), vec![]);
+        assert_eq!(check(50, Some(60 * 60)), vec![ProgressEvent::Threshold(50)]);
+        assert_eq!(check(55, Some(10 * 60)), vec![ProgressEvent::Eta(10 * 60)]);
+        assert_eq!(check(60, Some(60)), vec![]);
+    }
+}
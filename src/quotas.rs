@@ -0,0 +1,109 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Deserialize;
+
+use crate::transmissionrpc::Torrent;
+
+/// Disk quota for the torrents with the specified label
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LabelQuota {
+    pub label: String,
+    /// Maximum total size of the label's torrents in GB
+    pub max_size: u64,
+    /// Remove the oldest processed torrents of the label until it fits into the quota
+    #[serde(default)]
+    pub cleanup: bool,
+}
+
+impl LabelQuota {
+    pub fn size_limit(&self) -> u64 {
+        self.max_size * 1024 * 1024 * 1024
+    }
+}
+
+/// Tracks the labels which have exceeded their quotas: their downloads are paused until the quota is freed
+pub struct LabelQuotas {
+    quotas: Vec<LabelQuota>,
+    usage: HashMap<String, u64>,
+    exceeded: HashSet<String>,
+    /// Incremented on each change of the exceeded quotas
+    version: u64,
+}
+
+impl LabelQuotas {
+    pub fn new(quotas: Vec<LabelQuota>) -> LabelQuotas {
+        LabelQuotas {
+            quotas: quotas,
+            usage: HashMap::new(),
+            exceeded: HashSet::new(),
+            version: 0,
+        }
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Updates the labels' usage. Returns the quotas whose state has changed, their usage and whether they are
+    /// exceeded now.
+    pub fn update(&mut self, torrents: &[Torrent]) -> Vec<(&LabelQuota, u64, bool)> {
+        let mut changes = Vec::new();
+        self.usage.clear();
+
+        for quota in &self.quotas {
+            let usage = torrents.iter()
+                .filter(|torrent| torrent.labels.contains(&quota.label))
+                .map(|torrent| torrent.size)
+                .sum();
+            self.usage.insert(quota.label.clone(), usage);
+
+            let exceeded = usage > quota.size_limit();
+            if exceeded != self.exceeded.contains(&quota.label) {
+                if exceeded {
+                    self.exceeded.insert(quota.label.clone());
+                } else {
+                    self.exceeded.remove(&quota.label);
+                }
+                changes.push((quota, usage, exceeded));
+                self.version += 1;
+            }
+        }
+
+        changes
+    }
+
+    /// Returns the exceeded quota of the torrent labels
+    pub fn get_exceeded(&self, torrent: &Torrent) -> Option<&LabelQuota> {
+        self.quotas.iter().find(|quota| self.exceeded.contains(&quota.label) && torrent.labels.contains(&quota.label))
+    }
+
+    /// Selects the torrents to remove to fit into the exceeded quotas with enabled cleanup (the oldest ones first)
+    pub fn select_for_cleanup<'a>(&self, torrents: &'a [Torrent]) -> Vec<(&'a Torrent, &LabelQuota)> {
+        let mut selected: Vec<(&Torrent, &LabelQuota)> = Vec::new();
+
+        for quota in self.quotas.iter().filter(|quota| quota.cleanup && self.exceeded.contains(&quota.label)) {
+            let mut usage = self.usage.get(&quota.label).copied().unwrap_or_default();
+            let removed_size: u64 = selected.iter()
+                .filter(|(torrent, _)| torrent.labels.contains(&quota.label))
+                .map(|(torrent, _)| torrent.size)
+                .sum();
+            usage = usage.saturating_sub(removed_size);
+
+            let mut candidates: Vec<&Torrent> = torrents.iter().filter(|torrent| {
+                torrent.labels.contains(&quota.label) && !selected.iter().any(|(other, _)| other.hash == torrent.hash)
+            }).collect();
+            candidates.sort_by_key(|torrent| (torrent.done_time, torrent.id));
+
+            for torrent in candidates {
+                if usage <= quota.size_limit() {
+                    break;
+                }
+                usage = usage.saturating_sub(torrent.size);
+                selected.push((torrent, quota));
+            }
+        }
+
+        selected
+    }
+}
@@ -0,0 +1,162 @@
+use std::path::{Component, PathBuf};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::common::{EmptyResult, GenericResult};
+use crate::torrent_client::blocking::TorrentClient;
+use crate::transmissionrpc::{Torrent, TorrentFields};
+use crate::util::matching;
+
+/// A rule to rename top-level torrent directory with before copying (to strip release group suffixes, for example)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenameRule {
+    #[serde(deserialize_with = "matching::deserialize_regex")]
+    pattern: Regex,
+    /// Replacement string which may reference the pattern's capture groups as `$1` or `${name}`
+    replacement: String,
+}
+
+/// A rule to lay out the copied files in the destination directory (like `Show/Season 02/Show - S02E03.mkv`)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SortRule {
+    /// Pattern for the file's path in the torrent (starting with the torrent's top-level directory)
+    #[serde(deserialize_with = "matching::deserialize_regex")]
+    pattern: Regex,
+    /// Destination path template which may reference the pattern's capture groups as `$1` or `${name}`
+    path: String,
+}
+
+/// Returns the file's destination path (relative to the destination directory) according to the first matching rule
+pub fn get_sorted_path(rules: &[SortRule], name: &str) -> Option<PathBuf> {
+    let (rule, captures) = rules.iter().find_map(|rule| Some((rule, rule.pattern.captures(name)?)))?;
+
+    let mut path = String::new();
+    captures.expand(&rule.path, &mut path);
+    let path = PathBuf::from(path.trim());
+
+    // The hidden files are skipped during copying, so they mustn't be produced as well
+    let valid = path.components().next().is_some() && path.components().all(|component| match component {
+        Component::Normal(name) => !name.to_string_lossy().starts_with('.'),
+        _ => false,
+    });
+
+    if !valid {
+        warn!("Unable to sort {:?} to {:?}: invalid path.", name, path);
+        return None;
+    }
+
+    Some(path)
+}
+
+/// Returns a new name for the directory according to the first matching rule
+pub fn get_new_name(rules: &[RenameRule], name: &str) -> Option<String> {
+    let rule = rules.iter().find(|rule| rule.pattern.is_match(name))?;
+    let new_name = rule.pattern.replace(name, rule.replacement.as_str()).trim().to_owned();
+
+    if new_name == name {
+        return None;
+    }
+
+    if let Err(err) = validate_name(&new_name) {
+        warn!("Unable to rename {:?} to {:?}: {}.", name, new_name, err);
+        return None;
+    }
+
+    Some(new_name)
+}
+
+/// Renames top-level directory of the torrent. Returns the updated torrent info.
+pub fn rename_torrent(client: &TorrentClient, torrent: &Torrent, name: &str) -> GenericResult<Torrent> {
+    validate_name(name)?;
+
+    if !has_root_directory(torrent) {
+        return Err!("'{}' torrent doesn't have a top-level directory", torrent.name);
+    }
+
+    info!("Renaming '{}' torrent to '{}'...", torrent.name, name);
+    client.rename_path(&torrent.hash, &torrent.name, name)?;
+
+    Ok(client.get_torrent(&torrent.hash, TorrentFields::basic().with_files())?)
+}
+
+/// Renames top-level directory of the torrent if it matches any of the rules
+pub fn apply_rules(client: &TorrentClient, rules: &[RenameRule], torrent: &Torrent) -> GenericResult<Option<Torrent>> {
+    if !has_root_directory(torrent) {
+        return Ok(None);
+    }
+
+    match get_new_name(rules, &torrent.name) {
+        Some(name) => Ok(Some(rename_torrent(client, torrent, &name)?)),
+        None => Ok(None),
+    }
+}
+
+fn has_root_directory(torrent: &Torrent) -> bool {
+    let prefix = torrent.name.clone() + "/";
+
+    match torrent.files {
+        Some(ref files) => !files.is_empty() && files.iter().all(|file| file.name.starts_with(&prefix)),
+        None => false,
+    }
+}
+
+fn validate_name(name: &str) -> EmptyResult {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err!("Invalid name: {:?}", name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn test_get_new_name() {
+        let rules = vec![
+            RenameRule {
+                pattern: Regex::new(r"^(?P<name>.+?)[-.]\w+-GROUP$").unwrap(),
+                replacement: s!("${name}"),
+            },
+            RenameRule {
+                pattern: Regex::new(r"^(.+) \[.+\]$").unwrap(),
+                replacement: s!("$1"),
+            },
+            RenameRule {
+                pattern: Regex::new(r"^Broken$").unwrap(),
+                replacement: s!("some/path"),
+            },
+        ];
+
+        assert_eq!(get_new_name(&rules, "Show.S01.1080p.WEB-GROUP").unwrap(), "Show.S01.1080p");
+        assert_eq!(get_new_name(&rules, "Some Album [FLAC]").unwrap(), "Some Album");
+        assert_eq!(get_new_name(&rules, "Other"), None);
+        assert_eq!(get_new_name(&rules, "Broken"), None);
+    }
+
+    #[test]
+    fn test_get_sorted_path() {
+        let episode = r"(?i)(?:^|/)(?P<show>[^/]+?)\.S(?P<season>\d+)E(?P<episode>\d+)[^/]*\.(?P<ext>mkv|mp4)$";
+        let rules = vec![
+            SortRule {
+                pattern: Regex::new(episode).unwrap(),
+                path: s!("${show}/Season ${season}/${show} - S${season}E${episode}.${ext}"),
+            },
+            SortRule {
+                pattern: Regex::new(r"^Broken/(.+)$").unwrap(),
+                path: s!("../$1"),
+            },
+        ];
+
+        assert_eq!(get_sorted_path(&rules, "Show.S02.1080p/Show.S02E03.1080p.WEB.mkv").unwrap(),
+                   Path::new("Show/Season 02/Show - S02E03.mkv"));
+        assert_eq!(get_sorted_path(&rules, "Show.s01e01.mp4").unwrap(), Path::new("Show/Season 01/Show - S01E01.mp4"));
+        assert_eq!(get_sorted_path(&rules, "Show.S02.1080p/Show.S02E03.nfo"), None);
+        assert_eq!(get_sorted_path(&rules, "Broken/file"), None);
+    }
+}
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use time::OffsetDateTime;
+
+use crate::config;
+use crate::transmissionrpc::Torrent;
+use crate::util::matching::HostPattern;
+use crate::util::time::format_duration;
+
+/// Maximum age of the downloaded torrents of the specified trackers or labels (or of all torrents if none are
+/// specified). The torrents are removed after it regardless of their seeding limits, so the download directory doesn't
+/// grow without bound. The first matching rule is applied.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RetentionRule {
+    #[serde(default)]
+    pub trackers: Vec<HostPattern>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Time since the torrent has been downloaded
+    #[serde(deserialize_with = "config::deserialize_duration")]
+    pub max_age: Duration,
+}
+
+impl RetentionRule {
+    fn matches(&self, torrent: &Torrent, tracker_hosts: &[String]) -> bool {
+        if self.labels.is_empty() && self.trackers.is_empty() {
+            return true;
+        }
+
+        self.labels.iter().any(|label| torrent.labels.contains(label)) ||
+        self.trackers.iter().any(|pattern| pattern.matches_any(tracker_hosts.iter().map(String::as_str)))
+    }
+}
+
+pub struct Retention {
+    rules: Vec<RetentionRule>,
+}
+
+impl Retention {
+    pub fn new(rules: Vec<RetentionRule>) -> Retention {
+        Retention { rules: rules }
+    }
+
+    /// Returns the removal reason if the torrent has exceeded its maximum age
+    pub fn check(&self, torrent: &Torrent) -> Option<String> {
+        if self.rules.is_empty() {
+            return None;
+        }
+
+        let done_time = torrent.done_time?;
+        let tracker_hosts = torrent.tracker_hosts();
+        let rule = self.rules.iter().find(|rule| rule.matches(torrent, &tracker_hosts))?;
+
+        let age = (OffsetDateTime::now_utc().unix_timestamp() - done_time).max(0) as u64;
+        if age < rule.max_age.as_secs() {
+            return None;
+        }
+
+        Some(format!("been downloaded {} ago", format_duration(age)))
+    }
+}
@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::config;
+use crate::transmissionrpc::Torrent;
+
+/// Destination for the completed torrents with the specified labels or from the specified download directories (TV
+/// shows, movies and music libraries, for example). The first matching route takes precedence over --copy-to and
+/// --move-to.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Route {
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Torrent download directories (absolute or relative to the download directory)
+    #[serde(default)]
+    pub download_dirs: Vec<PathBuf>,
+
+    pub copy_to: PathBuf,
+    pub move_to: Option<PathBuf>,
+    /// Whether rename and sort rules should be applied on copying
+    #[serde(default = "config::default_true")]
+    pub rename: bool,
+}
+
+/// Where and how the torrent should be consumed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Destination {
+    pub copy_to: Option<PathBuf>,
+    pub move_to: Option<PathBuf>,
+    pub rename: bool,
+}
+
+#[derive(Clone)]
+pub struct Routes {
+    routes: Vec<Route>,
+    default: Destination,
+}
+
+impl Routes {
+    pub fn new(mut routes: Vec<Route>, download_dir: &Path, copy_to: Option<PathBuf>, move_to: Option<PathBuf>) -> Routes {
+        for route in &mut routes {
+            for path in &mut route.download_dirs {
+                *path = download_dir.join(&path);
+            }
+        }
+
+        Routes {
+            routes: routes,
+            default: Destination {
+                copy_to: copy_to,
+                move_to: move_to,
+                rename: true,
+            },
+        }
+    }
+
+    pub fn default(&self) -> &Destination {
+        &self.default
+    }
+
+    pub fn get_destination(&self, torrent: &Torrent) -> Destination {
+        let download_dir = Path::new(&torrent.download_dir);
+
+        let route = self.routes.iter().find(|route| {
+            route.labels.iter().any(|label| torrent.labels.contains(label)) ||
+            route.download_dirs.iter().any(|path| path == download_dir)
+        });
+
+        match route {
+            Some(route) => Destination {
+                copy_to: Some(route.copy_to.clone()),
+                move_to: route.move_to.clone(),
+                rename: route.rename,
+            },
+            None => self.default.clone(),
+        }
+    }
+}
+
@@ -0,0 +1,82 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::transmissionrpc::Torrent;
+use crate::util::matching::HostPattern;
+
+/// Limits the controller to the specified torrents when Transmission is shared with other tools: the torrents outside
+/// of the scope are completely ignored. A torrent must match all the specified filters (any of the values of each).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ManageOnlyConfig {
+    pub labels: Vec<String>,
+    /// Download directories (relative paths are relative to the download directory) including their subdirectories
+    pub download_dirs: Vec<PathBuf>,
+    pub trackers: Vec<HostPattern>,
+}
+
+/// Manual exceptions: the ignored torrents are never copied, removed or reported on
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct IgnoreConfig {
+    /// Info hashes of the ignored torrents
+    pub hashes: Vec<String>,
+    /// The torrents with this label are ignored
+    pub label: Option<String>,
+}
+
+impl Default for IgnoreConfig {
+    fn default() -> IgnoreConfig {
+        IgnoreConfig {
+            hashes: Vec::new(),
+            label: Some(s!("tc:ignore")),
+        }
+    }
+}
+
+pub struct Scope {
+    ignored_hashes: Vec<String>,
+    ignore_label: Option<String>,
+    labels: Vec<String>,
+    download_dirs: Vec<PathBuf>,
+    trackers: Vec<HostPattern>,
+}
+
+impl Scope {
+    pub fn new(config: &ManageOnlyConfig, ignore: &IgnoreConfig, download_dir: &Path) -> Scope {
+        Scope {
+            ignored_hashes: ignore.hashes.iter().map(|hash| hash.to_lowercase()).collect(),
+            ignore_label: ignore.label.clone(),
+            labels: config.labels.clone(),
+            download_dirs: config.download_dirs.iter().map(|path| download_dir.join(path)).collect(),
+            trackers: config.trackers.clone(),
+        }
+    }
+
+    pub fn contains(&self, torrent: &Torrent) -> bool {
+        if self.ignored_hashes.contains(&torrent.hash.to_lowercase()) ||
+            self.ignore_label.as_ref().is_some_and(|label| torrent.labels.contains(label)) {
+            return false;
+        }
+
+        if !self.labels.is_empty() && !self.labels.iter().any(|label| torrent.labels.contains(label)) {
+            return false;
+        }
+
+        if !self.download_dirs.is_empty() && !self.download_dirs.iter().any(|path| {
+            Path::new(&torrent.download_dir).starts_with(path)
+        }) {
+            return false;
+        }
+
+        if !self.trackers.is_empty() {
+            let tracker_hosts = torrent.tracker_hosts();
+            if !self.trackers.iter().any(|pattern| pattern.matches_any(tracker_hosts.iter().map(String::as_str))) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
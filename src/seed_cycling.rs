@@ -0,0 +1,104 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::config;
+use crate::seeding::SeedingLimits;
+use crate::transmissionrpc::{Torrent, TorrentStatus};
+
+/// Limits the number of actively seeding torrents, so hundreds of seeds don't exhaust the peer connections
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SeedCyclingConfig {
+    /// Maximum number of simultaneously seeding torrents
+    pub max_active: Option<usize>,
+    /// How often the seeding torrents are rotated
+    #[serde(deserialize_with = "config::deserialize_duration")]
+    pub interval: Duration,
+}
+
+impl Default for SeedCyclingConfig {
+    fn default() -> SeedCyclingConfig {
+        SeedCyclingConfig {
+            max_active: None,
+            interval: Duration::from_secs(6 * 60 * 60),
+        }
+    }
+}
+
+/// Rotates the seeding torrents: on each cycle the torrents which are the furthest from their ratio target get the
+/// seeding slots and the others are paused until the next cycle. Between the cycles only the free slots are filled.
+pub struct SeedCycler {
+    max_active: usize,
+    interval: Duration,
+    last_rotation: Option<Instant>,
+    cycled_out: HashSet<String>,
+}
+
+impl SeedCycler {
+    pub fn new(max_active: usize, interval: Duration) -> SeedCycler {
+        SeedCycler {
+            max_active: max_active,
+            interval: interval,
+            last_rotation: None,
+            cycled_out: HashSet::new(),
+        }
+    }
+
+    /// Returns true if the torrent has been paused by the cycler
+    pub fn is_cycled_out(&self, hash: &str) -> bool {
+        self.cycled_out.contains(hash)
+    }
+
+    /// Returns the torrents which must be stopped and started to satisfy the limit. The torrents which have reached
+    /// their seeding target are left to the seeding limits.
+    pub fn check<'a>(
+        &mut self, torrents: &'a [Torrent], seeding_limits: &SeedingLimits,
+    ) -> (Vec<&'a Torrent>, Vec<&'a Torrent>) {
+        let mut candidates: Vec<(&Torrent, f64)> = torrents.iter().filter(|torrent| {
+            torrent.done && torrent.processed && seeding_limits.check(torrent).is_none() && (
+                matches!(torrent.status, TorrentStatus::Seeding | TorrentStatus::SeedWait) ||
+                torrent.status == TorrentStatus::Stopped && self.cycled_out.contains(&torrent.hash)
+            )
+        }).map(|torrent| {
+            // The torrents without a ratio target are seeded to 1.0 ratio
+            let target = seeding_limits.ratio_limit(torrent).unwrap_or(1.0);
+            (torrent, torrent.upload_ratio.unwrap_or_default() / target)
+        }).collect();
+
+        self.cycled_out.retain(|hash| candidates.iter().any(|(torrent, _)| &torrent.hash == hash));
+
+        let rotate = self.last_rotation.is_none_or(|time| time.elapsed() >= self.interval);
+        if rotate {
+            self.last_rotation = Some(Instant::now());
+        }
+
+        // The active torrents keep their slots until the next rotation
+        candidates.sort_by(|(a, a_progress), (b, b_progress)| {
+            let a_stopped = !rotate && a.status == TorrentStatus::Stopped;
+            let b_stopped = !rotate && b.status == TorrentStatus::Stopped;
+            a_stopped.cmp(&b_stopped).then(a_progress.total_cmp(b_progress)).then(a.id.cmp(&b.id))
+        });
+
+        let (mut to_stop, mut to_start) = (Vec::new(), Vec::new());
+
+        for (index, (torrent, _)) in candidates.into_iter().enumerate() {
+            let stopped = torrent.status == TorrentStatus::Stopped;
+
+            if index < self.max_active {
+                if stopped {
+                    to_start.push(torrent);
+                }
+                self.cycled_out.remove(&torrent.hash);
+            } else {
+                if !stopped {
+                    to_stop.push(torrent);
+                }
+                self.cycled_out.insert(torrent.hash.clone());
+            }
+        }
+
+        (to_stop, to_start)
+    }
+}
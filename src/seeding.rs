@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use time::OffsetDateTime;
+
+use crate::config;
+use crate::transmissionrpc::Torrent;
+use crate::util::matching::HostPattern;
+use crate::util::time::format_duration;
+
+/// Seeding target for the torrents of the specified trackers or labels (private trackers usually require to seed
+/// longer)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SeedingLimit {
+    #[serde(default)]
+    pub trackers: Vec<HostPattern>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+
+    /// Upload ratio after which the torrent is stopped or removed
+    pub ratio: Option<f64>,
+    /// Maximum seeding time since the torrent has been downloaded
+    #[serde(default, deserialize_with = "config::deserialize_optional_duration")]
+    pub seed_time: Option<Duration>,
+    #[serde(default)]
+    pub action: SeedingAction,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SeedingAction {
+    #[default]
+    Remove,
+    Stop,
+}
+
+/// Decides whether the downloaded torrents have reached their seeding targets
+pub struct SeedingLimits {
+    limits: Vec<SeedingLimit>,
+    default_ratio: Option<f64>,
+    default_seed_time: Option<Duration>,
+}
+
+impl SeedingLimits {
+    pub fn new(limits: Vec<SeedingLimit>, default_ratio: Option<f64>, default_seed_time: Option<Duration>) -> SeedingLimits {
+        SeedingLimits {
+            limits: limits,
+            default_ratio: default_ratio,
+            default_seed_time: default_seed_time,
+        }
+    }
+
+    /// Replaces the configured limits keeping the default ones
+    pub fn set_limits(&mut self, limits: Vec<SeedingLimit>) {
+        self.limits = limits;
+    }
+
+    /// Returns the action to take and its reason if the torrent has reached its seeding target
+    pub fn check(&self, torrent: &Torrent) -> Option<(SeedingAction, String)> {
+        let (ratio_limit, seed_time_limit, action) = match self.find_limit(torrent) {
+            Some(limit) => (limit.ratio, limit.seed_time, limit.action),
+            None => (self.default_ratio, self.default_seed_time, SeedingAction::Remove),
+        };
+
+        if let (Some(ratio), Some(limit)) = (torrent.upload_ratio, ratio_limit) {
+            if ratio >= limit {
+                return Some((action, format!("seeded above upload ratio limit ({:.2} >= {})", ratio, limit)));
+            }
+        }
+
+        if let (Some(done_time), Some(limit)) = (torrent.done_time, seed_time_limit) {
+            let seed_time = (OffsetDateTime::now_utc().unix_timestamp() - done_time).max(0) as u64;
+
+            if seed_time >= limit.as_secs() {
+                return Some((action, format!(
+                    "seeded enough time ({}, {} of active seeding)",
+                    format_duration(seed_time), format_duration(torrent.seeding_time))));
+            }
+        }
+
+        None
+    }
+
+    /// Returns the upload ratio target of the torrent
+    pub fn ratio_limit(&self, torrent: &Torrent) -> Option<f64> {
+        match self.find_limit(torrent) {
+            Some(limit) => limit.ratio,
+            None => self.default_ratio,
+        }
+    }
+
+    fn find_limit(&self, torrent: &Torrent) -> Option<&SeedingLimit> {
+        let tracker_hosts = torrent.tracker_hosts();
+
+        self.limits.iter().find(|limit| {
+            limit.labels.iter().any(|label| torrent.labels.contains(label)) ||
+            limit.trackers.iter().any(|pattern| pattern.matches_any(tracker_hosts.iter().map(String::as_str)))
+        })
+    }
+}
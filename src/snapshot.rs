@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use crate::transmissionrpc::{Torrent, TorrentStatus};
+
+/// The part of torrent's state which the controller acts on
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TorrentState {
+    status: TorrentStatus,
+    done: bool,
+    processed: bool,
+    error: bool,
+}
+
+impl TorrentState {
+    fn new(torrent: &Torrent) -> TorrentState {
+        TorrentState {
+            status: torrent.status,
+            done: torrent.done,
+            processed: torrent.processed,
+            error: torrent.error.is_some(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TorrentChange {
+    Added,
+    Completed,
+    Errored,
+    Recovered,
+    Changed,
+}
+
+/// The torrents' state as of the previous poll, which allows to detect the actual changes
+pub struct TorrentSnapshot {
+    torrents: Option<HashMap<String, TorrentState>>,
+}
+
+impl TorrentSnapshot {
+    pub fn new() -> TorrentSnapshot {
+        TorrentSnapshot {torrents: None}
+    }
+
+    /// Updates the snapshot returning the changed torrents. The first update reports no changes.
+    pub fn update(&mut self, torrents: &[Torrent]) -> HashMap<String, TorrentChange> {
+        let current: HashMap<String, TorrentState> = torrents.iter().map(|torrent| {
+            (torrent.hash.clone(), TorrentState::new(torrent))
+        }).collect();
+
+        let previous = match self.torrents.replace(current) {
+            Some(previous) => previous,
+            None => return HashMap::new(),
+        };
+
+        let mut changes = HashMap::new();
+
+        for (hash, &state) in self.torrents.as_ref().unwrap() {
+            let change = match previous.get(hash) {
+                None => TorrentChange::Added,
+                Some(&previous) if previous == state => continue,
+                Some(previous) if state.error && !previous.error => TorrentChange::Errored,
+                Some(previous) if !state.error && previous.error => TorrentChange::Recovered,
+                Some(previous) if state.done && !previous.done => TorrentChange::Completed,
+                Some(_) => TorrentChange::Changed,
+            };
+            changes.insert(hash.clone(), change);
+        }
+
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::TorrentFields;
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    #[test]
+    fn test_change_detection() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+        let client = server.client();
+        let get_torrents = || runtime.block_on(client.get_torrents(TorrentFields::basic())).unwrap();
+
+        server.add_torrent(MockTorrent::new(1, "seeding", "/downloads"));
+        server.add_torrent(MockTorrent::new(2, "downloading", "/downloads").downloading());
+        server.add_torrent(MockTorrent::new(3, "errored", "/downloads"));
+
+        let mut snapshot = TorrentSnapshot::new();
+        assert!(snapshot.update(&get_torrents()).is_empty());
+        assert!(snapshot.update(&get_torrents()).is_empty());
+
+        {
+            let mut state = server.state();
+            state.torrents[1] = MockTorrent::new(2, "downloading", "/downloads");
+            state.torrents[2].error = 3;
+            state.torrents[2].error_string = s!("No data found");
+        }
+        server.add_torrent(MockTorrent::new(4, "new", "/downloads"));
+
+        let hash = |id: u64| format!("{:040x}", id);
+        assert_eq!(snapshot.update(&get_torrents()), HashMap::from([
+            (hash(2), TorrentChange::Completed),
+            (hash(3), TorrentChange::Errored),
+            (hash(4), TorrentChange::Added),
+        ]));
+
+        server.state().torrents[2].error = 0;
+        assert_eq!(snapshot.update(&get_torrents()), HashMap::from([(hash(3), TorrentChange::Recovered)]));
+    }
+}
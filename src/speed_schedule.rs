@@ -0,0 +1,99 @@
+use serde::Deserialize;
+
+use crate::config;
+use crate::torrent_client::{self, TorrentClient};
+use crate::transmissionrpc::SpeedLimits;
+use crate::util::time::{self, WeekPeriods};
+
+/// Global speed limits which are applied during the specified time periods (the first matching rule is applied)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SpeedScheduleRule {
+    /// Time periods in D[-D]/HH:MM-HH:MM format
+    #[serde(deserialize_with = "config::deserialize_periods")]
+    pub periods: WeekPeriods,
+    /// Enable Transmission's alternative speed limits
+    #[serde(default)]
+    pub alt_speed: bool,
+    /// Speed limits in KB/s
+    pub download_limit: Option<u64>,
+    pub upload_limit: Option<u64>,
+}
+
+/// Switches global speed limits according to the schedule. Outside of the scheduled periods the limits are disabled.
+pub struct SpeedSchedule {
+    rules: Vec<SpeedScheduleRule>,
+    manage_alt_speed: bool,
+    manage_limits: bool,
+    current: Option<SpeedLimits>,
+}
+
+impl SpeedSchedule {
+    pub fn new(rules: Vec<SpeedScheduleRule>) -> SpeedSchedule {
+        SpeedSchedule {
+            manage_alt_speed: rules.iter().any(|rule| rule.alt_speed),
+            manage_limits: rules.iter().any(|rule| rule.download_limit.is_some() || rule.upload_limit.is_some()),
+            rules: rules,
+            current: None,
+        }
+    }
+
+    /// Applies the limits on schedule changes only, so they may be changed manually until the next period starts
+    pub async fn apply(&mut self, client: &dyn TorrentClient) -> torrent_client::EmptyResult {
+        if self.rules.is_empty() {
+            return Ok(());
+        }
+
+        let rule = self.rules.iter().find(|rule| time::is_now_in(&rule.periods));
+        let limits = self.get_limits(rule);
+
+        if self.current.as_ref() == Some(&limits) {
+            return Ok(());
+        }
+
+        info!(action = "speed-limits"; "Setting speed limits: {}...", match rule {
+            Some(rule) => format_rule(rule),
+            None => s!("unlimited"),
+        });
+
+        client.set_speed_limits(&limits).await?;
+        self.current = Some(limits);
+
+        Ok(())
+    }
+
+    fn get_limits(&self, rule: Option<&SpeedScheduleRule>) -> SpeedLimits {
+        let mut limits = SpeedLimits::default();
+
+        if self.manage_alt_speed {
+            limits.alt_speed_enabled = Some(rule.is_some_and(|rule| rule.alt_speed));
+        }
+
+        if self.manage_limits {
+            let download_limit = rule.and_then(|rule| rule.download_limit);
+            let upload_limit = rule.and_then(|rule| rule.upload_limit);
+
+            limits.download_limit_enabled = Some(download_limit.is_some());
+            limits.download_limit = download_limit;
+            limits.upload_limit_enabled = Some(upload_limit.is_some());
+            limits.upload_limit = upload_limit;
+        }
+
+        limits
+    }
+}
+
+fn format_rule(rule: &SpeedScheduleRule) -> String {
+    let format_limit = |limit: Option<u64>| match limit {
+        Some(limit) => format!("{} KB/s", limit),
+        None => s!("unlimited"),
+    };
+
+    let mut result = format!(
+        "download - {}, upload - {}", format_limit(rule.download_limit), format_limit(rule.upload_limit));
+    if rule.alt_speed {
+        result += ", alternative speed limits";
+    }
+
+    result
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::transmissionrpc::{Torrent, TorrentStatus};
+
+/// Remediation step for a stalled torrent. Each step is taken after another stall timeout without any progress.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StallAction {
+    Reannounce,
+    /// Stop and start the torrent
+    Restart,
+    Notify,
+}
+
+impl StallAction {
+    fn next(self) -> Option<StallAction> {
+        match self {
+            StallAction::Reannounce => Some(StallAction::Restart),
+            StallAction::Restart => Some(StallAction::Notify),
+            StallAction::Notify => None,
+        }
+    }
+}
+
+struct StallState {
+    left_until_done: u64,
+    progress_time: Instant,
+    last_action: Option<StallAction>,
+}
+
+/// Detects the downloading torrents which don't make any progress and escalates their remediation
+pub struct StalledTorrents {
+    timeout: Duration,
+    torrents: HashMap<String, StallState>,
+}
+
+impl StalledTorrents {
+    pub fn new(timeout: Duration) -> StalledTorrents {
+        StalledTorrents {
+            timeout: timeout,
+            torrents: HashMap::new(),
+        }
+    }
+
+    /// Returns the actions to take for the stalled torrents. Must be called with all the torrents to forget the ones
+    /// which are no longer downloading.
+    pub fn check<'a>(&mut self, torrents: &'a [Torrent]) -> Vec<(&'a Torrent, StallAction)> {
+        let now = Instant::now();
+        let mut actions = Vec::new();
+
+        let downloading: Vec<&Torrent> = torrents.iter()
+            .filter(|torrent| !torrent.done && torrent.status == TorrentStatus::Downloading)
+            .collect();
+        self.torrents.retain(|hash, _| downloading.iter().any(|torrent| &torrent.hash == hash));
+
+        for torrent in downloading {
+            if let Some(action) = self.check_torrent(&torrent.hash, torrent.left_until_done, now) {
+                actions.push((torrent, action));
+            }
+        }
+
+        actions
+    }
+
+    fn check_torrent(&mut self, hash: &str, left_until_done: u64, now: Instant) -> Option<StallAction> {
+        let state = self.torrents.entry(s!(hash)).or_insert_with(|| StallState {
+            left_until_done: left_until_done,
+            progress_time: now,
+            last_action: None,
+        });
+
+        if left_until_done < state.left_until_done {
+            state.left_until_done = left_until_done;
+            state.progress_time = now;
+            state.last_action = None;
+            return None;
+        }
+
+        let next_action = match state.last_action {
+            Some(action) => action.next()?,
+            None => StallAction::Reannounce,
+        };
+
+        let stages = match next_action {
+            StallAction::Reannounce => 1,
+            StallAction::Restart => 2,
+            StallAction::Notify => 3,
+        };
+
+        if now.duration_since(state.progress_time) < self.timeout * stages {
+            return None;
+        }
+
+        state.last_action = Some(next_action);
+        Some(next_action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escalation() {
+        let timeout = Duration::from_secs(60);
+        let mut stalled = StalledTorrents::new(timeout);
+        let start_time = Instant::now();
+
+        let mut check = |left_until_done, elapsed| {
+            stalled.check_torrent("hash", left_until_done, start_time + elapsed)
+        };
+
+        assert_eq!(check(100, Duration::ZERO), None);
+        assert_eq!(check(100, timeout / 2), None);
+        assert_eq!(check(100, timeout), Some(StallAction::Reannounce));
+        assert_eq!(check(100, timeout), None);
+        assert_eq!(check(100, timeout * 2), Some(StallAction::Restart));
+        assert_eq!(check(100, timeout * 3), Some(StallAction::Notify));
+        assert_eq!(check(100, timeout * 10), None);
+
+        // Progress resets the escalation
+        assert_eq!(check(90, timeout * 11), None);
+        assert_eq!(check(90, timeout * 12), Some(StallAction::Reannounce));
+    }
+}
@@ -0,0 +1,149 @@
+//! A small on-disk database of the processed torrents and the notifications sent for them, which allows to not copy
+//! and notify again after restarts.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::common::{EmptyResult, GenericResult};
+use crate::transmissionrpc::Torrent;
+use crate::util::time::Timestamp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Notification {
+    Downloaded,
+    Duplicate,
+    BlockedTracker,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TorrentRecord {
+    pub name: String,
+    /// Time when the torrent has been consumed
+    pub processed_time: Option<Timestamp>,
+    /// Where the torrent has been copied to
+    pub copied_to: Option<PathBuf>,
+    /// Time of the last successful verification of the torrent's data
+    pub verified_time: Option<Timestamp>,
+    pub notifications: Vec<Notification>,
+}
+
+/// The database is stored in memory only if path is not specified (or in dry run mode)
+pub struct StateDb {
+    path: Option<PathBuf>,
+    dry_run: bool,
+    torrents: Mutex<HashMap<String, TorrentRecord>>,
+}
+
+impl StateDb {
+    pub fn new(path: Option<PathBuf>, dry_run: bool) -> StateDb {
+        let torrents = match path {
+            Some(ref path) => load(path).unwrap_or_else(|e| {
+                error!("Failed to load state database from {:?}: {}.", path, e);
+                HashMap::new()
+            }),
+            None => HashMap::new(),
+        };
+
+        StateDb {
+            path: path,
+            dry_run: dry_run,
+            torrents: Mutex::new(torrents),
+        }
+    }
+
+    pub fn get(&self, hash: &str) -> Option<TorrentRecord> {
+        self.torrents.lock().unwrap().get(hash).cloned()
+    }
+
+    pub fn processed_time(&self, hash: &str) -> Option<Timestamp> {
+        self.torrents.lock().unwrap().get(hash).and_then(|record| record.processed_time)
+    }
+
+    pub fn record_processed(&self, torrent: &Torrent, copied_to: Option<&Path>) {
+        self.update(torrent, |record| {
+            record.processed_time = Some(OffsetDateTime::now_utc().unix_timestamp());
+            record.copied_to = copied_to.map(Path::to_owned);
+            true
+        });
+    }
+
+    pub fn record_verified(&self, torrent: &Torrent) {
+        self.update(torrent, |record| {
+            record.verified_time = Some(OffsetDateTime::now_utc().unix_timestamp());
+            true
+        });
+    }
+
+    /// Records the notification. Returns false if it has been already sent.
+    pub fn record_notification(&self, torrent: &Torrent, notification: Notification) -> bool {
+        self.update(torrent, |record| {
+            if record.notifications.contains(&notification) {
+                return false;
+            }
+            record.notifications.push(notification);
+            true
+        })
+    }
+
+    /// Forgets the removed torrents
+    pub fn retain(&self, torrents: &[Torrent]) {
+        let mut records = self.torrents.lock().unwrap();
+        let count = records.len();
+
+        records.retain(|hash, _| torrents.iter().any(|torrent| &torrent.hash == hash));
+        if records.len() != count {
+            self.save(&records);
+        }
+    }
+
+    fn update<F: FnOnce(&mut TorrentRecord) -> bool>(&self, torrent: &Torrent, update: F) -> bool {
+        let mut records = self.torrents.lock().unwrap();
+
+        let record = records.entry(torrent.hash.clone()).or_default();
+        record.name.clone_from(&torrent.name);
+
+        let changed = update(record);
+        if changed {
+            self.save(&records);
+        }
+
+        changed
+    }
+
+    fn save(&self, records: &HashMap<String, TorrentRecord>) {
+        let path = match self.path {
+            Some(ref path) if !self.dry_run => path,
+            _ => return,
+        };
+
+        if let Err(e) = save(path, records) {
+            error!("Failed to save state database to {:?}: {}.", path, e);
+        }
+    }
+}
+
+fn load(path: &Path) -> GenericResult<HashMap<String, TorrentRecord>> {
+    match fs::read_to_string(path) {
+        Ok(data) => Ok(serde_json::from_str(&data)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn save(path: &Path, records: &HashMap<String, TorrentRecord>) -> EmptyResult {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+
+    fs::write(&temp_path, serde_json::to_string_pretty(records)?)?;
+    fs::rename(&temp_path, path)?;
+
+    Ok(())
+}
@@ -0,0 +1,145 @@
+//! systemd service notifications (sd_notify protocol) for `Type=notify` services
+
+use std::env;
+use std::io;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+const NOTIFY_SOCKET_ENV_VAR: &str = "NOTIFY_SOCKET";
+const WATCHDOG_USEC_ENV_VAR: &str = "WATCHDOG_USEC";
+const WATCHDOG_PID_ENV_VAR: &str = "WATCHDOG_PID";
+
+/// Does nothing if the daemon isn't started by systemd
+pub struct ServiceNotifier {
+    socket: Option<(UnixDatagram, SocketAddr)>,
+    watchdog_interval: Option<Duration>,
+    ready: bool,
+    status: String,
+}
+
+impl ServiceNotifier {
+    pub fn new() -> ServiceNotifier {
+        let socket = env::var_os(NOTIFY_SOCKET_ENV_VAR).and_then(|path| {
+            let result = get_socket_address(&path.to_string_lossy()).and_then(|address| {
+                Ok((UnixDatagram::unbound()?, address))
+            });
+
+            result.map_err(|e| error!("Unable to connect to systemd notification socket {:?}: {}.", path, e)).ok()
+        });
+
+        // The watchdog may be configured for another process of the service
+        let watchdog_pid = env::var(WATCHDOG_PID_ENV_VAR).ok().and_then(|pid| pid.parse::<u32>().ok());
+        let watchdog_interval = match watchdog_pid {
+            Some(pid) if pid != std::process::id() => None,
+            _ => env::var(WATCHDOG_USEC_ENV_VAR).ok()
+                .and_then(|usec| usec.parse().ok())
+                .filter(|&usec| usec != 0)
+                .map(Duration::from_micros),
+        };
+
+        ServiceNotifier {
+            watchdog_interval: watchdog_interval.filter(|_| socket.is_some()),
+            socket: socket,
+            ready: false,
+            status: String::new(),
+        }
+    }
+
+    /// The interval at which the watchdog must be pinged
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog_interval.map(|interval| interval / 2)
+    }
+
+    /// Notifies that the daemon has started up successfully
+    pub fn ready(&mut self) {
+        if !self.ready {
+            self.ready = true;
+            self.send("READY=1");
+        }
+    }
+
+    pub fn status(&mut self, status: String) {
+        if status != self.status {
+            self.send(&format!("STATUS={}", status));
+            self.status = status;
+        }
+    }
+
+    pub fn ping_watchdog(&self) {
+        if self.watchdog_interval.is_some() {
+            self.send("WATCHDOG=1");
+        }
+    }
+
+    pub fn stopping(&self) {
+        self.send("STOPPING=1");
+    }
+
+    /// Asks the service manager to not kill the daemon for the specified time while it's shutting down
+    pub fn extend_timeout(&self, timeout: Duration) {
+        self.send(&format!("EXTEND_TIMEOUT_USEC={}", timeout.as_micros()));
+    }
+
+    fn send(&self, message: &str) {
+        if let Some((ref socket, ref address)) = self.socket {
+            if let Err(e) = socket.send_to_addr(message.as_bytes(), address) {
+                error!("Failed to send notification to systemd: {}.", e);
+            }
+        }
+    }
+}
+
+fn get_socket_address(path: &str) -> io::Result<SocketAddr> {
+    // Abstract namespace socket
+    if let Some(name) = path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            return SocketAddr::from_abstract_name(name);
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        return Err(io::Error::new(io::ErrorKind::Unsupported, format!(
+            "abstract namespace sockets are not supported: {}", name)));
+    }
+
+    SocketAddr::from_pathname(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_notifications() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("notify.sock");
+        let server = UnixDatagram::bind(&path).unwrap();
+
+        let mut notifier = ServiceNotifier {
+            socket: Some((UnixDatagram::unbound().unwrap(), get_socket_address(path.to_str().unwrap()).unwrap())),
+            watchdog_interval: Some(Duration::from_secs(10)),
+            ready: false,
+            status: String::new(),
+        };
+        assert_eq!(notifier.watchdog_interval(), Some(Duration::from_secs(5)));
+
+        notifier.ready();
+        notifier.ready();
+        notifier.status(s!("1 torrents, 0 copying"));
+        notifier.status(s!("1 torrents, 0 copying"));
+        notifier.ping_watchdog();
+        notifier.extend_timeout(Duration::from_secs(60));
+
+        let mut messages = Vec::new();
+        let mut buf = [0; 1024];
+        server.set_nonblocking(true).unwrap();
+        while let Ok(size) = server.recv(&mut buf) {
+            messages.push(String::from_utf8(buf[..size].to_vec()).unwrap());
+        }
+
+        assert_eq!(messages, ["READY=1", "STATUS=1 torrents, 0 copying", "WATCHDOG=1", "EXTEND_TIMEOUT_USEC=60000000"]);
+    }
+}
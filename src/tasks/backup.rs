@@ -0,0 +1,251 @@
+//! Backups of Transmission's state (the resume files, the torrent files and the settings), so all torrents aren't lost
+//! on the disk failure.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::common::{EmptyResult, GenericResult};
+use crate::config;
+use crate::util::process::run_command;
+use crate::util::time::Timestamp;
+use crate::torrent_client::TorrentClient;
+
+use super::{BackgroundJob, Task};
+
+/// The entries of Transmission's config directory which are backed up
+const STATE_ENTRIES: [&str; 3] = ["resume", "torrents", "settings.json"];
+
+const ARCHIVE_PREFIX: &str = "transmission-";
+const ARCHIVE_EXTENSION: &str = ".tar.gz";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BackupConfig {
+    /// Transmission's config directory (like /var/lib/transmission-daemon/.config/transmission-daemon)
+    pub config_dir: Option<PathBuf>,
+    /// Directory to store the backups in
+    pub path: Option<PathBuf>,
+    #[serde(deserialize_with = "config::deserialize_duration")]
+    pub interval: Duration,
+    /// Number of the backups to keep
+    pub max_backups: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> BackupConfig {
+        BackupConfig {
+            config_dir: None,
+            path: None,
+            interval: Duration::from_secs(24 * 60 * 60),
+            max_backups: 7,
+        }
+    }
+}
+
+/// Archives the state with tar and deletes the old backups. Archiving may take a while, so it's done in background.
+pub struct StateBackup {
+    archiver: Arc<StateArchiver>,
+    job: BackgroundJob<()>,
+}
+
+impl StateBackup {
+    pub fn new(config_dir: PathBuf, path: PathBuf, max_backups: usize) -> StateBackup {
+        StateBackup {
+            archiver: Arc::new(StateArchiver {
+                config_dir: config_dir,
+                path: path,
+                max_backups: max_backups,
+            }),
+            job: BackgroundJob::new(),
+        }
+    }
+}
+
+struct StateArchiver {
+    config_dir: PathBuf,
+    path: PathBuf,
+    max_backups: usize,
+}
+
+impl StateArchiver {
+    fn run(&self) -> EmptyResult {
+        let time = time::OffsetDateTime::now_utc().unix_timestamp();
+        let path = self.backup(time).map_err(|e| format!("Failed to backup Transmission's state: {}", e))?;
+        info!("Transmission's state has been backed up to '{}'.", path.display());
+        self.rotate()
+    }
+
+    fn backup(&self, time: Timestamp) -> GenericResult<PathBuf> {
+        let mut entries = Vec::new();
+        for name in STATE_ENTRIES {
+            if fs::symlink_metadata(self.config_dir.join(name)).is_ok() {
+                entries.push(s!(name));
+            }
+        }
+
+        if entries.is_empty() {
+            return Err!("'{}' doesn't contain Transmission's state", self.config_dir.display());
+        }
+
+        fs::create_dir_all(&self.path).map_err(|e| format!(
+            "Failed to create '{}': {}", self.path.display(), e))?;
+
+        let name = legacy_time::at_utc(legacy_time::Timespec::new(time, 0)).strftime("%Y%m%d-%H%M%S").unwrap().to_string();
+        let path = self.path.join(format!("{}{}{}", ARCHIVE_PREFIX, name, ARCHIVE_EXTENSION));
+
+        // The archive is written to a temporary file, so an incomplete backup is never taken for a valid one
+        let temp_path = self.path.join(format!(".{}{}{}.tmp", ARCHIVE_PREFIX, name, ARCHIVE_EXTENSION));
+
+        let mut args = vec![
+            s!("-czf"), temp_path.to_string_lossy().into_owned(),
+            s!("-C"), self.config_dir.to_string_lossy().into_owned(),
+        ];
+        args.extend(entries);
+
+        if let Err(e) = run_command("tar", &args) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+
+        fs::rename(&temp_path, &path).map_err(|e| format!(
+            "Failed to rename '{}' to '{}': {}", temp_path.display(), path.display(), e))?;
+
+        Ok(path)
+    }
+
+    fn rotate(&self) -> EmptyResult {
+        let backups = list_backups(&self.path)?;
+
+        for path in backups.iter().take(backups.len().saturating_sub(self.max_backups)) {
+            info!("Deleting old '{}' backup...", path.display());
+            fs::remove_file(path).map_err(|e| format!("Failed to delete '{}': {}", path.display(), e))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Task for StateBackup {
+    fn name(&self) -> &'static str {
+        "Transmission state backup"
+    }
+
+    async fn run(&mut self, _client: &dyn TorrentClient) -> EmptyResult {
+        if !self.job.is_running() {
+            let archiver = self.archiver.clone();
+            self.job.start(move || archiver.run());
+            return Ok(());
+        }
+
+        match self.job.result().await {
+            Some(result) => result,
+            None => Ok(()),
+        }
+    }
+
+    fn in_progress(&self) -> bool {
+        self.job.is_running()
+    }
+
+    fn is_heavy(&self) -> bool {
+        true
+    }
+}
+
+/// Restores Transmission's state from the specified backup (the latest one by default). The daemon must be stopped.
+pub fn restore(config: &BackupConfig, archive: Option<&Path>) -> GenericResult<PathBuf> {
+    let config_dir = config.config_dir.as_ref().ok_or("Transmission's config directory isn't configured")?;
+
+    let archive = match archive {
+        Some(archive) => archive.to_owned(),
+        None => {
+            let path = config.path.as_ref().ok_or("Backup directory isn't configured")?;
+            list_backups(path)?.pop().ok_or_else(|| format!("There are no backups in '{}'", path.display()))?
+        },
+    };
+
+    fs::create_dir_all(config_dir).map_err(|e| format!("Failed to create '{}': {}", config_dir.display(), e))?;
+    run_command("tar", &[
+        s!("-xzf"), archive.to_string_lossy().into_owned(),
+        s!("-C"), config_dir.to_string_lossy().into_owned(),
+    ])?;
+
+    Ok(archive)
+}
+
+/// Returns the backups ordered by their creation time
+fn list_backups(path: &Path) -> GenericResult<Vec<PathBuf>> {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err!("Unable to read '{}': {}", path.display(), e),
+    };
+
+    let mut backups = Vec::new();
+
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+
+        if name.starts_with(ARCHIVE_PREFIX) && name.ends_with(ARCHIVE_EXTENSION) {
+            backups.push(entry.path());
+        }
+    }
+
+    backups.sort();
+    Ok(backups)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_backup() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path().join("transmission");
+        let backup_dir = temp_dir.path().join("backups");
+
+        fs::create_dir_all(config_dir.join("resume")).unwrap();
+        fs::create_dir_all(config_dir.join("torrents")).unwrap();
+        fs::write(config_dir.join("resume/hash.resume"), "resume").unwrap();
+        fs::write(config_dir.join("torrents/hash.torrent"), "torrent").unwrap();
+        fs::write(config_dir.join("settings.json"), "{}").unwrap();
+        fs::write(config_dir.join("stats.json"), "{}").unwrap();
+
+        let backup = StateBackup::new(config_dir.clone(), backup_dir.clone(), 2);
+        let mut backups = Vec::new();
+
+        for time in [1_000_000, 1_000_001, 1_000_002] {
+            backups.push(backup.archiver.backup(time).unwrap());
+            backup.archiver.rotate().unwrap();
+        }
+
+        assert_eq!(list_backups(&backup_dir).unwrap(), backups[1..].to_vec());
+
+        fs::remove_dir_all(&config_dir).unwrap();
+        fs::create_dir(&config_dir).unwrap();
+
+        let config = BackupConfig {
+            config_dir: Some(config_dir.clone()),
+            path: Some(backup_dir),
+            ..Default::default()
+        };
+        assert_eq!(restore(&config, None).unwrap(), backups[2]);
+
+        assert_eq!(fs::read_to_string(config_dir.join("resume/hash.resume")).unwrap(), "resume");
+        assert_eq!(fs::read_to_string(config_dir.join("torrents/hash.torrent")).unwrap(), "torrent");
+        assert_eq!(fs::read_to_string(config_dir.join("settings.json")).unwrap(), "{}");
+        assert!(!config_dir.join("stats.json").exists());
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::common::EmptyResult;
+use crate::config;
+use crate::torrent_client::TorrentClient;
+use crate::transmissionrpc::{Torrent, TorrentFields};
+use crate::util::time::Timestamp;
+
+use super::Task;
+
+/// Public backup trackers which are added to the public torrents whose primary tracker fails the specified number of
+/// consecutive announces. The private torrents are never touched.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BackupTrackersConfig {
+    pub urls: Vec<String>,
+    #[serde(deserialize_with = "config::deserialize_duration")]
+    pub check_interval: Duration,
+    pub max_failures: u32,
+}
+
+impl Default for BackupTrackersConfig {
+    fn default() -> BackupTrackersConfig {
+        BackupTrackersConfig {
+            urls: Vec::new(),
+            check_interval: Duration::from_secs(60 * 60),
+            max_failures: 3,
+        }
+    }
+}
+
+pub struct BackupTrackers {
+    urls: Vec<String>,
+    max_failures: u32,
+    /// Torrent hash -> time of the last primary tracker announce and the number of consecutive failures
+    announces: HashMap<String, (Timestamp, u32)>,
+}
+
+impl BackupTrackers {
+    pub fn new(config: &BackupTrackersConfig) -> BackupTrackers {
+        BackupTrackers {
+            urls: config.urls.clone(),
+            max_failures: config.max_failures,
+            announces: HashMap::new(),
+        }
+    }
+
+    /// Returns the torrents with the failing primary tracker and the backup trackers to add to them
+    fn update<'a>(&mut self, torrents: &'a [Torrent]) -> Vec<(&'a Torrent, Vec<String>)> {
+        let mut failing = Vec::new();
+        self.announces.retain(|hash, _| torrents.iter().any(|torrent| &torrent.hash == hash));
+
+        for torrent in torrents.iter().filter(|torrent| !torrent.private) {
+            let urls: Vec<String> = self.urls.iter().filter(|url| !torrent.trackers.contains(url)).cloned().collect();
+
+            let stats = match torrent.tracker_stats.as_ref().and_then(|stats| stats.first()) {
+                Some(stats) if !urls.is_empty() => stats,
+                _ => {
+                    self.announces.remove(&torrent.hash);
+                    continue;
+                },
+            };
+
+            let Some(time) = stats.last_announce_time else {
+                continue;
+            };
+
+            // Count each announce only once
+            let (last_time, failures) = self.announces.entry(torrent.hash.clone()).or_default();
+            if *last_time != time {
+                *last_time = time;
+                *failures = if stats.last_announce_error.is_some() { *failures + 1 } else { 0 };
+            }
+
+            if *failures >= self.max_failures {
+                failing.push((torrent, urls));
+            }
+        }
+
+        failing
+    }
+}
+
+#[async_trait]
+impl Task for BackupTrackers {
+    fn name(&self) -> &'static str {
+        "Backup trackers"
+    }
+
+    async fn run(&mut self, client: &dyn TorrentClient) -> EmptyResult {
+        let torrents = client.get_torrents(TorrentFields::basic().with_tracker_stats()).await?;
+
+        for (torrent, urls) in self.update(&torrents) {
+            info!(hash = torrent.hash.as_str(), action = "add-trackers";
+                  "'{}' torrent's primary tracker is failing. Adding {} backup trackers to it...",
+                  torrent.name, urls.len());
+            client.add_trackers(&torrent.hash, &urls).await?;
+            self.announces.remove(&torrent.hash);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    #[test]
+    fn test_backup_trackers() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+        let client = server.client();
+
+        let primary = s!("http://tracker.example.com/announce");
+        let backup = vec![s!("udp://backup.example.com:1337/announce"), s!("udp://other.example.com:6969/announce")];
+
+        let mut torrent = MockTorrent::new(1, "public", "/downloads");
+        torrent.trackers = vec![primary.clone(), backup[1].clone()];
+        server.add_torrent(torrent.clone());
+
+        torrent.id = 2;
+        torrent.hash = format!("{:040x}", 2);
+        torrent.name = s!("private");
+        torrent.private = true;
+        server.add_torrent(torrent);
+
+        let mut task = BackupTrackers::new(&BackupTrackersConfig {
+            urls: backup.clone(),
+            max_failures: 2,
+            ..Default::default()
+        });
+
+        let mut check = |time: Timestamp| {
+            for torrent in &mut server.state().torrents {
+                torrent.announces = vec![(time, Some(s!("Connection refused")))];
+            }
+            runtime.block_on(task.run(&client)).unwrap();
+            server.state().torrents.iter().map(|torrent| torrent.trackers.len()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(check(100), vec![2, 2]);
+        assert_eq!(check(100), vec![2, 2]);
+        assert_eq!(check(200), vec![3, 2]);
+        assert_eq!(server.state().torrents[0].trackers, vec![primary, backup[1].clone(), backup[0].clone()]);
+
+        // All backup trackers have been already added
+        assert_eq!(check(300), vec![3, 2]);
+        assert_eq!(check(400), vec![3, 2]);
+    }
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::common::EmptyResult;
+use crate::notifications::{Event, Notifier};
+use crate::torrent_client::TorrentClient;
+
+use super::Task;
+
+// Blocklist server may be temporary unavailable, so notify only about repeated failures
+const MAX_FAILURES: u32 = 3;
+
+pub struct BlocklistUpdater {
+    notifier: Arc<Notifier>,
+    failures: u32,
+}
+
+impl BlocklistUpdater {
+    pub fn new(notifier: Arc<Notifier>) -> BlocklistUpdater {
+        BlocklistUpdater {
+            notifier: notifier,
+            failures: 0,
+        }
+    }
+}
+
+#[async_trait]
+impl Task for BlocklistUpdater {
+    fn name(&self) -> &'static str {
+        "Blocklist update"
+    }
+
+    async fn run(&mut self, client: &dyn TorrentClient) -> EmptyResult {
+        match client.update_blocklist().await {
+            Ok(size) => {
+                info!("Blocklist has been updated: {} rules.", size);
+
+                if self.failures >= MAX_FAILURES {
+                    self.notifier.notify_in_background(Event::Recovered, "Blocklist update has recovered", &format!(
+                        "Blocklist has been successfully updated after {} failed attempts: {} rules.",
+                        self.failures, size));
+                }
+                self.failures = 0;
+
+                Ok(())
+            },
+            Err(err) => {
+                self.failures += 1;
+
+                if self.failures == MAX_FAILURES {
+                    self.notifier.notify_in_background(Event::Problem, "Blocklist update failure", &format!(
+                        "Blocklist update has failed {} times in a row. The last error: {}.", self.failures, err));
+                }
+
+                Err!("Failed to update the blocklist: {}", err)
+            },
+        }
+    }
+}
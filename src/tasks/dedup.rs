@@ -0,0 +1,319 @@
+//! Deduplication of the identical files in the destination library (the same episode which arrives in several packs,
+//! for example): the duplicates are replaced by hardlinks to a single copy.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::common::{EmptyResult, ErrorContext, GenericResult};
+use crate::config;
+use crate::disk_space::format_size;
+use crate::notifications::{Event, Notifier};
+use crate::util;
+use crate::util::sha1::{DIGEST_SIZE, Sha1};
+use crate::torrent_client::TorrentClient;
+
+use super::{BackgroundJob, Task};
+
+const READ_BUFFER_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct DeduplicationConfig {
+    /// Directories to search the duplicates in (each file is only linked to the files of the same file system)
+    pub paths: Vec<PathBuf>,
+    #[serde(deserialize_with = "config::deserialize_duration")]
+    pub check_interval: Duration,
+    /// Minimum size of the file in MB to be deduplicated
+    pub min_size: u64,
+}
+
+impl Default for DeduplicationConfig {
+    fn default() -> DeduplicationConfig {
+        DeduplicationConfig {
+            paths: Vec::new(),
+            check_interval: Duration::from_secs(24 * 60 * 60),
+            min_size: 1,
+        }
+    }
+}
+
+/// A file's inode and all its paths found in the library
+struct Inode {
+    paths: Vec<PathBuf>,
+    links: u64,
+}
+
+/// Hashing of the whole library may take hours, so it's done in background
+pub struct FileDeduplicator {
+    deduplicator: Arc<Deduplicator>,
+    job: BackgroundJob<()>,
+}
+
+impl FileDeduplicator {
+    pub fn new(config: &DeduplicationConfig, dry_run: bool, notifier: Arc<Notifier>) -> FileDeduplicator {
+        FileDeduplicator {
+            deduplicator: Arc::new(Deduplicator {
+                paths: config.paths.clone(),
+                min_size: config.min_size * 1024 * 1024,
+                dry_run: dry_run,
+                notifier: notifier,
+            }),
+            job: BackgroundJob::new(),
+        }
+    }
+}
+
+struct Deduplicator {
+    paths: Vec<PathBuf>,
+    min_size: u64,
+    dry_run: bool,
+    notifier: Arc<Notifier>,
+}
+
+impl Deduplicator {
+    /// Returns the inodes grouped by their device and size
+    fn scan(&self) -> GenericResult<HashMap<(u64, u64), HashMap<u64, Inode>>> {
+        let mut files: HashMap<(u64, u64), HashMap<u64, Inode>> = HashMap::new();
+
+        // The configured paths may be nested, so each directory is tracked to not count its files twice
+        let mut scanned_dirs = HashSet::new();
+
+        for path in &self.paths {
+            self.scan_dir(path, &mut scanned_dirs, &mut files)?;
+        }
+
+        files.retain(|_, inodes| inodes.len() > 1);
+        Ok(files)
+    }
+
+    fn scan_dir(
+        &self, path: &Path, scanned_dirs: &mut HashSet<(u64, u64)>,
+        files: &mut HashMap<(u64, u64), HashMap<u64, Inode>>,
+    ) -> EmptyResult {
+        let metadata = fs::metadata(path).file_context("Failed to stat()", path)?;
+        if !scanned_dirs.insert((metadata.dev(), metadata.ino())) {
+            return Ok(());
+        }
+
+        for entry in fs::read_dir(path).file_context("Unable to read", path)? {
+            let path = entry?.path();
+            let metadata = fs::symlink_metadata(&path).file_context("Failed to stat()", &path)?;
+
+            if metadata.is_dir() {
+                self.scan_dir(&path, scanned_dirs, files)?;
+            } else if metadata.is_file() && metadata.len() != 0 && metadata.len() >= self.min_size {
+                let inode = files.entry((metadata.dev(), metadata.len())).or_default()
+                    .entry(metadata.ino()).or_insert_with(|| Inode {paths: Vec::new(), links: metadata.nlink()});
+                inode.paths.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces all paths of the duplicate inode by hardlinks to the original one. Returns true if the inode's space has
+    /// been reclaimed (it has no links outside of the library).
+    fn link(&self, original: &Path, duplicate: &Inode) -> GenericResult<bool> {
+        for path in &duplicate.paths {
+            // Protects against hash collisions and the files which have been modified since the scan
+            util::fs::verify_copy(original, path, false)?;
+
+            if self.dry_run {
+                info!("Dry run: would replace '{}' by a hardlink to '{}'.", path.display(), original.display());
+                continue;
+            }
+
+            info!("Replacing '{}' by a hardlink to '{}'...", path.display(), original.display());
+
+            let mut temp_name = s!(".");
+            temp_name += &path.file_name().unwrap().to_string_lossy();
+            temp_name += ".dedup";
+            let temp_path = path.with_file_name(temp_name);
+
+            fs::hard_link(original, &temp_path).file_context("Unable to create", &temp_path)?;
+            if let Err(e) = fs::rename(&temp_path, path) {
+                let _ = fs::remove_file(&temp_path);
+                return Err!("Unable to rename '{}' to '{}': {}", temp_path.display(), path.display(), e);
+            }
+        }
+
+        Ok(duplicate.links == duplicate.paths.len() as u64)
+    }
+
+    fn deduplicate(&self) -> EmptyResult {
+        let mut report = String::new();
+        let mut reclaimed_space = 0;
+
+        for ((_, size), inodes) in self.scan()? {
+            let mut digests: HashMap<[u8; DIGEST_SIZE], Vec<Inode>> = HashMap::new();
+            for inode in inodes.into_values() {
+                let path = &inode.paths[0];
+                match hash_file(path) {
+                    Ok(digest) => digests.entry(digest).or_default().push(inode),
+                    Err(e) => error!("Failed to read '{}': {}.", path.display(), e),
+                }
+            }
+
+            for mut duplicates in digests.into_values().filter(|inodes| inodes.len() > 1) {
+                // The most linked inode is kept: it's likely to be seeded from the download directory
+                duplicates.sort_by(|a, b| b.links.cmp(&a.links).then_with(|| a.paths.cmp(&b.paths)));
+                let original = duplicates.remove(0);
+                let original_path = &original.paths[0];
+
+                for duplicate in duplicates {
+                    match self.link(original_path, &duplicate) {
+                        Ok(reclaimed) => {
+                            for path in &duplicate.paths {
+                                report += &format!("* {} ({})\n", path.display(), format_size(size));
+                            }
+                            if reclaimed {
+                                reclaimed_space += size;
+                            }
+                        },
+                        Err(e) => error!("Failed to deduplicate '{}': {}.", original_path.display(), e),
+                    }
+                }
+            }
+        }
+
+        if !report.is_empty() {
+            let action = if self.dry_run { "can be reclaimed" } else { "has been reclaimed" };
+            report += &format!("\n{} {}.", format_size(reclaimed_space), action);
+            info!("Duplicate files have been found: {} {}.", format_size(reclaimed_space), action);
+            self.notifier.notify_in_background(Event::Report, "Duplicate files", &report);
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Task for FileDeduplicator {
+    fn name(&self) -> &'static str {
+        "File deduplication"
+    }
+
+    async fn run(&mut self, _client: &dyn TorrentClient) -> EmptyResult {
+        if !self.job.is_running() {
+            let deduplicator = self.deduplicator.clone();
+            self.job.start(move || deduplicator.deduplicate());
+            return Ok(());
+        }
+
+        match self.job.result().await {
+            Some(result) => result,
+            None => Ok(()),
+        }
+    }
+
+    fn in_progress(&self) -> bool {
+        self.job.is_running()
+    }
+
+    fn is_heavy(&self) -> bool {
+        true
+    }
+}
+
+fn hash_file(path: &Path) -> GenericResult<[u8; DIGEST_SIZE]> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+    let mut hasher = Sha1::new();
+
+    loop {
+        let size = file.read(&mut buffer)?;
+        if size == 0 {
+            break;
+        }
+        hasher.update(&buffer[..size]);
+    }
+
+    Ok(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use tokio::runtime::Runtime;
+
+    use crate::tasks::run_to_completion;
+    use crate::transmissionrpc::mock::MockServer;
+
+    use super::*;
+
+    #[test]
+    fn test_deduplication() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+
+        let temp_dir = TempDir::new().unwrap();
+        let library = temp_dir.path();
+
+        for (path, data) in [
+            ("Pack 1/Episode 1.mkv", "episode 1"),
+            ("Pack 1/Episode 2.mkv", "episode 2"),
+            ("Pack 2/Episode 2.mkv", "episode 2"),
+            ("Pack 2/Episode 3.mkv", "episode 3"),
+            ("Pack 3/Episode 1.mkv", "episode 1"),
+            ("Pack 3/Other.mkv", "episode x"),
+        ] {
+            let path = library.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        }
+
+        let inode = |path: &str| fs::metadata(library.join(path)).unwrap().ino();
+        let mut deduplicator = FileDeduplicator::new(&DeduplicationConfig {
+            paths: vec![library.to_owned()],
+            min_size: 0,
+            ..Default::default()
+        }, false, Arc::new(Notifier::new(None)));
+
+        runtime.block_on(run_to_completion(&mut deduplicator, &server.client())).unwrap();
+
+        assert_eq!(inode("Pack 1/Episode 1.mkv"), inode("Pack 3/Episode 1.mkv"));
+        assert_eq!(inode("Pack 1/Episode 2.mkv"), inode("Pack 2/Episode 2.mkv"));
+        assert_ne!(inode("Pack 3/Episode 1.mkv"), inode("Pack 3/Other.mkv"));
+        assert_eq!(fs::read_to_string(library.join("Pack 3/Episode 1.mkv")).unwrap(), "episode 1");
+
+        let names: Vec<_> = fs::read_dir(library.join("Pack 3")).unwrap()
+            .map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(names.len(), 2);
+
+        // The linked files are seen as a single inode
+        let scan = deduplicator.deduplicator.scan().unwrap();
+        assert_eq!(scan.values().map(|inodes| inodes.len()).sum::<usize>(), 4);
+    }
+
+    #[test]
+    fn test_nested_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let library = temp_dir.path();
+
+        for path in ["Pack 1/Episode 1.mkv", "Pack 2/Episode 1.mkv"] {
+            let path = library.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "episode 1").unwrap();
+        }
+
+        let deduplicator = FileDeduplicator::new(&DeduplicationConfig {
+            paths: vec![library.to_owned(), library.join("Pack 2"), library.join("Pack 2/../Pack 2")],
+            min_size: 0,
+            ..Default::default()
+        }, false, Arc::new(Notifier::new(None)));
+
+        let scan = deduplicator.deduplicator.scan().unwrap();
+        assert_eq!(scan.len(), 1);
+
+        let inodes = scan.values().next().unwrap();
+        assert_eq!(inodes.len(), 2);
+        assert!(inodes.values().all(|inode| inode.paths.len() == 1));
+    }
+}
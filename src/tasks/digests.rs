@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::common::EmptyResult;
+use crate::notifications::Notifier;
+use crate::torrent_client::TorrentClient;
+
+use super::Task;
+
+/// Sends the notification digests which are due. The pending ones are sent on shutdown.
+pub struct NotificationDigests {
+    notifier: Arc<Notifier>,
+}
+
+impl NotificationDigests {
+    pub fn new(notifier: Arc<Notifier>) -> NotificationDigests {
+        NotificationDigests { notifier: notifier }
+    }
+}
+
+#[async_trait]
+impl Task for NotificationDigests {
+    fn name(&self) -> &'static str {
+        "Notification digests"
+    }
+
+    async fn run(&mut self, _client: &dyn TorrentClient) -> EmptyResult {
+        // Sending is synchronous
+        let notifier = self.notifier.clone();
+        tokio::task::spawn_blocking(move || notifier.send_digests(false));
+        Ok(())
+    }
+}
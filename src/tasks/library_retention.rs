@@ -0,0 +1,284 @@
+//! Retention of the previously copied items in the library directories: the top-level entries which exceed the
+//! configured age, total size or count limits are deleted (or moved to the trash), the oldest first.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::common::{EmptyResult, ErrorContext, GenericResult};
+use crate::config;
+use crate::disk_space::format_size;
+use crate::notifications::{Event, Notifier};
+use crate::torrent_client::TorrentClient;
+use crate::transmissionrpc::TorrentFields;
+use crate::util;
+
+use super::{BackgroundJob, Task};
+use super::trash;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LibraryRetentionConfig {
+    pub rules: Vec<LibraryRetentionRule>,
+    #[serde(deserialize_with = "config::deserialize_duration")]
+    pub check_interval: Duration,
+}
+
+impl Default for LibraryRetentionConfig {
+    fn default() -> LibraryRetentionConfig {
+        LibraryRetentionConfig {
+            rules: Vec::new(),
+            check_interval: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LibraryRetentionRule {
+    /// Library directory whose top-level entries are pruned
+    pub path: PathBuf,
+    #[serde(default, deserialize_with = "config::deserialize_optional_duration")]
+    pub max_age: Option<Duration>,
+    /// Maximum total size of the entries in GB
+    pub max_size: Option<u64>,
+    /// Maximum number of the entries (the newest ones are kept)
+    pub keep_last: Option<usize>,
+}
+
+struct Item {
+    path: PathBuf,
+    size: u64,
+    modify_time: SystemTime,
+}
+
+/// Walking and deleting the library items may take a while, so it's done in background
+pub struct LibraryRetention {
+    pruner: Arc<LibraryPruner>,
+    job: BackgroundJob<()>,
+}
+
+impl LibraryRetention {
+    pub fn new(
+        config: &LibraryRetentionConfig, trash_dir: Option<PathBuf>, dry_run: bool, notifier: Arc<Notifier>,
+    ) -> LibraryRetention {
+        LibraryRetention {
+            pruner: Arc::new(LibraryPruner {
+                rules: config.rules.clone(),
+                trash_dir: trash_dir,
+                dry_run: dry_run,
+                notifier: notifier,
+            }),
+            job: BackgroundJob::new(),
+        }
+    }
+}
+
+struct LibraryPruner {
+    rules: Vec<LibraryRetentionRule>,
+    trash_dir: Option<PathBuf>,
+    dry_run: bool,
+    notifier: Arc<Notifier>,
+}
+
+impl LibraryPruner {
+    fn run(&self, seeded: &[PathBuf]) -> EmptyResult {
+        let now = SystemTime::now();
+        let unix_time = now.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+
+        // Each cleanup gets its own trash directory, so the trash retention is counted from the deletion time
+        let trash_dir_name = format!("library-{}", unix_time);
+
+        let mut report = String::new();
+        let mut reclaimed_space = 0;
+
+        for rule in &self.rules {
+            let items = match LibraryPruner::select(rule, seeded, now) {
+                Ok(items) => items,
+                Err(e) => {
+                    error!("Failed to apply '{}' retention rule: {}.", rule.path.display(), e);
+                    continue;
+                },
+            };
+
+            for item in items {
+                match self.prune(&item, &trash_dir_name) {
+                    Ok(()) => {
+                        report += &format!("* {} ({})\n", item.path.display(), format_size(item.size));
+                        reclaimed_space += item.size;
+                    },
+                    Err(e) => error!("Failed to delete '{}': {}.", item.path.display(), e),
+                }
+            }
+        }
+
+        if !report.is_empty() {
+            let action = if self.dry_run { "can be reclaimed" } else { "has been reclaimed" };
+            report += &format!("\n{} {}.", format_size(reclaimed_space), action);
+            self.notifier.notify_in_background(Event::Removed, "Library cleanup", &report);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the items which exceed the rule's limits
+    fn select(rule: &LibraryRetentionRule, seeded: &[PathBuf], now: SystemTime) -> GenericResult<Vec<Item>> {
+        let mut items = Vec::new();
+
+        for entry in fs::read_dir(&rule.path).file_context("Unable to read", &rule.path)? {
+            let entry = entry?;
+
+            // The copies which are in progress are located in the hidden temporary directories
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+
+            let path = entry.path();
+            let modify_time = fs::symlink_metadata(&path)?.modified()?;
+            let size = util::fs::get_total_size(&path)?;
+            items.push(Item {path, size, modify_time});
+        }
+
+        // The newest items go first
+        items.sort_by(|a, b| b.modify_time.cmp(&a.modify_time).then_with(|| a.path.cmp(&b.path)));
+
+        let max_size = rule.max_size.map(|size| size * 1024 * 1024 * 1024);
+        let mut total_size = 0;
+        let mut count = 0;
+
+        Ok(items.into_iter().filter(|item| {
+            total_size += item.size;
+            count += 1;
+
+            // Deleting the data which is seeded by Transmission would break the torrent
+            if seeded.iter().any(|path| path.starts_with(&item.path)) {
+                return false;
+            }
+
+            let age = now.duration_since(item.modify_time).unwrap_or_default();
+            rule.max_age.is_some_and(|max_age| age >= max_age) ||
+                max_size.is_some_and(|max_size| total_size > max_size) ||
+                rule.keep_last.is_some_and(|keep_last| count > keep_last)
+        }).collect())
+    }
+
+    fn prune(&self, item: &Item, trash_dir_name: &str) -> EmptyResult {
+        if self.dry_run {
+            info!("Dry run: would delete '{}' ({}).", item.path.display(), format_size(item.size));
+            return Ok(());
+        }
+
+        if let Some(ref trash_dir) = self.trash_dir {
+            info!(action = "trash";
+                  "Moving '{}' ({}) to the trash...", item.path.display(), format_size(item.size));
+            return trash::move_path_to_trash(trash_dir, trash_dir_name, &item.path);
+        }
+
+        info!(action = "delete"; "Deleting '{}' ({})...", item.path.display(), format_size(item.size));
+
+        if fs::symlink_metadata(&item.path)?.is_dir() {
+            fs::remove_dir_all(&item.path)
+        } else {
+            fs::remove_file(&item.path)
+        }.file_context("Unable to delete", &item.path)
+    }
+}
+
+#[async_trait]
+impl Task for LibraryRetention {
+    fn name(&self) -> &'static str {
+        "Library retention"
+    }
+
+    async fn run(&mut self, client: &dyn TorrentClient) -> EmptyResult {
+        if self.job.is_running() {
+            return match self.job.result().await {
+                Some(result) => result,
+                None => Ok(()),
+            };
+        }
+
+        let seeded: Vec<PathBuf> = client.get_torrents(TorrentFields::basic()).await?.into_iter()
+            .map(|torrent| Path::new(&torrent.download_dir).join(&torrent.name))
+            .collect();
+
+        let pruner = self.pruner.clone();
+        self.job.start(move || pruner.run(&seeded));
+        Ok(())
+    }
+
+    fn in_progress(&self) -> bool {
+        self.job.is_running()
+    }
+
+    fn is_heavy(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use tempfile::TempDir;
+    use tokio::runtime::Runtime;
+
+    use crate::tasks::run_to_completion;
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    #[test]
+    fn test_library_retention() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+
+        let temp_dir = TempDir::new().unwrap();
+        let library = temp_dir.path().join("library");
+        let trash_dir = temp_dir.path().join("trash");
+
+        let now = SystemTime::now();
+        for (index, name) in ["newest", "seeded", "new", "old", "oldest", ".copying"].iter().enumerate() {
+            let path = library.join(name);
+            fs::create_dir_all(&path).unwrap();
+            fs::write(path.join("file"), "data").unwrap();
+
+            let time = now - Duration::from_secs(index as u64 * 24 * 60 * 60);
+            File::open(&path).unwrap().set_modified(time).unwrap();
+        }
+        server.add_torrent(MockTorrent::new(1, "seeded", library.to_str().unwrap()));
+
+        let names = |path: &Path| {
+            let mut names: Vec<String> = fs::read_dir(path).unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string()).collect();
+            names.sort();
+            names
+        };
+
+        let config = LibraryRetentionConfig {
+            rules: vec![LibraryRetentionRule {
+                path: library.clone(),
+                max_age: Some(Duration::from_secs(4 * 24 * 60 * 60)),
+                max_size: None,
+                keep_last: Some(3),
+            }],
+            ..Default::default()
+        };
+
+        let notifier = Arc::new(Notifier::new(None));
+        let mut retention = LibraryRetention::new(&config, Some(trash_dir.clone()), true, notifier.clone());
+        runtime.block_on(run_to_completion(&mut retention, &server.client())).unwrap();
+        assert_eq!(names(&library).len(), 6);
+
+        let mut retention = LibraryRetention::new(&config, Some(trash_dir.clone()), false, notifier);
+        runtime.block_on(run_to_completion(&mut retention, &server.client())).unwrap();
+        assert_eq!(names(&library), vec![".copying", "new", "newest", "seeded"]);
+        let trash_dirs = names(&trash_dir);
+        assert_eq!(trash_dirs.len(), 1);
+        assert_eq!(names(&trash_dir.join(&trash_dirs[0])), vec!["old", "oldest"]);
+    }
+}
@@ -0,0 +1,145 @@
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use tokio::task::JoinHandle;
+
+use crate::common::{EmptyResult, GenericResult};
+use crate::torrent_client::TorrentClient;
+
+pub mod backup;
+pub mod backup_trackers;
+pub mod blocklist;
+pub mod dedup;
+pub mod digests;
+pub mod library_retention;
+pub mod orphans;
+pub mod port_forwarding;
+pub mod port_test;
+pub mod report;
+pub mod rss;
+pub mod tracker_errors;
+pub mod trash;
+pub mod watch_dir;
+
+/// A maintenance task which is executed periodically by the controller
+#[async_trait]
+pub trait Task: Send {
+    fn name(&self) -> &'static str;
+    async fn run(&mut self, client: &dyn TorrentClient) -> EmptyResult;
+
+    /// Returns true while the task's background job is running: such task is run on each scheduler run to check the
+    /// job's result.
+    fn in_progress(&self) -> bool {
+        false
+    }
+
+    /// The tasks which walk the whole library or archive a lot of data aren't executed ahead of schedule on the full
+    /// checks
+    fn is_heavy(&self) -> bool {
+        false
+    }
+}
+
+/// A blocking job (file system walking, hashing, archiving) which is executed on the blocking thread pool, so the
+/// controller isn't blocked while it's running. Its result is checked on the following runs of the task.
+pub struct BackgroundJob<T> {
+    handle: Option<JoinHandle<GenericResult<T>>>,
+}
+
+impl<T: Send + 'static> BackgroundJob<T> {
+    pub fn new() -> BackgroundJob<T> {
+        BackgroundJob {handle: None}
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.handle.is_some()
+    }
+
+    pub fn start<F: FnOnce() -> GenericResult<T> + Send + 'static>(&mut self, job: F) {
+        assert!(self.handle.is_none());
+        self.handle = Some(tokio::task::spawn_blocking(job));
+    }
+
+    /// Returns the job's result if it has completed
+    pub async fn result(&mut self) -> Option<GenericResult<T>> {
+        if !self.handle.as_ref()?.is_finished() {
+            return None;
+        }
+
+        Some(match self.handle.take().unwrap().await {
+            Ok(result) => result,
+            Err(e) => Err!("the job has crashed: {}", e),
+        })
+    }
+}
+
+struct ScheduledTask {
+    task: Box<dyn Task>,
+    interval: Duration,
+    last_run_time: Option<Instant>,
+}
+
+#[derive(Default)]
+pub struct Scheduler {
+    tasks: Vec<ScheduledTask>,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler::default()
+    }
+
+    /// Adds a task which will be executed on the first run and then with the specified interval
+    pub fn add<T: Task + 'static>(&mut self, task: T, interval: Duration) {
+        self.tasks.push(ScheduledTask {
+            task: Box::new(task),
+            interval: interval,
+            last_run_time: None,
+        });
+    }
+
+    /// Makes all tasks except the heavy ones to be executed on the next run
+    pub fn reset(&mut self) {
+        for scheduled in &mut self.tasks {
+            if !scheduled.task.is_heavy() {
+                scheduled.last_run_time = None;
+            }
+        }
+    }
+
+    pub async fn run(&mut self, client: &dyn TorrentClient) {
+        for scheduled in &mut self.tasks {
+            if scheduled.task.in_progress() {
+                if let Err(e) = scheduled.task.run(client).await {
+                    warn!("{} task has failed: {}.", scheduled.task.name(), e);
+                }
+                continue;
+            }
+
+            if let Some(last_run_time) = scheduled.last_run_time {
+                if last_run_time.elapsed() < scheduled.interval {
+                    continue;
+                }
+            }
+
+            debug!("Running {} task...", scheduled.task.name());
+            scheduled.last_run_time = Some(Instant::now());
+
+            // Tasks are responsible for notifying the user about persistent failures themselves
+            if let Err(e) = scheduled.task.run(client).await {
+                warn!("{} task has failed: {}.", scheduled.task.name(), e);
+            }
+        }
+    }
+}
+
+/// Runs the task waiting for its background job to complete
+#[cfg(test)]
+pub async fn run_to_completion(task: &mut dyn Task, client: &dyn TorrentClient) -> EmptyResult {
+    task.run(client).await?;
+    while task.in_progress() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        task.run(client).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,220 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::mem;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::common::{EmptyResult, GenericResult};
+use crate::disk_space::format_size;
+use crate::notifications::{Event, Notifier};
+use crate::torrent_client::TorrentClient;
+use crate::transmissionrpc::TorrentFields;
+use crate::util;
+
+use super::{BackgroundJob, Task};
+
+/// Finds the files in the download directory which don't belong to any torrent (leftovers from the torrents removed
+/// without their data, for example) and optionally deletes them.
+pub struct OrphanedFilesCleaner {
+    download_dir: PathBuf,
+    delete_after: Option<Duration>,
+    notifier: Arc<Notifier>,
+    reported: HashSet<OsString>,
+    /// Size calculation and deletion of the orphaned files walk them, so they are done in background. The job takes
+    /// the reported files and returns them back.
+    job: BackgroundJob<HashSet<OsString>>,
+}
+
+impl OrphanedFilesCleaner {
+    pub fn new(download_dir: PathBuf, delete_after: Option<Duration>, notifier: Arc<Notifier>) -> OrphanedFilesCleaner {
+        OrphanedFilesCleaner {
+            download_dir: download_dir,
+            delete_after: delete_after,
+            notifier: notifier,
+            reported: HashSet::new(),
+            job: BackgroundJob::new(),
+        }
+    }
+
+    /// Returns top-level entries of the download directory which are referenced by the torrents
+    async fn get_referenced(&self, client: &dyn TorrentClient) -> GenericResult<HashSet<OsString>> {
+        let mut referenced = HashSet::new();
+
+        for torrent in client.get_torrents(TorrentFields::basic().with_files()).await? {
+            let relative_dir = match Path::new(&torrent.download_dir).strip_prefix(&self.download_dir) {
+                Ok(path) => path.to_owned(),
+                Err(_) => continue,
+            };
+
+            // The torrent may be located in a subdirectory of the download directory
+            if let Some(Component::Normal(name)) = relative_dir.components().next() {
+                referenced.insert(name.to_owned());
+                continue;
+            }
+
+            for file in torrent.files.as_ref().unwrap() {
+                if let Some(Component::Normal(name)) = Path::new(&file.name).components().next() {
+                    let mut part_name = name.to_owned();
+                    part_name.push(".part");
+
+                    referenced.insert(name.to_owned());
+                    referenced.insert(part_name);
+                }
+            }
+        }
+
+        Ok(referenced)
+    }
+}
+
+#[async_trait]
+impl Task for OrphanedFilesCleaner {
+    fn name(&self) -> &'static str {
+        "Orphaned files cleanup"
+    }
+
+    async fn run(&mut self, client: &dyn TorrentClient) -> EmptyResult {
+        if self.job.is_running() {
+            return match self.job.result().await {
+                Some(Ok(reported)) => {
+                    self.reported = reported;
+                    Ok(())
+                },
+                Some(Err(e)) => Err(e),
+                None => Ok(()),
+            };
+        }
+
+        // List the directory before getting the torrents to not treat the files of just added torrents as orphaned
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.download_dir).map_err(|e| format!(
+            "Unable to read '{}': {}", self.download_dir.display(), e))? {
+            let name = entry?.file_name();
+            if !name.to_string_lossy().starts_with('.') {
+                entries.push(name);
+            }
+        }
+
+        let referenced = self.get_referenced(client).await?;
+        let orphaned: Vec<OsString> = entries.into_iter().filter(|name| !referenced.contains(name)).collect();
+
+        let download_dir = self.download_dir.clone();
+        let delete_after = self.delete_after;
+        let notifier = self.notifier.clone();
+        let reported = mem::take(&mut self.reported);
+
+        self.job.start(move || handle_orphaned(&download_dir, orphaned, delete_after, &notifier, reported));
+        Ok(())
+    }
+
+    fn in_progress(&self) -> bool {
+        self.job.is_running()
+    }
+
+    fn is_heavy(&self) -> bool {
+        true
+    }
+}
+
+/// Reports and deletes the orphaned files returning the reported ones
+fn handle_orphaned(
+    download_dir: &Path, orphaned: Vec<OsString>, delete_after: Option<Duration>, notifier: &Arc<Notifier>,
+    mut reported: HashSet<OsString>,
+) -> GenericResult<HashSet<OsString>> {
+    let mut report = String::new();
+    let mut reclaimed_space = 0;
+
+    for name in &orphaned {
+        let path = download_dir.join(name);
+        let size = util::fs::get_total_size(&path)?;
+
+        let age = fs::symlink_metadata(&path)?.modified()?.elapsed().unwrap_or_default();
+        let delete = delete_after.is_some_and(|delete_after| age >= delete_after);
+
+        if delete {
+            info!(action = "delete"; "Deleting orphaned '{}' ({})...", path.display(), format_size(size));
+
+            let result = if fs::symlink_metadata(&path)?.is_dir() {
+                fs::remove_dir_all(&path)
+            } else {
+                fs::remove_file(&path)
+            };
+
+            if let Err(e) = result {
+                error!("Failed to delete '{}': {}.", path.display(), e);
+                continue;
+            }
+
+            report += &format!("* {} ({}) - deleted\n", name.to_string_lossy(), format_size(size));
+            reclaimed_space += size;
+            reported.remove(name);
+        } else if reported.insert(name.clone()) {
+            warn!("'{}' ({}) doesn't belong to any torrent.", path.display(), format_size(size));
+            report += &format!("* {} ({})\n", name.to_string_lossy(), format_size(size));
+        }
+    }
+
+    reported.retain(|name| orphaned.contains(name));
+
+    if !report.is_empty() {
+        if reclaimed_space != 0 {
+            report += &format!("\nReclaimed space: {}.", format_size(reclaimed_space));
+        }
+        notifier.notify_in_background(Event::Report, &format!(
+            "Orphaned files in '{}'", download_dir.display()), &report);
+    }
+
+    Ok(reported)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use tokio::runtime::Runtime;
+
+    use crate::tasks::run_to_completion;
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    #[test]
+    fn test_orphaned_files() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+
+        let temp_dir = TempDir::new().unwrap();
+        let download_dir = temp_dir.path();
+
+        for name in ["torrent", "single.mkv.part", "orphaned", ".hidden"] {
+            fs::write(download_dir.join(name), name).unwrap();
+        }
+        fs::create_dir(download_dir.join("subdir")).unwrap();
+        fs::create_dir(download_dir.join("orphaned-dir")).unwrap();
+        fs::write(download_dir.join("orphaned-dir/file"), "data").unwrap();
+
+        let mut torrent = MockTorrent::new(1, "torrent", download_dir.to_str().unwrap());
+        torrent.files = vec![(s!("torrent"), 7, true)];
+        server.add_torrent(torrent);
+
+        let mut torrent = MockTorrent::new(2, "single", download_dir.to_str().unwrap()).downloading();
+        torrent.files = vec![(s!("single.mkv"), 100, true)];
+        server.add_torrent(torrent);
+
+        server.add_torrent(MockTorrent::new(3, "other", download_dir.join("subdir").to_str().unwrap()));
+
+        let client = server.client();
+        let mut cleaner = OrphanedFilesCleaner::new(
+            download_dir.to_owned(), Some(Duration::ZERO), Arc::new(Notifier::new(None)));
+        runtime.block_on(run_to_completion(&mut cleaner, &client)).unwrap();
+
+        let mut names: Vec<String> = fs::read_dir(download_dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string()).collect();
+        names.sort();
+
+        assert_eq!(names, vec![".hidden", "single.mkv.part", "subdir", "torrent"]);
+    }
+}
@@ -0,0 +1,175 @@
+//! Peer port forwarding via NAT-PMP (RFC 6886) for the VPN providers which assign dynamic forwarded ports
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use tokio::net::UdpSocket;
+
+use crate::common::{EmptyResult, GenericResult};
+use crate::config;
+use crate::torrent_client::TorrentClient;
+
+use super::Task;
+
+const NAT_PMP_PORT: u16 = 5351;
+const INITIAL_TIMEOUT: Duration = Duration::from_millis(250);
+const MAX_ATTEMPTS: usize = 5;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct PortForwardingConfig {
+    /// NAT-PMP gateway (the VPN server's internal address, like 10.2.0.1)
+    pub gateway: Option<IpAddr>,
+    /// Lifetime of the mapping which is requested. The mapping is renewed after half of it.
+    #[serde(deserialize_with = "config::deserialize_duration")]
+    pub lifetime: Duration,
+}
+
+impl Default for PortForwardingConfig {
+    fn default() -> PortForwardingConfig {
+        PortForwardingConfig {
+            gateway: None,
+            lifetime: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Protocol {
+    Udp = 1,
+    Tcp = 2,
+}
+
+/// Requests (renews) the port mappings and sets Transmission's peer port to the forwarded one
+pub struct PortForwarder {
+    gateway: SocketAddr,
+    lifetime: Duration,
+}
+
+impl PortForwarder {
+    pub fn new(gateway: IpAddr, lifetime: Duration) -> PortForwarder {
+        PortForwarder {
+            gateway: SocketAddr::new(gateway, NAT_PMP_PORT),
+            lifetime: lifetime,
+        }
+    }
+
+    /// Returns the mapped external port
+    async fn map_port(&self, protocol: Protocol, port: u16) -> GenericResult<u16> {
+        let socket = UdpSocket::bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0)).await?;
+        socket.connect(self.gateway).await?;
+
+        // The same external port is suggested to get the same mapping on renewal
+        let mut request = vec![0, protocol as u8, 0, 0];
+        request.extend(port.to_be_bytes());
+        request.extend(port.to_be_bytes());
+        request.extend((self.lifetime.as_secs() as u32).to_be_bytes());
+
+        let mut timeout = INITIAL_TIMEOUT;
+
+        for _ in 0..MAX_ATTEMPTS {
+            socket.send(&request).await?;
+
+            let mut response = [0; 16];
+            match tokio::time::timeout(timeout, socket.recv(&mut response)).await {
+                Ok(result) => return parse_response(protocol, &response[..result?]),
+                Err(_) => timeout *= 2,
+            }
+        }
+
+        Err!("{} gateway doesn't respond", self.gateway.ip())
+    }
+}
+
+#[async_trait]
+impl Task for PortForwarder {
+    fn name(&self) -> &'static str {
+        "Port forwarding"
+    }
+
+    async fn run(&mut self, client: &dyn TorrentClient) -> EmptyResult {
+        let port = client.get_peer_port().await?;
+
+        let error = |protocol, e| format!("Failed to map {} {:?} port via NAT-PMP: {}", port, protocol, e);
+        let udp_port = self.map_port(Protocol::Udp, port).await.map_err(|e| error(Protocol::Udp, e))?;
+        let tcp_port = self.map_port(Protocol::Tcp, port).await.map_err(|e| error(Protocol::Tcp, e))?;
+
+        // TCP is used for most of the peer connections
+        if udp_port != tcp_port {
+            warn!("The gateway has mapped different UDP ({}) and TCP ({}) ports.", udp_port, tcp_port);
+        }
+
+        if tcp_port == port {
+            debug!("Peer port {} mapping has been renewed.", port);
+            return Ok(());
+        }
+
+        info!("Forwarded port has changed to {}. Changing Transmission's peer port from {}...", tcp_port, port);
+        client.set_peer_port(tcp_port).await?;
+
+        Ok(())
+    }
+}
+
+fn parse_response(protocol: Protocol, response: &[u8]) -> GenericResult<u16> {
+    if response.len() != 16 || response[0] != 0 || response[1] != 128 + protocol as u8 {
+        return Err!("got an invalid response");
+    }
+
+    let result = u16::from_be_bytes([response[2], response[3]]);
+    if result != 0 {
+        return Err!("the gateway has rejected the request: {}", match result {
+            1 => "unsupported version",
+            2 => "not authorized",
+            3 => "network failure",
+            4 => "out of resources",
+            5 => "unsupported opcode",
+            _ => "unknown error",
+        });
+    }
+
+    Ok(u16::from_be_bytes([response[10], response[11]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::mock::MockServer;
+
+    use super::*;
+
+    #[test]
+    fn test_port_forwarding() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+        let client = server.client();
+        server.state().peer_port = 51413;
+
+        // The gateway maps all ports to 40000
+        let gateway = runtime.block_on(UdpSocket::bind("127.0.0.1:0")).unwrap();
+        let mut forwarder = PortForwarder::new(IpAddr::V4(Ipv4Addr::LOCALHOST), Duration::from_secs(60));
+        forwarder.gateway = gateway.local_addr().unwrap();
+
+        runtime.spawn(async move {
+            let mut request = [0; 12];
+            loop {
+                let (_, address) = gateway.recv_from(&mut request).await.unwrap();
+                let mut response = vec![0, 128 + request[1], 0, 0, 0, 0, 0, 0, request[4], request[5], 0x9c, 0x40];
+                response.extend(&request[8..12]);
+                gateway.send_to(&response, address).await.unwrap();
+            }
+        });
+
+        runtime.block_on(forwarder.run(&client)).unwrap();
+        assert_eq!(server.state().peer_port, 40000);
+
+        runtime.block_on(forwarder.run(&client)).unwrap();
+        assert_eq!(server.state().peer_port, 40000);
+
+        assert!(parse_response(Protocol::Tcp, &[0, 130, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+        assert!(parse_response(Protocol::Tcp, &[0, 129, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+}
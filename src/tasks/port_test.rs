@@ -0,0 +1,75 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use async_trait::async_trait;
+
+use crate::common::EmptyResult;
+use crate::notifications::{Event, Notifier};
+use crate::util::time::format_duration;
+use crate::torrent_client::TorrentClient;
+
+use super::Task;
+
+// The test is performed via an external service, so don't trust a single failure
+const MIN_FAILED_CHECKS: u32 = 2;
+
+/// Periodically checks peer port reachability (it's often lost after router reboots)
+pub struct PortTester {
+    notifier: Arc<Notifier>,
+    closed_since: Option<Instant>,
+    failed_checks: u32,
+    notified: bool,
+}
+
+impl PortTester {
+    pub fn new(notifier: Arc<Notifier>) -> PortTester {
+        PortTester {
+            notifier: notifier,
+            closed_since: None,
+            failed_checks: 0,
+            notified: false,
+        }
+    }
+}
+
+#[async_trait]
+impl Task for PortTester {
+    fn name(&self) -> &'static str {
+        "Port test"
+    }
+
+    async fn run(&mut self, client: &dyn TorrentClient) -> EmptyResult {
+        let port = client.get_peer_port().await?;
+        let is_open = client.test_port().await?;
+
+        if is_open {
+            debug!("Peer port {} is open.", port);
+
+            if let (true, Some(closed_since)) = (self.notified, self.closed_since) {
+                info!("Peer port {} is reachable again.", port);
+                self.notifier.notify_in_background(Event::Recovered, "Peer port is reachable again", &format!(
+                    "Peer port {} is reachable again after being closed for {}.",
+                    port, format_duration(closed_since.elapsed().as_secs())));
+            }
+
+            self.closed_since = None;
+            self.failed_checks = 0;
+            self.notified = false;
+
+            return Ok(());
+        }
+
+        let closed_since = *self.closed_since.get_or_insert_with(Instant::now);
+        self.failed_checks += 1;
+        warn!("Peer port {} is not reachable.", port);
+
+        if !self.notified && self.failed_checks >= MIN_FAILED_CHECKS {
+            self.notifier.notify_in_background(Event::Problem, "Peer port is not reachable", &format!(
+                "Peer port {} is not reachable from the Internet for at least {}.",
+                port, format_duration(closed_since.elapsed().as_secs())));
+            self.notified = true;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use time::OffsetDateTime;
+
+use crate::common::{EmptyResult, GenericResult};
+use crate::config;
+use crate::disk_space::{self, DiskSpace, format_size};
+use crate::email::EmailTemplate;
+use crate::history::{History, HistoryRecord};
+use crate::metrics::METRICS;
+use crate::notifications::{Event, Notifier};
+use crate::traffic::Traffic;
+use crate::torrent_client::TorrentClient;
+use crate::transmissionrpc::{SessionStats, Torrent, TorrentFields};
+use crate::util::time::{Timestamp, format_duration};
+
+use super::Task;
+
+const DEFAULT_SUBJECT: &str = "Transmission statistics for the last {{period}}";
+const DEFAULT_BODY: &str = "\
+Torrents: {{added}} added, {{completed}} completed, {{removed}} removed ({{torrents}} in total).
+Traffic: {{downloaded}} downloaded, {{uploaded}} uploaded.
+
+Traffic by tracker:
+{{tracker_traffic}}
+
+Completed torrents:
+{{downloads}}
+
+Ratio by tracker:
+{{trackers}}
+
+Disk space:
+{{disk_space}}
+
+Errors: {{copy_failures}} failed copies, {{rpc_errors}} failed checks.
+";
+
+/// Periodic statistics report (a weekly digest, for example)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ReportConfig {
+    #[serde(deserialize_with = "config::deserialize_optional_duration")]
+    pub interval: Option<Duration>,
+    /// Email template: the first line is the subject, then an empty line and the body
+    pub template: Option<PathBuf>,
+}
+
+/// The state at the start of the report period
+struct Snapshot {
+    time: Timestamp,
+    torrents: HashSet<String>,
+    traffic: SessionStats,
+    tracker_traffic: BTreeMap<String, Traffic>,
+    free_space: BTreeMap<PathBuf, DiskSpace>,
+    copy_failures: u64,
+    rpc_errors: u64,
+}
+
+/// Sends the statistics collected since the previous run. The statistics are kept in memory, so the first report
+/// after restart covers the time since the daemon start.
+pub struct StatisticsReport {
+    download_dir: PathBuf,
+    template: Option<PathBuf>,
+    history: Arc<History>,
+    notifier: Arc<Notifier>,
+    snapshot: Option<Snapshot>,
+}
+
+impl StatisticsReport {
+    pub fn new(
+        config: &ReportConfig, download_dir: PathBuf, history: Arc<History>, notifier: Arc<Notifier>,
+    ) -> StatisticsReport {
+        StatisticsReport {
+            download_dir: download_dir,
+            template: config.template.clone(),
+            history: history,
+            notifier: notifier,
+            snapshot: None,
+        }
+    }
+
+    async fn take_snapshot(&self, client: &dyn TorrentClient) -> GenericResult<(Snapshot, Vec<Torrent>)> {
+        let torrents = client.get_torrents(TorrentFields::basic()).await?;
+        let traffic = client.get_session_stats().await?;
+
+        // Destination directories are reported by the copying
+        let mut free_space: BTreeMap<_, _> = METRICS.get_free_space().into_iter().collect();
+        free_space.insert(
+            self.download_dir.clone(), disk_space::get_download_dir_space(client, &self.download_dir).await?);
+
+        let snapshot = Snapshot {
+            time: OffsetDateTime::now_utc().unix_timestamp(),
+            torrents: torrents.iter().map(|torrent| torrent.hash.clone()).collect(),
+            traffic: traffic,
+            tracker_traffic: METRICS.get_tracker_traffic(),
+            free_space: free_space,
+            copy_failures: METRICS.get_copy_failures(),
+            rpc_errors: METRICS.get_rpc_errors(),
+        };
+
+        Ok((snapshot, torrents))
+    }
+
+    fn get_template(&self) -> GenericResult<EmailTemplate> {
+        Ok(match self.template {
+            Some(ref path) => EmailTemplate::new_from_file(path).map_err(|e| format!(
+                "Unable to read '{}' report template: {}", path.display(), e))?,
+            None => EmailTemplate::new(DEFAULT_SUBJECT, DEFAULT_BODY),
+        })
+    }
+}
+
+#[async_trait]
+impl Task for StatisticsReport {
+    fn name(&self) -> &'static str {
+        "Statistics report"
+    }
+
+    async fn run(&mut self, client: &dyn TorrentClient) -> EmptyResult {
+        let template = self.get_template()?;
+        let (snapshot, torrents) = self.take_snapshot(client).await?;
+
+        // The first run only starts the report period
+        let previous = match self.snapshot.replace(snapshot) {
+            Some(previous) => previous,
+            None => return Ok(()),
+        };
+
+        let current = self.snapshot.as_ref().unwrap();
+
+        // The history also has the torrents which have been removed during the period
+        let completed = if self.history.is_enabled() {
+            Some(self.history.get_completed(previous.time + 1, current.time + 1)?)
+        } else {
+            None
+        };
+
+        let params = get_params(&previous, current, &torrents, completed.as_deref());
+        let (subject, body) = template.render(&params)?;
+
+        info!("Sending the statistics report for the last {}...", params["period"]);
+        self.notifier.notify_in_background(Event::Report, &subject, &body);
+
+        Ok(())
+    }
+}
+
+fn get_params(
+    previous: &Snapshot, current: &Snapshot, torrents: &[Torrent], completed: Option<&[HistoryRecord]>,
+) -> HashMap<&'static str, String> {
+    let in_period = |time: Timestamp| time > previous.time && time <= current.time;
+
+    let added = torrents.iter().filter(|torrent| in_period(torrent.added_time)).count();
+    let completed: Vec<(&str, u64)> = match completed {
+        Some(records) => records.iter().map(|record| (record.name.as_str(), record.size)).collect(),
+        None => torrents.iter().filter(|torrent| torrent.done_time.is_some_and(in_period)).map(|torrent| {
+            (torrent.name.as_str(), torrent.size)
+        }).collect(),
+    };
+    let removed = previous.torrents.difference(&current.torrents).count();
+
+    // Uploaded and total size of the torrents
+    let mut trackers: BTreeMap<String, (f64, u64, usize)> = BTreeMap::new();
+    for torrent in torrents {
+        for host in torrent.tracker_hosts() {
+            let stats = trackers.entry(host).or_default();
+            stats.0 += torrent.upload_ratio.unwrap_or_default() * torrent.size as f64;
+            stats.1 += torrent.size;
+            stats.2 += 1;
+        }
+    }
+
+    let trackers: Vec<String> = trackers.iter().map(|(host, &(uploaded, size, count))| {
+        let ratio = if size == 0 { 0.0 } else { uploaded / size as f64 };
+        format!("* {}: {:.2} ({} torrents)", host, ratio, count)
+    }).collect();
+
+    // The trackers which upload the most go first
+    let mut tracker_traffic: Vec<(&String, Traffic)> = current.tracker_traffic.iter().map(|(host, traffic)| {
+        let before = previous.tracker_traffic.get(host).copied().unwrap_or_default();
+        (host, Traffic {
+            uploaded: traffic.uploaded.saturating_sub(before.uploaded),
+            downloaded: traffic.downloaded.saturating_sub(before.downloaded),
+        })
+    }).filter(|(_, traffic)| *traffic != Traffic::default()).collect();
+    tracker_traffic.sort_by(|a, b| b.1.uploaded.cmp(&a.1.uploaded).then_with(|| a.0.cmp(b.0)));
+
+    let tracker_traffic: Vec<String> = tracker_traffic.iter().map(|(host, traffic)| {
+        format!("* {}: {} uploaded, {} downloaded",
+                host, format_size(traffic.uploaded), format_size(traffic.downloaded))
+    }).collect();
+
+    let disk_space: Vec<String> = current.free_space.iter().map(|(path, space)| {
+        let trend = match previous.free_space.get(path) {
+            Some(before) if space.free >= before.free => format!(" (+{})", format_size(space.free - before.free)),
+            Some(before) => format!(" (-{})", format_size(before.free - space.free)),
+            None => String::new(),
+        };
+        format!("* {}: {}{}", path.display(), space, trend)
+    }).collect();
+
+    let downloads: Vec<String> = completed.iter().map(|&(name, size)| {
+        format!("* {} ({})", name, format_size(size))
+    }).collect();
+
+    let list = |items: Vec<String>| if items.is_empty() { s!("* none") } else { items.join("\n") };
+    let traffic = |get: fn(&SessionStats) -> u64| {
+        format_size(get(&current.traffic).saturating_sub(get(&previous.traffic)))
+    };
+
+    HashMap::from([
+        ("period", format_duration(current.time.saturating_sub(previous.time).max(0) as u64)),
+        ("added", added.to_string()),
+        ("completed", completed.len().to_string()),
+        ("removed", removed.to_string()),
+        ("torrents", torrents.len().to_string()),
+        ("downloaded", traffic(|stats| stats.downloaded)),
+        ("uploaded", traffic(|stats| stats.uploaded)),
+        ("downloads", list(downloads)),
+        ("trackers", list(trackers)),
+        ("tracker_traffic", list(tracker_traffic)),
+        ("disk_space", list(disk_space)),
+        ("copy_failures", current.copy_failures.saturating_sub(previous.copy_failures).to_string()),
+        ("rpc_errors", current.rpc_errors.saturating_sub(previous.rpc_errors).to_string()),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    #[test]
+    fn test_statistics_report() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+        let client = server.client();
+
+        let mut torrent = MockTorrent::new(1, "old", "/downloads");
+        torrent.trackers = vec![s!("http://tracker.example.com/announce")];
+        torrent.files = vec![(s!("old"), 1000, true)];
+        torrent.upload_ratio = 2.0;
+        server.add_torrent(torrent);
+
+        let mut torrent = MockTorrent::new(2, "new", "/downloads");
+        torrent.trackers = vec![s!("http://tracker.example.com/announce"), s!("http://other.example.com/announce")];
+        torrent.files = vec![(s!("new"), 3000, true)];
+        torrent.added_date = 2_000_000;
+        torrent.done_date = 2_000_100;
+        server.add_torrent(torrent.clone().downloading());
+
+        torrent.id = 3;
+        torrent.hash = format!("{:040x}", 3);
+        server.add_torrent(torrent.clone());
+
+        {
+            let mut state = server.state();
+            state.downloaded = 5 * 1024 * 1024;
+            state.uploaded = 1024;
+            state.free_space = 10 * 1024 * 1024;
+        }
+
+        let report = StatisticsReport::new(&ReportConfig::default(), PathBuf::from("/downloads"),
+                                           Arc::new(History::new(None, false)), Arc::new(Notifier::new(None)));
+        let (current, torrents) = runtime.block_on(report.take_snapshot(&client)).unwrap();
+
+        let previous = Snapshot {
+            time: 1_500_000,
+            torrents: [format!("{:040x}", 1), format!("{:040x}", 4)].into(),
+            traffic: SessionStats {downloaded: 1024 * 1024, uploaded: 1024},
+            tracker_traffic: BTreeMap::from([
+                (s!("idle.example.com"), Traffic {uploaded: 1024, downloaded: 0}),
+                (s!("tracker.example.com"), Traffic {uploaded: 1024, downloaded: 0}),
+            ]),
+            free_space: BTreeMap::from([
+                (PathBuf::from("/downloads"), DiskSpace {free: 12 * 1024 * 1024, total: None}),
+            ]),
+            copy_failures: current.copy_failures,
+            rpc_errors: current.rpc_errors,
+        };
+
+        let mut current = current;
+        current.tracker_traffic = BTreeMap::from([
+            (s!("idle.example.com"), Traffic {uploaded: 1024, downloaded: 0}),
+            (s!("other.example.com"), Traffic {uploaded: 1024, downloaded: 2048}),
+            (s!("tracker.example.com"), Traffic {uploaded: 3072, downloaded: 0}),
+        ]);
+
+        let params = get_params(&previous, &current, &torrents, None);
+        assert_eq!(params["added"], "2");
+        assert_eq!(params["completed"], "1");
+        assert_eq!(params["downloads"], "* new (2.9 KB)");
+        assert_eq!(params["removed"], "1");
+        assert_eq!(params["torrents"], "3");
+        assert_eq!(params["downloaded"], "4.0 MB");
+        assert_eq!(params["uploaded"], "0 B");
+        assert_eq!(params["trackers"], [
+            "* other.example.com: 0.00 (2 torrents)",
+            "* tracker.example.com: 0.29 (3 torrents)",
+        ].join("\n"));
+
+        assert_eq!(params["tracker_traffic"], [
+            "* tracker.example.com: 2.0 KB uploaded, 0 B downloaded",
+            "* other.example.com: 1.0 KB uploaded, 2.0 KB downloaded",
+        ].join("\n"));
+
+        // The destination directories are shared with the other tests via the metrics
+        let disk_space = "* /downloads: 10.0 MB free of 100.0 GB (0%) (-2.0 MB)";
+        assert!(params["disk_space"].lines().any(|line| line == disk_space));
+        assert_eq!(params["copy_failures"], "0");
+
+        let (subject, body) = report.get_template().unwrap().render(&params).unwrap();
+        assert!(subject.starts_with("Transmission statistics for the last "));
+        assert!(body.starts_with("Torrents: 2 added, 1 completed, 1 removed (3 in total).\n"));
+    }
+}
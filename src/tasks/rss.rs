@@ -0,0 +1,389 @@
+//! RSS/Atom feeds auto-downloader: adds the feed items which match the feed's filters.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use regex::Regex;
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::common::{EmptyResult, GenericResult};
+use crate::config;
+use crate::torrent_client::{ClientError, TorrentClient};
+use crate::transmissionrpc::TorrentSource;
+use crate::util::matching;
+
+use super::Task;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RssConfig {
+    #[serde(deserialize_with = "config::deserialize_duration")]
+    pub check_interval: Duration,
+    /// File to store IDs of the already seen feed items in to not add them again after restart
+    pub state_file: Option<PathBuf>,
+    pub feeds: Vec<Feed>,
+}
+
+impl Default for RssConfig {
+    fn default() -> RssConfig {
+        RssConfig {
+            check_interval: Duration::from_secs(15 * 60),
+            state_file: None,
+            feeds: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Feed {
+    pub name: String,
+    pub url: String,
+
+    /// Add only the items with matching titles
+    #[serde(default, deserialize_with = "matching::deserialize_optional_regex")]
+    pub include: Option<Regex>,
+    /// Don't add the items with matching titles
+    #[serde(default, deserialize_with = "matching::deserialize_optional_regex")]
+    pub exclude: Option<Regex>,
+
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Download directory (absolute or relative to the download directory)
+    pub download_dir: Option<PathBuf>,
+    /// Download the pieces in order, so the files can be watched before the download completes
+    #[serde(default)]
+    pub sequential_download: bool,
+}
+
+impl Feed {
+    fn matches(&self, title: &str) -> bool {
+        self.include.as_ref().is_none_or(|regex| regex.is_match(title)) &&
+        !self.exclude.as_ref().is_some_and(|regex| regex.is_match(title))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct FeedItem {
+    id: String,
+    title: String,
+    url: String,
+}
+
+pub struct RssFeeds {
+    feeds: Vec<Feed>,
+    download_dir: PathBuf,
+    http: Client,
+    state_file: Option<PathBuf>,
+    /// Feed name -> IDs of the seen items
+    seen: HashMap<String, HashSet<String>>,
+}
+
+impl RssFeeds {
+    pub fn new(config: &RssConfig, download_dir: PathBuf) -> RssFeeds {
+        let seen = match config.state_file {
+            Some(ref path) => load_state(path).unwrap_or_else(|e| {
+                error!("Failed to load RSS feeds state from {:?}: {}.", path, e);
+                HashMap::new()
+            }),
+            None => HashMap::new(),
+        };
+
+        RssFeeds {
+            feeds: config.feeds.clone(),
+            download_dir: download_dir,
+            http: Client::new(),
+            state_file: config.state_file.clone(),
+            seen: seen,
+        }
+    }
+
+    async fn check(&self, client: &dyn TorrentClient, feed: &Feed, seen: &mut HashSet<String>) -> EmptyResult {
+        let response = self.http.get(&feed.url).timeout(REQUEST_TIMEOUT).send().await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Failed to fetch '{}' feed: {}", feed.name, e))?;
+        let body = response.text().await.map_err(|e| format!("Failed to fetch '{}' feed: {}", feed.name, e))?;
+        let items = parse_feed(&body);
+
+        let download_dir = match feed.download_dir {
+            Some(ref path) => {
+                let path = self.download_dir.join(path);
+                Some(path.to_str().ok_or_else(|| format!("Invalid path: {:?}", path))?.to_owned())
+            },
+            None => None,
+        };
+
+        for item in &items {
+            if seen.contains(&item.id) {
+                continue;
+            }
+
+            if !feed.matches(&item.title) {
+                debug!("Skipping '{}' from '{}' feed: it doesn't match the filters.", item.title, feed.name);
+                seen.insert(item.id.clone());
+                continue;
+            }
+
+            match client.add_torrent(TorrentSource::Url(&item.url), download_dir.as_deref(), &feed.labels).await {
+                Ok(torrent) => if torrent.duplicate {
+                    info!("'{}' torrent from '{}' feed already exists.", torrent.name, feed.name);
+                } else {
+                    info!("'{}' torrent has been added from '{}' feed.", torrent.name, feed.name);
+
+                    if feed.sequential_download {
+                        if let Err(err) = client.set_sequential_download(&torrent.hash, true).await {
+                            error!("Failed to enable sequential download for '{}' torrent: {}.", torrent.name, err);
+                        }
+                    }
+                },
+
+                // The item has been rejected by the client, so don't try to add it again
+                Err(ClientError::Rejected(error)) => {
+                    error!("Failed to add '{}' from '{}' feed: {}.", item.title, feed.name, error);
+                },
+
+                Err(err) => return Err!("Failed to add '{}' from '{}' feed: {}", item.title, feed.name, err),
+            }
+
+            seen.insert(item.id.clone());
+        }
+
+        // Forget the items which have gone from the feed to not let the state grow infinitely
+        seen.retain(|id| items.iter().any(|item| &item.id == id));
+
+        Ok(())
+    }
+
+    fn save_state(&self) -> EmptyResult {
+        let path = match self.state_file {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+
+        let data = serde_json::to_string(&self.seen)?;
+        fs::write(&temp_path, data).and_then(|_| fs::rename(&temp_path, path))
+            .map_err(|e| format!("Unable to write {:?}: {}", path, e))?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Task for RssFeeds {
+    fn name(&self) -> &'static str {
+        "RSS feeds"
+    }
+
+    async fn run(&mut self, client: &dyn TorrentClient) -> EmptyResult {
+        let mut result = Ok(());
+
+        for feed in &self.feeds {
+            let mut seen = self.seen.get(&feed.name).cloned().unwrap_or_default();
+
+            if let Err(err) = self.check(client, feed, &mut seen).await {
+                warn!("{}.", err);
+                result = Err!("Failed to check some of the feeds");
+            }
+
+            self.seen.insert(feed.name.clone(), seen);
+        }
+
+        self.seen.retain(|name, _| self.feeds.iter().any(|feed| &feed.name == name));
+        self.save_state()?;
+
+        result
+    }
+}
+
+fn load_state(path: &Path) -> GenericResult<HashMap<String, HashSet<String>>> {
+    match fs::read_to_string(path) {
+        Ok(data) => Ok(serde_json::from_str(&data)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// A simple RSS 2.0 and Atom parser which extracts only the fields we need. Torrent URL is taken from the item's
+/// enclosure or link, item ID - from its guid (id), falling back to the torrent URL.
+fn parse_feed(data: &str) -> Vec<FeedItem> {
+    static ITEM_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(
+        r"(?s)<item\b.*?</item>|<entry\b.*?</entry>").unwrap());
+
+    let mut items = Vec::new();
+
+    for item in ITEM_REGEX.find_iter(data) {
+        let item = item.as_str();
+
+        let url = get_attribute(item, "enclosure", "url")
+            .or_else(|| get_attribute(item, "link", "href"))
+            .or_else(|| get_element(item, "link"));
+        let url = match url {
+            Some(url) if !url.is_empty() => url,
+            _ => continue,
+        };
+
+        let id = get_element(item, "guid").or_else(|| get_element(item, "id"))
+            .filter(|id| !id.is_empty()).unwrap_or_else(|| url.clone());
+
+        items.push(FeedItem {
+            id: id,
+            title: get_element(item, "title").unwrap_or_default(),
+            url: url,
+        });
+    }
+
+    items
+}
+
+fn get_element(data: &str, name: &str) -> Option<String> {
+    let regex = Regex::new(&format!(r"(?s)<{name}(?:\s[^>]*)?>(.*?)</{name}>", name = name)).unwrap();
+    let value = regex.captures(data)?.get(1).unwrap().as_str().trim();
+
+    Some(match value.strip_prefix("<![CDATA[").and_then(|value| value.strip_suffix("]]>")) {
+        Some(value) => value.trim().to_owned(),
+        None => unescape(value),
+    })
+}
+
+fn get_attribute(data: &str, element: &str, name: &str) -> Option<String> {
+    let regex = Regex::new(&format!(
+        r#"<{element}\s[^>]*?\b{name}\s*=\s*(?:"([^"]*)"|'([^']*)')"#, element = element, name = name)).unwrap();
+    let captures = regex.captures(data)?;
+    Some(unescape(captures.get(1).or_else(|| captures.get(2)).unwrap().as_str().trim()))
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<").replace("&gt;", ">")
+        .replace("&quot;", "\"").replace("&apos;", "'").replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::mock::MockServer;
+
+    use super::*;
+
+    const RSS_FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0">
+  <channel>
+    <title>Tracker</title>
+    <item>
+      <title>Show S01E01 1080p</title>
+      <guid isPermaLink="false">show-1</guid>
+      <enclosure url="http://tracker.example.com/download?id=1&amp;key=secret" type="application/x-bittorrent"/>
+    </item>
+    <item>
+      <title><![CDATA[Show S01E02 720p]]></title>
+      <link>magnet:?xt=urn:btih:0002&amp;dn=Show S01E02 720p</link>
+    </item>
+    <item>
+      <title>Show S01E03 1080p</title>
+      <link>magnet:?xt=urn:btih:0003&amp;dn=Show S01E03 1080p</link>
+    </item>
+  </channel>
+</rss>"#;
+
+    #[test]
+    fn test_parsing() {
+        assert_eq!(parse_feed(RSS_FEED), vec![FeedItem {
+            id: s!("show-1"),
+            title: s!("Show S01E01 1080p"),
+            url: s!("http://tracker.example.com/download?id=1&key=secret"),
+        }, FeedItem {
+            id: s!("magnet:?xt=urn:btih:0002&dn=Show S01E02 720p"),
+            title: s!("Show S01E02 720p"),
+            url: s!("magnet:?xt=urn:btih:0002&dn=Show S01E02 720p"),
+        }, FeedItem {
+            id: s!("magnet:?xt=urn:btih:0003&dn=Show S01E03 1080p"),
+            title: s!("Show S01E03 1080p"),
+            url: s!("magnet:?xt=urn:btih:0003&dn=Show S01E03 1080p"),
+        }]);
+
+        let atom = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+            <entry>
+                <id>urn:uuid:1</id>
+                <title type="text">Movie</title>
+                <link rel="enclosure" href="http://example.com/movie.torrent"/>
+            </entry>
+        </feed>"#;
+
+        assert_eq!(parse_feed(atom), vec![FeedItem {
+            id: s!("urn:uuid:1"),
+            title: s!("Movie"),
+            url: s!("http://example.com/movie.torrent"),
+        }]);
+    }
+
+    #[test]
+    fn test_rss_feeds() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+
+        let feed_url = runtime.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}/feed", listener.local_addr().unwrap());
+
+            tokio::spawn(async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    let mut request = [0; 4096];
+                    let _ = stream.read(&mut request).await.unwrap();
+
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/rss+xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        RSS_FEED.len(), RSS_FEED);
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                }
+            });
+
+            url
+        });
+
+        let temp_dir = TempDir::new().unwrap();
+        let config: RssConfig = toml::from_str(&format!(r#"
+            state-file = "{state_file}"
+
+            [[feeds]]
+            name = "shows"
+            url = "{url}"
+            include = "1080p"
+            exclude = "S01E03"
+            labels = ["tv"]
+            download-dir = "tv"
+        "#, state_file = temp_dir.path().join("rss.json").display(), url = feed_url)).unwrap();
+
+        let client = server.client();
+        let mut feeds = RssFeeds::new(&config, PathBuf::from("/downloads"));
+        runtime.block_on(feeds.run(&client)).unwrap();
+
+        let torrents: Vec<(String, String, Vec<String>)> = server.state().torrents.iter()
+            .map(|torrent| (torrent.name.clone(), torrent.download_dir.clone(), torrent.labels.clone()))
+            .collect();
+        assert_eq!(torrents, vec![
+            (s!("download"), s!("/downloads/tv"), vec![s!("tv")]),
+        ]);
+
+        // The seen items mustn't be added again even after restart
+        server.state().torrents.clear();
+        let mut feeds = RssFeeds::new(&config, PathBuf::from("/downloads"));
+        runtime.block_on(feeds.run(&client)).unwrap();
+        assert!(server.state().torrents.is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::common::EmptyResult;
+use crate::config;
+use crate::notifications::{Event, Notifier};
+use crate::torrent_client::TorrentClient;
+use crate::transmissionrpc::{Torrent, TorrentFields};
+use crate::util::matching;
+use crate::util::time::Timestamp;
+
+use super::Task;
+
+/// Monitoring of announce results: notifies when a tracker fails the specified number of consecutive announces (it's
+/// down, doesn't know the torrent, rate limits us, etc.)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TrackerMonitoringConfig {
+    #[serde(deserialize_with = "config::deserialize_optional_duration")]
+    pub check_interval: Option<Duration>,
+    pub max_failures: u32,
+}
+
+impl Default for TrackerMonitoringConfig {
+    fn default() -> TrackerMonitoringConfig {
+        TrackerMonitoringConfig {
+            check_interval: None,
+            max_failures: 3,
+        }
+    }
+}
+
+struct AnnounceState {
+    time: Timestamp,
+    error: Option<String>,
+    failures: u32,
+}
+
+#[derive(Debug, PartialEq)]
+enum TrackerEvent {
+    /// Tracker host and the failing torrents with their last errors
+    Failing(String, Vec<(String, String)>),
+    Recovered(String),
+}
+
+pub struct TrackerMonitor {
+    max_failures: u32,
+    notifier: Arc<Notifier>,
+    /// (torrent hash, announce URL) -> the last announce
+    announces: HashMap<(String, String), AnnounceState>,
+    failing_trackers: HashSet<String>,
+}
+
+impl TrackerMonitor {
+    pub fn new(max_failures: u32, notifier: Arc<Notifier>) -> TrackerMonitor {
+        TrackerMonitor {
+            max_failures: max_failures,
+            notifier: notifier,
+            announces: HashMap::new(),
+            failing_trackers: HashSet::new(),
+        }
+    }
+
+    /// Updates announce states with the current tracker stats. Failures are grouped by tracker host to notify once
+    /// per tracker instead of once per torrent.
+    fn update(&mut self, torrents: &[Torrent]) -> Vec<TrackerEvent> {
+        let mut current = HashSet::new();
+        let mut trackers: BTreeMap<String, Vec<(String, String)>> = BTreeMap::new();
+
+        for torrent in torrents {
+            for stats in torrent.tracker_stats.iter().flatten() {
+                let time = match stats.last_announce_time {
+                    Some(time) => time,
+                    None => continue,
+                };
+
+                let key = (torrent.hash.clone(), stats.announce.clone());
+                let failed = stats.last_announce_error.is_some() as u32;
+
+                let state = self.announces.entry(key.clone()).or_insert_with(|| AnnounceState {
+                    time: time,
+                    error: stats.last_announce_error.clone(),
+                    failures: failed,
+                });
+
+                // Count each announce only once
+                if state.time != time {
+                    state.time = time;
+                    state.error = stats.last_announce_error.clone();
+                    state.failures = if failed != 0 { state.failures + 1 } else { 0 };
+                }
+
+                let host = match matching::get_url_host(&stats.announce) {
+                    Some(host) => host,
+                    None => continue,
+                };
+                let failures = trackers.entry(host).or_default();
+
+                if state.failures >= self.max_failures {
+                    if let Some(ref error) = state.error {
+                        failures.push((torrent.name.clone(), error.clone()));
+                    }
+                }
+
+                current.insert(key);
+            }
+        }
+
+        self.announces.retain(|key, _| current.contains(key));
+
+        let mut events = Vec::new();
+
+        for (host, failures) in trackers {
+            if failures.is_empty() {
+                if self.failing_trackers.remove(&host) {
+                    events.push(TrackerEvent::Recovered(host));
+                }
+            } else if self.failing_trackers.insert(host.clone()) {
+                events.push(TrackerEvent::Failing(host, failures));
+            }
+        }
+
+        // The tracker has gone with all its torrents
+        self.failing_trackers.retain(|host| torrents.iter().any(|torrent| torrent.tracker_hosts().contains(host)));
+
+        events
+    }
+}
+
+#[async_trait]
+impl Task for TrackerMonitor {
+    fn name(&self) -> &'static str {
+        "Tracker monitoring"
+    }
+
+    async fn run(&mut self, client: &dyn TorrentClient) -> EmptyResult {
+        let torrents = client.get_torrents(TorrentFields::basic().with_tracker_stats()).await?;
+
+        for event in self.update(&torrents) {
+            match event {
+                TrackerEvent::Failing(host, failures) => {
+                    let message = format!("'{}' tracker is failing", host);
+                    let details: Vec<String> = failures.iter()
+                        .map(|(name, error)| format!("* {}: {}", name, error))
+                        .collect();
+
+                    warn!("{}: {} torrents have failed {} consecutive announces.",
+                          message, failures.len(), self.max_failures);
+                    self.notifier.notify_in_background(Event::Problem, &message, &format!(
+                        "The following torrents have failed {} consecutive announces to '{}':\n{}",
+                        self.max_failures, host, details.join("\n")));
+                },
+                TrackerEvent::Recovered(host) => {
+                    let message = format!("'{}' tracker has recovered", host);
+                    info!("{}.", message);
+                    self.notifier.notify_in_background(Event::Recovered, &message, &format!("{}.", message));
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    #[test]
+    fn test_tracker_monitoring() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+        let client = server.client();
+
+        for id in 1..=2 {
+            let mut torrent = MockTorrent::new(id, &format!("torrent-{}", id), "/downloads");
+            torrent.trackers = vec![s!("http://tracker.example.com/announce"), s!("http://other.example.com/announce")];
+            server.add_torrent(torrent);
+        }
+
+        let mut monitor = TrackerMonitor::new(2, Arc::new(Notifier::new(None)));
+        let mut check = |time: Timestamp, error: Option<&str>| {
+            for torrent in &mut server.state().torrents {
+                torrent.announces = vec![(time, error.map(String::from)), (time, None)];
+            }
+            let torrents = runtime.block_on(client.get_torrents(TorrentFields::basic().with_tracker_stats())).unwrap();
+            monitor.update(&torrents)
+        };
+
+        let error = "Torrent not registered with this tracker";
+        assert_eq!(check(0, None), vec![]);
+        assert_eq!(check(100, Some(error)), vec![]);
+        assert_eq!(check(100, Some(error)), vec![]);
+        assert_eq!(check(200, Some(error)), vec![TrackerEvent::Failing(s!("tracker.example.com"), vec![
+            (s!("torrent-1"), s!(error)),
+            (s!("torrent-2"), s!(error)),
+        ])]);
+        assert_eq!(check(300, Some(error)), vec![]);
+        assert_eq!(check(400, None), vec![TrackerEvent::Recovered(s!("tracker.example.com"))]);
+    }
+}
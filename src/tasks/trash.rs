@@ -0,0 +1,153 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::common::EmptyResult;
+use crate::disk_space::format_size;
+use crate::torrent_client::TorrentClient;
+use crate::transmissionrpc::Torrent;
+use crate::util;
+
+use super::{BackgroundJob, Task};
+
+/// Moves data of the removed torrent to the trash directory. Each torrent gets its own directory named after its hash,
+/// so the torrents with the same name don't conflict and the directory's modification time tells the removal time.
+pub fn move_to_trash(trash_dir: &Path, torrent: &Torrent) -> EmptyResult {
+    let src_path = Path::new(&torrent.download_dir).join(&torrent.name);
+    if fs::symlink_metadata(&src_path).is_err() {
+        info!("'{}' doesn't exist: nothing to move to the trash.", src_path.display());
+        return Ok(());
+    }
+
+    move_path_to_trash(trash_dir, &torrent.hash, &src_path)
+}
+
+/// Moves the file or directory to the specified directory of the trash. The trash may be on another file system: the
+/// data is copied and deleted from the source then.
+pub fn move_path_to_trash(trash_dir: &Path, dir_name: &str, src_path: &Path) -> EmptyResult {
+    let target_dir = trash_dir.join(dir_name);
+    fs::create_dir_all(&target_dir).map_err(|e| format!("Failed to create '{}': {}", target_dir.display(), e))?;
+
+    let dst_path = target_dir.join(src_path.file_name().ok_or_else(|| format!(
+        "Invalid path: '{}'", src_path.display()))?);
+    if fs::symlink_metadata(&dst_path).is_ok() {
+        return Err!("'{}' already exists", dst_path.display());
+    }
+
+    util::fs::move_path(src_path, &dst_path)
+}
+
+/// Deletes the torrents which have been in the trash for longer than the retention period. Deleting large directories
+/// may take a while, so it's done in background.
+pub struct TrashCleaner {
+    cleaner: Arc<Cleaner>,
+    job: BackgroundJob<()>,
+}
+
+impl TrashCleaner {
+    pub fn new(path: PathBuf, retention: Duration) -> TrashCleaner {
+        TrashCleaner {
+            cleaner: Arc::new(Cleaner {
+                path: path,
+                retention: retention,
+            }),
+            job: BackgroundJob::new(),
+        }
+    }
+}
+
+struct Cleaner {
+    path: PathBuf,
+    retention: Duration,
+}
+
+impl Cleaner {
+    fn run(&self) -> EmptyResult {
+        let entries = match fs::read_dir(&self.path) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err!("Unable to read '{}': {}", self.path.display(), e),
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+
+            let age = fs::symlink_metadata(&path)?.modified()?.elapsed().unwrap_or_default();
+            if age < self.retention {
+                continue;
+            }
+
+            let size = util::fs::get_total_size(&path)?;
+            info!(action = "delete"; "Deleting '{}' from the trash ({})...", path.display(), format_size(size));
+
+            if let Err(e) = util::fs::remove_path(&path) {
+                error!("{}.", e);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Task for TrashCleaner {
+    fn name(&self) -> &'static str {
+        "Trash cleanup"
+    }
+
+    async fn run(&mut self, _client: &dyn TorrentClient) -> EmptyResult {
+        if self.job.is_running() {
+            return match self.job.result().await {
+                Some(result) => result,
+                None => Ok(()),
+            };
+        }
+
+        let cleaner = self.cleaner.clone();
+        self.job.start(move || cleaner.run());
+        Ok(())
+    }
+
+    fn in_progress(&self) -> bool {
+        self.job.is_running()
+    }
+
+    fn is_heavy(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use tokio::runtime::Runtime;
+
+    use crate::tasks::run_to_completion;
+    use crate::transmissionrpc::mock::MockServer;
+
+    use super::*;
+
+    #[test]
+    fn test_trash_cleanup() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+        let client = server.client();
+
+        let temp_dir = TempDir::new().unwrap();
+        let trash_dir = temp_dir.path();
+
+        fs::create_dir(trash_dir.join("torrent")).unwrap();
+        fs::write(trash_dir.join("torrent/file"), "data").unwrap();
+
+        let mut cleaner = TrashCleaner::new(trash_dir.to_owned(), Duration::from_secs(60 * 60));
+        runtime.block_on(run_to_completion(&mut cleaner, &client)).unwrap();
+        assert!(trash_dir.join("torrent/file").exists());
+
+        let mut cleaner = TrashCleaner::new(trash_dir.to_owned(), Duration::ZERO);
+        runtime.block_on(run_to_completion(&mut cleaner, &client)).unwrap();
+        assert_eq!(fs::read_dir(trash_dir).unwrap().count(), 0);
+    }
+}
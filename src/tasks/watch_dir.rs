@@ -0,0 +1,223 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::common::{EmptyResult, GenericResult};
+use crate::torrent_client::{ClientError, TorrentClient};
+use crate::transmissionrpc::TorrentSource;
+
+use super::Task;
+
+/// Files which have been modified recently may be still being written
+const MIN_FILE_AGE: Duration = Duration::from_secs(2);
+
+/// Adds .torrent files and magnet links (.magnet files) dropped to the watch directory. The files from its
+/// subdirectories get the subdirectory's name as a label.
+pub struct WatchDirectory {
+    path: PathBuf,
+    download_dir: PathBuf,
+    archive_dir: Option<PathBuf>,
+    subdir_download_dirs: bool,
+}
+
+struct WatchedFile {
+    path: PathBuf,
+    subdir: Option<String>,
+    magnet: bool,
+}
+
+impl WatchDirectory {
+    pub fn new(
+        path: PathBuf, download_dir: PathBuf, archive_dir: Option<PathBuf>, subdir_download_dirs: bool,
+    ) -> WatchDirectory {
+        WatchDirectory {
+            path: path,
+            download_dir: download_dir,
+            archive_dir: archive_dir,
+            subdir_download_dirs: subdir_download_dirs,
+        }
+    }
+
+    fn list(&self) -> GenericResult<Vec<WatchedFile>> {
+        let mut files = Vec::new();
+        let mut dirs = vec![(self.path.clone(), None)];
+
+        while let Some((dir_path, subdir)) = dirs.pop() {
+            for entry in fs::read_dir(&dir_path).map_err(|e| format!(
+                "Unable to read '{}': {}", dir_path.display(), e))? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                if name.starts_with('.') {
+                    continue;
+                }
+
+                let metadata = entry.metadata()?;
+
+                if metadata.is_dir() {
+                    // Only one level of subdirectories is supported
+                    if subdir.is_none() {
+                        dirs.push((entry.path(), Some(name)));
+                    }
+                    continue;
+                }
+
+                let lowercase = name.to_lowercase();
+                let magnet = lowercase.ends_with(".magnet");
+                if !magnet && !lowercase.ends_with(".torrent") {
+                    continue;
+                }
+
+                if metadata.modified()?.elapsed().unwrap_or_default() < MIN_FILE_AGE {
+                    continue;
+                }
+
+                files.push(WatchedFile {
+                    path: entry.path(),
+                    subdir: subdir.clone(),
+                    magnet: magnet,
+                });
+            }
+        }
+
+        Ok(files)
+    }
+
+    async fn add(&self, client: &dyn TorrentClient, file: &WatchedFile) -> EmptyResult {
+        let data = fs::read(&file.path).map_err(|e| format!("Unable to read '{}': {}", file.path.display(), e))?;
+
+        let magnet;
+        let source = if file.magnet {
+            magnet = String::from_utf8_lossy(&data).lines().map(str::trim).find(|line| !line.is_empty())
+                .map(String::from).unwrap_or_default();
+            TorrentSource::Url(&magnet)
+        } else {
+            TorrentSource::Metainfo(&data)
+        };
+
+        let labels: Vec<String> = file.subdir.iter().cloned().collect();
+        let download_dir = match file.subdir {
+            Some(ref subdir) if self.subdir_download_dirs => Some(self.download_dir.join(subdir)),
+            _ => None,
+        };
+        let download_dir = match download_dir {
+            Some(ref path) => Some(path.to_str().ok_or_else(|| format!("Invalid path: {:?}", path))?),
+            None => None,
+        };
+
+        match client.add_torrent(source, download_dir, &labels).await {
+            Ok(torrent) => {
+                if torrent.duplicate {
+                    warn!("'{}' torrent from '{}' already exists.", torrent.name, file.path.display());
+                } else {
+                    info!("'{}' torrent ({}) has been added from '{}'.", torrent.name, torrent.hash, file.path.display());
+                }
+                self.archive(&file.path)
+            },
+
+            // The file has been rejected by the client, so rename it to not try to add it again
+            Err(ClientError::Rejected(error)) => {
+                let mut invalid_path = file.path.as_os_str().to_owned();
+                invalid_path.push(".invalid");
+
+                error!("Failed to add torrent from '{}': {}. Renaming it to '{}'.",
+                       file.path.display(), error, Path::new(&invalid_path).display());
+
+                fs::rename(&file.path, &invalid_path).map_err(|e| format!(
+                    "Failed to rename '{}': {}", file.path.display(), e))?;
+
+                Ok(())
+            },
+
+            Err(err) => Err!("Failed to add torrent from '{}': {}", file.path.display(), err),
+        }
+    }
+
+    fn archive(&self, path: &Path) -> EmptyResult {
+        match self.archive_dir {
+            Some(ref archive_dir) => {
+                let name: OsString = path.file_name().unwrap().to_owned();
+                let archive_path = archive_dir.join(name);
+
+                fs::rename(path, &archive_path).map_err(|e| format!(
+                    "Failed to move '{}' to '{}': {}", path.display(), archive_path.display(), e))?;
+            },
+            None => {
+                fs::remove_file(path).map_err(|e| format!("Failed to delete '{}': {}", path.display(), e))?;
+            },
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Task for WatchDirectory {
+    fn name(&self) -> &'static str {
+        "Watch directory"
+    }
+
+    async fn run(&mut self, client: &dyn TorrentClient) -> EmptyResult {
+        for file in self.list()? {
+            self.add(client, &file).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use tempfile::TempDir;
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::mock::MockServer;
+
+    use super::*;
+
+    #[test]
+    fn test_watch_directory() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+
+        let temp_dir = TempDir::new().unwrap();
+        let (watch_dir, archive_dir) = (temp_dir.path().join("watch"), temp_dir.path().join("archive"));
+        fs::create_dir_all(watch_dir.join("tv")).unwrap();
+        fs::create_dir(&archive_dir).unwrap();
+
+        let modify_time = SystemTime::now() - MIN_FILE_AGE * 2;
+        for (name, contents) in [
+            ("movie.torrent", "movie"),
+            ("tv/show.magnet", "\nmagnet:?xt=urn:btih:0123&dn=show\n"),
+            ("invalid.torrent", ""),
+            ("notes.txt", "notes"),
+        ] {
+            let path = watch_dir.join(name);
+            fs::write(&path, contents).unwrap();
+            fs::File::options().write(true).open(&path).unwrap().set_modified(modify_time).unwrap();
+        }
+        fs::write(watch_dir.join("new.torrent"), "new").unwrap();
+
+        let client = server.client();
+        let mut task = WatchDirectory::new(
+            watch_dir.clone(), PathBuf::from("/downloads"), Some(archive_dir.clone()), true);
+        runtime.block_on(task.run(&client)).unwrap();
+
+        let torrents: Vec<(String, String, Vec<String>)> = server.state().torrents.iter()
+            .map(|torrent| (torrent.name.clone(), torrent.download_dir.clone(), torrent.labels.clone()))
+            .collect();
+        assert_eq!(torrents, vec![
+            (s!("movie"), s!("/downloads"), vec![]),
+            (s!("show"), s!("/downloads/tv"), vec![s!("tv")]),
+        ]);
+
+        assert!(archive_dir.join("movie.torrent").exists());
+        assert!(archive_dir.join("show.magnet").exists());
+        assert!(watch_dir.join("invalid.torrent.invalid").exists());
+        assert!(watch_dir.join("notes.txt").exists());
+        assert!(watch_dir.join("new.torrent").exists());
+    }
+}
@@ -0,0 +1,303 @@
+//! Telegram bot: sends the notifications to a chat and optionally accepts the commands from it
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::{Client, header};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use time::OffsetDateTime;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::common::{EmptyResult, GenericResult};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_TIMEOUT: Duration = Duration::from_secs(50);
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Telegram limits the message size to 4096 characters
+const MAX_MESSAGE_SIZE: usize = 4000;
+
+const HELP: &str = "\
+Available commands:
+/status - short status of the controller
+/list - managed torrents
+/pause <name> - pause the torrent
+/process - run a full check right now";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TelegramConfig {
+    pub bot_token: Option<String>,
+    /// The chat to send the notifications to and to accept the commands from
+    pub chat_id: Option<i64>,
+    /// Accept the commands from the chat
+    pub commands: bool,
+    pub api_url: String,
+}
+
+impl Default for TelegramConfig {
+    fn default() -> TelegramConfig {
+        TelegramConfig {
+            bot_token: None,
+            chat_id: None,
+            commands: false,
+            api_url: s!("https://api.telegram.org"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BotCommand {
+    Status,
+    List,
+    Pause(String),
+    Process,
+}
+
+impl BotCommand {
+    fn parse(text: &str) -> Result<BotCommand, String> {
+        let (command, argument) = match text.trim().split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (text.trim(), ""),
+        };
+
+        // The commands may be addressed to the bot explicitly in group chats: /status@SomeBot
+        let command = command.split_once('@').map(|(command, _)| command).unwrap_or(command);
+
+        Ok(match (command, argument) {
+            ("/status", "") => BotCommand::Status,
+            ("/list", "") => BotCommand::List,
+            ("/pause", "") => return Err(s!("Usage: /pause <name>")),
+            ("/pause", name) => BotCommand::Pause(s!(name)),
+            ("/process", "") => BotCommand::Process,
+            _ => return Err(s!(HELP)),
+        })
+    }
+}
+
+/// The commands are executed by the daemon's main loop, so they never race with the controller's checks
+pub struct BotRequest {
+    pub command: BotCommand,
+    reply: oneshot::Sender<GenericResult<String>>,
+}
+
+impl BotRequest {
+    pub fn reply(self, result: GenericResult<String>) {
+        let _ = self.reply.send(result);
+    }
+}
+
+pub struct BotListener {
+    requests: mpsc::Receiver<BotRequest>,
+}
+
+impl BotListener {
+    pub async fn request(&mut self) -> BotRequest {
+        match self.requests.recv().await {
+            Some(request) => request,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+pub struct TelegramBot {
+    http: Client,
+    api_url: String,
+    token: String,
+    chat_id: i64,
+    commands: bool,
+}
+
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    ok: bool,
+    description: Option<String>,
+    result: Option<T>,
+}
+
+#[derive(Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    chat: Chat,
+    date: i64,
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+impl TelegramBot {
+    /// Returns None if the bot isn't configured
+    pub fn new(config: &TelegramConfig) -> Option<Arc<TelegramBot>> {
+        let (token, chat_id) = match (config.bot_token.as_ref(), config.chat_id) {
+            (Some(token), Some(chat_id)) => (token.clone(), chat_id),
+            _ => return None,
+        };
+
+        Some(Arc::new(TelegramBot {
+            http: Client::new(),
+            api_url: config.api_url.trim_end_matches('/').to_owned(),
+            token: token,
+            chat_id: chat_id,
+            commands: config.commands,
+        }))
+    }
+
+    pub async fn send(&self, subject: &str, body: &str) -> EmptyResult {
+        let text = match body.trim() {
+            "" => s!(subject),
+            body => format!("{}\n\n{}", subject, body),
+        };
+        self.send_message(&text).await
+    }
+
+    async fn send_message(&self, text: &str) -> EmptyResult {
+        let text = truncate(text);
+        let _: Value = self.call("sendMessage", json!({
+            "chat_id": self.chat_id,
+            "text": text,
+        }), REQUEST_TIMEOUT).await?;
+        Ok(())
+    }
+
+    async fn get_updates(&self, offset: i64) -> GenericResult<Vec<Update>> {
+        self.call("getUpdates", json!({
+            "offset": offset,
+            "timeout": POLL_TIMEOUT.as_secs(),
+            "allowed_updates": ["message"],
+        }), POLL_TIMEOUT + REQUEST_TIMEOUT).await
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: Value, timeout: Duration) -> GenericResult<T> {
+        // The URL contains the bot token, so it's stripped from the errors
+        let url = format!("{}/bot{}/{}", self.api_url, self.token, method);
+
+        let response = self.http.post(url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(params.to_string())
+            .timeout(timeout)
+            .send().await.map_err(|e| format!("Telegram API request failed: {}", e.without_url()))?;
+
+        let body = response.text().await.map_err(|e| format!(
+            "Telegram API request failed: {}", e.without_url()))?;
+
+        let response: ApiResponse<T> = serde_json::from_str(&body).map_err(|e| format!(
+            "Got an invalid response from Telegram API: {}", e))?;
+
+        match (response.ok, response.result) {
+            (true, Some(result)) => Ok(result),
+            _ => Err!("Telegram API request failed: {}", response.description.as_deref().unwrap_or("unknown error")),
+        }
+    }
+
+    /// Starts listening for the commands from the chat (returns None if the commands are disabled)
+    pub fn listen(self: &Arc<Self>) -> Option<BotListener> {
+        if !self.commands {
+            return None;
+        }
+
+        let (sender, receiver) = mpsc::channel(16);
+        let bot = self.clone();
+        tokio::spawn(async move { bot.poll_commands(sender).await });
+
+        info!("Listening for Telegram bot commands.");
+        Some(BotListener { requests: receiver })
+    }
+
+    async fn poll_commands(&self, requests: mpsc::Sender<BotRequest>) {
+        // The commands which have been sent while the daemon wasn't running are ignored
+        let start_time = OffsetDateTime::now_utc().unix_timestamp();
+        let mut offset = 0;
+
+        loop {
+            let updates = match self.get_updates(offset).await {
+                Ok(updates) => updates,
+                Err(e) => {
+                    warn!("Failed to get Telegram bot updates: {}.", e);
+                    tokio::time::sleep(RETRY_DELAY).await;
+                    continue;
+                },
+            };
+
+            for update in updates {
+                offset = offset.max(update.update_id + 1);
+
+                let Some(message) = update.message else { continue };
+                let Some(text) = message.text else { continue };
+
+                if message.chat.id != self.chat_id {
+                    warn!("Ignoring Telegram bot command from unknown {} chat: {:?}.", message.chat.id, text);
+                    continue;
+                } else if message.date < start_time {
+                    debug!("Ignoring stale Telegram bot command: {:?}.", text);
+                    continue;
+                }
+
+                let reply = match BotCommand::parse(&text) {
+                    Ok(command) => {
+                        info!("Got {:?} Telegram bot command.", text);
+                        match execute(&requests, command).await {
+                            Ok(reply) => reply,
+                            Err(e) => format!("Error: {}.", e),
+                        }
+                    },
+                    Err(reply) => reply,
+                };
+
+                if let Err(e) = self.send_message(&reply).await {
+                    error!("Failed to reply to {:?} Telegram bot command: {}.", text, e);
+                }
+            }
+        }
+    }
+}
+
+async fn execute(requests: &mpsc::Sender<BotRequest>, command: BotCommand) -> GenericResult<String> {
+    let (reply, result) = oneshot::channel();
+
+    requests.send(BotRequest {
+        command: command,
+        reply: reply,
+    }).await.map_err(|_| "The daemon is shutting down")?;
+
+    result.await.map_err(|_| "The daemon is shutting down")?
+}
+
+fn truncate(text: &str) -> &str {
+    match text.char_indices().nth(MAX_MESSAGE_SIZE) {
+        Some((index, _)) => &text[..index],
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_parsing() {
+        assert_eq!(BotCommand::parse("/status"), Ok(BotCommand::Status));
+        assert_eq!(BotCommand::parse(" /list@SomeBot "), Ok(BotCommand::List));
+        assert_eq!(BotCommand::parse("/pause  Some torrent "), Ok(BotCommand::Pause(s!("Some torrent"))));
+        assert_eq!(BotCommand::parse("/process"), Ok(BotCommand::Process));
+
+        assert_eq!(BotCommand::parse("/pause"), Err(s!("Usage: /pause <name>")));
+        assert_eq!(BotCommand::parse("/status now"), Err(s!(HELP)));
+        assert_eq!(BotCommand::parse("hello"), Err(s!(HELP)));
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("message"), "message");
+        assert_eq!(truncate(&"ы".repeat(MAX_MESSAGE_SIZE + 1)).chars().count(), MAX_MESSAGE_SIZE);
+    }
+}
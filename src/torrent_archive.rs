@@ -0,0 +1,103 @@
+//! Archive of the source .torrent files, which allows to re-add or cross-seed the torrents after their removal. The
+//! files are organized by tracker and month in which the torrent has been added: <tracker>/<YYYY-MM>/<name>.torrent.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::common::EmptyResult;
+use crate::transmissionrpc::Torrent;
+use crate::util::time::format_date;
+
+pub struct TorrentArchive {
+    path: PathBuf,
+    dry_run: bool,
+}
+
+impl TorrentArchive {
+    pub fn new(path: PathBuf, dry_run: bool) -> TorrentArchive {
+        TorrentArchive {
+            path: path,
+            dry_run: dry_run,
+        }
+    }
+
+    pub fn contains(&self, torrent: &Torrent) -> bool {
+        fs::symlink_metadata(self.get_path(torrent)).is_ok()
+    }
+
+    /// Copies the torrent's .torrent file from Transmission's torrents directory (requires the torrent's files info)
+    pub fn archive(&self, torrent: &Torrent) -> EmptyResult {
+        let src_path = Path::new(torrent.torrent_file.as_ref().ok_or("unknown .torrent file path")?);
+        let dst_path = self.get_path(torrent);
+
+        if self.dry_run {
+            info!("Dry run: would archive '{}' torrent file to '{}'.", torrent.name, dst_path.display());
+            return Ok(());
+        }
+
+        let dst_dir = dst_path.parent().unwrap();
+        fs::create_dir_all(dst_dir).map_err(|e| format!("Failed to create '{}': {}", dst_dir.display(), e))?;
+
+        // Copy to a temporary file first to not consider a partially copied file archived
+        let mut temp_path = dst_path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+
+        fs::copy(src_path, &temp_path).map_err(|e| format!(
+            "Failed to copy '{}' to '{}': {}", src_path.display(), dst_dir.display(), e))?;
+        fs::rename(&temp_path, &dst_path).map_err(|e| format!(
+            "Failed to rename '{}': {}", Path::new(&temp_path).display(), e))?;
+
+        info!("'{}' torrent file has been archived to '{}'.", torrent.name, dst_path.display());
+        Ok(())
+    }
+
+    fn get_path(&self, torrent: &Torrent) -> PathBuf {
+        let tracker = torrent.tracker_hosts().into_iter().next().unwrap_or_else(|| s!("no-tracker"));
+        let month = &format_date(torrent.added_time)[..7];
+
+        // The hash prefix tells apart the torrents with the same name
+        let name = torrent.name.replace('/', "_");
+        let name = format!("{}.{}.torrent", name.trim_start_matches('.'), &torrent.hash[..8]);
+
+        self.path.join(tracker).join(month).join(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::TorrentFields;
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    #[test]
+    fn test_archive() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+        let client = server.client();
+
+        let temp_dir = TempDir::new().unwrap();
+        let torrent_file = temp_dir.path().join("source.torrent");
+        fs::write(&torrent_file, "metainfo").unwrap();
+
+        let mut torrent = MockTorrent::new(1, "Some/Show", "/downloads");
+        torrent.trackers = vec![s!("http://tracker.example.com/announce")];
+        torrent.torrent_file = torrent_file.to_str().unwrap().to_owned();
+        server.add_torrent(torrent);
+
+        let torrent = runtime.block_on(client.get_torrent(&format!("{:040x}", 1), TorrentFields::basic().with_files()))
+            .unwrap();
+
+        let archive = TorrentArchive::new(temp_dir.path().join("archive"), false);
+        assert!(!archive.contains(&torrent));
+        archive.archive(&torrent).unwrap();
+        assert!(archive.contains(&torrent));
+
+        let path = temp_dir.path().join("archive/tracker.example.com")
+            .join(&format_date(torrent.added_time)[..7]).join("Some_Show.00000000.torrent");
+        assert_eq!(fs::read_to_string(path).unwrap(), "metainfo");
+    }
+}
@@ -0,0 +1,79 @@
+//! Blocking facade for the asynchronous client to use it from synchronous code (worker threads and command line
+//! commands). Must not be used from within the asynchronous runtime threads.
+
+#![allow(dead_code)] // The facade mirrors the whole client API, but not all of it is needed in synchronous code
+
+use std::future::Future;
+use std::sync::Arc;
+
+use tokio::runtime::Handle;
+
+use crate::transmissionrpc::{
+    AddedTorrent, FreeSpace, RecentlyActiveTorrents, SessionStats, SpeedLimits, Torrent, TorrentFields, TorrentSource};
+
+use super::{BandwidthGroup, Capability, EmptyResult, Result};
+
+#[derive(Clone)]
+pub struct TorrentClient {
+    client: Arc<dyn super::TorrentClient>,
+    runtime: Handle,
+}
+
+macro_rules! blocking_methods {
+    ($($(#[$attr:meta])* fn $name:ident(&self $(, $arg:ident: $type:ty)*) -> $result:ty;)*) => {$(
+        $(#[$attr])*
+        pub fn $name(&self $(, $arg: $type)*) -> $result {
+            self.runtime.block_on(self.client.$name($($arg),*))
+        }
+    )*}
+}
+
+impl TorrentClient {
+    pub fn new(client: Arc<dyn super::TorrentClient>, runtime: Handle) -> TorrentClient {
+        TorrentClient {
+            client: client,
+            runtime: runtime,
+        }
+    }
+
+    /// Runs the asynchronous code of the other components which is used along with the client
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    blocking_methods! {
+        fn get_version(&self) -> Result<String>;
+        fn check_capability(&self, capability: Capability) -> EmptyResult;
+        fn supports(&self, capability: Capability) -> Result<bool>;
+
+        fn is_manual_mode(&self) -> Result<bool>;
+        fn set_manual_mode(&self, enabled: bool) -> EmptyResult;
+        fn set_speed_limits(&self, limits: &SpeedLimits) -> EmptyResult;
+        fn get_peer_port(&self) -> Result<u16>;
+        fn test_port(&self) -> Result<bool>;
+        fn get_session_stats(&self) -> Result<SessionStats>;
+        fn get_free_space(&self, path: &str) -> Result<FreeSpace>;
+        fn update_blocklist(&self) -> Result<u64>;
+
+        fn get_torrents(&self, fields: TorrentFields) -> Result<Vec<Torrent>>;
+        fn get_recently_active_torrents(&self, fields: TorrentFields) -> Result<RecentlyActiveTorrents>;
+        fn get_torrent(&self, hash: &str, fields: TorrentFields) -> Result<Torrent>;
+        fn get_torrents_by_hash(&self, hashes: &[String], fields: TorrentFields) -> Result<Vec<Torrent>>;
+
+        fn start(&self, hash: &str) -> EmptyResult;
+        fn stop(&self, hash: &str) -> EmptyResult;
+        fn verify(&self, hash: &str) -> EmptyResult;
+        fn reannounce(&self, hash: &str) -> EmptyResult;
+        fn mark_processed(&self, hash: &str) -> EmptyResult;
+        fn set_labels(&self, hash: &str, labels: &[String]) -> EmptyResult;
+        fn set_location(&self, hash: &str, location: &str, move_data: bool) -> EmptyResult;
+        fn rename_path(&self, hash: &str, path: &str, name: &str) -> EmptyResult;
+        fn add_torrent(
+            &self, source: TorrentSource<'_>, download_dir: Option<&str>, labels: &[String]) -> Result<AddedTorrent>;
+        fn remove(&self, hash: &str, delete_local_data: bool) -> EmptyResult;
+
+        fn get_bandwidth_groups(&self) -> Result<Vec<BandwidthGroup>>;
+        fn set_bandwidth_group(&self, group: &BandwidthGroup) -> EmptyResult;
+        fn set_torrent_bandwidth_group(&self, hash: &str, group: &str) -> EmptyResult;
+    }
+}
@@ -0,0 +1,309 @@
+//! Interface of the BitTorrent client backend. The controller, the consumer and all other components work with the
+//! client through it, so another daemon may be supported by implementing the trait and adding it to [`Backend`]. The
+//! optional capabilities (labels, bandwidth groups, etc.) must be checked with `check_capability()` before using them.
+
+use std::error::Error;
+use std::fmt;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::transmissionrpc::{
+    self, AddedTorrent, Feature, FreeSpace, RecentlyActiveTorrents, SessionStats, SpeedLimits, Torrent, TorrentFields,
+    TorrentSource, TransmissionClient, TransmissionClientError, TransmissionRpcError};
+
+pub mod blocking;
+
+/// The supported client backends
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    #[default]
+    Transmission,
+}
+
+/// Optional client capabilities which may be missing in some clients or their versions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capability {
+    Labels,
+    BandwidthGroups,
+    SequentialDownload,
+}
+
+/// A named group of torrents which share the speed limits (in KB/s)
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandwidthGroup {
+    pub name: String,
+    /// The group's torrents are limited by the global speed limits as well
+    pub honors_session_limits: bool,
+    pub download_limit: Option<u64>,
+    pub upload_limit: Option<u64>,
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+pub type EmptyResult = Result<()>;
+
+#[async_trait]
+pub trait TorrentClient: Send + Sync {
+    fn is_dry_run(&self) -> bool;
+
+    /// Returns human-readable name and version of the client
+    async fn get_version(&self) -> Result<String>;
+    /// Returns an error if the client doesn't support the capability
+    async fn check_capability(&self, capability: Capability) -> EmptyResult;
+
+    async fn supports(&self, capability: Capability) -> Result<bool> {
+        match self.check_capability(capability).await {
+            Ok(()) => Ok(true),
+            Err(ClientError::Unsupported(_)) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn is_manual_mode(&self) -> Result<bool>;
+    async fn set_manual_mode(&self, enabled: bool) -> EmptyResult;
+    async fn set_speed_limits(&self, limits: &SpeedLimits) -> EmptyResult;
+    async fn get_peer_port(&self) -> Result<u16>;
+    async fn set_peer_port(&self, port: u16) -> EmptyResult;
+    async fn test_port(&self) -> Result<bool>;
+    async fn get_session_stats(&self) -> Result<SessionStats>;
+    async fn get_free_space(&self, path: &str) -> Result<FreeSpace>;
+    async fn update_blocklist(&self) -> Result<u64>;
+
+    async fn get_torrents(&self, fields: TorrentFields) -> Result<Vec<Torrent>>;
+    async fn get_recently_active_torrents(&self, fields: TorrentFields) -> Result<RecentlyActiveTorrents>;
+    async fn get_torrent(&self, hash: &str, fields: TorrentFields) -> Result<Torrent>;
+    async fn get_torrents_by_hash(&self, hashes: &[String], fields: TorrentFields) -> Result<Vec<Torrent>>;
+
+    async fn start(&self, hash: &str) -> EmptyResult;
+    async fn stop(&self, hash: &str) -> EmptyResult;
+    async fn verify(&self, hash: &str) -> EmptyResult;
+    async fn reannounce(&self, hash: &str) -> EmptyResult;
+    /// Persistently marks the torrent as processed, so it survives the controller restarts. The way the mark is stored
+    /// is up to the backend.
+    async fn mark_processed(&self, hash: &str) -> EmptyResult;
+    async fn set_labels(&self, hash: &str, labels: &[String]) -> EmptyResult;
+    /// Makes the client download the torrent's pieces in order
+    async fn set_sequential_download(&self, hash: &str, enabled: bool) -> EmptyResult;
+    async fn add_trackers(&self, hash: &str, urls: &[String]) -> EmptyResult;
+    async fn replace_trackers(&self, hash: &str, trackers: &[(u64, String)]) -> EmptyResult;
+    async fn set_torrent_speed_limits(
+        &self, hash: &str, download_limit: Option<u64>, upload_limit: Option<u64>) -> EmptyResult;
+    async fn set_location(&self, hash: &str, location: &str, move_data: bool) -> EmptyResult;
+    async fn rename_path(&self, hash: &str, path: &str, name: &str) -> EmptyResult;
+    async fn add_torrent(
+        &self, source: TorrentSource<'_>, download_dir: Option<&str>, labels: &[String]) -> Result<AddedTorrent>;
+    async fn remove(&self, hash: &str, delete_local_data: bool) -> EmptyResult;
+
+    async fn get_bandwidth_groups(&self) -> Result<Vec<BandwidthGroup>>;
+    async fn set_bandwidth_group(&self, group: &BandwidthGroup) -> EmptyResult;
+    async fn set_torrent_bandwidth_group(&self, hash: &str, group: &str) -> EmptyResult;
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// The client has rejected the credentials
+    Authentication(String),
+    /// The client is unavailable at the moment (it's restarting, for example)
+    Connection(String),
+    /// The client doesn't support the requested capability
+    Unsupported(String),
+    TorrentNotFound(String),
+    /// The client has rejected the request (an invalid torrent file, for example)
+    Rejected(String),
+    /// Communication and protocol errors
+    Internal(String),
+}
+
+impl ClientError {
+    /// Returns true for errors that are likely to go away on their own (the daemon is restarting, for example)
+    pub fn is_transient(&self) -> bool {
+        matches!(*self, ClientError::Connection(_))
+    }
+
+    /// Returns true for errors that won't go away without user intervention
+    pub fn is_fatal(&self) -> bool {
+        matches!(*self, ClientError::Authentication(_) | ClientError::Unsupported(_))
+    }
+}
+
+impl Error for ClientError {
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ClientError::Authentication(ref err) => write!(f, "Failed to authenticate in the torrent client: {}", err),
+            ClientError::Connection(ref err) => write!(f, "Failed to connect to the torrent client: {}", err),
+            ClientError::Unsupported(ref err) => write!(f, "{}", err),
+            ClientError::TorrentNotFound(ref hash) => write!(f, "{} torrent doesn't exist", hash),
+            ClientError::Rejected(ref err) => write!(f, "The torrent client returned an error: {}", err),
+            ClientError::Internal(ref err) => write!(f, "Error in communication with the torrent client: {}", err),
+        }
+    }
+}
+
+impl From<TransmissionClientError> for ClientError {
+    fn from(err: TransmissionClientError) -> ClientError {
+        match err {
+            TransmissionClientError::Authentication(err) => ClientError::Authentication(err),
+            TransmissionClientError::Connection(err) => ClientError::Connection(err),
+            TransmissionClientError::Internal(err) | TransmissionClientError::Protocol(err) => {
+                ClientError::Internal(err)
+            },
+            TransmissionClientError::Rpc(TransmissionRpcError::GeneralError(err)) => ClientError::Rejected(err),
+            TransmissionClientError::Rpc(TransmissionRpcError::TorrentNotFoundError(hash)) => {
+                ClientError::TorrentNotFound(hash)
+            },
+            TransmissionClientError::Rpc(TransmissionRpcError::UnsupportedFeatureError(err)) => {
+                ClientError::Unsupported(err)
+            },
+        }
+    }
+}
+
+impl From<Capability> for Feature {
+    fn from(capability: Capability) -> Feature {
+        match capability {
+            Capability::Labels => Feature::Labels,
+            Capability::BandwidthGroups => Feature::BandwidthGroups,
+            Capability::SequentialDownload => Feature::SequentialDownload,
+        }
+    }
+}
+
+impl From<transmissionrpc::BandwidthGroup> for BandwidthGroup {
+    fn from(group: transmissionrpc::BandwidthGroup) -> BandwidthGroup {
+        BandwidthGroup {
+            name: group.name,
+            honors_session_limits: group.honors_session_limits,
+            download_limit: group.download_limit_enabled.then_some(group.download_limit),
+            upload_limit: group.upload_limit_enabled.then_some(group.upload_limit),
+        }
+    }
+}
+
+impl From<&BandwidthGroup> for transmissionrpc::BandwidthGroup {
+    fn from(group: &BandwidthGroup) -> transmissionrpc::BandwidthGroup {
+        transmissionrpc::BandwidthGroup {
+            name: group.name.clone(),
+            honors_session_limits: group.honors_session_limits,
+            download_limit_enabled: group.download_limit.is_some(),
+            download_limit: group.download_limit.unwrap_or(0),
+            upload_limit_enabled: group.upload_limit.is_some(),
+            upload_limit: group.upload_limit.unwrap_or(0),
+        }
+    }
+}
+
+macro_rules! transmission_methods {
+    (delegate { $(fn $name:ident(&self $(, $arg:ident: $type:ty)*) -> $result:ty;)* } $($custom:tt)*) => {
+        #[async_trait]
+        impl TorrentClient for TransmissionClient {
+            fn is_dry_run(&self) -> bool {
+                TransmissionClient::is_dry_run(self)
+            }
+
+            $(
+                async fn $name(&self $(, $arg: $type)*) -> $result {
+                    Ok(TransmissionClient::$name(self $(, $arg)*).await?)
+                }
+            )*
+
+            $($custom)*
+        }
+    }
+}
+
+transmission_methods! {
+    delegate {
+        fn is_manual_mode(&self) -> Result<bool>;
+        fn set_manual_mode(&self, enabled: bool) -> EmptyResult;
+        fn set_speed_limits(&self, limits: &SpeedLimits) -> EmptyResult;
+        fn get_peer_port(&self) -> Result<u16>;
+        fn set_peer_port(&self, port: u16) -> EmptyResult;
+        fn test_port(&self) -> Result<bool>;
+        fn get_session_stats(&self) -> Result<SessionStats>;
+        fn get_free_space(&self, path: &str) -> Result<FreeSpace>;
+        fn update_blocklist(&self) -> Result<u64>;
+
+        fn get_torrents(&self, fields: TorrentFields) -> Result<Vec<Torrent>>;
+        fn get_recently_active_torrents(&self, fields: TorrentFields) -> Result<RecentlyActiveTorrents>;
+        fn get_torrent(&self, hash: &str, fields: TorrentFields) -> Result<Torrent>;
+        fn get_torrents_by_hash(&self, hashes: &[String], fields: TorrentFields) -> Result<Vec<Torrent>>;
+
+        fn start(&self, hash: &str) -> EmptyResult;
+        fn stop(&self, hash: &str) -> EmptyResult;
+        fn verify(&self, hash: &str) -> EmptyResult;
+        fn reannounce(&self, hash: &str) -> EmptyResult;
+        fn set_labels(&self, hash: &str, labels: &[String]) -> EmptyResult;
+        fn set_sequential_download(&self, hash: &str, enabled: bool) -> EmptyResult;
+        fn add_trackers(&self, hash: &str, urls: &[String]) -> EmptyResult;
+        fn replace_trackers(&self, hash: &str, trackers: &[(u64, String)]) -> EmptyResult;
+        fn set_torrent_speed_limits(
+            &self, hash: &str, download_limit: Option<u64>, upload_limit: Option<u64>) -> EmptyResult;
+        fn set_location(&self, hash: &str, location: &str, move_data: bool) -> EmptyResult;
+        fn rename_path(&self, hash: &str, path: &str, name: &str) -> EmptyResult;
+        fn add_torrent(
+            &self, source: TorrentSource<'_>, download_dir: Option<&str>, labels: &[String]) -> Result<AddedTorrent>;
+        fn remove(&self, hash: &str, delete_local_data: bool) -> EmptyResult;
+        fn set_torrent_bandwidth_group(&self, hash: &str, group: &str) -> EmptyResult;
+    }
+
+    async fn get_version(&self) -> Result<String> {
+        Ok(self.get_server_version().await?.to_string())
+    }
+
+    async fn check_capability(&self, capability: Capability) -> EmptyResult {
+        Ok(self.check_feature(capability.into()).await?)
+    }
+
+    // Transmission has no custom torrent properties, so the processed torrents are marked with a special download
+    // limit value
+    async fn mark_processed(&self, hash: &str) -> EmptyResult {
+        Ok(self.set_processed(hash).await?)
+    }
+
+    async fn get_bandwidth_groups(&self) -> Result<Vec<BandwidthGroup>> {
+        let groups = TransmissionClient::get_bandwidth_groups(self).await?;
+        Ok(groups.into_iter().map(BandwidthGroup::from).collect())
+    }
+
+    async fn set_bandwidth_group(&self, group: &BandwidthGroup) -> EmptyResult {
+        Ok(TransmissionClient::set_bandwidth_group(self, &group.into()).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::mock::MockServer;
+
+    use super::*;
+
+    #[test]
+    fn test_transmission_client() {
+        let runtime = Runtime::new().unwrap();
+
+        runtime.block_on(async {
+            let server = MockServer::start().await;
+            server.state().rpc_version = 16;
+
+            let client = server.client();
+            let client: &dyn TorrentClient = &client;
+
+            assert_eq!(client.get_version().await.unwrap(), "Transmission 4.0.6 (RPC version 16)");
+            assert!(client.supports(Capability::Labels).await.unwrap());
+            assert!(!client.supports(Capability::BandwidthGroups).await.unwrap());
+
+            let error = client.get_bandwidth_groups().await.unwrap_err();
+            assert!(matches!(error, ClientError::Unsupported(_)), "{}", error);
+            assert!(error.is_fatal());
+
+            let hash = format!("{:040x}", 1);
+            let error = client.get_torrent(&hash, TorrentFields::basic()).await.unwrap_err();
+            assert!(matches!(error, ClientError::TorrentNotFound(ref value) if *value == hash), "{}", error);
+        });
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::mem;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::config;
+use crate::transmissionrpc::{Torrent, TorrentError};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TorrentErrorsConfig {
+    /// Send a notification when a torrent gets an error
+    pub notify: bool,
+    /// Notify about tracker warnings as well
+    pub notify_tracker_warnings: bool,
+
+    /// Restart the torrents with local errors (which may be caused by temporary unavailable storage, for example)
+    #[serde(deserialize_with = "config::deserialize_optional_duration")]
+    pub retry_interval: Option<Duration>,
+    pub max_retries: u32,
+}
+
+impl Default for TorrentErrorsConfig {
+    fn default() -> TorrentErrorsConfig {
+        TorrentErrorsConfig {
+            notify: false,
+            notify_tracker_warnings: false,
+            retry_interval: None,
+            max_retries: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorAction {
+    Notify,
+    Retry,
+}
+
+struct ErrorState {
+    error: TorrentError,
+    retry_time: Instant,
+    retries: u32,
+}
+
+/// Tracks torrent errors to notify only about the new ones and to retry the torrents with local errors
+pub struct TorrentErrors {
+    config: TorrentErrorsConfig,
+    torrents: HashMap<String, ErrorState>,
+}
+
+impl TorrentErrors {
+    pub fn new(config: TorrentErrorsConfig) -> TorrentErrors {
+        TorrentErrors {
+            config: config,
+            torrents: HashMap::new(),
+        }
+    }
+
+    /// Returns the actions to take for the errored torrents. Must be called with all the torrents to forget the ones
+    /// which have recovered.
+    pub fn check<'a>(&mut self, torrents: &'a [Torrent]) -> Vec<(&'a Torrent, ErrorAction)> {
+        let now = Instant::now();
+        let mut actions = Vec::new();
+
+        self.torrents.retain(|hash, _| torrents.iter().any(|torrent| &torrent.hash == hash && torrent.error.is_some()));
+
+        for torrent in torrents {
+            let error = match torrent.error {
+                Some(ref error) => error,
+                None => continue,
+            };
+
+            let retry_time = now + self.config.retry_interval.unwrap_or_default();
+
+            let state = match self.torrents.get_mut(&torrent.hash) {
+                Some(state) => state,
+                None => {
+                    self.torrents.insert(torrent.hash.clone(), ErrorState {
+                        error: error.clone(),
+                        retry_time: retry_time,
+                        retries: 0,
+                    });
+
+                    if self.should_notify(error) {
+                        actions.push((torrent, ErrorAction::Notify));
+                    }
+                    continue;
+                },
+            };
+
+            // Notify again only when the error changes its kind (tracker error turns into local, for example)
+            if mem::discriminant(&state.error) != mem::discriminant(error) {
+                state.error = error.clone();
+                state.retry_time = retry_time;
+                state.retries = 0;
+
+                if self.should_notify(error) {
+                    actions.push((torrent, ErrorAction::Notify));
+                }
+                continue;
+            }
+
+            if let TorrentError::LocalError(_) = *error {
+                if self.config.retry_interval.is_some() && state.retries < self.config.max_retries &&
+                    now >= state.retry_time {
+                    state.retry_time = retry_time;
+                    state.retries += 1;
+                    actions.push((torrent, ErrorAction::Retry));
+                }
+            }
+        }
+
+        actions
+    }
+
+    fn should_notify(&self, error: &TorrentError) -> bool {
+        match *error {
+            TorrentError::TrackerWarning(_) => self.config.notify && self.config.notify_tracker_warnings,
+            TorrentError::TrackerError(_) | TorrentError::LocalError(_) => self.config.notify,
+        }
+    }
+}
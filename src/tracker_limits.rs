@@ -0,0 +1,69 @@
+use std::collections::HashSet;
+
+use serde::Deserialize;
+
+use crate::torrent_client::{self, TorrentClient};
+use crate::transmissionrpc::Torrent;
+use crate::util::matching::HostPattern;
+
+/// Per-torrent speed limits for the torrents of the specified trackers (public trackers may be throttled while the
+/// private ones run uncapped, for example). Unlike bandwidth groups, the limits apply to each torrent separately.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TrackerSpeedLimit {
+    pub trackers: Vec<HostPattern>,
+    /// Speed limits in KB/s
+    pub download_limit: Option<u64>,
+    pub upload_limit: Option<u64>,
+}
+
+/// Applies the first matching limit to the torrents
+pub struct TrackerSpeedLimits {
+    limits: Vec<TrackerSpeedLimit>,
+}
+
+impl TrackerSpeedLimits {
+    pub fn new(limits: Vec<TrackerSpeedLimit>) -> TrackerSpeedLimits {
+        TrackerSpeedLimits {
+            limits: limits,
+        }
+    }
+
+    /// Sets the limits of the torrents which don't have them yet. Returns hashes of the changed torrents.
+    pub async fn apply(
+        &self, client: &dyn TorrentClient, torrents: &[Torrent],
+    ) -> torrent_client::Result<HashSet<String>> {
+        let mut changed = HashSet::new();
+
+        for torrent in torrents {
+            let Some(limit) = self.find_limit(torrent) else {
+                continue;
+            };
+
+            // Download limit field is used as processed torrent marker, so it's set only for the downloads
+            let download_limit = limit.download_limit.filter(|&limit| {
+                !torrent.processed && torrent.download_limit != Some(limit)
+            });
+            let upload_limit = limit.upload_limit.filter(|&limit| torrent.upload_limit != Some(limit));
+
+            if download_limit.is_none() && upload_limit.is_none() {
+                continue;
+            }
+
+            info!(hash = torrent.hash.as_str(), action = "speed-limits";
+                  "Applying tracker speed limits to '{}' torrent...", torrent.name);
+            client.set_torrent_speed_limits(&torrent.hash, download_limit, upload_limit).await?;
+            changed.insert(torrent.hash.clone());
+        }
+
+        Ok(changed)
+    }
+
+    fn find_limit(&self, torrent: &Torrent) -> Option<&TrackerSpeedLimit> {
+        let tracker_hosts = torrent.tracker_hosts();
+
+        self.limits.iter().find(|limit| {
+            limit.trackers.iter().any(|pattern| pattern.matches_any(tracker_hosts.iter().map(String::as_str)))
+        })
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashSet;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::torrent_client::{self, TorrentClient};
+use crate::transmissionrpc::Torrent;
+use crate::util::matching::{self, HostPattern};
+
+/// Tracker allowlist and blocklist for shared seedboxes: the controller manages (copies, cleans up, etc.) only the
+/// torrents from the allowed trackers and leaves the others untouched.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TrackersConfig {
+    /// Manage only the torrents from the matching trackers (all torrents are managed if not specified)
+    pub allowed: Vec<HostPattern>,
+    /// The torrents from the matching trackers are never managed (blocklist takes precedence over allowlist)
+    pub blocked: Vec<HostPattern>,
+    /// Action to take on the torrents from the blocked trackers
+    pub blocked_action: Option<BlockedTrackerAction>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BlockedTrackerAction {
+    /// Send a notification
+    Notify,
+    /// Remove the torrent
+    Remove,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrackerStatus {
+    Managed,
+    Unmanaged,
+    Blocked,
+}
+
+pub struct Trackers {
+    config: TrackersConfig,
+}
+
+impl Trackers {
+    pub fn new(config: TrackersConfig) -> Trackers {
+        Trackers {
+            config: config,
+        }
+    }
+
+    pub fn blocked_action(&self) -> Option<BlockedTrackerAction> {
+        self.config.blocked_action
+    }
+
+    pub fn check(&self, torrent: &Torrent) -> TrackerStatus {
+        let tracker_hosts = torrent.tracker_hosts();
+        let matches = |patterns: &[HostPattern]| patterns.iter().any(|pattern| {
+            pattern.matches_any(tracker_hosts.iter().map(String::as_str))
+        });
+
+        if matches(&self.config.blocked) {
+            TrackerStatus::Blocked
+        } else if self.config.allowed.is_empty() || matches(&self.config.allowed) {
+            TrackerStatus::Managed
+        } else {
+            TrackerStatus::Unmanaged
+        }
+    }
+}
+
+/// Rewrites the announce URLs matching the pattern (tracker domain migrations, switching to HTTPS, etc)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrackerRewriteRule {
+    #[serde(deserialize_with = "matching::deserialize_regex")]
+    pub pattern: Regex,
+    pub replacement: String,
+}
+
+pub struct TrackerRewriter {
+    rules: Vec<TrackerRewriteRule>,
+}
+
+impl TrackerRewriter {
+    pub fn new(rules: Vec<TrackerRewriteRule>) -> TrackerRewriter {
+        TrackerRewriter {
+            rules: rules,
+        }
+    }
+
+    /// Rewrites the matching trackers of the torrents. Returns hashes of the changed torrents.
+    pub async fn rewrite(
+        &self, client: &dyn TorrentClient, torrents: &[Torrent],
+    ) -> torrent_client::Result<HashSet<String>> {
+        let mut changed = HashSet::new();
+
+        for torrent in torrents {
+            let replacements = self.get_replacements(torrent);
+            if replacements.is_empty() {
+                continue;
+            }
+
+            for (index, url) in &replacements {
+                info!(hash = torrent.hash.as_str(), action = "rewrite-tracker";
+                      "Rewriting '{}' torrent's tracker {} to {}...", torrent.name, torrent.trackers[*index], url);
+            }
+
+            let trackers: Vec<(u64, String)> = replacements.into_iter().map(|(index, url)| {
+                (torrent.tracker_ids[index], url)
+            }).collect();
+
+            client.replace_trackers(&torrent.hash, &trackers).await?;
+            changed.insert(torrent.hash.clone());
+        }
+
+        Ok(changed)
+    }
+
+    /// Returns indexes of the trackers to rewrite and their new URLs
+    fn get_replacements(&self, torrent: &Torrent) -> Vec<(usize, String)> {
+        let mut replacements = Vec::new();
+
+        for (index, url) in torrent.trackers.iter().enumerate() {
+            let Some(new_url) = rewrite_url(&self.rules, url) else {
+                continue;
+            };
+
+            // Transmission rejects duplicate announce URLs
+            if torrent.trackers.contains(&new_url) || replacements.iter().any(|(_, other)| other == &new_url) {
+                continue;
+            }
+
+            replacements.push((index, new_url));
+        }
+
+        replacements
+    }
+}
+
+/// Applies the first matching rule to the URL. Returns None if it's not changed.
+fn rewrite_url(rules: &[TrackerRewriteRule], url: &str) -> Option<String> {
+    let rule = rules.iter().find(|rule| rule.pattern.is_match(url))?;
+    let new_url = rule.pattern.replace(url, rule.replacement.as_str());
+    (new_url != url).then(|| new_url.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::TorrentFields;
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    #[test]
+    fn test_tracker_rewrites() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+        let client = server.client();
+
+        let mut torrent = MockTorrent::new(1, "migrated", "/downloads");
+        torrent.trackers = vec![
+            s!("http://udp.example.com/announce"),
+            s!("http://old.example.com/key/announce"),
+            s!("http://other.example.com/announce"),
+        ];
+        server.add_torrent(torrent);
+
+        let mut torrent = MockTorrent::new(2, "duplicate", "/downloads");
+        torrent.trackers = vec![s!("http://new.example.com/announce"), s!("https://new.example.com/announce")];
+        server.add_torrent(torrent);
+
+        let rewriter = TrackerRewriter::new(vec![
+            TrackerRewriteRule {
+                pattern: Regex::new(r"^http://old\.example\.com/").unwrap(),
+                replacement: s!("https://new.example.com/"),
+            },
+            TrackerRewriteRule {
+                pattern: Regex::new(r"^http://(\w+\.example\.com)/").unwrap(),
+                replacement: s!("https://$1/"),
+            },
+            TrackerRewriteRule {
+                pattern: Regex::new(r"^http://udp\.example\.com/").unwrap(),
+                replacement: s!("udp://udp.example.com/"),
+            },
+        ]);
+
+        let torrents = runtime.block_on(client.get_torrents(TorrentFields::basic())).unwrap();
+        let changed = runtime.block_on(rewriter.rewrite(&client, &torrents)).unwrap();
+        assert_eq!(changed, HashSet::from([torrents[0].hash.clone()]));
+
+        // The first matching rule wins
+        assert_eq!(server.state().torrents[0].trackers, vec![
+            s!("https://udp.example.com/announce"),
+            s!("https://new.example.com/key/announce"),
+            s!("https://other.example.com/announce"),
+        ]);
+        assert_eq!(server.state().torrents[1].trackers, vec![
+            s!("http://new.example.com/announce"), s!("https://new.example.com/announce"),
+        ]);
+
+        let torrents = runtime.block_on(client.get_torrents(TorrentFields::basic())).unwrap();
+        assert!(runtime.block_on(rewriter.rewrite(&client, &torrents)).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,107 @@
+//! Per-tracker traffic accounting: the torrents' transfer counters are sampled on each check, and their increments are
+//! attributed to the torrents' main (first) trackers.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::transmissionrpc::Torrent;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Traffic {
+    pub uploaded: u64,
+    pub downloaded: u64,
+}
+
+pub struct TrackerTraffic {
+    /// The torrents' counters as of the previous sample
+    torrents: Option<HashMap<String, Traffic>>,
+    /// The traffic since the daemon start
+    trackers: BTreeMap<String, Traffic>,
+}
+
+impl TrackerTraffic {
+    pub fn new() -> TrackerTraffic {
+        TrackerTraffic {
+            torrents: None,
+            trackers: BTreeMap::new(),
+        }
+    }
+
+    /// The first sample only sets the baseline. The torrents which are added after it are accounted from zero.
+    pub fn update(&mut self, torrents: &[Torrent]) {
+        let current: HashMap<String, Traffic> = torrents.iter().map(|torrent| {
+            (torrent.hash.clone(), Traffic {uploaded: torrent.uploaded, downloaded: torrent.downloaded})
+        }).collect();
+
+        let previous = match self.torrents.replace(current) {
+            Some(previous) => previous,
+            None => return,
+        };
+
+        for torrent in torrents {
+            let Some(tracker) = torrent.tracker_hosts().into_iter().next() else {
+                continue;
+            };
+
+            let before = previous.get(&torrent.hash).copied().unwrap_or_default();
+            let traffic = self.trackers.entry(tracker).or_default();
+
+            // The counters are reset when the torrent's data is removed and added again
+            traffic.uploaded += torrent.uploaded.checked_sub(before.uploaded).unwrap_or(torrent.uploaded);
+            traffic.downloaded += torrent.downloaded.checked_sub(before.downloaded).unwrap_or(torrent.downloaded);
+        }
+    }
+
+    pub fn get(&self) -> &BTreeMap<String, Traffic> {
+        &self.trackers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::TorrentFields;
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    #[test]
+    fn test_tracker_traffic() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+        let client = server.client();
+        let get_torrents = || runtime.block_on(client.get_torrents(TorrentFields::basic())).unwrap();
+
+        let mut torrent = MockTorrent::new(1, "first", "/downloads");
+        torrent.trackers = vec![s!("http://first.example.com/announce"), s!("http://second.example.com/announce")];
+        torrent.uploaded_ever = 1000;
+        server.add_torrent(torrent);
+        server.add_torrent(MockTorrent::new(2, "trackerless", "/downloads"));
+
+        let mut traffic = TrackerTraffic::new();
+        traffic.update(&get_torrents());
+        assert!(traffic.get().is_empty());
+
+        let mut torrent = MockTorrent::new(3, "second", "/downloads").downloading();
+        torrent.trackers = vec![s!("http://second.example.com/announce")];
+        torrent.downloaded_ever = 300;
+        server.add_torrent(torrent);
+
+        {
+            let mut state = server.state();
+            state.torrents[0].uploaded_ever = 1500;
+            state.torrents[1].uploaded_ever = 100;
+        }
+
+        traffic.update(&get_torrents());
+        traffic.update(&get_torrents());
+
+        server.state().torrents[2].downloaded_ever = 200;
+        traffic.update(&get_torrents());
+
+        assert_eq!(traffic.get(), &BTreeMap::from([
+            (s!("first.example.com"), Traffic {uploaded: 500, downloaded: 0}),
+            (s!("second.example.com"), Traffic {uploaded: 0, downloaded: 500}),
+        ]));
+    }
+}
@@ -0,0 +1,528 @@
+//! A mock Transmission daemon which serves the RPC protocol over TCP for end-to-end tests of the client and the
+//! controller. Torrents are described by fixtures which the tests can inspect and modify at any moment.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde_json::{json, Map, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::util::time::Timestamp;
+
+use super::{TlsOptions, TorrentStatus, TransmissionClient, TORRENT_PROCESSED_MARKER};
+
+#[derive(Debug, Clone)]
+pub struct MockTorrent {
+    pub id: u64,
+    pub hash: String,
+    pub name: String,
+    pub download_dir: String,
+    pub status: TorrentStatus,
+    /// File name, size and whether it's selected for download
+    pub files: Vec<(String, u64, bool)>,
+    pub torrent_file: String,
+    pub left_until_done: u64,
+    pub added_date: Timestamp,
+    pub done_date: Timestamp,
+    pub download_limit: u64,
+    pub download_limited: bool,
+    pub upload_limit: u64,
+    pub upload_limited: bool,
+    pub upload_ratio: f64,
+    pub seconds_seeding: u64,
+    pub uploaded_ever: u64,
+    pub downloaded_ever: u64,
+    pub private: bool,
+    pub error: i64,
+    pub error_string: String,
+    pub eta: i64,
+    pub peers_connected: u64,
+    pub bandwidth_priority: i64,
+    pub labels: Vec<String>,
+    pub trackers: Vec<String>,
+    /// Time and error of the last announce for each tracker
+    pub announces: Vec<(Timestamp, Option<String>)>,
+    pub group: String,
+    pub sequential_download: bool,
+}
+
+impl MockTorrent {
+    /// Creates a downloaded torrent with a single file
+    pub fn new(id: u64, name: &str, download_dir: &str) -> MockTorrent {
+        MockTorrent {
+            id: id,
+            hash: format!("{:040x}", id),
+            name: s!(name),
+            download_dir: s!(download_dir),
+            status: TorrentStatus::Seeding,
+            files: vec![(s!(name), 1024, true)],
+            torrent_file: String::new(),
+            left_until_done: 0,
+            added_date: 1_000_000 + id as Timestamp,
+            done_date: 1_000_000 + id as Timestamp,
+            download_limit: 100,
+            download_limited: false,
+            upload_limit: 100,
+            upload_limited: false,
+            upload_ratio: 0.0,
+            seconds_seeding: 0,
+            uploaded_ever: 0,
+            downloaded_ever: 0,
+            private: false,
+            error: 0,
+            error_string: String::new(),
+            eta: -1,
+            peers_connected: 0,
+            bandwidth_priority: 0,
+            labels: Vec::new(),
+            trackers: Vec::new(),
+            announces: Vec::new(),
+            group: String::new(),
+            sequential_download: false,
+        }
+    }
+
+    pub fn downloading(mut self) -> MockTorrent {
+        self.status = TorrentStatus::Downloading;
+        self.left_until_done = self.size();
+        self.done_date = 0;
+        self
+    }
+
+    pub fn processed(mut self) -> MockTorrent {
+        self.download_limit = TORRENT_PROCESSED_MARKER;
+        self
+    }
+
+    pub fn is_processed(&self) -> bool {
+        self.download_limit == TORRENT_PROCESSED_MARKER
+    }
+
+    pub fn size(&self) -> u64 {
+        self.files.iter().filter(|file| file.2).map(|file| file.1).sum()
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "hashString": self.hash,
+            "name": self.name,
+            "downloadDir": self.download_dir,
+            "status": self.status.code(),
+            "addedDate": self.added_date,
+            "wanted": self.files.iter().map(|file| file.2 as u8).collect::<Vec<_>>(),
+            "leftUntilDone": self.left_until_done,
+            "sizeWhenDone": self.size(),
+            "doneDate": self.done_date,
+            "downloadLimit": self.download_limit,
+            "downloadLimited": self.download_limited,
+            "uploadLimit": self.upload_limit,
+            "uploadLimited": self.upload_limited,
+            "uploadRatio": self.upload_ratio,
+            "secondsSeeding": self.seconds_seeding,
+            "uploadedEver": self.uploaded_ever,
+            "downloadedEver": self.downloaded_ever,
+            "isPrivate": self.private,
+            "error": self.error,
+            "errorString": self.error_string,
+            "eta": self.eta,
+            "peersConnected": self.peers_connected,
+            "bandwidthPriority": self.bandwidth_priority,
+            "labels": self.labels,
+            "trackers": self.trackers.iter().enumerate().map(|(index, url)| {
+                json!({"id": index, "announce": url})
+            }).collect::<Vec<_>>(),
+            "trackerStats": self.trackers.iter().enumerate().map(|(index, url)| {
+                let (time, error) = self.announces.get(index).cloned().unwrap_or_default();
+                json!({
+                    "announce": url,
+                    "hasAnnounced": time != 0,
+                    "lastAnnounceTime": time,
+                    "lastAnnounceSucceeded": error.is_none(),
+                    "lastAnnounceResult": error.unwrap_or_else(|| s!("Success")),
+                })
+            }).collect::<Vec<_>>(),
+            "group": self.group,
+            "files": self.files.iter().map(|file| json!({"name": file.0, "length": file.1})).collect::<Vec<_>>(),
+            "fileStats": self.files.iter().map(|file| json!({"wanted": file.2})).collect::<Vec<_>>(),
+            "torrentFile": self.torrent_file,
+        })
+    }
+}
+
+pub struct MockState {
+    pub version: String,
+    pub rpc_version: u32,
+    pub session_id: String,
+    pub alt_speed_enabled: bool,
+    /// Global speed limits in KB/s
+    pub download_limit: Option<u64>,
+    pub upload_limit: Option<u64>,
+    pub peer_port: u16,
+    pub port_is_open: bool,
+    /// Free space is increased by the size of the torrents removed with their data
+    pub free_space: u64,
+    /// Total space is reported only by Transmission 4.0+
+    pub total_space: Option<u64>,
+    /// Cumulative traffic in bytes
+    pub downloaded: u64,
+    pub uploaded: u64,
+
+    pub torrents: Vec<MockTorrent>,
+    pub removed: Vec<u64>,
+    /// Names of all received RPC methods
+    pub calls: Vec<String>,
+    /// Number of requests rejected due to invalid session ID
+    pub conflicts: u32,
+    /// HTTP errors (status line and JSON body) which are returned instead of handling the next requests
+    pub http_errors: VecDeque<(&'static str, String)>,
+}
+
+pub struct MockServer {
+    url: String,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockServer {
+    /// Starts the server. Must be called within the runtime which will serve the requests.
+    pub async fn start() -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/transmission/rpc", listener.local_addr().unwrap());
+
+        let state = Arc::new(Mutex::new(MockState {
+            version: s!("4.0.6"),
+            rpc_version: 17,
+            session_id: s!("session-1"),
+            alt_speed_enabled: false,
+            download_limit: None,
+            upload_limit: None,
+            peer_port: 51413,
+            port_is_open: true,
+            free_space: 50 * 1024 * 1024 * 1024,
+            total_space: Some(100 * 1024 * 1024 * 1024),
+            downloaded: 0,
+            uploaded: 0,
+
+            torrents: Vec::new(),
+            removed: Vec::new(),
+            calls: Vec::new(),
+            conflicts: 0,
+            http_errors: VecDeque::new(),
+        }));
+
+        let server_state = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_connection(stream, server_state.clone()));
+            }
+        });
+
+        MockServer {
+            url: url,
+            state: state,
+        }
+    }
+
+    pub fn client(&self) -> TransmissionClient {
+        TransmissionClient::new(&self.url, &TlsOptions {
+            ca_file: None,
+            verify_certificate: true,
+            server_name: None,
+            client_identity: None,
+        }, None).unwrap()
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+
+    pub fn add_torrent(&self, torrent: MockTorrent) {
+        self.state().torrents.push(torrent);
+    }
+
+    pub fn torrent(&self, id: u64) -> Option<MockTorrent> {
+        self.state().torrents.iter().find(|torrent| torrent.id == id).cloned()
+    }
+
+    /// Emulates daemon restart which invalidates the session ID
+    pub fn restart(&self) {
+        let mut state = self.state();
+        let id: u64 = state.session_id.trim_start_matches("session-").parse().unwrap();
+        state.session_id = format!("session-{}", id + 1);
+    }
+}
+
+async fn serve_connection(mut stream: TcpStream, state: Arc<Mutex<MockState>>) {
+    let mut buffer = Vec::new();
+
+    loop {
+        let (session_id, body) = loop {
+            if let Some((offset, session_id, length)) = parse_request_headers(&buffer) {
+                if buffer.len() >= offset + length {
+                    let body = buffer[offset..offset + length].to_vec();
+                    buffer.drain(..offset + length);
+                    break (session_id, body);
+                }
+            }
+
+            let mut data = [0; 4096];
+            match stream.read(&mut data).await {
+                Ok(0) | Err(_) => return,
+                Ok(size) => buffer.extend_from_slice(&data[..size]),
+            }
+        };
+
+        let response = {
+            let mut state = state.lock().unwrap();
+
+            if session_id.as_ref() != Some(&state.session_id) {
+                state.conflicts += 1;
+                format!(
+                    "HTTP/1.1 409 Conflict\r\nX-Transmission-Session-Id: {}\r\nContent-Length: 0\r\n\r\n",
+                    state.session_id)
+            } else if let Some((status, body)) = state.http_errors.pop_front() {
+                format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    status, body.len(), body)
+            } else {
+                let body = handle_request(&mut state, &body).to_string();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json; charset=UTF-8\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(), body)
+            }
+        };
+
+        if stream.write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn parse_request_headers(data: &[u8]) -> Option<(usize, Option<String>, usize)> {
+    let mut headers = [httparse::EMPTY_HEADER; 32];
+    let mut request = httparse::Request::new(&mut headers);
+
+    let offset = match request.parse(data).unwrap() {
+        httparse::Status::Complete(offset) => offset,
+        httparse::Status::Partial => return None,
+    };
+
+    let mut session_id = None;
+    let mut length = 0;
+
+    for header in request.headers.iter() {
+        let value = String::from_utf8_lossy(header.value).to_string();
+        if header.name.eq_ignore_ascii_case("X-Transmission-Session-Id") {
+            session_id = Some(value);
+        } else if header.name.eq_ignore_ascii_case("Content-Length") {
+            length = value.parse().unwrap();
+        }
+    }
+
+    Some((offset, session_id, length))
+}
+
+fn handle_request(state: &mut MockState, body: &[u8]) -> Value {
+    let request: Value = serde_json::from_slice(body).unwrap();
+    let method = request["method"].as_str().unwrap().to_owned();
+    let arguments = &request["arguments"];
+
+    state.calls.push(method.clone());
+
+    let result = match method.as_str() {
+        "session-get" => Ok(json!({
+            "version": state.version,
+            "rpc-version": state.rpc_version,
+            "rpc-version-minimum": 14,
+            "alt-speed-enabled": state.alt_speed_enabled,
+            "peer-port": state.peer_port,
+        })),
+        "session-set" => {
+            if let Some(enabled) = arguments["alt-speed-enabled"].as_bool() {
+                state.alt_speed_enabled = enabled;
+            }
+            if let Some(port) = arguments["peer-port"].as_u64() {
+                state.peer_port = port as u16;
+            }
+            for (enabled, limit, value) in [
+                ("speed-limit-down-enabled", "speed-limit-down", &mut state.download_limit),
+                ("speed-limit-up-enabled", "speed-limit-up", &mut state.upload_limit),
+            ] {
+                match arguments[enabled].as_bool() {
+                    Some(true) => *value = arguments[limit].as_u64(),
+                    Some(false) => *value = None,
+                    None => {},
+                }
+            }
+            Ok(json!({}))
+        },
+        "port-test" => Ok(json!({"port-is-open": state.port_is_open})),
+        "session-stats" => Ok(json!({
+            "cumulative-stats": {
+                "downloadedBytes": state.downloaded,
+                "uploadedBytes": state.uploaded,
+            },
+        })),
+        "free-space" => {
+            let mut response = json!({
+                "path": arguments["path"],
+                "size-bytes": state.free_space,
+            });
+            if let Some(total) = state.total_space {
+                response["total_size"] = json!(total);
+            }
+            Ok(response)
+        },
+        "torrent-get" => {
+            let recently_active = arguments["ids"].as_str() == Some("recently-active");
+            let hashes: Option<HashSet<&str>> = arguments["ids"].as_array().map(|ids| {
+                ids.iter().filter_map(Value::as_str).collect()
+            });
+
+            let fields: HashSet<&str> = arguments["fields"].as_array().unwrap().iter()
+                .filter_map(Value::as_str).collect();
+
+            // Only the requested fields are returned to catch the code which relies on the missing ones
+            let torrents: Vec<Value> = state.torrents.iter()
+                .filter(|torrent| hashes.as_ref().is_none_or(|hashes| hashes.contains(torrent.hash.as_str())))
+                .map(|torrent| {
+                    let mut torrent = torrent.to_json();
+                    torrent.as_object_mut().unwrap().retain(|name, _| fields.contains(name.as_str()));
+                    torrent
+                })
+                .collect();
+
+            let mut response = json!({"torrents": torrents});
+            if recently_active {
+                response["removed"] = json!(state.removed);
+                state.removed.clear();
+            }
+            Ok(response)
+        },
+        "torrent-add" => add_torrent(state, arguments),
+        "torrent-set" | "torrent-set-location" | "torrent-start" | "torrent-stop" | "torrent-verify" |
+        "torrent-reannounce" | "torrent-remove" => {
+            let hashes: HashSet<String> = arguments["ids"].as_array().unwrap().iter()
+                .map(|id| s!(id.as_str().unwrap())).collect();
+            modify_torrents(state, &method, &hashes, arguments);
+            Ok(json!({}))
+        },
+        _ => Err(format!("Unsupported method: {}", method)),
+    };
+
+    match result {
+        Ok(arguments) => json!({"result": "success", "arguments": arguments}),
+        Err(error) => json!({"result": error}),
+    }
+}
+
+/// Treats contents of .torrent files (`dn` parameter of magnet links or the last path component of HTTP URLs) as
+/// torrent names
+fn add_torrent(state: &mut MockState, arguments: &Value) -> Result<Value, String> {
+    let name = if let Some(metainfo) = arguments["metainfo"].as_str() {
+        let data = BASE64.decode(metainfo).unwrap();
+        String::from_utf8(data).map_err(|_| s!("invalid or corrupt torrent file"))?
+    } else {
+        let url = arguments["filename"].as_str().unwrap();
+        if let Some(url) = url.strip_prefix("magnet:?") {
+            url.split('&').find_map(|param| param.strip_prefix("dn=")).map(String::from).unwrap_or_else(|| s!("magnet"))
+        } else if url.starts_with("http://") || url.starts_with("https://") {
+            let path = url.split('?').next().unwrap();
+            s!(path.rsplit('/').next().unwrap())
+        } else {
+            return Err(s!("invalid or corrupt torrent file"));
+        }
+    };
+
+    if name.trim().is_empty() || name.contains('\n') {
+        return Err(s!("invalid or corrupt torrent file"));
+    }
+
+    if let Some(torrent) = state.torrents.iter().find(|torrent| torrent.name == name) {
+        return Ok(json!({"torrent-duplicate": {"id": torrent.id, "name": torrent.name, "hashString": torrent.hash}}));
+    }
+
+    let id = state.torrents.iter().map(|torrent| torrent.id).max().unwrap_or(0) + 1;
+    let mut torrent = MockTorrent::new(id, &name, arguments["download-dir"].as_str().unwrap_or("/downloads"))
+        .downloading();
+    if let Some(labels) = arguments["labels"].as_array() {
+        torrent.labels = labels.iter().map(|label| s!(label.as_str().unwrap())).collect();
+    }
+
+    let response = json!({"torrent-added": {"id": torrent.id, "name": torrent.name, "hashString": torrent.hash}});
+    state.torrents.push(torrent);
+
+    Ok(response)
+}
+
+fn modify_torrents(state: &mut MockState, method: &str, hashes: &HashSet<String>, arguments: &Value) {
+    if method == "torrent-remove" {
+        let delete_local_data = arguments["delete-local-data"].as_bool().unwrap_or(false);
+
+        let (removed, torrents): (Vec<_>, Vec<_>) = state.torrents.drain(..).partition(|torrent| {
+            hashes.contains(&torrent.hash)
+        });
+        state.torrents = torrents;
+
+        for torrent in removed {
+            if delete_local_data {
+                state.free_space += torrent.size();
+            }
+            state.removed.push(torrent.id);
+        }
+
+        return;
+    }
+
+    let arguments: &Map<String, Value> = arguments.as_object().unwrap();
+
+    for torrent in state.torrents.iter_mut().filter(|torrent| hashes.contains(&torrent.hash)) {
+        match method {
+            "torrent-start" => torrent.status = if torrent.left_until_done == 0 {
+                TorrentStatus::Seeding
+            } else {
+                TorrentStatus::Downloading
+            },
+            "torrent-stop" => torrent.status = TorrentStatus::Stopped,
+            "torrent-verify" => torrent.status = TorrentStatus::CheckWait,
+            "torrent-set" => {
+                if let Some(limit) = arguments.get("downloadLimit").and_then(Value::as_u64) {
+                    torrent.download_limit = limit;
+                }
+                if let Some(limited) = arguments.get("downloadLimited").and_then(Value::as_bool) {
+                    torrent.download_limited = limited;
+                }
+                if let Some(limit) = arguments.get("uploadLimit").and_then(Value::as_u64) {
+                    torrent.upload_limit = limit;
+                }
+                if let Some(limited) = arguments.get("uploadLimited").and_then(Value::as_bool) {
+                    torrent.upload_limited = limited;
+                }
+                if let Some(group) = arguments.get("group").and_then(Value::as_str) {
+                    torrent.group = s!(group);
+                }
+                if let Some(enabled) = arguments.get("sequential_download").and_then(Value::as_bool) {
+                    torrent.sequential_download = enabled;
+                }
+                if let Some(labels) = arguments.get("labels").and_then(Value::as_array) {
+                    torrent.labels = labels.iter().map(|label| s!(label.as_str().unwrap())).collect();
+                }
+                if let Some(trackers) = arguments.get("trackerAdd").and_then(Value::as_array) {
+                    torrent.trackers.extend(trackers.iter().map(|url| s!(url.as_str().unwrap())));
+                }
+                if let Some(trackers) = arguments.get("trackerReplace").and_then(Value::as_array) {
+                    for pair in trackers.chunks(2) {
+                        torrent.trackers[pair[0].as_u64().unwrap() as usize] = s!(pair[1].as_str().unwrap());
+                    }
+                }
+            },
+            "torrent-set-location" => {
+                torrent.download_dir = s!(arguments["location"].as_str().unwrap());
+            },
+            _ => {},
+        }
+    }
+}
@@ -0,0 +1,1693 @@
+#![allow(deprecated)] // We still use deprecated RustcDecodable here
+
+use std::convert::From;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use itertools::Itertools;
+use mime::{self, Mime};
+use rand::Rng;
+use reqwest::{Certificate, Client, ClientBuilder, Identity, StatusCode, Url, header};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde::{ser, de, Serialize, Deserialize};
+
+use crate::common::GenericResult;
+use crate::util::matching;
+use crate::util::time::Timestamp;
+
+use self::transport::{HttpResponse, Transport, UnixSocketTransport};
+pub use self::status::{Eta, TorrentError, TorrentStatus};
+pub use self::version::{Feature, ServerVersion};
+
+#[cfg(test)] pub mod mock;
+mod status;
+mod transport;
+mod version;
+
+pub struct TransmissionClient {
+    transport: Transport,
+    url: Url,
+    user: Option<String>,
+    password: Option<String>,
+    session_id: RwLock<Option<String>>,
+    server_version: RwLock<Option<ServerVersion>>,
+    retry_policy: RetryPolicy,
+    dry_run: bool,
+    /// Actions which have been skipped in dry run mode (to log each of them only once)
+    dry_run_actions: Mutex<HashSet<String>>,
+}
+
+/// Retry policy for the calls that failed due to transient errors (connection errors while Transmission restarts, for
+/// example). Uses exponential backoff with jitter.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn no_retries() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            initial_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        }
+    }
+
+    pub fn get_delay(&self, attempt: u32) -> Duration {
+        let delay = self.initial_delay.saturating_mul(2_u32.saturating_pow(attempt - 1));
+        let delay = std::cmp::min(delay, self.max_delay);
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+/// Address family which is tried first when RPC host resolves to both IPv4 and IPv6 addresses
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+/// TLS settings for https:// RPC URLs
+pub struct TlsOptions {
+    pub ca_file: Option<PathBuf>,
+    pub verify_certificate: bool,
+    pub server_name: Option<String>,
+    /// Client certificate and private key (PKCS #8) PEM files
+    pub client_identity: Option<(PathBuf, PathBuf)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Torrent {
+    pub id: u64,
+    pub hash: String,
+    pub name: String,
+    pub status: TorrentStatus,
+    pub files: Option<Vec<TorrentFile>>,
+    /// Path to the torrent's .torrent file (requested along with the files)
+    pub torrent_file: Option<String>,
+    pub download_dir: String,
+    pub done: bool,
+    pub done_time: Option<Timestamp>,
+    /// Size of the selected files in bytes
+    pub size: u64,
+    pub left_until_done: u64,
+    pub peers_connected: u64,
+    pub added_time: Timestamp,
+    /// Bandwidth priority: -1 (low), 0 (normal) or 1 (high)
+    pub priority: i64,
+    /// Time spent in seeding state in seconds
+    pub seeding_time: u64,
+    pub upload_ratio: Option<f64>,
+    /// Bytes transferred over the torrent's lifetime
+    pub uploaded: u64,
+    pub downloaded: u64,
+    /// The torrent is from a private tracker (DHT, PEX and arbitrary trackers mustn't be used)
+    pub private: bool,
+    /// Per-torrent speed limits in KB/s (if enabled)
+    pub download_limit: Option<u64>,
+    pub upload_limit: Option<u64>,
+    pub processed: bool,
+    pub error: Option<TorrentError>,
+    pub eta: Eta,
+
+    pub labels: Vec<String>,
+    /// Announce URLs
+    pub trackers: Vec<String>,
+    /// IDs of the trackers (in the same order as the announce URLs)
+    pub tracker_ids: Vec<u64>,
+    pub tracker_stats: Option<Vec<TrackerStats>>,
+    pub bandwidth_group: Option<String>,
+}
+
+impl Torrent {
+    pub fn tracker_hosts(&self) -> Vec<String> {
+        self.trackers.iter().filter_map(|url| matching::get_url_host(url)).unique().collect()
+    }
+}
+
+/// Cumulative traffic statistics of the daemon (persisted by Transmission across restarts)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+pub struct SessionStats {
+    #[serde(rename = "downloadedBytes")]
+    pub downloaded: u64,
+    #[serde(rename = "uploadedBytes")]
+    pub uploaded: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FreeSpace {
+    pub free: u64,
+    pub total: Option<u64>,
+}
+
+/// Global speed limits (in KB/s) to set via session-set. Unspecified values are left untouched.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SpeedLimits {
+    #[serde(rename = "alt-speed-enabled", skip_serializing_if = "Option::is_none")]
+    pub alt_speed_enabled: Option<bool>,
+    #[serde(rename = "speed-limit-down-enabled", skip_serializing_if = "Option::is_none")]
+    pub download_limit_enabled: Option<bool>,
+    #[serde(rename = "speed-limit-down", skip_serializing_if = "Option::is_none")]
+    pub download_limit: Option<u64>,
+    #[serde(rename = "speed-limit-up-enabled", skip_serializing_if = "Option::is_none")]
+    pub upload_limit_enabled: Option<bool>,
+    #[serde(rename = "speed-limit-up", skip_serializing_if = "Option::is_none")]
+    pub upload_limit: Option<u64>,
+}
+
+/// Transmission 4.x bandwidth group. Speed limits are in KB/s.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BandwidthGroup {
+    pub name: String,
+    #[serde(rename = "honorsSessionLimits")]
+    pub honors_session_limits: bool,
+    #[serde(rename = "speed-limit-down-enabled")]
+    pub download_limit_enabled: bool,
+    #[serde(rename = "speed-limit-down")]
+    pub download_limit: u64,
+    #[serde(rename = "speed-limit-up-enabled")]
+    pub upload_limit_enabled: bool,
+    #[serde(rename = "speed-limit-up")]
+    pub upload_limit: u64,
+}
+
+/// Optional (expensive) torrent fields to request in addition to the basic ones
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TorrentFields {
+    pub files: bool,
+    pub tracker_stats: bool,
+}
+
+impl TorrentFields {
+    pub fn basic() -> TorrentFields {
+        TorrentFields::default()
+    }
+
+    pub fn with_files(mut self) -> TorrentFields {
+        self.files = true;
+        self
+    }
+
+    pub fn with_tracker_stats(mut self) -> TorrentFields {
+        self.tracker_stats = true;
+        self
+    }
+}
+
+pub enum TorrentSource<'a> {
+    /// Contents of .torrent file
+    Metainfo(&'a [u8]),
+    /// Magnet link or URL of .torrent file
+    Url(&'a str),
+}
+
+pub struct AddedTorrent {
+    pub hash: String,
+    pub name: String,
+    /// The torrent already exists
+    pub duplicate: bool,
+}
+
+/// Result of incremental torrents polling
+pub struct RecentlyActiveTorrents {
+    pub torrents: Vec<Torrent>,
+    pub removed: Vec<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TorrentFile {
+    pub name: String,
+    pub size: u64,
+    pub selected: bool,
+}
+
+/// Result of the last announce to the tracker
+#[derive(Debug, Clone)]
+pub struct TrackerStats {
+    pub announce: String,
+    /// Not set if the torrent hasn't been announced to the tracker yet
+    pub last_announce_time: Option<Timestamp>,
+    /// Error message if the last announce has failed
+    pub last_announce_error: Option<String>,
+}
+
+enum TorrentIds<'a> {
+    Hashes(&'a [String]),
+    RecentlyActive,
+}
+
+impl Serialize for TorrentIds<'_> {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match *self {
+            TorrentIds::Hashes(hashes) => hashes.serialize(serializer),
+            TorrentIds::RecentlyActive => serializer.serialize_str("recently-active"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SessionGetRequest {
+    fields: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+struct EmptyRequest{
+}
+
+#[derive(Deserialize)]
+struct EmptyResponse{
+}
+
+/// torrent-get response's torrent list. The torrents are converted into compact [`Torrent`] structs as they are being
+/// decoded, so there may be thousands of them without keeping their raw representation in memory.
+struct Torrents(Vec<Torrent>);
+
+impl<'de> Deserialize<'de> for Torrents {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> std::result::Result<Torrents, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Torrents;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a list of torrents")
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Torrents, A::Error> {
+                let mut torrents = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+                while let Some(torrent) = seq.next_element::<TransmissionTorrent>()? {
+                    torrents.push(convert_torrent(torrent).map_err(de::Error::custom)?);
+                }
+                Ok(Torrents(torrents))
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TransmissionTorrent {
+    id: u64,
+    #[serde(rename = "hashString")]
+    hash_string: String,
+    name: String,
+    #[serde(rename = "downloadDir")]
+    download_dir: String,
+    status: TorrentStatus,
+    #[serde(rename = "addedDate")]
+    added_date: Timestamp,
+    #[serde(rename = "bandwidthPriority")]
+    bandwidth_priority: i64,
+    wanted: Option<Vec<u8>>,
+    #[serde(rename = "leftUntilDone")]
+    left_until_done: u64,
+    #[serde(rename = "sizeWhenDone")]
+    size_when_done: u64,
+    #[serde(rename = "doneDate")]
+    done_date: Timestamp,
+    #[serde(rename = "downloadLimit")]
+    download_limit: u64,
+    #[serde(rename = "downloadLimited")]
+    download_limited: bool,
+    #[serde(rename = "uploadLimit")]
+    upload_limit: u64,
+    #[serde(rename = "uploadLimited")]
+    upload_limited: bool,
+    files: Option<Vec<File>>,
+    #[serde(rename = "fileStats")]
+    file_stats: Option<Vec<FileStats>>,
+    #[serde(rename = "torrentFile")]
+    torrent_file: Option<String>,
+    #[serde(rename = "uploadRatio")]
+    upload_ratio: f64,
+    #[serde(rename = "secondsSeeding")]
+    seconds_seeding: u64,
+    #[serde(rename = "uploadedEver")]
+    uploaded_ever: u64,
+    #[serde(rename = "downloadedEver")]
+    downloaded_ever: u64,
+    #[serde(rename = "isPrivate")]
+    is_private: bool,
+    error: i64,
+    #[serde(rename = "errorString")]
+    error_string: String,
+    eta: i64,
+    #[serde(rename = "peersConnected")]
+    peers_connected: u64,
+    // Available since Transmission 3.0 (RPC version 16)
+    #[serde(default)]
+    labels: Vec<String>,
+    #[serde(default)]
+    trackers: Vec<Tracker>,
+    #[serde(rename = "trackerStats")]
+    tracker_stats: Option<Vec<TransmissionTrackerStats>>,
+    // Available since Transmission 4.0 (RPC version 17)
+    #[serde(default)]
+    group: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Tracker {
+    id: u64,
+    announce: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransmissionTrackerStats {
+    announce: String,
+    #[serde(rename = "hasAnnounced")]
+    has_announced: bool,
+    #[serde(rename = "lastAnnounceTime")]
+    last_announce_time: Timestamp,
+    #[serde(rename = "lastAnnounceSucceeded")]
+    last_announce_succeeded: bool,
+    #[serde(rename = "lastAnnounceResult")]
+    last_announce_result: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct File {
+    name: String,
+    length: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct FileStats {
+    wanted: bool,
+}
+
+fn convert_torrent(torrent: TransmissionTorrent) -> std::result::Result<Torrent, String> {
+    let files = match (torrent.files, torrent.file_stats) {
+        (Some(file_infos), Some(file_stats)) => {
+            if file_infos.len() != file_stats.len() {
+                return Err(s!("Torrent's `files` and `fileStats` don't match"));
+            }
+
+            Some(file_infos.into_iter().zip(&file_stats).map(|item| {
+                TorrentFile {
+                    name: item.0.name,
+                    size: item.0.length,
+                    selected: item.1.wanted,
+                }
+            }).collect())
+        },
+        (None, None) => None,
+        _ => return Err(s!("Got a torrent with missing `files` or `fileStats`")),
+    };
+
+    let tracker_stats = torrent.tracker_stats.map(|stats| {
+        stats.into_iter().map(|stats| {
+            let announced = stats.has_announced && stats.last_announce_time != 0;
+
+            TrackerStats {
+                announce: stats.announce,
+                last_announce_time: if announced { Some(stats.last_announce_time) } else { None },
+                last_announce_error: if announced && !stats.last_announce_succeeded {
+                    Some(stats.last_announce_result)
+                } else {
+                    None
+                },
+            }
+        }).collect()
+    });
+
+    // It's not actually easy to determine when torrent is downloaded:
+    // * doneDate is not reset when we add new files to download
+    // * percentDone may be 1.0 even when only 99% has been downloaded
+    // * leftUntilDone looks like a best marker (or we can use files + wanted, but it's more expensive)
+    let done = torrent.left_until_done == 0 && (
+        // Ensure that we check torrent status not in the moment when user temporary unmarked all files to start
+        // select only individual ones. The per-file wanted flags are requested only along with the files, and
+        // sizeWhenDone (the size of the wanted files) is zero in this case otherwise.
+        match torrent.wanted {
+            Some(ref wanted) => wanted.contains(&1),
+            None => torrent.size_when_done != 0,
+        }
+    );
+
+    let done_time = if done {
+        // doneDate is set only when torrent is downloaded. If we add a torrent that
+        // already downloaded on the disk doneDate won't be updated.
+        Some(if torrent.done_date != 0 { torrent.done_date } else { torrent.added_date })
+    } else {
+        None
+    };
+
+    Ok(Torrent {
+        id:           torrent.id,
+        hash:         torrent.hash_string,
+        name:         torrent.name,
+        status:       torrent.status,
+        files:        files,
+        torrent_file: torrent.torrent_file.filter(|path| !path.is_empty()),
+        download_dir: torrent.download_dir,
+        done:         done,
+        done_time:    done_time,
+        size:         torrent.size_when_done,
+        seeding_time: torrent.seconds_seeding,
+        upload_ratio: if torrent.upload_ratio > 0.0 {
+            Some(torrent.upload_ratio)
+        } else {
+            None
+        },
+        uploaded:     torrent.uploaded_ever,
+        downloaded:   torrent.downloaded_ever,
+        private:      torrent.is_private,
+        processed:    torrent.download_limit == TORRENT_PROCESSED_MARKER,
+        download_limit: torrent.download_limited.then_some(torrent.download_limit),
+        upload_limit:   torrent.upload_limited.then_some(torrent.upload_limit),
+        error:        TorrentError::from_code(torrent.error, torrent.error_string),
+        eta:          Eta::from_value(torrent.eta),
+
+        left_until_done: torrent.left_until_done,
+        peers_connected: torrent.peers_connected,
+        added_time:      torrent.added_date,
+        priority:        torrent.bandwidth_priority,
+
+        labels:          torrent.labels,
+        tracker_ids:     torrent.trackers.iter().map(|tracker| tracker.id).collect(),
+        trackers:        torrent.trackers.into_iter().map(|tracker| tracker.announce).collect(),
+        tracker_stats:   tracker_stats,
+        bandwidth_group: if torrent.group.is_empty() {
+            None
+        } else {
+            Some(torrent.group)
+        },
+    })
+}
+
+pub type Result<T> = std::result::Result<T, TransmissionClientError>;
+pub type EmptyResult = Result<()>;
+
+// Use this value of downloadLimit as marker for processed torrents
+const TORRENT_PROCESSED_MARKER: u64 = 42;
+
+const SESSION_ID_HEADER_NAME: &str = "X-Transmission-Session-Id";
+const SESSION_ID_MAX_RETRIES: usize = 2;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Transmission closes idle connections after some time, so there is no reason to keep them longer
+const CONNECTION_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl TransmissionClient{
+    pub fn new(
+        url: &str, tls: &TlsOptions, address_family: Option<AddressFamily>,
+    ) -> GenericResult<TransmissionClient> {
+        let mut url = Url::parse(url).map_err(|e| format!("Invalid RPC URL {:?}: {}", url, e))?;
+        // All RPC calls (including the ones from consumer thread) go through a single keep-alive connection
+        let mut builder = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .pool_max_idle_per_host(1)
+            .pool_idle_timeout(CONNECTION_IDLE_TIMEOUT)
+            .tcp_keepalive(CONNECTION_IDLE_TIMEOUT);
+
+        let mut resolver = Resolver {
+            address_family: address_family,
+            alias: None,
+        };
+
+        if url.scheme() == "https" {
+            builder = configure_tls(builder, &mut url, tls, &mut resolver)?;
+        }
+
+        if resolver.address_family.is_some() || resolver.alias.is_some() {
+            builder = builder.dns_resolver(Arc::new(resolver));
+        }
+
+        let client = builder.build().map_err(|e| format!("Unable to create HTTP client: {}", e))?;
+        Ok(TransmissionClient::new_with_transport(Transport::Http(client), url))
+    }
+
+    /// Creates a client that sends HTTP requests to the specified URL via UNIX domain socket
+    pub fn new_with_unix_socket(path: &Path, url: &str) -> GenericResult<TransmissionClient> {
+        let url = Url::parse(url).map_err(|e| format!("Invalid RPC URL {:?}: {}", url, e))?;
+        let transport = Transport::UnixSocket(UnixSocketTransport::new(path, REQUEST_TIMEOUT));
+        Ok(TransmissionClient::new_with_transport(transport, url))
+    }
+
+    fn new_with_transport(transport: Transport, url: Url) -> TransmissionClient {
+        TransmissionClient {
+            transport: transport,
+            url: url,
+            user: None,
+            password: None,
+            session_id: RwLock::new(None),
+            server_version: RwLock::new(None),
+            retry_policy: RetryPolicy::no_retries(),
+            dry_run: false,
+            dry_run_actions: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn set_authentication(&mut self, user: &str, password: &str) {
+        self.user = Some(s!(user));
+        self.password = Some(s!(password));
+    }
+
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    /// In dry run mode all modifying calls are only logged
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Returns version of the daemon. The version is requested on first use and after each daemon restart.
+    pub async fn get_server_version(&self) -> Result<ServerVersion> {
+        if let Some(ref version) = *self.server_version.read().unwrap() {
+            return Ok(version.clone());
+        }
+
+        let version: ServerVersion = self.call("session-get", &SessionGetRequest {
+            fields: vec!["version", "rpc-version", "rpc-version-minimum"],
+        }).await?;
+
+        info!("Connected to {}.", version);
+        if version.min_rpc_version > version::MAX_SUPPORTED_RPC_VERSION {
+            warn!("{} doesn't support RPC version {} we use. Some operations may fail.",
+                  version, version::MAX_SUPPORTED_RPC_VERSION);
+        }
+
+        *self.server_version.write().unwrap() = Some(version.clone());
+        Ok(version)
+    }
+
+    /// Checks that the daemon supports the specified feature
+    pub async fn check_feature(&self, feature: Feature) -> EmptyResult {
+        let version = self.get_server_version().await?;
+
+        if !version.supports(feature) {
+            return Err(Rpc(UnsupportedFeatureError(format!(
+                "{} requires Transmission {}+ (RPC version {}), but the daemon is {}",
+                feature, feature.transmission_version(), feature.rpc_version(), version))));
+        }
+
+        Ok(())
+    }
+
+    pub async fn is_manual_mode(&self) -> Result<bool> {
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(rename = "alt-speed-enabled")]
+            alt_speed_enabled: bool,
+        }
+
+        let response: Response = self.call("session-get", &EmptyRequest{}).await?;
+
+        Ok(response.alt_speed_enabled)
+    }
+
+    pub async fn set_manual_mode(&self, enabled: bool) -> EmptyResult {
+        #[derive(Serialize)]
+        struct Request {
+            #[serde(rename = "alt-speed-enabled")]
+            alt_speed_enabled: bool,
+        }
+
+        if self.skip_in_dry_run(|| format!("turn {} alternative speed limits", if enabled { "on" } else { "off" })) {
+            return Ok(());
+        }
+
+        let _: EmptyResponse = self.call("session-set", &Request {
+            alt_speed_enabled: enabled,
+        }).await?;
+
+        Ok(())
+    }
+
+    pub async fn set_speed_limits(&self, limits: &SpeedLimits) -> EmptyResult {
+        if self.skip_in_dry_run(|| format!("set speed limits: {:?}", limits)) {
+            return Ok(());
+        }
+
+        let _: EmptyResponse = self.call("session-set", limits).await?;
+        Ok(())
+    }
+
+    pub async fn get_torrents(&self, fields: TorrentFields) -> Result<Vec<Torrent>> {
+        Ok(self._get_torrents(None, fields).await?.0)
+    }
+
+    /// Returns torrents that have been active in the last 60 seconds and IDs of the torrents that have been removed
+    /// during this period.
+    pub async fn get_recently_active_torrents(&self, fields: TorrentFields) -> Result<RecentlyActiveTorrents> {
+        let (torrents, removed) = self._get_torrents(Some(TorrentIds::RecentlyActive), fields).await?;
+        Ok(RecentlyActiveTorrents {
+            torrents: torrents,
+            removed: removed.ok_or_else(|| Protocol(s!(
+                "Got a response to `recently-active` request without removed torrents list")))?,
+        })
+    }
+
+    pub async fn get_torrent(&self, hash: &str, fields: TorrentFields) -> Result<Torrent> {
+        let mut torrents = self.get_torrents_by_hash(&[s!(hash)], fields).await?;
+        match torrents.len() {
+            0 => Err(Rpc(TorrentNotFoundError(s!(hash)))),
+            1 => Ok(torrents.pop().unwrap()),
+            _ => Err(Protocol(s!("Got a few torrents when requested only one"))),
+        }
+    }
+
+    /// Returns the specified torrents skipping the ones that don't exist
+    pub async fn get_torrents_by_hash(&self, hashes: &[String], fields: TorrentFields) -> Result<Vec<Torrent>> {
+        Ok(self._get_torrents(Some(TorrentIds::Hashes(hashes)), fields).await?.0)
+    }
+
+    async fn _get_torrents(&self, ids: Option<TorrentIds<'_>>, requested_fields: TorrentFields) -> Result<(Vec<Torrent>, Option<Vec<u64>>)> {
+        #[derive(Serialize)]
+        struct Request<'a> {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            ids: Option<TorrentIds<'a>>,
+            fields: Vec<&'static str>,
+        }
+
+        #[derive(Deserialize)]
+        struct Response {
+            torrents: Torrents,
+            removed: Option<Vec<u64>>,
+        }
+
+        let mut fields = vec![
+            "id", "hashString", "name", "downloadDir", "status", "addedDate", "leftUntilDone", "sizeWhenDone",
+            "doneDate", "downloadLimit", "downloadLimited", "uploadLimit", "uploadLimited", "uploadRatio",
+            "secondsSeeding", "uploadedEver", "downloadedEver", "isPrivate", "error", "errorString", "eta",
+            "peersConnected", "bandwidthPriority", "labels", "trackers", "group",
+        ];
+        let with_files = requested_fields.files;
+        if with_files {
+            fields.push("files");
+            fields.push("fileStats");
+            fields.push("wanted");
+            fields.push("torrentFile");
+        }
+        let with_tracker_stats = requested_fields.tracker_stats;
+        if with_tracker_stats {
+            fields.push("trackerStats");
+        }
+
+        let response: Response = self.call("torrent-get", &Request {
+            ids: ids,
+            fields: fields,
+        }).await?;
+
+        let torrents = response.torrents.0;
+
+        if with_files && torrents.iter().any(|torrent| torrent.files.is_none()) {
+            return Err(Protocol(s!("Got a torrent with missing `files`")));
+        }
+        if with_tracker_stats && torrents.iter().any(|torrent| torrent.tracker_stats.is_none()) {
+            return Err(Protocol(s!("Got a torrent with missing `trackerStats`")));
+        }
+
+        Ok((torrents, response.removed))
+    }
+
+    pub async fn start(&self, hash: &str) -> EmptyResult {
+        #[derive(Serialize)]
+        struct Request {
+            ids: Vec<String>,
+        }
+
+        if self.skip_in_dry_run(|| format!("start {} torrent", hash)) {
+            return Ok(());
+        }
+
+        let _: EmptyResponse = self.call("torrent-start", &Request {
+            ids: vec![s!(hash)]
+        }).await?;
+
+        Ok(())
+    }
+
+    pub async fn stop(&self, hash: &str) -> EmptyResult {
+        #[derive(Serialize)]
+        struct Request {
+            ids: Vec<String>,
+        }
+
+        if self.skip_in_dry_run(|| format!("stop {} torrent", hash)) {
+            return Ok(());
+        }
+
+        let _: EmptyResponse = self.call("torrent-stop", &Request {
+            ids: vec![s!(hash)]
+        }).await?;
+
+        Ok(())
+    }
+
+    /// Starts hash checking of the torrent's local data
+    pub async fn verify(&self, hash: &str) -> EmptyResult {
+        #[derive(Serialize)]
+        struct Request {
+            ids: Vec<String>,
+        }
+
+        if self.skip_in_dry_run(|| format!("verify {} torrent", hash)) {
+            return Ok(());
+        }
+
+        let _: EmptyResponse = self.call("torrent-verify", &Request {
+            ids: vec![s!(hash)]
+        }).await?;
+
+        Ok(())
+    }
+
+    /// Asks trackers for more peers right now instead of waiting for the next scheduled announce
+    pub async fn reannounce(&self, hash: &str) -> EmptyResult {
+        #[derive(Serialize)]
+        struct Request {
+            ids: Vec<String>,
+        }
+
+        if self.skip_in_dry_run(|| format!("reannounce {} torrent", hash)) {
+            return Ok(());
+        }
+
+        let _: EmptyResponse = self.call("torrent-reannounce", &Request {
+            ids: vec![s!(hash)]
+        }).await?;
+
+        Ok(())
+    }
+
+    pub async fn set_processed(&self, hash: &str) -> EmptyResult {
+        #[derive(Serialize)]
+        struct Request {
+            ids: Vec<String>,
+            #[serde(rename = "downloadLimit")]
+            download_limit: u64,
+        }
+
+        if self.skip_in_dry_run(|| format!("mark {} torrent as processed", hash)) {
+            return Ok(());
+        }
+
+        let _: EmptyResponse = self.call("torrent-set", &Request {
+            ids: vec![s!(hash)],
+            download_limit: TORRENT_PROCESSED_MARKER,
+        }).await?;
+
+        Ok(())
+    }
+
+    pub async fn set_labels(&self, hash: &str, labels: &[String]) -> EmptyResult {
+        #[derive(Serialize)]
+        struct Request<'a> {
+            ids: Vec<String>,
+            labels: &'a [String],
+        }
+
+        self.check_feature(Feature::Labels).await?;
+
+        if self.skip_in_dry_run(|| format!("set {} torrent labels to {:?}", hash, labels)) {
+            return Ok(());
+        }
+
+        let _: EmptyResponse = self.call("torrent-set", &Request {
+            ids: vec![s!(hash)],
+            labels: labels,
+        }).await?;
+
+        Ok(())
+    }
+
+    /// Appends the trackers to the torrent's tracker list
+    pub async fn add_trackers(&self, hash: &str, urls: &[String]) -> EmptyResult {
+        #[derive(Serialize)]
+        struct Request<'a> {
+            ids: Vec<String>,
+            #[serde(rename = "trackerAdd")]
+            tracker_add: &'a [String],
+        }
+
+        if self.skip_in_dry_run(|| format!("add {:?} trackers to {} torrent", urls, hash)) {
+            return Ok(());
+        }
+
+        let _: EmptyResponse = self.call("torrent-set", &Request {
+            ids: vec![s!(hash)],
+            tracker_add: urls,
+        }).await?;
+
+        Ok(())
+    }
+
+    /// Replaces the torrent's announce URLs by tracker IDs
+    pub async fn replace_trackers(&self, hash: &str, trackers: &[(u64, String)]) -> EmptyResult {
+        #[derive(Serialize)]
+        struct Request {
+            ids: Vec<String>,
+            #[serde(rename = "trackerReplace")]
+            tracker_replace: Vec<serde_json::Value>,
+        }
+
+        if self.skip_in_dry_run(|| format!("replace {} torrent's trackers: {}", hash, trackers.iter().map(|(id, url)| {
+            format!("{} -> {}", id, url)
+        }).join(", "))) {
+            return Ok(());
+        }
+
+        // The tracker IDs and URLs are passed as a flat list of pairs
+        let _: EmptyResponse = self.call("torrent-set", &Request {
+            ids: vec![s!(hash)],
+            tracker_replace: trackers.iter().flat_map(|(id, url)| {
+                [serde_json::json!(id), serde_json::json!(url)]
+            }).collect(),
+        }).await?;
+
+        Ok(())
+    }
+
+    pub async fn get_peer_port(&self) -> Result<u16> {
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(rename = "peer-port")]
+            peer_port: u16,
+        }
+
+        let response: Response = self.call("session-get", &SessionGetRequest {
+            fields: vec!["peer-port"],
+        }).await?;
+
+        Ok(response.peer_port)
+    }
+
+    pub async fn set_peer_port(&self, port: u16) -> EmptyResult {
+        #[derive(Serialize)]
+        struct Request {
+            #[serde(rename = "peer-port")]
+            peer_port: u16,
+        }
+
+        if self.skip_in_dry_run(|| format!("set peer port to {}", port)) {
+            return Ok(());
+        }
+
+        let _: EmptyResponse = self.call("session-set", &Request {
+            peer_port: port,
+        }).await?;
+
+        Ok(())
+    }
+
+    /// Checks whether the peer port is reachable from the Internet
+    pub async fn test_port(&self) -> Result<bool> {
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(rename = "port-is-open")]
+            port_is_open: bool,
+        }
+
+        let response: Response = self.call("port-test", &EmptyRequest{}).await?;
+        Ok(response.port_is_open)
+    }
+
+    pub async fn get_session_stats(&self) -> Result<SessionStats> {
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(rename = "cumulative-stats")]
+            cumulative_stats: SessionStats,
+        }
+
+        let response: Response = self.call("session-stats", &EmptyRequest{}).await?;
+        Ok(response.cumulative_stats)
+    }
+
+    /// Returns free space (and total space since Transmission 4.0) of the specified path on the server side
+    pub async fn get_free_space(&self, path: &str) -> Result<FreeSpace> {
+        #[derive(Serialize)]
+        struct Request<'a> {
+            path: &'a str,
+        }
+
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(rename = "size-bytes")]
+            free: i64,
+            #[serde(rename = "total_size")]
+            total: Option<i64>,
+        }
+
+        let response: Response = self.call("free-space", &Request {path: path}).await?;
+
+        // Transmission returns -1 when it fails to get the value
+        if response.free < 0 {
+            return Err(Rpc(GeneralError(format!("Failed to get free space of '{}'", path))));
+        }
+
+        Ok(FreeSpace {
+            free: response.free as u64,
+            total: response.total.and_then(|total| if total > 0 {
+                Some(total as u64)
+            } else {
+                None
+            }),
+        })
+    }
+
+    /// Updates the blocklist and returns the number of rules in it
+    pub async fn update_blocklist(&self) -> Result<u64> {
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(rename = "blocklist-size")]
+            blocklist_size: u64,
+        }
+
+        if self.skip_in_dry_run(|| s!("update the blocklist")) {
+            return Err(Internal(s!("Blocklist can't be updated in dry run mode")));
+        }
+
+        let response: Response = self.call("blocklist-update", &EmptyRequest{}).await?;
+        Ok(response.blocklist_size)
+    }
+
+    pub async fn get_bandwidth_groups(&self) -> Result<Vec<BandwidthGroup>> {
+        #[derive(Deserialize)]
+        struct Response {
+            group: Vec<BandwidthGroup>,
+        }
+
+        self.check_feature(Feature::BandwidthGroups).await?;
+        let response: Response = self.call("group-get", &EmptyRequest{}).await?;
+        Ok(response.group)
+    }
+
+    pub async fn set_bandwidth_group(&self, group: &BandwidthGroup) -> EmptyResult {
+        self.check_feature(Feature::BandwidthGroups).await?;
+        if self.skip_in_dry_run(|| format!("configure '{}' bandwidth group: {:?}", group.name, group)) {
+            return Ok(());
+        }
+
+        let _: EmptyResponse = self.call("group-set", group).await?;
+        Ok(())
+    }
+
+    pub async fn set_torrent_bandwidth_group(&self, hash: &str, group: &str) -> EmptyResult {
+        #[derive(Serialize)]
+        struct Request<'a> {
+            ids: Vec<String>,
+            group: &'a str,
+        }
+
+        self.check_feature(Feature::BandwidthGroups).await?;
+
+        if self.skip_in_dry_run(|| format!("assign {} torrent to '{}' bandwidth group", hash, group)) {
+            return Ok(());
+        }
+
+        let _: EmptyResponse = self.call("torrent-set", &Request {
+            ids: vec![s!(hash)],
+            group: group,
+        }).await?;
+
+        Ok(())
+    }
+
+    pub async fn set_sequential_download(&self, hash: &str, enabled: bool) -> EmptyResult {
+        #[derive(Serialize)]
+        struct Request {
+            ids: Vec<String>,
+            sequential_download: bool,
+        }
+
+        self.check_feature(Feature::SequentialDownload).await?;
+
+        if self.skip_in_dry_run(|| format!("set {} torrent sequential download to {}", hash, enabled)) {
+            return Ok(());
+        }
+
+        let _: EmptyResponse = self.call("torrent-set", &Request {
+            ids: vec![s!(hash)],
+            sequential_download: enabled,
+        }).await?;
+
+        Ok(())
+    }
+
+    /// Sets per-torrent speed limits in KB/s. The unspecified limits are left untouched.
+    pub async fn set_torrent_speed_limits(
+        &self, hash: &str, download_limit: Option<u64>, upload_limit: Option<u64>,
+    ) -> EmptyResult {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Request {
+            ids: Vec<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            download_limited: Option<bool>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            download_limit: Option<u64>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            upload_limited: Option<bool>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            upload_limit: Option<u64>,
+        }
+
+        let format_limit = |limit: Option<u64>| limit.map(|limit| format!("{} KB/s", limit)).unwrap_or(s!("unchanged"));
+        if self.skip_in_dry_run(|| format!(
+            "set {} torrent speed limits: download - {}, upload - {}",
+            hash, format_limit(download_limit), format_limit(upload_limit),
+        )) {
+            return Ok(());
+        }
+
+        let _: EmptyResponse = self.call("torrent-set", &Request {
+            ids: vec![s!(hash)],
+            download_limited: download_limit.map(|_| true),
+            download_limit: download_limit,
+            upload_limited: upload_limit.map(|_| true),
+            upload_limit: upload_limit,
+        }).await?;
+
+        Ok(())
+    }
+
+    /// Changes torrent's download directory. If `move_data` is false, the data is expected to be already there.
+    pub async fn set_location(&self, hash: &str, location: &str, move_data: bool) -> EmptyResult {
+        #[derive(Serialize)]
+        struct Request<'a> {
+            ids: Vec<String>,
+            location: &'a str,
+            #[serde(rename = "move")]
+            move_data: bool,
+        }
+
+        if self.skip_in_dry_run(|| format!("set location of {} torrent to '{}'", hash, location)) {
+            return Ok(());
+        }
+
+        let _: EmptyResponse = self.call("torrent-set-location", &Request {
+            ids: vec![s!(hash)],
+            location: location,
+            move_data: move_data,
+        }).await?;
+
+        Ok(())
+    }
+
+    /// Renames a file or directory of the torrent (`path` is relative to torrent's download directory)
+    pub async fn rename_path(&self, hash: &str, path: &str, name: &str) -> EmptyResult {
+        #[derive(Serialize)]
+        struct Request<'a> {
+            ids: Vec<String>,
+            path: &'a str,
+            name: &'a str,
+        }
+
+        #[derive(Deserialize)]
+        struct Response {
+        }
+
+        if self.skip_in_dry_run(|| format!("rename '{}' of {} torrent to '{}'", path, hash, name)) {
+            return Ok(());
+        }
+
+        let _: Response = self.call("torrent-rename-path", &Request {
+            ids: vec![s!(hash)],
+            path: path,
+            name: name,
+        }).await?;
+
+        Ok(())
+    }
+
+    /// Adds a new torrent. Returns an existing torrent if it's a duplicate.
+    pub async fn add_torrent(
+        &self, source: TorrentSource<'_>, download_dir: Option<&str>, labels: &[String],
+    ) -> Result<AddedTorrent> {
+        #[derive(Serialize)]
+        struct Request<'a> {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            metainfo: Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            filename: Option<&'a str>,
+            #[serde(rename = "download-dir", skip_serializing_if = "Option::is_none")]
+            download_dir: Option<&'a str>,
+            #[serde(skip_serializing_if = "<[String]>::is_empty")]
+            labels: &'a [String],
+        }
+
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(rename = "torrent-added")]
+            added: Option<TorrentInfo>,
+            #[serde(rename = "torrent-duplicate")]
+            duplicate: Option<TorrentInfo>,
+        }
+
+        #[derive(Deserialize)]
+        struct TorrentInfo {
+            #[serde(rename = "hashString")]
+            hash: String,
+            name: String,
+        }
+
+        if self.skip_in_dry_run(|| s!("add a torrent")) {
+            return Err(Internal(s!("Torrents can't be added in dry run mode")));
+        }
+
+        let (metainfo, filename) = match source {
+            TorrentSource::Metainfo(data) => (Some(BASE64.encode(data)), None),
+            TorrentSource::Url(url) => (None, Some(url)),
+        };
+
+        let response: Response = self.call("torrent-add", &Request {
+            metainfo: metainfo,
+            filename: filename,
+            download_dir: download_dir,
+            labels: labels,
+        }).await?;
+
+        let (torrent, duplicate) = match (response.added, response.duplicate) {
+            (Some(torrent), _) => (torrent, false),
+            (None, Some(torrent)) => (torrent, true),
+            (None, None) => return Err(Protocol(s!("Got an unexpected response to torrent-add request"))),
+        };
+
+        Ok(AddedTorrent {
+            hash: torrent.hash,
+            name: torrent.name,
+            duplicate: duplicate,
+        })
+    }
+
+    pub async fn remove(&self, hash: &str, delete_local_data: bool) -> EmptyResult {
+        #[derive(Serialize)]
+        struct Request {
+            ids: Vec<String>,
+            #[serde(rename = "delete-local-data")]
+            delete_local_data: bool,
+        }
+
+        if self.skip_in_dry_run(|| format!("remove {} torrent{}", hash, if delete_local_data { " with its data" } else { "" })) {
+            return Ok(());
+        }
+
+        let _: EmptyResponse = self.call("torrent-remove", &Request {
+            ids: vec![s!(hash)],
+            delete_local_data: delete_local_data,
+        }).await?;
+
+        Ok(())
+    }
+
+    /// Returns true if the modifying call must be skipped due to dry run mode. Each action is logged only once to not
+    /// flood the log on every check.
+    fn skip_in_dry_run<F: FnOnce() -> String>(&self, action: F) -> bool {
+        if !self.dry_run {
+            return false;
+        }
+
+        let action = action();
+        if self.dry_run_actions.lock().unwrap().insert(action.clone()) {
+            info!("Dry run: would {}.", action);
+        }
+
+        true
+    }
+
+    async fn call<I: ser::Serialize, O: de::DeserializeOwned>(&self, method: &str, arguments: &I) -> Result<O> {
+        let mut attempt = 1;
+
+        loop {
+            let error = match self._call(method, arguments).await {
+                Ok(result) => return Ok(result),
+                Err(err) => err,
+            };
+
+            trace!("RPC error: {}.", error);
+
+            if !error.is_transient() || attempt >= self.retry_policy.max_attempts {
+                return Err(error.with_method(method));
+            }
+
+            let delay = self.retry_policy.get_delay(attempt);
+            debug!("{} RPC call has failed: {}. Retrying in {:.1}s...", method, error, delay.as_secs_f64());
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn _call<I: ser::Serialize, O: de::DeserializeOwned>(&self, method: &str, arguments: &I) -> Result<O> {
+        #[derive(Serialize)]
+        struct Request<'a, T: 'a> {
+            method: String,
+            arguments: &'a T,
+        }
+
+        #[derive(Deserialize)]
+        struct Response<T> {
+            result: String,
+            arguments: Option<T>,
+        }
+
+        let request_json = serde_json::to_string(&Request {
+            method: s!(method),
+            arguments: &arguments,
+        }).map_err(|e| Internal(format!(
+            "Failed to encode the request: {}", e
+        )))?;
+
+        trace!("RPC call: {}", request_json);
+        let response = self.send_request_with_session(&request_json).await?;
+
+        if response.status == StatusCode::UNAUTHORIZED || response.status == StatusCode::FORBIDDEN {
+            return Err(Authentication(format!("Got {} HTTP status code", response.status)));
+        } else if response.status == StatusCode::INTERNAL_SERVER_ERROR {
+            // The daemon itself may report the request failure this way, which is a permanent error, but without a
+            // valid RPC reply the error most likely comes from a reverse proxy while the daemon is restarting.
+            return Err(match serde_json::from_slice::<Response<de::IgnoredAny>>(&response.body) {
+                Ok(reply) if reply.result != "success" => Rpc(GeneralError(reply.result)),
+                Ok(_) => Internal(format!("Got {} HTTP status code", response.status)),
+                Err(_) => Connection(format!("Got {} HTTP status code", response.status)),
+            });
+        } else if response.status.is_server_error() {
+            return Err(Connection(format!("Got {} HTTP status code", response.status)));
+        } else if response.status != StatusCode::OK {
+            return Err(Internal(format!("Got {} HTTP status code", response.status)));
+        }
+
+        response.headers.get(header::CONTENT_TYPE)
+            .ok_or_else(|| Protocol(format!(
+                "Server returned {} response without Content-Type", response.status)))
+            .and_then(|value| {
+                value.to_str().map_err(|_| Protocol(format!(
+                    "Got an invalid Content-Type header value: {:?}", value)))
+            })
+            .and_then(|content_type| {
+                Mime::from_str(content_type).ok().and_then(|content_type| {
+                    if content_type.type_() == mime::APPLICATION && content_type.subtype() == mime::JSON {
+                        Some(content_type)
+                    } else {
+                        None
+                    }
+                }).ok_or_else(|| Protocol(format!(
+                    "Server returned {} response with an invalid content type: {}",
+                    response.status, content_type
+                )))
+            })?;
+
+        // The response is decoded right from the received data: torrent lists may be huge, so we avoid any extra copies
+        let body = response.body;
+        if log_enabled!(log::Level::Trace) {
+            trace!("RPC result: {}", String::from_utf8_lossy(&body).trim());
+        }
+
+        let response: Response<O> = serde_json::from_slice(&body).map_err(|e| Protocol(format!(
+            "Got an invalid response from server: {}", e)))?;
+
+        if response.result != "success" {
+            return Err(Rpc(GeneralError(response.result)))
+        }
+
+        match response.arguments {
+            Some(arguments) => Ok(arguments),
+            None => Err(Protocol(s!("Got a successful reply without arguments"))),
+        }
+    }
+
+    // Transmission protects its RPC from CSRF attacks by requiring X-Transmission-Session-Id header which is issued
+    // via 409 HTTP status code. The session ID changes on each daemon restart, so we may get 409 at any moment - not
+    // only on the first request.
+    async fn send_request_with_session(&self, body: &str) -> Result<HttpResponse> {
+        let (mut response, mut sent_session_id) = self.send_request(body).await?;
+
+        for _ in 0..SESSION_ID_MAX_RETRIES {
+            if response.status != StatusCode::CONFLICT {
+                return Ok(response);
+            }
+
+            let session_id = get_session_id(&response)?;
+
+            {
+                let mut current_session_id = self.session_id.write().unwrap();
+
+                if sent_session_id.as_ref() == Some(&session_id) {
+                    if current_session_id.as_ref() == Some(&session_id) {
+                        *current_session_id = None;
+                    }
+                    return Err(Protocol(format!(
+                        "Transmission daemon has rejected the session ID it has issued: {:?}", session_id)));
+                }
+
+                // The ID might have been already updated by a concurrent request
+                if current_session_id.as_ref() != Some(&session_id) {
+                    if current_session_id.is_some() {
+                        debug!("Session ID is expired (the daemon has been restarted?). Got a new session ID.");
+                        // The daemon might have been upgraded
+                        *self.server_version.write().unwrap() = None;
+                    } else {
+                        debug!("Got a new session ID.");
+                    }
+                    *current_session_id = Some(session_id);
+                }
+            }
+
+            (response, sent_session_id) = self.send_request(body).await?;
+        }
+
+        if response.status == StatusCode::CONFLICT {
+            return Err(Protocol(format!(
+                "Unable to obtain a valid session ID: got {} HTTP status code after {} retries",
+                response.status, SESSION_ID_MAX_RETRIES)));
+        }
+
+        Ok(response)
+    }
+
+    async fn send_request(&self, body: &str) -> Result<(HttpResponse, Option<String>)> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+        if let (Some(user), Some(password)) = (self.user.as_ref(), self.password.as_ref()) {
+            let credentials = BASE64.encode(format!("{}:{}", user, password));
+            let mut value = HeaderValue::from_str(&format!("Basic {}", credentials)).map_err(|e| Internal(format!(
+                "Invalid credentials: {}", e)))?;
+            value.set_sensitive(true);
+            headers.insert(header::AUTHORIZATION, value);
+        }
+
+        let session_id = self.session_id.read().unwrap().clone();
+        if let Some(ref session_id) = session_id {
+            headers.insert(SESSION_ID_HEADER_NAME, HeaderValue::from_str(session_id).map_err(|e| Internal(format!(
+                "Invalid session ID: {}", e)))?);
+        }
+
+        let response = self.transport.send(&self.url, headers, body).await?;
+        Ok((response, session_id))
+    }
+}
+
+fn configure_tls(
+    mut builder: ClientBuilder, url: &mut Url, tls: &TlsOptions, resolver: &mut Resolver,
+) -> GenericResult<ClientBuilder> {
+    builder = builder.https_only(true);
+
+    if let Some(ref path) = tls.ca_file {
+        let certificate = fs::read(path).map_err(|e| e.to_string()).and_then(|data| {
+            Certificate::from_pem(&data).map_err(|e| e.to_string())
+        }).map_err(|e| format!("Unable to load CA certificate from '{}': {}", path.display(), e))?;
+
+        builder = builder.add_root_certificate(certificate);
+    }
+
+    if let Some((ref certificate_path, ref key_path)) = tls.client_identity {
+        let read = |path: &Path| fs::read(path).map_err(|e| format!("Unable to read '{}': {}", path.display(), e));
+        let identity = Identity::from_pkcs8_pem(&read(certificate_path)?, &read(key_path)?).map_err(|e| format!(
+            "Unable to load client certificate from '{}': {}", certificate_path.display(), e))?;
+
+        builder = builder.identity(identity);
+    }
+
+    if !tls.verify_certificate {
+        warn!("TLS certificate verification is disabled for Transmission RPC.");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    // To use a custom SNI name we connect to the URL's host, but present the server name as the request host
+    if let Some(ref server_name) = tls.server_name {
+        let host = url.host_str().ok_or("RPC URL has no host")?;
+        let host = host.trim_start_matches('[').trim_end_matches(']').to_owned();
+
+        url.set_host(Some(server_name)).map_err(|e| format!(
+            "Invalid server name {:?}: {}", server_name, e))?;
+
+        resolver.alias = Some((url.host_str().unwrap().to_owned(), host));
+    }
+
+    Ok(builder)
+}
+
+/// Resolves the RPC host on each new connection, so the daemon's address changes (a container restart, for example)
+/// are picked up. The addresses are tried in the order they are resolved in, so the preferred ones are put first.
+struct Resolver {
+    address_family: Option<AddressFamily>,
+    /// The server name which is presented as the request host and the actual host to connect to
+    alias: Option<(String, String)>,
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = match self.alias {
+            Some((ref server_name, ref host)) if server_name == name.as_str() => host.clone(),
+            _ => s!(name.as_str()),
+        };
+        let address_family = self.address_family;
+
+        Box::pin(async move {
+            // The port is taken from the URL
+            let addresses = tokio::task::spawn_blocking(move || resolve(&host, 0, address_family)).await??;
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Resolves the host putting the addresses of the preferred family first
+fn resolve(host: &str, port: u16, address_family: Option<AddressFamily>) -> GenericResult<Vec<SocketAddr>> {
+    let mut addresses: Vec<_> = (host, port).to_socket_addrs()
+        .map_err(|e| format!("Unable to resolve {:?}: {}", host, e))?.collect();
+
+    if let Some(address_family) = address_family {
+        sort_addresses(&mut addresses, address_family);
+    }
+
+    Ok(addresses)
+}
+
+fn sort_addresses(addresses: &mut [SocketAddr], address_family: AddressFamily) {
+    // The sorting is stable, so the resolver's order is preserved within the families
+    addresses.sort_by_key(|address| address.is_ipv6() != (address_family == AddressFamily::Ipv6));
+}
+
+fn get_session_id(response: &HttpResponse) -> Result<String> {
+    let value = response.headers.get(SESSION_ID_HEADER_NAME).ok_or_else(|| Protocol(format!(
+        "Got {} HTTP status code without {} header", response.status, SESSION_ID_HEADER_NAME)))?;
+
+    let session_id = value.to_str().ok().map(str::trim).filter(|value| !value.is_empty()).ok_or_else(|| Protocol(format!(
+        "Got an invalid {} header value: {:?}", SESSION_ID_HEADER_NAME, value)))?;
+
+    Ok(s!(session_id))
+}
+
+
+#[derive(Debug)]
+pub enum TransmissionClientError {
+    Authentication(String),
+    Connection(String),
+    Internal(String),
+    Protocol(String),
+    Rpc(TransmissionRpcError),
+}
+use self::TransmissionClientError::*;
+
+impl TransmissionClientError {
+    /// Returns true for errors that are likely to go away on their own (the daemon is restarting, for example)
+    pub fn is_transient(&self) -> bool {
+        matches!(*self, Connection(_))
+    }
+
+    /// Adds the failed RPC method to the communication errors to make them traceable
+    fn with_method(self, method: &str) -> TransmissionClientError {
+        match self {
+            Internal(err) => Internal(format!("{} call: {}", method, err)),
+            Protocol(err) => Protocol(format!("{} call: {}", method, err)),
+            err => err,
+        }
+    }
+}
+
+impl Error for TransmissionClientError {
+    fn description(&self) -> &str {
+        "Transmission client error"
+    }
+}
+
+impl fmt::Display for TransmissionClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Authentication(ref err) => write!(f,
+                                              "Failed to authenticate in Transmission daemon: {}", err),
+            Connection(ref err) => write!(f,
+                                          "Failed to connect to Transmission daemon: {}", err),
+            Internal(ref err) | Protocol(ref err) => write!(f,
+                                                            "Error in communication with Transmission daemon: {}", err),
+            Rpc(ref err) => write!(f,
+                                   "Transmission daemon returned an error: {}", err),
+        }
+    }
+}
+
+impl From<reqwest::Error> for TransmissionClientError {
+    fn from(err: reqwest::Error) -> TransmissionClientError {
+        Connection(err.to_string())
+    }
+}
+
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum TransmissionRpcError {
+    GeneralError(String),
+    TorrentNotFoundError(String),
+    UnsupportedFeatureError(String),
+}
+use self::TransmissionRpcError::*;
+
+impl Error for TransmissionRpcError {
+    fn description(&self) -> &str {
+        "Transmission RPC error"
+    }
+}
+
+impl fmt::Display for TransmissionRpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GeneralError(ref err) => write!(f, "{}", err),
+            TorrentNotFoundError(_) => write!(f, "The specified torrent doesn't exist"),
+            UnsupportedFeatureError(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use super::*;
+    use super::mock::{MockServer, MockTorrent};
+
+    #[test]
+    fn test_session_id_renewal() {
+        let runtime = Runtime::new().unwrap();
+
+        runtime.block_on(async {
+            let server = MockServer::start().await;
+            server.add_torrent(MockTorrent::new(1, "torrent", "/downloads"));
+            let client = server.client();
+
+            let torrents = client.get_torrents(TorrentFields::basic()).await.unwrap();
+            assert_eq!(torrents.len(), 1);
+            assert_eq!(torrents[0].name, "torrent");
+            assert!(torrents[0].done);
+
+            server.restart();
+            client.start(&torrents[0].hash).await.unwrap();
+
+            let state = server.state();
+            assert_eq!(state.calls, ["torrent-get", "torrent-start"]);
+            assert_eq!(state.conflicts, 2);
+        });
+    }
+
+    #[test]
+    fn test_feature_gating() {
+        let runtime = Runtime::new().unwrap();
+
+        runtime.block_on(async {
+            let server = MockServer::start().await;
+            server.add_torrent(MockTorrent::new(1, "torrent", "/downloads"));
+            server.state().rpc_version = 17;
+
+            let client = server.client();
+            let hash = client.get_torrents(TorrentFields::basic()).await.unwrap().remove(0).hash;
+
+            let error = client.set_sequential_download(&hash, true).await.unwrap_err();
+            assert!(matches!(error, Rpc(UnsupportedFeatureError(_))), "{}", error);
+            assert_eq!(error.to_string(), concat!(
+                "Transmission daemon returned an error: Sequential download requires Transmission 4.1+ ",
+                "(RPC version 18), but the daemon is Transmission 4.0.6 (RPC version 17)"));
+            assert!(!server.torrent(1).unwrap().sequential_download);
+
+            // The version is requested once and cached until the daemon restarts
+            client.set_torrent_bandwidth_group(&hash, "group").await.unwrap();
+            assert_eq!(server.state().calls, ["torrent-get", "session-get", "torrent-set"]);
+
+            // The daemon has been upgraded: the version is requested again after the session renewal
+            server.state().rpc_version = 18;
+            server.restart();
+            client.get_torrents(TorrentFields::basic()).await.unwrap();
+            client.set_sequential_download(&hash, true).await.unwrap();
+            assert!(server.torrent(1).unwrap().sequential_download);
+        });
+    }
+
+    #[test]
+    fn test_server_errors() {
+        let runtime = Runtime::new().unwrap();
+
+        runtime.block_on(async {
+            let server = MockServer::start().await;
+            let client = server.client();
+
+            server.state().http_errors.extend([
+                ("502 Bad Gateway", s!("")),
+                ("500 Internal Server Error", s!("")),
+                ("500 Internal Server Error", serde_json::json!({"result": "torrent not found"}).to_string()),
+            ]);
+
+            let error = client.get_torrents(TorrentFields::basic()).await.unwrap_err();
+            assert!(error.is_transient(), "{}", error);
+
+            let error = client.get_torrents(TorrentFields::basic()).await.unwrap_err();
+            assert!(error.is_transient(), "{}", error);
+
+            let error = client.get_torrents(TorrentFields::basic()).await.unwrap_err();
+            assert!(!error.is_transient(), "{}", error);
+            assert!(matches!(error, Rpc(GeneralError(ref result)) if result == "torrent not found"), "{}", error);
+
+            assert!(client.get_torrents(TorrentFields::basic()).await.unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_resolver() {
+        let runtime = Runtime::new().unwrap();
+
+        runtime.block_on(async {
+            let resolver = Resolver {
+                address_family: Some(AddressFamily::Ipv4),
+                alias: Some((s!("transmission.example.com"), s!("127.0.0.1"))),
+            };
+
+            let addresses: Vec<_> = resolver.resolve(Name::from_str("transmission.example.com").unwrap())
+                .await.unwrap().collect();
+            assert_eq!(addresses, vec![SocketAddr::from(([127, 0, 0, 1], 0))]);
+
+            let addresses: Vec<_> = resolver.resolve(Name::from_str("localhost").unwrap()).await.unwrap().collect();
+            assert!(addresses[0].is_ipv4(), "{:?}", addresses);
+        });
+    }
+
+    #[test]
+    fn test_address_family() {
+        let resolved: Vec<SocketAddr> = ["[2001:db8::1]:9091", "192.0.2.1:9091", "[2001:db8::2]:9091", "192.0.2.2:9091"]
+            .iter().map(|address| address.parse().unwrap()).collect();
+
+        for (address_family, expected) in [(AddressFamily::Ipv4, [1, 3, 0, 2]), (AddressFamily::Ipv6, [0, 2, 1, 3])] {
+            let mut addresses = resolved.clone();
+            sort_addresses(&mut addresses, address_family);
+            assert_eq!(addresses, expected.map(|index| resolved[index]));
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, de};
+
+use crate::util::time::format_duration;
+
+/// Torrent status (tr_torrent_activity)
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TorrentStatus {
+    Stopped,
+    /// Queued for file checking
+    CheckWait,
+    Checking,
+    /// Queued for downloading
+    DownloadWait,
+    Downloading,
+    /// Queued for seeding
+    SeedWait,
+    Seeding,
+}
+
+impl TorrentStatus {
+    const ALL: [TorrentStatus; 7] = [
+        TorrentStatus::Stopped, TorrentStatus::CheckWait, TorrentStatus::Checking, TorrentStatus::DownloadWait,
+        TorrentStatus::Downloading, TorrentStatus::SeedWait, TorrentStatus::Seeding,
+    ];
+
+    pub fn from_code(code: i64) -> Option<TorrentStatus> {
+        TorrentStatus::ALL.iter().copied().find(|status| status.code() == code)
+    }
+
+    pub fn code(self) -> i64 {
+        match self {
+            TorrentStatus::Stopped      => 0,
+            TorrentStatus::CheckWait    => 1,
+            TorrentStatus::Checking     => 2,
+            TorrentStatus::DownloadWait => 3,
+            TorrentStatus::Downloading  => 4,
+            TorrentStatus::SeedWait     => 5,
+            TorrentStatus::Seeding      => 6,
+        }
+    }
+
+    /// Returns true if the torrent is being verified or waiting for verification
+    pub fn is_checking(self) -> bool {
+        matches!(self, TorrentStatus::CheckWait | TorrentStatus::Checking)
+    }
+}
+
+impl fmt::Display for TorrentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            TorrentStatus::Stopped      => "stopped",
+            TorrentStatus::CheckWait    => "check-wait",
+            TorrentStatus::Checking     => "checking",
+            TorrentStatus::DownloadWait => "download-wait",
+            TorrentStatus::Downloading  => "downloading",
+            TorrentStatus::SeedWait     => "seed-wait",
+            TorrentStatus::Seeding      => "seeding",
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for TorrentStatus {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TorrentStatus, D::Error> {
+        let code = i64::deserialize(deserializer)?;
+        TorrentStatus::from_code(code).ok_or_else(|| de::Error::custom(format!("Invalid torrent status: {}", code)))
+    }
+}
+
+/// Torrent error (tr_stat_errtype with the error message)
+#[derive(Debug, PartialEq, Clone)]
+pub enum TorrentError {
+    /// Tracker returned a warning
+    TrackerWarning(String),
+    /// Tracker returned an error
+    TrackerError(String),
+    /// Local problem: missing or corrupted data, I/O error, etc.
+    LocalError(String),
+}
+
+impl TorrentError {
+    pub fn from_code(code: i64, message: String) -> Option<TorrentError> {
+        Some(match code {
+            0 => return None,
+            1 => TorrentError::TrackerWarning(message),
+            2 => TorrentError::TrackerError(message),
+            _ => TorrentError::LocalError(message),
+        })
+    }
+}
+
+impl fmt::Display for TorrentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TorrentError::TrackerWarning(ref message) => write!(f, "Tracker warning: {}", message),
+            TorrentError::TrackerError(ref message) => write!(f, "Tracker error: {}", message),
+            TorrentError::LocalError(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Estimated time until the torrent is downloaded (or until it reaches its seeding target)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Eta {
+    /// The torrent is not downloading/seeding towards a target
+    NotAvailable,
+    /// There are no peers to estimate the time
+    Unknown,
+    Seconds(u64),
+}
+
+impl Eta {
+    pub fn from_value(value: i64) -> Eta {
+        match value {
+            -1 => Eta::NotAvailable,
+            value if value < 0 => Eta::Unknown,
+            value => Eta::Seconds(value as u64),
+        }
+    }
+}
+
+impl fmt::Display for Eta {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Eta::NotAvailable => f.write_str("n/a"),
+            Eta::Unknown => f.write_str("unknown"),
+            Eta::Seconds(seconds) => f.write_str(&format_duration(seconds)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_torrent_status() {
+        for status in TorrentStatus::ALL {
+            assert_eq!(TorrentStatus::from_code(status.code()), Some(status));
+        }
+        assert_eq!(serde_json::from_str::<TorrentStatus>("6").unwrap(), TorrentStatus::Seeding);
+        assert!(serde_json::from_str::<TorrentStatus>("7").is_err());
+    }
+
+    #[test]
+    fn test_eta() {
+        assert_eq!(Eta::from_value(-1), Eta::NotAvailable);
+        assert_eq!(Eta::from_value(-2), Eta::Unknown);
+        assert_eq!(Eta::from_value(90), Eta::Seconds(90));
+    }
+}
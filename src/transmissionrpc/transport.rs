@@ -0,0 +1,225 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use reqwest::{Client, Method, StatusCode, Url};
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+use super::{Result, TransmissionClientError::*};
+
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+pub enum Transport {
+    Http(Client),
+    UnixSocket(UnixSocketTransport),
+}
+
+impl Transport {
+    pub async fn send(&self, url: &Url, headers: HeaderMap, body: &str) -> Result<HttpResponse> {
+        match *self {
+            Transport::Http(ref client) => send_http_request(client, url, headers, body).await,
+            Transport::UnixSocket(ref transport) => transport.send(url, &headers, body).await,
+        }
+    }
+}
+
+async fn send_http_request(client: &Client, url: &Url, headers: HeaderMap, body: &str) -> Result<HttpResponse> {
+    let request = client.request(Method::POST, url.clone())
+        .headers(headers)
+        .body(body.to_owned())
+        .build()?;
+
+    let retry_request = request.try_clone();
+
+    let response = match client.execute(request).await {
+        Ok(response) => response,
+
+        // The server may close the reused keep-alive connection at any moment (for example, on restart), so
+        // retry the request once on a new connection.
+        Err(err) if err.is_request() && !err.is_timeout() && retry_request.is_some() => {
+            debug!("RPC request has failed ({}). Retrying it on a new connection...", err);
+            client.execute(retry_request.unwrap()).await?
+        },
+
+        Err(err) => return Err(err.into()),
+    };
+
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = response.bytes().await?.into();
+
+    Ok(HttpResponse {
+        status: status,
+        headers: headers,
+        body: body,
+    })
+}
+
+/// A minimal HTTP/1.1 client over UNIX domain socket. Connections are short-lived: they are cheap for UNIX sockets
+/// and it allows us to read the response until EOF without dealing with keep-alive.
+pub struct UnixSocketTransport {
+    path: PathBuf,
+    timeout: Duration,
+}
+
+const MAX_HEADERS: usize = 64;
+
+impl UnixSocketTransport {
+    pub fn new(path: &Path, timeout: Duration) -> UnixSocketTransport {
+        UnixSocketTransport {
+            path: path.to_owned(),
+            timeout: timeout,
+        }
+    }
+
+    async fn send(&self, url: &Url, headers: &HeaderMap, body: &str) -> Result<HttpResponse> {
+        match tokio::time::timeout(self.timeout, self.send_request(url, headers, body)).await {
+            Ok(result) => result,
+            Err(_) => Err(Connection(format!("'{}': Request timed out", self.path.display()))),
+        }
+    }
+
+    async fn send_request(&self, url: &Url, headers: &HeaderMap, body: &str) -> Result<HttpResponse> {
+        let mut stream = UnixStream::connect(&self.path).await.map_err(|e| Connection(format!(
+            "Unable to connect to '{}': {}", self.path.display(), e)))?;
+
+        let map_io_error = |e| Connection(format!("'{}': {}", self.path.display(), e));
+
+        let mut path = s!(url.path());
+        if let Some(query) = url.query() {
+            path.push('?');
+            path.push_str(query);
+        }
+
+        let mut request = format!(
+            "POST {path} HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\nContent-Length: {length}\r\n",
+            path=path, host=url.host_str().unwrap_or("localhost"), length=body.len()).into_bytes();
+
+        for (name, value) in headers {
+            request.extend_from_slice(name.as_str().as_bytes());
+            request.extend_from_slice(b": ");
+            request.extend_from_slice(value.as_bytes());
+            request.extend_from_slice(b"\r\n");
+        }
+        request.extend_from_slice(b"\r\n");
+        request.extend_from_slice(body.as_bytes());
+
+        stream.write_all(&request).await.map_err(map_io_error)?;
+
+        let mut data = Vec::new();
+        stream.read_to_end(&mut data).await.map_err(map_io_error)?;
+
+        parse_response(&data)
+    }
+}
+
+fn parse_response(data: &[u8]) -> Result<HttpResponse> {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut response = httparse::Response::new(&mut headers);
+
+    let body_offset = match response.parse(data) {
+        Ok(httparse::Status::Complete(offset)) => offset,
+        Ok(httparse::Status::Partial) => return Err(Protocol(s!("Got a truncated HTTP response"))),
+        Err(err) => return Err(Protocol(format!("Got an invalid HTTP response: {}", err))),
+    };
+
+    let status = response.code.and_then(|code| StatusCode::from_u16(code).ok()).ok_or_else(|| Protocol(s!(
+        "Got an HTTP response with an invalid status code")))?;
+
+    let mut header_map = HeaderMap::new();
+    for header in response.headers.iter() {
+        let name = HeaderName::from_bytes(header.name.as_bytes());
+        let value = HeaderValue::from_bytes(header.value);
+
+        if let (Ok(name), Ok(value)) = (name, value) {
+            header_map.append(name, value);
+        } else {
+            return Err(Protocol(format!("Got an invalid HTTP header: {:?}", header.name)));
+        }
+    }
+
+    let mut body = &data[body_offset..];
+    let chunked = header_map.get(header::TRANSFER_ENCODING).and_then(|value| value.to_str().ok()).is_some_and(
+        |value| value.eq_ignore_ascii_case("chunked"));
+
+    let body = if chunked {
+        decode_chunked_body(body)?
+    } else {
+        if let Some(length) = header_map.get(header::CONTENT_LENGTH) {
+            let length: usize = length.to_str().ok().and_then(|length| length.parse().ok()).ok_or_else(|| Protocol(
+                format!("Got an invalid Content-Length header value: {:?}", length)))?;
+
+            if body.len() < length {
+                return Err(Protocol(s!("Got a truncated HTTP response")));
+            }
+            body = &body[..length];
+        }
+        body.to_vec()
+    };
+
+    Ok(HttpResponse {
+        status: status,
+        headers: header_map,
+        body: body,
+    })
+}
+
+fn decode_chunked_body(mut data: &[u8]) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+
+    loop {
+        let (offset, size) = match httparse::parse_chunk_size(data) {
+            Ok(httparse::Status::Complete(result)) => result,
+            _ => return Err(Protocol(s!("Got an HTTP response with invalid chunked encoding"))),
+        };
+
+        let size = size as usize;
+        data = &data[offset..];
+
+        if size == 0 {
+            return Ok(body);
+        }
+
+        if data.len() < size + 2 {
+            return Err(Protocol(s!("Got a truncated HTTP response")));
+        }
+
+        body.extend_from_slice(&data[..size]);
+        data = &data[size + 2..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        let response = parse_response(
+            b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 4\r\n\r\ntest").unwrap();
+
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.headers.get(header::CONTENT_TYPE).unwrap(), "application/json");
+        assert_eq!(response.body, b"test");
+    }
+
+    #[test]
+    fn test_parse_chunked_response() {
+        let response = parse_response(
+            b"HTTP/1.1 409 Conflict\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nsome\r\n5\r\n body\r\n0\r\n\r\n").unwrap();
+
+        assert_eq!(response.status, StatusCode::CONFLICT);
+        assert_eq!(response.body, b"some body");
+    }
+
+    #[test]
+    fn test_parse_truncated_response() {
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\ntest").is_err());
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\nContent-Le").is_err());
+    }
+}
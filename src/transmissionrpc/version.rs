@@ -0,0 +1,64 @@
+use std::fmt;
+
+use serde::Deserialize;
+
+/// RPC features that aren't supported by all Transmission versions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Feature {
+    Labels,
+    BandwidthGroups,
+    SequentialDownload,
+}
+
+impl Feature {
+    pub fn rpc_version(self) -> u32 {
+        match self {
+            Feature::Labels => 16,
+            Feature::BandwidthGroups => 17,
+            Feature::SequentialDownload => 18,
+        }
+    }
+
+    pub fn transmission_version(self) -> &'static str {
+        match self {
+            Feature::Labels => "3.00",
+            Feature::BandwidthGroups => "4.0",
+            Feature::SequentialDownload => "4.1",
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Feature::Labels => "Torrent labels",
+            Feature::BandwidthGroups => "Bandwidth groups",
+            Feature::SequentialDownload => "Sequential download",
+        })
+    }
+}
+
+// The newest RPC version we know about
+pub const MAX_SUPPORTED_RPC_VERSION: u32 = 18;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerVersion {
+    #[serde(rename = "version")]
+    pub transmission_version: String,
+    #[serde(rename = "rpc-version")]
+    pub rpc_version: u32,
+    #[serde(rename = "rpc-version-minimum")]
+    pub min_rpc_version: u32,
+}
+
+impl ServerVersion {
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.rpc_version >= feature.rpc_version()
+    }
+}
+
+impl fmt::Display for ServerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Transmission {} (RPC version {})", self.transmission_version, self.rpc_version)
+    }
+}
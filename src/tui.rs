@@ -0,0 +1,410 @@
+//! Interactive terminal dashboard (`tui` command) which is handy over SSH: the managed torrents with their controller
+//! state, the copy queue, recent notifications and disk space. The torrents are requested from Transmission and the
+//! daemon's state - via its control socket.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::commands;
+use crate::common::{EmptyResult, GenericResult};
+use crate::config::ControllerConfig;
+use crate::control;
+use crate::disk_space::format_size;
+use crate::scope::Scope;
+use crate::torrent_client::blocking::TorrentClient;
+use crate::transmissionrpc::{Torrent, TorrentFields};
+use crate::util;
+use crate::util::time::Timestamp;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_EVENTS: usize = 5;
+const BAR_WIDTH: usize = 20;
+const KEYS_HELP: &str = "Up/Down (j/k) select  p pause  r resume  c copy now  P process all  q quit";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Up,
+    Down,
+    Pause,
+    Resume,
+    Copy,
+    ProcessNow,
+    Quit,
+}
+
+struct State {
+    torrents: Vec<Torrent>,
+    /// The daemon's dashboard or the reason why it's unavailable
+    dashboard: Result<Value, String>,
+    selected: usize,
+    /// Result of the last action
+    message: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Line {
+    text: String,
+    highlighted: bool,
+}
+
+pub fn run(client: &TorrentClient, config: &ControllerConfig, download_dir: &Path) -> EmptyResult {
+    let scope = Scope::new(&config.manage_only, &config.ignore, download_dir);
+    let control_socket = config.control_socket.as_deref();
+
+    let mut state = State {
+        torrents: Vec::new(),
+        dashboard: Err(String::new()),
+        selected: 0,
+        message: None,
+    };
+
+    let terminal = Terminal::open()?;
+    let mut update_time: Option<Instant> = None;
+
+    loop {
+        if update_time.is_none_or(|time| time.elapsed() >= REFRESH_INTERVAL) {
+            state.update(client, &scope, control_socket);
+            update_time = Some(Instant::now());
+        }
+
+        let (width, height) = terminal.size();
+        terminal.draw(&render(&state, width, height))?;
+
+        let Some(key) = terminal.read_key()? else {
+            continue;
+        };
+
+        match key {
+            Key::Quit => break,
+            Key::Up => state.selected = state.selected.saturating_sub(1),
+            Key::Down => state.selected = (state.selected + 1).min(state.torrents.len().saturating_sub(1)),
+            _ => {
+                state.message = Some(match execute(client, control_socket, key, state.torrents.get(state.selected)) {
+                    Ok(message) => message,
+                    Err(e) => format!("Error: {}", e),
+                });
+                update_time = None;
+            },
+        }
+    }
+
+    Ok(())
+}
+
+impl State {
+    fn update(&mut self, client: &TorrentClient, scope: &Scope, control_socket: Option<&Path>) {
+        match client.get_torrents(TorrentFields::basic()) {
+            Ok(torrents) => {
+                self.torrents = torrents.into_iter().filter(|torrent| scope.contains(torrent)).collect();
+                self.torrents.sort_by(|a, b| a.name.cmp(&b.name));
+                self.selected = self.selected.min(self.torrents.len().saturating_sub(1));
+            },
+            Err(e) => self.message = Some(format!("Error: Failed to get the torrents: {}", e)),
+        }
+
+        self.dashboard = send_command(control_socket, "dashboard")
+            .and_then(|dashboard| Ok(serde_json::from_str(&dashboard)?))
+            .map_err(|e| e.to_string());
+    }
+}
+
+fn execute(
+    client: &TorrentClient, control_socket: Option<&Path>, key: Key, torrent: Option<&Torrent>,
+) -> GenericResult<String> {
+    if key == Key::ProcessNow {
+        return send_command(control_socket, "process-now");
+    }
+
+    let torrent = torrent.ok_or("There are no torrents")?;
+
+    Ok(match key {
+        Key::Pause => {
+            client.stop(&torrent.hash)?;
+            format!("'{}' torrent has been paused", torrent.name)
+        },
+        Key::Resume => {
+            client.start(&torrent.hash)?;
+            format!("'{}' torrent has been resumed", torrent.name)
+        },
+        Key::Copy => send_command(control_socket, &format!("copy {}", torrent.hash))?,
+        _ => unreachable!(),
+    })
+}
+
+fn send_command(control_socket: Option<&Path>, command: &str) -> GenericResult<String> {
+    let path = control_socket.ok_or("The daemon's control socket isn't configured ('control-socket' option)")?;
+    control::send_command(path, command)
+}
+
+fn render(state: &State, width: usize, height: usize) -> Vec<Line> {
+    let mut header = Vec::new();
+    let mut footer = Vec::new();
+    let mut controller_states = HashMap::new();
+
+    match state.dashboard {
+        Ok(ref dashboard) => {
+            header.push(format!("Transmission controller: {}", dashboard["status"].as_str().unwrap_or_default()));
+
+            for space in dashboard["disk_space"].as_array().into_iter().flatten() {
+                let (free, total) = (space["free"].as_u64().unwrap_or(0), space["total"].as_u64().unwrap_or(0));
+                let used = total.saturating_sub(free) * 100 / total.max(1);
+                header.push(format!("{}: [{}] {}% used, {} free", space["path"].as_str().unwrap_or_default(),
+                                    progress_bar(used), used, format_size(free)));
+            }
+
+            for torrent in dashboard["torrents"].as_array().into_iter().flatten() {
+                if let Some(hash) = torrent["hash"].as_str() {
+                    controller_states.insert(hash, get_controller_state(torrent));
+                }
+            }
+
+            footer.push(s!(""));
+            footer.push(s!("Copies:"));
+            for copy in dashboard["copies"].as_array().into_iter().flatten() {
+                let percent = copy["percent"].as_u64().unwrap_or(0);
+                let eta = copy["eta"].as_u64().map(util::time::format_duration).unwrap_or_else(|| s!("unknown"));
+                footer.push(format!("  [{}] {:>3}% {:>10}/s ETA {:8} {}", progress_bar(percent), percent,
+                                    format_size(copy["speed"].as_u64().unwrap_or(0)), eta,
+                                    copy["name"].as_str().unwrap_or_default()));
+            }
+
+            let queued: Vec<&str> = dashboard["queued"].as_array().into_iter().flatten()
+                .filter_map(Value::as_str).collect();
+            if !queued.is_empty() {
+                footer.push(format!("  Queued: {}", queued.join(", ")));
+            }
+
+            footer.push(s!(""));
+            footer.push(s!("Recent events:"));
+            for event in dashboard["notifications"].as_array().into_iter().flatten().take(MAX_EVENTS) {
+                footer.push(format!("  {} {}", format_time(event["time"].as_i64().unwrap_or(0)),
+                                    event["subject"].as_str().unwrap_or_default()));
+            }
+        },
+        Err(ref e) => header.push(format!("The daemon is unavailable: {}", e)),
+    }
+
+    header.push(s!(""));
+    header.push(format!("Torrents ({}):", state.torrents.len()));
+
+    footer.push(s!(""));
+    footer.push(state.message.clone().unwrap_or_default());
+    footer.push(s!(KEYS_HELP));
+
+    let rows = height.saturating_sub(header.len() + footer.len()).max(1);
+    let offset = state.selected.saturating_sub(rows - 1);
+
+    let mut lines: Vec<Line> = header.into_iter().map(|text| Line::new(text, false, width)).collect();
+
+    for (index, torrent) in state.torrents.iter().enumerate().skip(offset).take(rows) {
+        let state_name = match commands::get_state(torrent) {
+            "downloading" => format!("{}%", commands::get_percent_done(torrent)),
+            state => s!(state),
+        };
+        let ratio = torrent.upload_ratio.map(|ratio| format!("{:.2}", ratio)).unwrap_or_else(|| s!("-"));
+        let controller_state = controller_states.get(torrent.hash.as_str()).copied().unwrap_or("-");
+
+        let text = format!("  {:11} {:9} {:9} {:>6}  {}", torrent.status.to_string(), state_name, controller_state,
+                           ratio, torrent.name);
+        lines.push(Line::new(text, index == state.selected, width));
+    }
+
+    lines.extend(footer.into_iter().map(|text| Line::new(text, false, width)));
+    lines
+}
+
+impl Line {
+    fn new(text: String, highlighted: bool, width: usize) -> Line {
+        Line {
+            text: text.chars().take(width).collect(),
+            highlighted: highlighted,
+        }
+    }
+}
+
+fn get_controller_state(torrent: &Value) -> &'static str {
+    if torrent["consuming"].as_bool().unwrap_or(false) {
+        "consuming"
+    } else if torrent["removable"].as_bool().unwrap_or(false) {
+        "removable"
+    } else if torrent["kept"].as_bool().unwrap_or(false) {
+        "kept"
+    } else {
+        "-"
+    }
+}
+
+fn progress_bar(percent: u64) -> String {
+    let filled = (percent.min(100) as usize * BAR_WIDTH).div_ceil(100);
+    "#".repeat(filled) + &"-".repeat(BAR_WIDTH - filled)
+}
+
+fn format_time(timestamp: Timestamp) -> String {
+    let time = legacy_time::at(legacy_time::Timespec::new(timestamp, 0));
+    time.strftime("%H:%M").unwrap().to_string()
+}
+
+fn parse_key(input: &[u8]) -> Option<Key> {
+    Some(match input {
+        b"\x1b[A" | b"k" => Key::Up,
+        b"\x1b[B" | b"j" => Key::Down,
+        b"p" => Key::Pause,
+        b"r" => Key::Resume,
+        b"c" => Key::Copy,
+        b"P" => Key::ProcessNow,
+        b"q" | b"\x03" => Key::Quit,
+        _ => return None,
+    })
+}
+
+/// Switches the terminal to the alternate screen with unbuffered input which is read with a timeout. The original
+/// state is restored on drop.
+struct Terminal {
+    attributes: libc::termios,
+}
+
+impl Terminal {
+    fn open() -> GenericResult<Terminal> {
+        if unsafe { libc::isatty(libc::STDIN_FILENO) } == 0 || unsafe { libc::isatty(libc::STDOUT_FILENO) } == 0 {
+            return Err!("The dashboard requires an interactive terminal");
+        }
+
+        let mut attributes: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut attributes) } != 0 {
+            return Err!("Unable to get terminal attributes: {}", io::Error::last_os_error());
+        }
+
+        // Ctrl+C is handled as a key press to restore the terminal on exit
+        let mut raw = attributes;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 2; // In tenths of a second
+
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err!("Unable to set terminal attributes: {}", io::Error::last_os_error());
+        }
+
+        let terminal = Terminal {attributes: attributes};
+        write_output("\x1b[?1049h\x1b[?25l")?;
+
+        Ok(terminal)
+    }
+
+    fn size(&self) -> (usize, usize) {
+        let mut size: libc::winsize = unsafe { mem::zeroed() };
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } != 0 || size.ws_col == 0 {
+            return (80, 24);
+        }
+        (size.ws_col.into(), size.ws_row.into())
+    }
+
+    fn draw(&self, lines: &[Line]) -> EmptyResult {
+        let mut screen = s!("\x1b[H");
+
+        for (index, line) in lines.iter().enumerate() {
+            if index != 0 {
+                screen += "\r\n";
+            }
+
+            if line.highlighted {
+                screen += "\x1b[7m";
+                screen += &line.text;
+                screen += "\x1b[0m";
+            } else {
+                screen += &line.text;
+            }
+
+            screen += "\x1b[K";
+        }
+
+        screen += "\x1b[J";
+        write_output(&screen)
+    }
+
+    /// Waits for a key press for a short time
+    fn read_key(&self) -> GenericResult<Option<Key>> {
+        let mut input = [0; 8];
+        let size = match io::stdin().lock().read(&mut input) {
+            Ok(size) => size,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => 0,
+            Err(e) => return Err!("Unable to read the terminal input: {}", e),
+        };
+        Ok(parse_key(&input[..size]))
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = write_output("\x1b[?25h\x1b[?1049l");
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.attributes) };
+    }
+}
+
+fn write_output(data: &str) -> EmptyResult {
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(data.as_bytes())?;
+    Ok(stdout.flush()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+
+        server.add_torrent(MockTorrent::new(1, "copying", "/downloads"));
+        server.add_torrent(MockTorrent::new(2, "downloading", "/downloads").downloading());
+        let torrents = runtime.block_on(server.client().get_torrents(TorrentFields::basic())).unwrap();
+
+        let mut state = State {
+            torrents: torrents,
+            dashboard: Ok(json!({
+                "status": "2 torrents, 1 copying",
+                "disk_space": [{"path": "/downloads", "free": 750, "total": 1000}],
+                "torrents": [{"hash": format!("{:040x}", 1), "consuming": true}],
+                "copies": [{"name": "copying", "percent": 50, "speed": 1024, "eta": 60}],
+                "queued": [],
+                "notifications": [],
+            })),
+            selected: 1,
+            message: None,
+        };
+
+        let text = |lines: Vec<Line>| lines.into_iter().map(|line| line.text).collect::<Vec<_>>();
+
+        let lines = render(&state, 120, 40);
+        assert_eq!(lines[0].text, "Transmission controller: 2 torrents, 1 copying");
+        assert_eq!(lines[1].text, "/downloads: [#####---------------] 25% used, 750 B free");
+        assert!(lines[4].text.contains(" consuming ") && lines[4].text.ends_with(" copying"));
+        assert!(lines[5].highlighted && lines[5].text.ends_with(" downloading"));
+        assert!(text(render(&state, 120, 40)).contains(&s!(
+            "  [##########----------]  50%     1.0 KB/s ETA 1m       copying")));
+
+        // The list is scrolled to the selected torrent
+        let lines = render(&state, 20, 14);
+        assert_eq!(lines.len(), 14);
+        assert!(lines.iter().all(|line| line.text.chars().count() <= 20));
+        assert_eq!(lines.iter().filter(|line| line.highlighted).count(), 1);
+
+        state.dashboard = Err(s!("connection refused"));
+        let lines = text(render(&state, 120, 40));
+        assert_eq!(lines[0], "The daemon is unavailable: connection refused");
+        assert_eq!(lines.last().unwrap(), KEYS_HELP);
+
+        assert_eq!(parse_key(b"\x1b[B"), Some(Key::Down));
+        assert_eq!(parse_key(b"x"), None);
+    }
+}
@@ -0,0 +1,210 @@
+//! Extraction of the archives (rar, zip, 7z) from the completed torrents using 7-Zip.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::common::{EmptyResult, GenericResult};
+use crate::util::process::run_command;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct UnpackConfig {
+    pub enabled: bool,
+    /// 7-Zip executable (7z, 7za, 7zz)
+    pub command: String,
+    /// Don't copy the archives which have been successfully extracted
+    pub skip_archives: bool,
+}
+
+impl Default for UnpackConfig {
+    fn default() -> UnpackConfig {
+        UnpackConfig {
+            enabled: false,
+            command: s!("7z"),
+            skip_archives: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFile {
+    /// A single-volume archive or the first volume of a multi-volume one
+    First,
+    /// Non-first volume of a multi-volume archive
+    Volume,
+}
+
+/// Detects archive files by their names
+pub fn get_archive_file(name: &str) -> Option<ArchiveFile> {
+    let name = name.to_lowercase();
+
+    if let Some(stem) = name.strip_suffix(".rar") {
+        if let Some((_, part)) = stem.rsplit_once(".part") {
+            if !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()) {
+                return Some(if part.trim_start_matches('0') == "1" {
+                    ArchiveFile::First
+                } else {
+                    ArchiveFile::Volume
+                });
+            }
+        }
+        return Some(ArchiveFile::First);
+    }
+
+    if name.ends_with(".zip") || name.ends_with(".7z") {
+        return Some(ArchiveFile::First);
+    }
+
+    let (stem, extension) = name.rsplit_once('.')?;
+    let is_number = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+
+    // Old style RAR volumes: .rar, .r00, .r01, ...
+    if let Some(number) = extension.strip_prefix('r') {
+        if number.len() == 2 && is_number(number) {
+            return Some(ArchiveFile::Volume);
+        }
+    }
+
+    // Split 7z archives: .7z.001, .7z.002, ...
+    if stem.ends_with(".7z") && extension.len() == 3 && is_number(extension) {
+        return Some(if extension == "001" { ArchiveFile::First } else { ArchiveFile::Volume });
+    }
+
+    None
+}
+
+pub struct Unpacker {
+    command: String,
+    skip_archives: bool,
+}
+
+impl Unpacker {
+    pub fn new(config: &UnpackConfig) -> Option<Unpacker> {
+        if !config.enabled {
+            return None;
+        }
+
+        Some(Unpacker {
+            command: config.command.clone(),
+            skip_archives: config.skip_archives,
+        })
+    }
+
+    pub fn skip_archives(&self) -> bool {
+        self.skip_archives
+    }
+
+    /// Tests the archive, extracts it to the destination directory and checks that all its files have been extracted
+    pub fn extract(&self, archive: &Path, destination: &Path) -> EmptyResult {
+        let archive_path = archive.to_str().ok_or_else(|| format!("Invalid archive path: {:?}", archive))?;
+        let destination_path = destination.to_str().ok_or_else(|| format!(
+            "Invalid destination path: {:?}", destination))?;
+
+        info!("Extracting '{}' to '{}'...", archive.display(), destination.display());
+
+        run_command(&self.command, &[s!("t"), s!("-y"), s!(archive_path)])?;
+        let listing = run_command(&self.command, &[s!("l"), s!("-slt"), s!(archive_path)])?;
+        run_command(&self.command, &[s!("x"), s!("-y"), format!("-o{}", destination_path), s!(archive_path)])?;
+
+        for (path, size) in parse_listing(&listing)? {
+            let path = destination.join(path);
+            let metadata = fs::metadata(&path).map_err(|e| format!(
+                "Extraction verification failed for '{}': {}", path.display(), e))?;
+
+            if metadata.len() != size {
+                return Err!("Extraction verification failed: '{}' has invalid size", path.display());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses `7z l -slt` output returning paths and sizes of the archive's files
+fn parse_listing(listing: &str) -> GenericResult<Vec<(PathBuf, u64)>> {
+    let mut files = Vec::new();
+
+    let listing = match listing.split_once("\n----------\n") {
+        Some((_, entries)) => entries,
+        None => return Err!("Got an unexpected archive listing"),
+    };
+
+    for entry in listing.split("\n\n") {
+        let mut path = None;
+        let mut size = None;
+        let mut folder = false;
+
+        for line in entry.lines() {
+            match line.split_once(" = ") {
+                Some(("Path", value)) => path = Some(PathBuf::from(value)),
+                Some(("Size", value)) => size = value.parse().ok(),
+                Some(("Folder", value)) => folder = value == "+",
+                Some(("Attributes", value)) => folder |= value.starts_with('D'),
+                _ => {},
+            }
+        }
+
+        if folder {
+            continue;
+        }
+
+        if let Some(path) = path {
+            let size = size.ok_or_else(|| format!("Got an unexpected archive listing for {:?}", path))?;
+            files.push((path, size));
+        }
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_file_detection() {
+        for (name, file) in [
+            ("movie.mkv", None),
+            ("archive.zip", Some(ArchiveFile::First)),
+            ("archive.7z", Some(ArchiveFile::First)),
+            ("archive.7z.001", Some(ArchiveFile::First)),
+            ("archive.7z.002", Some(ArchiveFile::Volume)),
+            ("Archive.RAR", Some(ArchiveFile::First)),
+            ("archive.r00", Some(ArchiveFile::Volume)),
+            ("archive.part01.rar", Some(ArchiveFile::First)),
+            ("archive.part1.rar", Some(ArchiveFile::First)),
+            ("archive.part02.rar", Some(ArchiveFile::Volume)),
+            ("some.party.rar", Some(ArchiveFile::First)),
+        ] {
+            assert_eq!(get_archive_file(name), file, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_listing_parsing() {
+        let listing = [
+            "7-Zip [64] 16.02 : Copyright (c) 1999-2016 Igor Pavlov : 2016-05-21",
+            "",
+            "Listing archive: archive.zip",
+            "",
+            "--",
+            "Path = archive.zip",
+            "Type = zip",
+            "Physical Size = 412",
+            "",
+            "----------",
+            "Path = dir",
+            "Folder = +",
+            "Size = 0",
+            "",
+            "Path = dir/file",
+            "Folder = -",
+            "Size = 4",
+            "",
+        ].join("\n");
+
+        assert_eq!(parse_listing(&listing).unwrap(), vec![(PathBuf::from("dir/file"), 4)]);
+    }
+}
@@ -1,24 +1,293 @@
+use std::ffi::CString;
 use std::fs::{self, File, OpenOptions};
-use std::io::{self, ErrorKind};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{self as unix_fs, MetadataExt, PermissionsExt};
 use std::path::{Path, PathBuf};
+use std::ptr;
+use std::thread;
 use std::time::{Instant, Duration};
 
 use regex::Regex;
 
-use crate::common::{EmptyResult, GenericResult};
+use crate::common::{EmptyResult, ErrorContext, GenericResult};
 use crate::util::process::{RunCommandProvider, RunCommand};
 
-pub fn copy_downloaded_file<S: AsRef<Path>, D: AsRef<Path>>(src: S, dst: D) -> EmptyResult {
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CopyOptions {
+    /// Copy speed limit in bytes per second
+    pub speed_limit: Option<u64>,
+    /// Don't use reflinks and copy_file_range()
+    pub legacy: bool,
+}
+
+/// Copies the file limiting the copy speed if requested. The copy gets modification time of the original file, which
+/// allows to resume an interrupted copying: the already copied files (with the same size and modification time) are
+/// skipped, the partially copied ones are appended. `progress` is called with the number of bytes copied (or skipped)
+/// since the previous call.
+pub fn copy_downloaded_file<S: AsRef<Path>, D: AsRef<Path>>(
+    src: S, dst: D, options: CopyOptions, progress: &mut dyn FnMut(u64),
+) -> EmptyResult {
+    let src = src.as_ref();
     let mut src_file = open_downloaded_file(src)?;
+    let src_metadata = src_file.metadata().file_context("Failed to stat()", src)?;
+    let src_modify_time = src_metadata.modified().file_context("Failed to stat()", src)?;
 
     let dst = dst.as_ref();
-    let mut dst_file = OpenOptions::new()
-        .create_new(true)
-        .write(true)
-        .open(dst)
-        .map_err(|e| format!("Failed to create '{}': {}", dst.display(), e))?;
+    let mut offset = 0;
+
+    match fs::metadata(dst) {
+        Ok(dst_metadata) => {
+            if dst_metadata.len() == src_metadata.len() && dst_metadata.modified()? == src_modify_time {
+                debug!("'{}' has already been copied.", dst.display());
+                progress(src_metadata.len());
+                return Ok(());
+            }
+
+            if dst_metadata.len() < src_metadata.len() {
+                debug!("Resuming copying to '{}' from {} byte...", dst.display(), dst_metadata.len());
+                offset = dst_metadata.len();
+                progress(offset);
+            }
+        },
+        Err(err) if err.kind() == ErrorKind::NotFound => {},
+        Err(err) => return Err(err).file_context("Failed to stat()", dst),
+    }
+
+    // Not in append mode: copy_file_range() doesn't support it
+    let mut dst_file = OpenOptions::new().create(true).write(true).truncate(offset == 0).open(dst)
+        .file_context("Failed to open", dst)?;
+
+    src_file.seek(SeekFrom::Start(offset))
+        .and_then(|_| dst_file.seek(SeekFrom::Start(offset)))
+        .file_context("Failed to copy", src)?;
+
+    let size = src_metadata.len() - offset;
+
+    if !options.legacy && offset == 0 && size != 0 && clone_file(&src_file, &dst_file) {
+        progress(size);
+    } else {
+        preallocate(&dst_file, offset, size);
+
+        let accelerated = !options.legacy && copy_file_range_data(
+            &src_file, &dst_file, options.speed_limit, progress).file_context("Failed to copy", src)?;
+
+        if !accelerated {
+            copy_data(&mut src_file, &mut dst_file, options.speed_limit, progress).file_context("Failed to copy", src)?;
+        }
+    }
 
-    io::copy(&mut src_file, &mut dst_file)?;
+    dst_file.set_modified(src_modify_time).file_context("Failed to set modification time of", dst)?;
+
+    // The torrent mustn't be considered copied until its data is on the disk
+    dst_file.sync_all().file_context("Failed to fsync()", dst)?;
+
+    Ok(())
+}
+
+/// Flushes the directory entries (created or renamed files) to the disk
+pub fn sync_dir<P: AsRef<Path>>(path: P) -> EmptyResult {
+    let path = path.as_ref();
+    File::open(path).and_then(|dir| dir.sync_all()).file_context("Failed to fsync()", path)?;
+    Ok(())
+}
+
+const COPY_BLOCK_SIZE: usize = 1024 * 1024;
+const THROTTLED_COPY_BLOCK_SIZE: usize = 256 * 1024;
+
+/// Makes the file a reflink (btrfs, XFS) of the source: they share the data blocks
+#[cfg(target_os = "linux")]
+fn clone_file(src: &File, dst: &File) -> bool {
+    use std::os::fd::AsRawFd;
+
+    if unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) } == 0 {
+        return true;
+    }
+
+    debug!("Reflinks aren't supported: {}.", io::Error::last_os_error());
+    false
+}
+
+#[cfg(not(target_os = "linux"))]
+fn clone_file(_src: &File, _dst: &File) -> bool {
+    false
+}
+
+/// Reserves the disk space for the rest of the file to reduce fragmentation. The file size isn't changed, so an
+/// interrupted copying is still resumed from the right place.
+#[cfg(target_os = "linux")]
+fn preallocate(file: &File, offset: u64, size: u64) {
+    use std::os::fd::AsRawFd;
+
+    if size == 0 {
+        return;
+    }
+
+    if unsafe {
+        libc::fallocate(file.as_raw_fd(), libc::FALLOC_FL_KEEP_SIZE, offset as libc::off_t, size as libc::off_t)
+    } != 0 {
+        debug!("Unable to preallocate the file: {}.", io::Error::last_os_error());
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn preallocate(_file: &File, _offset: u64, _size: u64) {
+}
+
+/// Copies the data within the kernel (or on the server side for network file systems) via copy_file_range(). Returns
+/// false if it isn't supported and nothing has been copied.
+#[cfg(target_os = "linux")]
+fn copy_file_range_data(
+    src: &File, dst: &File, speed_limit: Option<u64>, progress: &mut dyn FnMut(u64),
+) -> io::Result<bool> {
+    use std::os::fd::AsRawFd;
+
+    let (src_fd, dst_fd) = (src.as_raw_fd(), dst.as_raw_fd());
+    let block_size = if speed_limit.is_some() { THROTTLED_COPY_BLOCK_SIZE } else { COPY_BLOCK_SIZE };
+    let start_time = Instant::now();
+    let mut copied = 0;
+
+    loop {
+        let size = unsafe {
+            libc::copy_file_range(src_fd, ptr::null_mut(), dst_fd, ptr::null_mut(), block_size, 0)
+        };
+
+        let size = match size {
+            0 => break,
+            size if size > 0 => size as u64,
+            _ => {
+                let err = io::Error::last_os_error();
+                match err.raw_os_error() {
+                    Some(libc::EINTR) => continue,
+                    Some(libc::ENOSYS | libc::EXDEV | libc::EOPNOTSUPP | libc::EINVAL) if copied == 0 => {
+                        debug!("copy_file_range() isn't supported: {}.", err);
+                        return Ok(false);
+                    },
+                    _ => return Err(err),
+                }
+            },
+        };
+
+        copied += size;
+        progress(size);
+
+        if let Some(speed_limit) = speed_limit {
+            let expected_time = Duration::from_secs_f64(copied as f64 / speed_limit as f64);
+            if let Some(delay) = expected_time.checked_sub(start_time.elapsed()) {
+                thread::sleep(delay);
+            }
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_file_range_data(
+    _src: &File, _dst: &File, _speed_limit: Option<u64>, _progress: &mut dyn FnMut(u64),
+) -> io::Result<bool> {
+    Ok(false)
+}
+
+fn copy_data<R: Read, W: Write>(
+    src: &mut R, dst: &mut W, speed_limit: Option<u64>, progress: &mut dyn FnMut(u64),
+) -> io::Result<u64> {
+    let mut buf = vec![0; if speed_limit.is_some() { THROTTLED_COPY_BLOCK_SIZE } else { COPY_BLOCK_SIZE }];
+    let start_time = Instant::now();
+    let mut copied = 0;
+
+    loop {
+        let size = match src.read(&mut buf) {
+            Ok(0) => break,
+            Ok(size) => size,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+
+        dst.write_all(&buf[..size])?;
+        copied += size as u64;
+        progress(size as u64);
+
+        if let Some(speed_limit) = speed_limit {
+            let expected_time = Duration::from_secs_f64(copied as f64 / speed_limit as f64);
+            if let Some(delay) = expected_time.checked_sub(start_time.elapsed()) {
+                thread::sleep(delay);
+            }
+        }
+    }
+
+    Ok(copied)
+}
+
+pub fn link_downloaded_file<S: AsRef<Path>, D: AsRef<Path>>(src: S, dst: D) -> EmptyResult {
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    let src_metadata = open_downloaded_file(src)?.metadata()?;
+
+    // Left from an interrupted linking
+    if let Ok(dst_metadata) = fs::symlink_metadata(dst) {
+        if dst_metadata.dev() == src_metadata.dev() && dst_metadata.ino() == src_metadata.ino() {
+            return Ok(());
+        }
+        fs::remove_file(dst).file_context("Failed to remove", dst)?;
+    }
+
+    fs::hard_link(src, dst).map_err(|e| format!(
+        "Failed to create '{}' hard link to '{}': {}", dst.display(), src.display(), e))?;
+
+    Ok(())
+}
+
+/// Checks whether the paths reside on the same file system (so hard links can be created between them)
+pub fn is_same_file_system<A: AsRef<Path>, B: AsRef<Path>>(a: A, b: B) -> GenericResult<bool> {
+    let device = |path: &Path| fs::metadata(path).map(|metadata| metadata.dev()).map_err(|e| format!(
+        "'{}': {}", path.display(), e));
+    Ok(device(a.as_ref())? == device(b.as_ref())?)
+}
+
+const VERIFICATION_BLOCK_SIZE: u64 = 1024 * 1024;
+const VERIFICATION_SAMPLES: u64 = 16;
+
+/// Checks that the copy is identical to the original file. In fast mode only file sizes and a few blocks evenly
+/// distributed over the file are compared.
+pub fn verify_copy<S: AsRef<Path>, D: AsRef<Path>>(src: S, dst: D, fast: bool) -> EmptyResult {
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+
+    let mut src_file = File::open(src).file_context("Failed to open", src)?;
+    let mut dst_file = File::open(dst).file_context("Failed to open", dst)?;
+
+    let size = src_file.metadata()?.len();
+    let copy_size = dst_file.metadata()?.len();
+    if copy_size != size {
+        return Err!("'{}' has an invalid size: {} vs {} bytes of the original file", dst.display(), copy_size, size);
+    }
+
+    let blocks = size.div_ceil(VERIFICATION_BLOCK_SIZE);
+    let samples = if fast { blocks.min(VERIFICATION_SAMPLES) } else { blocks };
+
+    let mut src_data = vec![0; VERIFICATION_BLOCK_SIZE as usize];
+    let mut dst_data = vec![0; VERIFICATION_BLOCK_SIZE as usize];
+
+    for sample in 0..samples {
+        let block = if samples == blocks {
+            sample
+        } else {
+            sample * (blocks - 1) / (samples - 1)
+        };
+
+        let offset = block * VERIFICATION_BLOCK_SIZE;
+        let length = VERIFICATION_BLOCK_SIZE.min(size - offset) as usize;
+
+        for (file, data, path) in [(&mut src_file, &mut src_data, src), (&mut dst_file, &mut dst_data, dst)] {
+            file.seek(SeekFrom::Start(offset))
+                .and_then(|_| file.read_exact(&mut data[..length]))
+                .file_context("Failed to read", path)?;
+        }
+
+        if src_data[..length] != dst_data[..length] {
+            return Err!("'{}' differs from the original file at {}-{} bytes", dst.display(), offset, offset + length as u64);
+        }
+    }
 
     Ok(())
 }
@@ -168,6 +437,202 @@ fn _get_device_usage<P: AsRef<Path>>(path: P, provider: &dyn RunCommandProvider)
     ))
 }
 
+/// Moves the file or the directory. When the destination is on another file system, the data is copied to a temporary
+/// path next to the destination, renamed into place and only then deleted from the source, so an interrupted move
+/// never leaves a partial copy under the destination name.
+pub fn move_path<S: AsRef<Path>, D: AsRef<Path>>(src: S, dst: D) -> EmptyResult {
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+
+    match fs::rename(src, dst) {
+        Ok(()) => Ok(()),
+        Err(err) if err.raw_os_error() == Some(libc::EXDEV) => {
+            debug!("'{}' and '{}' are on different file systems. Copying the data...", src.display(), dst.display());
+            copy_and_remove(src, dst)
+        },
+        Err(err) => Err!("Failed to move '{}' to '{}': {}", src.display(), dst.display(), err),
+    }
+}
+
+fn copy_and_remove(src: &Path, dst: &Path) -> EmptyResult {
+    let name = dst.file_name().ok_or_else(|| format!("Invalid path: '{}'", dst.display()))?;
+    let temp_path = dst.with_file_name(format!(".{}.partial", name.to_string_lossy()));
+
+    // A leftover of the previous interrupted move
+    if fs::symlink_metadata(&temp_path).is_ok() {
+        remove_path(&temp_path)?;
+    }
+
+    copy_tree(src, &temp_path)?;
+    fs::rename(&temp_path, dst).rename_context(&temp_path, dst)?;
+
+    if let Some(parent) = dst.parent() {
+        sync_dir(parent)?;
+    }
+
+    remove_path(src).map_err(|e| format!(
+        "'{}' has been copied to '{}', but the source can't be deleted: {}", src.display(), dst.display(), e))?;
+
+    Ok(())
+}
+
+/// Copies the file or the directory with all its contents preserving permissions and modification times of the files.
+/// Symlinks are copied as is.
+fn copy_tree(src: &Path, dst: &Path) -> EmptyResult {
+    let metadata = fs::symlink_metadata(src).file_context("Failed to stat()", src)?;
+
+    if metadata.file_type().is_symlink() {
+        let target = fs::read_link(src).file_context("Failed to read", src)?;
+        unix_fs::symlink(target, dst).file_context("Failed to create", dst)?;
+    } else if metadata.is_dir() {
+        fs::create_dir(dst).file_context("Failed to create", dst)?;
+        for entry in fs::read_dir(src).file_context("Unable to read", src)? {
+            let entry = entry.file_context("Unable to read", src)?;
+            copy_tree(&entry.path(), &dst.join(entry.file_name()))?;
+        }
+        fs::set_permissions(dst, metadata.permissions()).file_context("Failed to set permissions of", dst)?;
+        sync_dir(dst)?;
+    } else {
+        copy_downloaded_file(src, dst, CopyOptions::default(), &mut |_| {})?;
+        fs::set_permissions(dst, metadata.permissions()).file_context("Failed to set permissions of", dst)?;
+    }
+
+    Ok(())
+}
+
+/// Deletes the file or the directory with all its contents
+pub fn remove_path<P: AsRef<Path>>(path: P) -> EmptyResult {
+    let path = path.as_ref();
+
+    let result = if fs::symlink_metadata(path).file_context("Failed to stat()", path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+
+    result.file_context("Failed to delete", path)
+}
+
+/// Returns total size of the file or the directory with all its contents (symlinks aren't followed)
+pub fn get_total_size<P: AsRef<Path>>(path: P) -> GenericResult<u64> {
+    let path = path.as_ref();
+    let metadata = fs::symlink_metadata(path).map_err(|e| format!("'{}': {}", path.display(), e))?;
+
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut size = 0;
+    for entry in fs::read_dir(path).file_context("Unable to read", path)? {
+        size += get_total_size(entry?.path())?;
+    }
+
+    Ok(size)
+}
+
+/// Returns free (available to unprivileged users) and total space of the file system containing `path`
+pub fn get_file_system_space<P: AsRef<Path>>(path: P) -> GenericResult<(u64, u64)> {
+    let path = path.as_ref();
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).map_err(|_| format!(
+        "Invalid path: '{}'", path.display()))?;
+
+    let mut stat: libc::statvfs = unsafe { mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err!("Unable to get file system information for '{}': {}", path.display(), io::Error::last_os_error());
+    }
+
+    let block_size = if stat.f_frsize != 0 { stat.f_frsize } else { stat.f_bsize } as u64;
+    Ok((stat.f_bavail as u64 * block_size, stat.f_blocks as u64 * block_size))
+}
+
+/// Ownership and permissions to set on the files
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FilePermissions {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub file_mode: Option<u32>,
+    pub dir_mode: Option<u32>,
+}
+
+impl FilePermissions {
+    pub fn is_empty(&self) -> bool {
+        *self == FilePermissions::default()
+    }
+}
+
+/// Applies the ownership and permissions to the file or the directory with all its contents (symlinks aren't followed)
+pub fn set_permissions<P: AsRef<Path>>(path: P, permissions: &FilePermissions) -> EmptyResult {
+    let path = path.as_ref();
+    let metadata = fs::symlink_metadata(path).map_err(|e| format!("'{}': {}", path.display(), e))?;
+
+    if permissions.uid.is_some() || permissions.gid.is_some() {
+        unix_fs::lchown(path, permissions.uid, permissions.gid).file_context("Unable to change owner of", path)?;
+    }
+
+    let mode = if metadata.is_dir() {
+        permissions.dir_mode
+    } else if metadata.is_file() {
+        permissions.file_mode
+    } else {
+        None
+    };
+
+    if let Some(mode) = mode {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+            .file_context("Unable to change permissions of", path)?;
+    }
+
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path).file_context("Unable to read", path)? {
+            set_permissions(entry?.path(), permissions)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns ID of the user with the specified name or ID
+pub fn get_user_id(name: &str) -> GenericResult<u32> {
+    if let Ok(id) = name.parse() {
+        return Ok(id);
+    }
+
+    let c_name = CString::new(name).map_err(|_| format!("Invalid user name: {:?}", name))?;
+    let mut passwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    let mut result = ptr::null_mut();
+
+    let errno = unsafe { libc::getpwnam_r(c_name.as_ptr(), &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if errno != 0 {
+        return Err!("Unable to get information about {:?} user: {}", name, io::Error::from_raw_os_error(errno));
+    } else if result.is_null() {
+        return Err!("{:?} user doesn't exist", name);
+    }
+
+    Ok(passwd.pw_uid)
+}
+
+/// Returns ID of the group with the specified name or ID
+pub fn get_group_id(name: &str) -> GenericResult<u32> {
+    if let Ok(id) = name.parse() {
+        return Ok(id);
+    }
+
+    let c_name = CString::new(name).map_err(|_| format!("Invalid group name: {:?}", name))?;
+    let mut group: libc::group = unsafe { mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    let mut result = ptr::null_mut();
+
+    let errno = unsafe { libc::getgrnam_r(c_name.as_ptr(), &mut group, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if errno != 0 {
+        return Err!("Unable to get information about {:?} group: {}", name, io::Error::from_raw_os_error(errno));
+    } else if result.is_null() {
+        return Err!("{:?} group doesn't exist", name);
+    }
+
+    Ok(group.gr_gid)
+}
+
 // Transmission 4.X has a bug due to which torrents are marked as downloaded before their renaming from *.part files.
 fn open_downloaded_file<P: AsRef<Path>>(path: P) -> GenericResult<File> {
     let path = path.as_ref();
@@ -179,7 +644,7 @@ fn open_downloaded_file<P: AsRef<Path>>(path: P) -> GenericResult<File> {
             Ok(file) => return Ok(file),
             Err(err) => {
                 if err.kind() != ErrorKind::NotFound || !check_part_file {
-                    return Err!("Failed to open '{}': {}", path.display(), err);
+                    return Err(err).file_context("Failed to open", path);
                 }
 
                 let part_path = {
@@ -219,8 +684,135 @@ fn is_no_such_file_error(error: &io::Error) -> bool {
 
 #[cfg(test)]
 mod tests {
+    use std::fs;
+    use std::os::unix::fs::{self as unix_fs, PermissionsExt};
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    use tempfile::TempDir;
+
     use crate::util::process::tests::RunCommandMock;
 
+    #[test]
+    fn test_verify_copy() {
+        let temp_dir = TempDir::new().unwrap();
+        let (src, dst) = (temp_dir.path().join("src"), temp_dir.path().join("dst"));
+
+        let mut data: Vec<u8> = (0..5 * super::VERIFICATION_BLOCK_SIZE / 2).map(|i| i as u8).collect();
+        fs::write(&src, &data).unwrap();
+
+        fs::write(&dst, &data).unwrap();
+        super::verify_copy(&src, &dst, false).unwrap();
+        super::verify_copy(&src, &dst, true).unwrap();
+
+        fs::write(&dst, &data[1..]).unwrap();
+        assert!(super::verify_copy(&src, &dst, true).unwrap_err().to_string().contains("invalid size"));
+
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        fs::write(&dst, &data).unwrap();
+        assert!(super::verify_copy(&src, &dst, false).is_err());
+        assert!(super::verify_copy(&src, &dst, true).is_err());
+    }
+
+    #[test]
+    fn test_resumed_copying() {
+        let temp_dir = TempDir::new().unwrap();
+        let (src, dst) = (temp_dir.path().join("src"), temp_dir.path().join("dst"));
+        fs::write(&src, "some data").unwrap();
+
+        fs::write(&dst, "some").unwrap();
+        super::copy_downloaded_file(&src, &dst, Default::default(), &mut |_| {}).unwrap();
+        assert_eq!(fs::read_to_string(&dst).unwrap(), "some data");
+
+        // The copy with the same size and modification time is considered complete
+        fs::write(&dst, "same size").unwrap();
+        let modify_time = fs::metadata(&src).unwrap().modified().unwrap();
+        fs::File::options().write(true).open(&dst).unwrap().set_modified(modify_time).unwrap();
+        super::copy_downloaded_file(&src, &dst, Default::default(), &mut |_| {}).unwrap();
+        assert_eq!(fs::read_to_string(&dst).unwrap(), "same size");
+
+        fs::write(&dst, "much longer data").unwrap();
+        super::copy_downloaded_file(&src, &dst, Default::default(), &mut |_| {}).unwrap();
+        assert_eq!(fs::read_to_string(&dst).unwrap(), "some data");
+    }
+
+    #[test]
+    fn test_accelerated_copying() {
+        let temp_dir = TempDir::new().unwrap();
+        let src = temp_dir.path().join("src");
+        let data: Vec<u8> = (0..3 * super::COPY_BLOCK_SIZE + 1).map(|index| index as u8).collect();
+        fs::write(&src, &data).unwrap();
+
+        for legacy in [false, true] {
+            let dst = temp_dir.path().join(format!("dst-{}", legacy));
+            fs::write(&dst, &data[..super::COPY_BLOCK_SIZE / 2]).unwrap();
+
+            let options = super::CopyOptions {speed_limit: None, legacy: legacy};
+            let mut progress = 0;
+            super::copy_downloaded_file(&src, &dst, options, &mut |size| progress += size).unwrap();
+
+            assert_eq!(fs::read(&dst).unwrap(), data);
+            assert_eq!(progress, data.len() as u64);
+        }
+    }
+
+    #[test]
+    fn test_copy_throttled() {
+        let data = vec![1; 3 * super::THROTTLED_COPY_BLOCK_SIZE];
+        let mut copy = Vec::new();
+
+        let start_time = Instant::now();
+        let speed_limit = 10 * super::THROTTLED_COPY_BLOCK_SIZE as u64;
+
+        let mut progress = 0;
+        assert_eq!(
+            super::copy_data(&mut data.as_slice(), &mut copy, Some(speed_limit), &mut |size| progress += size).unwrap(),
+            data.len() as u64);
+        assert_eq!(copy, data);
+        assert_eq!(progress, data.len() as u64);
+        assert!(start_time.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[test]
+    fn test_move_across_file_systems() {
+        let temp_dir = TempDir::new().unwrap();
+        let shm_temp_dir = Path::new("/dev/shm").is_dir().then(|| TempDir::new_in("/dev/shm").unwrap());
+
+        let mut targets = vec![temp_dir.path().join("local")];
+        match shm_temp_dir {
+            Some(ref shm_temp_dir) if !super::is_same_file_system(temp_dir.path(), shm_temp_dir.path()).unwrap() => {
+                targets.push(shm_temp_dir.path().join("remote"));
+            },
+            _ => {},
+        }
+
+        for (index, dst) in targets.iter().enumerate() {
+            let src = temp_dir.path().join(format!("src-{}", index));
+            fs::create_dir_all(src.join("dir")).unwrap();
+            fs::write(src.join("dir/file"), "data").unwrap();
+            fs::set_permissions(src.join("dir/file"), fs::Permissions::from_mode(0o600)).unwrap();
+            unix_fs::symlink("dir/file", src.join("link")).unwrap();
+
+            // A leftover of an interrupted move
+            fs::create_dir(dst.with_file_name(format!(".{}.partial", dst.file_name().unwrap().to_str().unwrap())))
+                .unwrap();
+
+            if index == 0 {
+                // Force the copying on the same file system
+                super::copy_and_remove(&src, dst).unwrap();
+            } else {
+                super::move_path(&src, dst).unwrap();
+            }
+
+            assert!(fs::symlink_metadata(&src).is_err());
+            assert_eq!(fs::read_to_string(dst.join("dir/file")).unwrap(), "data");
+            assert_eq!(fs::metadata(dst.join("dir/file")).unwrap().permissions().mode() & 0o777, 0o600);
+            assert_eq!(fs::read_link(dst.join("link")).unwrap(), Path::new("dir/file"));
+            assert_eq!(fs::read_dir(dst.parent().unwrap()).unwrap().count(), 1);
+        }
+    }
+
     #[test]
     fn test_get_device_usage() {
         assert_eq!(
@@ -253,4 +845,13 @@ mod tests {
             "Got an unexpected output from `df`"
         );
     }
+
+    #[test]
+    fn test_get_user_and_group_id() {
+        assert_eq!(super::get_user_id("root").unwrap(), 0);
+        assert_eq!(super::get_user_id("1234").unwrap(), 1234);
+        assert_eq!(super::get_group_id("5678").unwrap(), 5678);
+        assert_eq!(super::get_user_id("no-such-user").unwrap_err().to_string(), r#""no-such-user" user doesn't exist"#);
+        assert_eq!(super::get_group_id("no-such-group").unwrap_err().to_string(), r#""no-such-group" group doesn't exist"#);
+    }
 }
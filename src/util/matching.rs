@@ -0,0 +1,174 @@
+use std::fmt;
+
+use regex::Regex;
+use reqwest::Url;
+use serde::{Deserialize, Deserializer, de};
+
+use crate::common::GenericResult;
+
+/// Host name pattern: `example.com` matches the host itself and all its subdomains, `*` matches any sequence of
+/// characters within the host name (`tracker*.example.com`, for example).
+#[derive(Clone)]
+pub struct HostPattern {
+    pattern: String,
+    regex: Regex,
+}
+
+impl HostPattern {
+    pub fn new(pattern: &str) -> GenericResult<HostPattern> {
+        let pattern = pattern.trim().to_lowercase();
+
+        if pattern.is_empty() || pattern.contains(|c: char| c.is_whitespace() || c == '/' || c == ':') {
+            return Err!("Invalid host pattern: {:?}", pattern);
+        }
+
+        let regex = format!(r"^(?:.+\.)?{}$", pattern.split('*').map(regex::escape).collect::<Vec<_>>().join(".*"));
+
+        Ok(HostPattern {
+            regex: Regex::new(&regex).map_err(|e| format!("Invalid host pattern {:?}: {}", pattern, e))?,
+            pattern: pattern,
+        })
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        self.regex.is_match(&host.to_lowercase())
+    }
+
+    pub fn matches_any<'a, I: IntoIterator<Item = &'a str>>(&self, hosts: I) -> bool {
+        hosts.into_iter().any(|host| self.matches(host))
+    }
+}
+
+impl fmt::Debug for HostPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.pattern)
+    }
+}
+
+impl<'de> Deserialize<'de> for HostPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<HostPattern, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        HostPattern::new(&pattern).map_err(de::Error::custom)
+    }
+}
+
+/// File pattern: a case-insensitive glob (`*`, `?`) which is matched against the file name (or against the file path
+/// relative to the torrent's download directory if it contains `/`) or a regular expression with `regex:` prefix which
+/// is always matched against the path.
+#[derive(Clone)]
+pub struct FilePattern {
+    pattern: String,
+    regex: Regex,
+    match_path: bool,
+}
+
+impl FilePattern {
+    pub fn new(pattern: &str) -> GenericResult<FilePattern> {
+        if pattern.is_empty() {
+            return Err!("Invalid file pattern: {:?}", pattern);
+        }
+
+        let (regex, match_path) = match pattern.strip_prefix("regex:") {
+            Some(regex) => (s!(regex), true),
+            None => {
+                let regex = pattern.chars().map(|c| match c {
+                    '*' => s!(".*"),
+                    '?' => s!("."),
+                    _ => regex::escape(&c.to_string()),
+                }).collect::<String>();
+                (format!("(?i)^{}$", regex), pattern.contains('/'))
+            },
+        };
+
+        Ok(FilePattern {
+            pattern: s!(pattern),
+            regex: Regex::new(&regex).map_err(|e| format!("Invalid file pattern {:?}: {}", pattern, e))?,
+            match_path: match_path,
+        })
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        if self.match_path {
+            self.regex.is_match(path)
+        } else {
+            self.regex.is_match(path.rsplit('/').next().unwrap())
+        }
+    }
+}
+
+impl fmt::Debug for FilePattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.pattern)
+    }
+}
+
+impl<'de> Deserialize<'de> for FilePattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<FilePattern, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        FilePattern::new(&pattern).map_err(de::Error::custom)
+    }
+}
+
+pub fn deserialize_regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+    let regex = String::deserialize(deserializer)?;
+    Regex::new(&regex).map_err(|e| de::Error::custom(format!("Invalid regular expression {:?}: {}", regex, e)))
+}
+
+pub fn deserialize_optional_regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Regex>, D::Error> {
+    Ok(Some(deserialize_regex(deserializer)?))
+}
+
+/// Returns host name of the specified URL (tracker announce URL, for example)
+pub fn get_url_host(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    url.host_str().map(|host| host.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_pattern() {
+        let pattern = HostPattern::new("example.com").unwrap();
+        assert!(pattern.matches("example.com"));
+        assert!(pattern.matches("tracker.Example.com"));
+        assert!(!pattern.matches("myexample.com"));
+        assert!(!pattern.matches("example.com.org"));
+
+        let pattern = HostPattern::new("tracker*.example.com").unwrap();
+        assert!(pattern.matches("tracker.example.com"));
+        assert!(pattern.matches("tracker2.example.com"));
+        assert!(pattern.matches("bt.tracker2.example.com"));
+        assert!(!pattern.matches("example.com"));
+
+        for invalid in ["", "http://example.com", "example.com:80"] {
+            assert!(HostPattern::new(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn test_file_pattern() {
+        let pattern = FilePattern::new("*sample*").unwrap();
+        assert!(!pattern.matches("Movie/Sample/movie.mkv"));
+        assert!(pattern.matches("Movie/movie-SAMPLE.mkv"));
+        assert!(!pattern.matches("Movie/movie.mkv"));
+
+        let pattern = FilePattern::new("*/extras/*.nf?").unwrap();
+        assert!(pattern.matches("Movie/Extras/movie.nfo"));
+        assert!(!pattern.matches("Movie/movie.nfo"));
+
+        let pattern = FilePattern::new(r"regex:(?i)(^|/)sample/").unwrap();
+        assert!(pattern.matches("Movie/Sample/movie.mkv"));
+        assert!(!pattern.matches("Movie/movie.mkv"));
+
+        assert!(FilePattern::new("regex:(").is_err());
+    }
+
+    #[test]
+    fn test_get_url_host() {
+        assert_eq!(get_url_host("http://Tracker.example.com:8080/announce?key=1").unwrap(), "tracker.example.com");
+        assert_eq!(get_url_host("udp://tracker.example.org:6969").unwrap(), "tracker.example.org");
+        assert!(get_url_host("invalid").is_none());
+    }
+}
@@ -1,4 +1,6 @@
 pub mod fs;
 pub mod helpers;
+pub mod matching;
 pub mod process;
+pub mod sha1;
 pub mod time;
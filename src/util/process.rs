@@ -1,6 +1,8 @@
+use std::io;
 use std::process::Command;
 
-use crate::common::GenericResult;
+use crate::common::{EmptyResult, GenericResult};
+use crate::config::IoClass;
 
 pub trait RunCommandProvider {
     fn run_command(&self, command: &str, args: &[String]) -> GenericResult<String> {
@@ -33,6 +35,44 @@ pub fn run_command(command: &str, args: &[String]) -> GenericResult<String> {
         "Error during reading `{}` output: {}", command_string, e))?)
 }
 
+/// Sets I/O scheduling class of the current thread (and the processes spawned by it) like ionice does
+#[cfg(target_os = "linux")]
+pub fn set_io_class(class: IoClass, priority: Option<u8>) -> EmptyResult {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+
+    let priority = match class {
+        IoClass::Normal => return Ok(()),
+        IoClass::BestEffort => IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT | priority.unwrap_or(7) as libc::c_int,
+        IoClass::Idle => IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+    };
+
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, priority) } != 0 {
+        return Err!("Failed to set I/O priority: {}", io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_io_class(class: IoClass, _priority: Option<u8>) -> EmptyResult {
+    match class {
+        IoClass::Normal => Ok(()),
+        _ => Err!("I/O scheduling classes are not supported on this platform"),
+    }
+}
+
+/// Sets CPU scheduling priority of the current thread (and the processes spawned by it) like nice does. On Linux the
+/// nice value is a per-thread attribute, so the other threads are left untouched.
+pub fn set_nice(nice: i32) -> EmptyResult {
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+        return Err!("Failed to set nice value: {}", io::Error::last_os_error());
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -53,6 +93,14 @@ pub mod tests {
         }
     }
 
+    #[test]
+    fn test_set_nice() {
+        std::thread::spawn(|| {
+            set_nice(19).unwrap();
+            assert_eq!(unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) }, 19);
+        }).join().unwrap();
+    }
+
     #[test]
     fn test_run_command() {
         assert_eq!(run_command("echo", &[s!("aaa"), s!("bbb\nccc")]).unwrap(), "aaa bbb\nccc\n");
@@ -0,0 +1,118 @@
+//! SHA-1 which is used by BitTorrent (v1) for the piece hashes
+
+pub const DIGEST_SIZE: usize = 20;
+const BLOCK_SIZE: usize = 64;
+
+pub struct Sha1 {
+    state: [u32; 5],
+    buffer: Vec<u8>,
+    length: u64,
+}
+
+impl Sha1 {
+    pub fn new() -> Sha1 {
+        Sha1 {
+            state: [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0],
+            buffer: Vec::with_capacity(BLOCK_SIZE),
+            length: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+
+        if !self.buffer.is_empty() {
+            let size = (BLOCK_SIZE - self.buffer.len()).min(data.len());
+            self.buffer.extend_from_slice(&data[..size]);
+            data = &data[size..];
+
+            if self.buffer.len() < BLOCK_SIZE {
+                return;
+            }
+
+            let block = std::mem::take(&mut self.buffer);
+            self.process_block(&block);
+            self.buffer = block;
+            self.buffer.clear();
+        }
+
+        let mut blocks = data.chunks_exact(BLOCK_SIZE);
+        for block in &mut blocks {
+            self.process_block(block);
+        }
+        self.buffer.extend_from_slice(blocks.remainder());
+    }
+
+    pub fn finish(mut self) -> [u8; DIGEST_SIZE] {
+        let bit_length = self.length.wrapping_mul(8);
+
+        // 0x80, zeros and the message length in bits which complete the last block
+        let zeros = (BLOCK_SIZE * 2 - self.buffer.len() - 1 - 8) % BLOCK_SIZE;
+        let mut padding = vec![0x80];
+        padding.resize(1 + zeros, 0);
+        padding.extend_from_slice(&bit_length.to_be_bytes());
+        self.update(&padding);
+
+        let mut digest = [0; DIGEST_SIZE];
+        for (chunk, value) in digest.chunks_exact_mut(4).zip(self.state) {
+            chunk.copy_from_slice(&value.to_be_bytes());
+        }
+        digest
+    }
+
+    fn process_block(&mut self, block: &[u8]) {
+        let mut words = [0u32; 80];
+        for (word, chunk) in words.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes(chunk.try_into().unwrap());
+        }
+        for index in 16..80 {
+            words[index] = (words[index - 3] ^ words[index - 8] ^ words[index - 14] ^ words[index - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = self.state;
+
+        for (index, word) in words.iter().enumerate() {
+            let (f, k) = match index {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(chunks: &[&[u8]]) -> String {
+        let mut hasher = Sha1::new();
+        for chunk in chunks {
+            hasher.update(chunk);
+        }
+        hasher.finish().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn test_sha1() {
+        assert_eq!(hash(&[]), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hash(&[b"abc"]), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(hash(&[b"abcdbcdecdefdefgefghfghighij", b"hijkijkljklmklmnlmnomnopnopq"]),
+                   "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+
+        let data = vec![b'a'; 1_000_000];
+        assert_eq!(hash(&[&data[..1], &data[1..100], &data[100..]]), "34aa973cd4c4daa4f61eeb2bdbad27316534016f");
+    }
+}
@@ -1,9 +1,11 @@
 use std::cmp::Ordering;
+use std::env;
+use std::path::{Component, Path, PathBuf};
 
 use legacy_time::Tm;
 use regex::Regex;
 
-use crate::common::GenericResult;
+use crate::common::{EmptyResult, GenericResult};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Time {
@@ -22,6 +24,40 @@ pub type Timestamp = i64;
 pub type DayPeriods = Vec<Period>;
 pub type WeekPeriods = Vec<DayPeriods>;
 
+extern "C" {
+    fn tzset();
+}
+
+/// Checks that the timezone (like "Europe/Berlin") exists in the system timezone database
+pub fn check_timezone(name: &str) -> EmptyResult {
+    let relative = Path::new(name);
+    if name.is_empty() || !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err!("Invalid timezone name: {:?}", name);
+    }
+
+    let database = env::var_os("TZDIR").map_or_else(|| PathBuf::from("/usr/share/zoneinfo"), PathBuf::from);
+    if !database.join(relative).is_file() {
+        return Err!("Unknown timezone: {:?} (it's not found in '{}')", name, database.display());
+    }
+
+    Ok(())
+}
+
+/// Makes all local time calculations (schedules) to use the specified timezone instead of the system one. The DST
+/// rules are taken from the system timezone database.
+///
+/// Must be called before any threads are spawned: the environment modification isn't thread-safe.
+pub fn set_timezone(name: &str) -> EmptyResult {
+    check_timezone(name)?;
+
+    // The colon prefix tells libc to read the timezone from the database instead of parsing it as a POSIX TZ string
+    env::set_var("TZ", format!(":{}", name));
+    unsafe { tzset() };
+
+    Ok(())
+}
+
+/// The periods are matched against the local wall clock time, so they follow DST transitions
 #[allow(clippy::ptr_arg)]
 pub fn is_now_in(periods: &WeekPeriods) -> bool {
     is_in(periods, &legacy_time::now())
@@ -47,13 +83,62 @@ pub fn is_in(periods: &WeekPeriods, now: &Tm) -> bool {
     false
 }
 
+/// Parses a calendar period (YYYY, YYYY-MM or YYYY-MM-DD) returning its [start, end) local time range
+pub fn parse_date_period(string: &str) -> GenericResult<(Timestamp, Timestamp)> {
+    let re = Regex::new(r"^(?P<year>\d{4})(?:-(?P<month>\d{2})(?:-(?P<day>\d{2}))?)?$").unwrap();
+    let invalid = || format!("Invalid period: {:?}", string);
+    let captures = re.captures(string).ok_or_else(invalid)?;
+
+    let get = |name| captures.name(name).map(|value| value.as_str().parse::<i32>().unwrap());
+    let year = get("year").unwrap();
+    let month = get("month");
+    let day = get("day");
+
+    if month.is_some_and(|month| !(1..=12).contains(&month)) || day.is_some_and(|day| !(1..=31).contains(&day)) {
+        return Err(invalid().into());
+    }
+
+    let start = local_timestamp(year, month.unwrap_or(1), day.unwrap_or(1));
+    let end = match (month, day) {
+        (Some(month), Some(day)) => local_timestamp(year, month, day + 1),
+        (Some(month), None) => local_timestamp(year, month + 1, 1),
+        _ => local_timestamp(year + 1, 1, 1),
+    };
+
+    // mktime() normalizes the out of range days (like February 30)
+    if let Some(day) = day {
+        if format_date(start) != format!("{:04}-{:02}-{:02}", year, month.unwrap(), day) {
+            return Err(invalid().into());
+        }
+    }
+
+    Ok((start, end))
+}
+
+/// Formats the timestamp as a local date
+pub fn format_date(timestamp: Timestamp) -> String {
+    let time = legacy_time::at(legacy_time::Timespec::new(timestamp, 0));
+    time.strftime("%Y-%m-%d").unwrap().to_string()
+}
+
+/// Returns the timestamp of the local midnight of the specified day (the out of range days and months are normalized)
+fn local_timestamp(year: i32, month: i32, day: i32) -> Timestamp {
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    tm.tm_year = year - 1900;
+    tm.tm_mon = month - 1;
+    tm.tm_mday = day;
+    tm.tm_isdst = -1;
+    unsafe { libc::mktime(&mut tm) }
+}
+
 pub fn parse_duration(string: &str) -> GenericResult<Duration> {
-    let re = Regex::new(r"^(?P<number>[1-9]\d*)(?P<unit>[mhd])$").unwrap();
+    let re = Regex::new(r"^(?P<number>[1-9]\d*)(?P<unit>[smhd])$").unwrap();
     let captures = re.captures(string).ok_or(format!(
         "Invalid time specification: {}", string))?;
 
     let mut duration = captures.name("number").unwrap().as_str().parse::<Duration>().unwrap();
     duration *= match captures.name("unit").unwrap().as_str() {
+        "s" => 1,
         "m" => 60,
         "h" => 60 * 60,
         "d" => 60 * 60 * 24,
@@ -63,6 +148,24 @@ pub fn parse_duration(string: &str) -> GenericResult<Duration> {
     Ok(duration)
 }
 
+/// Formats duration (in seconds) in a human-readable form like "2d 3h" or "5m 10s"
+pub fn format_duration(seconds: u64) -> String {
+    let units = [("d", 60 * 60 * 24), ("h", 60 * 60), ("m", 60), ("s", 1)];
+
+    // Two most significant units are enough for humans
+    let start = units.iter().position(|&(_, size)| seconds >= size).unwrap_or(units.len() - 1);
+    let mut parts = vec![format!("{}{}", seconds / units[start].1, units[start].0)];
+
+    if let Some(&(name, size)) = units.get(start + 1) {
+        let value = seconds % units[start].1 / size;
+        if value != 0 {
+            parts.push(format!("{}{}", value, name));
+        }
+    }
+
+    parts.join(" ")
+}
+
 pub fn parse_periods(period_strings: &[String]) -> GenericResult<WeekPeriods> {
     let mut week_periods = Vec::with_capacity(7);
     for _ in 0..7 {
@@ -130,7 +233,7 @@ pub fn parse_periods(period_strings: &[String]) -> GenericResult<WeekPeriods> {
     }
 
     for day_periods in &mut week_periods {
-        day_periods.sort_by(|a, b| a.start.cmp(&b.start));
+        day_periods.sort_by_key(|a| a.start);
 
         let mut prev: Option<Time> = None;
         for period in day_periods {
@@ -225,6 +328,55 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_check_timezone() {
+        if Path::new("/usr/share/zoneinfo/Europe/Berlin").exists() {
+            check_timezone("Europe/Berlin").unwrap();
+        }
+
+        for invalid in ["", "Unknown/Timezone", "/etc/localtime", "../zoneinfo/UTC", "Europe/../UTC"] {
+            assert!(check_timezone(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_date_period() {
+        let (start, end) = parse_date_period("2024").unwrap();
+        assert_eq!((format_date(start), format_date(end)), (s!("2024-01-01"), s!("2025-01-01")));
+
+        let (start, end) = parse_date_period("2024-12").unwrap();
+        assert_eq!((format_date(start), format_date(end)), (s!("2024-12-01"), s!("2025-01-01")));
+
+        let (start, end) = parse_date_period("2024-02-29").unwrap();
+        assert_eq!((format_date(start), format_date(end)), (s!("2024-02-29"), s!("2024-03-01")));
+
+        for invalid in ["", "24", "2024-1", "2024-13", "2023-02-29", "2024-03-32", "2024/03"] {
+            assert!(parse_date_period(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30s").unwrap(), 30);
+        assert_eq!(parse_duration("5m").unwrap(), 5 * 60);
+        assert_eq!(parse_duration("2h").unwrap(), 2 * 60 * 60);
+        assert_eq!(parse_duration("3d").unwrap(), 3 * 24 * 60 * 60);
+
+        for invalid in ["", "0m", "5", "m", "1w", "-1h"] {
+            assert!(parse_duration(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0), "0s");
+        assert_eq!(format_duration(59), "59s");
+        assert_eq!(format_duration(60), "1m");
+        assert_eq!(format_duration(61), "1m 1s");
+        assert_eq!(format_duration(2 * 60 * 60 + 5), "2h");
+        assert_eq!(format_duration(3 * 24 * 60 * 60 + 4 * 60 * 60 + 5 * 60), "3d 4h");
+    }
+
     #[test]
     fn test_parse_periods() {
         let period_strings = ["1-5/6:20-7:09", "1-5/0:00-5:19", "6-7/0:00-8:59"]
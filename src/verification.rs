@@ -0,0 +1,125 @@
+//! Periodic verification of the long-term seeds: a rotating subset of the seeding torrents is hash-checked, so bit-rot
+//! on the seeding disk is detected before the trackers flag the corrupted uploads.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::config;
+use crate::state_db::StateDb;
+use crate::transmissionrpc::{Torrent, TorrentStatus};
+use crate::util::time::Timestamp;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct PeriodicVerificationConfig {
+    pub enabled: bool,
+    /// Each torrent is verified once per the period (if the size limit allows it)
+    #[serde(deserialize_with = "config::deserialize_duration")]
+    pub period: Duration,
+    /// Maximum size of the data in GB which is verified per period
+    pub max_size: Option<u64>,
+}
+
+impl Default for PeriodicVerificationConfig {
+    fn default() -> PeriodicVerificationConfig {
+        PeriodicVerificationConfig {
+            enabled: false,
+            period: Duration::from_secs(7 * 24 * 60 * 60),
+            max_size: None,
+        }
+    }
+}
+
+pub struct PeriodicVerification {
+    period: Duration,
+    max_size: Option<u64>,
+}
+
+impl PeriodicVerification {
+    pub fn new(config: &PeriodicVerificationConfig) -> PeriodicVerification {
+        PeriodicVerification {
+            period: config.period,
+            max_size: config.max_size.map(|size| size * 1024 * 1024 * 1024),
+        }
+    }
+
+    /// Returns the next torrent to verify: the seeding torrent which hasn't been verified (or downloaded) for the
+    /// longest time. The torrents are verified one by one until the period's size limit is exhausted.
+    pub fn select<'a>(&self, torrents: &[&'a Torrent], state_db: &StateDb, now: Timestamp) -> Option<&'a Torrent> {
+        let since = now - self.period.as_secs() as Timestamp;
+        let verified_time = |torrent: &Torrent| state_db.get(&torrent.hash).and_then(|record| record.verified_time);
+
+        let verified_size: u64 = torrents.iter()
+            .filter(|torrent| verified_time(torrent).is_some_and(|time| time > since))
+            .map(|torrent| torrent.size)
+            .sum();
+
+        let torrent = torrents.iter().filter_map(|&torrent| {
+            if !torrent.done || !torrent.processed || torrent.status != TorrentStatus::Seeding {
+                return None;
+            }
+            let time = verified_time(torrent).or(torrent.done_time)?;
+            (time <= since).then_some((time, torrent))
+        }).min_by_key(|&(time, torrent)| (time, torrent.id))?.1;
+
+        // A torrent which exceeds the limit by itself is verified alone
+        if let Some(max_size) = self.max_size {
+            if verified_size != 0 && verified_size + torrent.size > max_size {
+                return None;
+            }
+        }
+
+        Some(torrent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::TorrentFields;
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+        let client = server.client();
+
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        let day = 24 * 60 * 60;
+
+        for (id, done_date) in [(1, now - 10 * day), (2, now - 20 * day), (3, now - day), (4, now - 30 * day)] {
+            let mut torrent = MockTorrent::new(id, &format!("torrent-{}", id), "/downloads").processed();
+            torrent.done_date = done_date;
+            torrent.files = vec![(torrent.name.clone(), 600 * 1024 * 1024, true)];
+            if id == 4 {
+                torrent.status = TorrentStatus::Stopped;
+            }
+            server.add_torrent(torrent);
+        }
+
+        let torrents = runtime.block_on(client.get_torrents(TorrentFields::basic())).unwrap();
+        let torrents: Vec<&Torrent> = torrents.iter().collect();
+
+        let state_db = StateDb::new(None, false);
+        let verification = PeriodicVerification::new(&PeriodicVerificationConfig {
+            enabled: true,
+            max_size: Some(1),
+            ..Default::default()
+        });
+
+        let selected = verification.select(&torrents, &state_db, now).unwrap();
+        assert_eq!(selected.id, 2);
+
+        state_db.record_verified(selected);
+        let verify = |now| verification.select(&torrents, &state_db, now).map(|torrent| torrent.id);
+
+        // The size limit is exhausted for the current period
+        assert_eq!(verify(now + 1), None);
+        assert_eq!(verify(now + 8 * day), Some(1));
+    }
+}
@@ -0,0 +1,269 @@
+//! VPN kill-switch: pauses all torrents when the VPN connection drops and resumes them once it's restored
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::common::GenericResult;
+use crate::config;
+use crate::notifications::{Event, Notifier};
+use crate::torrent_client::{self, TorrentClient};
+use crate::transmissionrpc::{TorrentFields, TorrentStatus};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct VpnConfig {
+    /// Network interface which must be up (like "wg0" or "tun0")
+    pub interface: Option<String>,
+
+    /// The public IP must be one of the specified
+    pub ips: Vec<IpAddr>,
+    /// The public IP must belong to one of the countries (ISO 3166-1 alpha-2 codes)
+    pub countries: Vec<String>,
+    /// The public IP must belong to one of the autonomous systems
+    pub asns: Vec<u32>,
+
+    /// Public IP information service which responds in ipinfo.io JSON format
+    pub ip_info_url: String,
+    #[serde(deserialize_with = "config::deserialize_duration")]
+    pub check_interval: Duration,
+}
+
+impl Default for VpnConfig {
+    fn default() -> VpnConfig {
+        VpnConfig {
+            interface: None,
+            ips: Vec::new(),
+            countries: Vec::new(),
+            asns: Vec::new(),
+            ip_info_url: s!("https://ipinfo.io/json"),
+            check_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+impl VpnConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.interface.is_some() || self.checks_public_ip()
+    }
+
+    fn checks_public_ip(&self) -> bool {
+        !self.ips.is_empty() || !self.countries.is_empty() || !self.asns.is_empty()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct IpInfo {
+    ip: IpAddr,
+    country: Option<String>,
+    /// Autonomous system number and name: "AS13335 Cloudflare, Inc."
+    org: Option<String>,
+}
+
+pub struct VpnMonitor {
+    config: VpnConfig,
+    http: Client,
+    /// Time and result of the last public IP check
+    ip_check: Option<(Instant, Result<(), String>)>,
+    down: bool,
+    /// The torrents which have been paused by us
+    paused: HashSet<String>,
+}
+
+impl VpnMonitor {
+    pub fn new(config: VpnConfig) -> VpnMonitor {
+        VpnMonitor {
+            config: config,
+            http: Client::new(),
+            ip_check: None,
+            down: false,
+            paused: HashSet::new(),
+        }
+    }
+
+    pub fn is_down(&self) -> bool {
+        self.down
+    }
+
+    /// Checks the VPN connection pausing or resuming the torrents on its state changes. Returns false if the VPN is
+    /// down and the torrents mustn't be processed.
+    pub async fn control(
+        &mut self, client: &dyn TorrentClient, notifier: &Arc<Notifier>,
+    ) -> torrent_client::Result<bool> {
+        let Some(problem) = self.check().await.err() else {
+            if self.down {
+                info!("VPN connection has been restored. Resuming the torrents...");
+                self.resume(client).await?;
+                self.down = false;
+                notifier.notify_in_background(
+                    Event::Recovered, "VPN connection has been restored", &format!(
+                        "VPN connection has been restored. {} paused torrents have been resumed.", self.paused.len()));
+                self.paused.clear();
+            }
+            return Ok(true);
+        };
+
+        if !self.down {
+            error!("VPN connection is down: {}. Pausing all torrents...", problem);
+            self.down = true;
+            notifier.notify_in_background(Event::Problem, "VPN connection is down", &format!(
+                "VPN connection is down: {}. All torrents have been paused until it's restored.", problem));
+        }
+
+        // The torrents which have been started while the VPN is down are paused as well
+        for torrent in client.get_torrents(TorrentFields::basic()).await? {
+            if torrent.status != TorrentStatus::Stopped {
+                info!(hash = torrent.hash.as_str(), action = "pause";
+                      "Pausing '{}' torrent: VPN connection is down...", torrent.name);
+                client.stop(&torrent.hash).await?;
+                self.paused.insert(torrent.hash);
+            }
+        }
+
+        Ok(false)
+    }
+
+    async fn resume(&self, client: &dyn TorrentClient) -> torrent_client::EmptyResult {
+        for torrent in client.get_torrents(TorrentFields::basic()).await? {
+            if self.paused.contains(&torrent.hash) && torrent.status == TorrentStatus::Stopped {
+                info!(hash = torrent.hash.as_str(), action = "resume"; "Resuming '{}' torrent...", torrent.name);
+                client.start(&torrent.hash).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn check(&mut self) -> Result<(), String> {
+        if let Some(ref interface) = self.config.interface {
+            check_interface(interface)?;
+        }
+
+        if !self.config.checks_public_ip() {
+            return Ok(());
+        }
+
+        // The public IP is checked less frequently to not exceed the service's rate limits
+        match self.ip_check {
+            Some((time, ref result)) if time.elapsed() < self.config.check_interval => result.clone(),
+            _ => {
+                // A failed request is considered as a VPN failure: it may be blocked by the system's kill-switch
+                let result = match self.get_ip_info().await {
+                    Ok(info) => check_ip_info(&self.config, &info),
+                    Err(e) => Err(format!("unable to get the public IP: {}", e)),
+                };
+                self.ip_check = Some((Instant::now(), result.clone()));
+                result
+            },
+        }
+    }
+
+    async fn get_ip_info(&self) -> GenericResult<IpInfo> {
+        let response = self.http.get(&self.config.ip_info_url).timeout(REQUEST_TIMEOUT).send().await
+            .and_then(|response| response.error_for_status())?;
+        Ok(serde_json::from_slice(&response.bytes().await?)?)
+    }
+}
+
+fn check_interface(name: &str) -> Result<(), String> {
+    // The tunnel interfaces report "unknown" state
+    match fs::read_to_string(format!("/sys/class/net/{}/operstate", name)) {
+        Ok(state) if matches!(state.trim(), "up" | "unknown") => Ok(()),
+        Ok(state) => Err(format!("{} interface is {}", name, state.trim())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(format!("{} interface doesn't exist", name)),
+        Err(e) => Err(format!("unable to get {} interface state: {}", name, e)),
+    }
+}
+
+fn check_ip_info(config: &VpnConfig, info: &IpInfo) -> Result<(), String> {
+    if !config.ips.is_empty() && !config.ips.contains(&info.ip) {
+        return Err(format!("the public IP is {}", info.ip));
+    }
+
+    if !config.countries.is_empty() && !info.country.as_ref().is_some_and(|country| {
+        config.countries.iter().any(|expected| expected.eq_ignore_ascii_case(country))
+    }) {
+        return Err(format!("the public IP {} is located in {}", info.ip, info.country.as_deref().unwrap_or("unknown")));
+    }
+
+    if !config.asns.is_empty() {
+        let asn = info.org.as_deref()
+            .and_then(|org| org.split_whitespace().next())
+            .and_then(|asn| asn.strip_prefix("AS"))
+            .and_then(|asn| asn.parse::<u32>().ok());
+
+        if !asn.is_some_and(|asn| config.asns.contains(&asn)) {
+            return Err(format!("the public IP {} belongs to {}", info.ip, info.org.as_deref().unwrap_or("unknown AS")));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    #[test]
+    fn test_ip_check() {
+        let config = VpnConfig {
+            countries: vec![s!("nl"), s!("SE")],
+            asns: vec![13335, 9009],
+            ..Default::default()
+        };
+
+        let info = |ip: &str, country: &str, org: &str| IpInfo {
+            ip: ip.parse().unwrap(),
+            country: Some(s!(country)),
+            org: Some(s!(org)),
+        };
+
+        assert!(check_ip_info(&config, &info("1.2.3.4", "NL", "AS9009 M247 Europe SRL")).is_ok());
+        assert_eq!(check_ip_info(&config, &info("1.2.3.4", "DE", "AS9009 M247 Europe SRL")),
+                   Err(s!("the public IP 1.2.3.4 is located in DE")));
+        assert_eq!(check_ip_info(&config, &info("1.2.3.4", "SE", "AS3301 Telia Company AB")),
+                   Err(s!("the public IP 1.2.3.4 belongs to AS3301 Telia Company AB")));
+
+        let config = VpnConfig {ips: vec!["1.2.3.4".parse().unwrap()], ..Default::default()};
+        assert!(check_ip_info(&config, &info("1.2.3.4", "DE", "")).is_ok());
+        assert_eq!(check_ip_info(&config, &info("4.3.2.1", "DE", "")), Err(s!("the public IP is 4.3.2.1")));
+    }
+
+    #[test]
+    fn test_kill_switch() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+        let client = server.client();
+        let notifier = Arc::new(Notifier::new(None));
+
+        server.add_torrent(MockTorrent::new(1, "seeding", "/downloads"));
+        server.add_torrent(MockTorrent::new(2, "downloading", "/downloads").downloading());
+        let mut torrent = MockTorrent::new(3, "stopped", "/downloads");
+        torrent.status = TorrentStatus::Stopped;
+        server.add_torrent(torrent);
+
+        let mut vpn = VpnMonitor::new(VpnConfig {interface: Some(s!("missing-vpn0")), ..Default::default()});
+        assert!(!runtime.block_on(vpn.control(&client, &notifier)).unwrap());
+        assert!(vpn.is_down());
+
+        let statuses = || server.state().torrents.iter().map(|torrent| torrent.status).collect::<Vec<_>>();
+        assert_eq!(statuses(), vec![TorrentStatus::Stopped; 3]);
+
+        // The loopback interface is always up
+        vpn.config.interface = Some(s!("lo"));
+        assert!(runtime.block_on(vpn.control(&client, &notifier)).unwrap());
+        assert!(!vpn.is_down());
+        assert_eq!(statuses(), vec![TorrentStatus::Seeding, TorrentStatus::Downloading, TorrentStatus::Stopped]);
+    }
+}
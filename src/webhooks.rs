@@ -0,0 +1,415 @@
+//! Slack-compatible, Discord and generic JSON webhook notification channels
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use reqwest::{Client, StatusCode, header};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::common::EmptyResult;
+use crate::config::RetryConfig;
+use crate::notifications::{self, Event};
+use crate::transmissionrpc::{RetryPolicy, Torrent};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Discord limits: https://discord.com/developers/docs/resources/message#embed-object-embed-limits
+const DISCORD_MAX_TITLE_SIZE: usize = 256;
+const DISCORD_MAX_DESCRIPTION_SIZE: usize = 4096;
+
+const PUSHOVER_URL: &str = "https://api.pushover.net/1/messages.json";
+const PUSHOVER_MAX_TITLE_SIZE: usize = 250;
+const PUSHOVER_MAX_MESSAGE_SIZE: usize = 1024;
+
+const NTFY_URL: &str = "https://ntfy.sh";
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WebhookKind {
+    /// Slack incoming webhook (Mattermost and Rocket.Chat accept the same format)
+    Slack,
+    Discord,
+    /// The event with the torrent's metadata for the custom services
+    Json,
+    /// Push notifications via https://pushover.net/
+    Pushover,
+    /// Push notifications via https://gotify.net/ server
+    Gotify,
+    /// Push notifications via https://ntfy.sh/ or a self-hosted ntfy server
+    Ntfy,
+}
+
+impl WebhookKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            WebhookKind::Slack => "slack",
+            WebhookKind::Discord => "discord",
+            WebhookKind::Json => "json",
+            WebhookKind::Pushover => "pushover",
+            WebhookKind::Gotify => "gotify",
+            WebhookKind::Ntfy => "ntfy",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct WebhookConfig {
+    pub kind: WebhookKind,
+    /// The name to refer to the webhook in the notification routes (the kind by default)
+    pub name: Option<String>,
+    /// Gotify or ntfy server URL for the push services (optional for Pushover and ntfy)
+    pub url: Option<String>,
+    /// The events to send (all by default)
+    pub events: Option<Vec<Event>>,
+    /// Pushover or Gotify application token or ntfy access token
+    pub token: Option<String>,
+    /// Pushover user key
+    pub user: Option<String>,
+    /// ntfy topic
+    pub topic: Option<String>,
+    /// Push message priorities by event overriding the default ones
+    #[serde(default)]
+    pub priorities: HashMap<Event, i64>,
+    /// ntfy tags (emojis) by event overriding the default ones
+    #[serde(default)]
+    pub tags: HashMap<Event, Vec<String>>,
+    /// Additional request headers (authorization, for example)
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub retry: RetryConfig,
+}
+
+pub struct Webhook {
+    http: Client,
+    config: WebhookConfig,
+    retry_policy: RetryPolicy,
+}
+
+impl Webhook {
+    pub fn new(config: &WebhookConfig) -> Webhook {
+        Webhook {
+            http: Client::new(),
+            config: config.clone(),
+            retry_policy: RetryPolicy {
+                max_attempts: config.retry.max_attempts,
+                initial_delay: config.retry.initial_delay,
+                max_delay: config.retry.max_delay,
+            },
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.config.name.as_deref().unwrap_or(self.config.kind.name())
+    }
+
+    pub fn accepts(&self, event: Event) -> bool {
+        self.config.events.as_ref().is_none_or(|events| events.contains(&event))
+    }
+
+    pub async fn send(&self, event: Event, torrent: Option<&Torrent>, subject: &str, body: &str) -> EmptyResult {
+        let payload = self.get_payload(event, torrent, subject, body).to_string();
+        let mut attempt = 1;
+
+        loop {
+            // The URL contains the webhook's secret, so it's stripped from the errors
+            let error = match self.post(&payload).await {
+                Ok(()) => return Ok(()),
+                Err(e) => e.without_url(),
+            };
+
+            let transient = error.is_connect() || error.is_timeout() || error.status().is_some_and(|status| {
+                status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+            });
+
+            if !transient || attempt >= self.retry_policy.max_attempts {
+                return Err!("{:?} webhook request failed: {}", self.config.kind, error);
+            }
+
+            let delay = self.retry_policy.get_delay(attempt);
+            debug!("{:?} webhook request has failed: {}. Retrying in {:.1}s...",
+                   self.config.kind, error, delay.as_secs_f64());
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn post(&self, payload: &str) -> reqwest::Result<()> {
+        let url = match self.config.kind {
+            WebhookKind::Pushover => s!(self.config.url.as_deref().unwrap_or(PUSHOVER_URL)),
+            WebhookKind::Gotify => format!("{}/message", self.config.url.as_deref().unwrap().trim_end_matches('/')),
+            // Publishing as JSON requires posting to the root URL
+            WebhookKind::Ntfy => s!(self.config.url.as_deref().unwrap_or(NTFY_URL)),
+            _ => self.config.url.clone().unwrap(),
+        };
+
+        let mut request = self.http.post(url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .timeout(REQUEST_TIMEOUT);
+
+        match (self.config.kind, self.config.token.as_ref()) {
+            (WebhookKind::Gotify, Some(token)) => request = request.header("X-Gotify-Key", token),
+            (WebhookKind::Ntfy, Some(token)) => request = request.bearer_auth(token),
+            _ => {},
+        }
+
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+
+        request.body(payload.to_owned()).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    fn get_payload(&self, event: Event, torrent: Option<&Torrent>, subject: &str, body: &str) -> Value {
+        let title = format!("{} {}", get_emoji(event), subject);
+        let color = get_color(event);
+
+        // The message is required by the push services
+        let message = match body.trim() {
+            "" => subject,
+            body => body,
+        };
+
+        match self.config.kind {
+            WebhookKind::Slack => json!({
+                "text": escape_slack(&title),
+                "attachments": [{
+                    "color": format!("#{:06x}", color),
+                    "text": escape_slack(body.trim()),
+                }],
+            }),
+            WebhookKind::Discord => json!({
+                "embeds": [{
+                    "title": truncate(&title, DISCORD_MAX_TITLE_SIZE),
+                    "description": truncate(body.trim(), DISCORD_MAX_DESCRIPTION_SIZE),
+                    "color": color,
+                }],
+            }),
+            WebhookKind::Json => notifications::get_event_json(event, torrent, subject, body),
+            WebhookKind::Pushover => json!({
+                "token": self.config.token,
+                "user": self.config.user,
+                "title": truncate(subject, PUSHOVER_MAX_TITLE_SIZE),
+                "message": truncate(message, PUSHOVER_MAX_MESSAGE_SIZE),
+                "priority": self.get_priority(event),
+            }),
+            WebhookKind::Gotify => json!({
+                "title": subject,
+                "message": message,
+                "priority": self.get_priority(event),
+            }),
+            WebhookKind::Ntfy => json!({
+                "topic": self.config.topic,
+                "title": subject,
+                "message": message,
+                "priority": self.get_priority(event),
+                "tags": self.config.tags.get(&event).cloned().unwrap_or_else(|| vec![s!(get_ntfy_tag(event))]),
+            }),
+        }
+    }
+
+    fn get_priority(&self, event: Event) -> i64 {
+        if let Some(&priority) = self.config.priorities.get(&event) {
+            return priority;
+        }
+
+        // Failures must be delivered right away while the reports may wait
+        match (self.config.kind, event) {
+            (WebhookKind::Pushover, Event::Problem | Event::FreeSpace) => 1,
+            (WebhookKind::Pushover, Event::Downloaded | Event::Recovered) => 0,
+            (WebhookKind::Pushover, _) => -1,
+            (WebhookKind::Ntfy, Event::Problem | Event::FreeSpace) => 4,
+            (WebhookKind::Ntfy, Event::Downloaded | Event::Recovered) => 3,
+            (WebhookKind::Ntfy, _) => 2,
+            (_, Event::Problem | Event::FreeSpace) => 8,
+            (_, Event::Downloaded | Event::Recovered) => 5,
+            (_, _) => 2,
+        }
+    }
+}
+
+fn get_emoji(event: Event) -> &'static str {
+    match event {
+        Event::Downloaded => "✅",
+        Event::Removed => "🗑️",
+        Event::FreeSpace => "💾",
+        Event::Problem => "⚠️",
+        Event::Recovered => "👌",
+        Event::Report => "📊",
+        Event::Progress => "⏳",
+    }
+}
+
+/// ntfy shows the tags which are emoji short codes as emojis
+fn get_ntfy_tag(event: Event) -> &'static str {
+    match event {
+        Event::Downloaded => "white_check_mark",
+        Event::Removed => "wastebasket",
+        Event::FreeSpace => "floppy_disk",
+        Event::Problem => "warning",
+        Event::Recovered => "ok_hand",
+        Event::Report => "bar_chart",
+        Event::Progress => "hourglass_flowing_sand",
+    }
+}
+
+fn get_color(event: Event) -> u32 {
+    match event {
+        Event::Downloaded | Event::Recovered => 0x2eb886,
+        Event::Removed => 0x9e9e9e,
+        Event::FreeSpace => 0xf2a516,
+        Event::Problem => 0xd9534f,
+        Event::Report | Event::Progress => 0x4a90d9,
+    }
+}
+
+/// Slack treats `&`, `<` and `>` as control characters in its markup
+fn escape_slack(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn truncate(text: &str, max_size: usize) -> &str {
+    match text.char_indices().nth(max_size) {
+        Some((index, _)) => &text[..index],
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use crate::transmissionrpc::TorrentFields;
+    use crate::transmissionrpc::mock::{MockServer, MockTorrent};
+
+    use super::*;
+
+    fn webhook(config: &str) -> Webhook {
+        Webhook::new(&toml::from_str(config).unwrap())
+    }
+
+    #[test]
+    fn test_payload() {
+        let subject = "'Some <torrent>' torrent has been downloaded";
+        let body = "It has been copied to '/mnt/media' in 1m.\n";
+
+        let slack = webhook(r#"kind = "slack"
+                                url = "https://hooks.slack.com/services/T000/B000/secret""#);
+        assert_eq!(slack.get_payload(Event::Downloaded, None, subject, body), json!({
+            "text": "✅ 'Some &lt;torrent&gt;' torrent has been downloaded",
+            "attachments": [{
+                "color": "#2eb886",
+                "text": "It has been copied to '/mnt/media' in 1m.",
+            }],
+        }));
+
+        let discord = webhook(r#"kind = "discord"
+                                  url = "https://discord.com/api/webhooks/1/secret""#);
+        assert_eq!(discord.get_payload(Event::Problem, None, subject, body), json!({
+            "embeds": [{
+                "title": "⚠️ 'Some <torrent>' torrent has been downloaded",
+                "description": "It has been copied to '/mnt/media' in 1m.",
+                "color": 0xd9534f,
+            }],
+        }));
+
+        let payload = discord.get_payload(Event::Report, None, &"x".repeat(300), "");
+        assert_eq!(payload["embeds"][0]["title"].as_str().unwrap().chars().count(), DISCORD_MAX_TITLE_SIZE);
+    }
+
+    #[test]
+    fn test_push_payload() {
+        let pushover = webhook(r#"kind = "pushover"
+                                  token = "app-token"
+                                  user = "user-key"
+                                  priorities = { removed = -2 }"#);
+
+        assert_eq!(pushover.get_payload(Event::Problem, None, "'Some torrent' torrent is stalled", ""), json!({
+            "token": "app-token",
+            "user": "user-key",
+            "title": "'Some torrent' torrent is stalled",
+            "message": "'Some torrent' torrent is stalled",
+            "priority": 1,
+        }));
+        assert_eq!(pushover.get_payload(Event::Downloaded, None, "Downloaded", "")["priority"], 0);
+        assert_eq!(pushover.get_payload(Event::Removed, None, "Removed", "")["priority"], -2);
+
+        let gotify = webhook(r#"kind = "gotify"
+                                url = "https://gotify.example.com/"
+                                token = "app-token""#);
+
+        assert_eq!(gotify.get_payload(Event::FreeSpace, None, "Free space is running low", "Details.\n"), json!({
+            "title": "Free space is running low",
+            "message": "Details.",
+            "priority": 8,
+        }));
+        assert_eq!(gotify.get_payload(Event::Report, None, "Report", "")["priority"], 2);
+
+        let ntfy = webhook(r#"kind = "ntfy"
+                              topic = "seedbox"
+                              tags = { downloaded = ["tada", "movie_camera"] }"#);
+
+        assert_eq!(ntfy.get_payload(Event::Problem, None, "'Some torrent' torrent is stalled", "Details."), json!({
+            "topic": "seedbox",
+            "title": "'Some torrent' torrent is stalled",
+            "message": "Details.",
+            "priority": 4,
+            "tags": ["warning"],
+        }));
+        let payload = ntfy.get_payload(Event::Downloaded, None, "Downloaded", "");
+        assert_eq!(payload["tags"], json!(["tada", "movie_camera"]));
+    }
+
+    #[test]
+    fn test_json_payload() {
+        let runtime = Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+
+        let mut torrent = MockTorrent::new(1, "Some torrent", "/downloads");
+        torrent.labels = vec![s!("movies")];
+        server.add_torrent(torrent);
+
+        let torrent = runtime.block_on(server.client().get_torrent(&format!("{:040x}", 1), TorrentFields::basic()))
+            .unwrap();
+
+        let json = webhook(r#"kind = "json"
+                              url = "https://example.com/webhook""#);
+        let mut payload = json.get_payload(
+            Event::Downloaded, Some(&torrent), "'Some torrent' torrent has been downloaded", "");
+        assert!(payload["time"].as_i64().unwrap() > 0);
+        payload.as_object_mut().unwrap().remove("time");
+
+        assert_eq!(payload, json!({
+            "event": "downloaded",
+            "subject": "'Some torrent' torrent has been downloaded",
+            "message": "",
+            "torrent": {
+                "hash": format!("{:040x}", 1),
+                "name": "Some torrent",
+                "download_dir": "/downloads",
+                "size": torrent.size,
+                "labels": ["movies"],
+                "added_time": 1_000_001,
+                "done_time": 1_000_001,
+            },
+        }));
+    }
+
+    #[test]
+    fn test_events() {
+        let config: WebhookConfig = toml::from_str(r#"
+            kind = "discord"
+            url = "https://discord.com/api/webhooks/1/secret"
+            events = ["downloaded", "free-space"]
+        "#).unwrap();
+
+        let webhook = Webhook::new(&config);
+        assert!(webhook.accepts(Event::Downloaded));
+        assert!(webhook.accepts(Event::FreeSpace));
+        assert!(!webhook.accepts(Event::Report));
+    }
+}
@@ -0,0 +1,302 @@
+//! XMPP (Jabber) notification channel. Implements only the required subset of the client protocol: STARTTLS or direct
+//! TLS, SASL PLAIN authentication, resource binding and sending a chat message. Each notification is sent over a new
+//! connection since they are rare.
+
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::Deserialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_native_tls::{TlsConnector, native_tls};
+
+use crate::common::{EmptyResult, GenericResult};
+use crate::notifications::Event;
+
+const DEFAULT_PORT: u16 = 5222;
+const RESOURCE: &str = "transmission-controller";
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum XmppTls {
+    /// Upgrade the connection via STARTTLS (port 5222)
+    #[default]
+    Starttls,
+    /// TLS from the start (port 5223 usually)
+    Direct,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct XmppConfig {
+    pub jid: String,
+    pub password: String,
+    pub recipient: String,
+    /// Server address in host[:port] format (the JID's domain by default: SRV records aren't supported)
+    pub server: Option<String>,
+    #[serde(default)]
+    pub tls: XmppTls,
+    /// The events to send (all by default)
+    pub events: Option<Vec<Event>>,
+}
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+pub struct XmppClient {
+    config: XmppConfig,
+    username: String,
+    domain: String,
+}
+
+impl XmppClient {
+    pub fn new(config: &XmppConfig) -> GenericResult<XmppClient> {
+        let (username, domain) = parse_jid(&config.jid)?;
+        Ok(XmppClient {
+            config: config.clone(),
+            username: username,
+            domain: domain,
+        })
+    }
+
+    pub fn accepts(&self, event: Event) -> bool {
+        self.config.events.as_ref().is_none_or(|events| events.contains(&event))
+    }
+
+    pub async fn send(&self, subject: &str, body: &str) -> EmptyResult {
+        let text = match body.trim() {
+            "" => s!(subject),
+            body => format!("{}\n\n{}", subject, body),
+        };
+
+        tokio::time::timeout(TIMEOUT, async {
+            let stream = self.connect().await?;
+            self.deliver(stream, &text).await
+        }).await.map_err(|_| "XMPP server has timed out")?.map_err(|e| format!("XMPP: {}", e).into())
+    }
+
+    async fn connect(&self) -> GenericResult<Box<dyn Stream>> {
+        let address = match self.config.server {
+            Some(ref server) if server.contains(':') => server.clone(),
+            Some(ref server) => format!("{}:{}", server, DEFAULT_PORT),
+            None => format!("{}:{}", self.domain, DEFAULT_PORT),
+        };
+
+        let mut stream = TcpStream::connect(&address).await.map_err(|e| format!(
+            "unable to connect to {}: {}", address, e))?;
+
+        if self.config.tls == XmppTls::Starttls {
+            let mut reader = Reader::new();
+            self.open_stream(&mut stream, &mut reader).await?;
+
+            if !reader.read_features(&mut stream).await?.contains("urn:ietf:params:xml:ns:xmpp-tls") {
+                return Err!("the server doesn't support STARTTLS");
+            }
+
+            stream.write_all(b"<starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>").await?;
+            if reader.read_until(&mut stream, &["<proceed", "<failure"]).await? != 0 {
+                return Err!("the server has refused STARTTLS");
+            }
+        }
+
+        let connector = TlsConnector::from(native_tls::TlsConnector::new()?);
+        let stream = connector.connect(&self.domain, stream).await.map_err(|e| format!("TLS error: {}", e))?;
+
+        Ok(Box::new(stream))
+    }
+
+    /// Authenticates over the secured connection and sends the message
+    async fn deliver<S: Stream>(&self, mut stream: S, text: &str) -> EmptyResult {
+        let mut reader = Reader::new();
+        self.open_stream(&mut stream, &mut reader).await?;
+
+        if !reader.read_features(&mut stream).await?.contains("<mechanism>PLAIN</mechanism>") {
+            return Err!("the server doesn't support PLAIN authentication");
+        }
+
+        let credentials = BASE64.encode(format!("\0{}\0{}", self.username, self.config.password));
+        stream.write_all(format!(
+            "<auth xmlns='urn:ietf:params:xml:ns:xmpp-sasl' mechanism='PLAIN'>{}</auth>", credentials,
+        ).as_bytes()).await?;
+
+        if reader.read_until(&mut stream, &["<success", "<failure"]).await? != 0 {
+            return Err!("authentication failed");
+        }
+
+        // The stream is restarted after authentication
+        let mut reader = Reader::new();
+        self.open_stream(&mut stream, &mut reader).await?;
+        reader.read_features(&mut stream).await?;
+
+        stream.write_all(format!(
+            "<iq type='set' id='bind'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'><resource>{}</resource></bind></iq>",
+            RESOURCE,
+        ).as_bytes()).await?;
+
+        let response = reader.read_element(&mut stream, "<iq", "</iq>").await?;
+        if !response.contains("type='result'") && !response.contains("type=\"result\"") {
+            return Err!("resource binding failed");
+        }
+
+        stream.write_all(format!(
+            "<message to='{}' type='chat'><body>{}</body></message></stream:stream>",
+            escape(&self.config.recipient), escape(text),
+        ).as_bytes()).await?;
+        stream.flush().await?;
+
+        // Wait for the server to close the stream to make sure that the message has been processed
+        let _ = reader.read_until(&mut stream, &["</stream:stream>"]).await;
+        let _ = stream.shutdown().await;
+
+        Ok(())
+    }
+
+    async fn open_stream<S: AsyncWrite + Unpin>(&self, stream: &mut S, reader: &mut Reader) -> EmptyResult {
+        stream.write_all(format!(
+            "<?xml version='1.0'?><stream:stream to='{}' version='1.0' xmlns='jabber:client' \
+             xmlns:stream='http://etherx.jabber.org/streams'>", escape(&self.domain),
+        ).as_bytes()).await?;
+        reader.buffer.clear();
+        Ok(())
+    }
+}
+
+/// A naive reader which looks for the expected elements in the incoming data instead of parsing XML
+struct Reader {
+    buffer: String,
+}
+
+impl Reader {
+    fn new() -> Reader {
+        Reader { buffer: String::new() }
+    }
+
+    async fn read_features<S: AsyncRead + Unpin>(&mut self, stream: &mut S) -> GenericResult<String> {
+        self.read_element(stream, "<stream:features", "</stream:features>").await
+    }
+
+    async fn read_element<S: AsyncRead + Unpin>(
+        &mut self, stream: &mut S, start: &str, end: &str,
+    ) -> GenericResult<String> {
+        self.read_until(stream, &[end]).await?;
+
+        let end_position = self.buffer.find(end).unwrap() + end.len();
+        let element = match self.buffer[..end_position].find(start) {
+            Some(start_position) => s!(&self.buffer[start_position..end_position]),
+            None => return Err!("got an invalid response from the server"),
+        };
+        self.buffer.drain(..end_position);
+
+        Ok(element)
+    }
+
+    /// Returns the index of the first found pattern
+    async fn read_until<S: AsyncRead + Unpin>(&mut self, stream: &mut S, patterns: &[&str]) -> GenericResult<usize> {
+        let mut data = [0; 4096];
+
+        loop {
+            if let Some(index) = patterns.iter().position(|pattern| self.buffer.contains(pattern)) {
+                return Ok(index);
+            } else if self.buffer.contains("<stream:error") {
+                return Err!("got a stream error: {}", self.buffer.trim());
+            }
+
+            let size = stream.read(&mut data).await?;
+            if size == 0 {
+                return Err!("the server has closed the connection");
+            }
+            self.buffer.push_str(&String::from_utf8_lossy(&data[..size]));
+        }
+    }
+}
+
+fn parse_jid(jid: &str) -> GenericResult<(String, String)> {
+    let bare = jid.split_once('/').map(|(bare, _)| bare).unwrap_or(jid);
+    match bare.split_once('@') {
+        Some((username, domain)) if !username.is_empty() && !domain.is_empty() => Ok((s!(username), s!(domain))),
+        _ => Err!("invalid JID: {:?}", jid),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('\'', "&apos;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::DuplexStream;
+    use tokio::runtime::Runtime;
+
+    use super::*;
+
+    async fn expect(stream: &mut DuplexStream, reader: &mut Reader, pattern: &str, response: &str) {
+        reader.read_until(stream, &[pattern]).await.unwrap();
+        reader.buffer.clear();
+        stream.write_all(response.as_bytes()).await.unwrap();
+    }
+
+    #[test]
+    fn test_delivery() {
+        let runtime = Runtime::new().unwrap();
+
+        let client = XmppClient::new(&toml::from_str(r#"
+            jid = "bot@example.com/home"
+            password = "secret"
+            recipient = "user@example.com"
+        "#).unwrap()).unwrap();
+        assert_eq!((client.username.as_str(), client.domain.as_str()), ("bot", "example.com"));
+
+        let result = runtime.block_on(async {
+            let (client_stream, mut server) = tokio::io::duplex(4096);
+            let delivery = tokio::spawn(async move {
+                client.deliver(client_stream, "Subject\n\n<Body> & more").await
+            });
+
+            let features = "<stream:stream><stream:features><mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'>\
+                            <mechanism>PLAIN</mechanism></mechanisms></stream:features>";
+
+            let mut reader = Reader::new();
+            expect(&mut server, &mut reader, "<stream:stream to='example.com'", features).await;
+            expect(&mut server, &mut reader, &format!(">{}</auth>", BASE64.encode("\0bot\0secret")),
+                   "<success xmlns='urn:ietf:params:xml:ns:xmpp-sasl'/>").await;
+            expect(&mut server, &mut reader, "<stream:stream", "<stream:stream><stream:features>\
+                   <bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'/></stream:features>").await;
+            expect(&mut server, &mut reader, "<resource>transmission-controller</resource></bind></iq>",
+                   "<iq type='result' id='bind'><bind><jid>bot@example.com/transmission-controller</jid></bind></iq>",
+            ).await;
+            expect(&mut server, &mut reader, "</stream:stream>", "</stream:stream>").await;
+
+            delivery.await.unwrap()
+        });
+        result.unwrap();
+
+        assert_eq!(escape("<Body> & 'more'"), "&lt;Body&gt; &amp; &apos;more&apos;");
+        assert!(parse_jid("example.com").is_err());
+    }
+
+    #[test]
+    fn test_authentication_failure() {
+        let runtime = Runtime::new().unwrap();
+
+        let client = XmppClient::new(&toml::from_str(r#"
+            jid = "bot@example.com"
+            password = "invalid"
+            recipient = "user@example.com"
+        "#).unwrap()).unwrap();
+
+        let result = runtime.block_on(async {
+            let (client_stream, mut server) = tokio::io::duplex(4096);
+            let delivery = tokio::spawn(async move { client.deliver(client_stream, "Subject").await });
+
+            let mut reader = Reader::new();
+            expect(&mut server, &mut reader, "<stream:stream", "<stream:stream><stream:features><mechanisms>\
+                   <mechanism>PLAIN</mechanism></mechanisms></stream:features>").await;
+            expect(&mut server, &mut reader, "</auth>", "<failure><not-authorized/></failure>").await;
+
+            delivery.await.unwrap()
+        });
+        assert_eq!(result.unwrap_err().to_string(), "authentication failed");
+    }
+}